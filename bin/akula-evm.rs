@@ -0,0 +1,228 @@
+use akula::{
+    execution::struct_logger::StructLoggerConfig,
+    models::*,
+    t8n::{self, Alloc, Env, Txs},
+};
+use anyhow::Context;
+use clap::Parser;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+#[derive(Parser)]
+#[clap(
+    name = "Akula EVM",
+    about = "EVM utilities compatible with geth's evm tool"
+)]
+struct Opt {
+    #[clap(subcommand)]
+    command: OptCommand,
+}
+
+#[derive(Parser)]
+enum OptCommand {
+    /// Execute transactions of one block on top of a pre-state allocation
+    T8n(T8nOpts),
+}
+
+#[derive(Parser)]
+struct T8nOpts {
+    /// Pre-state allocation, or `stdin` to read it from `alloc` of JSON on stdin
+    #[clap(long = "input.alloc", default_value = "alloc.json")]
+    input_alloc: String,
+
+    /// Block environment, or `stdin`
+    #[clap(long = "input.env", default_value = "env.json")]
+    input_env: String,
+
+    /// Transactions as JSON objects or hex encoded RLP list, or `stdin`
+    #[clap(long = "input.txs", default_value = "txs.json")]
+    input_txs: String,
+
+    /// Directory outputs and traces are written to
+    #[clap(long = "output.basedir", parse(from_os_str), default_value = ".")]
+    output_basedir: PathBuf,
+
+    /// Post-state allocation, or `stdout` or `stderr`
+    #[clap(long = "output.alloc", default_value = "alloc.json")]
+    output_alloc: String,
+
+    /// Execution result, or `stdout` or `stderr`
+    #[clap(long = "output.result", default_value = "result.json")]
+    output_result: String,
+
+    /// Fork rules to execute the block with
+    #[clap(long = "state.fork", default_value = "London", possible_values = t8n::FORKS)]
+    fork: String,
+
+    #[clap(long = "state.chainid", default_value = "1")]
+    chain_id: u64,
+
+    /// Block reward in wei, negative to pay no rewards at all
+    #[clap(long = "state.reward", default_value = "0", allow_hyphen_values = true)]
+    reward: i64,
+
+    /// Write EIP-3155 trace of every transaction to `trace-<index>-<hash>.jsonl`
+    #[clap(long)]
+    trace: bool,
+
+    #[clap(long = "trace.memory")]
+    trace_memory: bool,
+
+    #[clap(long = "trace.nostack")]
+    trace_nostack: bool,
+
+    #[clap(long = "trace.returndata")]
+    trace_returndata: bool,
+}
+
+const STDIN: &str = "stdin";
+
+#[derive(Deserialize)]
+struct StdinInput {
+    alloc: Option<Alloc>,
+    env: Option<Env>,
+    txs: Option<Txs>,
+}
+
+fn read_input<T: DeserializeOwned>(
+    path: &str,
+    from_stdin: &mut Option<T>,
+    name: &str,
+) -> anyhow::Result<T> {
+    if path == STDIN {
+        return from_stdin
+            .take()
+            .with_context(|| format!("no {} on stdin", name));
+    }
+
+    serde_json::from_reader(BufReader::new(
+        File::open(path).with_context(|| format!("failed to open {}", path))?,
+    ))
+    .with_context(|| format!("failed to parse {}", path))
+}
+
+/// Outputs going to stdout and stderr, printed as one object each.
+#[derive(Default)]
+struct StdOutputs {
+    stdout: Map<String, Value>,
+    stderr: Map<String, Value>,
+}
+
+impl StdOutputs {
+    fn write(
+        &mut self,
+        base_dir: &Path,
+        path: &str,
+        name: &str,
+        value: &impl Serialize,
+    ) -> anyhow::Result<()> {
+        match path {
+            "stdout" => {
+                self.stdout
+                    .insert(name.to_string(), serde_json::to_value(value)?);
+            }
+            "stderr" => {
+                self.stderr
+                    .insert(name.to_string(), serde_json::to_value(value)?);
+            }
+            _ => {
+                let path = base_dir.join(path);
+                let mut file = BufWriter::new(
+                    File::create(&path)
+                        .with_context(|| format!("failed to create {}", path.display()))?,
+                );
+                serde_json::to_writer_pretty(&mut file, value)?;
+                file.flush()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn print(self) -> anyhow::Result<()> {
+        if !self.stdout.is_empty() {
+            println!("{}", serde_json::to_string_pretty(&self.stdout)?);
+        }
+        if !self.stderr.is_empty() {
+            eprintln!("{}", serde_json::to_string_pretty(&self.stderr)?);
+        }
+
+        Ok(())
+    }
+}
+
+async fn t8n(opts: T8nOpts) -> anyhow::Result<()> {
+    let StdinInput {
+        mut alloc,
+        mut env,
+        mut txs,
+    } = if [&opts.input_alloc, &opts.input_env, &opts.input_txs]
+        .iter()
+        .any(|path| *path == STDIN)
+    {
+        serde_json::from_reader(std::io::stdin().lock()).context("failed to parse stdin")?
+    } else {
+        StdinInput {
+            alloc: None,
+            env: None,
+            txs: None,
+        }
+    };
+    let alloc = read_input(&opts.input_alloc, &mut alloc, "alloc")?;
+    let env = read_input(&opts.input_env, &mut env, "env")?;
+    let txs = if opts.input_txs == STDIN {
+        txs.take().unwrap_or_default()
+    } else {
+        read_input(&opts.input_txs, &mut txs, "txs")?
+    };
+
+    let chain_spec = t8n::chain_spec(
+        &opts.fork,
+        opts.chain_id,
+        u64::try_from(opts.reward).ok().map(U256::from),
+    )?;
+    let trace = opts.trace.then(|| StructLoggerConfig {
+        disable_stack: opts.trace_nostack,
+        disable_storage: true,
+        enable_memory: opts.trace_memory,
+        enable_return_data: opts.trace_returndata,
+    });
+
+    let out = t8n::transition(&chain_spec, alloc, &env, txs, trace).await?;
+
+    std::fs::create_dir_all(&opts.output_basedir)?;
+    for (index, hash, trace) in &out.traces {
+        let path = opts
+            .output_basedir
+            .join(format!("trace-{}-{:?}.jsonl", index, hash));
+        std::fs::write(&path, trace)
+            .with_context(|| format!("failed to write {}", path.display()))?;
+    }
+
+    let mut std_outputs = StdOutputs::default();
+    std_outputs.write(
+        &opts.output_basedir,
+        &opts.output_alloc,
+        "alloc",
+        &out.alloc,
+    )?;
+    std_outputs.write(
+        &opts.output_basedir,
+        &opts.output_result,
+        "result",
+        &out.result,
+    )?;
+    std_outputs.print()
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    match Opt::parse().command {
+        OptCommand::T8n(opts) => t8n(opts).await,
+    }
+}
@@ -1,10 +1,48 @@
-use akula::{binutil::AkulaDataDir, kv::traits::*, models::*, stagedsync::stages::*};
-use async_trait::async_trait;
+use akula::{
+    accessors::{canonical_cache::CanonicalCache, chain},
+    binutil::AkulaDataDir,
+    execution::receipts::ReceiptsCache,
+    history_files::HistoryFiles,
+    kv::{reader_pool::ReaderPool, traits::*},
+    models::*,
+    reload::{follow_log_filter, ConfigReloader},
+    rpc::{
+        pubsub::{
+            publish_new_heads, watch_sync_status, NEW_HEADS_CAPACITY,
+            PENDING_TRANSACTIONS_CAPACITY, SYNC_EVENTS_CAPACITY,
+        },
+        txpool::refresh_pool,
+        AdminApiServer, AdminApiServerImpl, AkulaApiServer, AkulaApiServerImpl, DebugApiServer,
+        DebugApiServerImpl, EthApiServer, EthApiServerImpl, EthFilterApiServer,
+        EthFilterApiServerImpl, EthPubSubApiServer, EthPubSubApiServerImpl, MinerApiServer,
+        MinerApiServerImpl, MinerConfig, TraceApiServer, TraceApiServerImpl, TxpoolApiServer,
+        TxpoolApiServerImpl,
+    },
+    rpc_compat,
+    snapshot::{block_provider, set_block_provider, BlockProvider},
+    stagedsync::stages::*,
+    txpool::pool::{PoolConfig, TxPool},
+};
+use anyhow::{ensure, format_err};
+use bytes::Bytes;
 use clap::Parser;
-use ethnum::U256;
-use jsonrpsee::{core::RpcResult, http_server::HttpServerBuilder, proc_macros::rpc};
-use std::{future::pending, net::SocketAddr, sync::Arc};
-use tracing_subscriber::{prelude::*, EnvFilter};
+use ethereum_interfaces::txpool::txpool_client::TxpoolClient;
+use jsonrpsee::{
+    core::server::rpc_module::Methods, http_server::HttpServerBuilder, ws_server::WsServerBuilder,
+    RpcModule,
+};
+use parking_lot::{Mutex, RwLock};
+use std::{
+    future::pending,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::sync::{broadcast, watch};
+use tonic::transport::Channel;
+use tracing::*;
+use tracing_subscriber::{prelude::*, reload, EnvFilter};
 
 #[derive(Parser)]
 #[clap(name = "Akula RPC", about = "RPC server for Akula")]
@@ -12,74 +50,385 @@ pub struct Opt {
     #[clap(long)]
     pub datadir: AkulaDataDir,
 
+    /// Serve HTTP RPC on this address, required unless `--rpc-compat` is given.
     #[clap(long)]
-    pub listen_address: SocketAddr,
-}
+    pub listen_address: Option<SocketAddr>,
+
+    /// Serve WebSocket RPC, including subscriptions, on this address.
+    #[clap(long)]
+    pub ws_listen_address: Option<SocketAddr>,
+
+    /// Log database operations and cursor walks slower than this (ms).
+    #[clap(long = "db.slow-query-threshold")]
+    pub db_slow_query_threshold: Option<u64>,
+
+    /// Number of read transactions shared between RPC requests.
+    #[clap(long = "db.readers", default_value = "16")]
+    pub db_readers: usize,
+
+    /// Reopen shared read transactions older than this (s), even if head did not change.
+    #[clap(long = "db.reader-max-age", default_value = "60")]
+    pub db_reader_max_age: u64,
+
+    /// Enable debug_simulateValidation for EIP-4337 bundlers.
+    #[clap(long)]
+    pub bundler_api: bool,
+
+    /// Enable admin_reloadConfig.
+    #[clap(long)]
+    pub admin_api: bool,
+
+    /// TOML file with settings that are reloaded on SIGHUP or admin_reloadConfig,
+    /// e.g. log levels and RPC limits.
+    #[clap(long)]
+    pub config: Option<PathBuf>,
+
+    /// Transaction pool GRPC service URL as 'http://host:port', consulted for `pending` nonces.
+    #[clap(long = "txpool.api-addr")]
+    pub txpool_api_addr: Option<String>,
 
-#[rpc(server, namespace = "eth")]
-pub trait EthApi {
-    #[method(name = "blockNumber")]
-    async fn block_number(&self) -> RpcResult<BlockNumber>;
-    #[method(name = "getBalance")]
-    async fn get_balance(&self, address: Address, block_number: BlockNumber) -> RpcResult<U256>;
+    /// Number of blocks whose re-executed receipts are cached.
+    #[clap(long = "receipts.cache-size", default_value = "1024")]
+    pub receipts_cache_size: usize,
+
+    /// Do not re-execute blocks further behind head than this to compute their receipts.
+    #[clap(long = "receipts.max-depth", default_value = "90000")]
+    pub receipts_max_depth: u64,
+
+    /// Number of recent canonical headers kept in memory to serve lookups near head.
+    #[clap(long = "canonical.cache-size", default_value = "128")]
+    pub canonical_cache_size: usize,
+
+    /// Fee recipient of built blocks, returned by `eth_coinbase`.
+    #[clap(long = "miner.etherbase")]
+    pub miner_etherbase: Option<Address>,
+
+    /// Hex-encoded extra data of built blocks, at most 32 bytes.
+    #[clap(long = "miner.extradata", default_value = "")]
+    pub miner_extra_data: String,
+
+    /// Lowest priority fee (wei) of transactions included in built blocks.
+    #[clap(long = "miner.gasprice", default_value = "0")]
+    pub miner_min_tip: u64,
+
+    /// Serve blocks missing from the database from snapshot segments in this directory,
+    /// as written with `--snapshot.publish-dir`.
+    #[clap(long = "snapshot.dir", parse(from_os_str))]
+    pub snapshot_dir: Option<PathBuf>,
+
+    /// Instead of serving, run the JSON-RPC compatibility fixtures in this directory, e.g.
+    /// `tests/rpc-compat`, in-process against a synthetic chain and exit.
+    /// `--datadir` is replaced with a temporary one.
+    #[clap(long = "rpc-compat", parse(from_os_str))]
+    pub rpc_compat: Option<PathBuf>,
+
+    /// Rewrite failing fixtures of `--rpc-compat` with the responses received.
+    #[clap(long = "rpc-compat.update")]
+    pub rpc_compat_update: bool,
 }
 
-pub struct EthApiServerImpl<DB>
-where
-    DB: KV,
-{
-    db: Arc<DB>,
+/// Write the synthetic chain of the RPC compatibility suite into a new database in `datadir`.
+async fn write_rpc_compat_chain(datadir: &AkulaDataDir) -> anyhow::Result<()> {
+    let chain_spec = rpc_compat::synthetic_chain_spec();
+    let blocks =
+        rpc_compat::synthetic_chain(&chain_spec, rpc_compat::SYNTHETIC_CHAIN_LENGTH).await?;
+
+    let db = akula::kv::new_database(&datadir.chain_data_dir())?;
+    let mut tx = db.begin_mutable().await?;
+    rpc_compat::write_synthetic_chain(
+        &mut tx,
+        Arc::new(tempfile::tempdir_in(&datadir.0)?),
+        chain_spec,
+        &blocks,
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(())
 }
 
-#[async_trait]
-impl<DB> EthApiServer for EthApiServerImpl<DB>
-where
-    DB: KV,
-{
-    async fn block_number(&self) -> RpcResult<BlockNumber> {
-        Ok(FINISH
-            .get_progress(&self.db.begin().await?)
-            .await?
-            .unwrap_or(BlockNumber(0)))
+/// Run the RPC compatibility fixtures in `dir` against `api`, failing if any of them fails.
+async fn run_rpc_compat<DB: KV, Context: Send + Sync + 'static>(
+    db: &DB,
+    canonical: &CanonicalCache,
+    api: RpcModule<Context>,
+    dir: &Path,
+    update: bool,
+) -> anyhow::Result<()> {
+    // Don't wait for the head check to pick up the synthetic chain.
+    let tx = db.begin().await?;
+    if let Some(head) = FINISH.get_progress(&tx).await? {
+        canonical.refresh(&tx, head).await?;
     }
 
-    async fn get_balance(&self, address: Address, block_number: BlockNumber) -> RpcResult<U256> {
-        Ok(akula::accessors::state::account::read(
-            &self.db.begin().await?,
-            address,
-            Some(block_number),
-        )
-        .await?
-        .map(|acc| acc.balance)
-        .unwrap_or(U256::ZERO))
-    }
+    let results = rpc_compat::run_suite(&Methods::from(api), dir, update).await?;
+    let failed = results
+        .iter()
+        .filter(|result| result.error.is_some())
+        .count();
+    info!(
+        "RPC compatibility: {} passed, {} failed",
+        results.len() - failed,
+        failed
+    );
+    ensure!(
+        failed == 0,
+        "{} of {} RPC compatibility tests failed",
+        failed,
+        results.len()
+    );
+
+    Ok(())
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    let opt = Opt::parse();
-
-    let env_filter = if std::env::var(EnvFilter::DEFAULT_ENV)
-        .unwrap_or_default()
-        .is_empty()
-    {
-        EnvFilter::new("akula=info,rpc=info")
-    } else {
-        EnvFilter::from_default_env()
-    };
+    let mut opt = Opt::parse();
+
+    let startup_filter = std::env::var(EnvFilter::DEFAULT_ENV)
+        .ok()
+        .filter(|directives| !directives.is_empty())
+        .unwrap_or_else(|| "akula=info,rpc=info".to_string());
+    let (env_filter, log_filter_handle) = reload::Layer::new(EnvFilter::new(&startup_filter));
     tracing_subscriber::registry()
-        .with(tracing_subscriber::fmt::layer().with_target(false))
         .with(env_filter)
+        .with(tracing_subscriber::fmt::layer().with_target(false))
         .init();
 
+    let config = Arc::new(ConfigReloader::new(opt.config.clone())?);
+    follow_log_filter(&config, log_filter_handle, startup_filter);
+    if opt.config.is_some() {
+        config.clone().reload_on_sighup()?;
+    }
+
+    akula::kv::stats::set_slow_query_threshold(
+        opt.db_slow_query_threshold.map(Duration::from_millis),
+    );
+
+    let _rpc_compat_dir = match opt.rpc_compat {
+        Some(_) => {
+            let dir = tempfile::tempdir()?;
+            opt.datadir = AkulaDataDir(dir.path().to_path_buf());
+            write_rpc_compat_chain(&opt.datadir).await?;
+            Some(dir)
+        }
+        None => None,
+    };
+
     let db = Arc::new(akula::kv::mdbx::Environment::<mdbx::NoWriteMap>::open_ro(
         mdbx::Environment::new(),
         &opt.datadir,
         akula::kv::tables::CHAINDATA_TABLES.clone(),
     )?);
+    let readers = Arc::new(ReaderPool::new(
+        db.clone(),
+        opt.db_readers,
+        Duration::from_secs(opt.db_reader_max_age),
+    ));
+
+    let canonical = Arc::new(CanonicalCache::new(opt.canonical_cache_size));
 
-    let server = HttpServerBuilder::default().build(opt.listen_address)?;
-    let _server_handle = server.start(EthApiServerImpl { db }.into_rpc())?;
+    if let Some(dir) = &opt.snapshot_dir {
+        let provider = BlockProvider::open(dir)?;
+        info!(
+            "Serving snapshots up to block {}",
+            provider.snapshots().last_block()
+        );
+        set_block_provider(provider)?;
+    }
+
+    let (chain_spec, pool) = {
+        let tx = db.begin().await?;
+        let chain_spec = chain::chain_spec::read(&tx).await?;
+        let mut pool_config = PoolConfig::new(chain_spec.params.chain_id);
+        pool_config.policy = config.current().txpool.clone();
+        pool_config.min_tip = U256::from(opt.miner_min_tip);
+        // Head rules are set on the first head check.
+        let pool = TxPool::new(
+            pool_config,
+            chain_spec.collect_block_spec(BlockNumber(0)).revision,
+            u64::MAX,
+        );
+        (chain_spec, Arc::new(Mutex::new(pool)))
+    };
+    let chain_id = chain_spec.params.chain_id;
+    tokio::spawn({
+        let pool = pool.clone();
+        let mut config = config.subscribe();
+        async move {
+            while config.changed().await.is_ok() {
+                let policy = config.borrow().txpool.clone();
+                pool.lock().set_policy(policy);
+            }
+        }
+    });
+
+    let (new_heads, _) = broadcast::channel(NEW_HEADS_CAPACITY);
+    tokio::spawn({
+        let db = db.clone();
+        let readers = readers.clone();
+        let canonical = canonical.clone();
+        let pool = pool.clone();
+        let new_heads = new_heads.clone();
+        async move {
+            let mut head = None;
+            loop {
+                let res = async {
+                    let tx = db.begin().await?;
+                    let new_head = FINISH.get_progress(&tx).await?;
+                    if new_head != head {
+                        debug!("New head {:?}, refreshing readers", new_head);
+                        // Bodies of newly frozen blocks may be gone from the database.
+                        if let Some(provider) = block_provider() {
+                            provider.reload()?;
+                        }
+                        readers.refresh();
+                        if let Some(new_head) = new_head {
+                            canonical.refresh(&tx, new_head).await?;
+                            refresh_pool(&tx, &pool, &chain_spec, new_head).await?;
+                            // Head found on startup is not new.
+                            if let Some(old_head) = head {
+                                publish_new_heads(&tx, &new_heads, old_head, new_head).await?;
+                            }
+                        }
+                        head = new_head;
+                    }
+                    Ok::<_, anyhow::Error>(())
+                }
+                .await;
+                if let Err(e) = res {
+                    warn!("Failed to check head: {}", e);
+                }
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+        }
+    });
+
+    let txpool = opt
+        .txpool_api_addr
+        .map(|addr| {
+            Ok::<_, anyhow::Error>(TxpoolClient::new(
+                Channel::from_shared(addr)?.connect_lazy()?,
+            ))
+        })
+        .transpose()?;
+
+    let mut miner = MinerConfig {
+        etherbase: opt.miner_etherbase,
+        extra_data: Bytes::new(),
+        min_tip: U256::from(opt.miner_min_tip),
+    };
+    miner.set_extra_data(hex::decode(opt.miner_extra_data.trim_start_matches("0x"))?.into())?;
+    let miner = Arc::new(RwLock::new(miner));
+
+    let (pending_transactions, _) = broadcast::channel(PENDING_TRANSACTIONS_CAPACITY);
+    let mut api = EthApiServerImpl {
+        readers: readers.clone(),
+        canonical: canonical.clone(),
+        txpool,
+        pool: pool.clone(),
+        receipts: Arc::new(ReceiptsCache::new(
+            opt.receipts_cache_size,
+            opt.receipts_max_depth,
+        )),
+        miner: miner.clone(),
+        chain_id,
+        history: Arc::new(RwLock::new(Arc::new(HistoryFiles::open(
+            &opt.datadir.history_dir(),
+        )?))),
+        history_dir: opt.datadir.history_dir(),
+        pending_transactions: pending_transactions.clone(),
+    }
+    .into_rpc();
+    let akula_config = config.subscribe();
+    let filter_config = config.subscribe();
+    api.merge(
+        MinerApiServerImpl {
+            miner,
+            pool: pool.clone(),
+        }
+        .into_rpc(),
+    )?;
+    api.merge(TxpoolApiServerImpl { pool }.into_rpc())?;
+    api.merge(
+        AdminApiServerImpl {
+            config,
+            admin_api: opt.admin_api,
+        }
+        .into_rpc(),
+    )?;
+    api.merge(
+        DebugApiServerImpl {
+            readers: readers.clone(),
+            bundler_api: opt.bundler_api,
+        }
+        .into_rpc(),
+    )?;
+    api.merge(
+        TraceApiServerImpl {
+            readers: readers.clone(),
+        }
+        .into_rpc(),
+    )?;
+    api.merge(EthFilterApiServerImpl::new(readers.clone(), filter_config).into_rpc())?;
+
+    let (sync_events, _) = broadcast::channel(SYNC_EVENTS_CAPACITY);
+    let (sync_status_sender, sync_status) = watch::channel(None);
+    tokio::spawn(watch_sync_status(
+        db.clone(),
+        sync_events.clone(),
+        sync_status_sender,
+    ));
+    api.merge(
+        EthPubSubApiServerImpl {
+            readers: readers.clone(),
+            new_heads,
+            pending_transactions,
+            sync_events,
+            sync_status,
+        }
+        .into_rpc(),
+    )?;
+
+    // Merged last to report namespaces of all other modules.
+    let mut namespaces = api
+        .method_names()
+        .filter_map(|method| method.split_once('_').map(|(namespace, _)| namespace))
+        .chain(["akula"])
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+    // Admin methods are registered anyway to reply that they are disabled.
+    namespaces.retain(|namespace| namespace != "admin" || opt.admin_api);
+    namespaces.sort();
+    namespaces.dedup();
+    api.merge(
+        AkulaApiServerImpl {
+            readers,
+            config: akula_config,
+            namespaces,
+            receipts_max_depth: opt.receipts_max_depth,
+        }
+        .into_rpc(),
+    )?;
+
+    if let Some(dir) = &opt.rpc_compat {
+        return run_rpc_compat(&*db, &canonical, api, dir, opt.rpc_compat_update).await;
+    }
+
+    let _ws_server_handle = match opt.ws_listen_address {
+        Some(ws_listen_address) => Some(
+            WsServerBuilder::default()
+                .build(ws_listen_address)
+                .await?
+                .start(api.clone())?,
+        ),
+        None => None,
+    };
+    let listen_address = opt
+        .listen_address
+        .ok_or_else(|| format_err!("--listen-address is required"))?;
+    let server = HttpServerBuilder::default().build(listen_address)?;
+    let _server_handle = server.start(api)?;
 
     pending().await
 }
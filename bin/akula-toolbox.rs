@@ -1,19 +1,27 @@
 use akula::{
     binutil::AkulaDataDir,
+    engine_api::{new_payload, PayloadStatus},
     hex_to_bytes,
     kv::{
         tables::{self, CHAINDATA_TABLES},
         traits::*,
     },
     models::*,
-    stagedsync,
+    stagedsync::{self, stages::*},
     stages::*,
 };
 use anyhow::{bail, ensure, format_err, Context};
 use bytes::Bytes;
 use clap::Parser;
 use itertools::Itertools;
-use std::{borrow::Cow, path::PathBuf, sync::Arc};
+use serde::Deserialize;
+use std::{
+    borrow::Cow,
+    fs::File,
+    io::{BufRead, BufReader},
+    path::PathBuf,
+    sync::Arc,
+};
 use tokio::pin;
 use tokio_stream::StreamExt;
 use tracing::*;
@@ -56,6 +64,18 @@ pub enum OptCommand {
         max_entries: Option<usize>,
     },
 
+    /// Copy the database table by table into a new one without free pages,
+    /// reporting space saved per table. Reads a single snapshot, so the node may keep running.
+    DbCompact {
+        /// Directory of the compacted database, must not exist yet
+        #[clap(parse(from_os_str))]
+        output: PathBuf,
+
+        /// Maximum size of the compacted database (GiB)
+        #[clap(long, default_value = "4096")]
+        max_size: u64,
+    },
+
     /// Check table equality in two databases
     CheckEqual {
         #[clap(long, parse(from_os_str))]
@@ -95,6 +115,106 @@ pub enum OptCommand {
     ReadStorageChanges {
         block: BlockNumber,
     },
+
+    /// Replay recorded Engine API calls against the database
+    ReplayEngineApi {
+        /// File with one engine_newPayloadV1/engine_forkchoiceUpdatedV1 JSON-RPC request per line
+        #[clap(parse(from_os_str))]
+        file: PathBuf,
+
+        #[clap(long = "chain", default_value = "mainnet")]
+        chain_name: String,
+    },
+
+    /// Export canonical hashes of every 192nd block as a preverified hashes bundle
+    ExportPreverifiedHashes {
+        #[clap(parse(from_os_str))]
+        output: PathBuf,
+
+        /// Last block to include, defaults to the last canonical header
+        #[clap(long)]
+        max_block: Option<BlockNumber>,
+    },
+
+    /// Export blocks, transactions, receipts, logs and traces into partitioned Parquet files
+    ExportAnalytics {
+        #[clap(parse(from_os_str))]
+        output: PathBuf,
+
+        #[clap(long, default_value = "0")]
+        from: BlockNumber,
+
+        /// Last block to export, defaults to the last executed block
+        #[clap(long)]
+        to: Option<BlockNumber>,
+
+        /// Number of blocks per file
+        #[clap(long, default_value = "1000")]
+        partition_size: u64,
+    },
+
+    /// Re-execute blocks and write time spent per opcode, precompile and host function
+    /// as folded stacks, e.g. for `inferno-flamegraph`
+    ProfileExecution {
+        #[clap(parse(from_os_str))]
+        output: PathBuf,
+
+        #[clap(long)]
+        from: BlockNumber,
+
+        /// Last block to execute, defaults to `from`
+        #[clap(long)]
+        to: Option<BlockNumber>,
+    },
+
+    /// Compare executed blocks with a reference node and stop at the first divergence
+    DiffSync {
+        /// JSON-RPC endpoint of the reference node
+        #[clap(long)]
+        reference_node: String,
+
+        /// First block to compare, defaults to the last executed block
+        #[clap(long)]
+        from: Option<BlockNumber>,
+
+        /// Account whose balance is compared after every block, may be repeated
+        #[clap(long = "address")]
+        addresses: Vec<Address>,
+
+        /// Also compare call traces, the reference node must serve debug_traceBlockByNumber
+        #[clap(long)]
+        traces: bool,
+
+        /// Keep comparing newly executed blocks
+        #[clap(long)]
+        follow: bool,
+    },
+
+    /// Recompute the state root from hashed state, ignoring intermediate hashes, and compare it
+    /// with the header. On mismatch, print the deepest account trie path whose intermediate
+    /// hash does not match hashed state
+    StateRoot {
+        /// Block whose state is checked, must be the one hashed state is at, defaults to it
+        #[clap(long)]
+        block: Option<BlockNumber>,
+
+        /// Compute subtries under the root in parallel
+        #[clap(long)]
+        parallel: bool,
+    },
+
+    /// Print the enode URL of a sentry's node key, generating the key if it does not exist
+    Enode {
+        /// File with hex encoded node key, e.g. `nodekey` in the sentry data directory
+        #[clap(long, parse(from_os_str))]
+        nodekey: PathBuf,
+
+        #[clap(long, default_value = "127.0.0.1")]
+        ip: std::net::IpAddr,
+
+        #[clap(long, default_value = "30303")]
+        port: u16,
+    },
 }
 
 #[derive(Parser)]
@@ -160,6 +280,7 @@ async fn header_download(data_dir: AkulaDataDir, opts: HeaderDownloadOpts) -> an
 
     let stage = akula::stages::HeaderDownload::new(
         chain_config,
+        opts.downloader_opts.preverified_hashes_file.as_deref(),
         opts.downloader_opts.headers_mem_limit(),
         opts.downloader_opts.headers_batch_size,
         sentry.clone(),
@@ -277,6 +398,58 @@ async fn db_walk(
     Ok(())
 }
 
+async fn db_compact(data_dir: AkulaDataDir, output: PathBuf, max_size: u64) -> anyhow::Result<()> {
+    /// Entries copied per write transaction of the compacted database.
+    const BATCH_SIZE: usize = 1_000_000;
+
+    ensure!(!output.exists(), "{} already exists", output.display());
+    std::fs::create_dir_all(&output)?;
+
+    let src = open_db(data_dir)?;
+    let mut builder = mdbx::Environment::<mdbx::NoWriteMap>::new();
+    builder.set_geometry(mdbx::Geometry {
+        size: Some(0..byte_unit::n_gib_bytes!(max_size as u128) as usize),
+        growth_step: Some(byte_unit::n_gib_bytes!(4) as isize),
+        shrink_threshold: None,
+        page_size: None,
+    });
+    let dst = akula::kv::mdbx::Environment::<mdbx::NoWriteMap>::open_rw(
+        builder,
+        &output,
+        CHAINDATA_TABLES.clone(),
+    )?;
+
+    let tx = src.begin().await?;
+    let sizes_before = tx.table_sizes()?;
+    let entries = tx.copy_to(&dst, &CHAINDATA_TABLES, BATCH_SIZE)?;
+    let sizes_after = dst.begin().await?.table_sizes()?;
+
+    let mut total_before = 0;
+    let mut total_after = 0;
+    for (table, entries) in entries.into_iter().sorted() {
+        let before = sizes_before.get(&table).copied().unwrap_or_default();
+        let after = sizes_after.get(&table).copied().unwrap_or_default();
+        total_before += before;
+        total_after += after;
+        println!(
+            "{} - {} entries, {} -> {}, saved {}",
+            table,
+            entries,
+            bytesize::ByteSize::b(before),
+            bytesize::ByteSize::b(after),
+            bytesize::ByteSize::b(before.saturating_sub(after))
+        );
+    }
+    println!(
+        "TOTAL: {} -> {}, saved {}",
+        bytesize::ByteSize::b(total_before),
+        bytesize::ByteSize::b(total_after),
+        bytesize::ByteSize::b(total_before.saturating_sub(total_after))
+    );
+
+    Ok(())
+}
+
 async fn check_table_eq(db1_path: PathBuf, db2_path: PathBuf, table: String) -> anyhow::Result<()> {
     let env1 = akula::kv::mdbx::Environment::<mdbx::NoWriteMap>::open_ro(
         mdbx::Environment::new(),
@@ -346,6 +519,131 @@ async fn check_table_eq(db1_path: PathBuf, db2_path: PathBuf, table: String) ->
     Ok(())
 }
 
+async fn export_preverified_hashes(
+    data_dir: AkulaDataDir,
+    output: PathBuf,
+    max_block: Option<BlockNumber>,
+) -> anyhow::Result<()> {
+    const STEP: u64 = 192;
+
+    let env = open_db(data_dir)?;
+    let tx = env.begin().await?;
+
+    let last_block = match tx.cursor(tables::CanonicalHeader).await?.last().await? {
+        Some((block_number, _)) => block_number,
+        None => bail!("no canonical headers"),
+    };
+    let max_block = max_block.map_or(last_block, |max_block| max_block.min(last_block));
+
+    let mut hashes = vec![];
+    for block_number in (0..=max_block.0).step_by(STEP as usize) {
+        hashes.push(
+            tx.get(tables::CanonicalHeader, BlockNumber(block_number))
+                .await?
+                .ok_or_else(|| format_err!("no canonical hash for block {}", block_number))?,
+        );
+    }
+
+    let config = akula::downloader::PreverifiedHashesConfig { hashes };
+    std::fs::write(&output, config.to_toml_string())?;
+    info!(
+        "Exported {} hashes up to block {} into {:?}",
+        config.hashes.len(),
+        config.height().unwrap_or(BlockNumber(0)),
+        output
+    );
+
+    Ok(())
+}
+
+async fn export_analytics(
+    data_dir: AkulaDataDir,
+    output: PathBuf,
+    from: BlockNumber,
+    to: Option<BlockNumber>,
+    partition_size: u64,
+) -> anyhow::Result<()> {
+    let env = open_db(data_dir)?;
+    let tx = env.begin().await?;
+
+    let to = match to {
+        Some(to) => to,
+        None => EXECUTION
+            .get_progress(&tx)
+            .await?
+            .ok_or_else(|| format_err!("no executed blocks"))?,
+    };
+
+    akula::analytics::export(&tx, &output, from, to, partition_size).await
+}
+
+async fn profile_execution(
+    data_dir: AkulaDataDir,
+    output: PathBuf,
+    from: BlockNumber,
+    to: Option<BlockNumber>,
+) -> anyhow::Result<()> {
+    let env = open_db(data_dir)?;
+    let tx = env.begin().await?;
+    let to = to.unwrap_or(from);
+
+    let mut profiler = akula::execution::profiler::Profiler::default();
+    akula::execution::profiler::profile_blocks(&tx, from, to, &mut profiler).await?;
+    let mut file = std::io::BufWriter::new(File::create(&output)?);
+    profiler.write_folded(&mut file)?;
+    std::io::Write::flush(&mut file)?;
+
+    info!(
+        "Blocks {}..={} profiled, {:?} spent in execution, written to {}",
+        from,
+        to,
+        profiler.total(),
+        output.display()
+    );
+
+    Ok(())
+}
+
+async fn diff_sync(
+    data_dir: AkulaDataDir,
+    reference_node: String,
+    from: Option<BlockNumber>,
+    addresses: Vec<Address>,
+    traces: bool,
+    follow: bool,
+) -> anyhow::Result<()> {
+    let env = open_db(data_dir)?;
+    let diff_sync = akula::diffsync::DiffSync::new(&reference_node, addresses, traces)?;
+
+    let chain_spec = akula::accessors::chain::chain_spec::read(&env.begin().await?).await?;
+
+    let mut next = from;
+    loop {
+        // New transaction every round to see blocks executed by the running node.
+        let tx = env.begin().await?;
+        let executed = EXECUTION.get_progress(&tx).await?.unwrap_or(BlockNumber(0));
+        let mut number = next.unwrap_or(executed);
+        while number <= executed {
+            if let Some(divergence) = diff_sync.compare_block(&tx, &chain_spec, number).await? {
+                error!(
+                    "Block {} diverges from reference node: {}",
+                    number, divergence
+                );
+                bail!("divergence at block {}", number);
+            }
+            info!("Block {} matches reference node", number);
+            number.0 += 1;
+        }
+        next = Some(number);
+        drop(tx);
+
+        if !follow {
+            return Ok(());
+        }
+        tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+    }
+}
+
 async fn read_block(data_dir: AkulaDataDir, block_num: BlockNumber) -> anyhow::Result<()> {
     let env = open_db(data_dir)?;
 
@@ -476,6 +774,205 @@ async fn read_storage_changes(data_dir: AkulaDataDir, block: BlockNumber) -> any
     Ok(())
 }
 
+#[derive(Deserialize)]
+struct EngineApiCall {
+    method: String,
+    #[serde(default)]
+    params: Vec<serde_json::Value>,
+}
+
+async fn replay_engine_api(
+    data_dir: AkulaDataDir,
+    file: PathBuf,
+    chain_name: String,
+) -> anyhow::Result<()> {
+    let chains_config = akula::sentry::chain_config::ChainsConfig::new()?;
+    let chain_config = chains_config.get(&chain_name)?;
+
+    std::fs::create_dir_all(&data_dir.0)?;
+    let etl_temp_path = data_dir.etl_temp_dir();
+    let _ = std::fs::remove_dir_all(&etl_temp_path);
+    std::fs::create_dir_all(&etl_temp_path)?;
+    let etl_temp_dir =
+        Arc::new(tempfile::tempdir_in(&etl_temp_path).context("failed to create ETL temp dir")?);
+
+    let db = akula::kv::new_database(&data_dir.chain_data_dir())?;
+    let txn = db.begin_mutable().await?;
+    if akula::genesis::initialize_genesis(&txn, &*etl_temp_dir, chain_config.chain_spec().clone())
+        .await?
+    {
+        txn.commit().await?;
+    }
+
+    let reader = BufReader::new(
+        File::open(&file).with_context(|| format!("failed to open {}", file.display()))?,
+    );
+    for (i, line) in reader.lines().enumerate() {
+        let line_no = i + 1;
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let call = serde_json::from_str::<EngineApiCall>(&line)
+            .with_context(|| format!("malformed call on line {}", line_no))?;
+        let param = call
+            .params
+            .into_iter()
+            .next()
+            .ok_or_else(|| format_err!("no params on line {}", line_no));
+
+        if call.method.starts_with("engine_newPayload") {
+            let payload = serde_json::from_value::<ExecutionPayload>(param?)
+                .with_context(|| format!("malformed payload on line {}", line_no))?;
+            let number = payload.block_number;
+            let hash = payload.block_hash;
+
+            let status = new_payload(&db, &etl_temp_dir, payload).await?;
+
+            info!(
+                line = line_no,
+                "newPayload {}/{:?}: {:?}", number, hash, status.status
+            );
+        } else if call.method.starts_with("engine_forkchoiceUpdated") {
+            let state = serde_json::from_value::<ForkchoiceState>(param?)
+                .with_context(|| format!("malformed forkchoice state on line {}", line_no))?;
+            let head_hash = state.head_block_hash;
+
+            let head_number = match db
+                .begin()
+                .await?
+                .get(tables::HeaderNumber, head_hash)
+                .await?
+            {
+                Some(number) => number,
+                None => {
+                    info!(
+                        line = line_no,
+                        "forkchoiceUpdated {:?}: {:?}",
+                        head_hash,
+                        PayloadStatus::Syncing
+                    );
+                    continue;
+                }
+            };
+
+            info!(
+                line = line_no,
+                "forkchoiceUpdated {}/{:?}: syncing to head", head_number, head_hash
+            );
+
+            let mut staged_sync = stagedsync::StagedSync::new();
+            staged_sync.set_max_block(Some(head_number));
+            staged_sync.push(ForkchoiceHeaders::fixed((head_number, head_hash)));
+            staged_sync.push(TotalGasIndex);
+            staged_sync.push(BlockHashes {
+                temp_dir: etl_temp_dir.clone(),
+            });
+            staged_sync.push(TotalTxIndex);
+            staged_sync.push(SenderRecovery {
+                batch_size: 500_000,
+            });
+            staged_sync.push(Execution {
+                batch_size: 5_000_000_000_000,
+                history_batch_size: 250_000_000_000,
+                exit_after_batch: false,
+                batch_until: None,
+                commit_every: None,
+                prune_from: BlockNumber(0),
+                analysis_cache: Default::default(),
+                contract_gas_top: None,
+            });
+            staged_sync.push(HashState::new(etl_temp_dir.clone(), None));
+            staged_sync.push(Interhashes::new(etl_temp_dir.clone(), None));
+            staged_sync.run(&db).await?;
+        } else {
+            debug!(line = line_no, "Skipping {}", call.method);
+        }
+    }
+
+    Ok(())
+}
+
+async fn state_root(
+    data_dir: AkulaDataDir,
+    block: Option<BlockNumber>,
+    parallel: bool,
+) -> anyhow::Result<()> {
+    let env: &'static _ = Box::leak(Box::new(open_db(data_dir)?));
+    let tx = Arc::new(env.begin().await?);
+
+    let hashed = HASH_STATE
+        .get_progress(&*tx)
+        .await?
+        .ok_or_else(|| format_err!("no hashed state"))?;
+    let block = block.unwrap_or(hashed);
+    ensure!(
+        block == hashed,
+        "hashed state is at block {}, not {}",
+        hashed,
+        block
+    );
+
+    let hash = akula::accessors::chain::canonical_hash::read(&*tx, block)
+        .await?
+        .ok_or_else(|| format_err!("no canonical block {}", block))?;
+    let header = akula::accessors::chain::header::read(&*tx, hash, block)
+        .await?
+        .ok_or_else(|| format_err!("no header for block {}", block))?;
+
+    let started = std::time::Instant::now();
+    let root = if parallel {
+        akula::trie::compute_state_root_parallel(tx.clone()).await?
+    } else {
+        akula::trie::compute_state_root(&*tx).await?
+    };
+    info!("State root computed in {:?}", started.elapsed());
+
+    if root == header.state_root {
+        println!("State root of block {} matches: {:?}", block, root);
+        return Ok(());
+    }
+
+    println!(
+        "State root mismatch at block {}: expected {:?}, computed {:?}",
+        block, header.state_root, root
+    );
+    match akula::trie::first_divergent_path(&*tx, vec![]).await? {
+        Some(path) => println!(
+            "First divergent account path: {}",
+            path.iter().map(|nibble| format!("{:x}", nibble)).join("")
+        ),
+        None => println!("Intermediate hashes match hashed state"),
+    }
+
+    bail!("state root mismatch")
+}
+
+fn enode(nodekey: PathBuf, ip: std::net::IpAddr, port: u16) -> anyhow::Result<()> {
+    let key = if nodekey.exists() {
+        let text = std::fs::read_to_string(&nodekey)
+            .with_context(|| format!("failed to read {}", nodekey.display()))?;
+        let text = text.trim();
+        secp256k1::SecretKey::from_slice(&hex::decode(text.strip_prefix("0x").unwrap_or(text))?)
+            .with_context(|| format!("invalid node key in {}", nodekey.display()))?
+    } else {
+        let key = akula::crypto::generate_key();
+        std::fs::write(&nodekey, hex::encode(&key[..]))
+            .with_context(|| format!("failed to write {}", nodekey.display()))?;
+        key
+    };
+
+    let pubkey = akula::crypto::to_pubkey(&key).serialize_uncompressed();
+    let host = match ip {
+        std::net::IpAddr::V4(ip) => ip.to_string(),
+        std::net::IpAddr::V6(ip) => format!("[{}]", ip),
+    };
+    println!("enode://{}@{}:{}", hex::encode(&pubkey[1..]), host, port);
+
+    Ok(())
+}
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     let opt: Opt = Opt::parse();
@@ -502,6 +999,9 @@ async fn main() -> anyhow::Result<()> {
             starting_key,
             max_entries,
         } => db_walk(opt.data_dir, table, starting_key, max_entries).await?,
+        OptCommand::DbCompact { output, max_size } => {
+            db_compact(opt.data_dir, output, max_size).await?
+        }
         OptCommand::CheckEqual { db1, db2, table } => check_table_eq(db1, db2, table).await?,
         OptCommand::HeaderDownload { opts } => header_download(opt.data_dir, opts).await?,
         OptCommand::ReadBlock { block_number } => read_block(opt.data_dir, block_number).await?,
@@ -513,6 +1013,42 @@ async fn main() -> anyhow::Result<()> {
         OptCommand::ReadStorageChanges { block } => {
             read_storage_changes(opt.data_dir, block).await?
         }
+        OptCommand::ReplayEngineApi { file, chain_name } => {
+            replay_engine_api(opt.data_dir, file, chain_name).await?
+        }
+        OptCommand::ExportPreverifiedHashes { output, max_block } => {
+            export_preverified_hashes(opt.data_dir, output, max_block).await?
+        }
+        OptCommand::ExportAnalytics {
+            output,
+            from,
+            to,
+            partition_size,
+        } => export_analytics(opt.data_dir, output, from, to, partition_size).await?,
+        OptCommand::ProfileExecution { output, from, to } => {
+            profile_execution(opt.data_dir, output, from, to).await?
+        }
+        OptCommand::DiffSync {
+            reference_node,
+            from,
+            addresses,
+            traces,
+            follow,
+        } => {
+            diff_sync(
+                opt.data_dir,
+                reference_node,
+                from,
+                addresses,
+                traces,
+                follow,
+            )
+            .await?
+        }
+        OptCommand::StateRoot { block, parallel } => {
+            state_root(opt.data_dir, block, parallel).await?
+        }
+        OptCommand::Enode { nodekey, ip, port } => enode(nodekey, ip, port)?,
     }
 
     Ok(())
@@ -1,15 +1,31 @@
 use akula::{
     binutil::AkulaDataDir,
+    consensus::engine_factory,
     downloader::sentry_status_provider::SentryStatusProvider,
+    engine_api::{self, BlockTemplateSource, EngineApiServer, EngineApiServerImpl, JwtSecret},
+    execution::analysis_cache::AnalysisCache,
     kv::{
+        disk_guard::DiskGuard,
         tables::{self, ErasedTable},
         traits::*,
+        ttl::Sweeper,
     },
+    metrics,
     models::*,
+    reload::{follow_log_filter, ConfigReloader},
     sentry::{
+        block_gossip::BlockGossip,
+        discovery::{
+            self,
+            known_nodes::{self, KnownNodes},
+            v4::Discv4,
+            v5::Discv5,
+            Candidates, NodeRecord, PeerDiscovery,
+        },
         sentry_client_connector::SentryClientConnectorImpl,
         sentry_client_reactor::SentryClientReactor,
     },
+    snapshot::{self, SnapshotDownloader, SnapshotPublication, SnapshotSource},
     stagedsync::{self, stage::*, stages::*},
     stages::*,
     version_string, StageId,
@@ -19,15 +35,16 @@ use async_trait::async_trait;
 use clap::Parser;
 use rayon::prelude::*;
 use std::{
+    net::{IpAddr, Ipv4Addr, SocketAddr},
     panic,
     path::PathBuf,
     sync::Arc,
-    time::{Duration, Instant},
+    time::{Duration, Instant, SystemTime},
 };
-use tokio::pin;
+use tokio::{pin, sync::watch};
 use tokio_stream::StreamExt;
 use tracing::*;
-use tracing_subscriber::{prelude::*, EnvFilter};
+use tracing_subscriber::{prelude::*, reload, EnvFilter};
 
 #[derive(Parser)]
 #[clap(name = "Akula", about = "Next-generation Ethereum implementation.")]
@@ -48,6 +65,11 @@ pub struct Opt {
     )]
     pub chain_name: String,
 
+    /// Chain spec file to use instead of `--chain`: Akula's RON format if it has the `.ron`
+    /// extension, otherwise a geth-style genesis.json, e.g. the one provided by Hive.
+    #[clap(long = "chain.spec", parse(from_os_str))]
+    pub chain_spec: Option<PathBuf>,
+
     /// Sentry GRPC service URL
     #[clap(
         long = "sentry.api.addr",
@@ -84,17 +106,189 @@ pub struct Opt {
     #[clap(long)]
     pub execution_exit_after_batch: bool,
 
+    /// Number of contracts whose analyzed code is kept between execution batches.
+    #[clap(long, default_value = "5000")]
+    pub execution_analysis_cache_size: usize,
+
+    /// Record this many contracts that used the most gas in every execution batch.
+    #[clap(long)]
+    pub execution_contract_gas_top: Option<usize>,
+
     /// Skip commitment (state root) verification.
     #[clap(long)]
     pub skip_commitment: bool,
 
+    /// Store preimages of hashed addresses and storage locations, served by akula_getKeyPreimage.
+    /// Only keys hashed while enabled get preimages.
+    #[clap(long)]
+    pub hash_state_preimages: bool,
+
+    /// Node to compare accounts and slots with on state root mismatch, via eth_getProof.
+    #[clap(long)]
+    pub state_root_reference_node: Option<String>,
+
+    /// Most accounts and slots to dump on state root mismatch.
+    #[clap(long, default_value = "1000")]
+    pub state_root_diff_entries: usize,
+
+    /// UNSAFE: until caught up with headers, only verify the state root once every this many
+    /// blocks. Faster initial sync, but a bad block is only noticed at the next such block.
+    #[clap(long)]
+    pub unsafe_state_root_interval: Option<u64>,
+
     /// Exit Akula after sync is complete and there's no progress.
     #[clap(long)]
     pub exit_after_sync: bool,
 
+    /// Forward new blocks announced by peers and announce our own new heads.
+    /// Only for networks that are not merged yet.
+    #[clap(long)]
+    pub block_gossip: bool,
+
+    /// File with hex encoded node key of the sentry. Enables discovery of peers for the sentry
+    /// to dial, on the ports below, in addition to its own.
+    #[clap(long = "discovery.nodekey", parse(from_os_str))]
+    pub discovery_nodekey: Option<PathBuf>,
+
+    /// UDP port of node discovery v4.
+    #[clap(long = "discovery.v4-port")]
+    pub discovery_v4_port: Option<u16>,
+
+    /// UDP port of node discovery v5.
+    #[clap(long = "discovery.v5-port")]
+    pub discovery_v5_port: Option<u16>,
+
+    /// Node records (`enr:...`) to bootstrap discovery v5 from.
+    #[clap(long = "discovery.v5-bootnodes", multiple_occurrences(true))]
+    pub discovery_v5_bootnodes: Vec<String>,
+
+    /// Public IP address advertised in our node record, with the sentry listening on
+    /// `--discovery.tcp-port`.
+    #[clap(long = "discovery.public-ip")]
+    pub discovery_public_ip: Option<IpAddr>,
+
+    /// RLPx port of the sentry.
+    #[clap(long = "discovery.tcp-port", default_value = "30303")]
+    pub discovery_tcp_port: u16,
+
+    /// Stop offering peers to the sentry once it has this many.
+    #[clap(long = "discovery.max-peers", default_value = "50")]
+    pub discovery_max_peers: usize,
+
     /// Delay applied at the terminating stage.
     #[clap(long, default_value = "2000")]
     pub delay_after_sync: u64,
+
+    /// Unwind stages by at most this many blocks per commit.
+    #[clap(long, default_value = "10000")]
+    pub unwind_batch_size: u64,
+
+    /// Log database operations and cursor walks slower than this (ms).
+    #[clap(long = "db.slow-query-threshold")]
+    pub db_slow_query_threshold: Option<u64>,
+
+    /// Maximum database size (GiB).
+    #[clap(long = "db.max-size", default_value = "4096")]
+    pub db_max_size: u64,
+
+    /// Grow database by this much at a time (GiB).
+    #[clap(long = "db.growth-step", default_value = "4")]
+    pub db_growth_step: u64,
+
+    /// Pause sync when free space on the database volume drops below this (GiB). Zero disables the check.
+    #[clap(long = "db.min-free-space", default_value = "8")]
+    pub db_min_free_space: u64,
+
+    /// TOML file with settings that are reloaded on SIGHUP, e.g. log levels.
+    #[clap(long, parse(from_os_str))]
+    pub config: Option<PathBuf>,
+
+    /// Prune data of old blocks, any of: h - history, r - receipts, t - transaction lookups,
+    /// c - call traces. Pruned blocks cannot be unwound.
+    #[clap(long = "prune", default_value = "")]
+    pub prune: PruneMode,
+
+    /// Keep history of this many blocks below the head, implies pruning history.
+    #[clap(long = "prune.h.older")]
+    pub prune_history_older: Option<u64>,
+
+    /// Keep receipts of this many blocks below the head, implies pruning receipts.
+    #[clap(long = "prune.r.older")]
+    pub prune_receipts_older: Option<u64>,
+
+    /// Keep transaction lookups of this many blocks below the head, implies pruning them.
+    #[clap(long = "prune.t.older")]
+    pub prune_tx_lookup_older: Option<u64>,
+
+    /// Keep call traces of this many blocks below the head, implies pruning call traces.
+    #[clap(long = "prune.c.older")]
+    pub prune_call_traces_older: Option<u64>,
+
+    /// Move history of old blocks from changesets into append-only files, one per step of
+    /// blocks. Such blocks cannot be unwound.
+    #[clap(long = "history.freeze")]
+    pub history_freeze: bool,
+
+    /// Move history of blocks at least this deep below the head.
+    #[clap(long = "history.freeze-depth", default_value = "90000")]
+    pub history_freeze_depth: u64,
+
+    /// Publish snapshots of frozen history into this directory, to be served over HTTP or seeded.
+    #[clap(long = "snapshot.publish-dir", parse(from_os_str))]
+    pub snapshot_publish_dir: Option<PathBuf>,
+
+    /// File with hex encoded secret key that published snapshot manifests are signed with.
+    #[clap(long = "snapshot.publish-key", parse(from_os_str))]
+    pub snapshot_publish_key: Option<PathBuf>,
+
+    /// Blocks per published snapshot segment, a multiple of 1000.
+    #[clap(long = "snapshot.segment-size", default_value = "500000")]
+    pub snapshot_segment_size: u64,
+
+    /// Publish only blocks at least this deep below the head.
+    #[clap(long = "snapshot.freeze-depth", default_value = "90000")]
+    pub snapshot_freeze_depth: u64,
+
+    /// Delete bodies and transactions of published blocks from the database, leaving them
+    /// only in snapshot segments.
+    #[clap(long = "snapshot.delete-bodies")]
+    pub snapshot_delete_bodies: bool,
+
+    /// Bootstrap from snapshots at these HTTP URLs or local directories before syncing, trying
+    /// them in order for every file. Torrent webseeds can be used as HTTP mirrors.
+    #[clap(long = "snapshot.source", multiple_occurrences(true))]
+    pub snapshot_source: Vec<SnapshotSource>,
+
+    /// Snapshot segments to download at a time ahead of import.
+    #[clap(long = "snapshot.download-concurrency", default_value = "4")]
+    pub snapshot_download_concurrency: usize,
+
+    /// Address that snapshot manifests must be signed by.
+    #[clap(long = "snapshot.publisher")]
+    pub snapshot_publisher: Option<Address>,
+
+    /// Serve Engine API and follow the forkchoice of a consensus client instead of downloading
+    /// headers from peers. For networks that are merged already.
+    #[clap(long)]
+    pub engine_api: bool,
+
+    /// Engine API listen address.
+    #[clap(long = "engine.listen-address", default_value = "127.0.0.1:8551")]
+    pub engine_listen_address: SocketAddr,
+
+    /// File with hex encoded JWT secret shared with the consensus client, generated if missing.
+    /// Defaults to jwt.hex in the data directory.
+    #[clap(long = "engine.jwt-secret", parse(from_os_str))]
+    pub engine_jwt_secret: Option<PathBuf>,
+
+    /// JSON-RPC address of akula-rpc, whose pool transactions fill payloads built for the
+    /// consensus client. Payloads are built empty without it.
+    #[clap(long = "engine.template-url")]
+    pub engine_template_url: Option<String>,
+
+    /// Serve metrics in Prometheus format on this address.
+    #[clap(long = "metrics.listen-address")]
+    pub metrics_listen_address: Option<SocketAddr>,
 }
 
 #[derive(Debug)]
@@ -401,6 +595,7 @@ where
                         block_number,
                         block_hash,
                         body.uncles,
+                        body.withdrawals,
                         txs.into_iter()
                             .map(|v| {
                                 Ok(rlp::decode::<akula::models::MessageWithSignature>(&v)?
@@ -413,12 +608,13 @@ where
                 .collect_into_vec(&mut converted);
 
             for res in converted.drain(..) {
-                let (block_num, block_hash, uncles, txs) = res?;
+                let (block_num, block_hash, uncles, withdrawals, txs) = res?;
                 highest_block = block_num;
                 let body = BodyForStorage {
                     base_tx_id: starting_index,
                     tx_amount: txs.len().try_into()?,
                     uncles,
+                    withdrawals,
                 };
 
                 body_cur.append((block_num, block_hash), body).await?;
@@ -552,23 +748,24 @@ fn main() -> anyhow::Result<()> {
         .unwrap_or(false);
 
     // tracing setup
-    let env_filter = if std::env::var(EnvFilter::DEFAULT_ENV)
-        .unwrap_or_default()
-        .is_empty()
-    {
-        EnvFilter::new("akula=info")
-    } else {
-        EnvFilter::from_default_env()
-    };
+    let startup_filter = std::env::var(EnvFilter::DEFAULT_ENV)
+        .ok()
+        .filter(|directives| !directives.is_empty())
+        .unwrap_or_else(|| "akula=info".to_string());
+    let (env_filter, log_filter_handle) = reload::Layer::new(EnvFilter::new(&startup_filter));
     tracing_subscriber::registry()
+        .with(env_filter)
         .with(
             tracing_subscriber::fmt::layer()
                 .with_target(false)
                 .with_ansi(!nocolor),
         )
-        .with(env_filter)
         .init();
 
+    akula::kv::stats::set_slow_query_threshold(
+        opt.db_slow_query_threshold.map(Duration::from_millis),
+    );
+
     std::thread::Builder::new()
         .stack_size(64 * 1024 * 1024)
         .spawn(move || {
@@ -580,8 +777,24 @@ fn main() -> anyhow::Result<()> {
             rt.block_on(async move {
                 info!("Starting Akula ({})", version_string());
 
-                let chains_config = akula::sentry::chain_config::ChainsConfig::new()?;
-                let chain_config = chains_config.get(&opt.chain_name)?;
+                let config = Arc::new(ConfigReloader::new(opt.config.clone())?);
+                follow_log_filter(&config, log_filter_handle, startup_filter);
+                if opt.config.is_some() {
+                    config.reload_on_sighup()?;
+                }
+
+                let chain_config = if let Some(path) = &opt.chain_spec {
+                    akula::sentry::chain_config::ChainConfig::new(akula::res::chainspec::load(
+                        path,
+                    )?)
+                } else {
+                    akula::sentry::chain_config::ChainsConfig::new()?.get(&opt.chain_name)?
+                };
+                info!(
+                    "Chain {} with genesis {:?}",
+                    chain_config.chain_name(),
+                    chain_config.genesis_block_hash()
+                );
 
                 // database setup
                 let erigon_db = if let Some(erigon_data_dir) = opt.erigon_data_dir {
@@ -605,7 +818,11 @@ fn main() -> anyhow::Result<()> {
                     tempfile::tempdir_in(&etl_temp_path)
                         .context("failed to create ETL temp dir")?,
                 );
-                let db = akula::kv::new_database(&akula_chain_data_dir)?;
+                let db = Arc::new(akula::kv::new_database_with_geometry(
+                    &akula_chain_data_dir,
+                    byte_unit::n_gib_bytes!(opt.db_max_size as u128),
+                    byte_unit::n_gib_bytes!(opt.db_growth_step as u128),
+                )?);
                 async {
                     let txn = db.begin_mutable().await?;
                     if akula::genesis::initialize_genesis(
@@ -623,32 +840,173 @@ fn main() -> anyhow::Result<()> {
                 .instrument(span!(Level::INFO, "", " Genesis initialization "))
                 .await?;
 
+                if !opt.snapshot_source.is_empty() {
+                    let publisher = opt.snapshot_publisher.ok_or_else(|| {
+                        format_err!("--snapshot.publisher is required to bootstrap from snapshots")
+                    })?;
+                    let downloader = SnapshotDownloader::new(
+                        opt.snapshot_source.clone(),
+                        opt.data_dir.snapshot_download_dir(),
+                    )
+                    .with_concurrency(opt.snapshot_download_concurrency);
+                    snapshot::bootstrap(&*db, &downloader, publisher)
+                        .instrument(span!(Level::INFO, "", " Snapshot bootstrap "))
+                        .await?;
+                }
+
+                tokio::spawn(Sweeper::new(db.clone()).run());
+
+                if let Some(addr) = opt.metrics_listen_address {
+                    tokio::spawn(async move {
+                        if let Err(e) = metrics::serve(addr).await {
+                            error!("Metrics server stopped: {:#}", e);
+                        }
+                    });
+                }
+
                 let sentry_status_provider = SentryStatusProvider::new(chain_config.clone());
                 // staged sync setup
                 let mut staged_sync = stagedsync::StagedSync::new();
                 staged_sync.set_min_progress_to_commit_after_stage(1024);
                 staged_sync.set_max_block(opt.max_block);
+                staged_sync.set_unwind_batch_size(Some(opt.unwind_batch_size));
                 staged_sync.set_exit_after_sync(opt.exit_after_sync);
                 staged_sync.set_delay_after_sync(Some(Duration::from_millis(opt.delay_after_sync)));
+                staged_sync.set_disk_guard(Some(DiskGuard::new(
+                    &akula_chain_data_dir,
+                    byte_unit::n_gib_bytes!(opt.db_min_free_space as u128) as u64,
+                    byte_unit::n_gib_bytes!(opt.db_max_size as u128) as u64,
+                    byte_unit::n_gib_bytes!(opt.db_growth_step as u128) as u64,
+                )));
                 if let Some(erigon_db) = erigon_db.clone() {
                     staged_sync.push(ConvertHeaders {
                         db: erigon_db,
                         max_block: opt.max_block,
                         exit_after_progress: opt.increment,
                     });
+                } else if opt.engine_api {
+                    let secret = JwtSecret::load_or_generate(
+                        &opt.engine_jwt_secret
+                            .clone()
+                            .unwrap_or_else(|| opt.data_dir.jwt_secret_path()),
+                    )?;
+                    let (head_tx, head_rx) = watch::channel(None);
+                    let mut api =
+                        EngineApiServerImpl::new(db.clone(), etl_temp_dir.clone(), head_tx);
+                    if let Some(url) = &opt.engine_template_url {
+                        api = api.with_template_source(BlockTemplateSource::new(url)?);
+                    }
+                    let api = api.into_rpc();
+                    let addr = opt.engine_listen_address;
+                    tokio::spawn(async move {
+                        if let Err(e) = engine_api::serve(addr, secret, api).await {
+                            error!("Engine API stopped: {:#}", e);
+                        }
+                    });
+
+                    staged_sync.push(ForkchoiceHeaders::new(head_rx));
                 } else {
                     // sentry setup
                     let mut sentry_reactor = SentryClientReactor::new(
                         Box::new(SentryClientConnectorImpl::new(opt.sentry_api_addr.clone())),
                         sentry_status_provider.current_status_stream(),
-                    );
+                    )
+                    .with_ban_list(opt.data_dir.banned_peers_path())?;
                     sentry_reactor.start()?;
+                    let sentry = sentry_reactor.into_shared();
+
+                    if let Some(nodekey) = &opt.discovery_nodekey {
+                        let secret_key = snapshot::load_key(nodekey)?;
+                        let listen = |port| SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), port);
+                        let known = KnownNodes::load(opt.data_dir.known_nodes_path())?;
+                        let restart_nodes = known.best(
+                            known_nodes::RESTART_NODES,
+                            known_nodes::RESTART_MAX_AGE,
+                            SystemTime::now(),
+                        );
+                        info!("Loaded {} known nodes", known.len());
+
+                        let mut candidates = Candidates::new(discovery::REDIAL_INTERVAL);
+                        candidates.add_source(
+                            "known",
+                            Box::pin(tokio_stream::iter(
+                                restart_nodes.clone().into_iter().map(Ok),
+                            )),
+                        );
+                        if let Some(port) = opt.discovery_v4_port {
+                            // Lookups start from nodes that were our peers, as well.
+                            let bootnodes = chain_config
+                                .chain_spec()
+                                .p2p
+                                .bootnodes
+                                .iter()
+                                .map(|url| url.parse::<NodeRecord>())
+                                .chain(restart_nodes.into_iter().map(Ok))
+                                .collect::<anyhow::Result<Vec<_>>>()?;
+                            let discv4 = Discv4::start(
+                                secret_key,
+                                listen(port),
+                                opt.discovery_tcp_port,
+                                &bootnodes,
+                            )
+                            .await?;
+                            candidates.add_source("discv4", Box::pin(discv4));
+                        }
+                        if let Some(port) = opt.discovery_v5_port {
+                            let bootnodes = opt
+                                .discovery_v5_bootnodes
+                                .iter()
+                                .map(|enr| enr.parse().map_err(|e| format_err!("{}", e)))
+                                .collect::<anyhow::Result<Vec<_>>>()?;
+                            let discv5 = Discv5::start(
+                                secret_key,
+                                listen(port),
+                                opt.discovery_public_ip,
+                                opt.discovery_tcp_port,
+                                bootnodes,
+                                chain_config.clone(),
+                                sentry_status_provider.status_receiver(),
+                            )
+                            .await?;
+                            candidates.add_source("discv5", Box::pin(discv5));
+                        }
+                        if opt.discovery_v4_port.is_none() && opt.discovery_v5_port.is_none() {
+                            bail!("--discovery.nodekey needs --discovery.v4-port or --discovery.v5-port");
+                        }
+
+                        let discovery = PeerDiscovery::new(
+                            sentry.clone(),
+                            candidates,
+                            opt.discovery_max_peers,
+                            known,
+                        );
+                        tokio::spawn(async move {
+                            if let Err(e) = discovery.run().await {
+                                warn!("Peer discovery stopped: {}", e);
+                            }
+                        });
+                    }
+
+                    if opt.block_gossip {
+                        let block_gossip = BlockGossip::new(
+                            sentry.clone(),
+                            sentry_status_provider.status_receiver(),
+                            db.clone(),
+                            engine_factory(chain_config.chain_spec().clone())?,
+                        );
+                        tokio::spawn(async move {
+                            if let Err(e) = block_gossip.run().await {
+                                warn!("Block gossip stopped: {}", e);
+                            }
+                        });
+                    }
 
                     staged_sync.push(HeaderDownload::new(
                         chain_config,
+                        opt.downloader_opts.preverified_hashes_file.as_deref(),
                         opt.downloader_opts.headers_mem_limit(),
                         opt.downloader_opts.headers_batch_size,
-                        sentry_reactor.into_shared(),
+                        sentry,
                         sentry_status_provider,
                     )?);
                 }
@@ -677,19 +1035,80 @@ fn main() -> anyhow::Result<()> {
                     batch_until: None,
                     commit_every: None,
                     prune_from: BlockNumber(0),
+                    analysis_cache: AnalysisCache::new(opt.execution_analysis_cache_size),
+                    contract_gas_top: opt.execution_contract_gas_top,
                 });
                 if !opt.skip_commitment {
-                    staged_sync.push(HashState::new(etl_temp_dir.clone(), None));
-                    staged_sync.push(Interhashes::new(etl_temp_dir.clone(), None));
+                    staged_sync.push(
+                        HashState::new(etl_temp_dir.clone(), None)
+                            .with_preimages(opt.hash_state_preimages),
+                    );
+                    let mut interhashes = Interhashes::new(etl_temp_dir.clone(), None)
+                        .with_diff_dump(StateRootDiffDump {
+                            dir: opt.data_dir.state_root_diff_dir(),
+                            reference_node: opt.state_root_reference_node.clone(),
+                            max_entries: opt.state_root_diff_entries,
+                        });
+                    if let Some(interval) = opt.unsafe_state_root_interval {
+                        warn!(
+                            "State root is only verified every {} blocks until caught up",
+                            interval
+                        );
+                        interhashes = interhashes.with_deferred_verification(interval);
+                    }
+                    staged_sync.push(interhashes);
                 }
+                staged_sync.push(LogIndex {
+                    temp_dir: etl_temp_dir.clone(),
+                    flush_interval: 50_000,
+                });
                 staged_sync.push(CallTraceIndex {
                     temp_dir: etl_temp_dir.clone(),
                     flush_interval: 50_000,
                 });
+                let mut prune = opt.prune;
+                for (distance, older) in [
+                    (&mut prune.history, opt.prune_history_older),
+                    (&mut prune.receipts, opt.prune_receipts_older),
+                    (&mut prune.tx_lookup, opt.prune_tx_lookup_older),
+                    (&mut prune.call_traces, opt.prune_call_traces_older),
+                ] {
+                    if older.is_some() {
+                        *distance = older;
+                    }
+                }
+                if !prune.is_empty() {
+                    staged_sync.push(Prune { mode: prune });
+                }
+                if opt.history_freeze {
+                    staged_sync.push(HistoryFreeze {
+                        dir: opt.data_dir.history_dir(),
+                        temp_dir: etl_temp_dir.clone(),
+                        freeze_depth: opt.history_freeze_depth,
+                    });
+                }
+                if let Some(dir) = opt.snapshot_publish_dir.clone() {
+                    if opt.snapshot_segment_size == 0 {
+                        bail!("--snapshot.segment-size must be positive");
+                    }
+                    let key_path = opt.snapshot_publish_key.as_deref().ok_or_else(|| {
+                        format_err!("--snapshot.publish-key is required to publish snapshots")
+                    })?;
+                    // Blocks published so far are read from segments once their bodies are
+                    // deleted.
+                    snapshot::set_block_provider(snapshot::BlockProvider::open(&dir)?)?;
+                    staged_sync.push(SnapshotPublication {
+                        dir,
+                        segment_size: opt.snapshot_segment_size,
+                        freeze_depth: opt.snapshot_freeze_depth,
+                        delete_bodies: opt.snapshot_delete_bodies,
+                        key: snapshot::load_key(key_path)?,
+                    });
+                }
                 staged_sync.push(FinishStage);
 
                 info!("Running staged sync");
-                staged_sync.run(&db).await?;
+                staged_sync.run(&*db).await?;
 
                 Ok(())
             })
@@ -1,6 +1,7 @@
 #![feature(let_else)]
 #![allow(clippy::suspicious_else_formatting)]
 use akula::{
+    chain::protocol_param::param,
     consensus::{
         difficulty::{canonical_difficulty, BlockDifficultyBombData},
         *,
@@ -164,7 +165,27 @@ fn testconfig(
     let mut spec = MAINNET.clone();
     spec.name = format!("{:?}", name);
     spec.consensus.eip1559_block = upgrades.london;
-    let SealVerificationParams::Ethash { difficulty_bomb, skip_pow_verification, homestead_formula, byzantium_formula,.. } = &mut spec.consensus.seal_verification else { unreachable!() };
+    // Networks of the test suite never leave proof-of-work.
+    spec.consensus.terminal_total_difficulty = None;
+    let SealVerificationParams::Ethash {
+        block_reward,
+        difficulty_bomb,
+        skip_pow_verification,
+        homestead_formula,
+        byzantium_formula,
+        ..
+    } = &mut spec.consensus.seal_verification
+    else {
+        unreachable!()
+    };
+    *block_reward = [
+        (Some(BlockNumber(0)), param::BLOCK_REWARD_FRONTIER),
+        (upgrades.byzantium, param::BLOCK_REWARD_BYZANTIUM),
+        (upgrades.constantinople, param::BLOCK_REWARD_CONSTANTINOPLE),
+    ]
+    .into_iter()
+    .filter_map(|(block, reward)| Some((block?, U256::from(reward))))
+    .collect();
     *difficulty_bomb = Some(DifficultyBomb {
         delays: btreemap! { BlockNumber(0) => bomb_delay },
     });
@@ -298,6 +319,7 @@ static NETWORK_CONFIG: Lazy<HashMap<Network, ChainSpec>> = Lazy::new(|| {
                 istanbul: Some(0.into()),
                 berlin: Some(0.into()),
                 london: Some(0.into()),
+                ..Default::default()
             },
             None,
             9700000,
@@ -368,6 +390,7 @@ static NETWORK_CONFIG: Lazy<HashMap<Network, ChainSpec>> = Lazy::new(|| {
                 istanbul: Some(0.into()),
                 berlin: Some(0.into()),
                 london: Some(5.into()),
+                ..Default::default()
             },
             None,
             9700000,
@@ -399,6 +422,7 @@ static NETWORK_CONFIG: Lazy<HashMap<Network, ChainSpec>> = Lazy::new(|| {
                 istanbul: Some(0.into()),
                 berlin: Some(0.into()),
                 london: Some(0.into()),
+                ..Default::default()
             },
             None,
             10700000,
@@ -801,7 +825,15 @@ async fn difficulty_test(testdata: HashMap<String, Value>) -> anyhow::Result<()>
             };
 
             let config = NETWORK_CONFIG[&network].clone();
-            let SealVerificationParams::Ethash { homestead_formula, byzantium_formula, difficulty_bomb, .. } = config.consensus.seal_verification else {unreachable!()};
+            let SealVerificationParams::Ethash {
+                homestead_formula,
+                byzantium_formula,
+                difficulty_bomb,
+                ..
+            } = config.consensus.seal_verification
+            else {
+                unreachable!()
+            };
 
             let calculated_difficulty = canonical_difficulty(
                 testdata.current_block_number,
@@ -869,6 +901,10 @@ pub struct Opt {
     pub tests: PathBuf,
     #[clap(long)]
     pub test_names: Vec<String>,
+    /// Execute bytecode with EVMC VM from this shared library instead of the native interpreter
+    #[cfg(feature = "evmc")]
+    #[clap(long)]
+    pub evmc: Option<PathBuf>,
 }
 
 #[derive(Debug, Default)]
@@ -927,6 +963,13 @@ async fn run() {
         .with(env_filter)
         .init();
 
+    #[cfg(feature = "evmc")]
+    if let Some(path) = &opt.evmc {
+        let vm = akula::execution::evmc::EvmcVm::load(path).unwrap();
+        println!("Using EVMC VM {} {}", vm.name(), vm.version());
+        akula::execution::evmc::set_external_vm(vm).unwrap();
+    }
+
     let root_dir = opt.tests;
     let test_names = Arc::new(opt.test_names.into_iter().collect());
 
@@ -0,0 +1,38 @@
+//! Feed fuzzed frames into the eth message decoder, the way peers reach it through the sentry: RLP
+//! encoded message id followed by the message payload.
+//!
+//! Decoding must never panic, and whatever decodes must survive a roundtrip. Run with
+//! `-malloc_limit_mb` to also catch allocations out of proportion to the frame size.
+#![no_main]
+
+use akula::sentry::{message_decoder::decode_rlp_message, messages::EthMessageId};
+use libfuzzer_sys::fuzz_target;
+use rlp::Rlp;
+use strum::IntoEnumIterator;
+
+fuzz_target!(|frame: &[u8]| {
+    let header = match Rlp::new(frame).payload_info() {
+        Ok(header) => header,
+        Err(_) => return,
+    };
+    let id_len = match header.header_len.checked_add(header.value_len) {
+        Some(len) if len <= frame.len() => len,
+        _ => return,
+    };
+    let code = match Rlp::new(&frame[..id_len]).as_val::<u8>() {
+        Ok(code) => code,
+        Err(_) => return,
+    };
+    let id = match EthMessageId::iter().find(|id| *id as u8 == code) {
+        Some(id) => id,
+        None => return,
+    };
+
+    if let Ok(message) = decode_rlp_message(id, &frame[id_len..]) {
+        assert_eq!(message.eth_id(), id);
+        assert_eq!(
+            decode_rlp_message(id, &rlp::encode(&message)).unwrap(),
+            message
+        );
+    }
+});
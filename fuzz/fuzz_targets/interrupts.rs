@@ -0,0 +1,222 @@
+//! Drive the interpreter through its interrupts, answering every one of them with fuzzed data.
+//!
+//! The host does not keep any state, so the interpreter sees a world where balances, code sizes,
+//! storage values and call results are arbitrary and not consistent with each other.
+#![no_main]
+
+use akula::{
+    execution::host::{self, HostInterface},
+    models::*,
+};
+use arbitrary::Arbitrary;
+use async_trait::async_trait;
+use bytes::Bytes;
+use evmodin::{
+    host::*, AnalyzedCode, CallKind, Message as EvmMessage, Output, Revision, StatusCode,
+};
+use libfuzzer_sys::fuzz_target;
+
+const REVISIONS: &[Revision] = &[
+    Revision::Frontier,
+    Revision::Homestead,
+    Revision::Tangerine,
+    Revision::Spurious,
+    Revision::Byzantium,
+    Revision::Constantinople,
+    Revision::Petersburg,
+    Revision::Istanbul,
+    Revision::Berlin,
+    Revision::London,
+];
+
+const STATUS_CODES: &[StatusCode] = &[
+    StatusCode::Success,
+    StatusCode::Failure,
+    StatusCode::Revert,
+    StatusCode::OutOfGas,
+    StatusCode::InvalidInstruction,
+    StatusCode::UndefinedInstruction,
+    StatusCode::StackOverflow,
+    StatusCode::StackUnderflow,
+    StatusCode::BadJumpDestination,
+    StatusCode::InvalidMemoryAccess,
+    StatusCode::CallDepthExceeded,
+    StatusCode::StaticModeViolation,
+    StatusCode::PrecompileFailure,
+    StatusCode::ContractValidationFailure,
+    StatusCode::ArgumentOutOfRange,
+    StatusCode::InsufficientBalance,
+];
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    code: Vec<u8>,
+    input_data: Vec<u8>,
+    gas: u32,
+    is_static: bool,
+    trace: bool,
+    revision: u8,
+    value: [u8; 32],
+    /// Answers to interrupts, reused from the start once exhausted.
+    answers: Vec<Answer>,
+}
+
+#[derive(Arbitrary, Clone, Debug)]
+struct Answer {
+    flag: bool,
+    word: [u8; 32],
+    number: u64,
+    status: u8,
+    gas_left: i64,
+    data: Vec<u8>,
+}
+
+struct FuzzHost {
+    answers: Vec<Answer>,
+    next: usize,
+}
+
+impl FuzzHost {
+    fn answer(&mut self) -> Answer {
+        if self.answers.is_empty() {
+            return Answer {
+                flag: false,
+                word: [0; 32],
+                number: 0,
+                status: 0,
+                gas_left: 0,
+                data: vec![],
+            };
+        }
+
+        let answer = self.answers[self.next % self.answers.len()].clone();
+        self.next += 1;
+        answer
+    }
+
+    fn word(&mut self) -> U256 {
+        U256::from_be_bytes(self.answer().word)
+    }
+}
+
+#[async_trait]
+impl HostInterface for FuzzHost {
+    async fn account_exists(&mut self, _: Address) -> anyhow::Result<bool> {
+        Ok(self.answer().flag)
+    }
+    async fn get_balance(&mut self, _: Address) -> anyhow::Result<U256> {
+        Ok(self.word())
+    }
+    async fn get_code_size(&mut self, _: Address) -> anyhow::Result<U256> {
+        Ok(self.word())
+    }
+    async fn get_storage(&mut self, _: Address, _: U256) -> anyhow::Result<U256> {
+        Ok(self.word())
+    }
+    async fn set_storage(&mut self, _: Address, _: U256, _: U256) -> anyhow::Result<StorageStatus> {
+        Ok(match self.answer().status % 5 {
+            0 => StorageStatus::Unchanged,
+            1 => StorageStatus::ModifiedAgain,
+            2 => StorageStatus::Modified,
+            3 => StorageStatus::Deleted,
+            _ => StorageStatus::Added,
+        })
+    }
+    async fn get_code_hash(&mut self, _: Address) -> anyhow::Result<U256> {
+        Ok(self.word())
+    }
+    async fn copy_code(&mut self, _: Address, _: usize, max_size: usize) -> anyhow::Result<Bytes> {
+        let mut code = self.answer().data;
+        code.truncate(max_size);
+        Ok(code.into())
+    }
+    async fn selfdestruct(&mut self, _: Address, _: Address) -> anyhow::Result<()> {
+        Ok(())
+    }
+    async fn call(&mut self, call: Call) -> anyhow::Result<Output> {
+        let answer = self.answer();
+        Ok(Output {
+            status_code: STATUS_CODES[answer.status as usize % STATUS_CODES.len()].clone(),
+            gas_left: answer.gas_left,
+            output_data: answer.data.into(),
+            create_address: match call {
+                Call::Create(_) if answer.flag => Some(Address::from_low_u64_be(answer.number)),
+                _ => None,
+            },
+        })
+    }
+    async fn get_tx_context(&mut self) -> anyhow::Result<TxContext> {
+        let answer = self.answer();
+        let word = U256::from_be_bytes(answer.word);
+        Ok(TxContext {
+            tx_gas_price: word,
+            tx_origin: Address::from_low_u64_be(answer.number),
+            block_coinbase: Address::from_low_u64_be(!answer.number),
+            block_number: answer.number,
+            block_timestamp: answer.number,
+            block_gas_limit: answer.number,
+            block_difficulty: word,
+            chain_id: word,
+            block_base_fee: word,
+        })
+    }
+    async fn get_block_hash(&mut self, _: u64) -> anyhow::Result<U256> {
+        Ok(self.word())
+    }
+    fn emit_log(&mut self, _: Log) {}
+    fn access_account(&mut self, _: Address) -> AccessStatus {
+        if self.answer().flag {
+            AccessStatus::Warm
+        } else {
+            AccessStatus::Cold
+        }
+    }
+    fn access_storage(&mut self, _: Address, _: U256) -> AccessStatus {
+        if self.answer().flag {
+            AccessStatus::Warm
+        } else {
+            AccessStatus::Cold
+        }
+    }
+}
+
+fuzz_target!(|input: Input| {
+    let Input {
+        code,
+        input_data,
+        gas,
+        is_static,
+        trace,
+        revision,
+        value,
+        answers,
+    } = input;
+
+    let contract = Address::from_low_u64_be(0xc0de);
+    let message = EvmMessage {
+        kind: CallKind::Call,
+        is_static,
+        depth: 0,
+        gas: gas.into(),
+        recipient: contract,
+        code_address: contract,
+        sender: Address::from_low_u64_be(0xca11),
+        input_data: input_data.into(),
+        value: U256::from_be_bytes(value),
+    };
+    let revision = REVISIONS[revision as usize % REVISIONS.len()];
+
+    let mut host = FuzzHost { answers, next: 0 };
+
+    tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap()
+        .block_on(async move {
+            let interrupt = AnalyzedCode::analyze(code)
+                .execute_resumable(trace, message, revision)
+                .resume(());
+
+            // Host never fails, only the interpreter may panic.
+            host::run(&mut host, 0, interrupt).await.unwrap();
+        });
+});
@@ -0,0 +1,220 @@
+//! Execute fuzzed transactions against fuzzed pre-state: missing accounts, huge balances and nonces,
+//! empty or garbage code and precompile addresses are all fair game.
+#![no_main]
+
+use akula::{
+    consensus::{self, pre_validate_transaction},
+    crypto::keccak256,
+    execution::{analysis_cache::AnalysisCache, processor::ExecutionProcessor},
+    models::*,
+    res::chainspec::MAINNET,
+    InMemoryState, State,
+};
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use std::collections::HashMap;
+
+/// First block of every mainnet revision.
+const FORK_BLOCKS: &[u64] = &[
+    0, 1_150_000, 2_463_000, 2_675_000, 4_370_000, 7_280_000, 9_069_000, 12_244_000, 12_965_000,
+];
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    fork: u8,
+    base_fee: u32,
+    accounts: Vec<FuzzAccount>,
+    transactions: Vec<FuzzTransaction>,
+}
+
+#[derive(Arbitrary, Debug)]
+struct FuzzAccount {
+    address: u8,
+    nonce: u64,
+    balance: [u8; 32],
+    code: Vec<u8>,
+    storage: Vec<([u8; 32], [u8; 32])>,
+}
+
+#[derive(Arbitrary, Debug)]
+struct FuzzTransaction {
+    kind: u8,
+    sender: u8,
+    to: Option<u8>,
+    /// Added to the expected nonce.
+    nonce_offset: u8,
+    wrong_chain_id: bool,
+    gas_limit: u32,
+    max_priority_fee_per_gas: [u8; 32],
+    max_fee_per_gas: [u8; 32],
+    value: [u8; 32],
+    input: Vec<u8>,
+    access_list: Vec<(u8, Vec<[u8; 32]>)>,
+}
+
+/// Small address space, so that transactions hit prepared accounts and precompiles.
+fn address(index: u8) -> Address {
+    match index % 16 {
+        i @ 0..=8 => Address::from_low_u64_be(i as u64 + 1),
+        i => Address::from_low_u64_be(0x1000 + i as u64),
+    }
+}
+
+fuzz_target!(|input: Input| {
+    tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap()
+        .block_on(async move {
+            let number = FORK_BLOCKS[input.fork as usize % FORK_BLOCKS.len()];
+            let block_spec = MAINNET.collect_block_spec(number);
+            let header = PartialHeader {
+                number: number.into(),
+                beneficiary: address(15),
+                gas_limit: 30_000_000,
+                base_fee_per_gas: MAINNET
+                    .upgrades
+                    .london
+                    .filter(|&london| BlockNumber(number) >= london)
+                    .map(|_| U256::from(input.base_fee)),
+                ..PartialHeader::empty()
+            };
+
+            let mut state = InMemoryState::default();
+            let mut nonces = HashMap::new();
+            state.begin_block(BlockNumber(number));
+            for account in input.accounts {
+                let address = address(account.address);
+                let code_hash = keccak256(&account.code);
+                state.update_account(
+                    address,
+                    None,
+                    Some(Account {
+                        nonce: account.nonce,
+                        balance: U256::from_be_bytes(account.balance),
+                        code_hash,
+                    }),
+                );
+                state
+                    .update_code(code_hash, account.code.into())
+                    .await
+                    .unwrap();
+                for (location, value) in account.storage {
+                    state
+                        .update_storage(
+                            address,
+                            U256::from_be_bytes(location),
+                            U256::ZERO,
+                            U256::from_be_bytes(value),
+                        )
+                        .await
+                        .unwrap();
+                }
+                nonces.insert(address, account.nonce);
+            }
+
+            let transactions = input
+                .transactions
+                .into_iter()
+                .map(|txn| {
+                    let sender = address(txn.sender);
+                    let chain_id = if txn.wrong_chain_id {
+                        ChainId(block_spec.params.chain_id.0 ^ 1)
+                    } else {
+                        block_spec.params.chain_id
+                    };
+                    let nonce = nonces
+                        .get(&sender)
+                        .copied()
+                        .unwrap_or(0)
+                        .wrapping_add(txn.nonce_offset.into());
+                    let action = match txn.to {
+                        Some(to) => TransactionAction::Call(address(to)),
+                        None => TransactionAction::Create,
+                    };
+                    let max_fee_per_gas = U256::from_be_bytes(txn.max_fee_per_gas);
+                    let value = U256::from_be_bytes(txn.value);
+                    let input = txn.input.into();
+                    let gas_limit = txn.gas_limit.into();
+                    let access_list = txn
+                        .access_list
+                        .into_iter()
+                        .map(|(address_index, slots)| AccessListItem {
+                            address: address(address_index),
+                            slots: slots.into_iter().map(H256).collect(),
+                        })
+                        .collect();
+
+                    let message = match txn.kind % 3 {
+                        0 => Message::Legacy {
+                            chain_id: Some(chain_id),
+                            nonce,
+                            gas_price: max_fee_per_gas,
+                            gas_limit,
+                            action,
+                            value,
+                            input,
+                        },
+                        1 => Message::EIP2930 {
+                            chain_id,
+                            nonce,
+                            gas_price: max_fee_per_gas,
+                            gas_limit,
+                            action,
+                            value,
+                            input,
+                            access_list,
+                        },
+                        _ => Message::EIP1559 {
+                            chain_id,
+                            nonce,
+                            max_priority_fee_per_gas: U256::from_be_bytes(
+                                txn.max_priority_fee_per_gas,
+                            ),
+                            max_fee_per_gas,
+                            gas_limit,
+                            action,
+                            value,
+                            input,
+                            access_list,
+                        },
+                    };
+
+                    MessageWithSender { message, sender }
+                })
+                .collect();
+            let block = BlockBodyWithSenders {
+                transactions,
+                ommers: vec![],
+            };
+
+            let mut analysis_cache = AnalysisCache::default();
+            let mut engine = consensus::engine_factory(MAINNET.clone()).unwrap();
+            let mut processor = ExecutionProcessor::new(
+                &mut state,
+                None,
+                &mut analysis_cache,
+                &mut *engine,
+                &header,
+                &block,
+                &block_spec,
+            );
+
+            for txn in &block.transactions {
+                if pre_validate_transaction(
+                    txn,
+                    block_spec.params.chain_id,
+                    header.base_fee_per_gas,
+                )
+                .is_err()
+                {
+                    continue;
+                }
+                if processor.validate_transaction(txn).await.is_err() {
+                    continue;
+                }
+
+                // Errors such as an exhausted block gas pool are fine, panics are not.
+                let _ = processor.execute_transaction(txn).await;
+            }
+        });
+});
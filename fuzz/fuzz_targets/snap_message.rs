@@ -0,0 +1,38 @@
+//! Feed fuzzed frames into the snap message decoder: RLP encoded message id followed by the
+//! message payload.
+//!
+//! Decoding must never panic, and whatever decodes must survive a roundtrip. Run with
+//! `-malloc_limit_mb` to also catch allocations out of proportion to the frame size.
+#![no_main]
+
+use akula::sentry::snap::{decode_snap_message, SnapMessageId};
+use libfuzzer_sys::fuzz_target;
+use rlp::Rlp;
+use strum::IntoEnumIterator;
+
+fuzz_target!(|frame: &[u8]| {
+    let header = match Rlp::new(frame).payload_info() {
+        Ok(header) => header,
+        Err(_) => return,
+    };
+    let id_len = match header.header_len.checked_add(header.value_len) {
+        Some(len) if len <= frame.len() => len,
+        _ => return,
+    };
+    let code = match Rlp::new(&frame[..id_len]).as_val::<u8>() {
+        Ok(code) => code,
+        Err(_) => return,
+    };
+    let id = match SnapMessageId::iter().find(|id| *id as u8 == code) {
+        Some(id) => id,
+        None => return,
+    };
+
+    if let Ok(message) = decode_snap_message(id, &frame[id_len..]) {
+        assert_eq!(message.snap_id(), id);
+        assert_eq!(
+            decode_snap_message(id, &rlp::encode(&message)).unwrap(),
+            message
+        );
+    }
+});
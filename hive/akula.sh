@@ -0,0 +1,55 @@
+#!/bin/bash
+
+# Startup script for Akula in Hive.
+#
+# Hive provides the genesis in /genesis.json and the chain configuration in environment
+# variables:
+#
+#  - HIVE_CHAIN_ID               chain ID
+#  - HIVE_BOOTNODE               enode URL of the node to connect to
+#  - HIVE_FORK_*                 fork blocks, see mapper.jq
+#  - HIVE_SHANGHAI_TIMESTAMP     Shanghai fork timestamp, only supported at genesis
+#  - HIVE_CLIQUE_PERIOD          Clique block period, enables Clique instead of Ethash
+#  - HIVE_TERMINAL_TOTAL_DIFFICULTY  enables the Engine API
+#  - HIVE_LOGLEVEL               0 (silent) to 5 (trace)
+#
+# Importing /chain.rlp and /blocks is not supported.
+
+set -e
+
+case "$HIVE_LOGLEVEL" in
+    0|1) export RUST_LOG=error ;;
+    2)   export RUST_LOG=warn ;;
+    4)   export RUST_LOG=debug ;;
+    5)   export RUST_LOG=trace ;;
+    *)   export RUST_LOG=info ;;
+esac
+
+mkdir -p /akula /sentry
+jq -f /hive-bin/mapper.jq /genesis.json > /hive.json
+
+# The sentry owns the devp2p identity. Generate its key up front so that enode.sh
+# reports the same node.
+akula-toolbox enode --nodekey /sentry/nodekey > /dev/null
+
+sentry_flags="--datadir=/sentry --port=30303 --sentry.api.addr=127.0.0.1:8000"
+if [ -n "$HIVE_BOOTNODE" ]; then
+    sentry_flags="$sentry_flags --staticpeers=$HIVE_BOOTNODE"
+fi
+sentry $sentry_flags &
+
+akula_flags="--datadir=/akula --chain.spec=/hive.json --sentry.api.addr=http://127.0.0.1:8000"
+if [ -n "$HIVE_TERMINAL_TOTAL_DIFFICULTY" ]; then
+    # The secret used by all Hive simulators.
+    echo "0x7365637265747365637265747365637265747365637265747365637265747365" > /jwt.hex
+    akula_flags="$akula_flags --engine-api --engine.listen-address=0.0.0.0:8551"
+    akula_flags="$akula_flags --engine.jwt-secret=/jwt.hex"
+fi
+akula $akula_flags &
+
+# The RPC daemon needs the database that Akula creates on startup.
+while [ ! -d /akula/chaindata ]; do
+    sleep 0.1
+done
+
+exec akula-rpc --datadir=/akula --listen-address=0.0.0.0:8545 --ws-listen-address=0.0.0.0:8546
@@ -0,0 +1,7 @@
+#!/bin/bash
+
+# Prints the enode URL of the sentry for Hive.
+
+set -e
+
+akula-toolbox enode --nodekey /sentry/nodekey --ip "$(hostname -i | awk '{print $1}')" --port 30303
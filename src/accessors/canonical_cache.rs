@@ -0,0 +1,214 @@
+use super::chain;
+use crate::{kv::traits::*, models::*};
+use anyhow::format_err;
+use parking_lot::RwLock;
+use std::collections::{BTreeMap, HashMap};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CanonicalBlock {
+    pub hash: H256,
+    pub header: BlockHeader,
+    pub total_difficulty: U256,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    head: Option<BlockNumber>,
+    blocks: BTreeMap<BlockNumber, CanonicalBlock>,
+    numbers: HashMap<H256, BlockNumber>,
+}
+
+impl Inner {
+    fn remove(&mut self, removed: BTreeMap<BlockNumber, CanonicalBlock>) {
+        for block in removed.into_values() {
+            self.numbers.remove(&block.hash);
+        }
+    }
+}
+
+/// Headers, hashes and total difficulties of the most recent canonical blocks.
+///
+/// Lets hot lookups near head, such as current block number or latest header, skip the database.
+/// Contents are only updated by [`Self::refresh`], which is meant to be called on every new head;
+/// lookups of blocks outside of the cached range return `None` and should go to the database.
+#[derive(Debug)]
+pub struct CanonicalCache {
+    capacity: usize,
+    inner: RwLock<Inner>,
+}
+
+impl CanonicalCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            inner: Default::default(),
+        }
+    }
+
+    /// Load the last `capacity` canonical blocks up to `head`.
+    ///
+    /// Only blocks that are not cached yet or were replaced by a reorg are read.
+    pub async fn refresh<'db, Tx: Transaction<'db>>(
+        &self,
+        tx: &Tx,
+        head: BlockNumber,
+    ) -> anyhow::Result<()> {
+        let from = BlockNumber((head.0 + 1).saturating_sub(self.capacity as u64));
+
+        // Walk down from head until a block we already have, its ancestors are cached too.
+        let mut fresh = vec![];
+        let mut number = head;
+        while number >= from {
+            let hash = chain::canonical_hash::read(tx, number)
+                .await?
+                .ok_or_else(|| format_err!("canonical hash of block {} not found", number))?;
+            if self
+                .inner
+                .read()
+                .blocks
+                .get(&number)
+                .map_or(false, |block| block.hash == hash)
+            {
+                break;
+            }
+            let header = chain::header::read(tx, hash, number)
+                .await?
+                .ok_or_else(|| format_err!("header {}/{:?} not found", number, hash))?;
+            let total_difficulty = chain::td::read(tx, hash, number).await?.ok_or_else(|| {
+                format_err!("total difficulty of {}/{:?} not found", number, hash)
+            })?;
+            fresh.push(CanonicalBlock {
+                hash,
+                header,
+                total_difficulty,
+            });
+
+            match number.0.checked_sub(1) {
+                Some(parent) => number = BlockNumber(parent),
+                None => break,
+            }
+        }
+
+        let mut inner = self.inner.write();
+        // Blocks above the new head were unwound, those we re-read are replaced.
+        let first_stale = fresh
+            .last()
+            .map_or(head.0 + 1, |block| block.header.number.0);
+        let stale = inner.blocks.split_off(&BlockNumber(first_stale));
+        inner.remove(stale);
+        let kept = inner.blocks.split_off(&from);
+        let evicted = std::mem::replace(&mut inner.blocks, kept);
+        inner.remove(evicted);
+
+        for block in fresh {
+            inner.numbers.insert(block.hash, block.header.number);
+            inner.blocks.insert(block.header.number, block);
+        }
+        inner.head = Some(head);
+
+        Ok(())
+    }
+
+    /// Head as of the last refresh.
+    pub fn head(&self) -> Option<BlockNumber> {
+        self.inner.read().head
+    }
+
+    pub fn by_number(&self, number: BlockNumber) -> Option<CanonicalBlock> {
+        self.inner.read().blocks.get(&number).cloned()
+    }
+
+    pub fn by_hash(&self, hash: H256) -> Option<CanonicalBlock> {
+        let inner = self.inner.read();
+        inner
+            .numbers
+            .get(&hash)
+            .and_then(|number| inner.blocks.get(number))
+            .cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        kv::{new_mem_database, tables},
+        util::test_util::run_test,
+    };
+
+    async fn insert_chain<'db, RwTx: MutableTransaction<'db>>(
+        tx: &RwTx,
+        length: u64,
+        extra_data: u8,
+    ) -> Vec<H256> {
+        let mut hashes = vec![];
+        for number in 0..length {
+            let header = BlockHeader {
+                extra_data: vec![extra_data].into(),
+                ..BlockHeader::new(
+                    PartialHeader {
+                        number: BlockNumber(number),
+                        ..PartialHeader::empty()
+                    },
+                    EMPTY_LIST_HASH,
+                    EMPTY_ROOT,
+                )
+            };
+            let hash = header.hash();
+            tx.set(tables::Header, (BlockNumber(number), hash), header)
+                .await
+                .unwrap();
+            tx.set(
+                tables::HeadersTotalDifficulty,
+                (BlockNumber(number), hash),
+                U256::from(number),
+            )
+            .await
+            .unwrap();
+            chain::canonical_hash::write(tx, number, hash)
+                .await
+                .unwrap();
+            hashes.push(hash);
+        }
+        hashes
+    }
+
+    #[test]
+    fn follows_head_and_reorgs() {
+        run_test(async {
+            let db = new_mem_database().unwrap();
+            let tx = db.begin_mutable().await.unwrap();
+            let cache = CanonicalCache::new(4);
+
+            let hashes = insert_chain(&tx, 10, 0).await;
+            cache.refresh(&tx, BlockNumber(9)).await.unwrap();
+            assert_eq!(cache.head(), Some(BlockNumber(9)));
+            assert_eq!(cache.by_number(BlockNumber(6)).unwrap().hash, hashes[6]);
+            assert_eq!(cache.by_number(BlockNumber(5)), None);
+            assert_eq!(
+                cache.by_hash(hashes[8]).unwrap().total_difficulty,
+                U256::from(8_u64)
+            );
+
+            // Unwind to 7 and build a different chain on top of it.
+            let new_hashes = insert_chain(&tx, 12, 1).await;
+            for number in 0..=7_u64 {
+                chain::canonical_hash::write(&tx, number, hashes[number as usize])
+                    .await
+                    .unwrap();
+            }
+            cache.refresh(&tx, BlockNumber(11)).await.unwrap();
+            assert_eq!(cache.by_hash(hashes[9]), None);
+            assert_eq!(
+                cache.by_hash(hashes[7]).unwrap().header.number,
+                BlockNumber(7)
+            );
+            assert_eq!(
+                cache.by_number(BlockNumber(11)).unwrap().hash,
+                new_hashes[11]
+            );
+            assert_eq!(cache.by_number(BlockNumber(7)).unwrap().hash, hashes[7]);
+            assert_eq!(cache.by_number(BlockNumber(6)), None);
+        })
+    }
+}
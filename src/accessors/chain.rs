@@ -1,10 +1,20 @@
 use crate::{
     kv::{tables, traits::*},
     models::*,
+    snapshot::provider::{block_provider, Snapshots},
 };
+use anyhow::format_err;
 use tokio_stream::StreamExt;
 use tracing::*;
 
+/// Look up data that is not in the database in snapshots of the block provider, if it is set.
+fn frozen<T>(f: impl FnOnce(&Snapshots) -> anyhow::Result<Option<T>>) -> anyhow::Result<Option<T>> {
+    match block_provider() {
+        Some(provider) => f(&provider.snapshots()),
+        None => Ok(None),
+    }
+}
+
 pub mod canonical_hash {
     use super::*;
 
@@ -31,6 +41,20 @@ pub mod canonical_hash {
     }
 }
 
+pub mod chain_spec {
+    use super::*;
+
+    /// Chain spec the database was initialized with, kept under the genesis hash.
+    pub async fn read<'db, Tx: Transaction<'db>>(tx: &Tx) -> anyhow::Result<ChainSpec> {
+        let genesis_hash = canonical_hash::read(tx, 0)
+            .await?
+            .ok_or_else(|| format_err!("Genesis block absent"))?;
+        tx.get(tables::Config, genesis_hash)
+            .await?
+            .ok_or_else(|| format_err!("No chain config for genesis block {:?}", genesis_hash))
+    }
+}
+
 pub mod header_number {
     use super::*;
 
@@ -55,7 +79,25 @@ pub mod header {
         let number = number.into();
         trace!("Reading header for block {}/{:?}", number, hash);
 
-        tx.get(tables::Header, (number, hash)).await
+        if let Some(header) = tx.get(tables::Header, (number, hash)).await? {
+            return Ok(Some(header));
+        }
+
+        frozen(|snapshots| snapshots.header(hash, number))
+    }
+
+    /// Header of canonical block `number`, which has to exist.
+    pub async fn read_canonical<'db, Tx: Transaction<'db>>(
+        tx: &Tx,
+        number: impl Into<BlockNumber>,
+    ) -> anyhow::Result<BlockHeader> {
+        let number = number.into();
+        let hash = canonical_hash::read(tx, number)
+            .await?
+            .ok_or_else(|| format_err!("no canonical block {}", number))?;
+        read(tx, hash, number)
+            .await?
+            .ok_or_else(|| format_err!("header {}/{:?} not found", number, hash))
     }
 }
 
@@ -74,18 +116,29 @@ pub mod tx {
             base_tx_id
         );
 
-        if amount > 0 {
-            walk(
-                &mut tx.cursor(tables::BlockTransaction).await?,
-                Some(base_tx_id),
-            )
-            .take(amount)
-            .map(|res| res.map(|(_, v)| v))
-            .collect()
-            .await
-        } else {
-            Ok(vec![])
+        if amount == 0 {
+            return Ok(vec![]);
         }
+
+        let end = base_tx_id + amount as u64;
+        let txs = walk(
+            &mut tx.cursor(tables::BlockTransaction).await?,
+            Some(base_tx_id),
+        )
+        .take_while(|res| res.as_ref().map_or(true, |(id, _)| *id < end))
+        .map(|res| res.map(|(_, v)| v))
+        .collect::<anyhow::Result<Vec<_>>>()
+        .await?;
+
+        if txs.len() < amount {
+            if let Some(frozen) =
+                frozen(|snapshots| snapshots.transactions(base_tx_id, amount as u64))?
+            {
+                return Ok(frozen.into_iter().map(|(txn, _)| txn).collect());
+            }
+        }
+
+        Ok(txs)
     }
 
     pub async fn write<'db, RwTx: MutableTransaction<'db>>(
@@ -129,10 +182,19 @@ pub mod tx_sender {
             hash
         );
 
-        Ok(tx
-            .get(tables::TxSender, (number, hash))
-            .await?
-            .unwrap_or_default())
+        if let Some(senders) = tx.get(tables::TxSender, (number, hash)).await? {
+            return Ok(senders);
+        }
+
+        Ok(frozen(|snapshots| {
+            Ok(match snapshots.body(hash, number)? {
+                Some(body) => snapshots
+                    .transactions(body.base_tx_id, body.tx_amount)?
+                    .map(|txs| txs.into_iter().map(|(_, sender)| sender).collect()),
+                None => None,
+            })
+        })?
+        .unwrap_or_default())
     }
 
     pub async fn write<'db, RwTx: MutableTransaction<'db>>(
@@ -168,7 +230,11 @@ pub mod storage_body {
         let number = number.into();
         trace!("Reading storage body for block {}/{:?}", number, hash);
 
-        tx.get(tables::BlockBody, (number, hash)).await
+        if let Some(body) = tx.get(tables::BlockBody, (number, hash)).await? {
+            return Ok(Some(body));
+        }
+
+        frozen(|snapshots| snapshots.body(hash, number))
     }
 
     pub async fn has<'db, Tx: Transaction<'db>>(
@@ -212,6 +278,7 @@ pub mod block_body {
                 BlockBody {
                     transactions,
                     ommers: body.uncles,
+                    withdrawals: body.withdrawals,
                 },
                 body.base_tx_id,
             )));
@@ -248,6 +315,7 @@ pub mod block_body {
                     })
                     .collect(),
                 ommers: body.ommers,
+                withdrawals: body.withdrawals,
             }));
         }
 
@@ -305,6 +373,62 @@ pub mod tl {
     }
 }
 
+pub mod tx_offset {
+    use super::*;
+
+    /// Position of transaction `tx_hash` in its canonical block.
+    pub async fn read<'db, Tx: Transaction<'db>>(
+        tx: &Tx,
+        tx_hash: H256,
+    ) -> anyhow::Result<Option<u64>> {
+        trace!("Reading offset of tx {:?}", tx_hash);
+
+        tx.get(tables::BlockTransactionOffset, tx_hash).await
+    }
+}
+
+pub mod canonical_tx {
+    use super::*;
+
+    /// Canonical transaction `tx_hash` with number and hash of its block and its position in it.
+    ///
+    /// Only the transaction itself is decoded, not the rest of the block body.
+    pub async fn read<'db, Tx: Transaction<'db>>(
+        tx: &Tx,
+        tx_hash: H256,
+    ) -> anyhow::Result<Option<(BlockNumber, H256, usize, MessageWithSignature)>> {
+        let (block_number, offset) = match (
+            tl::read(tx, tx_hash).await?,
+            tx_offset::read(tx, tx_hash).await?,
+        ) {
+            (Some(block_number), Some(offset)) => (block_number, offset),
+            _ => return Ok(None),
+        };
+        let block_hash = match canonical_hash::read(tx, block_number).await? {
+            Some(block_hash) => block_hash,
+            None => return Ok(None),
+        };
+        let body = match storage_body::read(tx, block_hash, block_number).await? {
+            Some(body) if offset < body.tx_amount => body,
+            _ => return Ok(None),
+        };
+        let txn = match super::tx::read(tx, body.base_tx_id + offset, 1)
+            .await?
+            .pop()
+        {
+            Some(txn) => txn,
+            None => return Ok(None),
+        };
+
+        // Lookup lags behind reorgs until the lookup stage catches up.
+        if txn.hash() != tx_hash {
+            return Ok(None);
+        }
+
+        Ok(Some((block_number, block_hash, offset as usize, txn)))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -350,6 +474,7 @@ mod tests {
             base_tx_id: 1.into(),
             tx_amount: 2,
             uncles: vec![],
+            withdrawals: None,
         };
 
         let db = new_mem_database().unwrap();
@@ -0,0 +1,375 @@
+//! Filtered log queries, returned in pages for result sets too large to hold in memory.
+
+use crate::{
+    bitmapdb,
+    kv::{tables, traits::*},
+    models::*,
+    stagedsync::stages::LOG_INDEX,
+};
+use anyhow::{ensure, format_err};
+use croaring::Treemap as RoaringTreemap;
+use std::{collections::HashSet, ops::RangeInclusive};
+use tokio::pin;
+use tokio_stream::StreamExt;
+
+/// Log filter with `eth_getLogs` semantics.
+#[derive(Clone, Debug, Default)]
+pub struct LogFilter {
+    /// Match logs of these contracts, any contract if `None`.
+    pub addresses: Option<HashSet<Address>>,
+    /// Topic at every position must be one of the set, any topic if `None`.
+    pub topics: Vec<Option<HashSet<H256>>>,
+}
+
+impl LogFilter {
+    pub fn matches(&self, log: &Log) -> bool {
+        if let Some(addresses) = &self.addresses {
+            if !addresses.contains(&log.address) {
+                return false;
+            }
+        }
+
+        self.topics
+            .iter()
+            .enumerate()
+            .all(|(i, topics)| match topics {
+                None => true,
+                Some(topics) => log.topics.get(i).map_or(false, |t| topics.contains(t)),
+            })
+    }
+}
+
+/// Position of a log in the chain, the anchor of continuation tokens.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct LogPosition {
+    pub block_number: BlockNumber,
+    /// Index of transaction in block.
+    pub tx_index: TxIndex,
+    /// Index of log in transaction.
+    pub log_index: u64,
+}
+
+impl LogPosition {
+    pub fn start_of(block_number: BlockNumber) -> Self {
+        Self {
+            block_number,
+            tx_index: TxIndex(0),
+            log_index: 0,
+        }
+    }
+
+    /// Opaque token to resume a query from this position.
+    pub fn to_token(self) -> String {
+        let mut buf = [0; 24];
+        buf[..8].copy_from_slice(&self.block_number.0.to_be_bytes());
+        buf[8..16].copy_from_slice(&self.tx_index.0.to_be_bytes());
+        buf[16..].copy_from_slice(&self.log_index.to_be_bytes());
+        hex::encode(buf)
+    }
+
+    pub fn from_token(token: &str) -> anyhow::Result<Self> {
+        let buf = hex::decode(token).map_err(|_| format_err!("malformed continuation token"))?;
+        ensure!(buf.len() == 24, "malformed continuation token");
+        let read = |i: usize| u64::from_be_bytes(buf[i * 8..(i + 1) * 8].try_into().unwrap());
+        Ok(Self {
+            block_number: BlockNumber(read(0)),
+            tx_index: TxIndex(read(1)),
+            log_index: read(2),
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FoundLog {
+    pub position: LogPosition,
+    /// Index of log in block, as reported by `eth_getLogs`.
+    pub block_log_index: u64,
+    pub log: Log,
+}
+
+/// Blocks in `range` that may have logs matching `filter` according to the log index,
+/// `None` if the filter does not constrain addresses or topics.
+///
+/// The index does not record topic positions, so candidate blocks still have to be checked.
+async fn candidate_blocks<'db, Tx: Transaction<'db>>(
+    tx: &Tx,
+    filter: &LogFilter,
+    range: RangeInclusive<BlockNumber>,
+) -> anyhow::Result<Option<RoaringTreemap>> {
+    let mut out = None;
+    if let Some(addresses) = &filter.addresses {
+        let mut blocks = RoaringTreemap::create();
+        for &address in addresses {
+            blocks |= bitmapdb::get(tx, tables::LogAddressIndex, address, range.clone()).await?;
+        }
+        out = Some(blocks);
+    }
+    for topics in filter.topics.iter().flatten() {
+        let mut blocks = RoaringTreemap::create();
+        for &topic in topics {
+            blocks |= bitmapdb::get(tx, tables::LogTopicIndex, topic, range.clone()).await?;
+        }
+        out = Some(match out {
+            Some(mut out) => {
+                out &= blocks;
+                out
+            }
+            None => blocks,
+        });
+    }
+
+    Ok(out)
+}
+
+/// Scan logs of blocks `from_block..=to`, skipping those before `from`, into `page`.
+async fn scan<'db, Tx: Transaction<'db>>(
+    tx: &Tx,
+    filter: &LogFilter,
+    from: LogPosition,
+    from_block: BlockNumber,
+    to: BlockNumber,
+    limit: usize,
+    page: &mut Vec<FoundLog>,
+) -> anyhow::Result<Option<LogPosition>> {
+    let mut cursor = tx.cursor(tables::Log).await?;
+    // Start from the beginning of the block to count logs for block-wide log indices.
+    let walker = walk(&mut cursor, Some((from_block, TxIndex(0))));
+    pin!(walker);
+
+    let mut block = from_block;
+    let mut block_log_index = 0;
+    while let Some(((block_number, tx_index), logs)) = walker.try_next().await? {
+        if block_number > to {
+            break;
+        }
+        if block_number != block {
+            block = block_number;
+            block_log_index = 0;
+        }
+
+        for (log_index, log) in logs.into_iter().enumerate() {
+            let position = LogPosition {
+                block_number,
+                tx_index,
+                log_index: log_index as u64,
+            };
+            if position >= from && filter.matches(&log) {
+                if page.len() == limit {
+                    return Ok(Some(position));
+                }
+                page.push(FoundLog {
+                    position,
+                    block_log_index,
+                    log,
+                });
+            }
+            block_log_index += 1;
+        }
+    }
+
+    Ok(None)
+}
+
+/// Up to `limit` logs matching `filter`, starting from `from` up to the end of block `to`.
+///
+/// Blocks covered by the log index are only read if they may have matching logs.
+/// Returns position to continue from if the page is full, `None` once the range is exhausted.
+pub async fn read_page<'db, Tx: Transaction<'db>>(
+    tx: &Tx,
+    filter: &LogFilter,
+    from: LogPosition,
+    to: BlockNumber,
+    limit: usize,
+) -> anyhow::Result<(Vec<FoundLog>, Option<LogPosition>)> {
+    let mut page = vec![];
+
+    let mut unindexed_from = from.block_number;
+    if let Some(indexed_to) = LOG_INDEX.get_progress(tx).await? {
+        let indexed_to = indexed_to.min(to);
+        if from.block_number <= indexed_to {
+            let range = from.block_number..=indexed_to;
+            if let Some(blocks) = candidate_blocks(tx, filter, range.clone()).await? {
+                // Bitmap chunks extend past the range.
+                let blocks = blocks
+                    .iter()
+                    .map(BlockNumber)
+                    .filter(|block| range.contains(block))
+                    .collect::<Vec<_>>();
+                for block in blocks {
+                    if let Some(next) =
+                        scan(tx, filter, from, block, block, limit, &mut page).await?
+                    {
+                        return Ok((page, Some(next)));
+                    }
+                }
+                unindexed_from = indexed_to + 1;
+            }
+        }
+    }
+
+    let next = scan(tx, filter, from, unindexed_from, to, limit, &mut page).await?;
+
+    Ok((page, next))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        kv::new_mem_database,
+        stagedsync::{stage::*, stages::EXECUTION},
+        stages::LogIndex,
+    };
+    use bytes::Bytes;
+    use std::{sync::Arc, time::Instant};
+    use tempfile::TempDir;
+
+    fn log(address: u8, topic: u8) -> Log {
+        Log {
+            address: Address::repeat_byte(address),
+            topics: vec![H256::repeat_byte(topic)],
+            data: Bytes::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn pages_of_logs() {
+        let db = new_mem_database().unwrap();
+        let tx = db.begin_mutable().await.unwrap();
+
+        for block in 1..=3 {
+            for tx_index in 0..2 {
+                tx.set(
+                    tables::Log,
+                    (BlockNumber(block), TxIndex(tx_index)),
+                    vec![log(1, 1), log(2, 1), log(1, 2)],
+                )
+                .await
+                .unwrap();
+            }
+        }
+
+        let filter = LogFilter {
+            addresses: Some([Address::repeat_byte(1)].into_iter().collect()),
+            topics: vec![Some([H256::repeat_byte(2)].into_iter().collect())],
+        };
+
+        let mut found = vec![];
+        let mut from = LogPosition::start_of(BlockNumber(1));
+        loop {
+            let (page, next) = read_page(&tx, &filter, from, BlockNumber(2), 3)
+                .await
+                .unwrap();
+            assert!(page.len() <= 3);
+            found.extend(page);
+            match next {
+                Some(next) => {
+                    let token = next.to_token();
+                    from = LogPosition::from_token(&token).unwrap();
+                    assert_eq!(from, next);
+                }
+                None => break,
+            }
+        }
+
+        assert_eq!(
+            found
+                .iter()
+                .map(|found| (found.position.block_number.0, found.block_log_index))
+                .collect::<Vec<_>>(),
+            vec![(1, 2), (1, 5), (2, 2), (2, 5)]
+        );
+        assert!(found.iter().all(|found| found.log == log(1, 2)));
+        assert!(LogPosition::from_token("zz").is_err());
+    }
+
+    #[tokio::test]
+    async fn indexed_logs() {
+        let db = new_mem_database().unwrap();
+        let mut tx = db.begin_mutable().await.unwrap();
+
+        for block in 1..=4 {
+            let address = if block % 2 == 1 { 1 } else { 2 };
+            tx.set(
+                tables::Log,
+                (BlockNumber(block), TxIndex(0)),
+                vec![log(3, 1), log(address, 2)],
+            )
+            .await
+            .unwrap();
+        }
+
+        // Blocks after 2 are not indexed yet, and are scanned.
+        LogIndex {
+            temp_dir: Arc::new(TempDir::new().unwrap()),
+            flush_interval: 0,
+        }
+        .execute(
+            &mut tx,
+            StageInput {
+                restarted: false,
+                first_started_at: (Instant::now(), None),
+                previous_stage: Some((EXECUTION, BlockNumber(2))),
+                stage_progress: None,
+            },
+        )
+        .await
+        .unwrap();
+        LOG_INDEX.save_progress(&tx, BlockNumber(2)).await.unwrap();
+
+        let filter = LogFilter {
+            addresses: Some([Address::repeat_byte(1)].into_iter().collect()),
+            topics: vec![Some([H256::repeat_byte(2)].into_iter().collect())],
+        };
+        let (found, next) = read_page(
+            &tx,
+            &filter,
+            LogPosition::start_of(BlockNumber(1)),
+            BlockNumber(4),
+            10,
+        )
+        .await
+        .unwrap();
+        assert_eq!(next, None);
+        assert_eq!(
+            found
+                .iter()
+                .map(|found| (found.position.block_number.0, found.block_log_index))
+                .collect::<Vec<_>>(),
+            vec![(1, 1), (3, 1)]
+        );
+
+        // Continuing within an indexed block.
+        let (found, next) = read_page(
+            &tx,
+            &filter,
+            LogPosition::start_of(BlockNumber(1)),
+            BlockNumber(4),
+            1,
+        )
+        .await
+        .unwrap();
+        assert_eq!(found.len(), 1);
+        let (found, next) = read_page(&tx, &filter, next.unwrap(), BlockNumber(4), 1)
+            .await
+            .unwrap();
+        assert_eq!(found[0].position.block_number, BlockNumber(3));
+        assert_eq!(next, None);
+
+        // Topic in the wrong position matches no logs even though its blocks are candidates.
+        let filter = LogFilter {
+            addresses: None,
+            topics: vec![None, Some([H256::repeat_byte(1)].into_iter().collect())],
+        };
+        let (found, _) = read_page(
+            &tx,
+            &filter,
+            LogPosition::start_of(BlockNumber(1)),
+            BlockNumber(4),
+            10,
+        )
+        .await
+        .unwrap();
+        assert!(found.is_empty());
+    }
+}
@@ -1,2 +1,4 @@
+pub mod canonical_cache;
 pub mod chain;
+pub mod logs;
 pub mod state;
@@ -5,6 +5,7 @@ use crate::{
 
 pub mod account {
     use super::*;
+    use std::ops::RangeInclusive;
 
     pub async fn read<'db, Tx: Transaction<'db>>(
         tx: &Tx,
@@ -35,6 +36,117 @@ pub mod account {
 
         tx.get(tables::Account, address_to_find).await
     }
+
+    /// Accounts of `addresses` after `block_number`, or latest ones, in the same order.
+    ///
+    /// Addresses are looked up in key order reusing cursors, so that consecutive seeks
+    /// land on nearby pages.
+    pub async fn read_many<'db, Tx: Transaction<'db>>(
+        tx: &Tx,
+        addresses: &[Address],
+        block_number: Option<BlockNumber>,
+    ) -> anyhow::Result<Vec<Option<Account>>> {
+        let mut order = (0..addresses.len()).collect::<Vec<_>>();
+        order.sort_unstable_by_key(|&i| addresses[i]);
+
+        let mut history = tx.cursor(tables::AccountHistory).await?;
+        let mut changeset = tx.cursor_dup_sort(tables::AccountChangeSet).await?;
+        let mut state = tx.cursor(tables::Account).await?;
+        let mut out = vec![None; addresses.len()];
+        'addresses: for i in order {
+            let address = addresses[i];
+            if let Some(block_number) = block_number {
+                if let Some((index_key, change_blocks)) = history
+                    .seek(tables::BitmapKey {
+                        inner: address,
+                        block_number,
+                    })
+                    .await?
+                {
+                    if index_key.inner == address {
+                        if let Some(change_block) = change_blocks
+                            .iter()
+                            .find(|&change_block| *block_number < change_block)
+                        {
+                            if let Some(tables::AccountChange {
+                                address: changed,
+                                account,
+                            }) = changeset
+                                .seek_both_range(BlockNumber(change_block), address)
+                                .await?
+                            {
+                                if changed == address {
+                                    out[i] = account;
+                                    continue 'addresses;
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+
+            out[i] = state.seek_exact(address).await?.map(|(_, account)| account);
+        }
+
+        Ok(out)
+    }
+
+    /// Account state after every `step`-th block in `blocks`, starting with the first one.
+    ///
+    /// Reads the history index once for the whole range, and the changeset only where the account
+    /// has changed since the previous point.
+    pub async fn read_history<'db, Tx: Transaction<'db>>(
+        tx: &Tx,
+        address: Address,
+        blocks: RangeInclusive<BlockNumber>,
+        step: u64,
+    ) -> anyhow::Result<Vec<(BlockNumber, Option<Account>)>> {
+        let (from, to) = (*blocks.start(), *blocks.end());
+        let step = step.max(1);
+
+        // Chunk keys are the last block in the chunk, so the first chunk past `to`
+        // holds the first change after it.
+        let changes = crate::bitmapdb::get(
+            tx,
+            tables::AccountHistory,
+            address,
+            from..=BlockNumber(to.0.saturating_add(1)),
+        )
+        .await?
+        .iter()
+        .collect::<Vec<_>>();
+
+        let mut changeset = tx.cursor_dup_sort(tables::AccountChangeSet).await?;
+        let mut current = None;
+        let mut last_change = None;
+        let mut out = vec![];
+        let mut block_number = from;
+        while block_number <= to {
+            let next_change = changes
+                .get(changes.partition_point(|&change| change <= *block_number))
+                .copied();
+            if current.is_none() || next_change != last_change {
+                let account = match next_change {
+                    Some(change) => changeset
+                        .seek_both_range(BlockNumber(change), address)
+                        .await?
+                        .filter(|entry| entry.address == address)
+                        .and_then(|entry| entry.account),
+                    None => tx.get(tables::Account, address).await?,
+                };
+                current = Some(account);
+                last_change = next_change;
+            }
+            out.push((block_number, current.flatten()));
+
+            block_number = match block_number.0.checked_add(step) {
+                Some(next) => BlockNumber(next),
+                None => break,
+            };
+        }
+
+        Ok(out)
+    }
 }
 
 pub mod storage {
@@ -118,6 +230,146 @@ pub mod history_index {
     }
 }
 
+pub mod changeset {
+    use super::*;
+    use crate::h256_to_u256;
+    use async_stream::try_stream;
+    use futures_core::Stream;
+    use std::{collections::BTreeMap, ops::RangeInclusive};
+    use tokio::pin;
+    use tokio_stream::StreamExt;
+
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct AccountDiff {
+        pub block_number: BlockNumber,
+        pub address: Address,
+        pub before: Option<Account>,
+        pub after: Option<Account>,
+    }
+
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct StorageDiff {
+        pub block_number: BlockNumber,
+        pub address: Address,
+        pub location: H256,
+        pub before: U256,
+        pub after: U256,
+    }
+
+    /// Walk over account changes in a range of blocks, ordered by block and address.
+    ///
+    /// Value after the block is looked up in the history index, so it must be up to date for the range.
+    pub fn walk_accounts<'db: 'tx, 'tx, Tx: Transaction<'db>>(
+        tx: &'tx Tx,
+        blocks: RangeInclusive<BlockNumber>,
+    ) -> impl Stream<Item = anyhow::Result<AccountDiff>> + 'tx {
+        try_stream! {
+            let mut cursor = tx.cursor_dup_sort(tables::AccountChangeSet).await?;
+            let walker = walk(&mut cursor, Some(*blocks.start()));
+            pin!(walker);
+
+            while let Some((block_number, tables::AccountChange { address, account })) =
+                walker.try_next().await?
+            {
+                if block_number > *blocks.end() {
+                    break;
+                }
+
+                let after = super::account::read(tx, address, Some(block_number)).await?;
+
+                yield AccountDiff {
+                    block_number,
+                    address,
+                    before: account,
+                    after,
+                };
+            }
+        }
+    }
+
+    /// Walk over storage changes in a range of blocks, ordered by block, address and location.
+    ///
+    /// Value after the block is looked up in the history index, so it must be up to date for the range.
+    pub fn walk_storage<'db: 'tx, 'tx, Tx: Transaction<'db>>(
+        tx: &'tx Tx,
+        blocks: RangeInclusive<BlockNumber>,
+    ) -> impl Stream<Item = anyhow::Result<StorageDiff>> + 'tx {
+        try_stream! {
+            let mut cursor = tx.cursor_dup_sort(tables::StorageChangeSet).await?;
+            let walker = walk(&mut cursor, Some(*blocks.start()));
+            pin!(walker);
+
+            while let Some((
+                tables::StorageChangeKey {
+                    block_number,
+                    address,
+                },
+                tables::StorageChange { location, value },
+            )) = walker.try_next().await?
+            {
+                if block_number > *blocks.end() {
+                    break;
+                }
+
+                let after = super::storage::read(
+                    tx,
+                    address,
+                    h256_to_u256(location),
+                    Some(block_number),
+                )
+                .await?;
+
+                yield StorageDiff {
+                    block_number,
+                    address,
+                    location,
+                    before: value,
+                    after,
+                };
+            }
+        }
+    }
+
+    /// Storage slots of `address` whose values after block `from` and after block `to` differ,
+    /// with their values at both points, ordered by location.
+    ///
+    /// Changesets of every block in between are visited, so cost grows with the distance.
+    pub async fn storage_between<'db, Tx: Transaction<'db>>(
+        tx: &Tx,
+        address: Address,
+        from: BlockNumber,
+        to: BlockNumber,
+    ) -> anyhow::Result<BTreeMap<H256, (U256, U256)>> {
+        // Changeset keeps values before the block, so the earliest change after `from`
+        // holds the value after it.
+        let mut before = BTreeMap::new();
+        let mut cursor = tx.cursor_dup_sort(tables::StorageChangeSet).await?;
+        for block_number in from.0.saturating_add(1)..=to.0 {
+            let mut entry = cursor
+                .seek_exact(tables::StorageChangeKey {
+                    block_number: BlockNumber(block_number),
+                    address,
+                })
+                .await?;
+            while let Some((_, tables::StorageChange { location, value })) = entry {
+                before.entry(location).or_insert(value);
+                entry = cursor.next_dup().await?;
+            }
+        }
+
+        let mut out = BTreeMap::new();
+        for (location, before) in before {
+            let after = super::storage::read(tx, address, h256_to_u256(location), Some(to)).await?;
+            // Slot may have been changed back to its original value.
+            if before != after {
+                out.insert(location, (before, after));
+            }
+        }
+
+        Ok(out)
+    }
+}
+
 #[cfg(test)]
 pub mod tests {
     use super::*;
@@ -126,6 +378,7 @@ pub mod tests {
         kv::{new_mem_database, tables},
     };
     use hex_literal::hex;
+    use tokio_stream::StreamExt;
 
     #[tokio::test]
     async fn read_storage() {
@@ -181,4 +434,247 @@ pub mod tests {
             0.as_u256()
         );
     }
+
+    #[tokio::test]
+    async fn read_account_history() {
+        let db = new_mem_database().unwrap();
+        let txn = db.begin_mutable().await.unwrap();
+
+        let address = hex!("b000000000000000000000000000000000000008").into();
+
+        let acc = |balance: u64| Account {
+            balance: balance.into(),
+            nonce: balance,
+            ..Default::default()
+        };
+
+        // created in block 2, updated in blocks 5 and 9
+        for (block, before) in [(2, None), (5, Some(acc(1))), (9, Some(acc(2)))] {
+            txn.set(
+                tables::AccountChangeSet,
+                BlockNumber(block),
+                tables::AccountChange {
+                    address,
+                    account: before,
+                },
+            )
+            .await
+            .unwrap();
+        }
+        let mut bitmap = croaring::Treemap::create();
+        for block in [2, 5, 9] {
+            bitmap.add(block);
+        }
+        txn.set(
+            tables::AccountHistory,
+            tables::BitmapKey {
+                inner: address,
+                block_number: BlockNumber(u64::MAX),
+            },
+            bitmap,
+        )
+        .await
+        .unwrap();
+        txn.set(tables::Account, address, acc(3)).await.unwrap();
+
+        let history =
+            super::account::read_history(&txn, address, BlockNumber(1)..=BlockNumber(10), 2)
+                .await
+                .unwrap();
+        assert_eq!(
+            history,
+            vec![
+                (BlockNumber(1), None),
+                (BlockNumber(3), Some(acc(1))),
+                (BlockNumber(5), Some(acc(2))),
+                (BlockNumber(7), Some(acc(2))),
+                (BlockNumber(9), Some(acc(3))),
+            ]
+        );
+
+        for block in 1..=10 {
+            assert_eq!(
+                super::account::read_history(
+                    &txn,
+                    address,
+                    BlockNumber(block)..=BlockNumber(block),
+                    1
+                )
+                .await
+                .unwrap(),
+                vec![(
+                    BlockNumber(block),
+                    super::account::read(&txn, address, Some(BlockNumber(block)))
+                        .await
+                        .unwrap()
+                )]
+            );
+        }
+
+        let other = hex!("a000000000000000000000000000000000000001").into();
+        txn.set(tables::Account, other, acc(7)).await.unwrap();
+        let addresses = [address, Address::zero(), other, address];
+        assert_eq!(
+            super::account::read_many(&txn, &addresses, Some(BlockNumber(3)))
+                .await
+                .unwrap(),
+            vec![Some(acc(1)), None, Some(acc(7)), Some(acc(1))]
+        );
+        assert_eq!(
+            super::account::read_many(&txn, &addresses, None)
+                .await
+                .unwrap(),
+            vec![Some(acc(3)), None, Some(acc(7)), Some(acc(3))]
+        );
+    }
+
+    #[tokio::test]
+    async fn walk_account_changes() {
+        let db = new_mem_database().unwrap();
+        let txn = db.begin_mutable().await.unwrap();
+
+        let address = hex!("b000000000000000000000000000000000000008").into();
+        let other = hex!("b000000000000000000000000000000000000009").into();
+
+        let acc = |balance: u64| Account {
+            balance: balance.into(),
+            ..Default::default()
+        };
+
+        // address created in block 1 and updated in block 3, other created in block 2
+        for (block, address, before) in [
+            (1, address, None),
+            (2, other, None),
+            (3, address, Some(acc(1))),
+        ] {
+            txn.set(
+                tables::AccountChangeSet,
+                BlockNumber(block),
+                tables::AccountChange {
+                    address,
+                    account: before,
+                },
+            )
+            .await
+            .unwrap();
+        }
+        for (address, blocks) in [(address, vec![1, 3]), (other, vec![2])] {
+            let mut bitmap = croaring::Treemap::create();
+            for block in blocks {
+                bitmap.add(block);
+            }
+            txn.set(
+                tables::AccountHistory,
+                tables::BitmapKey {
+                    inner: address,
+                    block_number: BlockNumber(u64::MAX),
+                },
+                bitmap,
+            )
+            .await
+            .unwrap();
+        }
+        txn.set(tables::Account, address, acc(3)).await.unwrap();
+        txn.set(tables::Account, other, acc(2)).await.unwrap();
+
+        let diffs = super::changeset::walk_accounts(&txn, BlockNumber(1)..=BlockNumber(2))
+            .collect::<anyhow::Result<Vec<_>>>()
+            .await
+            .unwrap();
+
+        assert_eq!(
+            diffs,
+            vec![
+                super::changeset::AccountDiff {
+                    block_number: BlockNumber(1),
+                    address,
+                    before: None,
+                    after: Some(acc(1)),
+                },
+                super::changeset::AccountDiff {
+                    block_number: BlockNumber(2),
+                    address: other,
+                    before: None,
+                    after: Some(acc(2)),
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn storage_between_blocks() {
+        let db = new_mem_database().unwrap();
+        let txn = db.begin_mutable().await.unwrap();
+
+        let address = hex!("b000000000000000000000000000000000000008").into();
+        let other = hex!("b000000000000000000000000000000000000009").into();
+        let loc1 = H256::from_low_u64_be(1);
+        let loc2 = H256::from_low_u64_be(2);
+        let loc3 = H256::from_low_u64_be(3);
+
+        // loc1: 0 -> 1 in block 2, 1 -> 2 in block 4
+        // loc2: 5 -> 6 in block 3, 6 -> 5 in block 5
+        // loc3: 7 -> 8 in block 6
+        for (block, address, location, before) in [
+            (2, address, loc1, 0_u64),
+            (2, other, loc1, 9),
+            (3, address, loc2, 5),
+            (4, address, loc1, 1),
+            (5, address, loc2, 6),
+            (6, address, loc3, 7),
+        ] {
+            txn.set(
+                tables::StorageChangeSet,
+                tables::StorageChangeKey {
+                    block_number: BlockNumber(block),
+                    address,
+                },
+                tables::StorageChange {
+                    location,
+                    value: before.into(),
+                },
+            )
+            .await
+            .unwrap();
+        }
+        for (location, blocks) in [(loc1, vec![2, 4]), (loc2, vec![3, 5]), (loc3, vec![6])] {
+            let mut bitmap = croaring::Treemap::create();
+            for block in blocks {
+                bitmap.add(block);
+            }
+            txn.set(
+                tables::StorageHistory,
+                tables::BitmapKey {
+                    inner: (address, location),
+                    block_number: BlockNumber(u64::MAX),
+                },
+                bitmap,
+            )
+            .await
+            .unwrap();
+        }
+        for (location, value) in [(loc1, 2_u64), (loc2, 5), (loc3, 8)] {
+            txn.set(tables::Storage, address, (location, value.into()))
+                .await
+                .unwrap();
+        }
+
+        let diff = |from, to| {
+            super::changeset::storage_between(&txn, address, BlockNumber(from), BlockNumber(to))
+        };
+
+        // loc2 is back to its original value.
+        assert_eq!(
+            diff(1, 5).await.unwrap().into_iter().collect::<Vec<_>>(),
+            vec![(loc1, (0.as_u256(), 2.as_u256()))]
+        );
+        assert_eq!(
+            diff(2, 4).await.unwrap().into_iter().collect::<Vec<_>>(),
+            vec![
+                (loc1, (1.as_u256(), 2.as_u256())),
+                (loc2, (5.as_u256(), 6.as_u256()))
+            ]
+        );
+        assert!(diff(6, 10).await.unwrap().is_empty());
+    }
 }
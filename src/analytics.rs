@@ -0,0 +1,853 @@
+//! Export of chain data into Parquet files for analytics.
+//!
+//! Datasets and their columns follow the layout of [cryo](https://github.com/paradigmxyz/cryo),
+//! with receipts laid out as in Ethereum-ETL. Files are partitioned by block range and named
+//! `{chain}__{dataset}__{from}_to_{to}.parquet`, so that the existing tooling can read them as is.
+//!
+//! Receipts and traces are not stored, so blocks are re-executed on top of historical state.
+
+use crate::{
+    accessors::chain,
+    consensus,
+    execution::{
+        analysis_cache::AnalysisCache,
+        call_tracer::{CallFrame, CallFrameTracer},
+        processor::ExecutionProcessor,
+    },
+    kv::traits::*,
+    models::*,
+    stagedsync::stages::EXECUTION,
+    state::Buffer,
+};
+use anyhow::{bail, ensure, format_err};
+use arrow::{
+    array::*,
+    datatypes::{DataType, Field, Schema},
+    record_batch::RecordBatch,
+};
+use bytes::Bytes;
+use itertools::Itertools;
+use parquet::{arrow::ArrowWriter, basic::Compression, file::properties::WriterProperties};
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use tracing::*;
+
+pub const DATASETS: &[&str] = &["blocks", "transactions", "receipts", "logs", "traces"];
+
+/// Canonical block with the results of its execution.
+#[derive(Debug)]
+pub struct ExecutedBlock {
+    pub hash: H256,
+    pub header: BlockHeader,
+    pub transactions: Vec<MessageWithSignature>,
+    pub senders: Vec<Address>,
+    pub receipts: Vec<Receipt>,
+    /// Call tree of every transaction.
+    pub traces: Vec<CallFrame>,
+}
+
+/// Read canonical block `number` and re-execute it on top of the state after its parent.
+pub async fn execute_block<'db, Tx: Transaction<'db>>(
+    tx: &Tx,
+    chain_spec: &ChainSpec,
+    number: BlockNumber,
+) -> anyhow::Result<ExecutedBlock> {
+    let hash = chain::canonical_hash::read(tx, number)
+        .await?
+        .ok_or_else(|| format_err!("no canonical block {}", number))?;
+    let header = chain::header::read(tx, hash, number)
+        .await?
+        .ok_or_else(|| format_err!("header {}/{:?} not found", number, hash))?;
+    let body = chain::block_body::read_without_senders(tx, hash, number)
+        .await?
+        .ok_or_else(|| format_err!("body {}/{:?} not found", number, hash))?;
+    let senders = chain::tx_sender::read(tx, hash, number).await?;
+    ensure!(
+        senders.len() == body.transactions.len(),
+        "senders for block {} not recovered",
+        number
+    );
+
+    let mut out = ExecutedBlock {
+        hash,
+        header,
+        transactions: body.transactions,
+        senders,
+        receipts: vec![],
+        traces: vec![],
+    };
+
+    // Genesis is not executed.
+    if number.0 == 0 {
+        return Ok(out);
+    }
+
+    let block = BlockBodyWithSenders {
+        transactions: out
+            .transactions
+            .iter()
+            .zip(&out.senders)
+            .map(|(txn, &sender)| MessageWithSender {
+                message: txn.message.clone(),
+                sender,
+            })
+            .collect(),
+        ommers: body.ommers,
+        withdrawals: body.withdrawals,
+    };
+    let partial_header = PartialHeader::from(out.header.clone());
+    let block_spec = chain_spec.collect_block_spec(number);
+
+    let mut buffer = Buffer::new(tx, BlockNumber(0), Some(BlockNumber(number.0 - 1)));
+    let mut tracer = CallFrameTracer::default();
+    let mut analysis_cache = AnalysisCache::default();
+    let mut engine = consensus::engine_factory(chain_spec.clone())?;
+    out.receipts = ExecutionProcessor::new(
+        &mut buffer,
+        Some(&mut tracer),
+        &mut analysis_cache,
+        &mut *engine,
+        &partial_header,
+        &block,
+        &block_spec,
+    )
+    .execute_block_no_post_validation()
+    .await?;
+    out.traces = tracer.into_roots();
+
+    let gas_used = out.receipts.last().map_or(0, |r| r.cumulative_gas_used);
+    ensure!(
+        gas_used == out.header.gas_used,
+        "block {} re-executed with {} gas used instead of {}",
+        number,
+        gas_used,
+        out.header.gas_used
+    );
+    ensure!(
+        out.traces.len() == out.transactions.len(),
+        "traced {} out of {} transactions in block {}",
+        out.traces.len(),
+        out.transactions.len(),
+        number
+    );
+
+    Ok(out)
+}
+
+/// Columns of a record batch, all of them nullable.
+#[derive(Default)]
+struct Columns {
+    fields: Vec<Field>,
+    arrays: Vec<ArrayRef>,
+}
+
+impl Columns {
+    fn push(mut self, name: &str, data_type: DataType, array: ArrayRef) -> Self {
+        self.fields.push(Field::new(name, data_type, true));
+        self.arrays.push(array);
+        self
+    }
+
+    fn u64<T: Into<Option<u64>>>(self, name: &str, values: impl IntoIterator<Item = T>) -> Self {
+        let array = UInt64Array::from(values.into_iter().map(Into::into).collect::<Vec<_>>());
+        self.push(name, DataType::UInt64, Arc::new(array))
+    }
+
+    fn u32<T: Into<Option<u32>>>(self, name: &str, values: impl IntoIterator<Item = T>) -> Self {
+        let array = UInt32Array::from(values.into_iter().map(Into::into).collect::<Vec<_>>());
+        self.push(name, DataType::UInt32, Arc::new(array))
+    }
+
+    fn bool(self, name: &str, values: impl IntoIterator<Item = bool>) -> Self {
+        let array = BooleanArray::from(values.into_iter().collect::<Vec<_>>());
+        self.push(name, DataType::Boolean, Arc::new(array))
+    }
+
+    fn string<T: Into<Option<String>>>(
+        self,
+        name: &str,
+        values: impl IntoIterator<Item = T>,
+    ) -> Self {
+        let array = StringArray::from(values.into_iter().map(Into::into).collect::<Vec<_>>());
+        self.push(name, DataType::Utf8, Arc::new(array))
+    }
+
+    fn binary<'a, T: Into<Option<&'a [u8]>>>(
+        self,
+        name: &str,
+        values: impl IntoIterator<Item = T>,
+    ) -> Self {
+        let array = BinaryArray::from(values.into_iter().map(Into::into).collect::<Vec<_>>());
+        self.push(name, DataType::Binary, Arc::new(array))
+    }
+
+    fn finish(self) -> anyhow::Result<RecordBatch> {
+        Ok(RecordBatch::try_new(
+            Arc::new(Schema::new(self.fields)),
+            self.arrays,
+        )?)
+    }
+}
+
+fn u256_to_u64(v: U256) -> Option<u64> {
+    u64::try_from(v).ok()
+}
+
+struct BlockRow {
+    hash: H256,
+    parent_hash: H256,
+    author: Address,
+    state_root: H256,
+    transactions_root: H256,
+    receipts_root: H256,
+    number: u64,
+    gas_used: u64,
+    gas_limit: u64,
+    extra_data: Bytes,
+    logs_bloom: Bloom,
+    timestamp: u64,
+    difficulty: String,
+    base_fee_per_gas: Option<u64>,
+    transaction_count: u64,
+}
+
+struct TransactionRow {
+    block_number: u64,
+    block_hash: H256,
+    transaction_index: u64,
+    transaction_hash: H256,
+    nonce: u64,
+    from_address: Address,
+    to_address: Option<Address>,
+    value: String,
+    input: Bytes,
+    gas_limit: u64,
+    gas_used: u64,
+    gas_price: Option<u64>,
+    transaction_type: u32,
+    max_priority_fee_per_gas: Option<u64>,
+    max_fee_per_gas: Option<u64>,
+    success: bool,
+}
+
+struct ReceiptRow {
+    transaction_hash: H256,
+    transaction_index: u64,
+    block_hash: H256,
+    block_number: u64,
+    cumulative_gas_used: u64,
+    gas_used: u64,
+    contract_address: Option<Address>,
+    status: u64,
+    effective_gas_price: Option<u64>,
+    logs_bloom: Bloom,
+}
+
+struct LogRow {
+    block_number: u64,
+    block_hash: H256,
+    transaction_index: u64,
+    log_index: u64,
+    transaction_hash: H256,
+    address: Address,
+    topics: [Option<H256>; 4],
+    data: Bytes,
+}
+
+/// Transaction a trace belongs to.
+#[derive(Clone, Copy)]
+struct TraceLocation {
+    block_number: u64,
+    block_hash: H256,
+    transaction_position: u64,
+    transaction_hash: H256,
+}
+
+struct TraceRow {
+    location: TraceLocation,
+    action_type: &'static str,
+    action_call_type: Option<String>,
+    action_from: Address,
+    action_to: Option<Address>,
+    action_value: Option<String>,
+    action_gas: u64,
+    action_input: Option<Bytes>,
+    action_init: Option<Bytes>,
+    result_gas_used: u64,
+    result_output: Option<Bytes>,
+    result_code: Option<Bytes>,
+    result_address: Option<Address>,
+    trace_address: String,
+    subtraces: u32,
+    error: Option<String>,
+}
+
+/// Flatten call tree into rows in depth-first order, as in parity-style traces.
+fn push_traces(
+    out: &mut Vec<TraceRow>,
+    location: TraceLocation,
+    frame: &CallFrame,
+    trace_address: &mut Vec<usize>,
+) {
+    let is_create = frame.kind == "CREATE";
+    let succeeded = frame.error.is_none();
+    out.push(TraceRow {
+        location,
+        action_type: if is_create { "create" } else { "call" },
+        action_call_type: (!is_create).then(|| frame.kind.to_lowercase()),
+        action_from: frame.from,
+        action_to: (!is_create).then(|| frame.to),
+        action_value: frame.value.map(|v| v.to_string()),
+        action_gas: frame.gas.as_u64(),
+        action_input: (!is_create).then(|| frame.input.clone()),
+        action_init: is_create.then(|| frame.input.clone()),
+        result_gas_used: frame.gas_used.as_u64(),
+        result_output: (!is_create && succeeded).then(|| frame.output.clone()),
+        result_code: (is_create && succeeded).then(|| frame.output.clone()),
+        result_address: (is_create && succeeded).then(|| frame.to),
+        trace_address: trace_address.iter().join(","),
+        subtraces: frame.calls.len() as u32,
+        error: frame.error.clone(),
+    });
+
+    for (i, call) in frame.calls.iter().enumerate() {
+        trace_address.push(i);
+        push_traces(out, location, call, trace_address);
+        trace_address.pop();
+    }
+}
+
+/// Path of the file with rows of `dataset` for blocks `from..=to`.
+pub fn partition_path(
+    dir: &Path,
+    chain_name: &str,
+    dataset: &str,
+    from: BlockNumber,
+    to: BlockNumber,
+) -> PathBuf {
+    dir.join(format!(
+        "{}__{}__{:08}_to_{:08}.parquet",
+        chain_name.to_lowercase(),
+        dataset,
+        from.0,
+        to.0
+    ))
+}
+
+/// Rows of all datasets for a range of blocks, written out into a set of Parquet files at once.
+pub struct Partition {
+    chain_id: u64,
+    from: BlockNumber,
+    to: BlockNumber,
+    blocks: Vec<BlockRow>,
+    transactions: Vec<TransactionRow>,
+    receipts: Vec<ReceiptRow>,
+    logs: Vec<LogRow>,
+    traces: Vec<TraceRow>,
+}
+
+impl Partition {
+    pub fn new(chain_id: ChainId, from: BlockNumber, to: BlockNumber) -> Self {
+        Self {
+            chain_id: chain_id.0,
+            from,
+            to,
+            blocks: vec![],
+            transactions: vec![],
+            receipts: vec![],
+            logs: vec![],
+            traces: vec![],
+        }
+    }
+
+    pub fn push(&mut self, block: &ExecutedBlock) {
+        let header = &block.header;
+        let number = header.number.0;
+        let base_fee_per_gas = header.base_fee_per_gas.unwrap_or(U256::ZERO);
+
+        self.blocks.push(BlockRow {
+            hash: block.hash,
+            parent_hash: header.parent_hash,
+            author: header.beneficiary,
+            state_root: header.state_root,
+            transactions_root: header.transactions_root,
+            receipts_root: header.receipts_root,
+            number,
+            gas_used: header.gas_used,
+            gas_limit: header.gas_limit,
+            extra_data: header.extra_data.clone(),
+            logs_bloom: header.logs_bloom,
+            timestamp: header.timestamp,
+            difficulty: header.difficulty.to_string(),
+            base_fee_per_gas: header.base_fee_per_gas.and_then(u256_to_u64),
+            transaction_count: block.transactions.len() as u64,
+        });
+
+        let mut cumulative_gas_used = 0;
+        let mut log_index = 0;
+        for (i, ((txn, &sender), (receipt, trace))) in block
+            .transactions
+            .iter()
+            .zip(&block.senders)
+            .zip(block.receipts.iter().zip(&block.traces))
+            .enumerate()
+        {
+            let transaction_hash = txn.hash();
+            let transaction_index = i as u64;
+            let gas_used = receipt.cumulative_gas_used - cumulative_gas_used;
+            cumulative_gas_used = receipt.cumulative_gas_used;
+            let effective_gas_price = u256_to_u64(txn.effective_gas_price(base_fee_per_gas));
+
+            let (to_address, contract_address) = match txn.action() {
+                TransactionAction::Call(to) => (Some(to), None),
+                TransactionAction::Create => (None, receipt.success.then(|| trace.to)),
+            };
+            let (max_priority_fee_per_gas, max_fee_per_gas) = match txn.tx_type() {
+                TxType::EIP1559 | TxType::EIP4844 => (
+                    u256_to_u64(txn.max_priority_fee_per_gas()),
+                    u256_to_u64(txn.max_fee_per_gas()),
+                ),
+                _ => (None, None),
+            };
+
+            self.transactions.push(TransactionRow {
+                block_number: number,
+                block_hash: block.hash,
+                transaction_index,
+                transaction_hash,
+                nonce: txn.nonce(),
+                from_address: sender,
+                to_address,
+                value: txn.value().to_string(),
+                input: txn.input().clone(),
+                gas_limit: txn.gas_limit(),
+                gas_used,
+                gas_price: effective_gas_price,
+                transaction_type: txn.tx_type() as u32,
+                max_priority_fee_per_gas,
+                max_fee_per_gas,
+                success: receipt.success,
+            });
+
+            self.receipts.push(ReceiptRow {
+                transaction_hash,
+                transaction_index,
+                block_hash: block.hash,
+                block_number: number,
+                cumulative_gas_used,
+                gas_used,
+                contract_address,
+                status: receipt.success as u64,
+                effective_gas_price,
+                logs_bloom: receipt.bloom,
+            });
+
+            for log in &receipt.logs {
+                let mut topics = [None; 4];
+                for (slot, &topic) in topics.iter_mut().zip(&log.topics) {
+                    *slot = Some(topic);
+                }
+                self.logs.push(LogRow {
+                    block_number: number,
+                    block_hash: block.hash,
+                    transaction_index,
+                    log_index,
+                    transaction_hash,
+                    address: log.address,
+                    topics,
+                    data: log.data.clone(),
+                });
+                log_index += 1;
+            }
+
+            push_traces(
+                &mut self.traces,
+                TraceLocation {
+                    block_number: number,
+                    block_hash: block.hash,
+                    transaction_position: transaction_index,
+                    transaction_hash,
+                },
+                trace,
+                &mut vec![],
+            );
+        }
+    }
+
+    fn blocks(&self) -> anyhow::Result<RecordBatch> {
+        let rows = &self.blocks;
+        Columns::default()
+            .binary("block_hash", rows.iter().map(|r| r.hash.as_bytes()))
+            .binary("parent_hash", rows.iter().map(|r| r.parent_hash.as_bytes()))
+            .binary("author", rows.iter().map(|r| r.author.as_bytes()))
+            .binary("state_root", rows.iter().map(|r| r.state_root.as_bytes()))
+            .binary(
+                "transactions_root",
+                rows.iter().map(|r| r.transactions_root.as_bytes()),
+            )
+            .binary(
+                "receipts_root",
+                rows.iter().map(|r| r.receipts_root.as_bytes()),
+            )
+            .u32("block_number", rows.iter().map(|r| r.number as u32))
+            .u64("gas_used", rows.iter().map(|r| r.gas_used))
+            .u64("gas_limit", rows.iter().map(|r| r.gas_limit))
+            .binary("extra_data", rows.iter().map(|r| &r.extra_data[..]))
+            .binary("logs_bloom", rows.iter().map(|r| r.logs_bloom.as_bytes()))
+            .u32("timestamp", rows.iter().map(|r| r.timestamp as u32))
+            .string("difficulty", rows.iter().map(|r| r.difficulty.clone()))
+            .u64("base_fee_per_gas", rows.iter().map(|r| r.base_fee_per_gas))
+            .u64(
+                "transaction_count",
+                rows.iter().map(|r| r.transaction_count),
+            )
+            .u64("chain_id", rows.iter().map(|_| self.chain_id))
+            .finish()
+    }
+
+    fn transactions(&self) -> anyhow::Result<RecordBatch> {
+        let rows = &self.transactions;
+        Columns::default()
+            .u32("block_number", rows.iter().map(|r| r.block_number as u32))
+            .binary("block_hash", rows.iter().map(|r| r.block_hash.as_bytes()))
+            .u64(
+                "transaction_index",
+                rows.iter().map(|r| r.transaction_index),
+            )
+            .binary(
+                "transaction_hash",
+                rows.iter().map(|r| r.transaction_hash.as_bytes()),
+            )
+            .u64("nonce", rows.iter().map(|r| r.nonce))
+            .binary(
+                "from_address",
+                rows.iter().map(|r| r.from_address.as_bytes()),
+            )
+            .binary(
+                "to_address",
+                rows.iter()
+                    .map(|r| r.to_address.as_ref().map(|a| a.as_bytes())),
+            )
+            .string("value_string", rows.iter().map(|r| r.value.clone()))
+            .binary("input", rows.iter().map(|r| &r.input[..]))
+            .u64("gas_limit", rows.iter().map(|r| r.gas_limit))
+            .u64("gas_used", rows.iter().map(|r| r.gas_used))
+            .u64("gas_price", rows.iter().map(|r| r.gas_price))
+            .u32("transaction_type", rows.iter().map(|r| r.transaction_type))
+            .u64(
+                "max_priority_fee_per_gas",
+                rows.iter().map(|r| r.max_priority_fee_per_gas),
+            )
+            .u64("max_fee_per_gas", rows.iter().map(|r| r.max_fee_per_gas))
+            .bool("success", rows.iter().map(|r| r.success))
+            .u64("chain_id", rows.iter().map(|_| self.chain_id))
+            .finish()
+    }
+
+    fn receipts(&self) -> anyhow::Result<RecordBatch> {
+        let rows = &self.receipts;
+        Columns::default()
+            .binary(
+                "transaction_hash",
+                rows.iter().map(|r| r.transaction_hash.as_bytes()),
+            )
+            .u64(
+                "transaction_index",
+                rows.iter().map(|r| r.transaction_index),
+            )
+            .binary("block_hash", rows.iter().map(|r| r.block_hash.as_bytes()))
+            .u64("block_number", rows.iter().map(|r| r.block_number))
+            .u64(
+                "cumulative_gas_used",
+                rows.iter().map(|r| r.cumulative_gas_used),
+            )
+            .u64("gas_used", rows.iter().map(|r| r.gas_used))
+            .binary(
+                "contract_address",
+                rows.iter()
+                    .map(|r| r.contract_address.as_ref().map(|a| a.as_bytes())),
+            )
+            .u64("status", rows.iter().map(|r| r.status))
+            .u64(
+                "effective_gas_price",
+                rows.iter().map(|r| r.effective_gas_price),
+            )
+            .binary("logs_bloom", rows.iter().map(|r| r.logs_bloom.as_bytes()))
+            .u64("chain_id", rows.iter().map(|_| self.chain_id))
+            .finish()
+    }
+
+    fn logs(&self) -> anyhow::Result<RecordBatch> {
+        let rows = &self.logs;
+        let topic = |i: usize| {
+            rows.iter()
+                .map(move |r| r.topics[i].as_ref().map(|t| t.as_bytes()))
+        };
+        Columns::default()
+            .u32("block_number", rows.iter().map(|r| r.block_number as u32))
+            .binary("block_hash", rows.iter().map(|r| r.block_hash.as_bytes()))
+            .u32(
+                "transaction_index",
+                rows.iter().map(|r| r.transaction_index as u32),
+            )
+            .u32("log_index", rows.iter().map(|r| r.log_index as u32))
+            .binary(
+                "transaction_hash",
+                rows.iter().map(|r| r.transaction_hash.as_bytes()),
+            )
+            .binary("address", rows.iter().map(|r| r.address.as_bytes()))
+            .binary("topic0", topic(0))
+            .binary("topic1", topic(1))
+            .binary("topic2", topic(2))
+            .binary("topic3", topic(3))
+            .binary("data", rows.iter().map(|r| &r.data[..]))
+            .u64("chain_id", rows.iter().map(|_| self.chain_id))
+            .finish()
+    }
+
+    fn traces(&self) -> anyhow::Result<RecordBatch> {
+        let rows = &self.traces;
+        let bytes = |f: fn(&TraceRow) -> &Option<Bytes>| rows.iter().map(move |r| f(r).as_deref());
+        let address = |f: fn(&TraceRow) -> &Option<Address>| {
+            rows.iter()
+                .map(move |r| f(r).as_ref().map(|a| a.as_bytes()))
+        };
+        Columns::default()
+            .binary("action_from", rows.iter().map(|r| r.action_from.as_bytes()))
+            .binary("action_to", address(|r| &r.action_to))
+            .string("action_value", rows.iter().map(|r| r.action_value.clone()))
+            .u64("action_gas", rows.iter().map(|r| r.action_gas))
+            .binary("action_input", bytes(|r| &r.action_input))
+            .string(
+                "action_call_type",
+                rows.iter().map(|r| r.action_call_type.clone()),
+            )
+            .binary("action_init", bytes(|r| &r.action_init))
+            .string(
+                "action_type",
+                rows.iter().map(|r| r.action_type.to_string()),
+            )
+            .u64("result_gas_used", rows.iter().map(|r| r.result_gas_used))
+            .binary("result_output", bytes(|r| &r.result_output))
+            .binary("result_code", bytes(|r| &r.result_code))
+            .binary("result_address", address(|r| &r.result_address))
+            .string(
+                "trace_address",
+                rows.iter().map(|r| r.trace_address.clone()),
+            )
+            .u32("subtraces", rows.iter().map(|r| r.subtraces))
+            .u32(
+                "transaction_position",
+                rows.iter().map(|r| r.location.transaction_position as u32),
+            )
+            .binary(
+                "transaction_hash",
+                rows.iter().map(|r| r.location.transaction_hash.as_bytes()),
+            )
+            .u32(
+                "block_number",
+                rows.iter().map(|r| r.location.block_number as u32),
+            )
+            .binary(
+                "block_hash",
+                rows.iter().map(|r| r.location.block_hash.as_bytes()),
+            )
+            .string("error", rows.iter().map(|r| r.error.clone()))
+            .u64("chain_id", rows.iter().map(|_| self.chain_id))
+            .finish()
+    }
+
+    /// Write every dataset into its own file in `dir`, returning their paths.
+    pub fn write(&self, dir: &Path, chain_name: &str) -> anyhow::Result<Vec<PathBuf>> {
+        let mut paths = Vec::with_capacity(DATASETS.len());
+        for &dataset in DATASETS {
+            let batch = match dataset {
+                "blocks" => self.blocks()?,
+                "transactions" => self.transactions()?,
+                "receipts" => self.receipts()?,
+                "logs" => self.logs()?,
+                "traces" => self.traces()?,
+                _ => unreachable!(),
+            };
+
+            let path = partition_path(dir, chain_name, dataset, self.from, self.to);
+            // Write under a temporary name, so that an interrupted export leaves no partial files.
+            let tmp_path = path.with_extension("parquet.tmp");
+            let props = WriterProperties::builder()
+                .set_compression(Compression::SNAPPY)
+                .build();
+            let mut writer =
+                ArrowWriter::try_new(File::create(&tmp_path)?, batch.schema(), Some(props))?;
+            writer.write(&batch)?;
+            writer.close()?;
+            std::fs::rename(&tmp_path, &path)?;
+
+            paths.push(path);
+        }
+
+        Ok(paths)
+    }
+}
+
+/// Export canonical blocks `from..=to` into `dir`, one set of files per `partition_size` blocks.
+/// Partitions whose files already exist are skipped, so that an interrupted export can be resumed.
+pub async fn export<'db, Tx: Transaction<'db>>(
+    tx: &Tx,
+    dir: &Path,
+    from: BlockNumber,
+    to: BlockNumber,
+    partition_size: u64,
+) -> anyhow::Result<()> {
+    ensure!(partition_size > 0, "partition size must be positive");
+    ensure!(from <= to, "empty block range {}..={}", from, to);
+
+    let executed = EXECUTION.get_progress(tx).await?.unwrap_or(BlockNumber(0));
+    if to > executed {
+        bail!(
+            "block {} is not executed yet, execution stage is at {}",
+            to,
+            executed
+        );
+    }
+
+    let chain_spec = chain::chain_spec::read(tx).await?;
+
+    std::fs::create_dir_all(dir)?;
+
+    // Partitions are aligned to multiples of the partition size, as in cryo.
+    let mut start = from.0 - from.0 % partition_size;
+    while start <= to.0 {
+        let end = start + partition_size - 1;
+        let partition_from = BlockNumber(start.max(from.0));
+        let partition_to = BlockNumber(end.min(to.0));
+        start += partition_size;
+
+        if DATASETS.iter().all(|dataset| {
+            partition_path(dir, &chain_spec.name, dataset, partition_from, partition_to).exists()
+        }) {
+            info!(
+                "Blocks {}..={} already exported, skipping",
+                partition_from, partition_to
+            );
+            continue;
+        }
+
+        let mut partition =
+            Partition::new(chain_spec.params.chain_id, partition_from, partition_to);
+        for block_number in partition_from..=partition_to {
+            partition.push(&execute_block(tx, &chain_spec, block_number).await?);
+        }
+        let paths = partition.write(dir, &chain_spec.name)?;
+
+        info!(
+            "Exported blocks {}..={} into {} files",
+            partition_from,
+            partition_to,
+            paths.len()
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethereum_types::U64;
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+
+    fn frame(kind: &'static str, calls: Vec<CallFrame>) -> CallFrame {
+        CallFrame {
+            kind,
+            from: Address::from_low_u64_be(1),
+            to: Address::from_low_u64_be(2),
+            value: Some(U256::ZERO),
+            gas: U64::from(100_000),
+            gas_used: U64::from(21_000),
+            input: Bytes::new(),
+            selector: None,
+            signature: None,
+            output: Bytes::new(),
+            error: None,
+            revert_reason: None,
+            calls,
+        }
+    }
+
+    #[test]
+    fn writes_partition() {
+        let txn = MessageWithSignature {
+            message: Message::Legacy {
+                chain_id: Some(ChainId(1)),
+                nonce: 0,
+                gas_price: 10_u64.as_u256(),
+                gas_limit: 100_000,
+                action: TransactionAction::Call(Address::from_low_u64_be(2)),
+                value: 5_u64.as_u256(),
+                input: Bytes::new(),
+            },
+            signature: MessageSignature::new(false, H256::repeat_byte(1), H256::repeat_byte(2))
+                .unwrap(),
+        };
+        let block = ExecutedBlock {
+            hash: H256::repeat_byte(0xaa),
+            header: BlockHeader {
+                number: BlockNumber(1000),
+                gas_used: 50_000,
+                ..BlockHeader::empty()
+            },
+            transactions: vec![txn.clone(), txn],
+            senders: vec![Address::from_low_u64_be(1); 2],
+            receipts: vec![
+                Receipt::new(
+                    TxType::Legacy,
+                    true,
+                    21_000,
+                    vec![Log {
+                        address: Address::from_low_u64_be(2),
+                        topics: vec![H256::repeat_byte(3)],
+                        data: Bytes::new(),
+                    }],
+                ),
+                Receipt::new(TxType::Legacy, false, 50_000, vec![]),
+            ],
+            traces: vec![
+                frame("CALL", vec![]),
+                frame(
+                    "CALL",
+                    vec![frame("DELEGATECALL", vec![]), frame("CREATE", vec![])],
+                ),
+            ],
+        };
+
+        let mut partition = Partition::new(ChainId(1), BlockNumber(1000), BlockNumber(1999));
+        partition.push(&block);
+        assert_eq!(partition.transactions[1].gas_used, 29_000);
+        assert_eq!(partition.traces.len(), 4);
+        assert_eq!(partition.traces[3].trace_address, "1");
+        assert_eq!(partition.traces[3].action_type, "create");
+
+        let dir = tempfile::tempdir().unwrap();
+        let paths = partition.write(dir.path(), "Mainnet").unwrap();
+        assert_eq!(paths.len(), DATASETS.len());
+        assert_eq!(
+            paths[0].file_name().unwrap(),
+            "mainnet__blocks__00001000_to_00001999.parquet"
+        );
+
+        let rows = paths
+            .iter()
+            .map(|path| {
+                SerializedFileReader::new(File::open(path).unwrap())
+                    .unwrap()
+                    .metadata()
+                    .file_metadata()
+                    .num_rows()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(rows, [1, 2, 2, 1, 4]);
+    }
+}
@@ -14,6 +14,30 @@ impl AkulaDataDir {
     pub fn etl_temp_dir(&self) -> PathBuf {
         self.0.join("etl-temp")
     }
+
+    pub fn state_root_diff_dir(&self) -> PathBuf {
+        self.0.join("state-root-diffs")
+    }
+
+    pub fn history_dir(&self) -> PathBuf {
+        self.0.join("history")
+    }
+
+    pub fn snapshot_download_dir(&self) -> PathBuf {
+        self.0.join("snapshot-downloads")
+    }
+
+    pub fn jwt_secret_path(&self) -> PathBuf {
+        self.0.join("jwt.hex")
+    }
+
+    pub fn banned_peers_path(&self) -> PathBuf {
+        self.0.join("banned-peers.json")
+    }
+
+    pub fn known_nodes_path(&self) -> PathBuf {
+        self.0.join("known-nodes.json")
+    }
 }
 
 impl Default for AkulaDataDir {
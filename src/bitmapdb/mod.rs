@@ -1,8 +1,10 @@
 use crate::{
+    etl::collector::Collector,
     kv::{tables::BitmapKey, traits::*},
     models::*,
 };
 use croaring::{treemap::NativeSerializer, Treemap as RoaringTreemap};
+use itertools::Itertools;
 use std::{iter::Peekable, ops::RangeInclusive};
 use tokio::pin;
 use tokio_stream::StreamExt;
@@ -54,6 +56,125 @@ where
     Ok(out.unwrap_or_default())
 }
 
+/// Merge bitmaps collected for every key into the last chunk stored for the key
+/// and write them back in chunks.
+pub async fn load<'tx, 'tmp, C, T, K>(
+    cursor: &mut C,
+    mut collector: Collector<'tmp, K, RoaringTreemap>,
+) -> anyhow::Result<()>
+where
+    C: MutableCursor<'tx, T>,
+    T: Table<Key = BitmapKey<K>, Value = RoaringTreemap>,
+    K: TableEncode + TableDecode + Clone + PartialEq + Send,
+    BitmapKey<K>: TableDecode,
+    <K as TableEncode>::Encoded: Ord,
+    Vec<u8>: From<<K as TableEncode>::Encoded>,
+{
+    for res in collector
+        .iter()
+        .map(|res| {
+            let (key, bitmap) = res?;
+
+            let key = K::decode(&key)?;
+            let bitmap = RoaringTreemap::decode(&bitmap)?;
+
+            Ok::<_, anyhow::Error>((key, bitmap))
+        })
+        .coalesce(|prev, current| match (prev, current) {
+            (Ok((prev_key, prev_bitmap)), Ok((current_key, current_bitmap))) => {
+                if prev_key == current_key {
+                    Ok(Ok((prev_key, prev_bitmap | current_bitmap)))
+                } else {
+                    Err((
+                        Ok((prev_key, prev_bitmap)),
+                        Ok((current_key, current_bitmap)),
+                    ))
+                }
+            }
+            err => Err(err),
+        })
+    {
+        let (key, mut total_bitmap) = res?;
+
+        if !total_bitmap.is_empty() {
+            if let Some((_, last_bitmap)) = cursor
+                .seek_exact(BitmapKey {
+                    inner: key.clone(),
+                    block_number: BlockNumber(u64::MAX),
+                })
+                .await?
+            {
+                total_bitmap |= last_bitmap;
+            }
+
+            for (block_number, bitmap) in Chunks::new(total_bitmap, CHUNK_LIMIT).with_keys() {
+                cursor
+                    .put(
+                        BitmapKey {
+                            inner: key.clone(),
+                            block_number,
+                        },
+                        bitmap,
+                    )
+                    .await?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Remove blocks after `unwind_to` from bitmaps of `keys`.
+pub async fn unwind<'tx, C, T, K>(
+    cursor: &mut C,
+    keys: impl IntoIterator<Item = K>,
+    unwind_to: BlockNumber,
+) -> anyhow::Result<()>
+where
+    C: MutableCursor<'tx, T>,
+    T: Table<Key = BitmapKey<K>, Value = RoaringTreemap>,
+    K: Clone + PartialEq + Send,
+    BitmapKey<K>: TableDecode,
+{
+    for key in keys {
+        let mut bm = cursor
+            .seek_exact(BitmapKey {
+                inner: key.clone(),
+                block_number: BlockNumber(u64::MAX),
+            })
+            .await?
+            .map(|(_, bm)| bm);
+
+        while let Some(b) = bm {
+            cursor.delete_current().await?;
+
+            let new_bm = b
+                .iter()
+                .take_while(|&v| v <= *unwind_to)
+                .collect::<RoaringTreemap>();
+
+            if new_bm.cardinality() > 0 {
+                cursor
+                    .upsert(
+                        BitmapKey {
+                            inner: key.clone(),
+                            block_number: BlockNumber(u64::MAX),
+                        },
+                        new_bm,
+                    )
+                    .await?;
+            }
+
+            bm = cursor
+                .prev()
+                .await?
+                .and_then(|(BitmapKey { inner, .. }, b)| if inner == key { Some(b) } else { None });
+        }
+    }
+
+    Ok(())
+}
+
 pub struct Chunks {
     bm: RoaringTreemap,
     size_limit: usize,
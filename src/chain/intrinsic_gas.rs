@@ -1,10 +1,41 @@
 use super::protocol_param::fee;
-use crate::models::*;
+use crate::{consensus::ValidationError, models::*};
+use evmodin::Revision;
 
-pub fn intrinsic_gas(txn: &Message, homestead: bool, istanbul: bool) -> u128 {
+/// Fork-dependent parameters of intrinsic gas.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IntrinsicGasSchedule {
+    /// Whether contract creation costs [`fee::G_TX_CREATE`] on top of the base cost (EIP-2).
+    pub charge_create: bool,
+    /// Cost of non-zero byte of data (EIP-2028).
+    pub non_zero_data_byte: u64,
+    /// Cost of 32-byte word of initcode in contract creation (EIP-3860).
+    pub initcode_word: u64,
+}
+
+impl From<Revision> for IntrinsicGasSchedule {
+    fn from(revision: Revision) -> Self {
+        Self {
+            charge_create: revision >= Revision::Homestead,
+            non_zero_data_byte: if revision >= Revision::Istanbul {
+                fee::G_TX_DATA_NON_ZERO_ISTANBUL
+            } else {
+                fee::G_TX_DATA_NON_ZERO_FRONTIER
+            },
+            initcode_word: if revision >= Revision::Shanghai {
+                fee::G_INITCODE_WORD
+            } else {
+                0
+            },
+        }
+    }
+}
+
+pub fn intrinsic_gas(txn: &Message, schedule: IntrinsicGasSchedule) -> u128 {
     let mut gas = fee::G_TRANSACTION as u128;
 
-    if matches!(txn.action(), TransactionAction::Create) && homestead {
+    let is_create = matches!(txn.action(), TransactionAction::Create);
+    if is_create && schedule.charge_create {
         gas += u128::from(fee::G_TX_CREATE);
     }
 
@@ -19,16 +50,138 @@ pub fn intrinsic_gas(txn: &Message, homestead: bool, istanbul: bool) -> u128 {
     }
 
     let non_zero_bytes = txn.input().iter().filter(|&&c| c != 0).count() as u128;
-
-    let non_zero_gas = u128::from(if istanbul {
-        fee::G_TX_DATA_NON_ZERO_ISTANBUL
-    } else {
-        fee::G_TX_DATA_NON_ZERO_FRONTIER
-    });
-    gas += non_zero_bytes * non_zero_gas;
+    gas += non_zero_bytes * u128::from(schedule.non_zero_data_byte);
 
     let zero_bytes = txn.input().len() as u128 - non_zero_bytes;
     gas += zero_bytes * u128::from(fee::G_TX_DATA_ZERO);
 
+    // https://eips.ethereum.org/EIPS/eip-3860
+    if is_create {
+        let words = (txn.input().len() as u128 + 31) / 32;
+        gas += words * u128::from(schedule.initcode_word);
+    }
+
     gas
 }
+
+/// Gas left for execution once intrinsic gas is paid, or an error if gas limit does not cover it.
+///
+/// Same check for transactions entering the pool and for transactions being executed.
+pub fn check_intrinsic_gas(
+    txn: &Message,
+    schedule: IntrinsicGasSchedule,
+) -> Result<u64, ValidationError> {
+    u128::from(txn.gas_limit())
+        .checked_sub(intrinsic_gas(txn, schedule))
+        .map(|gas| gas as u64)
+        .ok_or(ValidationError::IntrinsicGas)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hex_literal::hex;
+
+    const REVISIONS: &[Revision] = &[
+        Revision::Frontier,
+        Revision::Homestead,
+        Revision::Tangerine,
+        Revision::Spurious,
+        Revision::Byzantium,
+        Revision::Constantinople,
+        Revision::Petersburg,
+        Revision::Istanbul,
+        Revision::Berlin,
+        Revision::London,
+        Revision::Shanghai,
+    ];
+
+    fn message(action: TransactionAction, input: &[u8], access_list: AccessList) -> Message {
+        Message::EIP2930 {
+            chain_id: ChainId(1),
+            nonce: 0,
+            gas_price: U256::ZERO,
+            gas_limit: 1_000_000,
+            action,
+            value: U256::ZERO,
+            input: input.to_vec().into(),
+            access_list,
+        }
+    }
+
+    #[test]
+    fn intrinsic_gas_per_fork() {
+        let to = TransactionAction::Call(hex!("5df9b87991262f6ba471f09758cde1c0fc1de734").into());
+        // 3 zero and 33 non-zero bytes, two words of initcode
+        let mut data = vec![0; 3];
+        data.extend_from_slice(&[1; 33]);
+        let access_list = vec![
+            AccessListItem {
+                address: Address::from_low_u64_be(1),
+                slots: vec![H256::zero(), H256::repeat_byte(1)],
+            },
+            AccessListItem {
+                address: Address::from_low_u64_be(2),
+                slots: vec![],
+            },
+        ];
+
+        for &revision in REVISIONS {
+            let schedule = IntrinsicGasSchedule::from(revision);
+            let create = if revision >= Revision::Homestead {
+                32_000
+            } else {
+                0
+            };
+            let non_zero = if revision >= Revision::Istanbul {
+                16
+            } else {
+                68
+            };
+            let initcode = if revision >= Revision::Shanghai { 2 } else { 0 };
+
+            for (txn, expected) in [
+                (message(to, &[], vec![]), 21_000),
+                (
+                    message(TransactionAction::Create, &[], vec![]),
+                    21_000 + create,
+                ),
+                (message(to, &data, vec![]), 21_000 + 3 * 4 + 33 * non_zero),
+                (
+                    message(TransactionAction::Create, &data, vec![]),
+                    21_000 + create + 3 * 4 + 33 * non_zero + 2 * initcode,
+                ),
+                (
+                    message(to, &[], access_list.clone()),
+                    21_000 + 2 * 2400 + 2 * 1900,
+                ),
+            ] {
+                assert_eq!(
+                    intrinsic_gas(&txn, schedule),
+                    expected,
+                    "{:?}: {:?}",
+                    revision,
+                    txn
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn gas_limit_covers_intrinsic_gas() {
+        let schedule = IntrinsicGasSchedule::from(Revision::London);
+        let mut txn = message(TransactionAction::Create, &[], vec![]);
+
+        for (gas_limit, expected) in [
+            (0, Err(ValidationError::IntrinsicGas)),
+            (52_999, Err(ValidationError::IntrinsicGas)),
+            (53_000, Ok(0)),
+            (60_000, Ok(7_000)),
+        ] {
+            if let Message::EIP2930 { gas_limit: g, .. } = &mut txn {
+                *g = gas_limit;
+            }
+            assert_eq!(check_intrinsic_gas(&txn, schedule), expected);
+        }
+    }
+}
@@ -22,11 +22,13 @@ pub mod fee {
     pub const G_TX_DATA_ZERO: u64 = 4;
     pub const G_TX_DATA_NON_ZERO_FRONTIER: u64 = 68;
     pub const G_TX_DATA_NON_ZERO_ISTANBUL: u64 = 16;
+    pub const G_INITCODE_WORD: u64 = 2;
     pub const G_TRANSACTION: u64 = 21_000;
 } // namespace fee
 
 pub mod param {
     use crate::models::*;
+    use hex_literal::hex;
 
     // https://eips.ethereum.org/EIPS/eip-170
     pub const MAX_CODE_SIZE: usize = 0x6000;
@@ -46,4 +48,16 @@ pub mod param {
     pub const INITIAL_BASE_FEE: u64 = 1_000_000_000;
     pub const BASE_FEE_MAX_CHANGE_DENOMINATOR: u64 = 8;
     pub const ELASTICITY_MULTIPLIER: u64 = 2;
+
+    // https://eips.ethereum.org/EIPS/eip-4844
+    pub const GAS_PER_BLOB: u64 = 1 << 17;
+    pub const TARGET_BLOB_GAS_PER_BLOCK: u64 = 393_216;
+    pub const MAX_BLOB_GAS_PER_BLOCK: u64 = 786_432;
+    pub const MIN_BLOB_BASE_FEE: u64 = 1;
+    pub const BLOB_BASE_FEE_UPDATE_FRACTION: u64 = 3_338_477;
+    pub const VERSIONED_HASH_VERSION_KZG: u8 = 0x01;
+
+    // https://eips.ethereum.org/EIPS/eip-4788
+    pub const SYSTEM_ADDRESS: Address = H160(hex!("fffffffffffffffffffffffffffffffffffffffe"));
+    pub const SYSTEM_CALL_GAS: u64 = 30_000_000;
 }
@@ -4,20 +4,128 @@ use anyhow::Context;
 use async_recursion::*;
 use std::time::SystemTime;
 
+/// Check block contents against the transactions root, ommers hash and withdrawals root of its
+/// header. Needs no state, so also applies to bodies downloaded ahead of execution.
+pub fn verify_body_roots(
+    header: &BlockHeader,
+    transactions: &[MessageWithSignature],
+    ommers: &[BlockHeader],
+    withdrawals: Option<&[Withdrawal]>,
+) -> Result<(), ValidationError> {
+    let expected_ommers_hash = Block::ommers_hash(ommers);
+    if header.ommers_hash != expected_ommers_hash {
+        return Err(ValidationError::WrongOmmersHash {
+            expected: expected_ommers_hash,
+            got: header.ommers_hash,
+        });
+    }
+
+    let expected_transactions_root = Block::transactions_root(transactions);
+    if header.transactions_root != expected_transactions_root {
+        return Err(ValidationError::WrongTransactionsRoot {
+            expected: expected_transactions_root,
+            got: header.transactions_root,
+        });
+    }
+
+    let expected_withdrawals_root = withdrawals.map(Block::withdrawals_root);
+    if header.withdrawals_root != expected_withdrawals_root {
+        return Err(ValidationError::WrongWithdrawalsRoot {
+            expected: expected_withdrawals_root,
+            got: header.withdrawals_root,
+        });
+    }
+
+    Ok(())
+}
+
+/// Base fee of block `number` with `parent`, if EIP-1559 is active since `eip1559_block`.
+///
+/// https://eips.ethereum.org/EIPS/eip-1559
+pub fn expected_base_fee_per_gas(
+    eip1559_block: Option<BlockNumber>,
+    number: BlockNumber,
+    parent: &BlockHeader,
+) -> Option<U256> {
+    if let Some(fork_block) = eip1559_block {
+        if number >= fork_block {
+            if number == fork_block {
+                return Some(param::INITIAL_BASE_FEE.into());
+            }
+
+            let parent_gas_target = parent.gas_limit / param::ELASTICITY_MULTIPLIER;
+
+            let parent_base_fee_per_gas = parent.base_fee_per_gas.unwrap();
+
+            if parent.gas_used == parent_gas_target {
+                return Some(parent_base_fee_per_gas);
+            }
+
+            if parent.gas_used > parent_gas_target {
+                let gas_used_delta = parent.gas_used - parent_gas_target;
+                let base_fee_per_gas_delta = std::cmp::max(
+                    U256::ONE,
+                    parent_base_fee_per_gas * U256::from(gas_used_delta)
+                        / U256::from(parent_gas_target)
+                        / U256::from(param::BASE_FEE_MAX_CHANGE_DENOMINATOR),
+                );
+                return Some(parent_base_fee_per_gas + base_fee_per_gas_delta);
+            } else {
+                let gas_used_delta = parent_gas_target - parent.gas_used;
+                let base_fee_per_gas_delta = parent_base_fee_per_gas * U256::from(gas_used_delta)
+                    / U256::from(parent_gas_target)
+                    / U256::from(param::BASE_FEE_MAX_CHANGE_DENOMINATOR);
+
+                return Some(parent_base_fee_per_gas.saturating_sub(base_fee_per_gas_delta));
+            }
+        }
+    }
+
+    None
+}
+
+/// Excess blob gas of block `number` with `parent`, if EIP-4844 is active since `eip4844_block`.
+///
+/// https://eips.ethereum.org/EIPS/eip-4844
+pub fn expected_excess_blob_gas(
+    eip4844_block: Option<BlockNumber>,
+    number: BlockNumber,
+    parent: &BlockHeader,
+) -> Option<u64> {
+    switch_is_active(eip4844_block, number).then(|| {
+        (parent.excess_blob_gas.unwrap_or(0) + parent.blob_gas_used.unwrap_or(0))
+            .saturating_sub(param::TARGET_BLOB_GAS_PER_BLOCK)
+    })
+}
+
 #[derive(Debug)]
 pub struct ConsensusEngineBase {
     chain_id: ChainId,
     eip1559_block: Option<BlockNumber>,
+    eip4844_block: Option<BlockNumber>,
+    extra_data_limit: Option<usize>,
 }
 
 impl ConsensusEngineBase {
-    pub fn new(chain_id: ChainId, eip1559_block: Option<BlockNumber>) -> Self {
+    pub fn new(
+        chain_id: ChainId,
+        eip1559_block: Option<BlockNumber>,
+        eip4844_block: Option<BlockNumber>,
+    ) -> Self {
         Self {
             chain_id,
             eip1559_block,
+            eip4844_block,
+            extra_data_limit: Some(32),
         }
     }
 
+    /// Skip the 32 byte limit of extra data, for engines that keep their own data there.
+    pub fn without_extra_data_limit(mut self) -> Self {
+        self.extra_data_limit = None;
+        self
+    }
+
     pub async fn validate_block_header(
         &self,
         header: &BlockHeader,
@@ -55,8 +163,10 @@ impl ConsensusEngineBase {
             return Err(ValidationError::InvalidGasLimit.into());
         }
 
-        if header.extra_data.len() > 32 {
-            return Err(ValidationError::ExtraDataTooLong.into());
+        if let Some(limit) = self.extra_data_limit {
+            if header.extra_data.len() > limit {
+                return Err(ValidationError::ExtraDataTooLong.into());
+            }
         }
 
         if header.timestamp <= parent.timestamp {
@@ -92,6 +202,35 @@ impl ConsensusEngineBase {
             .into());
         }
 
+        let expected_excess_blob_gas =
+            expected_excess_blob_gas(self.eip4844_block, header.number, parent);
+        if header.excess_blob_gas != expected_excess_blob_gas {
+            return Err(ValidationError::WrongExcessBlobGas {
+                expected: expected_excess_blob_gas,
+                got: header.excess_blob_gas,
+            }
+            .into());
+        }
+
+        match header.blob_gas_used {
+            Some(blob_gas_used) => {
+                if expected_excess_blob_gas.is_none() {
+                    return Err(ValidationError::UnexpectedBlobGasUsed.into());
+                }
+
+                if blob_gas_used > param::MAX_BLOB_GAS_PER_BLOCK
+                    || blob_gas_used % param::GAS_PER_BLOB != 0
+                {
+                    return Err(ValidationError::BlobGasLimitExceeded.into());
+                }
+            }
+            None => {
+                if expected_excess_blob_gas.is_some() {
+                    return Err(ValidationError::MissingBlobGasUsed.into());
+                }
+            }
+        }
+
         Ok(())
     }
 
@@ -158,48 +297,12 @@ impl ConsensusEngineBase {
         header.beneficiary
     }
 
-    // https://eips.ethereum.org/EIPS/eip-1559
     fn expected_base_fee_per_gas(
         &self,
         header: &BlockHeader,
         parent: &BlockHeader,
     ) -> Option<U256> {
-        if let Some(fork_block) = self.eip1559_block {
-            if header.number >= fork_block {
-                if header.number == fork_block {
-                    return Some(param::INITIAL_BASE_FEE.into());
-                }
-
-                let parent_gas_target = parent.gas_limit / param::ELASTICITY_MULTIPLIER;
-
-                let parent_base_fee_per_gas = parent.base_fee_per_gas.unwrap();
-
-                if parent.gas_used == parent_gas_target {
-                    return Some(parent_base_fee_per_gas);
-                }
-
-                if parent.gas_used > parent_gas_target {
-                    let gas_used_delta = parent.gas_used - parent_gas_target;
-                    let base_fee_per_gas_delta = std::cmp::max(
-                        U256::ONE,
-                        parent_base_fee_per_gas * U256::from(gas_used_delta)
-                            / U256::from(parent_gas_target)
-                            / U256::from(param::BASE_FEE_MAX_CHANGE_DENOMINATOR),
-                    );
-                    return Some(parent_base_fee_per_gas + base_fee_per_gas_delta);
-                } else {
-                    let gas_used_delta = parent_gas_target - parent.gas_used;
-                    let base_fee_per_gas_delta = parent_base_fee_per_gas
-                        * U256::from(gas_used_delta)
-                        / U256::from(parent_gas_target)
-                        / U256::from(param::BASE_FEE_MAX_CHANGE_DENOMINATOR);
-
-                    return Some(parent_base_fee_per_gas.saturating_sub(base_fee_per_gas_delta));
-                }
-            }
-        }
-
-        None
+        expected_base_fee_per_gas(self.eip1559_block, header.number, parent)
     }
 
     pub async fn pre_validate_block(
@@ -207,23 +310,12 @@ impl ConsensusEngineBase {
         block: &Block,
         state: &mut dyn State,
     ) -> anyhow::Result<()> {
-        let expected_ommers_hash = Block::ommers_hash(&block.ommers);
-        if block.header.ommers_hash != expected_ommers_hash {
-            return Err(ValidationError::WrongOmmersHash {
-                expected: expected_ommers_hash,
-                got: block.header.ommers_hash,
-            }
-            .into());
-        }
-
-        let expected_transactions_root = Block::transactions_root(&block.transactions);
-        if block.header.transactions_root != expected_transactions_root {
-            return Err(ValidationError::WrongTransactionsRoot {
-                expected: expected_transactions_root,
-                got: block.header.transactions_root,
-            }
-            .into());
-        }
+        verify_body_roots(
+            &block.header,
+            &block.transactions,
+            &block.ommers,
+            block.withdrawals.as_deref(),
+        )?;
 
         if block.ommers.len() > 2 {
             return Err(ValidationError::TooManyOmmers.into());
@@ -268,8 +360,23 @@ impl ConsensusEngineBase {
             }
         }
 
+        let blob_transactions_allowed = switch_is_active(self.eip4844_block, block.header.number);
+        let mut blob_gas_used = 0;
         for txn in &block.transactions {
             pre_validate_transaction(txn, self.chain_id, block.header.base_fee_per_gas)?;
+
+            if txn.tx_type() == TxType::EIP4844 && !blob_transactions_allowed {
+                return Err(ValidationError::UnsupportedTransactionType.into());
+            }
+            blob_gas_used += txn.blob_gas();
+        }
+
+        if blob_transactions_allowed && block.header.blob_gas_used != Some(blob_gas_used) {
+            return Err(ValidationError::WrongBlobGasUsed {
+                expected: Some(blob_gas_used),
+                got: block.header.blob_gas_used,
+            }
+            .into());
         }
 
         Ok(())
@@ -280,6 +387,31 @@ impl ConsensusEngineBase {
 mod tests {
     use super::*;
     use crate::res::chainspec::MAINNET;
+    use proptest::prelude::*;
+
+    #[test]
+    fn body_roots() {
+        let ommers = vec![BlockHeader {
+            number: BlockNumber(9),
+            ..BlockHeader::empty()
+        }];
+        let header = BlockHeader {
+            number: BlockNumber(10),
+            ommers_hash: Block::ommers_hash(&ommers),
+            transactions_root: Block::transactions_root(&[] as &[MessageWithSignature]),
+            ..BlockHeader::empty()
+        };
+
+        assert_eq!(verify_body_roots(&header, &[], &ommers, None), Ok(()));
+        assert!(matches!(
+            verify_body_roots(&header, &[], &[], None),
+            Err(ValidationError::WrongOmmersHash { .. })
+        ));
+        assert!(matches!(
+            verify_body_roots(&header, &[], &ommers, Some(&[])),
+            Err(ValidationError::WrongWithdrawalsRoot { .. })
+        ));
+    }
 
     #[test]
     fn validate_max_fee_per_gas() {
@@ -336,4 +468,279 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn excess_blob_gas() {
+        let parent = BlockHeader {
+            excess_blob_gas: Some(param::TARGET_BLOB_GAS_PER_BLOCK),
+            blob_gas_used: Some(param::MAX_BLOB_GAS_PER_BLOCK),
+            ..BlockHeader::empty()
+        };
+
+        assert_eq!(expected_excess_blob_gas(None, 1.into(), &parent), None);
+        assert_eq!(
+            expected_excess_blob_gas(Some(2.into()), 1.into(), &parent),
+            None
+        );
+        assert_eq!(
+            expected_excess_blob_gas(Some(1.into()), 1.into(), &parent),
+            Some(param::MAX_BLOB_GAS_PER_BLOCK)
+        );
+
+        // Fork block starts from zero, and excess does not go below it.
+        assert_eq!(
+            expected_excess_blob_gas(Some(1.into()), 1.into(), &BlockHeader::empty()),
+            Some(0)
+        );
+    }
+
+    #[test]
+    fn validate_blob_transaction() {
+        let txn = |blob_versioned_hashes| Message::EIP4844 {
+            chain_id: ChainId(1),
+            nonce: 0,
+            max_priority_fee_per_gas: U256::ZERO,
+            max_fee_per_gas: U256::ZERO,
+            gas_limit: 21_000,
+            action: TransactionAction::Call(Address::zero()),
+            value: U256::ZERO,
+            input: vec![].into(),
+            access_list: vec![],
+            max_fee_per_blob_gas: U256::ONE,
+            blob_versioned_hashes,
+        };
+        let hash = |version| {
+            let mut hash = H256::repeat_byte(0xaa);
+            hash.0[0] = version;
+            hash
+        };
+
+        for (hashes, expected) in [
+            (vec![hash(1)], Ok(())),
+            (vec![], Err(ValidationError::NoBlobs)),
+            (
+                vec![hash(1), hash(0)],
+                Err(ValidationError::WrongBlobVersionedHash),
+            ),
+            (vec![hash(1); 7], Err(ValidationError::BlobGasLimitExceeded)),
+        ] {
+            assert_eq!(
+                pre_validate_transaction(&txn(hashes), MAINNET.params.chain_id, None),
+                expected
+            );
+        }
+    }
+
+    const LONDON: u64 = 100;
+    const CANCUN: u64 = 200;
+
+    /// Base fee of block `number` per EIP-1559, written independently of the validator.
+    fn spec_base_fee(number: u64, parent: &BlockHeader) -> Option<U256> {
+        if number < LONDON {
+            return None;
+        }
+        if number == LONDON {
+            return Some(1_000_000_000.as_u256());
+        }
+
+        let parent_base_fee = parent.base_fee_per_gas.unwrap();
+        let target = U256::from(parent.gas_limit / 2);
+        let used = U256::from(parent.gas_used);
+        Some(if used == target {
+            parent_base_fee
+        } else if used > target {
+            parent_base_fee
+                + std::cmp::max(parent_base_fee * (used - target) / target / 8, U256::ONE)
+        } else {
+            parent_base_fee - parent_base_fee * (target - used) / target / 8
+        })
+    }
+
+    /// Excess blob gas of block `number` per EIP-4844.
+    fn spec_excess_blob_gas(number: u64, parent: &BlockHeader) -> Option<u64> {
+        if number < CANCUN {
+            return None;
+        }
+
+        let total = parent.excess_blob_gas.unwrap_or(0) + parent.blob_gas_used.unwrap_or(0);
+        Some(if total < 3 * (1 << 17) {
+            0
+        } else {
+            total - 3 * (1 << 17)
+        })
+    }
+
+    fn spec_valid(header: &BlockHeader, parent: &BlockHeader) -> bool {
+        let number = header.number.0;
+
+        let parent_gas_limit = if number == LONDON {
+            parent.gas_limit * 2
+        } else {
+            parent.gas_limit
+        };
+        let gas_limit_delta = if header.gas_limit > parent_gas_limit {
+            header.gas_limit - parent_gas_limit
+        } else {
+            parent_gas_limit - header.gas_limit
+        };
+
+        let blob_gas_used_valid = match header.blob_gas_used {
+            Some(used) => number >= CANCUN && used <= 6 * (1 << 17) && used % (1 << 17) == 0,
+            None => number < CANCUN,
+        };
+
+        header.gas_used <= header.gas_limit
+            && header.gas_limit >= 5000
+            && header.gas_limit <= i64::MAX as u64
+            && gas_limit_delta < parent_gas_limit / 1024
+            && header.extra_data.len() <= 32
+            && header.timestamp > parent.timestamp
+            && header.base_fee_per_gas == spec_base_fee(number, parent)
+            && header.excess_blob_gas == spec_excess_blob_gas(number, parent)
+            && blob_gas_used_valid
+    }
+
+    fn valid_child(parent: &BlockHeader) -> BlockHeader {
+        let number = parent.number.0 + 1;
+        BlockHeader {
+            number: number.into(),
+            gas_limit: if number == LONDON {
+                parent.gas_limit * 2
+            } else {
+                parent.gas_limit
+            },
+            timestamp: parent.timestamp + 12,
+            base_fee_per_gas: spec_base_fee(number, parent),
+            excess_blob_gas: spec_excess_blob_gas(number, parent),
+            blob_gas_used: (number >= CANCUN).then(|| 0),
+            ..BlockHeader::empty()
+        }
+    }
+
+    prop_compose! {
+        /// Parents of blocks around fork boundaries.
+        fn parents()(
+            number in prop::sample::select(
+                vec![1, LONDON - 1, LONDON, LONDON + 1, CANCUN - 1, CANCUN, CANCUN + 1]
+            ),
+            gas_limit in 5_000..30_000_000_u64,
+            gas_used_permille in 0..=1_000_u64,
+            base_fee in 0..1_000_000_000_000_u64,
+            excess_blob_gas in 0..4 * (1_u64 << 17),
+            blobs in 0..=6_u64,
+            timestamp in 1..1_000_000_000_u64,
+        ) -> BlockHeader {
+            let number = number - 1;
+            BlockHeader {
+                number: number.into(),
+                gas_limit,
+                gas_used: gas_limit * gas_used_permille / 1_000,
+                timestamp,
+                base_fee_per_gas: (number >= LONDON).then(|| base_fee.as_u256()),
+                excess_blob_gas: (number >= CANCUN).then(|| excess_blob_gas),
+                blob_gas_used: (number >= CANCUN).then(|| blobs * (1 << 17)),
+                ..BlockHeader::empty()
+            }
+        }
+    }
+
+    #[derive(Clone, Debug)]
+    enum Mutation {
+        GasLimitDelta(i64),
+        GasLimit(u64),
+        /// Set gas used relative to gas limit.
+        GasUsedOverLimit(i64),
+        /// Set timestamp relative to that of parent.
+        TimestampDelta(i64),
+        ExtraDataLen(usize),
+        BaseFeeDelta(i64),
+        ToggleBaseFee,
+        ExcessBlobGasDelta(i64),
+        ToggleExcessBlobGas,
+        BlobGasUsed(Option<u64>),
+    }
+
+    fn mutations() -> impl Strategy<Value = Mutation> {
+        prop_oneof![
+            (-3_000..3_000_i64).prop_map(Mutation::GasLimitDelta),
+            any::<u64>().prop_map(Mutation::GasLimit),
+            (-2..=2_i64).prop_map(Mutation::GasUsedOverLimit),
+            (-2..=2_i64).prop_map(Mutation::TimestampDelta),
+            (0..40_usize).prop_map(Mutation::ExtraDataLen),
+            (-2..=2_i64).prop_map(Mutation::BaseFeeDelta),
+            Just(Mutation::ToggleBaseFee),
+            (-2..=2_i64).prop_map(Mutation::ExcessBlobGasDelta),
+            Just(Mutation::ToggleExcessBlobGas),
+            prop::option::of(
+                (0..8_u64, any::<bool>())
+                    .prop_map(|(blobs, misaligned)| blobs * (1 << 17) + u64::from(misaligned))
+            )
+            .prop_map(Mutation::BlobGasUsed),
+        ]
+    }
+
+    fn offset(v: u64, delta: i64) -> u64 {
+        if delta >= 0 {
+            v.saturating_add(delta as u64)
+        } else {
+            v.saturating_sub(delta.unsigned_abs())
+        }
+    }
+
+    fn mutate(header: &mut BlockHeader, parent: &BlockHeader, mutation: &Mutation) {
+        match *mutation {
+            Mutation::GasLimitDelta(delta) => header.gas_limit = offset(header.gas_limit, delta),
+            Mutation::GasLimit(gas_limit) => header.gas_limit = gas_limit,
+            Mutation::GasUsedOverLimit(delta) => header.gas_used = offset(header.gas_limit, delta),
+            Mutation::TimestampDelta(delta) => header.timestamp = offset(parent.timestamp, delta),
+            Mutation::ExtraDataLen(len) => header.extra_data = vec![0; len].into(),
+            Mutation::BaseFeeDelta(delta) => {
+                if let Some(base_fee) = &mut header.base_fee_per_gas {
+                    *base_fee = if delta >= 0 {
+                        base_fee.saturating_add((delta as u64).as_u256())
+                    } else {
+                        base_fee.saturating_sub(delta.unsigned_abs().as_u256())
+                    };
+                }
+            }
+            Mutation::ToggleBaseFee => {
+                header.base_fee_per_gas = match header.base_fee_per_gas {
+                    Some(_) => None,
+                    None => Some(1_000_000_000.as_u256()),
+                }
+            }
+            Mutation::ExcessBlobGasDelta(delta) => {
+                if let Some(excess_blob_gas) = &mut header.excess_blob_gas {
+                    *excess_blob_gas = offset(*excess_blob_gas, delta);
+                }
+            }
+            Mutation::ToggleExcessBlobGas => {
+                header.excess_blob_gas = match header.excess_blob_gas {
+                    Some(_) => None,
+                    None => Some(0),
+                }
+            }
+            Mutation::BlobGasUsed(blob_gas_used) => header.blob_gas_used = blob_gas_used,
+        }
+    }
+
+    proptest! {
+        /// Headers derived from a valid one are rejected exactly when the spec says so.
+        #[test]
+        fn header_mutations(
+            parent in parents(),
+            mutations in prop::collection::vec(mutations(), 0..4),
+        ) {
+            let engine =
+                ConsensusEngineBase::new(ChainId(1), Some(LONDON.into()), Some(CANCUN.into()));
+
+            let mut header = valid_child(&parent);
+            for mutation in &mutations {
+                mutate(&mut header, &parent, mutation);
+            }
+
+            let res = tokio_test::block_on(engine.validate_block_header(&header, &parent, false));
+            prop_assert_eq!(res.is_ok(), spec_valid(&header, &parent), "{:?}", res);
+        }
+    }
 }
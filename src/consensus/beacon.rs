@@ -0,0 +1,260 @@
+use super::{base::ConsensusEngineBase, *};
+use async_trait::async_trait;
+
+/// Proof-of-stake consensus of EIP-3675, in effect once total difficulty of the chain reaches
+/// terminal total difficulty.
+///
+/// Blocks after the merge are sealed by the consensus layer and have zero difficulty, nonce and
+/// no ommers, their author gets no reward. Blocks before it are left to the proof-of-work engine.
+#[derive(Debug)]
+pub struct Beacon {
+    base: ConsensusEngineBase,
+    terminal_total_difficulty: U256,
+    pow: Box<dyn Consensus>,
+}
+
+impl Beacon {
+    pub fn new(
+        chain_id: ChainId,
+        eip1559_block: Option<BlockNumber>,
+        eip4844_block: Option<BlockNumber>,
+        terminal_total_difficulty: U256,
+        pow: Box<dyn Consensus>,
+    ) -> Self {
+        Self {
+            base: ConsensusEngineBase::new(chain_id, eip1559_block, eip4844_block),
+            terminal_total_difficulty,
+            pow,
+        }
+    }
+}
+
+/// Whether `header` is of a block after the merge.
+pub fn is_pos_header(header: &BlockHeader) -> bool {
+    header.difficulty == U256::ZERO
+}
+
+#[async_trait]
+impl Consensus for Beacon {
+    async fn pre_validate_block(&self, block: &Block, state: &mut dyn State) -> anyhow::Result<()> {
+        if !is_pos_header(&block.header) {
+            return self.pow.pre_validate_block(block, state).await;
+        }
+
+        if !block.ommers.is_empty() {
+            return Err(ValidationError::UnexpectedOmmers.into());
+        }
+
+        self.base.pre_validate_block(block, state).await
+    }
+
+    async fn validate_block_header(
+        &self,
+        header: &BlockHeader,
+        state: &mut dyn State,
+        with_future_timestamp_check: bool,
+    ) -> anyhow::Result<()> {
+        let parent = self
+            .base
+            .get_parent_header(state, header)
+            .await?
+            .ok_or(ValidationError::UnknownParent)?;
+        let parent_total_difficulty = state
+            .total_difficulty(parent.number, header.parent_hash)
+            .await?;
+        let merged = parent_total_difficulty.map(|td| td >= self.terminal_total_difficulty);
+
+        if !is_pos_header(header) {
+            if merged == Some(true) {
+                return Err(ValidationError::PowBlockAfterMerge.into());
+            }
+            return self
+                .pow
+                .validate_block_header(header, state, with_future_timestamp_check)
+                .await;
+        }
+
+        if merged != Some(true) {
+            return Err(ValidationError::PosBlockBeforeMerge.into());
+        }
+
+        self.base
+            .validate_block_header(header, &parent, with_future_timestamp_check)
+            .await?;
+
+        if header.nonce != H64::zero() {
+            return Err(ValidationError::InvalidSeal.into());
+        }
+
+        if header.ommers_hash != EMPTY_LIST_HASH {
+            return Err(ValidationError::UnexpectedOmmers.into());
+        }
+
+        Ok(())
+    }
+
+    async fn validate_seal(&self, header: &BlockHeader) -> anyhow::Result<()> {
+        if is_pos_header(header) {
+            // Sealed by the consensus layer.
+            return Ok(());
+        }
+
+        self.pow.validate_seal(header).await
+    }
+
+    async fn seal(&self, header: &mut BlockHeader) -> anyhow::Result<()> {
+        if is_pos_header(header) {
+            return Ok(());
+        }
+
+        self.pow.seal(header).await
+    }
+
+    async fn finalize(
+        &self,
+        header: &PartialHeader,
+        ommers: &[BlockHeader],
+        revision: Revision,
+    ) -> anyhow::Result<Vec<FinalizationChange>> {
+        if header.difficulty == U256::ZERO {
+            // Validators are rewarded on the beacon chain.
+            return Ok(vec![]);
+        }
+
+        self.pow.finalize(header, ommers, revision).await
+    }
+
+    async fn get_beneficiary(&self, header: &BlockHeader) -> anyhow::Result<Address> {
+        if is_pos_header(header) {
+            return Ok(self.base.get_beneficiary(header));
+        }
+
+        self.pow.get_beneficiary(header).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{res::chainspec::MAINNET, state::InMemoryState};
+
+    fn header(parent: &BlockHeader, difficulty: U256) -> BlockHeader {
+        BlockHeader::new(
+            PartialHeader {
+                parent_hash: parent.hash(),
+                number: parent.number + 1,
+                difficulty,
+                gas_limit: parent.gas_limit,
+                timestamp: parent.timestamp + 12,
+                ..PartialHeader::empty()
+            },
+            EMPTY_LIST_HASH,
+            EMPTY_ROOT,
+        )
+    }
+
+    #[tokio::test]
+    async fn merge_transition() {
+        let mut chain_spec = MAINNET.clone();
+        chain_spec.consensus.eip1559_block = None;
+        chain_spec.consensus.terminal_total_difficulty = Some(U256::from(100_u8));
+        let engine = engine_factory(chain_spec).unwrap();
+
+        let mut state = InMemoryState::default();
+        let genesis = BlockHeader::new(
+            PartialHeader {
+                difficulty: U256::from(60_u8),
+                gas_limit: 5_000_000,
+                ..PartialHeader::empty()
+            },
+            EMPTY_LIST_HASH,
+            EMPTY_ROOT,
+        );
+        state.insert_block(
+            Block::new(genesis.clone().into(), vec![], vec![]),
+            genesis.hash(),
+        );
+
+        // Terminal total difficulty is not reached yet.
+        let early = header(&genesis, U256::ZERO);
+        assert_eq!(
+            engine
+                .validate_block_header(&early, &mut state, false)
+                .await
+                .unwrap_err()
+                .downcast::<ValidationError>()
+                .unwrap(),
+            ValidationError::PosBlockBeforeMerge
+        );
+
+        let terminal = header(&genesis, U256::from(40_u8));
+        state.insert_block(
+            Block::new(terminal.clone().into(), vec![], vec![]),
+            terminal.hash(),
+        );
+
+        let pow = header(&terminal, U256::from(40_u8));
+        assert_eq!(
+            engine
+                .validate_block_header(&pow, &mut state, false)
+                .await
+                .unwrap_err()
+                .downcast::<ValidationError>()
+                .unwrap(),
+            ValidationError::PowBlockAfterMerge
+        );
+
+        let mut pos = header(&terminal, U256::ZERO);
+        engine
+            .validate_block_header(&pos, &mut state, false)
+            .await
+            .unwrap();
+        engine.validate_seal(&pos).await.unwrap();
+
+        pos.nonce = H64::from_low_u64_be(1);
+        assert_eq!(
+            engine
+                .validate_block_header(&pos, &mut state, false)
+                .await
+                .unwrap_err()
+                .downcast::<ValidationError>()
+                .unwrap(),
+            ValidationError::InvalidSeal
+        );
+    }
+
+    #[tokio::test]
+    async fn no_rewards_after_merge() {
+        let engine = engine_factory(MAINNET.clone()).unwrap();
+        let ommer = BlockHeader::new(
+            PartialHeader {
+                number: BlockNumber(15_537_393),
+                ..PartialHeader::empty()
+            },
+            EMPTY_LIST_HASH,
+            EMPTY_ROOT,
+        );
+        let finalize = |difficulty| {
+            let engine = &engine;
+            let ommers = vec![ommer.clone()];
+            async move {
+                engine
+                    .finalize(
+                        &PartialHeader {
+                            number: BlockNumber(15_537_394),
+                            difficulty,
+                            ..PartialHeader::empty()
+                        },
+                        &ommers,
+                        Revision::London,
+                    )
+                    .await
+                    .unwrap()
+                    .len()
+            }
+        };
+
+        assert_eq!(finalize(U256::ONE).await, 2);
+        assert_eq!(finalize(U256::ZERO).await, 0);
+    }
+}
@@ -154,6 +154,7 @@ impl<'state> Blockchain<'state> {
         let body = BlockBodyWithSenders {
             transactions: block.transactions.clone(),
             ommers: block.ommers.clone(),
+            withdrawals: block.withdrawals.clone(),
         };
 
         let block_spec = self.config.collect_block_spec(block.header.number);
@@ -206,6 +207,7 @@ impl<'state> Blockchain<'state> {
                 header: header.into(),
                 transactions: body.transactions,
                 ommers: body.ommers,
+                withdrawals: body.withdrawals,
             };
 
             let _ = self.execute_block(&block, false).await.unwrap();
@@ -256,6 +258,7 @@ impl<'state> Blockchain<'state> {
                     header,
                     transactions: body.transactions,
                     ommers: body.ommers,
+                    withdrawals: body.withdrawals,
                 },
                 hash,
             };
@@ -0,0 +1,637 @@
+use super::{base::ConsensusEngineBase, *};
+use crate::crypto::pubkey_to_address;
+use anyhow::{ensure, format_err};
+use async_trait::async_trait;
+use lru::LruCache;
+use parking_lot::Mutex;
+use secp256k1::{
+    ecdsa::{RecoverableSignature, RecoveryId},
+    Message as SecpMessage, SecretKey, SECP256K1,
+};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    time::Duration,
+};
+
+/// Bytes of extra data reserved for signer vanity.
+pub const EXTRA_VANITY: usize = 32;
+/// Bytes of extra data reserved for the signer seal.
+pub const EXTRA_SEAL: usize = 65;
+
+/// Nonce voting to add the beneficiary to the signers.
+pub const NONCE_AUTH: H64 = H64([0xff; 8]);
+/// Nonce voting to remove the beneficiary from the signers.
+pub const NONCE_DROP: H64 = H64([0; 8]);
+
+const SNAPSHOT_CACHE_SIZE: usize = 128;
+
+/// Hash signed by the block sealer: hash of the header without the seal in extra data.
+pub fn clique_seal_hash(header: &BlockHeader) -> anyhow::Result<H256> {
+    let len = header.extra_data.len();
+    if len < EXTRA_VANITY + EXTRA_SEAL {
+        return Err(ValidationError::MissingSignature.into());
+    }
+
+    let mut header = header.clone();
+    header.extra_data = header.extra_data.slice(..len - EXTRA_SEAL);
+    Ok(header.hash())
+}
+
+/// Address of the signer who sealed `header`.
+pub fn recover_signer(header: &BlockHeader) -> anyhow::Result<Address> {
+    let seal_hash = clique_seal_hash(header)?;
+    let seal = &header.extra_data[header.extra_data.len() - EXTRA_SEAL..];
+
+    let signature = RecoverableSignature::from_compact(
+        &seal[..64],
+        RecoveryId::from_i32(seal[64].into()).map_err(|_| ValidationError::InvalidSeal)?,
+    )
+    .map_err(|_| ValidationError::InvalidSeal)?;
+    let public = SECP256K1
+        .recover_ecdsa(&SecpMessage::from_slice(seal_hash.as_bytes())?, &signature)
+        .map_err(|_| ValidationError::InvalidSeal)?;
+
+    Ok(pubkey_to_address(&public))
+}
+
+/// Signers listed in the extra data of a checkpoint block.
+fn checkpoint_signers(header: &BlockHeader) -> anyhow::Result<Vec<Address>> {
+    let len = header.extra_data.len();
+    if len < EXTRA_VANITY + EXTRA_SEAL {
+        return Err(ValidationError::MissingSignature.into());
+    }
+
+    let signers = &header.extra_data[EXTRA_VANITY..len - EXTRA_SEAL];
+    if signers.len() % Address::len_bytes() != 0 {
+        return Err(ValidationError::WrongCheckpointSigners.into());
+    }
+
+    Ok(signers
+        .chunks(Address::len_bytes())
+        .map(Address::from_slice)
+        .collect())
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Vote {
+    signer: Address,
+    candidate: Address,
+    authorize: bool,
+}
+
+/// State of the signer set at a given block.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CliqueSnapshot {
+    pub number: BlockNumber,
+    pub hash: H256,
+    /// Authorized signers, sorted as required to determine in-turn signers.
+    pub signers: BTreeSet<Address>,
+    /// Recent signers, which may not sign again until enough blocks pass.
+    pub recents: BTreeMap<BlockNumber, Address>,
+    votes: Vec<Vote>,
+}
+
+impl CliqueSnapshot {
+    /// Snapshot at a checkpoint block, which lists the signers in its extra data.
+    pub fn checkpoint(header: &BlockHeader, hash: H256) -> anyhow::Result<Self> {
+        Ok(Self {
+            number: header.number,
+            hash,
+            signers: checkpoint_signers(header)?.into_iter().collect(),
+            recents: BTreeMap::new(),
+            votes: Vec::new(),
+        })
+    }
+
+    /// Whether `signer` is expected to seal block `number`.
+    pub fn is_in_turn(&self, number: BlockNumber, signer: Address) -> bool {
+        self.signers
+            .iter()
+            .position(|s| *s == signer)
+            .map(|position| number.0 % self.signers.len() as u64 == position as u64)
+            .unwrap_or(false)
+    }
+
+    /// Number of recent blocks that a signer has to wait before sealing again.
+    fn signer_limit(&self) -> u64 {
+        self.signers.len() as u64 / 2 + 1
+    }
+
+    /// Number of votes cast for `candidate` in the direction of `authorize`.
+    pub fn tally(&self, candidate: Address, authorize: bool) -> usize {
+        self.votes
+            .iter()
+            .filter(|vote| vote.candidate == candidate && vote.authorize == authorize)
+            .count()
+    }
+
+    /// Advance the snapshot by child block `header` with hash `hash`.
+    pub fn apply(&mut self, header: &BlockHeader, hash: H256, epoch: u64) -> anyhow::Result<()> {
+        let number = header.number;
+        ensure!(
+            number.0 == self.number.0 + 1,
+            "cannot apply block {} to snapshot at {}",
+            number,
+            self.number
+        );
+
+        if number.0 % epoch == 0 {
+            self.votes.clear();
+        }
+
+        // Oldest recent signer may sign again.
+        if let Some(expired) = number.0.checked_sub(self.signer_limit()) {
+            self.recents.remove(&BlockNumber(expired));
+        }
+
+        let signer = recover_signer(header)?;
+        if !self.signers.contains(&signer) {
+            return Err(ValidationError::UnauthorizedSigner { signer }.into());
+        }
+        if self.recents.values().any(|recent| *recent == signer) {
+            return Err(ValidationError::RecentlySigned { signer }.into());
+        }
+        self.recents.insert(number, signer);
+
+        let candidate = header.beneficiary;
+        let authorize = match header.nonce {
+            NONCE_AUTH => true,
+            NONCE_DROP => false,
+            _ => return Err(ValidationError::InvalidVote.into()),
+        };
+
+        // Signer replaces its previous vote on the candidate. Votes that would not change
+        // anything are not counted.
+        self.votes
+            .retain(|vote| !(vote.signer == signer && vote.candidate == candidate));
+        if self.signers.contains(&candidate) != authorize {
+            self.votes.push(Vote {
+                signer,
+                candidate,
+                authorize,
+            });
+        }
+
+        if self.tally(candidate, authorize) > self.signers.len() / 2 {
+            if authorize {
+                self.signers.insert(candidate);
+            } else {
+                self.signers.remove(&candidate);
+
+                // Signer set shrank, so one more recent signer may sign again.
+                if let Some(expired) = number.0.checked_sub(self.signer_limit()) {
+                    self.recents.remove(&BlockNumber(expired));
+                }
+                self.votes.retain(|vote| vote.signer != candidate);
+            }
+            self.votes.retain(|vote| vote.candidate != candidate);
+        }
+
+        self.number = number;
+        self.hash = hash;
+
+        Ok(())
+    }
+}
+
+/// Proof-of-authority engine of EIP-225, used by Goerli, Rinkeby and Sepolia-like networks.
+pub struct Clique {
+    base: ConsensusEngineBase,
+    period: Duration,
+    epoch: u64,
+    snapshots: Mutex<LruCache<H256, CliqueSnapshot>>,
+    signer_key: Option<SecretKey>,
+}
+
+impl Debug for Clique {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Clique")
+            .field("base", &self.base)
+            .field("period", &self.period)
+            .field("epoch", &self.epoch)
+            .field("snapshots", &self.snapshots.lock().len())
+            .field("signer", &self.signer_key.is_some())
+            .finish()
+    }
+}
+
+impl Clique {
+    pub fn new(
+        chain_id: ChainId,
+        eip1559_block: Option<BlockNumber>,
+        eip4844_block: Option<BlockNumber>,
+        period: Duration,
+        epoch: u64,
+    ) -> Self {
+        Self {
+            base: ConsensusEngineBase::new(chain_id, eip1559_block, eip4844_block)
+                .without_extra_data_limit(),
+            period,
+            epoch,
+            snapshots: Mutex::new(LruCache::new(SNAPSHOT_CACHE_SIZE)),
+            signer_key: None,
+        }
+    }
+
+    /// Seal blocks built by this node with `key`.
+    pub fn with_signer_key(mut self, key: SecretKey) -> Self {
+        self.signer_key = Some(key);
+        self
+    }
+
+    fn is_checkpoint(&self, number: BlockNumber) -> bool {
+        number.0 % self.epoch == 0
+    }
+
+    /// Snapshot after block `number` with hash `hash`, replayed from the closest cached
+    /// snapshot or checkpoint.
+    pub async fn snapshot(
+        &self,
+        state: &mut dyn State,
+        mut number: BlockNumber,
+        mut hash: H256,
+    ) -> anyhow::Result<CliqueSnapshot> {
+        let mut headers = Vec::new();
+        let mut snapshot = loop {
+            let cached = self.snapshots.lock().get(&hash).cloned();
+            if let Some(snapshot) = cached {
+                break snapshot;
+            }
+
+            let header = state
+                .read_header(number, hash)
+                .await?
+                .ok_or_else(|| format_err!("header {}/{:?} not found", number, hash))?;
+            if self.is_checkpoint(number) {
+                break CliqueSnapshot::checkpoint(&header, hash)?;
+            }
+
+            number = BlockNumber(number.0 - 1);
+            hash = header.parent_hash;
+            headers.push(header);
+        };
+
+        for header in headers.into_iter().rev() {
+            let hash = header.hash();
+            snapshot.apply(&header, hash, self.epoch)?;
+        }
+
+        self.snapshots.lock().put(snapshot.hash, snapshot.clone());
+
+        Ok(snapshot)
+    }
+}
+
+#[async_trait]
+impl Consensus for Clique {
+    async fn pre_validate_block(&self, block: &Block, state: &mut dyn State) -> anyhow::Result<()> {
+        if !block.ommers.is_empty() {
+            return Err(ValidationError::TooManyOmmers.into());
+        }
+
+        self.base.pre_validate_block(block, state).await
+    }
+
+    async fn validate_block_header(
+        &self,
+        header: &BlockHeader,
+        state: &mut dyn State,
+        with_future_timestamp_check: bool,
+    ) -> anyhow::Result<()> {
+        let parent = self
+            .base
+            .get_parent_header(state, header)
+            .await?
+            .ok_or(ValidationError::UnknownParent)?;
+
+        self.base
+            .validate_block_header(header, &parent, with_future_timestamp_check)
+            .await?;
+
+        if header.timestamp < parent.timestamp + self.period.as_secs() {
+            return Err(ValidationError::InvalidTimestamp {
+                parent: parent.timestamp,
+                current: header.timestamp,
+            }
+            .into());
+        }
+
+        let extra_len = header.extra_data.len();
+        if extra_len < EXTRA_VANITY + EXTRA_SEAL {
+            return Err(ValidationError::MissingSignature.into());
+        }
+
+        let checkpoint = self.is_checkpoint(header.number);
+        if checkpoint {
+            if !header.beneficiary.is_zero() || header.nonce != NONCE_DROP {
+                return Err(ValidationError::InvalidVote.into());
+            }
+        } else {
+            if header.nonce != NONCE_AUTH && header.nonce != NONCE_DROP {
+                return Err(ValidationError::InvalidVote.into());
+            }
+            if extra_len != EXTRA_VANITY + EXTRA_SEAL {
+                return Err(ValidationError::UnexpectedSigners.into());
+            }
+        }
+
+        if !header.mix_hash.is_zero() {
+            return Err(ValidationError::InvalidSeal.into());
+        }
+
+        if header.ommers_hash != EMPTY_LIST_HASH {
+            return Err(ValidationError::WrongOmmersHash {
+                expected: EMPTY_LIST_HASH,
+                got: header.ommers_hash,
+            }
+            .into());
+        }
+
+        let mut snapshot = self
+            .snapshot(state, parent.number, header.parent_hash)
+            .await?;
+
+        if checkpoint
+            && !checkpoint_signers(header)?
+                .into_iter()
+                .eq(snapshot.signers.iter().copied())
+        {
+            return Err(ValidationError::WrongCheckpointSigners.into());
+        }
+
+        let signer = recover_signer(header)?;
+        let expected_difficulty = if snapshot.is_in_turn(header.number, signer) {
+            BlockScore::InTurn
+        } else {
+            BlockScore::NoTurn
+        };
+        if header.difficulty != U256::from(expected_difficulty as u8) {
+            return Err(ValidationError::WrongDifficulty.into());
+        }
+
+        let hash = header.hash();
+        snapshot.apply(header, hash, self.epoch)?;
+        self.snapshots.lock().put(hash, snapshot);
+
+        Ok(())
+    }
+
+    async fn validate_seal(&self, header: &BlockHeader) -> anyhow::Result<()> {
+        recover_signer(header)?;
+
+        Ok(())
+    }
+
+    /// Sign the header with the configured signer key. Extra data must already have room for
+    /// the seal.
+    async fn seal(&self, header: &mut BlockHeader) -> anyhow::Result<()> {
+        let key = self
+            .signer_key
+            .as_ref()
+            .ok_or_else(|| format_err!("no Clique signer key configured"))?;
+
+        let seal_hash = clique_seal_hash(header)?;
+        let (recovery_id, signature) = SECP256K1
+            .sign_ecdsa_recoverable(&SecpMessage::from_slice(seal_hash.as_bytes())?, key)
+            .serialize_compact();
+
+        let mut extra_data = header.extra_data.to_vec();
+        let len = extra_data.len();
+        extra_data[len - EXTRA_SEAL..len - 1].copy_from_slice(&signature);
+        extra_data[len - 1] = recovery_id.to_i32() as u8;
+        header.extra_data = extra_data.into();
+
+        Ok(())
+    }
+
+    /// Clique has no block rewards, signers are paid by transaction fees only.
+    async fn finalize(
+        &self,
+        _: &PartialHeader,
+        _: &[BlockHeader],
+        _: Revision,
+    ) -> anyhow::Result<Vec<FinalizationChange>> {
+        Ok(vec![])
+    }
+
+    /// Beneficiary field of Clique blocks holds the vote candidate, the author is the signer.
+    async fn get_beneficiary(&self, header: &BlockHeader) -> anyhow::Result<Address> {
+        recover_signer(header)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::{generate_key, to_pubkey};
+
+    struct Signer {
+        key: SecretKey,
+        address: Address,
+    }
+
+    fn new_signers(n: usize) -> Vec<Signer> {
+        let mut signers = (0..n)
+            .map(|_| {
+                let key = generate_key();
+                Signer {
+                    address: pubkey_to_address(&to_pubkey(&key)),
+                    key,
+                }
+            })
+            .collect::<Vec<_>>();
+        signers.sort_by_key(|signer| signer.address);
+        signers
+    }
+
+    fn engine(epoch: u64) -> Clique {
+        Clique::new(ChainId(5), None, None, Duration::from_secs(15), epoch)
+    }
+
+    async fn sealed(
+        signer: &Signer,
+        number: u64,
+        parent_hash: H256,
+        candidate: Address,
+        nonce: H64,
+        in_turn: bool,
+    ) -> BlockHeader {
+        let mut header = BlockHeader::new(
+            PartialHeader {
+                parent_hash,
+                beneficiary: candidate,
+                number: BlockNumber(number),
+                difficulty: U256::from(if in_turn { 2_u8 } else { 1_u8 }),
+                gas_limit: 8_000_000,
+                timestamp: number * 15,
+                extra_data: vec![0; EXTRA_VANITY + EXTRA_SEAL].into(),
+                nonce,
+                ..PartialHeader::empty()
+            },
+            EMPTY_LIST_HASH,
+            EMPTY_ROOT,
+        );
+        engine(30_000)
+            .with_signer_key(signer.key)
+            .seal(&mut header)
+            .await
+            .unwrap();
+        header
+    }
+
+    fn genesis(signers: &[Signer]) -> BlockHeader {
+        let mut extra_data = vec![0; EXTRA_VANITY];
+        for signer in signers {
+            extra_data.extend_from_slice(signer.address.as_bytes());
+        }
+        extra_data.extend_from_slice(&[0; EXTRA_SEAL]);
+
+        BlockHeader::new(
+            PartialHeader {
+                gas_limit: 8_000_000,
+                extra_data: extra_data.into(),
+                ..PartialHeader::empty()
+            },
+            EMPTY_LIST_HASH,
+            EMPTY_ROOT,
+        )
+    }
+
+    #[tokio::test]
+    async fn seal_roundtrip() {
+        let signers = new_signers(1);
+        let header = sealed(
+            &signers[0],
+            1,
+            H256::zero(),
+            Address::zero(),
+            NONCE_DROP,
+            true,
+        )
+        .await;
+
+        let engine = engine(30_000);
+        engine.validate_seal(&header).await.unwrap();
+        assert_eq!(
+            engine.get_beneficiary(&header).await.unwrap(),
+            signers[0].address
+        );
+
+        let mut tampered = header.clone();
+        tampered.gas_limit += 1;
+        assert_ne!(recover_signer(&tampered).unwrap(), signers[0].address);
+
+        let mut unsealed = header;
+        unsealed.extra_data = unsealed.extra_data.slice(..EXTRA_VANITY);
+        assert!(engine.validate_seal(&unsealed).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn snapshot_voting() {
+        let signers = new_signers(3);
+        let newcomer = new_signers(1).pop().unwrap();
+        let epoch = 30_000;
+
+        let genesis = genesis(&signers);
+        let mut snapshot = CliqueSnapshot::checkpoint(&genesis, genesis.hash()).unwrap();
+        assert_eq!(
+            snapshot.signers,
+            signers.iter().map(|signer| signer.address).collect()
+        );
+
+        // Block 1 is in turn for the second signer of three.
+        assert!(snapshot.is_in_turn(BlockNumber(1), signers[1].address));
+        assert!(!snapshot.is_in_turn(BlockNumber(1), signers[0].address));
+
+        let mut parent_hash = genesis.hash();
+
+        // First vote for the newcomer is not a majority of three.
+        let header = sealed(
+            &signers[1],
+            1,
+            parent_hash,
+            newcomer.address,
+            NONCE_AUTH,
+            true,
+        )
+        .await;
+        parent_hash = header.hash();
+        snapshot.apply(&header, parent_hash, epoch).unwrap();
+        assert_eq!(snapshot.tally(newcomer.address, true), 1);
+        assert!(!snapshot.signers.contains(&newcomer.address));
+
+        // Same signer may not seal twice in a row.
+        let header = sealed(
+            &signers[1],
+            2,
+            parent_hash,
+            newcomer.address,
+            NONCE_AUTH,
+            false,
+        )
+        .await;
+        assert_eq!(
+            snapshot
+                .clone()
+                .apply(&header, header.hash(), epoch)
+                .unwrap_err()
+                .downcast::<ValidationError>()
+                .unwrap(),
+            ValidationError::RecentlySigned {
+                signer: signers[1].address
+            }
+        );
+
+        // Unknown signers may not seal at all.
+        let header = sealed(
+            &newcomer,
+            2,
+            parent_hash,
+            Address::zero(),
+            NONCE_DROP,
+            false,
+        )
+        .await;
+        assert_eq!(
+            snapshot
+                .clone()
+                .apply(&header, header.hash(), epoch)
+                .unwrap_err()
+                .downcast::<ValidationError>()
+                .unwrap(),
+            ValidationError::UnauthorizedSigner {
+                signer: newcomer.address
+            }
+        );
+
+        // Second vote makes a majority.
+        let header = sealed(
+            &signers[2],
+            2,
+            parent_hash,
+            newcomer.address,
+            NONCE_AUTH,
+            true,
+        )
+        .await;
+        parent_hash = header.hash();
+        snapshot.apply(&header, parent_hash, epoch).unwrap();
+        assert!(snapshot.signers.contains(&newcomer.address));
+        assert_eq!(snapshot.tally(newcomer.address, true), 0);
+
+        // Newcomer takes part in the rotation and may vote itself.
+        let header = sealed(
+            &newcomer,
+            3,
+            parent_hash,
+            signers[0].address,
+            NONCE_DROP,
+            false,
+        )
+        .await;
+        parent_hash = header.hash();
+        snapshot.apply(&header, parent_hash, epoch).unwrap();
+        assert_eq!(snapshot.tally(signers[0].address, false), 1);
+        assert_eq!(snapshot.signers.len(), 4);
+    }
+}
@@ -2,6 +2,7 @@ use self::difficulty::BlockDifficultyBombData;
 use super::{base::ConsensusEngineBase, *};
 use crate::{chain::protocol_param::param, h256_to_u256};
 use ::ethash::LightDAG;
+use anyhow::ensure;
 use async_trait::async_trait;
 use std::collections::BTreeMap;
 
@@ -23,6 +24,7 @@ impl Ethash {
     pub fn new(
         chain_id: ChainId,
         eip1559_block: Option<BlockNumber>,
+        eip4844_block: Option<BlockNumber>,
         duration_limit: u64,
         block_reward: BTreeMap<BlockNumber, U256>,
         homestead_formula: Option<BlockNumber>,
@@ -31,7 +33,7 @@ impl Ethash {
         skip_pow_verification: bool,
     ) -> Self {
         Self {
-            base: ConsensusEngineBase::new(chain_id, eip1559_block),
+            base: ConsensusEngineBase::new(chain_id, eip1559_block, eip4844_block),
             duration_limit,
             block_reward,
             homestead_formula,
@@ -40,6 +42,22 @@ impl Ethash {
             skip_pow_verification,
         }
     }
+
+    /// Reward of the miner of block `block_number` without ommers, from the chain spec schedule,
+    /// or the protocol default for `revision` before the schedule starts.
+    fn block_reward(&self, block_number: BlockNumber, revision: Revision) -> U256 {
+        if let Some((_, &block_reward)) = self.block_reward.range(..=block_number).next_back() {
+            return block_reward;
+        }
+
+        U256::from(if revision >= Revision::Constantinople {
+            param::BLOCK_REWARD_CONSTANTINOPLE
+        } else if revision >= Revision::Byzantium {
+            param::BLOCK_REWARD_BYZANTIUM
+        } else {
+            param::BLOCK_REWARD_FRONTIER
+        })
+    }
 }
 
 #[async_trait]
@@ -101,6 +119,31 @@ impl Consensus for Ethash {
         }
         Ok(())
     }
+    /// Search nonces from zero, only practical for development chains with low difficulty.
+    async fn seal(&self, header: &mut BlockHeader) -> anyhow::Result<()> {
+        if self.skip_pow_verification {
+            return Ok(());
+        }
+        ensure!(
+            header.difficulty != 0,
+            "cannot seal block without difficulty"
+        );
+
+        let light_dag = LightDAG::new(header.number.0.into());
+        let boundary = ::ethash::cross_boundary(header.difficulty);
+        let truncated_hash = header.truncated_hash();
+        for nonce in 0..=u64::MAX {
+            let nonce = H64::from_low_u64_be(nonce);
+            let (mix_hash, final_hash) = light_dag.hashimoto(truncated_hash, nonce);
+            if h256_to_u256(final_hash) <= boundary {
+                header.nonce = nonce;
+                header.mix_hash = mix_hash;
+                return Ok(());
+            }
+        }
+
+        bail!("no nonce satisfies difficulty {}", header.difficulty)
+    }
     async fn finalize(
         &self,
         header: &PartialHeader,
@@ -108,17 +151,9 @@ impl Consensus for Ethash {
         revision: Revision,
     ) -> anyhow::Result<Vec<FinalizationChange>> {
         let mut changes = Vec::with_capacity(1 + ommers.len());
-        let block_reward = {
-            if revision >= Revision::Constantinople {
-                param::BLOCK_REWARD_CONSTANTINOPLE
-            } else if revision >= Revision::Byzantium {
-                param::BLOCK_REWARD_BYZANTIUM
-            } else {
-                param::BLOCK_REWARD_FRONTIER
-            }
-        };
-
         let block_number = header.number;
+        let block_reward = self.block_reward(block_number, revision);
+
         let mut miner_reward = block_reward;
         for ommer in ommers {
             let ommer_reward =
@@ -126,13 +161,15 @@ impl Consensus for Ethash {
             changes.push(FinalizationChange::Reward {
                 address: ommer.beneficiary,
                 amount: ommer_reward,
+                kind: RewardKind::Ommer,
             });
             miner_reward += block_reward / 32;
         }
 
         changes.push(FinalizationChange::Reward {
             address: header.beneficiary,
-            amount: miner_reward.into(),
+            amount: miner_reward,
+            kind: RewardKind::Block,
         });
 
         Ok(changes)
@@ -142,3 +179,95 @@ impl Consensus for Ethash {
         Ok(header.beneficiary)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::res::chainspec::MAINNET;
+
+    #[tokio::test]
+    async fn sealed_header_passes_validation() {
+        let mut header = BlockHeader::new(
+            PartialHeader {
+                number: BlockNumber(1),
+                difficulty: U256::from(2_u8),
+                ..PartialHeader::empty()
+            },
+            EMPTY_LIST_HASH,
+            EMPTY_ROOT,
+        );
+        let engine = engine_factory(MAINNET.clone()).unwrap();
+        engine.seal(&mut header).await.unwrap();
+        engine.validate_seal(&header).await.unwrap();
+
+        header.mix_hash = H256::zero();
+        assert!(engine.validate_seal(&header).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn mainnet_rewards() {
+        let engine = engine_factory(MAINNET.clone()).unwrap();
+        let miner = Address::from_low_u64_be(0x1);
+        let uncle_miner = Address::from_low_u64_be(0x2);
+
+        let rewards = |number: u64, ommers: Vec<BlockHeader>| {
+            let engine = &engine;
+            async move {
+                engine
+                    .finalize(
+                        &PartialHeader {
+                            number: BlockNumber(number),
+                            beneficiary: miner,
+                            difficulty: U256::ONE,
+                            ..PartialHeader::empty()
+                        },
+                        &ommers,
+                        MAINNET.collect_block_spec(BlockNumber(number)).revision,
+                    )
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .map(
+                        |FinalizationChange::Reward {
+                             address, amount, ..
+                         }| (address, amount),
+                    )
+                    .collect::<Vec<_>>()
+            }
+        };
+        let ommer = |number: u64| {
+            BlockHeader::new(
+                PartialHeader {
+                    number: BlockNumber(number),
+                    beneficiary: uncle_miner,
+                    ..PartialHeader::empty()
+                },
+                EMPTY_LIST_HASH,
+                EMPTY_ROOT,
+            )
+        };
+
+        // Frontier, Byzantium and Constantinople schedules.
+        assert_eq!(
+            rewards(1, vec![]).await,
+            vec![(miner, U256::from(5 * ETHER))]
+        );
+        assert_eq!(
+            rewards(4_370_000, vec![]).await,
+            vec![(miner, U256::from(3 * ETHER))]
+        );
+        assert_eq!(
+            rewards(7_280_000, vec![]).await,
+            vec![(miner, U256::from(2 * ETHER))]
+        );
+
+        // Ommer one block behind gets 7/8 of the reward, miner gets 1/32 on top of it.
+        assert_eq!(
+            rewards(4_370_000, vec![ommer(4_369_999)]).await,
+            vec![
+                (uncle_miner, U256::from(3 * ETHER * 7 / 8)),
+                (miner, U256::from(3 * ETHER + 3 * ETHER / 32)),
+            ]
+        );
+    }
+}
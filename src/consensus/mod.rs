@@ -1,17 +1,36 @@
 mod base;
+mod beacon;
 mod blockchain;
+mod clique;
 mod ethash;
 
-pub use self::{blockchain::*, ethash::*};
-use crate::{models::*, State};
+pub use self::{
+    base::{expected_base_fee_per_gas, expected_excess_blob_gas, verify_body_roots},
+    beacon::*,
+    blockchain::*,
+    clique::*,
+    ethash::*,
+};
+use crate::{chain::protocol_param::param, models::*, State};
 use anyhow::bail;
 use async_trait::async_trait;
 use evmodin::Revision;
 use std::fmt::{Debug, Display};
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RewardKind {
+    /// Reward of the block author, including reward for ommers.
+    Block,
+    Ommer,
+}
+
 #[derive(Debug)]
 pub enum FinalizationChange {
-    Reward { address: Address, amount: U256 },
+    Reward {
+        address: Address,
+        amount: U256,
+        kind: RewardKind,
+    },
 }
 
 #[async_trait]
@@ -35,6 +54,9 @@ pub trait Consensus: Debug + Send + Sync + 'static {
     /// Validates the seal of the header
     async fn validate_seal(&self, header: &BlockHeader) -> anyhow::Result<()>;
 
+    /// Fills in the seal of a header built by this node, so that it passes [`Self::validate_seal`].
+    async fn seal(&self, header: &mut BlockHeader) -> anyhow::Result<()>;
+
     /// Finalizes block execution by applying changes in the state of accounts or of the consensus itself
     ///
     /// NOTE: For Ethash See [YP] Section 11.3 "Reward Application".
@@ -78,6 +100,10 @@ pub enum ValidationError {
         expected: Bloom,
         got: Bloom,
     }, // wrong Hb
+    WrongWithdrawalsRoot {
+        expected: Option<H256>,
+        got: Option<H256>,
+    }, // see EIP-4895
 
     // See [YP] Section 4.3.4 "Block Header Validity", Eq (50)
     UnknownParent,   // P(H) = ∅ ∨ Hi ≠ P(H)Hi + 1
@@ -97,8 +123,33 @@ pub enum ValidationError {
         expected: Option<U256>,
         got: Option<U256>,
     }, // see EIP-1559
+    WrongExcessBlobGas {
+        expected: Option<u64>,
+        got: Option<u64>,
+    }, // see EIP-4844
+    WrongBlobGasUsed {
+        expected: Option<u64>,
+        got: Option<u64>,
+    }, // see EIP-4844
     InvalidSeal,     // Nonce or mix_hash
 
+    // See EIP-225 "Clique proof-of-authority consensus protocol"
+    MissingSignature,       // ‖Hx‖ < 32 + 65
+    UnexpectedSigners,      // signer list outside of a checkpoint block
+    WrongCheckpointSigners, // checkpoint signer list differs from the snapshot
+    InvalidVote,            // Hn ∉ {0x00..00, 0xff..ff} or a vote in a checkpoint block
+    UnauthorizedSigner {
+        signer: Address,
+    }, // signer is not in the snapshot
+    RecentlySigned {
+        signer: Address,
+    }, // signer sealed one of the last ⌊N/2⌋ blocks
+
+    // See EIP-3675 "Upgrade consensus to Proof-of-Stake"
+    PowBlockAfterMerge,  // Hd ≠ 0 with terminal total difficulty reached by P(H)
+    PosBlockBeforeMerge, // Hd = 0 with terminal total difficulty not reached by P(H)
+    UnexpectedOmmers,    // ‖BU‖ > 0 after the merge
+
     // See [YP] Section 6.2 "Execution", Eq (58)
     MissingSender, // S(T) = ∅
     SenderNoEOA {
@@ -116,11 +167,14 @@ pub enum ValidationError {
         required: U512,
     }, // v0 > σ[S(T)]b
     BlockGasLimitExceeded {
+        /// Index of transaction in block.
+        txn: usize,
         available: u64,
         required: u64,
     }, // Tg > BHl - l(BR)u
     MaxFeeLessThanBase, // max_fee_per_gas < base_fee_per_gas (EIP-1559)
     MaxPriorityFeeGreaterThanMax, // max_priority_fee_per_gas > max_fee_per_gas (EIP-1559)
+    MaxFeePerBlobGasLessThanBlobBase, // max_fee_per_blob_gas < blob_base_fee (EIP-4844)
 
     // See [YP] Section 11.1 "Ommer Validation", Eq (157)
     TooManyOmmers,      // ‖BU‖ > 2
@@ -140,6 +194,17 @@ pub enum ValidationError {
     WrongChainId, // EIP-155
 
     UnsupportedTransactionType, // EIP-2718
+
+    MissingWithdrawals,    // EIP-4895
+    UnexpectedWithdrawals, // withdrawals before Shanghai
+
+    MissingBlobGasUsed,     // EIP-4844
+    UnexpectedBlobGasUsed,  // blob gas used before EIP-4844
+    NoBlobs,                // EIP-4844: blob transaction without blob versioned hashes
+    WrongBlobVersionedHash, // EIP-4844: unknown version of blob hash
+    BlobGasLimitExceeded,   // EIP-4844: blob gas used > MAX_BLOB_GAS_PER_BLOCK
+    WrongBlobSidecar,       // EIP-4844: sidecar does not match blob versioned hashes
+    InvalidBlobProof,       // EIP-4844: KZG proof does not verify
 }
 
 impl Display for ValidationError {
@@ -172,11 +237,74 @@ pub fn pre_validate_transaction(
         return Err(ValidationError::MaxPriorityFeeGreaterThanMax);
     }
 
+    // https://eips.ethereum.org/EIPS/eip-4844
+    if let Message::EIP4844 {
+        blob_versioned_hashes,
+        ..
+    } = txn
+    {
+        if blob_versioned_hashes.is_empty() {
+            return Err(ValidationError::NoBlobs);
+        }
+
+        if txn.blob_gas() > param::MAX_BLOB_GAS_PER_BLOCK {
+            return Err(ValidationError::BlobGasLimitExceeded);
+        }
+
+        if blob_versioned_hashes
+            .iter()
+            .any(|hash| hash.0[0] != param::VERSIONED_HASH_VERSION_KZG)
+        {
+            return Err(ValidationError::WrongBlobVersionedHash);
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks that `sidecar` carries the blobs of blob transaction `txn`, as it must for the
+/// transaction to be accepted from peers and into payloads.
+#[cfg(feature = "node")]
+pub fn validate_blob_sidecar(
+    txn: &Message,
+    sidecar: &BlobSidecar,
+    kzg_settings: &crate::crypto::kzg::KzgSettings,
+) -> anyhow::Result<()> {
+    let hashes = txn.blob_versioned_hashes();
+    if sidecar.blobs.len() != hashes.len()
+        || sidecar.proofs.len() != hashes.len()
+        || sidecar.versioned_hashes() != hashes
+    {
+        return Err(ValidationError::WrongBlobSidecar.into());
+    }
+
+    if sidecar
+        .blobs
+        .iter()
+        .any(|blob| blob.len() != BYTES_PER_BLOB)
+        || sidecar
+            .commitments
+            .iter()
+            .chain(&sidecar.proofs)
+            .any(|point| point.len() != BYTES_PER_KZG_POINT)
+    {
+        return Err(ValidationError::WrongBlobSidecar.into());
+    }
+
+    if !crate::crypto::kzg::verify_blob_proofs(
+        &sidecar.blobs,
+        &sidecar.commitments,
+        &sidecar.proofs,
+        kzg_settings,
+    )? {
+        return Err(ValidationError::InvalidBlobProof.into());
+    }
+
     Ok(())
 }
 
 pub fn engine_factory(chain_config: ChainSpec) -> anyhow::Result<Box<dyn Consensus>> {
-    Ok(match chain_config.consensus.seal_verification {
+    let engine: Box<dyn Consensus> = match chain_config.consensus.seal_verification {
         SealVerificationParams::Ethash {
             duration_limit,
             block_reward,
@@ -187,6 +315,7 @@ pub fn engine_factory(chain_config: ChainSpec) -> anyhow::Result<Box<dyn Consens
         } => Box::new(Ethash::new(
             chain_config.params.chain_id,
             chain_config.consensus.eip1559_block,
+            chain_config.consensus.eip4844_block,
             duration_limit,
             block_reward,
             homestead_formula,
@@ -194,6 +323,23 @@ pub fn engine_factory(chain_config: ChainSpec) -> anyhow::Result<Box<dyn Consens
             difficulty_bomb,
             skip_pow_verification,
         )),
-        _ => bail!("unsupported consensus engine"),
+        SealVerificationParams::Clique { period, epoch } => Box::new(Clique::new(
+            chain_config.params.chain_id,
+            chain_config.consensus.eip1559_block,
+            chain_config.consensus.eip4844_block,
+            period,
+            epoch,
+        )),
+    };
+
+    Ok(match chain_config.consensus.terminal_total_difficulty {
+        Some(terminal_total_difficulty) => Box::new(Beacon::new(
+            chain_config.params.chain_id,
+            chain_config.consensus.eip1559_block,
+            chain_config.consensus.eip4844_block,
+            terminal_total_difficulty,
+            engine,
+        )),
+        None => engine,
     })
 }
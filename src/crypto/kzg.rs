@@ -0,0 +1,43 @@
+//! KZG commitments of EIP-4844 blobs.
+
+use anyhow::format_err;
+use bytes::Bytes;
+use c_kzg::{Blob, Bytes48, KzgProof};
+use std::path::Path;
+
+pub use c_kzg::KzgSettings;
+
+/// Loads the trusted setup of the KZG ceremony from a file in the text format.
+pub fn load_trusted_setup(path: &Path) -> anyhow::Result<KzgSettings> {
+    KzgSettings::load_trusted_setup_file(path)
+        .map_err(|e| format_err!("failed to load trusted setup {}: {:?}", path.display(), e))
+}
+
+/// Checks that each proof proves its blob to be committed to by its commitment.
+///
+/// Slices must be of equal length, with items of the sizes required by EIP-4844.
+pub fn verify_blob_proofs(
+    blobs: &[Bytes],
+    commitments: &[Bytes],
+    proofs: &[Bytes],
+    settings: &KzgSettings,
+) -> anyhow::Result<bool> {
+    let blobs = blobs
+        .iter()
+        .map(|blob| Blob::from_bytes(blob))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format_err!("invalid blob: {:?}", e))?;
+    let commitments = commitments
+        .iter()
+        .map(|commitment| Bytes48::from_bytes(commitment))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format_err!("invalid commitment: {:?}", e))?;
+    let proofs = proofs
+        .iter()
+        .map(|proof| Bytes48::from_bytes(proof))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format_err!("invalid proof: {:?}", e))?;
+
+    KzgProof::verify_blob_kzg_proof_batch(&blobs, &commitments, &proofs, settings)
+        .map_err(|e| format_err!("failed to verify blob proofs: {:?}", e))
+}
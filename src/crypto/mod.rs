@@ -7,6 +7,11 @@ use secp256k1::{PublicKey, SECP256K1};
 use sha3::{Digest, Keccak256};
 
 pub mod blake2;
+mod keccak;
+#[cfg(feature = "node")]
+pub mod kzg;
+
+pub use self::keccak::keccak256_batch;
 
 /// Concrete `Hasher` impl for the Keccak-256 hash
 #[derive(Default, Debug, Clone, PartialEq)]
@@ -0,0 +1,403 @@
+//! Differential testing of sync against a reference node.
+//!
+//! Every executed block is compared with the same block of a trusted node (geth, Erigon) over
+//! JSON-RPC: block hash, state root, receipts root, balances of selected accounts and call traces.
+//! Comparison stops at the first divergence, which is the block worth investigating.
+
+use crate::{
+    accessors::{chain, state},
+    analytics,
+    crypto::root_hash,
+    execution::call_tracer::CallFrame,
+    hexbytes,
+    kv::traits::*,
+    models::*,
+};
+use anyhow::format_err;
+use bytes::Bytes;
+use jsonrpsee::{
+    core::client::ClientT,
+    http_client::{HttpClient, HttpClientBuilder},
+    rpc_params,
+};
+use serde::Deserialize;
+use std::fmt;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ReferenceBlock {
+    hash: H256,
+    state_root: H256,
+    receipts_root: H256,
+}
+
+/// Call frame as returned by geth's `callTracer`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReferenceFrame {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub from: Address,
+    #[serde(default)]
+    pub to: Option<Address>,
+    pub gas_used: U64,
+    #[serde(default, with = "hexbytes")]
+    pub output: Bytes,
+    #[serde(default)]
+    pub error: Option<String>,
+    #[serde(default)]
+    pub calls: Vec<ReferenceFrame>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ReferenceTrace {
+    result: ReferenceFrame,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Divergence {
+    BlockHash {
+        local: H256,
+        reference: H256,
+    },
+    StateRoot {
+        local: H256,
+        reference: H256,
+    },
+    ReceiptsRoot {
+        local: H256,
+        reference: H256,
+    },
+    Balance {
+        address: Address,
+        local: U256,
+        reference: U256,
+    },
+    Trace {
+        tx_index: usize,
+        /// Position of the frame in the call tree, e.g. `0.2` is the third call of the root.
+        path: String,
+        field: &'static str,
+        local: String,
+        reference: String,
+    },
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BlockHash { local, reference } => {
+                write!(f, "block hash {:?}, reference {:?}", local, reference)
+            }
+            Self::StateRoot { local, reference } => {
+                write!(f, "state root {:?}, reference {:?}", local, reference)
+            }
+            Self::ReceiptsRoot { local, reference } => {
+                write!(f, "receipts root {:?}, reference {:?}", local, reference)
+            }
+            Self::Balance {
+                address,
+                local,
+                reference,
+            } => write!(
+                f,
+                "balance of {:?} is {}, reference {}",
+                address, local, reference
+            ),
+            Self::Trace {
+                tx_index,
+                path,
+                field,
+                local,
+                reference,
+            } => write!(
+                f,
+                "transaction {} frame {}: {} is {}, reference {}",
+                tx_index, path, field, local, reference
+            ),
+        }
+    }
+}
+
+/// Compare call tree of a transaction executed locally with the one traced by the reference node.
+///
+/// Error messages differ between clients, so only whether a frame failed is compared.
+pub fn compare_traces(
+    tx_index: usize,
+    local: &CallFrame,
+    reference: &ReferenceFrame,
+) -> Option<Divergence> {
+    compare_frames(tx_index, "0".to_string(), local, reference)
+}
+
+fn compare_frames(
+    tx_index: usize,
+    path: String,
+    local: &CallFrame,
+    reference: &ReferenceFrame,
+) -> Option<Divergence> {
+    let diverged = |field, local: String, reference: String| {
+        Some(Divergence::Trace {
+            tx_index,
+            path: path.clone(),
+            field,
+            local,
+            reference,
+        })
+    };
+
+    if !local.kind.eq_ignore_ascii_case(&reference.kind) {
+        return diverged("type", local.kind.to_string(), reference.kind.clone());
+    }
+    if local.from != reference.from {
+        return diverged(
+            "from",
+            format!("{:?}", local.from),
+            format!("{:?}", reference.from),
+        );
+    }
+    if let Some(to) = reference.to {
+        if local.to != to {
+            return diverged("to", format!("{:?}", local.to), format!("{:?}", to));
+        }
+    }
+    if local.gas_used != reference.gas_used {
+        return diverged(
+            "gas used",
+            local.gas_used.to_string(),
+            reference.gas_used.to_string(),
+        );
+    }
+    if local.error.is_some() != reference.error.is_some() {
+        return diverged(
+            "error",
+            format!("{:?}", local.error),
+            format!("{:?}", reference.error),
+        );
+    }
+    if local.output != reference.output {
+        return diverged(
+            "output",
+            hex::encode(&local.output),
+            hex::encode(&reference.output),
+        );
+    }
+    if local.calls.len() != reference.calls.len() {
+        return diverged(
+            "number of calls",
+            local.calls.len().to_string(),
+            reference.calls.len().to_string(),
+        );
+    }
+
+    local
+        .calls
+        .iter()
+        .zip(&reference.calls)
+        .enumerate()
+        .find_map(|(i, (local, reference))| {
+            compare_frames(tx_index, format!("{}.{}", path, i), local, reference)
+        })
+}
+
+/// Compares locally executed blocks with the ones of a reference node.
+#[derive(Debug)]
+pub struct DiffSync {
+    client: HttpClient,
+    /// Accounts whose balances are compared after every block.
+    pub addresses: Vec<Address>,
+    /// Compare call traces, requires `debug_traceBlockByNumber` on the reference node.
+    pub traces: bool,
+}
+
+impl DiffSync {
+    pub fn new(
+        reference_node: &str,
+        addresses: Vec<Address>,
+        traces: bool,
+    ) -> anyhow::Result<Self> {
+        Ok(Self {
+            client: HttpClientBuilder::default().build(reference_node)?,
+            addresses,
+            traces,
+        })
+    }
+
+    /// Compare block `number`, which must be executed locally, returning the first divergence.
+    pub async fn compare_block<'db, Tx: Transaction<'db>>(
+        &self,
+        tx: &Tx,
+        chain_spec: &ChainSpec,
+        number: BlockNumber,
+    ) -> anyhow::Result<Option<Divergence>> {
+        let block_param = format!("0x{:x}", number.0);
+        let reference = self
+            .client
+            .request::<Option<ReferenceBlock>>(
+                "eth_getBlockByNumber",
+                rpc_params![block_param.clone(), false],
+            )
+            .await?
+            .ok_or_else(|| format_err!("reference node does not have block {}", number))?;
+
+        let hash = chain::canonical_hash::read(tx, number)
+            .await?
+            .ok_or_else(|| format_err!("no canonical block {}", number))?;
+        let header = chain::header::read(tx, hash, number)
+            .await?
+            .ok_or_else(|| format_err!("header {}/{:?} not found", number, hash))?;
+        // Roots are checked before the hash, as they tell more about what went wrong.
+        if header.state_root != reference.state_root {
+            return Ok(Some(Divergence::StateRoot {
+                local: header.state_root,
+                reference: reference.state_root,
+            }));
+        }
+        if header.receipts_root != reference.receipts_root {
+            return Ok(Some(Divergence::ReceiptsRoot {
+                local: header.receipts_root,
+                reference: reference.receipts_root,
+            }));
+        }
+        if hash != reference.hash {
+            return Ok(Some(Divergence::BlockHash {
+                local: hash,
+                reference: reference.hash,
+            }));
+        }
+
+        for &address in &self.addresses {
+            let local = state::account::read(tx, address, Some(number))
+                .await?
+                .map(|account| account.balance)
+                .unwrap_or(U256::ZERO);
+            let reference = self
+                .client
+                .request::<U256>("eth_getBalance", rpc_params![address, block_param.clone()])
+                .await?;
+            if local != reference {
+                return Ok(Some(Divergence::Balance {
+                    address,
+                    local,
+                    reference,
+                }));
+            }
+        }
+
+        // Genesis has neither receipts nor traces.
+        if number.0 == 0 {
+            return Ok(None);
+        }
+
+        let executed = analytics::execute_block(tx, chain_spec, number).await?;
+
+        // Header roots only say what the node accepted, receipts are checked against re-execution.
+        // Before Byzantium receipts carry intermediate state roots, which we do not compute.
+        if chain_spec.collect_block_spec(number).revision >= Revision::Byzantium {
+            let receipts_root = root_hash(&executed.receipts);
+            if receipts_root != reference.receipts_root {
+                return Ok(Some(Divergence::ReceiptsRoot {
+                    local: receipts_root,
+                    reference: reference.receipts_root,
+                }));
+            }
+        }
+
+        if self.traces && !executed.traces.is_empty() {
+            let reference = self
+                .client
+                .request::<Vec<ReferenceTrace>>(
+                    "debug_traceBlockByNumber",
+                    rpc_params![block_param, serde_json::json!({ "tracer": "callTracer" })],
+                )
+                .await?;
+            if reference.len() != executed.traces.len() {
+                return Err(format_err!(
+                    "reference node traced {} transactions of {} in block {}",
+                    reference.len(),
+                    executed.traces.len(),
+                    number
+                ));
+            }
+            for (tx_index, (local, reference)) in executed.traces.iter().zip(&reference).enumerate()
+            {
+                if let Some(divergence) = compare_traces(tx_index, local, &reference.result) {
+                    return Ok(Some(divergence));
+                }
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hex_literal::hex;
+
+    fn local_frame(gas_used: u64, calls: Vec<CallFrame>) -> CallFrame {
+        CallFrame {
+            kind: "CALL",
+            from: Address::repeat_byte(1),
+            to: Address::repeat_byte(2),
+            value: Some(U256::ZERO),
+            gas: U64::from(100_000),
+            gas_used: U64::from(gas_used),
+            input: Bytes::new(),
+            selector: None,
+            signature: None,
+            output: Bytes::from_static(&hex!("01")),
+            error: None,
+            revert_reason: None,
+            calls,
+        }
+    }
+
+    fn reference_frame(gas_used: u64, calls: Vec<ReferenceFrame>) -> ReferenceFrame {
+        ReferenceFrame {
+            kind: "CALL".to_string(),
+            from: Address::repeat_byte(1),
+            to: Some(Address::repeat_byte(2)),
+            gas_used: U64::from(gas_used),
+            output: Bytes::from_static(&hex!("01")),
+            error: None,
+            calls,
+        }
+    }
+
+    #[test]
+    fn trace_divergence() {
+        let local = local_frame(
+            30_000,
+            vec![local_frame(1_000, vec![]), local_frame(2_000, vec![])],
+        );
+        let mut reference = reference_frame(
+            30_000,
+            vec![
+                reference_frame(1_000, vec![]),
+                reference_frame(2_000, vec![]),
+            ],
+        );
+        assert_eq!(compare_traces(3, &local, &reference), None);
+
+        reference.calls[1].error = Some("execution reverted".to_string());
+        assert_eq!(
+            compare_traces(3, &local, &reference),
+            Some(Divergence::Trace {
+                tx_index: 3,
+                path: "0.1".to_string(),
+                field: "error",
+                local: "None".to_string(),
+                reference: "Some(\"execution reverted\")".to_string(),
+            })
+        );
+
+        reference.calls[1].error = None;
+        reference.gas_used = U64::from(29_000);
+        assert!(matches!(
+            compare_traces(3, &local, &reference),
+            Some(Divergence::Trace { field: "gas used", ref path, .. }) if path == "0"
+        ));
+    }
+}
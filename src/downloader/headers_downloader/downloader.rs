@@ -1,7 +1,12 @@
 use super::{
     downloader_forky, downloader_linear, downloader_preverified,
-    headers::header_slices::HeaderSlices, stages::fork_switch_command::ForkSwitchCommand,
-    ui::ui_system::UISystemShared, verification::header_slice_verifier::HeaderSliceVerifier,
+    headers::header_slices::HeaderSlices,
+    stages::fork_switch_command::ForkSwitchCommand,
+    ui::ui_system::UISystemShared,
+    verification::{
+        header_slice_verifier::HeaderSliceVerifier,
+        preverified_hashes_config::PreverifiedHashesConfig,
+    },
 };
 use crate::{
     kv,
@@ -11,6 +16,7 @@ use crate::{
 use parking_lot::Mutex;
 use std::{
     fmt::{Debug, Formatter},
+    path::Path,
     sync::Arc,
 };
 
@@ -68,13 +74,20 @@ impl Downloader {
     pub fn new(
         chain_config: ChainConfig,
         verifier: Box<dyn HeaderSliceVerifier>,
+        preverified_hashes_file: Option<&Path>,
         mem_limit: usize,
         sentry: SentryClientReactorShared,
     ) -> anyhow::Result<Self> {
         let verifier = Arc::new(verifier);
 
+        let preverified_hashes_config = match preverified_hashes_file {
+            Some(path) => PreverifiedHashesConfig::load(path)?,
+            None => verifier.preverified_hashes_config(&chain_config.chain_name())?,
+        };
+        preverified_hashes_config.verify_genesis(chain_config.genesis_block_hash())?;
+
         let downloader_preverified = downloader_preverified::DownloaderPreverified::new(
-            verifier.preverified_hashes_config(&chain_config.chain_name())?,
+            preverified_hashes_config,
             mem_limit,
             sentry.clone(),
         );
@@ -40,11 +40,9 @@ impl DownloaderPreverified {
     }
 
     fn target_final_block_num(&self) -> BlockNumber {
-        if self.preverified_hashes_config.is_empty() {
-            return BlockNumber(0);
-        }
-        let slice_size = header_slices::HEADER_SLICE_SIZE as u64;
-        BlockNumber((self.preverified_hashes_config.hashes.len() as u64 - 1) * slice_size)
+        self.preverified_hashes_config
+            .height()
+            .unwrap_or(BlockNumber(0))
     }
 
     pub async fn run<'downloader, 'db: 'downloader, RwTx: kv::traits::MutableTransaction<'db>>(
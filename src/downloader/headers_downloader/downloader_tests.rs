@@ -116,6 +116,7 @@ impl DownloaderTest {
         let downloader = Downloader::new(
             chain_config,
             Box::new(verifier),
+            None,
             byte_unit::n_mib_bytes!(50) as usize,
             sentry_reactor.clone(),
         )?;
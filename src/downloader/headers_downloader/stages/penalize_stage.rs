@@ -2,12 +2,12 @@ use super::headers::{
     header_slice_status_watch::HeaderSliceStatusWatch,
     header_slices::{HeaderSliceStatus, HeaderSlices},
 };
-use crate::sentry::{sentry_client::PeerId, sentry_client_reactor::*};
+use crate::sentry::{reputation::Misbehaviour, sentry_client::PeerId, sentry_client_reactor::*};
 use parking_lot::RwLockUpgradableReadGuard;
 use std::{collections::HashSet, ops::DerefMut, sync::Arc};
 use tracing::*;
 
-/// Penalize peers for sending us headers that failed to verify, and mark the related slices as Empty for retry.
+/// Report peers for sending us headers that failed to verify, and mark the related slices as Empty for retry.
 pub struct PenalizeStage {
     header_slices: Arc<HeaderSlices>,
     sentry: SentryClientReactorShared,
@@ -76,7 +76,9 @@ impl PenalizeStage {
     async fn penalize_peers(&self, peers: HashSet<PeerId>) -> anyhow::Result<()> {
         let sentry = self.sentry.read().await;
         for peer_id in peers {
-            sentry.penalize_peer(peer_id).await?;
+            sentry
+                .report_peer(peer_id, Misbehaviour::InvalidHeaders)
+                .await?;
         }
         Ok(())
     }
@@ -1,7 +1,6 @@
 use super::{
     headers::{
         header_slice_status_watch::HeaderSliceStatusWatch,
-        header_slices,
         header_slices::{HeaderSlice, HeaderSliceStatus, HeaderSlices},
     },
     verification::{
@@ -140,12 +139,7 @@ impl VerifyPreverifiedStage {
     }
 
     fn preverified_hash(&self, block_num: u64) -> Option<&H256> {
-        let preverified_step_size = header_slices::HEADER_SLICE_SIZE as u64;
-        if block_num % preverified_step_size != 0 {
-            return None;
-        }
-        let index = block_num / preverified_step_size;
-        self.preverified_hashes.hashes.get(index as usize)
+        self.preverified_hashes.hash(BlockNumber(block_num))
     }
 
     pub fn can_proceed_check(&self) -> impl Fn() -> bool {
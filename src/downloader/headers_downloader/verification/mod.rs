@@ -1,4 +1,4 @@
 pub mod header_slice_verifier;
 pub mod header_slice_verifier_mock;
 pub(super) mod parallel;
-pub(super) mod preverified_hashes_config;
+pub mod preverified_hashes_config;
@@ -1,6 +1,10 @@
+use super::super::headers::header_slices::HEADER_SLICE_SIZE;
 use crate::models::*;
+use anyhow::Context;
 use serde::{de, Deserialize};
-use std::str::FromStr;
+use std::{fmt::Write, path::Path, str::FromStr};
+
+const PREVERIFIED_HASHES_STEP: u64 = HEADER_SLICE_SIZE as u64;
 
 /// The preverified hashes is a list of known precomputed hashes of every 192-th block in the chain:
 ///
@@ -9,6 +13,10 @@ use std::str::FromStr;
 /// The preverified hashes are copied from:
 /// https://github.com/ledgerwatch/erigon/blob/devel/turbo/stages/headerdownload/preverified_hashes_mainnet.go
 /// https://github.com/ledgerwatch/erigon/blob/devel/turbo/stages/headerdownload/preverified_hashes_ropsten.go
+///
+/// A bundle for other chains (or a more recent one) can be loaded from a file of the same format.
+/// Headers below the last preverified hash are only checked to match the bundle,
+/// without seal verification.
 #[derive(Clone, Debug)]
 pub struct PreverifiedHashesConfig {
     pub hashes: Vec<H256>,
@@ -26,14 +34,38 @@ impl PreverifiedHashesConfig {
         let config_text = match chain_name.to_lowercase().as_str() {
             "mainnet" | "ethereum" => include_str!("preverified_hashes_mainnet.toml"),
             "ropsten" => include_str!("preverified_hashes_ropsten.toml"),
-            _ => anyhow::bail!("unsupported chain"),
+            // Chains without a bundle, e.g. loaded from a genesis file, are downloaded linearly.
+            _ => return Ok(Self::empty()),
         };
+        Self::from_toml_str(config_text)
+    }
+
+    /// Load bundle from a TOML file: `hashes = ["<hex>", ...]`.
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let config_text = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read preverified hashes from {:?}", path))?;
+        Self::from_toml_str(&config_text)
+            .with_context(|| format!("invalid preverified hashes file {:?}", path))
+    }
+
+    fn from_toml_str(config_text: &str) -> anyhow::Result<Self> {
         let config: PreverifiedHashesConfigUnprefixedHex = toml::from_str(config_text)?;
         Ok(Self {
             hashes: config.hashes.iter().map(|hash| hash.0).collect(),
         })
     }
 
+    /// Serialize into the format accepted by [`Self::load`].
+    pub fn to_toml_string(&self) -> String {
+        let mut s = String::from("hashes = [\n");
+        for hash in &self.hashes {
+            writeln!(s, "\t\"{}\",", hex::encode(hash)).unwrap();
+        }
+        s.push_str("]\n");
+        s
+    }
+
     pub fn empty() -> Self {
         Self { hashes: Vec::new() }
     }
@@ -41,6 +73,35 @@ impl PreverifiedHashesConfig {
     pub fn is_empty(&self) -> bool {
         self.hashes.is_empty()
     }
+
+    /// Preverified hash of the block, if the block is at the bundle step and within the bundle.
+    pub fn hash(&self, block_num: BlockNumber) -> Option<&H256> {
+        if block_num.0 % PREVERIFIED_HASHES_STEP != 0 {
+            return None;
+        }
+        self.hashes
+            .get(usize::try_from(block_num.0 / PREVERIFIED_HASHES_STEP).ok()?)
+    }
+
+    /// Number of the last preverified block, `None` if the bundle is empty.
+    pub fn height(&self) -> Option<BlockNumber> {
+        let len = self.hashes.len() as u64;
+        len.checked_sub(1)
+            .map(|last| BlockNumber(last * PREVERIFIED_HASHES_STEP))
+    }
+
+    /// Check that the bundle belongs to the chain with the given genesis.
+    pub fn verify_genesis(&self, genesis_hash: H256) -> anyhow::Result<()> {
+        if let Some(&first) = self.hashes.first() {
+            anyhow::ensure!(
+                first == genesis_hash,
+                "preverified hashes start with {:?}, expected genesis {:?}",
+                first,
+                genesis_hash
+            );
+        }
+        Ok(())
+    }
 }
 
 impl FromStr for UnprefixedHexH256 {
@@ -64,3 +125,44 @@ impl<'de> Deserialize<'de> for UnprefixedHexH256 {
         FromStr::from_str(&hash_str).map_err(de::Error::custom)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hex_literal::hex;
+
+    #[test]
+    fn bundle_roundtrip() {
+        let config = PreverifiedHashesConfig {
+            hashes: vec![
+                hex!("d4e56740f876aef8c010b86a40d5f56745a118d0906a34e69aec8c0db1cb8fa3").into(),
+                hex!("723899e82d352c6eabd21e34942f868687203ca14b3d5a23aeb47c555c123390").into(),
+                hex!("d3d5d5c1b501a00e76cbd467f2c670e436119b63974d19652d0d2d35bbc79cf3").into(),
+            ],
+        };
+
+        let file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(file.path(), config.to_toml_string()).unwrap();
+        let loaded = PreverifiedHashesConfig::load(file.path()).unwrap();
+        assert_eq!(loaded.hashes, config.hashes);
+
+        assert_eq!(loaded.height(), Some(BlockNumber(384)));
+        assert_eq!(loaded.hash(BlockNumber(192)), Some(&config.hashes[1]));
+        assert_eq!(loaded.hash(BlockNumber(193)), None);
+        assert_eq!(loaded.hash(BlockNumber(576)), None);
+        assert_eq!(PreverifiedHashesConfig::empty().height(), None);
+
+        loaded.verify_genesis(config.hashes[0]).unwrap();
+        assert!(loaded.verify_genesis(config.hashes[1]).is_err());
+    }
+
+    #[test]
+    fn builtin_bundles_start_at_genesis() {
+        let mainnet = PreverifiedHashesConfig::new("mainnet").unwrap();
+        mainnet
+            .verify_genesis(
+                hex!("d4e56740f876aef8c010b86a40d5f56745a118d0906a34e69aec8c0db1cb8fa3").into(),
+            )
+            .unwrap();
+    }
+}
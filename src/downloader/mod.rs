@@ -1,5 +1,6 @@
 pub mod opts;
 pub mod sentry_status_provider;
+pub mod snap_progress;
 pub mod ui;
 
 mod headers_downloader;
@@ -10,5 +11,5 @@ pub use headers_downloader::{
         DownloaderRunState as HeadersDownloaderRunState,
         DownloaderUnwindRequest as HeadersDownloaderUnwindRequest,
     },
-    verification::header_slice_verifier,
+    verification::{header_slice_verifier, preverified_hashes_config::PreverifiedHashesConfig},
 };
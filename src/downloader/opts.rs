@@ -1,4 +1,5 @@
 use clap::Parser;
+use std::path::PathBuf;
 
 #[derive(Parser, Debug)]
 pub struct Opts {
@@ -14,6 +15,12 @@ pub struct Opts {
         default_value = "100000"
     )]
     pub headers_batch_size: usize,
+    #[clap(
+        long = "downloader.preverified-hashes",
+        help = "TOML file with hashes of every 192nd block, overrides the built-in bundle. Headers below the last one skip seal verification.",
+        parse(from_os_str)
+    )]
+    pub preverified_hashes_file: Option<PathBuf>,
 }
 
 impl Opts {
@@ -31,6 +31,11 @@ impl SentryStatusProvider {
         }
     }
 
+    /// Receiver of status updates, starting with the current one.
+    pub fn status_receiver(&self) -> watch::Receiver<Status> {
+        self.sender.subscribe()
+    }
+
     pub fn current_status_stream(&self) -> sentry_client_connector::StatusStream {
         let receiver = self.sender.subscribe();
         let stream = async_stream::stream! {
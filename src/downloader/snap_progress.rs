@@ -0,0 +1,227 @@
+use crate::{
+    h256_to_u256,
+    kv::{tables, traits::*},
+    models::*,
+    u256_to_h256,
+};
+use parity_scale_codec::{Decode, Encode};
+
+/// Hashed account keys `next..=limit` that are not downloaded yet.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Encode, Decode)]
+pub struct AccountRange {
+    pub next: H256,
+    pub limit: H256,
+}
+
+/// Trie node that is referenced but absent locally, found while healing.
+#[derive(Clone, Debug, PartialEq, Eq, Encode, Decode)]
+pub struct HealTask {
+    /// Hashed address of the account whose storage trie contains the node, `None` for the account trie.
+    pub account: Option<H256>,
+    /// Nibbles from the trie root to the node.
+    pub path: Vec<u8>,
+    pub hash: H256,
+}
+
+/// Snap sync progress towards state at pivot block, persisted so that an interrupted sync resumes
+/// from where it stopped instead of downloading all account ranges again.
+///
+/// Sync first fills in [`Self::pending_ranges`]. Once there are none left, the state root is queued
+/// for healing, and every healed node is replaced in the queue by its missing children.
+#[derive(Clone, Debug, PartialEq, Eq, Encode, Decode)]
+pub struct SnapSyncProgress {
+    pub pivot: BlockNumber,
+    pub state_root: H256,
+    pub pending_ranges: Vec<AccountRange>,
+    pub heal_queue: Vec<HealTask>,
+    pub healed_nodes: u64,
+}
+
+impl SnapSyncProgress {
+    /// Fresh progress with the key space split into `ranges` equal parts, to be downloaded concurrently.
+    pub fn new(pivot: BlockNumber, state_root: H256, ranges: usize) -> Self {
+        let ranges = ranges.max(1);
+        let step = U256::MAX / U256::from(ranges as u64);
+        let pending_ranges = (0..ranges as u64)
+            .map(|i| AccountRange {
+                next: u256_to_h256(step * U256::from(i) + U256::from(i.min(1))),
+                limit: if i + 1 == ranges as u64 {
+                    H256::repeat_byte(0xff)
+                } else {
+                    u256_to_h256(step * U256::from(i + 1))
+                },
+            })
+            .collect();
+
+        Self {
+            pivot,
+            state_root,
+            pending_ranges,
+            heal_queue: vec![],
+            healed_nodes: 0,
+        }
+    }
+
+    /// Load persisted progress, moving it to a new pivot if necessary, or start over if there is none.
+    ///
+    /// Downloaded ranges are kept when the pivot changes: accounts that have changed since are fixed
+    /// by healing, which is restarted from the new state root.
+    pub async fn resume<'db, Tx: Transaction<'db>>(
+        tx: &Tx,
+        pivot: BlockNumber,
+        state_root: H256,
+        ranges: usize,
+    ) -> anyhow::Result<Self> {
+        let progress = match tx.get(tables::SnapProgress, Default::default()).await? {
+            Some(progress) if progress.state_root == state_root => progress,
+            Some(mut progress) => {
+                progress.pivot = pivot;
+                progress.state_root = state_root;
+                progress.heal_queue.clear();
+                progress.start_healing();
+                progress
+            }
+            None => Self::new(pivot, state_root, ranges),
+        };
+
+        Ok(progress)
+    }
+
+    pub async fn save<'db, RwTx: MutableTransaction<'db>>(&self, tx: &RwTx) -> anyhow::Result<()> {
+        tx.set(tables::SnapProgress, Default::default(), self.clone())
+            .await
+    }
+
+    /// Remove persisted progress once sync is complete.
+    pub async fn clear<'db, RwTx: MutableTransaction<'db>>(tx: &RwTx) -> anyhow::Result<()> {
+        tx.del(tables::SnapProgress, Default::default(), None).await
+    }
+
+    /// Record an account range response for the pending range starting at `origin`.
+    /// `last` is the last received key, and `more` tells whether the range has more accounts after it.
+    pub fn account_range_done(&mut self, origin: H256, last: Option<H256>, more: bool) {
+        let index = match self.pending_ranges.iter().position(|r| r.next == origin) {
+            Some(index) => index,
+            None => return,
+        };
+
+        match last {
+            Some(last) if more && last < self.pending_ranges[index].limit => {
+                self.pending_ranges[index].next = u256_to_h256(h256_to_u256(last) + U256::ONE);
+            }
+            _ => {
+                self.pending_ranges.remove(index);
+                self.start_healing();
+            }
+        }
+    }
+
+    /// Up to `max` nodes to request next.
+    pub fn next_missing(&self, max: usize) -> &[HealTask] {
+        &self.heal_queue[..max.min(self.heal_queue.len())]
+    }
+
+    /// Remove `healed` nodes from the queue and enqueue their children that are still missing.
+    pub fn nodes_healed(&mut self, healed: &[H256], missing_children: Vec<HealTask>) {
+        let before = self.heal_queue.len();
+        self.heal_queue.retain(|task| !healed.contains(&task.hash));
+        self.healed_nodes += (before - self.heal_queue.len()) as u64;
+        self.heal_queue.extend(missing_children);
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.pending_ranges.is_empty() && self.heal_queue.is_empty() && self.healed_nodes > 0
+    }
+
+    /// Queue the state root for healing, unless ranges are still pending or healing is underway.
+    pub fn start_healing(&mut self) {
+        if self.pending_ranges.is_empty() && self.heal_queue.is_empty() {
+            self.heal_queue.push(HealTask {
+                account: None,
+                path: vec![],
+                hash: self.state_root,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kv::new_mem_database;
+    use hex_literal::hex;
+
+    #[tokio::test]
+    async fn resumes_interrupted_sync() {
+        let db = new_mem_database().unwrap();
+        let root = H256(hex!(
+            "d7f8974fb5ac78d9ac099b9ad5018bedc2ce0a72dad1827a1709da30580f0544"
+        ));
+
+        let tx = db.begin().await.unwrap();
+        let mut progress = SnapSyncProgress::resume(&tx, BlockNumber(100), root, 4)
+            .await
+            .unwrap();
+        drop(tx);
+        assert_eq!(progress.pending_ranges.len(), 4);
+        assert_eq!(progress.pending_ranges[0].next, H256::zero());
+        assert_eq!(progress.pending_ranges[3].limit, H256::repeat_byte(0xff));
+        for w in progress.pending_ranges.windows(2) {
+            assert_eq!(
+                h256_to_u256(w[0].limit) + U256::ONE,
+                h256_to_u256(w[1].next)
+            );
+        }
+
+        let origin = progress.pending_ranges[1].next;
+        let last = u256_to_h256(h256_to_u256(origin) + U256::from(1000_u64));
+        progress.account_range_done(origin, Some(last), true);
+        assert_eq!(
+            progress.pending_ranges[1].next,
+            u256_to_h256(h256_to_u256(last) + U256::ONE)
+        );
+
+        let rwtx = db.begin_mutable().await.unwrap();
+        progress.save(&rwtx).await.unwrap();
+        rwtx.commit().await.unwrap();
+
+        let tx = db.begin().await.unwrap();
+        let resumed = SnapSyncProgress::resume(&tx, BlockNumber(100), root, 4)
+            .await
+            .unwrap();
+        assert_eq!(resumed, progress);
+
+        // New pivot keeps downloaded ranges
+        let new_root = H256::repeat_byte(0x01);
+        let moved = SnapSyncProgress::resume(&tx, BlockNumber(200), new_root, 4)
+            .await
+            .unwrap();
+        assert_eq!(moved.pivot, BlockNumber(200));
+        assert_eq!(moved.pending_ranges, progress.pending_ranges);
+        assert!(moved.heal_queue.is_empty());
+    }
+
+    #[test]
+    fn heals_after_ranges() {
+        let root = H256::repeat_byte(0xaa);
+        let mut progress = SnapSyncProgress::new(BlockNumber(1), root, 1);
+
+        progress.account_range_done(H256::zero(), Some(H256::repeat_byte(0xff)), false);
+        assert!(progress.pending_ranges.is_empty());
+        assert_eq!(progress.next_missing(10).len(), 1);
+        assert_eq!(progress.next_missing(10)[0].hash, root);
+        assert!(!progress.is_complete());
+
+        let child = HealTask {
+            account: None,
+            path: vec![0x3],
+            hash: H256::repeat_byte(0xbb),
+        };
+        progress.nodes_healed(&[root], vec![child.clone()]);
+        assert_eq!(progress.next_missing(10), &[child.clone()]);
+
+        progress.nodes_healed(&[child.hash], vec![]);
+        assert_eq!(progress.healed_nodes, 2);
+        assert!(progress.is_complete());
+    }
+}
@@ -0,0 +1,177 @@
+use anyhow::{bail, ensure, format_err, Context};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::{
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Most seconds that `iat` of a token may differ from our clock.
+pub const MAX_CLOCK_DRIFT: u64 = 60;
+
+#[derive(Deserialize)]
+struct JwtHeader {
+    alg: String,
+}
+
+#[derive(Deserialize)]
+struct JwtClaims {
+    iat: u64,
+}
+
+/// Secret shared with the consensus client, which signs its Engine API requests with it.
+#[derive(Clone, PartialEq, Eq)]
+pub struct JwtSecret([u8; 32]);
+
+impl std::fmt::Debug for JwtSecret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("JwtSecret(..)")
+    }
+}
+
+impl JwtSecret {
+    pub fn random() -> Self {
+        Self(rand::random())
+    }
+
+    pub fn from_hex(s: &str) -> anyhow::Result<Self> {
+        let s = s.trim();
+        let bytes = hex::decode(s.strip_prefix("0x").unwrap_or(s))?;
+        Ok(Self(bytes.try_into().map_err(|bytes: Vec<u8>| {
+            format_err!("JWT secret must be 32 bytes, got {}", bytes.len())
+        })?))
+    }
+
+    /// Read the hex encoded secret at `path`, generating a new one there if it does not exist,
+    /// so that the consensus client can be pointed to the same file.
+    pub fn load_or_generate(path: &Path) -> anyhow::Result<Self> {
+        if path.exists() {
+            return Self::from_hex(
+                &std::fs::read_to_string(path)
+                    .with_context(|| format!("failed to read {}", path.display()))?,
+            )
+            .with_context(|| format!("invalid JWT secret in {}", path.display()));
+        }
+
+        let secret = Self::random();
+        std::fs::write(path, format!("0x{}", hex::encode(secret.0)))
+            .with_context(|| format!("failed to write {}", path.display()))?;
+        Ok(secret)
+    }
+
+    // https://datatracker.ietf.org/doc/html/rfc2104
+    fn hmac(&self, data: &[u8]) -> [u8; 32] {
+        let mut key = [0; 64];
+        key[..32].copy_from_slice(&self.0);
+
+        let mut inner = Sha256::new();
+        inner.update(key.map(|b| b ^ 0x36));
+        inner.update(data);
+        let mut outer = Sha256::new();
+        outer.update(key.map(|b| b ^ 0x5c));
+        outer.update(inner.finalize());
+        outer.finalize().into()
+    }
+
+    /// HS256 token issued at `iat`.
+    pub fn encode(&self, iat: u64) -> String {
+        let encode = |data: &[u8]| base64::encode_config(data, base64::URL_SAFE_NO_PAD);
+
+        let message = format!(
+            "{}.{}",
+            encode(br#"{"typ":"JWT","alg":"HS256"}"#),
+            encode(format!(r#"{{"iat":{}}}"#, iat).as_bytes())
+        );
+        let signature = encode(&self.hmac(message.as_bytes()));
+        format!("{}.{}", message, signature)
+    }
+
+    /// Check that `token` is signed with this secret and issued around `now`.
+    pub fn validate(&self, token: &str, now: u64) -> anyhow::Result<()> {
+        let decode = |part: &str| base64::decode_config(part, base64::URL_SAFE_NO_PAD);
+
+        let mut parts = token.split('.');
+        let (header, claims, signature) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(header), Some(claims), Some(signature)) if parts.next().is_none() => {
+                (header, claims, signature)
+            }
+            _ => bail!("malformed token"),
+        };
+
+        let alg = serde_json::from_slice::<JwtHeader>(&decode(header)?)?.alg;
+        ensure!(alg == "HS256", "unsupported algorithm {}", alg);
+
+        let expected = self.hmac(format!("{}.{}", header, claims).as_bytes());
+        let signature = decode(signature)?;
+        // Compare in constant time, not to leak how much of a forged signature is right.
+        ensure!(
+            signature.len() == expected.len()
+                && signature
+                    .iter()
+                    .zip(expected)
+                    .fold(0, |acc, (a, b)| acc | (a ^ b))
+                    == 0,
+            "invalid signature"
+        );
+
+        let iat = serde_json::from_slice::<JwtClaims>(&decode(claims)?)?.iat;
+        ensure!(
+            iat.max(now) - iat.min(now) <= MAX_CLOCK_DRIFT,
+            "token issued at {}, now is {}",
+            iat,
+            now
+        );
+
+        Ok(())
+    }
+}
+
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hex_literal::hex;
+
+    #[test]
+    fn hmac_rfc4231() {
+        // Test case 2 of RFC 4231 with the key zero padded, which HMAC does anyway.
+        let mut key = [0; 32];
+        key[..4].copy_from_slice(b"Jefe");
+        assert_eq!(
+            JwtSecret(key).hmac(b"what do ya want for nothing?"),
+            hex!("5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843")
+        );
+    }
+
+    #[test]
+    fn validate() {
+        let secret = JwtSecret::random();
+        let now = 1_650_000_000;
+
+        secret.validate(&secret.encode(now), now).unwrap();
+        secret
+            .validate(&secret.encode(now - MAX_CLOCK_DRIFT), now)
+            .unwrap();
+        assert!(secret
+            .validate(&secret.encode(now - MAX_CLOCK_DRIFT - 1), now)
+            .is_err());
+        assert!(secret
+            .validate(&secret.encode(now + MAX_CLOCK_DRIFT + 1), now)
+            .is_err());
+        assert!(JwtSecret::random()
+            .validate(&secret.encode(now), now)
+            .is_err());
+        assert!(secret.validate("", now).is_err());
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("jwt.hex");
+        let generated = JwtSecret::load_or_generate(&path).unwrap();
+        assert_eq!(JwtSecret::load_or_generate(&path).unwrap(), generated);
+    }
+}
@@ -0,0 +1,15 @@
+//! Engine API that a consensus client drives the node with after the merge.
+//!
+//! Payloads are stored as non-canonical blocks as they come, forkchoice updates move the head
+//! that [`ForkchoiceHeaders`](crate::stages::ForkchoiceHeaders) makes canonical for the rest of
+//! staged sync.
+
+pub mod jwt;
+pub mod payload;
+pub mod server;
+
+pub use self::{
+    jwt::JwtSecret,
+    payload::{build_payload, new_payload, BlockTemplateSource, PayloadStatus, PayloadStatusV1},
+    server::{serve, EngineApiServer, EngineApiServerImpl},
+};
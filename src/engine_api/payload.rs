@@ -0,0 +1,552 @@
+use crate::{
+    accessors::chain,
+    consensus::{
+        self, expected_base_fee_per_gas, expected_excess_blob_gas, pre_validate_transaction,
+        ValidationError,
+    },
+    crypto::root_hash,
+    execution::{
+        analysis_cache::AnalysisCache,
+        processor::ExecutionProcessor,
+        side_chain::{execute_side_chain_block, SideChainExecution},
+    },
+    kv::{tables, traits::*},
+    models::*,
+    rpc::{miner::MAX_EXTRA_DATA_BYTES, BlockTemplate},
+    stagedsync::stages::*,
+    stages::{promote_accounts, promote_storage},
+    trie::increment_intermediate_hashes,
+    Buffer,
+};
+use anyhow::{ensure, format_err};
+use jsonrpsee::{
+    core::client::ClientT,
+    http_client::{HttpClient, HttpClientBuilder},
+    rpc_params,
+};
+use serde::Serialize;
+use tempfile::TempDir;
+use tracing::*;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum PayloadStatus {
+    Valid,
+    Invalid,
+    Accepted,
+    Syncing,
+    InvalidBlockHash,
+}
+
+/// Verdict on a payload or on the head of a forkchoice update.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PayloadStatusV1 {
+    pub status: PayloadStatus,
+    pub latest_valid_hash: Option<H256>,
+    pub validation_error: Option<String>,
+}
+
+impl PayloadStatusV1 {
+    pub fn new(status: PayloadStatus) -> Self {
+        Self {
+            status,
+            latest_valid_hash: None,
+            validation_error: None,
+        }
+    }
+
+    pub fn valid(hash: H256) -> Self {
+        Self {
+            latest_valid_hash: Some(hash),
+            ..Self::new(PayloadStatus::Valid)
+        }
+    }
+
+    pub fn with_validation_error(mut self, e: &anyhow::Error) -> Self {
+        self.validation_error = Some(format!("{:#}", e));
+        self
+    }
+}
+
+/// Handle `engine_newPayload`: validate the payload and store it if it's not invalid.
+pub async fn new_payload<DB: MutableKV>(
+    db: &DB,
+    etl_dir: &TempDir,
+    payload: ExecutionPayload,
+) -> anyhow::Result<PayloadStatusV1> {
+    let block = match payload.into_block() {
+        Ok(block) => block,
+        Err(e) => {
+            warn!("Rejecting payload: {}", e);
+            return Ok(
+                PayloadStatusV1::new(PayloadStatus::InvalidBlockHash).with_validation_error(&e)
+            );
+        }
+    };
+
+    let txn = db.begin_mutable().await?;
+    let status = validate_payload(&txn, etl_dir, &block).await?;
+    // Payload state is thrown away along with the transaction.
+    drop(txn);
+
+    if matches!(
+        status.status,
+        PayloadStatus::Valid | PayloadStatus::Accepted
+    ) {
+        let txn = db.begin_mutable().await?;
+        insert_payload(&txn, &block).await?;
+        txn.commit().await?;
+    }
+
+    Ok(status)
+}
+
+/// Validate the payload on top of its side chain.
+///
+/// Payload is only VALID once its state root is verified, which is done when it forks off the
+/// head of staged sync. Otherwise it's executed if state of the fork point is available, and
+/// ACCEPTED until staged sync gets to it. State of the payload is written into `tx`, so `tx` must
+/// never be committed.
+pub async fn validate_payload<'db, RwTx: MutableTransaction<'db>>(
+    tx: &RwTx,
+    etl_dir: &TempDir,
+    block: &Block,
+) -> anyhow::Result<PayloadStatusV1> {
+    let number = block.header.number;
+    let hash = block.header.hash();
+    let parent_number = BlockNumber(number.0.saturating_sub(1));
+    if number.0 == 0
+        || tx
+            .get(
+                tables::HeadersTotalDifficulty,
+                (parent_number, block.header.parent_hash),
+            )
+            .await?
+            .is_none()
+    {
+        return Ok(PayloadStatusV1::new(PayloadStatus::Syncing));
+    }
+
+    Ok(match execute_side_chain_block(tx, etl_dir, block).await? {
+        SideChainExecution::Valid => PayloadStatusV1::valid(hash),
+        SideChainExecution::Invalid(e) => {
+            warn!("Rejecting payload {}/{:?}: {:#}", number, hash, e);
+            PayloadStatusV1::new(PayloadStatus::Invalid).with_validation_error(&e)
+        }
+        SideChainExecution::Executed | SideChainExecution::Unavailable => {
+            PayloadStatusV1::new(PayloadStatus::Accepted)
+        }
+    })
+}
+
+/// Stores the payload as a non-canonical block. Canonical chain is only updated on forkchoice.
+///
+/// Only payloads with a known parent that passed [`validate_payload`] are to be stored.
+pub async fn insert_payload<'db, RwTx: MutableTransaction<'db>>(
+    tx: &RwTx,
+    block: &Block,
+) -> anyhow::Result<()> {
+    let number = block.header.number;
+    let hash = block.header.hash();
+
+    // Consensus layer may send the same payload again, e.g. after a restart.
+    if chain::header_number::read(tx, hash).await?.is_some() {
+        return Ok(());
+    }
+
+    let parent_number = BlockNumber(number.0 - 1);
+    let parent_td = tx
+        .get(
+            tables::HeadersTotalDifficulty,
+            (parent_number, block.header.parent_hash),
+        )
+        .await?
+        .ok_or_else(|| {
+            format_err!(
+                "no total difficulty of parent {}/{:?}",
+                parent_number,
+                block.header.parent_hash
+            )
+        })?;
+
+    let base_tx_id = tx
+        .cursor(tables::BlockTransaction)
+        .await?
+        .last()
+        .await?
+        .map(|(id, _)| id + 1)
+        .unwrap_or(TxIndex(0));
+
+    tx.set(tables::Header, (number, hash), block.header.clone())
+        .await?;
+    tx.set(tables::HeaderNumber, hash, number).await?;
+    tx.set(
+        tables::HeadersTotalDifficulty,
+        (number, hash),
+        parent_td + block.header.difficulty,
+    )
+    .await?;
+    chain::storage_body::write(
+        tx,
+        hash,
+        number,
+        &BodyForStorage {
+            base_tx_id,
+            tx_amount: block.transactions.len() as u64,
+            uncles: vec![],
+            withdrawals: block.withdrawals.clone(),
+        },
+    )
+    .await?;
+    chain::tx::write(tx, base_tx_id, &block.transactions).await?;
+
+    Ok(())
+}
+
+/// Pool of the RPC daemon, asked over JSON-RPC for transactions of built payloads.
+#[derive(Debug)]
+pub struct BlockTemplateSource {
+    client: HttpClient,
+}
+
+impl BlockTemplateSource {
+    pub fn new(url: &str) -> anyhow::Result<Self> {
+        Ok(Self {
+            client: HttpClientBuilder::default().build(url)?,
+        })
+    }
+
+    /// Template of the block on top of `parent_hash`, see [`build_payload`].
+    pub async fn fetch<DB: KV>(&self, db: &DB, parent_hash: H256) -> anyhow::Result<BlockTemplate> {
+        let (base_fee_per_gas, gas_limit) = {
+            let tx = db.begin().await?;
+            let parent_number = chain::header_number::read(&tx, parent_hash)
+                .await?
+                .ok_or_else(|| format_err!("unknown parent {:?}", parent_hash))?;
+            let parent = chain::header::read(&tx, parent_hash, parent_number)
+                .await?
+                .ok_or_else(|| {
+                    format_err!("no header for parent {}/{:?}", parent_number, parent_hash)
+                })?;
+            let chain_spec = chain::chain_spec::read(&tx).await?;
+            (
+                expected_base_fee_per_gas(
+                    chain_spec.consensus.eip1559_block,
+                    parent_number + 1,
+                    &parent,
+                ),
+                parent.gas_limit,
+            )
+        };
+
+        Ok(self
+            .client
+            .request(
+                "miner_blockTemplate",
+                rpc_params![base_fee_per_gas, U64::from(gas_limit)],
+            )
+            .await?)
+    }
+}
+
+/// Build a block with withdrawals of `attributes` on top of the canonical block `parent_hash`,
+/// which must be the last one with state root verified.
+///
+/// Block is filled with transactions of `template` in their order, leaving out those that are
+/// not valid on top of the previous ones. Blob transactions are left out too, payloads are built
+/// without blob sidecars.
+///
+/// State of the new block is written into `tx` to compute its state root, so `tx` must never be
+/// committed.
+pub async fn build_payload<'db, RwTx: MutableTransaction<'db>>(
+    tx: &RwTx,
+    etl_dir: &TempDir,
+    parent_hash: H256,
+    attributes: &PayloadAttributes,
+    template: BlockTemplate,
+) -> anyhow::Result<Block> {
+    let parent_number = chain::header_number::read(tx, parent_hash)
+        .await?
+        .ok_or_else(|| format_err!("unknown parent {:?}", parent_hash))?;
+    ensure!(
+        chain::canonical_hash::read(tx, parent_number).await? == Some(parent_hash),
+        "parent {}/{:?} is not canonical",
+        parent_number,
+        parent_hash
+    );
+    for stage in [EXECUTION, HASH_STATE, INTERMEDIATE_HASHES] {
+        let progress = stage.get_progress(tx).await?.unwrap_or_default();
+        ensure!(
+            progress == parent_number,
+            "{} is at block {} instead of parent {}",
+            stage,
+            progress,
+            parent_number
+        );
+    }
+    let parent = chain::header::read(tx, parent_hash, parent_number)
+        .await?
+        .ok_or_else(|| format_err!("no header for parent {}/{:?}", parent_number, parent_hash))?;
+    ensure!(
+        attributes.timestamp > parent.timestamp,
+        "timestamp {} is not after parent timestamp {}",
+        attributes.timestamp,
+        parent.timestamp
+    );
+
+    let chain_spec = chain::chain_spec::read(tx).await?;
+
+    let number = parent_number + 1;
+    let excess_blob_gas =
+        expected_excess_blob_gas(chain_spec.consensus.eip4844_block, number, &parent);
+    let mut header = PartialHeader {
+        parent_hash,
+        beneficiary: attributes.suggested_fee_recipient,
+        number,
+        gas_limit: parent.gas_limit,
+        timestamp: attributes.timestamp,
+        mix_hash: attributes.prev_randao,
+        base_fee_per_gas: expected_base_fee_per_gas(
+            chain_spec.consensus.eip1559_block,
+            number,
+            &parent,
+        ),
+        withdrawals_root: attributes
+            .withdrawals
+            .as_deref()
+            .map(Block::withdrawals_root),
+        // Payload is built without blob transactions.
+        blob_gas_used: excess_blob_gas.map(|_| 0),
+        excess_blob_gas,
+        ..PartialHeader::empty()
+    };
+
+    if template.extra_data.len() <= MAX_EXTRA_DATA_BYTES {
+        header.extra_data = template.extra_data;
+    } else {
+        warn!(
+            "Template extra data is {} bytes, building payload without it",
+            template.extra_data.len()
+        );
+    }
+
+    let mut candidates = vec![];
+    for encoded in &template.transactions {
+        let transaction = match MessageWithSignature::trie_decode(encoded) {
+            Ok(transaction) => transaction,
+            Err(e) => {
+                debug!("Skipping undecodable template transaction: {}", e);
+                continue;
+            }
+        };
+        if transaction.tx_type() == TxType::EIP4844 {
+            continue;
+        }
+        if let Ok(sender) = transaction.recover_sender() {
+            candidates.push((transaction, sender));
+        }
+    }
+
+    let mut engine = consensus::engine_factory(chain_spec.clone())?;
+    let mut analysis_cache = AnalysisCache::default();
+    let block_spec = chain_spec.collect_block_spec(number);
+
+    // Dry run picks transactions and fills in the receipt fields of the header, which the real
+    // run checks. Its state is thrown away.
+    let mut transactions = vec![];
+    let mut receipts = vec![];
+    {
+        let mut buffer = Buffer::new(tx, BlockNumber(0), None);
+        let no_transactions = BlockBodyWithSenders {
+            transactions: vec![],
+            ommers: vec![],
+            withdrawals: None,
+        };
+        let mut processor = ExecutionProcessor::new(
+            &mut buffer,
+            None,
+            &mut analysis_cache,
+            &mut *engine,
+            &header,
+            &no_transactions,
+            &block_spec,
+        );
+        processor.execute_block_prologue().await?;
+        for (transaction, sender) in candidates {
+            let txn = MessageWithSender {
+                message: transaction.message.clone(),
+                sender,
+            };
+            if pre_validate_transaction(
+                &txn.message,
+                chain_spec.params.chain_id,
+                header.base_fee_per_gas,
+            )
+            .is_err()
+            {
+                continue;
+            }
+            if let Err(e) = processor.validate_transaction(&txn).await {
+                if e.downcast_ref::<ValidationError>().is_some() {
+                    continue;
+                }
+                return Err(e);
+            }
+            receipts.push(processor.execute_transaction(&txn).await?);
+            transactions.push((transaction, sender));
+        }
+    }
+    header.gas_used = receipts.last().map(|r| r.cumulative_gas_used).unwrap_or(0);
+    header.receipts_root = root_hash(&receipts);
+    header.logs_bloom = receipts
+        .iter()
+        .fold(Bloom::zero(), |bloom, r| bloom | r.bloom);
+
+    let body = BlockBodyWithSenders {
+        transactions: transactions
+            .iter()
+            .map(|(transaction, sender)| MessageWithSender {
+                message: transaction.message.clone(),
+                sender: *sender,
+            })
+            .collect(),
+        ommers: vec![],
+        withdrawals: attributes.withdrawals.clone(),
+    };
+
+    let mut buffer = Buffer::new(tx, BlockNumber(0), None);
+    ExecutionProcessor::new(
+        &mut buffer,
+        None,
+        &mut analysis_cache,
+        &mut *engine,
+        &header,
+        &body,
+        &block_spec,
+    )
+    .execute_and_write_block()
+    .await?;
+    buffer.write_to_db().await?;
+
+    promote_accounts(tx, parent_number).await?;
+    promote_storage(tx, parent_number).await?;
+    header.state_root = increment_intermediate_hashes(tx, etl_dir, parent_number, None).await?;
+
+    let block = Block::new(
+        header,
+        transactions
+            .into_iter()
+            .map(|(transaction, _)| transaction)
+            .collect(),
+        vec![],
+    );
+    Ok(match body.withdrawals {
+        Some(withdrawals) => block.with_withdrawals(withdrawals),
+        None => block,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        crypto::TrieEncode,
+        kv::new_mem_database,
+        rpc_compat::{synthetic_chain, synthetic_chain_spec},
+        state::genesis::initialize_genesis,
+    };
+    use bytes::Bytes;
+    use serde_json::json;
+
+    #[test]
+    fn serialize_status() {
+        assert_eq!(
+            serde_json::to_value(PayloadStatusV1::valid(H256::repeat_byte(0xab))).unwrap(),
+            json!({
+                "status": "VALID",
+                "latestValidHash": format!("0x{}", "ab".repeat(32)),
+                "validationError": null,
+            })
+        );
+        assert_eq!(
+            serde_json::to_value(
+                PayloadStatusV1::new(PayloadStatus::InvalidBlockHash)
+                    .with_validation_error(&format_err!("bad hash"))
+            )
+            .unwrap(),
+            json!({
+                "status": "INVALID_BLOCK_HASH",
+                "latestValidHash": null,
+                "validationError": "bad hash",
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn build_and_validate() {
+        let mut chain_spec = synthetic_chain_spec();
+        chain_spec.upgrades.london = Some(BlockNumber(0));
+        chain_spec.consensus.eip1559_block = Some(BlockNumber(0));
+        chain_spec.consensus.terminal_total_difficulty = Some(U256::ZERO);
+        // Transfers with nonces 0 and 1.
+        let transfers = synthetic_chain(&chain_spec, 2)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|block| block.transactions[0].trie_encode())
+            .collect::<Vec<_>>();
+
+        let db = new_mem_database().unwrap();
+        let etl_dir = TempDir::new().unwrap();
+        let tx = db.begin_mutable().await.unwrap();
+        initialize_genesis(&tx, &etl_dir, chain_spec).await.unwrap();
+        let parent_hash = chain::canonical_hash::read(&tx, BlockNumber(0))
+            .await
+            .unwrap()
+            .unwrap();
+        tx.commit().await.unwrap();
+
+        let attributes = PayloadAttributes {
+            timestamp: 12,
+            prev_randao: H256::repeat_byte(1),
+            suggested_fee_recipient: Address::repeat_byte(2),
+            withdrawals: None,
+        };
+        let template = BlockTemplate {
+            extra_data: Bytes::from_static(b"akula"),
+            transactions: vec![
+                // Nonce gap.
+                transfers[1].clone(),
+                transfers[0].clone(),
+                transfers[1].clone(),
+                // Replayed.
+                transfers[1].clone(),
+                Bytes::from_static(b"junk"),
+            ],
+        };
+        let tx = db.begin_mutable().await.unwrap();
+        let block = build_payload(&tx, &etl_dir, parent_hash, &attributes, template)
+            .await
+            .unwrap();
+        drop(tx);
+
+        assert_eq!(
+            block
+                .transactions
+                .iter()
+                .map(|txn| txn.nonce())
+                .collect::<Vec<_>>(),
+            vec![0, 1]
+        );
+        assert_eq!(block.header.gas_used, 42_000);
+        assert_eq!(block.header.beneficiary, Address::repeat_byte(2));
+        assert_eq!(block.header.extra_data, Bytes::from_static(b"akula"));
+
+        let tx = db.begin_mutable().await.unwrap();
+        assert_eq!(
+            validate_payload(&tx, &etl_dir, &block).await.unwrap(),
+            PayloadStatusV1::valid(block.header.hash())
+        );
+    }
+}
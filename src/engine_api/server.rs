@@ -0,0 +1,385 @@
+use super::{jwt::*, payload::*};
+use crate::{
+    accessors::chain, kv::traits::*, models::*, rpc::BlockTemplate, stagedsync::stages::FINISH,
+    util::serialize_u256_as_hexstr,
+};
+use anyhow::format_err;
+use async_trait::async_trait;
+use futures_util::future::{BoxFuture, FutureExt, Shared};
+use hyper::{
+    header::{AUTHORIZATION, CONTENT_TYPE},
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, StatusCode,
+};
+use jsonrpsee::{
+    core::{server::rpc_module::Methods, RpcResult},
+    proc_macros::rpc,
+    types::error::CallError,
+    RpcModule,
+};
+use lru::LruCache;
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::{
+    convert::Infallible,
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tempfile::TempDir;
+use tokio::sync::watch;
+use tracing::*;
+
+/// How long forkchoice update waits for staged sync to reach the new head before answering
+/// that it is syncing.
+pub const FORKCHOICE_TIMEOUT: Duration = Duration::from_secs(4);
+
+/// Built payloads kept for `engine_getPayload`.
+const MAX_PAYLOADS: usize = 16;
+
+#[derive(Debug)]
+pub enum EngineApiError {
+    UnknownPayload(H64),
+    InvalidParams(String),
+}
+
+impl EngineApiError {
+    fn code(&self) -> i32 {
+        match self {
+            Self::UnknownPayload(_) => -38001,
+            Self::InvalidParams(_) => -32602,
+        }
+    }
+}
+
+impl std::fmt::Display for EngineApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnknownPayload(id) => write!(f, "unknown payload {:?}", id),
+            Self::InvalidParams(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for EngineApiError {}
+
+impl From<EngineApiError> for jsonrpsee::core::Error {
+    fn from(e: EngineApiError) -> Self {
+        Self::Call(CallError::Custom {
+            code: e.code(),
+            message: e.to_string(),
+            data: None,
+        })
+    }
+}
+
+/// V1 methods predate Shanghai and take no withdrawals.
+fn reject_withdrawals(withdrawals: &Option<Vec<Withdrawal>>) -> Result<(), EngineApiError> {
+    match withdrawals {
+        None => Ok(()),
+        Some(_) => Err(EngineApiError::InvalidParams(
+            "withdrawals are only accepted by V2 methods".to_string(),
+        )),
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ForkchoiceUpdatedResponse {
+    pub payload_status: PayloadStatusV1,
+    pub payload_id: Option<H64>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetPayloadV2Response {
+    pub execution_payload: ExecutionPayload,
+    /// Fees that the fee recipient gets from the payload.
+    #[serde(serialize_with = "serialize_u256_as_hexstr")]
+    pub block_value: U256,
+}
+
+#[rpc(server, namespace = "engine")]
+pub trait EngineApi {
+    #[method(name = "newPayloadV1")]
+    async fn new_payload_v1(&self, payload: ExecutionPayload) -> RpcResult<PayloadStatusV1>;
+    #[method(name = "newPayloadV2")]
+    async fn new_payload_v2(&self, payload: ExecutionPayload) -> RpcResult<PayloadStatusV1>;
+    #[method(name = "forkchoiceUpdatedV1")]
+    async fn forkchoice_updated_v1(
+        &self,
+        state: ForkchoiceState,
+        attributes: Option<PayloadAttributes>,
+    ) -> RpcResult<ForkchoiceUpdatedResponse>;
+    #[method(name = "forkchoiceUpdatedV2")]
+    async fn forkchoice_updated_v2(
+        &self,
+        state: ForkchoiceState,
+        attributes: Option<PayloadAttributes>,
+    ) -> RpcResult<ForkchoiceUpdatedResponse>;
+    #[method(name = "getPayloadV1")]
+    async fn get_payload_v1(&self, payload_id: H64) -> RpcResult<ExecutionPayload>;
+    #[method(name = "getPayloadV2")]
+    async fn get_payload_v2(&self, payload_id: H64) -> RpcResult<GetPayloadV2Response>;
+}
+
+type PayloadJob = Shared<BoxFuture<'static, Result<ExecutionPayload, String>>>;
+
+/// Engine API of a node whose staged sync follows the forkchoice head sent to `head`, see
+/// [`ForkchoiceHeaders`](crate::stages::ForkchoiceHeaders).
+pub struct EngineApiServerImpl<DB>
+where
+    DB: MutableKV,
+{
+    db: Arc<DB>,
+    etl_dir: Arc<TempDir>,
+    head: watch::Sender<Option<(BlockNumber, H256)>>,
+    payloads: Mutex<LruCache<H64, PayloadJob>>,
+    template_source: Option<Arc<BlockTemplateSource>>,
+}
+
+impl<DB> EngineApiServerImpl<DB>
+where
+    DB: MutableKV,
+{
+    pub fn new(
+        db: Arc<DB>,
+        etl_dir: Arc<TempDir>,
+        head: watch::Sender<Option<(BlockNumber, H256)>>,
+    ) -> Self {
+        Self {
+            db,
+            etl_dir,
+            head,
+            payloads: Mutex::new(LruCache::new(MAX_PAYLOADS)),
+            template_source: None,
+        }
+    }
+
+    /// Fill built payloads with transactions of `source`, instead of building them empty.
+    pub fn with_template_source(mut self, source: BlockTemplateSource) -> Self {
+        self.template_source = Some(Arc::new(source));
+        self
+    }
+
+    async fn new_payload(&self, payload: ExecutionPayload) -> anyhow::Result<PayloadStatusV1> {
+        let number = payload.block_number;
+        let hash = payload.block_hash;
+
+        let status = new_payload(&*self.db, &self.etl_dir, payload).await?;
+
+        debug!("newPayload {}/{:?}: {:?}", number, hash, status.status);
+        Ok(status)
+    }
+
+    /// Whether staged sync has made `hash` canonical and got through all stages with it.
+    async fn is_synced_to(&self, number: BlockNumber, hash: H256) -> anyhow::Result<bool> {
+        let txn = self.db.begin().await?;
+        Ok(
+            chain::canonical_hash::read(&txn, number).await? == Some(hash)
+                && FINISH.get_progress(&txn).await? == Some(number),
+        )
+    }
+
+    async fn forkchoice_updated(
+        &self,
+        state: ForkchoiceState,
+        attributes: Option<PayloadAttributes>,
+    ) -> anyhow::Result<ForkchoiceUpdatedResponse> {
+        let head_hash = state.head_block_hash;
+        let syncing = ForkchoiceUpdatedResponse {
+            payload_status: PayloadStatusV1::new(PayloadStatus::Syncing),
+            payload_id: None,
+        };
+
+        let head_number =
+            match chain::header_number::read(&self.db.begin().await?, head_hash).await? {
+                Some(number) => number,
+                None => return Ok(syncing),
+            };
+        let _ = self.head.send(Some((head_number, head_hash)));
+
+        let deadline = Instant::now() + FORKCHOICE_TIMEOUT;
+        while !self.is_synced_to(head_number, head_hash).await? {
+            if Instant::now() >= deadline {
+                debug!(
+                    "forkchoiceUpdated {}/{:?}: still syncing",
+                    head_number, head_hash
+                );
+                return Ok(syncing);
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+
+        let payload_id = attributes.map(|attributes| self.start_payload(head_hash, attributes));
+        Ok(ForkchoiceUpdatedResponse {
+            payload_status: PayloadStatusV1::valid(head_hash),
+            payload_id,
+        })
+    }
+
+    /// Start building a payload on top of `parent_hash` in the background.
+    fn start_payload(&self, parent_hash: H256, attributes: PayloadAttributes) -> H64 {
+        let db = self.db.clone();
+        let etl_dir = self.etl_dir.clone();
+        let template_source = self.template_source.clone();
+        let job = async move {
+            // Fetched before the write transaction is opened, to not hold it over a request.
+            let template = match template_source {
+                Some(source) => source.fetch(&*db, parent_hash).await.unwrap_or_else(|e| {
+                    warn!(
+                        "Failed to fetch block template, building empty payload: {:#}",
+                        e
+                    );
+                    BlockTemplate::default()
+                }),
+                None => BlockTemplate::default(),
+            };
+
+            let txn = db.begin_mutable().await?;
+            let block = build_payload(&txn, &etl_dir, parent_hash, &attributes, template).await?;
+            // Block state is thrown away along with the transaction.
+            drop(txn);
+            Ok::<_, anyhow::Error>(ExecutionPayload::from_block(&block))
+        }
+        .map(|res| res.map_err(|e| format!("{:#}", e)))
+        .boxed()
+        .shared();
+        tokio::spawn(job.clone());
+
+        let payload_id = H64(rand::random());
+        self.payloads.lock().put(payload_id, job);
+        payload_id
+    }
+
+    async fn get_payload(&self, payload_id: H64) -> RpcResult<ExecutionPayload> {
+        let job = self
+            .payloads
+            .lock()
+            .get(&payload_id)
+            .cloned()
+            .ok_or(EngineApiError::UnknownPayload(payload_id))?;
+        Ok(job
+            .await
+            .map_err(|e| format_err!("failed to build payload: {}", e))?)
+    }
+}
+
+#[async_trait]
+impl<DB> EngineApiServer for EngineApiServerImpl<DB>
+where
+    DB: MutableKV,
+{
+    async fn new_payload_v1(&self, payload: ExecutionPayload) -> RpcResult<PayloadStatusV1> {
+        reject_withdrawals(&payload.withdrawals)?;
+        Ok(self.new_payload(payload).await?)
+    }
+
+    async fn new_payload_v2(&self, payload: ExecutionPayload) -> RpcResult<PayloadStatusV1> {
+        Ok(self.new_payload(payload).await?)
+    }
+
+    async fn forkchoice_updated_v1(
+        &self,
+        state: ForkchoiceState,
+        attributes: Option<PayloadAttributes>,
+    ) -> RpcResult<ForkchoiceUpdatedResponse> {
+        if let Some(attributes) = &attributes {
+            reject_withdrawals(&attributes.withdrawals)?;
+        }
+        Ok(self.forkchoice_updated(state, attributes).await?)
+    }
+
+    async fn forkchoice_updated_v2(
+        &self,
+        state: ForkchoiceState,
+        attributes: Option<PayloadAttributes>,
+    ) -> RpcResult<ForkchoiceUpdatedResponse> {
+        Ok(self.forkchoice_updated(state, attributes).await?)
+    }
+
+    async fn get_payload_v1(&self, payload_id: H64) -> RpcResult<ExecutionPayload> {
+        self.get_payload(payload_id).await
+    }
+
+    async fn get_payload_v2(&self, payload_id: H64) -> RpcResult<GetPayloadV2Response> {
+        Ok(GetPayloadV2Response {
+            execution_payload: self.get_payload(payload_id).await?,
+            // Built payloads have no transactions, so no fees either.
+            block_value: U256::ZERO,
+        })
+    }
+}
+
+fn response(status: StatusCode, body: impl Into<Body>) -> Response<Body> {
+    let mut res = Response::new(body.into());
+    *res.status_mut() = status;
+    res
+}
+
+async fn handle(
+    methods: Methods,
+    secret: Arc<JwtSecret>,
+    req: Request<Body>,
+) -> Result<Response<Body>, Infallible> {
+    let token = req
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+    match token.map(|token| secret.validate(token, unix_now())) {
+        Some(Ok(())) => {}
+        Some(Err(e)) => {
+            debug!("Rejecting Engine API request: {}", e);
+            return Ok(response(StatusCode::UNAUTHORIZED, e.to_string()));
+        }
+        None => return Ok(response(StatusCode::UNAUTHORIZED, "missing JWT")),
+    }
+
+    if req.method() != Method::POST {
+        return Ok(response(StatusCode::METHOD_NOT_ALLOWED, ""));
+    }
+    let body = match hyper::body::to_bytes(req.into_body()).await {
+        Ok(body) => body,
+        Err(e) => return Ok(response(StatusCode::BAD_REQUEST, e.to_string())),
+    };
+    let call = match std::str::from_utf8(&body) {
+        Ok(call) => call,
+        Err(e) => return Ok(response(StatusCode::BAD_REQUEST, e.to_string())),
+    };
+
+    Ok(match methods.raw_json_request(call).await {
+        Ok((res, _)) => {
+            let mut res = response(StatusCode::OK, res);
+            res.headers_mut()
+                .insert(CONTENT_TYPE, "application/json".parse().unwrap());
+            res
+        }
+        Err(e) => response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+    })
+}
+
+/// Serve Engine API on `addr` to the consensus client that signs its requests with `secret`.
+pub async fn serve<Context: Send + Sync + 'static>(
+    addr: SocketAddr,
+    secret: JwtSecret,
+    module: RpcModule<Context>,
+) -> anyhow::Result<()> {
+    let methods = Methods::from(module);
+    let secret = Arc::new(secret);
+    let make_service = make_service_fn(move |_| {
+        let methods = methods.clone();
+        let secret = secret.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req| {
+                handle(methods.clone(), secret.clone(), req)
+            }))
+        }
+    });
+
+    let server = hyper::Server::try_bind(&addr)?.serve(make_service);
+    info!("Engine API listening on {}", server.local_addr());
+    server.await?;
+
+    Ok(())
+}
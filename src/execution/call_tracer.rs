@@ -0,0 +1,387 @@
+//! Call tree of a message, in the format of geth's `callTracer`.
+//!
+//! On top of what geth reports, every frame is annotated with the 4-byte selector of its input
+//! and the function signature if the selector is a well-known one, and standard Solidity revert
+//! payloads are decoded into a readable reason.
+
+use super::{analysis_cache::AnalysisCache, evm, signatures, tracer::*};
+#[cfg(feature = "node")]
+use crate::{accessors::chain, kv::traits::*, state::Buffer};
+use crate::{hexbytes, models::*, state::IntraBlockState, State};
+use anyhow::format_err;
+use bytes::Bytes;
+use ethereum_types::H32;
+use evmodin::StatusCode;
+use serde::Serialize;
+
+/// `Error(string)`
+pub const ERROR_SELECTOR: [u8; 4] = [0x08, 0xc3, 0x79, 0xa0];
+/// `Panic(uint256)`
+pub const PANIC_SELECTOR: [u8; 4] = [0x4e, 0x48, 0x7b, 0x71];
+
+/// Solidity panic codes, see <https://docs.soliditylang.org/en/latest/control-structures.html#panic-via-assert-and-error-via-require>.
+fn panic_description(code: U256) -> Option<&'static str> {
+    Some(match u8::try_from(code).ok()? {
+        0x00 => "generic compiler panic",
+        0x01 => "assertion failed",
+        0x11 => "arithmetic underflow or overflow",
+        0x12 => "division or modulo by zero",
+        0x21 => "invalid enum value",
+        0x22 => "invalid storage byte array encoding",
+        0x31 => "pop on empty array",
+        0x32 => "array index out of bounds",
+        0x41 => "out of memory",
+        0x51 => "call to zero-initialized function",
+        _ => return None,
+    })
+}
+
+/// Decode `Error(string)` or `Panic(uint256)` revert payload.
+pub fn decode_revert_reason(output: &[u8]) -> Option<String> {
+    if output.len() < 4 {
+        return None;
+    }
+    let (selector, data) = output.split_at(4);
+
+    let word = |index: usize| -> Option<U256> {
+        data.get(index * 32..(index + 1) * 32)
+            .map(|w| U256::from_be_bytes(w.try_into().unwrap()))
+    };
+
+    if selector == ERROR_SELECTOR {
+        let offset = usize::try_from(word(0)?).ok()?;
+        let len_start = offset.checked_add(32)?;
+        let len = usize::try_from(U256::from_be_bytes(
+            data.get(offset..len_start)?.try_into().unwrap(),
+        ))
+        .ok()?;
+        let reason = data.get(len_start..len_start.checked_add(len)?)?;
+
+        return Some(String::from_utf8_lossy(reason).into_owned());
+    }
+
+    if selector == PANIC_SELECTOR {
+        let code = word(0)?;
+        return Some(match panic_description(code) {
+            Some(description) => format!("panic: {} (0x{:02x})", description, code.as_u8()),
+            None => format!("panic: unknown code {}", code),
+        });
+    }
+
+    None
+}
+
+/// Error of a message that ended with `REVERT`, keeping its output for callers
+/// that need to return revert data.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Reverted {
+    pub output: Bytes,
+}
+
+impl std::fmt::Display for Reverted {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match decode_revert_reason(&self.output) {
+            Some(reason) => write!(f, "execution reverted: {}", reason),
+            None => write!(f, "execution reverted"),
+        }
+    }
+}
+
+impl std::error::Error for Reverted {}
+
+fn error_message(status_code: &StatusCode) -> String {
+    match status_code {
+        StatusCode::Revert => "execution reverted".to_string(),
+        other => format!("{:?}", other),
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CallFrame {
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub from: Address,
+    pub to: Address,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<U256>,
+    pub gas: U64,
+    pub gas_used: U64,
+    #[serde(with = "hexbytes")]
+    pub input: Bytes,
+    /// First 4 bytes of input, the function selector if the callee follows Solidity ABI.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub selector: Option<H32>,
+    /// Signature of the called function, if its selector is a well-known one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signature: Option<&'static str>,
+    #[serde(with = "hexbytes", skip_serializing_if = "Bytes::is_empty")]
+    pub output: Bytes,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Decoded `Error(string)` or `Panic(uint256)` revert payload.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub revert_reason: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub calls: Vec<CallFrame>,
+}
+
+/// Builds the [`CallFrame`] tree of traced messages.
+#[derive(Debug, Default)]
+pub struct CallFrameTracer {
+    stack: Vec<CallFrame>,
+    roots: Vec<CallFrame>,
+}
+
+impl CallFrameTracer {
+    /// Root frame of the last message, if any has been executed.
+    pub fn into_root(mut self) -> Option<CallFrame> {
+        self.roots.pop()
+    }
+
+    /// Root frames of all executed messages, e.g. of every transaction in block, in execution order.
+    pub fn into_roots(self) -> Vec<CallFrame> {
+        self.roots
+    }
+}
+
+impl Tracer for CallFrameTracer {
+    fn capture_start(
+        &mut self,
+        _: u16,
+        from: Address,
+        to: Address,
+        call_type: MessageKind,
+        input: Bytes,
+        gas: u64,
+        value: U256,
+    ) {
+        let (kind, value) = match call_type {
+            MessageKind::Create => ("CREATE", Some(value)),
+            MessageKind::Call { call_kind, .. } => match call_kind {
+                CallKind::Call => ("CALL", Some(value)),
+                CallKind::CallCode => ("CALLCODE", Some(value)),
+                CallKind::DelegateCall => ("DELEGATECALL", None),
+                CallKind::StaticCall => ("STATICCALL", None),
+            },
+        };
+        let selector = if kind != "CREATE" && input.len() >= 4 {
+            Some(H32::from_slice(&input[..4]))
+        } else {
+            None
+        };
+
+        self.stack.push(CallFrame {
+            kind,
+            from,
+            to,
+            value,
+            gas: gas.into(),
+            gas_used: U64::zero(),
+            input,
+            selector,
+            signature: selector.and_then(signatures::lookup),
+            output: Bytes::new(),
+            error: None,
+            revert_reason: None,
+            calls: vec![],
+        });
+    }
+
+    fn capture_end(&mut self, _: u16, output: Bytes, gas_left: u64, err: StatusCode) {
+        let mut frame = match self.stack.pop() {
+            Some(frame) => frame,
+            None => return,
+        };
+
+        frame.gas_used = frame.gas.saturating_sub(gas_left.into());
+        if err != StatusCode::Success {
+            frame.error = Some(error_message(&err));
+        }
+        if err == StatusCode::Revert {
+            frame.revert_reason = decode_revert_reason(&output);
+        }
+        frame.output = output;
+
+        match self.stack.last_mut() {
+            Some(parent) => parent.calls.push(frame),
+            None => self.roots.push(frame),
+        }
+    }
+}
+
+/// Execute `txn` and return its call tree. State changes are not written.
+pub async fn trace<S: State>(
+    state: &mut IntraBlockState<'_, S>,
+    header: &PartialHeader,
+    block_spec: &BlockExecutionSpec,
+    txn: &MessageWithSender,
+) -> anyhow::Result<CallFrame> {
+    state.access_account(txn.sender);
+    if let TransactionAction::Call(to) = txn.action() {
+        state.access_account(to);
+    }
+
+    let mut tracer = CallFrameTracer::default();
+    evm::execute(
+        state,
+        Some(&mut tracer),
+        &mut AnalysisCache::default(),
+        header,
+        block_spec,
+        txn,
+        txn.gas_limit(),
+    )
+    .await?;
+
+    tracer
+        .into_root()
+        .ok_or_else(|| format_err!("message was not executed"))
+}
+
+/// Run [`trace`] on top of the state after canonical block `block_number`.
+#[cfg(feature = "node")]
+pub async fn trace_call<'db, Tx: Transaction<'db>>(
+    tx: &Tx,
+    block_number: BlockNumber,
+    txn: &MessageWithSender,
+) -> anyhow::Result<CallFrame> {
+    let chain_spec = chain::chain_spec::read(tx).await?;
+
+    let header = chain::header::read_canonical(tx, block_number).await?;
+
+    let mut buffer = Buffer::new(tx, BlockNumber(0), Some(block_number));
+    let mut state = IntraBlockState::new(&mut buffer);
+
+    trace(
+        &mut state,
+        &PartialHeader::from(header),
+        &chain_spec.collect_block_spec(block_number),
+        txn,
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{res::chainspec::MAINNET, util::test_util::run_test, InMemoryState};
+    use hex_literal::hex;
+
+    fn error_payload(reason: &str) -> Vec<u8> {
+        let mut payload = ERROR_SELECTOR.to_vec();
+        payload.extend_from_slice(&0x20.as_u256().to_be_bytes());
+        payload.extend_from_slice(&(reason.len() as u64).as_u256().to_be_bytes());
+        payload.extend_from_slice(reason.as_bytes());
+        payload.resize(4 + 32 * 3, 0);
+        payload
+    }
+
+    #[test]
+    fn decodes_revert_reasons() {
+        assert_eq!(
+            decode_revert_reason(&error_payload("insufficient allowance")),
+            Some("insufficient allowance".to_string())
+        );
+
+        let mut panic = PANIC_SELECTOR.to_vec();
+        panic.extend_from_slice(&0x11.as_u256().to_be_bytes());
+        assert_eq!(
+            decode_revert_reason(&panic),
+            Some("panic: arithmetic underflow or overflow (0x11)".to_string())
+        );
+
+        let mut panic = PANIC_SELECTOR.to_vec();
+        panic.extend_from_slice(&0x99.as_u256().to_be_bytes());
+        assert_eq!(
+            decode_revert_reason(&panic),
+            Some("panic: unknown code 153".to_string())
+        );
+
+        // Custom error
+        assert_eq!(decode_revert_reason(&hex!("fb8f41b2")), None);
+        // Truncated
+        assert_eq!(decode_revert_reason(&error_payload("reason")[..40]), None);
+        assert_eq!(decode_revert_reason(&[]), None);
+    }
+
+    #[test]
+    fn traces_nested_revert() {
+        run_test(async {
+            let header = PartialHeader {
+                number: 13_000_000.into(),
+                ..PartialHeader::empty()
+            };
+            let block_spec = MAINNET.collect_block_spec(header.number);
+
+            let caller = hex!("5ff137d4b0fdcd49dca30c7cf57e578a026d2789").into();
+            let callee: Address = hex!("0a6bb546b9208cfab9e8fa2b9b2c042b18df7030").into();
+            let sender = hex!("b685342b8c54347aad148e1f22eff3eb3eb29391").into();
+
+            let mut db = InMemoryState::default();
+            let mut state = IntraBlockState::new(&mut db);
+
+            // 0      PUSH1  => 00  // retSize
+            // 2      PUSH1  => 00  // retOffset
+            // 4      PUSH1  => 04  // argsSize
+            // 6      PUSH1  => 00  // argsOffset
+            // 8      PUSH1  => 00  // value
+            // 10     PUSH20 => callee
+            // 31     GAS
+            // 32     CALL
+            // 33     STOP
+            let mut code = hex!("60006000600460006000").to_vec();
+            code.push(0x73);
+            code.extend_from_slice(callee.as_bytes());
+            code.extend_from_slice(&hex!("5af100"));
+            state.set_code(caller, code.into()).await.unwrap();
+
+            // 0      PUSH32 => 4e487b71 << 224  // Panic(uint256)
+            // 33     PUSH1  => 00
+            // 35     MSTORE
+            // 36     PUSH1  => 01               // assertion failed
+            // 38     PUSH1  => 04
+            // 40     MSTORE
+            // 41     PUSH1  => 24
+            // 43     PUSH1  => 00
+            // 45     REVERT
+            let mut code = vec![0x7f];
+            code.extend_from_slice(&PANIC_SELECTOR);
+            code.extend_from_slice(&[0; 28]);
+            code.extend_from_slice(&hex!("600052600160045260246000fd"));
+            state.set_code(callee, code.into()).await.unwrap();
+
+            let txn = MessageWithSender {
+                message: Message::Legacy {
+                    chain_id: Some(block_spec.params.chain_id),
+                    nonce: 0,
+                    gas_price: U256::ZERO,
+                    gas_limit: 100_000,
+                    action: TransactionAction::Call(caller),
+                    value: U256::ZERO,
+                    input: hex!("a9059cbb").to_vec().into(),
+                },
+                sender,
+            };
+
+            let root = trace(&mut state, &header, &block_spec, &txn).await.unwrap();
+
+            assert_eq!(root.kind, "CALL");
+            assert_eq!(root.to, caller);
+            assert_eq!(root.selector, Some(H32(hex!("a9059cbb"))));
+            assert_eq!(root.signature, Some("transfer(address,uint256)"));
+            assert_eq!(root.error, None);
+
+            assert_eq!(root.calls.len(), 1);
+            let inner = &root.calls[0];
+            assert_eq!(inner.from, caller);
+            assert_eq!(inner.to, callee);
+            assert_eq!(inner.error.as_deref(), Some("execution reverted"));
+            assert_eq!(
+                inner.revert_reason.as_deref(),
+                Some("panic: assertion failed (0x01)")
+            );
+            assert!(inner.gas_used > U64::zero());
+        })
+    }
+}
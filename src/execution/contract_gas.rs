@@ -0,0 +1,123 @@
+use super::tracer::{MessageKind, Tracer};
+use crate::models::*;
+use bytes::Bytes;
+use evmodin::StatusCode;
+use std::collections::HashMap;
+
+#[derive(Debug)]
+struct Frame {
+    address: Address,
+    gas: u64,
+    children_gas_used: u64,
+}
+
+/// Accounts gas used by every callee, excluding gas used by its own subcalls,
+/// so that gas of all callees adds up to the gas used by executed messages.
+///
+/// Intrinsic gas of transactions is not attributed to any callee.
+#[derive(Debug, Default)]
+pub struct ContractGasTracer {
+    stack: Vec<Frame>,
+    gas_used: HashMap<Address, u64>,
+}
+
+impl Tracer for ContractGasTracer {
+    fn capture_start(
+        &mut self,
+        _: u16,
+        _: Address,
+        to: Address,
+        _: MessageKind,
+        _: Bytes,
+        gas: u64,
+        _: U256,
+    ) {
+        self.stack.push(Frame {
+            address: to,
+            gas,
+            children_gas_used: 0,
+        });
+    }
+
+    fn capture_end(&mut self, _: u16, _: Bytes, gas_left: u64, _: StatusCode) {
+        if let Some(frame) = self.stack.pop() {
+            let gas_used = frame.gas.saturating_sub(gas_left);
+            *self.gas_used.entry(frame.address).or_default() +=
+                gas_used.saturating_sub(frame.children_gas_used);
+            if let Some(parent) = self.stack.last_mut() {
+                parent.children_gas_used += gas_used;
+            }
+        }
+    }
+}
+
+impl ContractGasTracer {
+    /// Gas used by every callee since the tracer was created.
+    pub fn gas_used(&self) -> &HashMap<Address, u64> {
+        &self.gas_used
+    }
+
+    /// `n` callees that used the most gas, in descending order of gas used.
+    pub fn top(&self, n: usize) -> Vec<(Address, u64)> {
+        top_gas_consumers(self.gas_used.iter().map(|(&a, &g)| (a, g)), n)
+    }
+}
+
+/// `n` entries with the most gas, in descending order of gas, ties broken by address.
+pub fn top_gas_consumers(
+    gas_used: impl IntoIterator<Item = (Address, u64)>,
+    n: usize,
+) -> Vec<(Address, u64)> {
+    let mut gas_used = gas_used.into_iter().collect::<Vec<_>>();
+    gas_used.sort_unstable_by(|(a1, g1), (a2, g2)| g2.cmp(g1).then(a1.cmp(a2)));
+    gas_used.truncate(n);
+    gas_used
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn call(tracer: &mut ContractGasTracer, to: u8, gas: u64) {
+        tracer.capture_start(
+            0,
+            Address::zero(),
+            Address::repeat_byte(to),
+            MessageKind::Create,
+            Bytes::new(),
+            gas,
+            U256::ZERO,
+        );
+    }
+
+    fn end(tracer: &mut ContractGasTracer, gas_left: u64) {
+        tracer.capture_end(0, Bytes::new(), gas_left, StatusCode::Success);
+    }
+
+    #[test]
+    fn attributes_own_gas() {
+        let mut tracer = ContractGasTracer::default();
+
+        // 1 uses 10_000 itself and calls 2 and 3.
+        call(&mut tracer, 1, 100_000);
+        call(&mut tracer, 2, 50_000);
+        // 2 calls 3.
+        call(&mut tracer, 3, 20_000);
+        end(&mut tracer, 15_000);
+        end(&mut tracer, 30_000);
+        call(&mut tracer, 3, 10_000);
+        end(&mut tracer, 0);
+        end(&mut tracer, 60_000);
+
+        assert_eq!(
+            tracer.top(10),
+            vec![
+                (Address::repeat_byte(2), 15_000),
+                (Address::repeat_byte(3), 15_000),
+                (Address::repeat_byte(1), 10_000),
+            ]
+        );
+        assert_eq!(tracer.top(1), vec![(Address::repeat_byte(2), 15_000)]);
+        assert_eq!(tracer.gas_used().values().sum::<u64>(), 40_000);
+    }
+}
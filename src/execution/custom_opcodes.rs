@@ -0,0 +1,223 @@
+//! Opcodes defined by operators of private chains in addition to those of the current revision.
+//!
+//! The interpreter does not know them: before analysis, custom opcodes in code are replaced with
+//! `JUMPDEST`, and handlers are applied to the execution state when the interpreter stops on that
+//! instruction. As a consequence, positions of custom opcodes are valid jump destinations.
+//!
+//! Custom opcodes are not applied when an external VM executes code.
+
+use super::host::StateModifier;
+use crate::models::*;
+use anyhow::{bail, ensure, format_err};
+use educe::*;
+use evmodin::ExecutionState;
+use once_cell::sync::OnceCell;
+use std::{collections::BTreeMap, sync::Arc};
+
+/// Chains whose consensus would be broken by custom opcodes.
+const PUBLIC_CHAIN_IDS: &[u64] = &[
+    1,        // Mainnet
+    3,        // Ropsten
+    4,        // Rinkeby
+    5,        // Goerli
+    42,       // Kovan
+    11155111, // Sepolia
+];
+
+const JUMPDEST: u8 = 0x5b;
+const JUMPDEST_GAS: u64 = 1;
+
+static CUSTOM_OPCODES: OnceCell<CustomOpcodes> = OnceCell::new();
+
+/// Executes custom opcode. Returning `false` halts execution exceptionally, consuming all gas.
+pub type CustomOpcodeHandler = Arc<dyn Fn(&mut ExecutionState) -> bool + Send + Sync>;
+
+#[derive(Clone, Educe)]
+#[educe(Debug)]
+pub struct CustomOpcode {
+    /// Charged before the handler is called.
+    pub gas: u64,
+    #[educe(Debug(ignore))]
+    pub handler: CustomOpcodeHandler,
+}
+
+/// Custom opcodes of a private chain.
+#[derive(Debug)]
+pub struct CustomOpcodes {
+    chain_id: ChainId,
+    opcodes: BTreeMap<u8, CustomOpcode>,
+}
+
+/// Whether `opcode` is not assigned in any revision.
+fn is_unassigned(opcode: u8) -> bool {
+    matches!(
+        opcode,
+        0x0c..=0x0f
+            | 0x1e..=0x1f
+            | 0x21..=0x2f
+            | 0x49..=0x4f
+            | 0x5c..=0x5e
+            | 0xa5..=0xef
+            | 0xf6..=0xf9
+            | 0xfb..=0xfc
+    )
+}
+
+impl CustomOpcodes {
+    pub fn new(chain_spec: &ChainSpec) -> anyhow::Result<Self> {
+        let chain_id = chain_spec.params.chain_id;
+        if PUBLIC_CHAIN_IDS.contains(&chain_id.0) {
+            bail!(
+                "custom opcodes are not allowed on public chain {} ({})",
+                chain_spec.name,
+                chain_id.0
+            );
+        }
+
+        Ok(Self {
+            chain_id,
+            opcodes: BTreeMap::new(),
+        })
+    }
+
+    /// Register `handler` of `opcode` costing `gas`. Opcodes of known revisions cannot be
+    /// redefined.
+    pub fn register(
+        &mut self,
+        opcode: u8,
+        gas: u64,
+        handler: impl Fn(&mut ExecutionState) -> bool + Send + Sync + 'static,
+    ) -> anyhow::Result<()> {
+        ensure!(
+            is_unassigned(opcode),
+            "opcode 0x{:02x} is already assigned",
+            opcode
+        );
+        ensure!(
+            gas >= JUMPDEST_GAS,
+            "custom opcode must cost at least {} gas",
+            JUMPDEST_GAS
+        );
+        ensure!(
+            !self.opcodes.contains_key(&opcode),
+            "opcode 0x{:02x} is already registered",
+            opcode
+        );
+
+        self.opcodes.insert(
+            opcode,
+            CustomOpcode {
+                gas,
+                handler: Arc::new(handler),
+            },
+        );
+
+        Ok(())
+    }
+
+    pub fn get(&self, opcode: u8) -> Option<&CustomOpcode> {
+        self.opcodes.get(&opcode)
+    }
+
+    /// Code for the interpreter, if `code` contains custom opcodes.
+    pub fn rewrite(&self, code: &[u8]) -> Option<Vec<u8>> {
+        let mut rewritten = None;
+        let mut pc = 0;
+        while pc < code.len() {
+            let opcode = code[pc];
+            if self.opcodes.contains_key(&opcode) {
+                rewritten.get_or_insert_with(|| code.to_vec())[pc] = JUMPDEST;
+            }
+
+            pc += 1;
+            // Skip immediate data of PUSH1..PUSH32.
+            if (0x60..=0x7f).contains(&opcode) {
+                pc += usize::from(opcode - 0x5f);
+            }
+        }
+        rewritten
+    }
+
+    /// State modifier executing custom opcode, to be applied instead of `JUMPDEST` it was
+    /// replaced with.
+    pub(crate) fn modifier(&self, opcode: u8) -> StateModifier {
+        let CustomOpcode { gas, handler } = self.get(opcode)?.clone();
+        Some(Arc::new(move |state: &mut ExecutionState| {
+            // Interpreter charges `JUMPDEST` afterwards, and halts on negative gas.
+            let gas = (gas - JUMPDEST_GAS) as i64;
+            if state.gas_left < gas {
+                state.gas_left = -1;
+                return;
+            }
+            state.gas_left -= gas;
+
+            if !(handler)(state) {
+                state.gas_left = -1;
+            }
+        }))
+    }
+}
+
+/// Execute code of `opcodes.chain_id` with custom opcodes. May only be set once per process.
+pub fn set_custom_opcodes(opcodes: CustomOpcodes) -> anyhow::Result<()> {
+    CUSTOM_OPCODES
+        .set(opcodes)
+        .map_err(|_| format_err!("custom opcodes already set"))
+}
+
+/// Custom opcodes of chain `chain_id`, if any.
+pub(crate) fn custom_opcodes(chain_id: ChainId) -> Option<&'static CustomOpcodes> {
+    CUSTOM_OPCODES
+        .get()
+        .filter(|opcodes| opcodes.chain_id == chain_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::res::chainspec::MAINNET;
+
+    fn private_chain() -> ChainSpec {
+        let mut chain_spec = MAINNET.clone();
+        chain_spec.name = "Private".into();
+        chain_spec.params.chain_id = ChainId(1337);
+        chain_spec
+    }
+
+    #[test]
+    fn registration() {
+        assert!(CustomOpcodes::new(&MAINNET).is_err());
+
+        let mut opcodes = CustomOpcodes::new(&private_chain()).unwrap();
+        opcodes.register(0x0c, 10, |_| true).unwrap();
+
+        // Already registered.
+        assert!(opcodes.register(0x0c, 10, |_| true).is_err());
+        // ADD.
+        assert!(opcodes.register(0x01, 10, |_| true).is_err());
+        // PUSH0.
+        assert!(opcodes.register(0x5f, 10, |_| true).is_err());
+        // Cheaper than JUMPDEST.
+        assert!(opcodes.register(0x0d, 0, |_| true).is_err());
+
+        assert_eq!(opcodes.get(0x0c).unwrap().gas, 10);
+        assert!(opcodes.get(0x0d).is_none());
+    }
+
+    #[test]
+    fn rewrite() {
+        let mut opcodes = CustomOpcodes::new(&private_chain()).unwrap();
+        opcodes.register(0x0c, 10, |_| true).unwrap();
+
+        assert_eq!(opcodes.rewrite(&[0x60, 0x00, 0x00]), None);
+
+        // 0      PUSH1  => 0c
+        // 2      CUSTOM
+        // 3      PUSH2  => 0c0c
+        // 6      CUSTOM
+        assert_eq!(
+            opcodes.rewrite(&[0x60, 0x0c, 0x0c, 0x61, 0x0c, 0x0c, 0x0c]),
+            Some(vec![0x60, 0x0c, 0x5b, 0x61, 0x0c, 0x0c, 0x5b])
+        );
+    }
+}
@@ -0,0 +1,318 @@
+//! Instruction-level trace in the format of [EIP-3155](https://eips.ethereum.org/EIPS/eip-3155),
+//! one JSON object per line, comparable between clients.
+//!
+//! Gas cost of an instruction is known only once the next instruction of the same frame starts,
+//! so lines of a transaction are written when its top-level message ends. The refund counter
+//! is not visible to tracers and is left out.
+
+use super::{evm::CallResult, struct_logger::StructLoggerConfig, tracer::*};
+use crate::{hexbytes, models::*};
+use bytes::Bytes;
+use evmodin::{ExecutionState, OpCode, StatusCode};
+use serde::Serialize;
+use std::io::{self, Write};
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Eip3155Step {
+    pub pc: u64,
+    pub op: u8,
+    pub gas: U64,
+    pub gas_cost: U64,
+    /// Whole memory in hex.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memory: Option<String>,
+    pub mem_size: u64,
+    /// Bottom to top.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stack: Option<Vec<U256>>,
+    #[serde(with = "hexbytes")]
+    pub return_data: Bytes,
+    /// Starts from 1 for the top-level message.
+    pub depth: u16,
+    pub op_name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Line following the steps of a transaction.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Eip3155Summary {
+    #[serde(with = "hexbytes")]
+    pub output: Bytes,
+    pub gas_used: U64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Eip3155Summary {
+    /// Summary of the transaction that used `gas_used` gas and ended with `result`.
+    pub fn new(gas_used: u64, result: &CallResult) -> Self {
+        Self {
+            output: result.output_data.clone(),
+            gas_used: gas_used.into(),
+            error: match result.status_code {
+                StatusCode::Success => None,
+                ref other => Some(format!("{:?}", other)),
+            },
+        }
+    }
+}
+
+/// Writes steps of traced transactions to `W`, honoring `disableStack`, `enableMemory` and
+/// `enableReturnData` of the struct logger options.
+#[derive(Debug)]
+pub struct Eip3155Tracer<W> {
+    config: StructLoggerConfig,
+    writer: W,
+    steps: Vec<Eip3155Step>,
+    /// Index of the last step of every message being executed, outermost first.
+    frames: Vec<Option<usize>>,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> Eip3155Tracer<W> {
+    pub fn new(config: StructLoggerConfig, writer: W) -> Self {
+        Self {
+            config,
+            writer,
+            steps: vec![],
+            frames: vec![],
+            error: None,
+        }
+    }
+
+    fn set_gas_cost(&mut self, index: usize, gas_left: u64) {
+        let step = &mut self.steps[index];
+        step.gas_cost = step.gas.as_u64().saturating_sub(gas_left).into();
+    }
+
+    fn write_line(&mut self, value: &impl Serialize) {
+        if self.error.is_some() {
+            return;
+        }
+
+        let res = serde_json::to_writer(&mut self.writer, value)
+            .map_err(io::Error::from)
+            .and_then(|_| self.writer.write_all(b"\n"));
+        if let Err(e) = res {
+            self.error = Some(e);
+        }
+    }
+
+    /// Write summary of the traced transaction that used `gas_used` gas and ended with
+    /// `result`, and return the writer.
+    pub fn finish(mut self, gas_used: u64, result: &CallResult) -> io::Result<W> {
+        self.write_line(&Eip3155Summary::new(gas_used, result));
+        if let Some(e) = self.error {
+            return Err(e);
+        }
+        self.writer.flush()?;
+
+        Ok(self.writer)
+    }
+}
+
+impl<W: Write + Send + 'static> Tracer for Eip3155Tracer<W> {
+    fn trace_instructions(&self) -> bool {
+        true
+    }
+
+    fn capture_start(
+        &mut self,
+        _: u16,
+        _: Address,
+        _: Address,
+        _: MessageKind,
+        _: Bytes,
+        _: u64,
+        _: U256,
+    ) {
+        self.frames.push(None);
+    }
+
+    fn capture_state(
+        &mut self,
+        env: &ExecutionState,
+        pc: u64,
+        op: OpCode,
+        _: u64,
+        return_data: Bytes,
+        depth: u16,
+        _: StatusCode,
+    ) {
+        let gas = env.gas_left.max(0) as u64;
+        let last_step = match self.frames.last_mut() {
+            Some(frame) => frame.replace(self.steps.len()),
+            None => return,
+        };
+        if let Some(index) = last_step {
+            self.set_gas_cost(index, gas);
+        }
+
+        self.steps.push(Eip3155Step {
+            pc,
+            op: op.0,
+            gas: gas.into(),
+            gas_cost: U64::zero(),
+            memory: if self.config.enable_memory {
+                Some(format!("0x{}", hex::encode(&env.memory[..])))
+            } else {
+                None
+            },
+            mem_size: env.memory.len() as u64,
+            stack: if self.config.disable_stack {
+                None
+            } else {
+                Some(
+                    (0..env.stack.len())
+                        .rev()
+                        .map(|i| *env.stack.get(i))
+                        .collect(),
+                )
+            },
+            return_data: if self.config.enable_return_data {
+                return_data
+            } else {
+                Bytes::new()
+            },
+            depth: depth + 1,
+            op_name: op.to_string(),
+            error: None,
+        });
+    }
+
+    fn capture_end(&mut self, _: u16, _: Bytes, gas_left: u64, err: StatusCode) {
+        let last_step = match self.frames.pop() {
+            Some(last_step) => last_step,
+            None => return,
+        };
+        if let Some(index) = last_step {
+            self.set_gas_cost(index, gas_left);
+            if err != StatusCode::Success && err != StatusCode::Revert {
+                self.steps[index].error = Some(format!("{:?}", err));
+            }
+        }
+
+        if self.frames.is_empty() {
+            for step in std::mem::take(&mut self.steps) {
+                self.write_line(&step);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        execution::{analysis_cache::AnalysisCache, evm},
+        res::chainspec::MAINNET,
+        state::IntraBlockState,
+        util::test_util::run_test,
+        InMemoryState,
+    };
+    use hex_literal::hex;
+    use serde_json::{json, Value};
+
+    #[test]
+    fn writes_json_lines() {
+        run_test(async {
+            let header = PartialHeader {
+                number: 13_000_000.into(),
+                ..PartialHeader::empty()
+            };
+            let block_spec = MAINNET.collect_block_spec(header.number);
+
+            let contract = hex!("0a6bb546b9208cfab9e8fa2b9b2c042b18df7030").into();
+            let sender = hex!("b685342b8c54347aad148e1f22eff3eb3eb29391").into();
+
+            let mut db = InMemoryState::default();
+            let mut state = IntraBlockState::new(&mut db);
+
+            // 0      PUSH1  => 2a
+            // 2      PUSH1  => 00
+            // 4      MSTORE
+            // 5      PUSH1  => 20
+            // 7      PUSH1  => 00
+            // 9      RETURN
+            state
+                .set_code(contract, hex!("602a60005260206000f3").to_vec().into())
+                .await
+                .unwrap();
+
+            let txn = MessageWithSender {
+                message: Message::Legacy {
+                    chain_id: Some(block_spec.params.chain_id),
+                    nonce: 0,
+                    gas_price: U256::ZERO,
+                    gas_limit: 100_000,
+                    action: TransactionAction::Call(contract),
+                    value: U256::ZERO,
+                    input: Bytes::new(),
+                },
+                sender,
+            };
+
+            let mut tracer = Eip3155Tracer::new(
+                StructLoggerConfig {
+                    enable_memory: true,
+                    ..Default::default()
+                },
+                vec![],
+            );
+            let res = evm::execute(
+                &mut state,
+                Some(&mut tracer),
+                &mut AnalysisCache::default(),
+                &header,
+                &block_spec,
+                &txn,
+                100_000,
+            )
+            .await
+            .unwrap();
+            assert_eq!(res.status_code, StatusCode::Success);
+
+            let out = tracer.finish(21_018, &res).unwrap();
+            let lines = String::from_utf8(out)
+                .unwrap()
+                .lines()
+                .map(|line| serde_json::from_str::<Value>(line).unwrap())
+                .collect::<Vec<_>>();
+            assert_eq!(lines.len(), 7);
+
+            assert_eq!(
+                lines[0],
+                json!({
+                    "pc": 0,
+                    "op": 0x60,
+                    "gas": "0x13498",
+                    "gasCost": "0x3",
+                    "memory": "0x",
+                    "memSize": 0,
+                    "stack": [],
+                    "returnData": "0x",
+                    "depth": 1,
+                    "opName": "PUSH1",
+                })
+            );
+            assert_eq!(lines[2]["opName"], "MSTORE");
+            assert_eq!(lines[2]["stack"].as_array().unwrap().len(), 2);
+            assert_eq!(lines[2]["gasCost"], "0x6");
+            assert_eq!(lines[3]["memSize"], 32);
+            assert_eq!(
+                lines[3]["memory"],
+                "0x000000000000000000000000000000000000000000000000000000000000002a"
+            );
+            assert_eq!(
+                lines[6],
+                json!({
+                    "output": "0x000000000000000000000000000000000000000000000000000000000000002a",
+                    "gasUsed": "0x521a",
+                })
+            );
+        })
+    }
+}
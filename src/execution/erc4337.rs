@@ -0,0 +1,358 @@
+//! Validation rules of [EIP-4337](https://eips.ethereum.org/EIPS/eip-4337) user operations.
+//!
+//! Bundlers run `EntryPoint.simulateValidation` before accepting a user operation into their mempool.
+//! The call itself only checks signatures and payments, so the bundler additionally has to make sure
+//! that validation does not depend on anything that may change between simulation and inclusion.
+
+use super::{analysis_cache::AnalysisCache, evm, tracer::*};
+#[cfg(feature = "node")]
+use crate::{accessors::chain, kv::traits::*, state::Buffer};
+use crate::{h256_to_u256, models::*, state::IntraBlockState, State};
+use bytes::Bytes;
+use evmodin::{ExecutionState, OpCode, StatusCode};
+use serde::Serialize;
+
+/// Opcodes which validation code may not use, as their results differ between simulation and inclusion.
+/// `GAS` is allowed only when immediately followed by a call, and `CREATE2` only once, for deploying the sender.
+const BANNED_OPCODES: &[OpCode] = &[
+    OpCode::GASPRICE,
+    OpCode::GASLIMIT,
+    OpCode::DIFFICULTY,
+    OpCode::TIMESTAMP,
+    OpCode::BASEFEE,
+    OpCode::BLOCKHASH,
+    OpCode::NUMBER,
+    OpCode::SELFBALANCE,
+    OpCode::BALANCE,
+    OpCode::ORIGIN,
+    OpCode::CREATE,
+    OpCode::COINBASE,
+    OpCode::SELFDESTRUCT,
+];
+
+const CALL_OPCODES: &[OpCode] = &[
+    OpCode::CALL,
+    OpCode::CALLCODE,
+    OpCode::DELEGATECALL,
+    OpCode::STATICCALL,
+];
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum ValidationViolation {
+    #[serde(rename_all = "camelCase")]
+    BannedOpcode {
+        depth: u16,
+        contract: Address,
+        opcode: String,
+    },
+    /// Access to storage which is neither owned by nor associated with the sender or the validating entity.
+    #[serde(rename_all = "camelCase")]
+    StorageAccess {
+        entity: Address,
+        contract: Address,
+        location: U256,
+    },
+}
+
+/// Checks opcode and storage access rules on everything below the entry point.
+///
+/// Entity is the contract called by the entry point: sender, paymaster or sender creator.
+/// Entities are allowed to access their own storage. Whether they are staked is left to the bundler.
+/// Storage slot is considered associated with the sender only if it equals the sender address,
+/// slots derived from mapping keys are not recognized yet.
+#[derive(Debug)]
+pub struct ValidationTracer {
+    entry_point: Address,
+    sender: Address,
+    call_stack: Vec<Address>,
+    pending_gas: Option<(u16, Address)>,
+    create2_used: bool,
+    violations: Vec<ValidationViolation>,
+}
+
+impl ValidationTracer {
+    pub fn new(entry_point: Address, sender: Address) -> Self {
+        Self {
+            entry_point,
+            sender,
+            call_stack: vec![],
+            pending_gas: None,
+            create2_used: false,
+            violations: vec![],
+        }
+    }
+
+    pub fn into_violations(mut self) -> Vec<ValidationViolation> {
+        self.check_pending_gas(None);
+        self.violations
+    }
+
+    fn push(&mut self, violation: ValidationViolation) {
+        if !self.violations.contains(&violation) {
+            self.violations.push(violation);
+        }
+    }
+
+    fn check_pending_gas(&mut self, next: Option<(u16, OpCode)>) {
+        if let Some((depth, contract)) = self.pending_gas.take() {
+            let followed_by_call =
+                matches!(next, Some((d, op)) if d == depth && CALL_OPCODES.contains(&op));
+            if !followed_by_call {
+                self.push(ValidationViolation::BannedOpcode {
+                    depth,
+                    contract,
+                    opcode: OpCode::GAS.to_string(),
+                });
+            }
+        }
+    }
+
+    fn check_storage(&mut self, contract: Address, location: U256) {
+        let entity = match self.call_stack.get(1) {
+            Some(&entity) => entity,
+            None => return,
+        };
+
+        if contract == self.sender
+            || contract == entity
+            || contract == self.entry_point
+            || location == h256_to_u256(H256::from(self.sender))
+        {
+            return;
+        }
+
+        self.push(ValidationViolation::StorageAccess {
+            entity,
+            contract,
+            location,
+        });
+    }
+}
+
+impl Tracer for ValidationTracer {
+    fn trace_instructions(&self) -> bool {
+        true
+    }
+
+    fn capture_start(
+        &mut self,
+        depth: u16,
+        _: Address,
+        to: Address,
+        _: MessageKind,
+        _: Bytes,
+        _: u64,
+        _: U256,
+    ) {
+        self.call_stack.truncate(depth.into());
+        self.call_stack.push(to);
+    }
+
+    fn capture_state(
+        &mut self,
+        _: &ExecutionState,
+        _: u64,
+        op: OpCode,
+        _: u64,
+        _: Bytes,
+        depth: u16,
+        _: StatusCode,
+    ) {
+        self.check_pending_gas(Some((depth, op)));
+
+        // Entry point itself is trusted.
+        if depth == 0 {
+            return;
+        }
+
+        let contract = self.call_stack[usize::from(depth)];
+        if op == OpCode::GAS {
+            self.pending_gas = Some((depth, contract));
+        } else if op == OpCode::CREATE2 && !self.create2_used {
+            self.create2_used = true;
+        } else if op == OpCode::CREATE2 || BANNED_OPCODES.contains(&op) {
+            self.push(ValidationViolation::BannedOpcode {
+                depth,
+                contract,
+                opcode: op.to_string(),
+            });
+        }
+    }
+
+    fn capture_storage_read(&mut self, address: Address, location: U256) {
+        self.check_storage(address, location)
+    }
+
+    fn capture_storage_write(&mut self, address: Address, location: U256) {
+        self.check_storage(address, location)
+    }
+}
+
+#[derive(Debug)]
+pub struct ValidationSimulation {
+    pub status_code: StatusCode,
+    pub output: Bytes,
+    pub violations: Vec<ValidationViolation>,
+}
+
+/// Call entry point with `data` (normally encoded `simulateValidation`) and check validation rules.
+/// State changes are discarded.
+#[allow(clippy::too_many_arguments)]
+pub async fn simulate<S: State>(
+    state: &mut IntraBlockState<'_, S>,
+    header: &PartialHeader,
+    block_spec: &BlockExecutionSpec,
+    entry_point: Address,
+    sender: Address,
+    data: Bytes,
+    gas: u64,
+) -> anyhow::Result<ValidationSimulation> {
+    let txn = MessageWithSender {
+        message: Message::Legacy {
+            chain_id: Some(block_spec.params.chain_id),
+            nonce: 0,
+            gas_price: U256::ZERO,
+            gas_limit: gas,
+            action: TransactionAction::Call(entry_point),
+            value: U256::ZERO,
+            input: data,
+        },
+        sender: Address::zero(),
+    };
+
+    state.access_account(txn.sender);
+    state.access_account(entry_point);
+
+    let mut tracer = ValidationTracer::new(entry_point, sender);
+    let res = evm::execute(
+        state,
+        Some(&mut tracer),
+        &mut AnalysisCache::default(),
+        header,
+        block_spec,
+        &txn,
+        gas,
+    )
+    .await?;
+
+    Ok(ValidationSimulation {
+        status_code: res.status_code,
+        output: res.output_data,
+        violations: tracer.into_violations(),
+    })
+}
+
+/// Run [`simulate`] on top of the state after canonical block `block_number`.
+#[cfg(feature = "node")]
+pub async fn simulate_validation<'db, Tx: Transaction<'db>>(
+    tx: &Tx,
+    block_number: BlockNumber,
+    entry_point: Address,
+    sender: Address,
+    data: Bytes,
+    gas: u64,
+) -> anyhow::Result<ValidationSimulation> {
+    let chain_spec = chain::chain_spec::read(tx).await?;
+
+    let header = chain::header::read_canonical(tx, block_number).await?;
+
+    let mut buffer = Buffer::new(tx, BlockNumber(0), Some(block_number));
+    let mut state = IntraBlockState::new(&mut buffer);
+
+    simulate(
+        &mut state,
+        &PartialHeader::from(header),
+        &chain_spec.collect_block_spec(block_number),
+        entry_point,
+        sender,
+        data,
+        gas,
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{res::chainspec::MAINNET, util::test_util::run_test, InMemoryState};
+    use hex_literal::hex;
+
+    #[test]
+    fn reports_banned_opcodes_below_entry_point() {
+        run_test(async {
+            let header = PartialHeader {
+                number: 13_000_000.into(),
+                ..PartialHeader::empty()
+            };
+            let block_spec = MAINNET.collect_block_spec(header.number);
+
+            let entry_point = hex!("5ff137d4b0fdcd49dca30c7cf57e578a026d2789").into();
+            let sender = hex!("0a6bb546b9208cfab9e8fa2b9b2c042b18df7030").into();
+
+            let mut db = InMemoryState::default();
+            let mut state = IntraBlockState::new(&mut db);
+
+            // 0      PUSH1  => 00  // retSize
+            // 2      PUSH1  => 00  // retOffset
+            // 4      PUSH1  => 00  // argsSize
+            // 6      PUSH1  => 00  // argsOffset
+            // 8      PUSH1  => 00  // value
+            // 10     PUSH20 => sender
+            // 31     GAS
+            // 32     CALL
+            // 33     TIMESTAMP     // allowed in entry point
+            // 34     STOP
+            let mut code = hex!("60006000600060006000").to_vec();
+            code.push(0x73);
+            code.extend_from_slice(&hex!("0a6bb546b9208cfab9e8fa2b9b2c042b18df7030"));
+            code.extend_from_slice(&hex!("5af14200"));
+            state.set_code(entry_point, code.into()).await.unwrap();
+
+            // 0      TIMESTAMP
+            // 1      POP
+            // 2      GAS
+            // 3      POP
+            // 4      PUSH1  => 05
+            // 6      SLOAD         // own storage
+            // 7      POP
+            // 8      PUSH20 => other
+            // 29     EXTCODESIZE   // account access is not restricted
+            // 30     STOP
+            let mut code = hex!("42505a5060055450").to_vec();
+            code.push(0x73);
+            code.extend_from_slice(&hex!("8b299e2b7d7f43c0ce3068263545309ff4ffb521"));
+            code.extend_from_slice(&hex!("3b00"));
+            state.set_code(sender, code.into()).await.unwrap();
+
+            let res = simulate(
+                &mut state,
+                &header,
+                &block_spec,
+                entry_point,
+                sender,
+                Bytes::new(),
+                1_000_000,
+            )
+            .await
+            .unwrap();
+
+            assert_eq!(res.status_code, StatusCode::Success);
+            assert_eq!(
+                res.violations,
+                vec![
+                    ValidationViolation::BannedOpcode {
+                        depth: 1,
+                        contract: sender,
+                        opcode: OpCode::TIMESTAMP.to_string(),
+                    },
+                    ValidationViolation::BannedOpcode {
+                        depth: 1,
+                        contract: sender,
+                        opcode: OpCode::GAS.to_string(),
+                    },
+                ]
+            );
+        })
+    }
+}
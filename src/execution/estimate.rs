@@ -0,0 +1,455 @@
+//! Calls, gas estimation and priority fee suggestion for transactions that are yet to be signed.
+
+use super::{analysis_cache::AnalysisCache, call_tracer::Reverted, processor::ExecutionProcessor};
+#[cfg(feature = "node")]
+use crate::{
+    accessors::chain,
+    kv::traits::*,
+    state::{history_files::HistoryFiles, Buffer},
+};
+use crate::{
+    chain::intrinsic_gas::intrinsic_gas,
+    consensus::{self, Consensus},
+    models::*,
+    State,
+};
+#[cfg(feature = "node")]
+use anyhow::format_err;
+use anyhow::{bail, ensure};
+use bytes::Bytes;
+use evmodin::StatusCode;
+#[cfg(feature = "node")]
+use std::sync::Arc;
+
+/// Number of latest blocks sampled by [`suggest_priority_fee`].
+pub const FEE_HISTORY_BLOCKS: u64 = 20;
+/// Percentile of priority fees paid in sampled blocks that is suggested.
+pub const FEE_PERCENTILE: usize = 60;
+/// Suggested priority fee if sampled blocks have no transactions.
+pub const DEFAULT_PRIORITY_FEE: u64 = GIGA;
+
+/// Same message with another gas limit and no fees, so that sender does not need to pay for gas.
+fn with_gas_limit(message: &Message, gas_limit: u64) -> Message {
+    let mut message = message.clone();
+    match &mut message {
+        Message::Legacy {
+            gas_price,
+            gas_limit: limit,
+            ..
+        }
+        | Message::EIP2930 {
+            gas_price,
+            gas_limit: limit,
+            ..
+        } => {
+            *gas_price = U256::ZERO;
+            *limit = gas_limit;
+        }
+        Message::EIP1559 {
+            max_priority_fee_per_gas,
+            max_fee_per_gas,
+            gas_limit: limit,
+            ..
+        }
+        | Message::EIP4844 {
+            max_priority_fee_per_gas,
+            max_fee_per_gas,
+            gas_limit: limit,
+            ..
+        } => {
+            *max_priority_fee_per_gas = U256::ZERO;
+            *max_fee_per_gas = U256::ZERO;
+            *limit = gas_limit;
+        }
+    }
+    message
+}
+
+/// Same header with zero base fee, so that fees are not charged.
+fn without_base_fee(header: &PartialHeader) -> PartialHeader {
+    PartialHeader {
+        base_fee_per_gas: header.base_fee_per_gas.map(|_| U256::ZERO),
+        ..header.clone()
+    }
+}
+
+/// Execute `txn` with given gas limit, discarding state changes.
+/// Returns status code, gas used and output.
+#[allow(clippy::too_many_arguments)]
+async fn execute_with_gas_limit<S: State>(
+    state: &mut S,
+    engine: &mut dyn Consensus,
+    analysis_cache: &mut AnalysisCache,
+    header: &PartialHeader,
+    block_spec: &BlockExecutionSpec,
+    txn: &MessageWithSender,
+    gas_limit: u64,
+) -> anyhow::Result<(StatusCode, u64, Bytes)> {
+    let txn = MessageWithSender {
+        message: with_gas_limit(&txn.message, gas_limit),
+        sender: txn.sender,
+    };
+    let body = BlockBodyWithSenders::default();
+    let mut processor = ExecutionProcessor::new(
+        state,
+        None,
+        analysis_cache,
+        engine,
+        header,
+        &body,
+        block_spec,
+    );
+    let (receipt, res) = processor.execute_transaction_with_output(&txn).await?;
+
+    Ok((
+        res.status_code,
+        receipt.cumulative_gas_used,
+        res.output_data,
+    ))
+}
+
+/// Lowest gas limit `txn` succeeds with in a block with given header,
+/// up to the gas limit of `txn` itself.
+///
+/// Nonce and fees of `txn` are not checked and base fee is zero,
+/// so that gas can be estimated for senders without funds for it.
+pub async fn estimate_gas<S: State>(
+    state: &mut S,
+    chain_spec: &ChainSpec,
+    header: &PartialHeader,
+    txn: &MessageWithSender,
+) -> anyhow::Result<u64> {
+    let mut engine = consensus::engine_factory(chain_spec.clone())?;
+    let mut analysis_cache = AnalysisCache::default();
+    let block_spec = chain_spec.collect_block_spec(header.number);
+    let header = without_base_fee(header);
+
+    let intrinsic_gas = intrinsic_gas(txn, block_spec.revision.into());
+    let cap = txn.gas_limit().min(header.gas_limit);
+    ensure!(
+        u128::from(cap) >= intrinsic_gas,
+        "gas limit {} is below intrinsic gas {}",
+        cap,
+        intrinsic_gas
+    );
+
+    let (status_code, gas_used, output) = execute_with_gas_limit(
+        state,
+        &mut *engine,
+        &mut analysis_cache,
+        &header,
+        &block_spec,
+        txn,
+        cap,
+    )
+    .await?;
+    match status_code {
+        StatusCode::Success => {}
+        StatusCode::Revert => return Err(Reverted { output }.into()),
+        other => bail!("gas required exceeds allowance ({}): {:?}", cap, other),
+    }
+
+    // Refunds are only paid out after execution, so `txn` never succeeds with less than it used.
+    let mut lo = gas_used.max(intrinsic_gas as u64) - 1;
+    let mut hi = cap;
+    while lo + 1 < hi {
+        let mid = lo + (hi - lo) / 2;
+        let (status_code, _, _) = execute_with_gas_limit(
+            state,
+            &mut *engine,
+            &mut analysis_cache,
+            &header,
+            &block_spec,
+            txn,
+            mid,
+        )
+        .await?;
+        if status_code == StatusCode::Success {
+            hi = mid;
+        } else {
+            lo = mid;
+        }
+    }
+
+    Ok(hi)
+}
+
+/// Output of `txn` executed in a block with given header, up to the block gas limit.
+/// State changes are not kept.
+///
+/// As in [`estimate_gas`], nonce and fees of `txn` are not checked and base fee is zero.
+pub async fn call<S: State>(
+    state: &mut S,
+    chain_spec: &ChainSpec,
+    header: &PartialHeader,
+    txn: &MessageWithSender,
+) -> anyhow::Result<Bytes> {
+    let mut engine = consensus::engine_factory(chain_spec.clone())?;
+    let block_spec = chain_spec.collect_block_spec(header.number);
+    let header = without_base_fee(header);
+
+    let gas_limit = txn.gas_limit().min(header.gas_limit);
+    let (status_code, _, output) = execute_with_gas_limit(
+        state,
+        &mut *engine,
+        &mut AnalysisCache::default(),
+        &header,
+        &block_spec,
+        txn,
+        gas_limit,
+    )
+    .await?;
+    match status_code {
+        StatusCode::Success => Ok(output),
+        StatusCode::Revert => Err(Reverted { output }.into()),
+        other => bail!("execution failed: {:?}", other),
+    }
+}
+
+/// Chain spec, and header of canonical block `block_number`.
+#[cfg(feature = "node")]
+async fn read_block<'db, Tx: Transaction<'db>>(
+    tx: &Tx,
+    block_number: BlockNumber,
+) -> anyhow::Result<(ChainSpec, PartialHeader)> {
+    let chain_spec = chain::chain_spec::read(tx).await?;
+
+    let header = chain::header::read_canonical(tx, block_number).await?;
+
+    Ok((chain_spec, PartialHeader::from(header)))
+}
+
+/// Run [`call`] on top of the state after canonical block `block_number`.
+#[cfg(feature = "node")]
+pub async fn call_at<'db, Tx: Transaction<'db>>(
+    tx: &Tx,
+    block_number: BlockNumber,
+    history_files: Option<Arc<HistoryFiles>>,
+    txn: &MessageWithSender,
+) -> anyhow::Result<Bytes> {
+    let (chain_spec, header) = read_block(tx, block_number).await?;
+    let mut buffer = Buffer::historical(tx, block_number, history_files);
+
+    call(&mut buffer, &chain_spec, &header, txn).await
+}
+
+/// Run [`estimate_gas`] on top of the state after canonical block `block_number`.
+#[cfg(feature = "node")]
+pub async fn estimate_gas_at<'db, Tx: Transaction<'db>>(
+    tx: &Tx,
+    block_number: BlockNumber,
+    history_files: Option<Arc<HistoryFiles>>,
+    txn: &MessageWithSender,
+) -> anyhow::Result<u64> {
+    let (chain_spec, header) = read_block(tx, block_number).await?;
+    let mut buffer = Buffer::historical(tx, block_number, history_files);
+
+    estimate_gas(&mut buffer, &chain_spec, &header, txn).await
+}
+
+/// [`FEE_PERCENTILE`]th percentile of `fees`, or [`DEFAULT_PRIORITY_FEE`] if there are none.
+fn fee_percentile(mut fees: Vec<U256>) -> U256 {
+    if fees.is_empty() {
+        return U256::from(DEFAULT_PRIORITY_FEE);
+    }
+
+    fees.sort_unstable();
+    fees[(fees.len() - 1) * FEE_PERCENTILE / 100]
+}
+
+/// Priority fee to include a transaction in one of the next blocks,
+/// based on fees paid in [`FEE_HISTORY_BLOCKS`] canonical blocks up to `block_number`.
+#[cfg(feature = "node")]
+pub async fn suggest_priority_fee<'db, Tx: Transaction<'db>>(
+    tx: &Tx,
+    block_number: BlockNumber,
+) -> anyhow::Result<U256> {
+    let mut fees = vec![];
+    for number in block_number.0.saturating_sub(FEE_HISTORY_BLOCKS - 1)..=block_number.0 {
+        let hash = chain::canonical_hash::read(tx, number)
+            .await?
+            .ok_or_else(|| format_err!("no canonical block {}", number))?;
+        let header = chain::header::read(tx, hash, number)
+            .await?
+            .ok_or_else(|| format_err!("header {}/{:?} not found", number, hash))?;
+        let body = chain::block_body::read_without_senders(tx, hash, number)
+            .await?
+            .ok_or_else(|| format_err!("body {}/{:?} not found", number, hash))?;
+
+        let base_fee_per_gas = header.base_fee_per_gas.unwrap_or(U256::ZERO);
+        fees.extend(
+            body.transactions
+                .iter()
+                .filter(|txn| txn.max_fee_per_gas() >= base_fee_per_gas)
+                .map(|txn| txn.priority_fee_per_gas(base_fee_per_gas)),
+        );
+    }
+
+    Ok(fee_percentile(fees))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{res::chainspec::MAINNET, util::test_util::run_test, InMemoryState, StateWriter};
+    use hex_literal::hex;
+
+    fn header() -> PartialHeader {
+        PartialHeader {
+            number: 13_000_000.into(),
+            gas_limit: 30_000_000,
+            timestamp: 1_628_166_822,
+            base_fee_per_gas: Some(U256::from(GIGA)),
+            ..PartialHeader::empty()
+        }
+    }
+
+    fn message(to: Address, gas_limit: u64) -> Message {
+        Message::EIP1559 {
+            chain_id: ChainId(1),
+            nonce: 0,
+            max_priority_fee_per_gas: U256::from(GIGA),
+            max_fee_per_gas: U256::from(100 * GIGA),
+            gas_limit,
+            action: TransactionAction::Call(to),
+            value: U256::ZERO,
+            input: Bytes::new(),
+            access_list: vec![],
+        }
+    }
+
+    #[test]
+    fn estimates_lowest_sufficient_gas_limit() {
+        run_test(async {
+            let header = header();
+            let sender = hex!("b685342b8c54347aad148e1f22eff3eb3eb29391").into();
+            let contract: Address = hex!("0a6bb546b9208cfab9e8fa2b9b2c042b18df7030").into();
+
+            // 0      PUSH1  => 01
+            // 2      PUSH1  => 00
+            // 4      SSTORE
+            let code = Bytes::from(hex!("6001600055").to_vec());
+            let code_hash = crate::crypto::keccak256(&code);
+
+            let mut state = InMemoryState::default();
+            state.begin_block(header.number);
+            state.update_account(
+                contract,
+                None,
+                Some(Account {
+                    code_hash,
+                    ..Default::default()
+                }),
+            );
+            state.update_code(code_hash, code).await.unwrap();
+
+            // Sender has no funds, fees are not charged.
+            let transfer = MessageWithSender {
+                message: message(Address::from_low_u64_be(0xdead), 100_000),
+                sender,
+            };
+            assert_eq!(
+                estimate_gas(&mut state, &MAINNET, &header, &transfer)
+                    .await
+                    .unwrap(),
+                21_000
+            );
+
+            let txn = MessageWithSender {
+                message: message(contract, 100_000),
+                sender,
+            };
+            let estimate = estimate_gas(&mut state, &MAINNET, &header, &txn)
+                .await
+                .unwrap();
+            assert!(estimate > 21_000 + 20_000);
+
+            let mut engine = consensus::engine_factory(MAINNET.clone()).unwrap();
+            let block_spec = MAINNET.collect_block_spec(header.number);
+            for (gas_limit, expected) in [
+                (estimate, StatusCode::Success),
+                (estimate - 1, StatusCode::OutOfGas),
+            ] {
+                let (status_code, _, _) = execute_with_gas_limit(
+                    &mut state,
+                    &mut *engine,
+                    &mut AnalysisCache::default(),
+                    &header,
+                    &block_spec,
+                    &txn,
+                    gas_limit,
+                )
+                .await
+                .unwrap();
+                assert_eq!(status_code, expected);
+            }
+
+            // Not enough gas even with the gas limit of the transaction.
+            let txn = MessageWithSender {
+                message: message(contract, 30_000),
+                sender,
+            };
+            assert!(estimate_gas(&mut state, &MAINNET, &header, &txn)
+                .await
+                .is_err());
+
+            // 0      PUSH1  => 2a
+            // 2      PUSH1  => 00
+            // 4      MSTORE
+            // 5      PUSH1  => 20
+            // 7      PUSH1  => 00
+            // 9      REVERT
+            let reverting: Address = hex!("0b6bb546b9208cfab9e8fa2b9b2c042b18df7030").into();
+            let code = Bytes::from(hex!("602a60005260206000fd").to_vec());
+            let code_hash = crate::crypto::keccak256(&code);
+            state.update_account(
+                reverting,
+                None,
+                Some(Account {
+                    code_hash,
+                    ..Default::default()
+                }),
+            );
+            state.update_code(code_hash, code).await.unwrap();
+
+            let txn = MessageWithSender {
+                message: message(reverting, 100_000),
+                sender,
+            };
+            let err = estimate_gas(&mut state, &MAINNET, &header, &txn)
+                .await
+                .unwrap_err()
+                .downcast::<Reverted>()
+                .unwrap();
+            assert_eq!(
+                err.output,
+                Bytes::from(U256::from(0x2a_u8).to_be_bytes().to_vec())
+            );
+
+            let err = call(&mut state, &MAINNET, &header, &txn)
+                .await
+                .unwrap_err()
+                .downcast::<Reverted>()
+                .unwrap();
+            assert_eq!(
+                err.output,
+                Bytes::from(U256::from(0x2a_u8).to_be_bytes().to_vec())
+            );
+            assert_eq!(
+                call(&mut state, &MAINNET, &header, &transfer)
+                    .await
+                    .unwrap(),
+                Bytes::new()
+            );
+        })
+    }
+
+    #[test]
+    fn fee_percentiles() {
+        assert_eq!(fee_percentile(vec![]), U256::from(DEFAULT_PRIORITY_FEE));
+        assert_eq!(
+            fee_percentile((1..=11_u64).rev().map(U256::from).collect()),
+            U256::from(7_u64)
+        );
+    }
+}
@@ -1,6 +1,8 @@
 use super::{
     address::*,
     analysis_cache::AnalysisCache,
+    custom_opcodes::custom_opcodes,
+    host::{self, HostInterface, StateModifier},
     precompiled,
     tracer::{CodeKind, MessageKind, Tracer},
 };
@@ -8,15 +10,15 @@ use crate::{
     chain::protocol_param::{fee, param},
     h256_to_u256,
     models::*,
-    u256_to_h256, IntraBlockState, State,
+    IntraBlockState, State,
 };
 use anyhow::Context;
 use async_recursion::async_recursion;
+use async_trait::async_trait;
 use bytes::Bytes;
 use evmodin::{
-    continuation::{interrupt::*, interrupt_data::*, resume_data::*, Interrupt},
-    host::*,
-    CallKind, CreateMessage, Message as EvmMessage, Output, Revision, StatusCode,
+    continuation::Interrupt, host::*, CallKind, CreateMessage, ExecutionState,
+    Message as EvmMessage, OpCode, Output, Revision, StatusCode,
 };
 use sha3::{Digest, Keccak256};
 use std::{cmp::min, convert::TryFrom};
@@ -41,6 +43,8 @@ where
     block_spec: &'c BlockExecutionSpec,
     txn: &'t MessageWithSender,
     beneficiary: Address,
+    /// Original code of executing frames, if it has custom opcodes.
+    custom_code: Vec<Option<Vec<u8>>>,
 }
 
 pub async fn execute<B: State>(
@@ -60,6 +64,7 @@ pub async fn execute<B: State>(
         block_spec,
         txn,
         beneficiary: header.beneficiary,
+        custom_code: vec![],
     };
 
     let res = if let TransactionAction::Call(to) = txn.action() {
@@ -149,6 +154,7 @@ where
             // https://github.com/ethereum/EIPs/issues/684
             res.status_code = StatusCode::InvalidInstruction;
             res.gas_left = 0;
+            self.capture_end(message.depth, &res);
             return Ok(res);
         }
 
@@ -215,6 +221,8 @@ where
             }
         }
 
+        self.capture_end(message.depth, &res);
+
         Ok(res)
     }
 
@@ -253,6 +261,10 @@ where
                     _ => unreachable!(),
                 }
             };
+            // Code of another account is executed in the context of the recipient.
+            if message.code_address != message.recipient {
+                tracer.capture_account_read(message.code_address);
+            }
             tracer.capture_start(
                 message.depth.try_into().unwrap(),
                 message.sender,
@@ -277,9 +289,11 @@ where
             && !precompiled
             && !self.state.exists(message.code_address).await?
         {
+            self.capture_end(message.depth, &res);
             return Ok(res);
         }
 
+        let depth = message.depth;
         let snapshot = self.state.take_snapshot();
 
         if message.kind == CallKind::Call {
@@ -299,7 +313,7 @@ where
             let num = message.code_address.0[ADDRESS_LENGTH - 1] as usize;
             let contract = &precompiled::CONTRACTS[num - 1];
             let input = message.input_data;
-            if let Some(gas) = (contract.gas)(input.clone(), self.block_spec.revision)
+            if let Some(gas) = (contract.gas)(input.clone(), &self.block_spec.precompile_pricing)
                 .and_then(|g| i64::try_from(g).ok())
             {
                 if gas > message.gas {
@@ -317,6 +331,7 @@ where
         } else {
             let code = code.unwrap_or_default();
             if code.is_empty() {
+                self.capture_end(depth, &res);
                 return Ok(res);
             }
 
@@ -334,15 +349,41 @@ where
             }
         }
 
+        self.capture_end(depth, &res);
+
         Ok(res)
     }
 
+    fn capture_end(&mut self, depth: i32, res: &Output) {
+        if let Some(tracer) = &mut self.tracer {
+            tracer.capture_end(
+                depth.try_into().unwrap(),
+                res.output_data.clone(),
+                res.gas_left.try_into().unwrap_or(0),
+                res.status_code.clone(),
+            );
+        }
+    }
+
     async fn execute(
         &mut self,
         msg: EvmMessage,
         code: Vec<u8>,
         code_hash: Option<H256>,
     ) -> anyhow::Result<Output> {
+        #[cfg(feature = "evmc")]
+        if let Some(vm) = super::evmc::external_vm() {
+            let revision = self.block_spec.revision;
+            return vm.execute(self, revision, &msg, &code);
+        }
+
+        let (code, custom_code) = match custom_opcodes(self.block_spec.params.chain_id)
+            .and_then(|opcodes| opcodes.rewrite(&code))
+        {
+            Some(rewritten) => (rewritten, Some(code)),
+            None => (code, None),
+        };
+
         let a;
         let analysis = if let Some(code_hash) = code_hash {
             if let Some(cache) = self.analysis_cache.get(code_hash) {
@@ -357,318 +398,348 @@ where
             &a
         };
 
-        let mut interrupt = analysis
-            .execute_resumable(false, msg, self.block_spec.revision)
+        let depth = msg.depth.try_into().unwrap();
+        // Custom opcodes are executed on instruction start.
+        let trace_instructions = custom_code.is_some()
+            || self
+                .tracer
+                .as_ref()
+                .map(|tracer| tracer.trace_instructions())
+                .unwrap_or(false);
+
+        let interrupt = analysis
+            .execute_resumable(trace_instructions, msg, self.block_spec.revision)
             .resume(());
 
-        let output = loop {
-            interrupt = match interrupt {
-                InterruptVariant::InstructionStart(_, _) => unreachable!("tracing is disabled"),
-                InterruptVariant::AccountExists(data, i) => {
-                    let address = data.address;
-                    let exists = if self.block_spec.revision >= Revision::Spurious {
-                        !self.state.is_dead(address).await?
-                    } else {
-                        self.state.exists(address).await?
-                    };
-                    i.resume(AccountExistsStatus { exists })
-                }
-                InterruptVariant::GetBalance(data, i) => {
-                    let balance = self.state.get_balance(data.address).await?;
-                    i.resume(Balance { balance })
-                }
-                InterruptVariant::GetCodeSize(data, i) => {
-                    let code_size = u64::try_from(
-                        self.state
-                            .get_code(data.address)
-                            .await?
-                            .map(|c| c.len())
-                            .unwrap_or(0),
-                    )?
-                    .into();
-                    i.resume(CodeSize { code_size })
-                }
-                InterruptVariant::GetStorage(data, i) => {
-                    let value = self
-                        .state
-                        .get_current_storage(data.address, data.key)
-                        .await?;
-                    i.resume(StorageValue { value })
+        self.custom_code.push(custom_code);
+        let res = host::run(self, depth, interrupt).await;
+        self.custom_code.pop();
+        res
+    }
+
+    fn number_of_precompiles(&self) -> u8 {
+        match self.block_spec.revision {
+            Revision::Frontier | Revision::Homestead | Revision::Tangerine | Revision::Spurious => {
+                precompiled::NUM_OF_FRONTIER_CONTRACTS as u8
+            }
+            Revision::Byzantium | Revision::Constantinople | Revision::Petersburg => {
+                precompiled::NUM_OF_BYZANTIUM_CONTRACTS as u8
+            }
+            Revision::Istanbul | Revision::Berlin | Revision::London | Revision::Shanghai => {
+                precompiled::NUM_OF_ISTANBUL_CONTRACTS as u8
+            }
+        }
+    }
+
+    fn is_precompiled(&self, contract: Address) -> bool {
+        if contract.is_zero() {
+            false
+        } else {
+            let mut max_precompiled = Address::zero();
+            max_precompiled.0[ADDRESS_LENGTH - 1] = self.number_of_precompiles() as u8;
+            contract <= max_precompiled
+        }
+    }
+}
+
+#[async_trait]
+impl<'r, 'state, 'tracer, 'analysis, 'h, 'c, 't, B> HostInterface
+    for Evm<'r, 'state, 'tracer, 'analysis, 'h, 'c, 't, B>
+where
+    B: State,
+{
+    fn instruction_start(
+        &mut self,
+        depth: u16,
+        pc: usize,
+        mut opcode: OpCode,
+        state: &ExecutionState,
+    ) -> StateModifier {
+        let mut modifier = None;
+        if let Some(Some(code)) = self.custom_code.last() {
+            if let Some(custom_opcodes) = custom_opcodes(self.block_spec.params.chain_id) {
+                modifier = custom_opcodes.modifier(code[pc]);
+                if modifier.is_some() {
+                    opcode = OpCode(code[pc]);
                 }
-                InterruptVariant::SetStorage(
-                    SetStorage {
-                        address,
-                        key,
-                        value: new_val,
-                    },
-                    i,
-                ) => {
-                    let current_val = self.state.get_current_storage(address, key).await?;
+            }
+        }
 
-                    let status = if current_val == new_val {
-                        StorageStatus::Unchanged
-                    } else {
-                        self.state.set_storage(address, key, new_val).await?;
+        if let Some(tracer) = &mut self.tracer {
+            if tracer.trace_instructions() {
+                tracer.capture_state(
+                    state,
+                    pc as u64,
+                    opcode,
+                    0,
+                    state.return_data.clone(),
+                    depth,
+                    StatusCode::Success,
+                );
+            }
+        }
 
-                        let eip1283 = self.block_spec.revision >= Revision::Istanbul
-                            || self.block_spec.revision == Revision::Constantinople;
+        modifier
+    }
 
-                        if !eip1283 {
-                            if current_val == 0 {
-                                StorageStatus::Added
-                            } else if new_val == 0 {
-                                self.state.add_refund(fee::R_SCLEAR);
-                                StorageStatus::Deleted
-                            } else {
-                                StorageStatus::Modified
-                            }
-                        } else {
-                            let sload_cost = {
-                                if self.block_spec.revision >= Revision::Berlin {
-                                    fee::WARM_STORAGE_READ_COST
-                                } else if self.block_spec.revision >= Revision::Istanbul {
-                                    fee::G_SLOAD_ISTANBUL
-                                } else {
-                                    fee::G_SLOAD_TANGERINE_WHISTLE
-                                }
-                            };
-
-                            let mut sstore_reset_gas = fee::G_SRESET;
-                            if self.block_spec.revision >= Revision::Berlin {
-                                sstore_reset_gas -= fee::COLD_SLOAD_COST;
-                            }
+    async fn account_exists(&mut self, address: Address) -> anyhow::Result<bool> {
+        if let Some(tracer) = &mut self.tracer {
+            tracer.capture_account_read(address);
+        }
 
-                            // https://eips.ethereum.org/EIPS/eip-1283
-                            let original_val =
-                                self.state.get_original_storage(address, key).await?;
-
-                            // https://eips.ethereum.org/EIPS/eip-3529
-                            let sstore_clears_refund =
-                                if self.block_spec.revision >= Revision::London {
-                                    sstore_reset_gas + fee::ACCESS_LIST_STORAGE_KEY_COST
-                                } else {
-                                    fee::R_SCLEAR
-                                };
-
-                            if original_val == current_val {
-                                if original_val == 0 {
-                                    StorageStatus::Added
-                                } else {
-                                    if new_val == 0 {
-                                        self.state.add_refund(sstore_clears_refund);
-                                    }
-                                    StorageStatus::Modified
-                                }
-                            } else {
-                                if original_val != 0 {
-                                    if current_val == 0 {
-                                        self.state.subtract_refund(sstore_clears_refund);
-                                    }
-                                    if new_val == 0 {
-                                        self.state.add_refund(sstore_clears_refund);
-                                    }
-                                }
-                                if original_val == new_val {
-                                    let refund = {
-                                        if original_val == 0 {
-                                            fee::G_SSET - sload_cost
-                                        } else {
-                                            sstore_reset_gas - sload_cost
-                                        }
-                                    };
-
-                                    self.state.add_refund(refund);
-                                }
-                                StorageStatus::ModifiedAgain
-                            }
-                        }
-                    };
+        Ok(if self.block_spec.revision >= Revision::Spurious {
+            !self.state.is_dead(address).await?
+        } else {
+            self.state.exists(address).await?
+        })
+    }
 
-                    i.resume(StorageStatusInfo { status })
-                }
-                InterruptVariant::GetCodeHash(data, i) => {
-                    let address = data.address;
-                    let hash = h256_to_u256({
-                        if self.state.is_dead(address).await? {
-                            H256::zero()
-                        } else {
-                            self.state.get_code_hash(address).await?
-                        }
-                    });
-                    i.resume(CodeHash { hash })
-                }
-                InterruptVariant::CopyCode(
-                    CopyCode {
-                        address,
-                        offset,
-                        max_size,
-                    },
-                    i,
-                ) => {
-                    let mut buffer = vec![0; max_size];
+    async fn get_balance(&mut self, address: Address) -> anyhow::Result<U256> {
+        if let Some(tracer) = &mut self.tracer {
+            tracer.capture_account_read(address);
+        }
 
-                    let code = self.state.get_code(address).await?.unwrap_or_default();
+        self.state.get_balance(address).await
+    }
 
-                    let mut copied = 0;
-                    if offset < code.len() {
-                        copied = min(max_size, code.len() - offset);
-                        buffer[..copied].copy_from_slice(&code[offset..offset + copied]);
-                    }
+    async fn get_code_size(&mut self, address: Address) -> anyhow::Result<U256> {
+        if let Some(tracer) = &mut self.tracer {
+            tracer.capture_account_read(address);
+        }
+
+        Ok(u64::try_from(
+            self.state
+                .get_code(address)
+                .await?
+                .map(|c| c.len())
+                .unwrap_or(0),
+        )?
+        .into())
+    }
 
-                    buffer.truncate(copied);
-                    let code = buffer.into();
+    async fn get_storage(&mut self, address: Address, key: U256) -> anyhow::Result<U256> {
+        if let Some(tracer) = &mut self.tracer {
+            tracer.capture_storage_read(address, key);
+        }
 
-                    i.resume(Code { code })
-                }
-                InterruptVariant::Selfdestruct(data, i) => {
-                    self.state.record_selfdestruct(data.address);
-                    let balance = self.state.get_balance(data.address).await?;
-                    self.state.add_to_balance(data.beneficiary, balance).await?;
-                    self.state.set_balance(data.address, 0).await?;
-
-                    if let Some(tracer) = &mut self.tracer {
-                        tracer.capture_self_destruct(data.address, data.beneficiary);
-                    }
+        self.state.get_current_storage(address, key).await
+    }
 
-                    i.resume(())
-                }
-                InterruptVariant::Call(data, i) => {
-                    let output = match data {
-                        Call::Create(message) => {
-                            let mut res = self.create(message).await?;
-
-                            // https://eips.ethereum.org/EIPS/eip-211
-                            if res.status_code != StatusCode::Revert {
-                                // geth returns CREATE output only in case of REVERT
-                                res.output_data = Default::default();
-                            }
+    async fn set_storage(
+        &mut self,
+        address: Address,
+        key: U256,
+        value: U256,
+    ) -> anyhow::Result<StorageStatus> {
+        if let Some(tracer) = &mut self.tracer {
+            tracer.capture_storage_write(address, key);
+        }
 
-                            res
-                        }
-                        Call::Call(message) => self.call(message).await?,
-                    };
+        let current_val = self.state.get_current_storage(address, key).await?;
 
-                    i.resume(CallOutput { output })
-                }
-                InterruptVariant::GetTxContext(i) => {
-                    let base_fee_per_gas = self.header.base_fee_per_gas.unwrap_or(U256::ZERO);
-                    let tx_gas_price = self.txn.effective_gas_price(base_fee_per_gas);
-                    let tx_origin = self.txn.sender;
-                    let block_coinbase = self.beneficiary;
-                    let block_number = self.header.number.0;
-                    let block_timestamp = self.header.timestamp;
-                    let block_gas_limit = self.header.gas_limit;
-                    let block_difficulty = self.header.difficulty;
-                    let chain_id = self.block_spec.params.chain_id.0.into();
-                    let block_base_fee = base_fee_per_gas;
-
-                    let context = TxContext {
-                        tx_gas_price,
-                        tx_origin,
-                        block_coinbase,
-                        block_number,
-                        block_timestamp,
-                        block_gas_limit,
-                        block_difficulty,
-                        chain_id,
-                        block_base_fee,
-                    };
-
-                    i.resume(TxContextData { context })
+        let status = if current_val == value {
+            StorageStatus::Unchanged
+        } else {
+            self.state.set_storage(address, key, value).await?;
+
+            let eip1283 = self.block_spec.revision >= Revision::Istanbul
+                || self.block_spec.revision == Revision::Constantinople;
+
+            if !eip1283 {
+                if current_val == 0 {
+                    StorageStatus::Added
+                } else if value == 0 {
+                    self.state.add_refund(fee::R_SCLEAR);
+                    StorageStatus::Deleted
+                } else {
+                    StorageStatus::Modified
                 }
-                InterruptVariant::GetBlockHash(data, i) => {
-                    let n = data.block_number;
-
-                    let base_number = self.header.number;
-                    let distance = base_number.0 - n;
-                    assert!(distance <= 256);
-
-                    let mut hash = self.header.parent_hash;
-
-                    for i in 1..distance {
-                        hash = self
-                            .state
-                            .db()
-                            .read_header(BlockNumber(base_number.0 - i), hash)
-                            .await?
-                            .context("no header")?
-                            .parent_hash;
+            } else {
+                let sload_cost = {
+                    if self.block_spec.revision >= Revision::Berlin {
+                        fee::WARM_STORAGE_READ_COST
+                    } else if self.block_spec.revision >= Revision::Istanbul {
+                        fee::G_SLOAD_ISTANBUL
+                    } else {
+                        fee::G_SLOAD_TANGERINE_WHISTLE
                     }
+                };
 
-                    let hash = h256_to_u256(hash);
-                    i.resume(BlockHash { hash })
-                }
-                InterruptVariant::EmitLog(data, i) => {
-                    self.state.add_log(Log {
-                        address: data.address,
-                        topics: data.topics.into_iter().map(u256_to_h256).collect(),
-                        data: data.data,
-                    });
-
-                    i.resume(())
+                let mut sstore_reset_gas = fee::G_SRESET;
+                if self.block_spec.revision >= Revision::Berlin {
+                    sstore_reset_gas -= fee::COLD_SLOAD_COST;
                 }
-                InterruptVariant::AccessAccount(data, i) => {
-                    let address = data.address;
 
-                    let status = if self.is_precompiled(address) {
-                        AccessStatus::Warm
+                // https://eips.ethereum.org/EIPS/eip-1283
+                let original_val = self.state.get_original_storage(address, key).await?;
+
+                // https://eips.ethereum.org/EIPS/eip-3529
+                let sstore_clears_refund = if self.block_spec.revision >= Revision::London {
+                    sstore_reset_gas + fee::ACCESS_LIST_STORAGE_KEY_COST
+                } else {
+                    fee::R_SCLEAR
+                };
+
+                if original_val == current_val {
+                    if original_val == 0 {
+                        StorageStatus::Added
                     } else {
-                        self.state.access_account(address)
-                    };
-                    i.resume(AccessAccountStatus { status })
-                }
-                InterruptVariant::AccessStorage(data, i) => {
-                    let status = self.state.access_storage(data.address, data.key);
-                    i.resume(AccessStorageStatus { status })
-                }
-                InterruptVariant::Complete(res, _) => {
-                    let output = match res {
-                        Ok(output) => output.into(),
-                        Err(status_code) => Output {
-                            status_code,
-                            gas_left: 0,
-                            output_data: bytes::Bytes::new(),
-                            create_address: None,
-                        },
-                    };
-
-                    break output;
+                        if value == 0 {
+                            self.state.add_refund(sstore_clears_refund);
+                        }
+                        StorageStatus::Modified
+                    }
+                } else {
+                    if original_val != 0 {
+                        if current_val == 0 {
+                            self.state.subtract_refund(sstore_clears_refund);
+                        }
+                        if value == 0 {
+                            self.state.add_refund(sstore_clears_refund);
+                        }
+                    }
+                    if original_val == value {
+                        let refund = {
+                            if original_val == 0 {
+                                fee::G_SSET - sload_cost
+                            } else {
+                                sstore_reset_gas - sload_cost
+                            }
+                        };
+
+                        self.state.add_refund(refund);
+                    }
+                    StorageStatus::ModifiedAgain
                 }
-            };
+            }
         };
 
-        Ok(output)
+        Ok(status)
     }
 
-    fn number_of_precompiles(&self) -> u8 {
-        match self.block_spec.revision {
-            Revision::Frontier | Revision::Homestead | Revision::Tangerine | Revision::Spurious => {
-                precompiled::NUM_OF_FRONTIER_CONTRACTS as u8
-            }
-            Revision::Byzantium | Revision::Constantinople | Revision::Petersburg => {
-                precompiled::NUM_OF_BYZANTIUM_CONTRACTS as u8
-            }
-            Revision::Istanbul | Revision::Berlin | Revision::London | Revision::Shanghai => {
-                precompiled::NUM_OF_ISTANBUL_CONTRACTS as u8
+    async fn get_code_hash(&mut self, address: Address) -> anyhow::Result<U256> {
+        if let Some(tracer) = &mut self.tracer {
+            tracer.capture_account_read(address);
+        }
+
+        Ok(h256_to_u256({
+            if self.state.is_dead(address).await? {
+                H256::zero()
+            } else {
+                self.state.get_code_hash(address).await?
             }
+        }))
+    }
+
+    async fn copy_code(
+        &mut self,
+        address: Address,
+        offset: usize,
+        max_size: usize,
+    ) -> anyhow::Result<Bytes> {
+        if let Some(tracer) = &mut self.tracer {
+            tracer.capture_account_read(address);
+        }
+
+        let mut buffer = vec![0; max_size];
+
+        let code = self.state.get_code(address).await?.unwrap_or_default();
+
+        let mut copied = 0;
+        if offset < code.len() {
+            copied = min(max_size, code.len() - offset);
+            buffer[..copied].copy_from_slice(&code[offset..offset + copied]);
         }
+
+        buffer.truncate(copied);
+        Ok(buffer.into())
     }
 
-    fn is_precompiled(&self, contract: Address) -> bool {
-        if contract.is_zero() {
-            false
+    async fn selfdestruct(&mut self, address: Address, beneficiary: Address) -> anyhow::Result<()> {
+        self.state.record_selfdestruct(address);
+        let balance = self.state.get_balance(address).await?;
+        self.state.add_to_balance(beneficiary, balance).await?;
+        self.state.set_balance(address, 0).await?;
+
+        if let Some(tracer) = &mut self.tracer {
+            tracer.capture_self_destruct(address, beneficiary, balance);
+        }
+
+        Ok(())
+    }
+
+    async fn call(&mut self, call: Call) -> anyhow::Result<Output> {
+        match call {
+            Call::Create(message) => self.create(message).await,
+            Call::Call(message) => Evm::call(self, message).await,
+        }
+    }
+
+    async fn get_tx_context(&mut self) -> anyhow::Result<TxContext> {
+        let base_fee_per_gas = self.header.base_fee_per_gas.unwrap_or(U256::ZERO);
+        let tx_gas_price = self.txn.effective_gas_price(base_fee_per_gas);
+        let tx_origin = self.txn.sender;
+        let block_coinbase = self.beneficiary;
+        let block_number = self.header.number.0;
+        let block_timestamp = self.header.timestamp;
+        let block_gas_limit = self.header.gas_limit;
+        let block_difficulty = self.header.difficulty;
+        let chain_id = self.block_spec.params.chain_id.0.into();
+        let block_base_fee = base_fee_per_gas;
+
+        Ok(TxContext {
+            tx_gas_price,
+            tx_origin,
+            block_coinbase,
+            block_number,
+            block_timestamp,
+            block_gas_limit,
+            block_difficulty,
+            chain_id,
+            block_base_fee,
+        })
+    }
+
+    async fn get_block_hash(&mut self, n: u64) -> anyhow::Result<U256> {
+        let base_number = self.header.number;
+        let distance = base_number.0 - n;
+        assert!(distance <= 256);
+
+        let hash = self
+            .state
+            .db()
+            .ancestor_hash(
+                BlockNumber(base_number.0 - 1),
+                self.header.parent_hash,
+                distance - 1,
+            )
+            .await?
+            .context("no header")?;
+
+        Ok(h256_to_u256(hash))
+    }
+
+    fn emit_log(&mut self, log: Log) {
+        self.state.add_log(log);
+    }
+
+    fn access_account(&mut self, address: Address) -> AccessStatus {
+        if self.is_precompiled(address) {
+            AccessStatus::Warm
         } else {
-            let mut max_precompiled = Address::zero();
-            max_precompiled.0[ADDRESS_LENGTH - 1] = self.number_of_precompiles() as u8;
-            contract <= max_precompiled
+            self.state.access_account(address)
         }
     }
+
+    fn access_storage(&mut self, address: Address, key: U256) -> AccessStatus {
+        self.state.access_storage(address, key)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{res::chainspec::MAINNET, util::test_util::run_test, InMemoryState};
+    use crate::{res::chainspec::MAINNET, u256_to_h256, util::test_util::run_test, InMemoryState};
     use bytes_literal::bytes;
     use hex_literal::hex;
 
@@ -1183,4 +1254,104 @@ mod tests {
             );
         })
     }
+
+    #[test]
+    fn custom_opcodes() {
+        run_test(async {
+            use crate::execution::custom_opcodes::*;
+
+            let mut chain_spec = MAINNET.clone();
+            chain_spec.params.chain_id = ChainId(0xc0de);
+
+            let mut opcodes = CustomOpcodes::new(&chain_spec).unwrap();
+            // Push 42.
+            opcodes
+                .register(0x0c, 5, |state| {
+                    state.stack.push(U256::from(42_u8));
+                    true
+                })
+                .unwrap();
+            // Always fails.
+            opcodes.register(0x0d, 5, |_| false).unwrap();
+            set_custom_opcodes(opcodes).unwrap();
+
+            let header = PartialHeader {
+                number: 13_000_000.into(),
+                ..PartialHeader::empty()
+            };
+            let block_spec = chain_spec.collect_block_spec(header.number);
+
+            let caller = hex!("0a6bb546b9208cfab9e8fa2b9b2c042b18df7030").into();
+            let contract = hex!("8b299e2b7d7f43c0ce3068263545309ff4ffb521").into();
+
+            let mut db = InMemoryState::default();
+            let mut state = IntraBlockState::new(&mut db);
+
+            let txn = MessageWithSender {
+                message: Message::Legacy {
+                    chain_id: None,
+                    nonce: 0,
+                    gas_price: U256::ZERO,
+                    gas_limit: 0,
+                    action: TransactionAction::Call(contract),
+                    value: U256::ZERO,
+                    input: Bytes::new(),
+                },
+                sender: caller,
+            };
+
+            // 0      CUSTOM 0c
+            // 1      PUSH1  => 00
+            // 3      MSTORE
+            // 4      PUSH1  => 20
+            // 6      PUSH1  => 00
+            // 8      RETURN
+            state
+                .set_code(contract, hex!("0c60005260206000f3").to_vec().into())
+                .await
+                .unwrap();
+            let res = super::execute(
+                &mut state,
+                None,
+                &mut AnalysisCache::default(),
+                &header,
+                &block_spec,
+                &txn,
+                100,
+            )
+            .await
+            .unwrap();
+            assert_eq!(res.status_code, StatusCode::Success);
+            assert_eq!(res.output_data, u256_to_h256(U256::from(42_u8)).0.to_vec());
+            // 5 + 3 + 3 (MSTORE) + 3 (memory expansion) + 3 + 3
+            assert_eq!(res.gas_left, 100 - 20);
+
+            // 0      CUSTOM 0d
+            state
+                .set_code(contract, hex!("0d").to_vec().into())
+                .await
+                .unwrap();
+            let res = super::execute(
+                &mut state,
+                None,
+                &mut AnalysisCache::default(),
+                &header,
+                &block_spec,
+                &txn,
+                100,
+            )
+            .await
+            .unwrap();
+            assert_ne!(res.status_code, StatusCode::Success);
+            assert_eq!(res.gas_left, 0);
+
+            // Only defined on their chain.
+            state
+                .set_code(contract, hex!("0c").to_vec().into())
+                .await
+                .unwrap();
+            let res = execute(&mut state, &header, &txn, 100).await;
+            assert_eq!(res.status_code, StatusCode::UndefinedInstruction);
+        })
+    }
 }
@@ -0,0 +1,660 @@
+//! Adapter exposing [`HostInterface`] through the [EVMC](https://github.com/ethereum/evmc) ABI version 9,
+//! so that an external VM (evmone, hera) can execute bytecode in place of the native interpreter.
+//! Meant for differential testing.
+//!
+//! ABI version 9 is used since, like evmodin, it leaves refund accounting to the host.
+
+use super::host::HostInterface;
+use crate::models::*;
+use anyhow::{bail, ensure, format_err};
+use bytes::Bytes;
+use evmc_sys as ffi;
+use evmodin::{
+    host::*, CallKind, CreateMessage, Message as EvmMessage, Output, Revision, StatusCode,
+};
+use once_cell::sync::OnceCell;
+use std::{
+    ffi::CStr,
+    future::Future,
+    path::Path,
+    sync::Arc,
+    task::{Context, Poll, Wake, Waker},
+    thread::{self, Thread},
+};
+
+static EXTERNAL_VM: OnceCell<EvmcVm> = OnceCell::new();
+
+/// Execute all bytecode in this process with the given VM instead of the native interpreter.
+pub fn set_external_vm(vm: EvmcVm) -> anyhow::Result<()> {
+    EXTERNAL_VM
+        .set(vm)
+        .map_err(|_| format_err!("external VM already set"))
+}
+
+pub(crate) fn external_vm() -> Option<&'static EvmcVm> {
+    EXTERNAL_VM.get()
+}
+
+/// VM loaded from a shared library. It is expected to allow concurrent execution, as evmone does.
+#[derive(Debug)]
+pub struct EvmcVm {
+    vm: *mut ffi::evmc_vm,
+    _lib: libloading::Library,
+}
+
+unsafe impl Send for EvmcVm {}
+unsafe impl Sync for EvmcVm {}
+
+impl EvmcVm {
+    /// Load VM from shared library, looking up `evmc_create_<name>` (`libevmone.so` => `evmc_create_evmone`),
+    /// then `evmc_create`.
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let name = path
+            .file_name()
+            .and_then(|s| s.to_str())
+            .map(|s| s.trim_start_matches("lib"))
+            .and_then(|s| s.split(&['.', '-'][..]).next())
+            .ok_or_else(|| format_err!("invalid VM path {:?}", path))?;
+
+        unsafe {
+            let lib = libloading::Library::new(path)?;
+            let create = match lib.get::<unsafe extern "C" fn() -> *mut ffi::evmc_vm>(
+                format!("evmc_create_{}", name.replace('-', "_")).as_bytes(),
+            ) {
+                Ok(create) => create,
+                Err(_) => lib.get(b"evmc_create")?,
+            };
+
+            let vm = create();
+            ensure!(!vm.is_null(), "failed to create VM from {:?}", path);
+
+            let abi_version = (*vm).abi_version;
+            if abi_version != ffi::EVMC_ABI_VERSION as i32 {
+                if let Some(destroy) = (*vm).destroy {
+                    destroy(vm);
+                }
+                bail!(
+                    "VM {:?} has EVMC ABI version {}, expected {}",
+                    path,
+                    abi_version,
+                    ffi::EVMC_ABI_VERSION
+                );
+            }
+
+            Ok(Self { vm, _lib: lib })
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        unsafe { CStr::from_ptr((*self.vm).name) }
+            .to_str()
+            .unwrap_or_default()
+    }
+
+    pub fn version(&self) -> &str {
+        unsafe { CStr::from_ptr((*self.vm).version) }
+            .to_str()
+            .unwrap_or_default()
+    }
+
+    /// Execute `code` in the context of `message`. Host futures are polled to completion on the calling thread.
+    pub fn execute<H: HostInterface>(
+        &self,
+        host: &mut H,
+        revision: Revision,
+        message: &EvmMessage,
+        code: &[u8],
+    ) -> anyhow::Result<Output> {
+        let msg = ffi::evmc_message {
+            // Initcode is executed as a call to the new account.
+            kind: match message.kind {
+                CallKind::CallCode => ffi::evmc_call_kind::EVMC_CALLCODE,
+                CallKind::DelegateCall => ffi::evmc_call_kind::EVMC_DELEGATECALL,
+                _ => ffi::evmc_call_kind::EVMC_CALL,
+            },
+            flags: if message.is_static {
+                ffi::evmc_flags::EVMC_STATIC as u32
+            } else {
+                0
+            },
+            depth: message.depth,
+            gas: message.gas,
+            recipient: to_address(message.recipient),
+            sender: to_address(message.sender),
+            input_data: message.input_data.as_ptr(),
+            input_size: message.input_data.len(),
+            value: to_bytes32(message.value),
+            create2_salt: Default::default(),
+        };
+
+        let mut context = HostContext {
+            host,
+            recipient: message.recipient,
+            error: None,
+        };
+        let interface = host_interface::<H>();
+
+        let result = unsafe {
+            let execute = (*self.vm)
+                .execute
+                .ok_or_else(|| format_err!("VM does not implement execute"))?;
+            execute(
+                self.vm,
+                &interface,
+                &mut context as *mut HostContext<H> as *mut ffi::evmc_host_context,
+                to_revision(revision),
+                &msg,
+                code.as_ptr(),
+                code.len(),
+            )
+        };
+
+        let output_data = if result.output_size > 0 {
+            Bytes::copy_from_slice(unsafe {
+                std::slice::from_raw_parts(result.output_data, result.output_size)
+            })
+        } else {
+            Bytes::new()
+        };
+        if let Some(release) = result.release {
+            unsafe { release(&result) };
+        }
+
+        if let Some(e) = context.error {
+            return Err(e);
+        }
+
+        Ok(Output {
+            status_code: from_status_code(result.status_code)?,
+            gas_left: result.gas_left,
+            output_data,
+            create_address: None,
+        })
+    }
+}
+
+impl Drop for EvmcVm {
+    fn drop(&mut self) {
+        unsafe {
+            if let Some(destroy) = (*self.vm).destroy {
+                destroy(self.vm);
+            }
+        }
+    }
+}
+
+struct HostContext<'h, H> {
+    host: &'h mut H,
+    /// Recipient of the message being executed, storage context for nested CALLCODE and DELEGATECALL.
+    recipient: Address,
+    /// First host error. VM is not aware of it, execution result is discarded.
+    error: Option<anyhow::Error>,
+}
+
+impl<'h, H: HostInterface> HostContext<'h, H> {
+    unsafe fn from_ptr<'a>(context: *mut ffi::evmc_host_context) -> &'a mut Self {
+        &mut *(context as *mut Self)
+    }
+}
+
+/// Run host future, saving its error. Once there is an error, the VM only gets default values.
+fn run<T>(
+    error: &mut Option<anyhow::Error>,
+    f: impl Future<Output = anyhow::Result<T>>,
+) -> Option<T> {
+    if error.is_some() {
+        return None;
+    }
+
+    match block_on(f) {
+        Ok(v) => Some(v),
+        Err(e) => {
+            *error = Some(e);
+            None
+        }
+    }
+}
+
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/// EVMC callbacks are synchronous. Host futures only wait on database reads,
+/// so they are simply polled on the current thread.
+fn block_on<F: Future>(f: F) -> F::Output {
+    let mut f = Box::pin(f);
+    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        match f.as_mut().poll(&mut cx) {
+            Poll::Ready(v) => return v,
+            Poll::Pending => thread::park(),
+        }
+    }
+}
+
+fn host_interface<H: HostInterface>() -> ffi::evmc_host_interface {
+    ffi::evmc_host_interface {
+        account_exists: Some(account_exists::<H>),
+        get_storage: Some(get_storage::<H>),
+        set_storage: Some(set_storage::<H>),
+        get_balance: Some(get_balance::<H>),
+        get_code_size: Some(get_code_size::<H>),
+        get_code_hash: Some(get_code_hash::<H>),
+        copy_code: Some(copy_code::<H>),
+        selfdestruct: Some(selfdestruct::<H>),
+        call: Some(call::<H>),
+        get_tx_context: Some(get_tx_context::<H>),
+        get_block_hash: Some(get_block_hash::<H>),
+        emit_log: Some(emit_log::<H>),
+        access_account: Some(access_account::<H>),
+        access_storage: Some(access_storage::<H>),
+    }
+}
+
+fn to_address(address: Address) -> ffi::evmc_address {
+    ffi::evmc_address { bytes: address.0 }
+}
+
+fn from_address(address: &ffi::evmc_address) -> Address {
+    Address::from(address.bytes)
+}
+
+fn to_bytes32(v: U256) -> ffi::evmc_bytes32 {
+    ffi::evmc_bytes32 {
+        bytes: v.to_be_bytes(),
+    }
+}
+
+fn from_bytes32(v: &ffi::evmc_bytes32) -> U256 {
+    U256::from_be_bytes(v.bytes)
+}
+
+fn to_revision(revision: Revision) -> ffi::evmc_revision {
+    match revision {
+        Revision::Frontier => ffi::evmc_revision::EVMC_FRONTIER,
+        Revision::Homestead => ffi::evmc_revision::EVMC_HOMESTEAD,
+        Revision::Tangerine => ffi::evmc_revision::EVMC_TANGERINE_WHISTLE,
+        Revision::Spurious => ffi::evmc_revision::EVMC_SPURIOUS_DRAGON,
+        Revision::Byzantium => ffi::evmc_revision::EVMC_BYZANTIUM,
+        Revision::Constantinople => ffi::evmc_revision::EVMC_CONSTANTINOPLE,
+        Revision::Petersburg => ffi::evmc_revision::EVMC_PETERSBURG,
+        Revision::Istanbul => ffi::evmc_revision::EVMC_ISTANBUL,
+        Revision::Berlin => ffi::evmc_revision::EVMC_BERLIN,
+        Revision::London => ffi::evmc_revision::EVMC_LONDON,
+        Revision::Shanghai => ffi::evmc_revision::EVMC_SHANGHAI,
+    }
+}
+
+fn from_status_code(status_code: ffi::evmc_status_code) -> anyhow::Result<StatusCode> {
+    use ffi::evmc_status_code::*;
+
+    Ok(match status_code {
+        EVMC_SUCCESS => StatusCode::Success,
+        EVMC_FAILURE => StatusCode::Failure,
+        EVMC_REVERT => StatusCode::Revert,
+        EVMC_OUT_OF_GAS => StatusCode::OutOfGas,
+        EVMC_INVALID_INSTRUCTION => StatusCode::InvalidInstruction,
+        EVMC_UNDEFINED_INSTRUCTION => StatusCode::UndefinedInstruction,
+        EVMC_STACK_OVERFLOW => StatusCode::StackOverflow,
+        EVMC_STACK_UNDERFLOW => StatusCode::StackUnderflow,
+        EVMC_BAD_JUMP_DESTINATION => StatusCode::BadJumpDestination,
+        EVMC_INVALID_MEMORY_ACCESS => StatusCode::InvalidMemoryAccess,
+        EVMC_CALL_DEPTH_EXCEEDED => StatusCode::CallDepthExceeded,
+        EVMC_STATIC_MODE_VIOLATION => StatusCode::StaticModeViolation,
+        EVMC_PRECOMPILE_FAILURE => StatusCode::PrecompileFailure,
+        EVMC_CONTRACT_VALIDATION_FAILURE => StatusCode::ContractValidationFailure,
+        EVMC_ARGUMENT_OUT_OF_RANGE => StatusCode::ArgumentOutOfRange,
+        EVMC_INSUFFICIENT_BALANCE => StatusCode::InsufficientBalance,
+        other => bail!("VM error: {:?}", other),
+    })
+}
+
+fn to_status_code(status_code: &StatusCode) -> ffi::evmc_status_code {
+    use ffi::evmc_status_code::*;
+
+    match status_code {
+        StatusCode::Success => EVMC_SUCCESS,
+        StatusCode::Revert => EVMC_REVERT,
+        StatusCode::OutOfGas => EVMC_OUT_OF_GAS,
+        StatusCode::InvalidInstruction => EVMC_INVALID_INSTRUCTION,
+        StatusCode::UndefinedInstruction => EVMC_UNDEFINED_INSTRUCTION,
+        StatusCode::StackOverflow => EVMC_STACK_OVERFLOW,
+        StatusCode::StackUnderflow => EVMC_STACK_UNDERFLOW,
+        StatusCode::BadJumpDestination => EVMC_BAD_JUMP_DESTINATION,
+        StatusCode::InvalidMemoryAccess => EVMC_INVALID_MEMORY_ACCESS,
+        StatusCode::CallDepthExceeded => EVMC_CALL_DEPTH_EXCEEDED,
+        StatusCode::StaticModeViolation => EVMC_STATIC_MODE_VIOLATION,
+        StatusCode::PrecompileFailure => EVMC_PRECOMPILE_FAILURE,
+        StatusCode::ContractValidationFailure => EVMC_CONTRACT_VALIDATION_FAILURE,
+        StatusCode::ArgumentOutOfRange => EVMC_ARGUMENT_OUT_OF_RANGE,
+        StatusCode::InsufficientBalance => EVMC_INSUFFICIENT_BALANCE,
+        _ => EVMC_FAILURE,
+    }
+}
+
+fn to_access_status(status: AccessStatus) -> ffi::evmc_access_status {
+    match status {
+        AccessStatus::Cold => ffi::evmc_access_status::EVMC_ACCESS_COLD,
+        AccessStatus::Warm => ffi::evmc_access_status::EVMC_ACCESS_WARM,
+    }
+}
+
+unsafe extern "C" fn account_exists<H: HostInterface>(
+    context: *mut ffi::evmc_host_context,
+    address: *const ffi::evmc_address,
+) -> bool {
+    let context = HostContext::<H>::from_ptr(context);
+    let address = from_address(&*address);
+    run(&mut context.error, context.host.account_exists(address)).unwrap_or_default()
+}
+
+unsafe extern "C" fn get_storage<H: HostInterface>(
+    context: *mut ffi::evmc_host_context,
+    address: *const ffi::evmc_address,
+    key: *const ffi::evmc_bytes32,
+) -> ffi::evmc_bytes32 {
+    let context = HostContext::<H>::from_ptr(context);
+    let (address, key) = (from_address(&*address), from_bytes32(&*key));
+    to_bytes32(run(&mut context.error, context.host.get_storage(address, key)).unwrap_or_default())
+}
+
+unsafe extern "C" fn set_storage<H: HostInterface>(
+    context: *mut ffi::evmc_host_context,
+    address: *const ffi::evmc_address,
+    key: *const ffi::evmc_bytes32,
+    value: *const ffi::evmc_bytes32,
+) -> ffi::evmc_storage_status {
+    let context = HostContext::<H>::from_ptr(context);
+    let (address, key, value) = (
+        from_address(&*address),
+        from_bytes32(&*key),
+        from_bytes32(&*value),
+    );
+    match run(
+        &mut context.error,
+        context.host.set_storage(address, key, value),
+    ) {
+        None | Some(StorageStatus::Unchanged) => ffi::evmc_storage_status::EVMC_STORAGE_UNCHANGED,
+        Some(StorageStatus::Modified) => ffi::evmc_storage_status::EVMC_STORAGE_MODIFIED,
+        Some(StorageStatus::ModifiedAgain) => ffi::evmc_storage_status::EVMC_STORAGE_MODIFIED_AGAIN,
+        Some(StorageStatus::Added) => ffi::evmc_storage_status::EVMC_STORAGE_ADDED,
+        Some(StorageStatus::Deleted) => ffi::evmc_storage_status::EVMC_STORAGE_DELETED,
+    }
+}
+
+unsafe extern "C" fn get_balance<H: HostInterface>(
+    context: *mut ffi::evmc_host_context,
+    address: *const ffi::evmc_address,
+) -> ffi::evmc_uint256be {
+    let context = HostContext::<H>::from_ptr(context);
+    let address = from_address(&*address);
+    to_bytes32(run(&mut context.error, context.host.get_balance(address)).unwrap_or_default())
+}
+
+unsafe extern "C" fn get_code_size<H: HostInterface>(
+    context: *mut ffi::evmc_host_context,
+    address: *const ffi::evmc_address,
+) -> usize {
+    let context = HostContext::<H>::from_ptr(context);
+    let address = from_address(&*address);
+    run(&mut context.error, context.host.get_code_size(address))
+        .unwrap_or_default()
+        .as_usize()
+}
+
+unsafe extern "C" fn get_code_hash<H: HostInterface>(
+    context: *mut ffi::evmc_host_context,
+    address: *const ffi::evmc_address,
+) -> ffi::evmc_bytes32 {
+    let context = HostContext::<H>::from_ptr(context);
+    let address = from_address(&*address);
+    to_bytes32(run(&mut context.error, context.host.get_code_hash(address)).unwrap_or_default())
+}
+
+unsafe extern "C" fn copy_code<H: HostInterface>(
+    context: *mut ffi::evmc_host_context,
+    address: *const ffi::evmc_address,
+    code_offset: usize,
+    buffer_data: *mut u8,
+    buffer_size: usize,
+) -> usize {
+    let context = HostContext::<H>::from_ptr(context);
+    let address = from_address(&*address);
+    let code = run(
+        &mut context.error,
+        context.host.copy_code(address, code_offset, buffer_size),
+    )
+    .unwrap_or_default();
+    std::slice::from_raw_parts_mut(buffer_data, code.len()).copy_from_slice(&code);
+    code.len()
+}
+
+unsafe extern "C" fn selfdestruct<H: HostInterface>(
+    context: *mut ffi::evmc_host_context,
+    address: *const ffi::evmc_address,
+    beneficiary: *const ffi::evmc_address,
+) {
+    let context = HostContext::<H>::from_ptr(context);
+    let (address, beneficiary) = (from_address(&*address), from_address(&*beneficiary));
+    run(
+        &mut context.error,
+        context.host.selfdestruct(address, beneficiary),
+    )
+    .unwrap_or_default()
+}
+
+unsafe extern "C" fn release_result(result: *const ffi::evmc_result) {
+    let result = &*result;
+    if !result.output_data.is_null() {
+        drop(Box::from_raw(std::slice::from_raw_parts_mut(
+            result.output_data as *mut u8,
+            result.output_size,
+        )));
+    }
+}
+
+unsafe extern "C" fn call<H: HostInterface>(
+    context: *mut ffi::evmc_host_context,
+    msg: *const ffi::evmc_message,
+) -> ffi::evmc_result {
+    let context = HostContext::<H>::from_ptr(context);
+    let msg = &*msg;
+
+    let input_data = if msg.input_size > 0 {
+        Bytes::copy_from_slice(std::slice::from_raw_parts(msg.input_data, msg.input_size))
+    } else {
+        Bytes::new()
+    };
+    let value = from_bytes32(&msg.value);
+    let destination = from_address(&msg.recipient);
+
+    let call = match msg.kind {
+        ffi::evmc_call_kind::EVMC_CREATE | ffi::evmc_call_kind::EVMC_CREATE2 => {
+            Call::Create(CreateMessage {
+                depth: msg.depth,
+                gas: msg.gas,
+                sender: from_address(&msg.sender),
+                initcode: input_data,
+                endowment: value,
+                salt: (msg.kind == ffi::evmc_call_kind::EVMC_CREATE2)
+                    .then(|| from_bytes32(&msg.create2_salt)),
+            })
+        }
+        kind => {
+            // In ABI version 9 recipient of CALLCODE and DELEGATECALL is the code address.
+            let (kind, recipient) = match kind {
+                ffi::evmc_call_kind::EVMC_CALLCODE => (CallKind::CallCode, context.recipient),
+                ffi::evmc_call_kind::EVMC_DELEGATECALL => {
+                    (CallKind::DelegateCall, context.recipient)
+                }
+                _ => (CallKind::Call, destination),
+            };
+            Call::Call(EvmMessage {
+                kind,
+                is_static: msg.flags & ffi::evmc_flags::EVMC_STATIC as u32 != 0,
+                depth: msg.depth,
+                gas: msg.gas,
+                recipient,
+                code_address: destination,
+                sender: from_address(&msg.sender),
+                input_data,
+                value,
+            })
+        }
+    };
+
+    let output = match run(&mut context.error, context.host.call(call)) {
+        Some(output) => output,
+        None => {
+            return ffi::evmc_result {
+                status_code: ffi::evmc_status_code::EVMC_FAILURE,
+                gas_left: 0,
+                output_data: std::ptr::null(),
+                output_size: 0,
+                release: None,
+                create_address: Default::default(),
+                padding: Default::default(),
+            }
+        }
+    };
+
+    let (output_data, output_size) = if output.output_data.is_empty() {
+        (std::ptr::null(), 0)
+    } else {
+        let data = Box::<[u8]>::from(&output.output_data[..]);
+        let size = data.len();
+        (Box::into_raw(data) as *const u8, size)
+    };
+
+    ffi::evmc_result {
+        status_code: to_status_code(&output.status_code),
+        gas_left: output.gas_left,
+        output_data,
+        output_size,
+        release: Some(release_result),
+        create_address: to_address(output.create_address.unwrap_or_default()),
+        padding: Default::default(),
+    }
+}
+
+unsafe extern "C" fn get_tx_context<H: HostInterface>(
+    context: *mut ffi::evmc_host_context,
+) -> ffi::evmc_tx_context {
+    let context = HostContext::<H>::from_ptr(context);
+    match run(&mut context.error, context.host.get_tx_context()) {
+        Some(c) => ffi::evmc_tx_context {
+            tx_gas_price: to_bytes32(c.tx_gas_price),
+            tx_origin: to_address(c.tx_origin),
+            block_coinbase: to_address(c.block_coinbase),
+            block_number: c.block_number as i64,
+            block_timestamp: c.block_timestamp as i64,
+            block_gas_limit: c.block_gas_limit as i64,
+            block_difficulty: to_bytes32(c.block_difficulty),
+            chain_id: to_bytes32(c.chain_id),
+            block_base_fee: to_bytes32(c.block_base_fee),
+        },
+        None => Default::default(),
+    }
+}
+
+unsafe extern "C" fn get_block_hash<H: HostInterface>(
+    context: *mut ffi::evmc_host_context,
+    number: i64,
+) -> ffi::evmc_bytes32 {
+    let context = HostContext::<H>::from_ptr(context);
+    to_bytes32(
+        run(
+            &mut context.error,
+            context.host.get_block_hash(number as u64),
+        )
+        .unwrap_or_default(),
+    )
+}
+
+unsafe extern "C" fn emit_log<H: HostInterface>(
+    context: *mut ffi::evmc_host_context,
+    address: *const ffi::evmc_address,
+    data: *const u8,
+    data_size: usize,
+    topics: *const ffi::evmc_bytes32,
+    topics_count: usize,
+) {
+    let context = HostContext::<H>::from_ptr(context);
+    let log = Log {
+        address: from_address(&*address),
+        topics: if topics_count > 0 {
+            std::slice::from_raw_parts(topics, topics_count)
+                .iter()
+                .map(|topic| H256(topic.bytes))
+                .collect()
+        } else {
+            vec![]
+        },
+        data: if data_size > 0 {
+            Bytes::copy_from_slice(std::slice::from_raw_parts(data, data_size))
+        } else {
+            Bytes::new()
+        },
+    };
+    context.host.emit_log(log)
+}
+
+unsafe extern "C" fn access_account<H: HostInterface>(
+    context: *mut ffi::evmc_host_context,
+    address: *const ffi::evmc_address,
+) -> ffi::evmc_access_status {
+    let context = HostContext::<H>::from_ptr(context);
+    to_access_status(context.host.access_account(from_address(&*address)))
+}
+
+unsafe extern "C" fn access_storage<H: HostInterface>(
+    context: *mut ffi::evmc_host_context,
+    address: *const ffi::evmc_address,
+    key: *const ffi::evmc_bytes32,
+) -> ffi::evmc_access_status {
+    let context = HostContext::<H>::from_ptr(context);
+    to_access_status(
+        context
+            .host
+            .access_storage(from_address(&*address), from_bytes32(&*key)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status_code_roundtrip() {
+        for status_code in [
+            StatusCode::Success,
+            StatusCode::Revert,
+            StatusCode::OutOfGas,
+            StatusCode::InvalidInstruction,
+            StatusCode::StaticModeViolation,
+            StatusCode::InsufficientBalance,
+        ] {
+            assert_eq!(
+                from_status_code(to_status_code(&status_code)).unwrap(),
+                status_code
+            );
+        }
+    }
+
+    #[test]
+    fn value_conversion() {
+        let v = U256::from_str_radix("0102030405060708090a0b0c0d0e0f10", 16).unwrap();
+        let b = to_bytes32(v);
+        assert_eq!(b.bytes[31], 0x10);
+        assert_eq!(b.bytes[16], 0x01);
+        assert_eq!(from_bytes32(&b), v);
+
+        assert_eq!(block_on(async { 42 }), 42);
+    }
+}
@@ -0,0 +1,707 @@
+//! Flat list of traces of executed messages, self-destructs and rewards, in the format of
+//! OpenEthereum's `trace` module.
+//!
+//! Every trace is addressed by its path in the call tree of its transaction: `traceAddress` is
+//! empty for the top-level message, `[0]` for its first subcall, `[0, 1]` for the second subcall
+//! of that one, and so on.
+
+use super::{analysis_cache::AnalysisCache, evm, tracer::*};
+#[cfg(feature = "node")]
+use crate::{
+    accessors::chain,
+    bitmapdb,
+    execution::replay::ReplayBlock,
+    kv::{
+        tables::{self, BitmapKey},
+        traits::*,
+    },
+    state::Buffer,
+};
+use crate::{consensus::RewardKind, hexbytes, models::*, state::IntraBlockState, State};
+use bytes::Bytes;
+#[cfg(feature = "node")]
+use croaring::Treemap;
+use evmodin::StatusCode;
+use serde::Serialize;
+#[cfg(feature = "node")]
+use std::ops::RangeInclusive;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CallType {
+    Call,
+    CallCode,
+    DelegateCall,
+    StaticCall,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CallAction {
+    pub from: Address,
+    pub call_type: CallType,
+    pub gas: U64,
+    #[serde(with = "hexbytes")]
+    pub input: Bytes,
+    pub to: Address,
+    pub value: U256,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateAction {
+    pub from: Address,
+    pub gas: U64,
+    #[serde(with = "hexbytes")]
+    pub init: Bytes,
+    pub value: U256,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SelfdestructAction {
+    pub address: Address,
+    pub refund_address: Address,
+    pub balance: U256,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RewardType {
+    Block,
+    Uncle,
+}
+
+impl From<RewardKind> for RewardType {
+    fn from(kind: RewardKind) -> Self {
+        match kind {
+            RewardKind::Block => Self::Block,
+            RewardKind::Ommer => Self::Uncle,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RewardAction {
+    pub author: Address,
+    pub reward_type: RewardType,
+    pub value: U256,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(untagged)]
+pub enum Action {
+    Call(CallAction),
+    Create(CreateAction),
+    Selfdestruct(SelfdestructAction),
+    Reward(RewardAction),
+}
+
+impl Action {
+    /// Sender and recipient of value or message, as matched by `trace_filter`.
+    pub fn from_to(&self, result: Option<&TraceOutput>) -> (Option<Address>, Option<Address>) {
+        match self {
+            Self::Call(action) => (Some(action.from), Some(action.to)),
+            Self::Create(action) => (
+                Some(action.from),
+                match result {
+                    Some(TraceOutput::Create(output)) => Some(output.address),
+                    _ => None,
+                },
+            ),
+            Self::Selfdestruct(action) => (Some(action.address), Some(action.refund_address)),
+            Self::Reward(action) => (None, Some(action.author)),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CallOutput {
+    pub gas_used: U64,
+    #[serde(with = "hexbytes")]
+    pub output: Bytes,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateOutput {
+    pub address: Address,
+    #[serde(with = "hexbytes")]
+    pub code: Bytes,
+    pub gas_used: U64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(untagged)]
+pub enum TraceOutput {
+    Call(CallOutput),
+    Create(CreateOutput),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FlatTrace {
+    pub action: Action,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// `None` for failed messages, self-destructs and rewards.
+    pub result: Option<TraceOutput>,
+    pub subtraces: usize,
+    pub trace_address: Vec<usize>,
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+}
+
+/// [`FlatTrace`] with its position in the chain, as returned by `trace_block` and others.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LocatedTrace {
+    #[serde(flatten)]
+    pub trace: FlatTrace,
+    pub block_hash: H256,
+    pub block_number: u64,
+    /// `None` for rewards.
+    pub transaction_hash: Option<H256>,
+    pub transaction_position: Option<usize>,
+}
+
+fn error_message(status_code: &StatusCode) -> String {
+    match status_code {
+        StatusCode::Revert => "Reverted".to_string(),
+        StatusCode::OutOfGas => "Out of gas".to_string(),
+        StatusCode::BadJumpDestination => "Bad jump destination".to_string(),
+        StatusCode::StackOverflow | StatusCode::CallDepthExceeded => "Out of stack".to_string(),
+        StatusCode::StackUnderflow => "Stack underflow".to_string(),
+        StatusCode::InvalidInstruction | StatusCode::UndefinedInstruction => {
+            "Bad instruction".to_string()
+        }
+        StatusCode::StaticModeViolation => "Mutable Call In Static Context".to_string(),
+        other => format!("{:?}", other),
+    }
+}
+
+#[derive(Debug)]
+struct Frame {
+    /// Index into traces.
+    trace: usize,
+    gas: u64,
+    /// Recipient of the message, or address of the created contract.
+    to: Address,
+}
+
+/// Records [`FlatTrace`]s of all transactions executed with it, in execution order.
+#[derive(Debug, Default)]
+pub struct FlatTracer {
+    traces: Vec<(Option<usize>, FlatTrace)>,
+    stack: Vec<Frame>,
+    transactions: usize,
+}
+
+impl FlatTracer {
+    /// Traces with position of their transaction among traced ones, `None` for rewards.
+    pub fn into_traces(self) -> Vec<(Option<usize>, FlatTrace)> {
+        self.traces
+    }
+
+    /// Append a trace nested in the current frame, if any.
+    fn push(&mut self, action: Action, kind: &'static str) -> usize {
+        let mut trace_address = vec![];
+        let mut transaction = self.transactions.checked_sub(1);
+        if let Some(frame) = self.stack.last() {
+            let (parent_transaction, parent) = &mut self.traces[frame.trace];
+            trace_address = parent.trace_address.clone();
+            trace_address.push(parent.subtraces);
+            parent.subtraces += 1;
+            transaction = *parent_transaction;
+        } else if kind == "reward" {
+            transaction = None;
+        }
+
+        self.traces.push((
+            transaction,
+            FlatTrace {
+                action,
+                error: None,
+                result: None,
+                subtraces: 0,
+                trace_address,
+                kind,
+            },
+        ));
+        self.traces.len() - 1
+    }
+}
+
+impl Tracer for FlatTracer {
+    fn capture_start(
+        &mut self,
+        depth: u16,
+        from: Address,
+        to: Address,
+        call_type: MessageKind,
+        input: Bytes,
+        gas: u64,
+        value: U256,
+    ) {
+        if depth == 0 {
+            self.stack.clear();
+            self.transactions += 1;
+        }
+
+        let (action, kind) = match call_type {
+            MessageKind::Create => (
+                Action::Create(CreateAction {
+                    from,
+                    gas: gas.into(),
+                    init: input,
+                    value,
+                }),
+                "create",
+            ),
+            MessageKind::Call { call_kind, .. } => (
+                Action::Call(CallAction {
+                    from,
+                    call_type: match call_kind {
+                        CallKind::Call => CallType::Call,
+                        CallKind::CallCode => CallType::CallCode,
+                        CallKind::DelegateCall => CallType::DelegateCall,
+                        CallKind::StaticCall => CallType::StaticCall,
+                    },
+                    gas: gas.into(),
+                    input,
+                    to,
+                    value,
+                }),
+                "call",
+            ),
+        };
+
+        let trace = self.push(action, kind);
+        self.stack.push(Frame { trace, gas, to });
+    }
+
+    fn capture_end(&mut self, _: u16, output: Bytes, gas_left: u64, err: StatusCode) {
+        let frame = match self.stack.pop() {
+            Some(frame) => frame,
+            None => return,
+        };
+
+        let trace = &mut self.traces[frame.trace].1;
+        if err != StatusCode::Success {
+            trace.error = Some(error_message(&err));
+            return;
+        }
+
+        let gas_used = frame.gas.saturating_sub(gas_left).into();
+        trace.result = Some(if matches!(trace.action, Action::Create(_)) {
+            TraceOutput::Create(CreateOutput {
+                address: frame.to,
+                code: output,
+                gas_used,
+            })
+        } else {
+            TraceOutput::Call(CallOutput { gas_used, output })
+        });
+    }
+
+    fn capture_self_destruct(&mut self, caller: Address, beneficiary: Address, balance: U256) {
+        self.push(
+            Action::Selfdestruct(SelfdestructAction {
+                address: caller,
+                refund_address: beneficiary,
+                balance,
+            }),
+            "suicide",
+        );
+    }
+
+    fn capture_reward(&mut self, beneficiary: Address, amount: U256, kind: RewardKind) {
+        self.stack.clear();
+        self.push(
+            Action::Reward(RewardAction {
+                author: beneficiary,
+                reward_type: kind.into(),
+                value: amount,
+            }),
+            "reward",
+        );
+    }
+}
+
+/// Execute `txn` and return its output and traces. State changes are not written.
+pub async fn trace<S: State>(
+    state: &mut IntraBlockState<'_, S>,
+    header: &PartialHeader,
+    block_spec: &BlockExecutionSpec,
+    txn: &MessageWithSender,
+) -> anyhow::Result<(Bytes, Vec<FlatTrace>)> {
+    state.access_account(txn.sender);
+    if let TransactionAction::Call(to) = txn.action() {
+        state.access_account(to);
+    }
+
+    let mut tracer = FlatTracer::default();
+    let res = evm::execute(
+        state,
+        Some(&mut tracer),
+        &mut AnalysisCache::default(),
+        header,
+        block_spec,
+        txn,
+        txn.gas_limit(),
+    )
+    .await?;
+
+    Ok((
+        res.output_data,
+        tracer
+            .into_traces()
+            .into_iter()
+            .map(|(_, trace)| trace)
+            .collect(),
+    ))
+}
+
+/// Run [`trace`] on top of the state after canonical block `block_number`.
+#[cfg(feature = "node")]
+pub async fn trace_call<'db, Tx: Transaction<'db>>(
+    tx: &Tx,
+    block_number: BlockNumber,
+    txn: &MessageWithSender,
+) -> anyhow::Result<(Bytes, Vec<FlatTrace>)> {
+    let chain_spec = chain::chain_spec::read(tx).await?;
+
+    let header = chain::header::read_canonical(tx, block_number).await?;
+
+    let mut buffer = Buffer::new(tx, BlockNumber(0), Some(block_number));
+    let mut state = IntraBlockState::new(&mut buffer);
+
+    trace(
+        &mut state,
+        &PartialHeader::from(header),
+        &chain_spec.collect_block_spec(block_number),
+        txn,
+    )
+    .await
+}
+
+/// Traces of canonical block `number`, including rewards.
+#[cfg(feature = "node")]
+pub async fn trace_block<'db, Tx: Transaction<'db>>(
+    tx: &Tx,
+    number: BlockNumber,
+) -> anyhow::Result<Option<Vec<LocatedTrace>>> {
+    let hash = match chain::canonical_hash::read(tx, number).await? {
+        Some(hash) => hash,
+        None => return Ok(None),
+    };
+    let block = ReplayBlock::load(tx, hash, number).await?;
+
+    let mut tracer = FlatTracer::default();
+    block.replay_block(tx, &mut tracer).await?;
+    let transaction_hashes = block.transaction_hashes(tx).await?;
+
+    Ok(Some(
+        tracer
+            .into_traces()
+            .into_iter()
+            .map(|(position, trace)| LocatedTrace {
+                trace,
+                block_hash: hash,
+                block_number: number.0,
+                transaction_hash: position.map(|position| transaction_hashes[position]),
+                transaction_position: position,
+            })
+            .collect(),
+    ))
+}
+
+/// Traces of canonical transaction `hash`.
+#[cfg(feature = "node")]
+pub async fn trace_transaction<'db, Tx: Transaction<'db>>(
+    tx: &Tx,
+    hash: H256,
+) -> anyhow::Result<Option<Vec<LocatedTrace>>> {
+    let (number, block_hash, index, _) = match chain::canonical_tx::read(tx, hash).await? {
+        Some(found) => found,
+        None => return Ok(None),
+    };
+    let block = ReplayBlock::load(tx, block_hash, number).await?;
+
+    let mut tracer = FlatTracer::default();
+    block.replay(tx, index, Some(&mut tracer), None).await?;
+
+    Ok(Some(
+        tracer
+            .into_traces()
+            .into_iter()
+            .map(|(_, trace)| LocatedTrace {
+                trace,
+                block_hash,
+                block_number: number.0,
+                transaction_hash: Some(hash),
+                transaction_position: Some(index),
+            })
+            .collect(),
+    ))
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TraceFilter {
+    pub from_block: BlockNumber,
+    pub to_block: BlockNumber,
+    /// Any sender, if empty.
+    pub from_address: Vec<Address>,
+    /// Any recipient, if empty.
+    pub to_address: Vec<Address>,
+    /// Number of matching traces to skip.
+    pub after: usize,
+    pub count: Option<usize>,
+}
+
+impl TraceFilter {
+    pub fn matches(&self, trace: &FlatTrace) -> bool {
+        let (from, to) = trace.action.from_to(trace.result.as_ref());
+        let matches = |addresses: &[Address], address: Option<Address>| {
+            addresses.is_empty() || address.map_or(false, |address| addresses.contains(&address))
+        };
+
+        matches(&self.from_address, from) && matches(&self.to_address, to)
+    }
+}
+
+/// Blocks in `range` where any of `addresses` appears in call traces.
+#[cfg(feature = "node")]
+async fn indexed_blocks<'db, Tx, T>(
+    tx: &Tx,
+    table: T,
+    addresses: &[Address],
+    range: RangeInclusive<BlockNumber>,
+) -> anyhow::Result<Treemap>
+where
+    Tx: Transaction<'db>,
+    T: Table<Key = BitmapKey<Address>, Value = Treemap, SeekKey = BitmapKey<Address>> + Copy,
+{
+    let mut blocks = Treemap::create();
+    for &address in addresses {
+        blocks = blocks | bitmapdb::get(tx, table, address, range.clone()).await?;
+    }
+    Ok(blocks)
+}
+
+/// Traces matching `filter`, from the call trace index where addresses are given.
+#[cfg(feature = "node")]
+pub async fn trace_filter<'db, Tx: Transaction<'db>>(
+    tx: &Tx,
+    filter: &TraceFilter,
+) -> anyhow::Result<Vec<LocatedTrace>> {
+    // Genesis is not executed.
+    let range = BlockNumber(filter.from_block.0.max(1))..=filter.to_block;
+
+    let mut indexed = None;
+    if !filter.from_address.is_empty() {
+        indexed = Some(
+            indexed_blocks(
+                tx,
+                tables::CallFromIndex,
+                &filter.from_address,
+                range.clone(),
+            )
+            .await?,
+        );
+    }
+    if !filter.to_address.is_empty() {
+        let to = indexed_blocks(tx, tables::CallToIndex, &filter.to_address, range.clone()).await?;
+        indexed = Some(match indexed {
+            Some(from) => from & to,
+            None => to,
+        });
+    }
+    let blocks = match indexed {
+        Some(indexed) => indexed
+            .iter()
+            .map(BlockNumber)
+            .filter(|block| range.contains(block))
+            .collect::<Vec<_>>(),
+        None => (range.start().0..=range.end().0).map(BlockNumber).collect(),
+    };
+
+    let mut skip = filter.after;
+    let mut out = vec![];
+    for block in blocks {
+        for trace in trace_block(tx, block).await?.unwrap_or_default() {
+            if filter.count.map_or(false, |count| out.len() >= count) {
+                return Ok(out);
+            }
+            if !filter.matches(&trace.trace) {
+                continue;
+            }
+            if skip > 0 {
+                skip -= 1;
+                continue;
+            }
+            out.push(trace);
+        }
+    }
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{res::chainspec::MAINNET, util::test_util::run_test, InMemoryState};
+    use hex_literal::hex;
+
+    #[test]
+    fn traces_nested_calls_and_selfdestruct() {
+        run_test(async {
+            let header = PartialHeader {
+                number: 13_000_000.into(),
+                ..PartialHeader::empty()
+            };
+            let block_spec = MAINNET.collect_block_spec(header.number);
+
+            let caller = hex!("5ff137d4b0fdcd49dca30c7cf57e578a026d2789").into();
+            let callee: Address = hex!("0a6bb546b9208cfab9e8fa2b9b2c042b18df7030").into();
+            let beneficiary: Address = hex!("00000000000000000000000000000000000000be").into();
+            let sender = hex!("b685342b8c54347aad148e1f22eff3eb3eb29391").into();
+
+            let mut db = InMemoryState::default();
+            let mut state = IntraBlockState::new(&mut db);
+
+            // 0      PUSH1  => 00  // retSize
+            // 2      PUSH1  => 00  // retOffset
+            // 4      PUSH1  => 00  // argsSize
+            // 6      PUSH1  => 00  // argsOffset
+            // 8      PUSH1  => 00  // value
+            // 10     PUSH20 => callee
+            // 31     GAS
+            // 32     CALL
+            // 33     STOP
+            let mut code = hex!("60006000600060006000").to_vec();
+            code.push(0x73);
+            code.extend_from_slice(callee.as_bytes());
+            code.extend_from_slice(&hex!("5af100"));
+            state.set_code(caller, code.into()).await.unwrap();
+
+            // 0      PUSH20 => beneficiary
+            // 21     SELFDESTRUCT
+            let mut code = vec![0x73];
+            code.extend_from_slice(beneficiary.as_bytes());
+            code.push(0xff);
+            state.set_code(callee, code.into()).await.unwrap();
+            state.add_to_balance(callee, 7.as_u256()).await.unwrap();
+
+            let txn = MessageWithSender {
+                message: Message::Legacy {
+                    chain_id: Some(block_spec.params.chain_id),
+                    nonce: 0,
+                    gas_price: U256::ZERO,
+                    gas_limit: 100_000,
+                    action: TransactionAction::Call(caller),
+                    value: U256::ZERO,
+                    input: Bytes::new(),
+                },
+                sender,
+            };
+
+            let (_, traces) = trace(&mut state, &header, &block_spec, &txn).await.unwrap();
+
+            assert_eq!(traces.len(), 3);
+
+            assert_eq!(traces[0].kind, "call");
+            assert_eq!(traces[0].trace_address, Vec::<usize>::new());
+            assert_eq!(traces[0].subtraces, 1);
+            assert!(matches!(&traces[0].result, Some(TraceOutput::Call(_))));
+
+            assert_eq!(traces[1].kind, "call");
+            assert_eq!(traces[1].trace_address, vec![0]);
+            assert_eq!(traces[1].subtraces, 1);
+            match &traces[1].action {
+                Action::Call(action) => {
+                    assert_eq!(action.from, caller);
+                    assert_eq!(action.to, callee);
+                    assert_eq!(action.call_type, CallType::Call);
+                }
+                other => panic!("unexpected action {:?}", other),
+            }
+
+            assert_eq!(traces[2].kind, "suicide");
+            assert_eq!(traces[2].trace_address, vec![0, 0]);
+            assert_eq!(
+                traces[2].action,
+                Action::Selfdestruct(SelfdestructAction {
+                    address: callee,
+                    refund_address: beneficiary,
+                    balance: 7.as_u256(),
+                })
+            );
+            assert_eq!(traces[2].result, None);
+        })
+    }
+
+    #[test]
+    fn rewards_and_filter() {
+        let author = Address::repeat_byte(0xaa);
+        let ommer_author = Address::repeat_byte(0xbb);
+
+        let mut tracer = FlatTracer::default();
+        tracer.capture_start(
+            0,
+            Address::repeat_byte(1),
+            Address::repeat_byte(2),
+            MessageKind::Call {
+                call_kind: CallKind::Call,
+                code_kind: CodeKind::Bytecode(None),
+            },
+            Bytes::new(),
+            1_000,
+            U256::ZERO,
+        );
+        tracer.capture_end(0, Bytes::new(), 400, StatusCode::Revert);
+        tracer.capture_reward(ommer_author, 1.as_u256(), RewardKind::Ommer);
+        tracer.capture_reward(author, 2.as_u256(), RewardKind::Block);
+
+        let traces = tracer.into_traces();
+        assert_eq!(
+            traces
+                .iter()
+                .map(|(position, _)| *position)
+                .collect::<Vec<_>>(),
+            [Some(0), None, None]
+        );
+        assert_eq!(traces[0].1.error.as_deref(), Some("Reverted"));
+        assert_eq!(traces[0].1.result, None);
+        assert_eq!(
+            traces[1].1.action,
+            Action::Reward(RewardAction {
+                author: ommer_author,
+                reward_type: RewardType::Uncle,
+                value: 1.as_u256(),
+            })
+        );
+
+        let filter = TraceFilter {
+            to_address: vec![author],
+            ..Default::default()
+        };
+        assert_eq!(
+            traces
+                .iter()
+                .filter(|(_, trace)| filter.matches(trace))
+                .count(),
+            1
+        );
+
+        let filter = TraceFilter {
+            from_address: vec![Address::repeat_byte(1)],
+            to_address: vec![Address::repeat_byte(2)],
+            ..Default::default()
+        };
+        assert!(filter.matches(&traces[0].1));
+        assert!(!filter.matches(&traces[2].1));
+    }
+}
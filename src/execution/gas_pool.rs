@@ -0,0 +1,65 @@
+use crate::consensus::ValidationError;
+
+/// Gas still available to transactions of a block.
+///
+/// Gas limit of each transaction is reserved before execution and unused gas is returned after,
+/// so that a block builder can tell whether another transaction fits.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GasPool {
+    gas: u64,
+}
+
+impl GasPool {
+    pub fn new(gas: u64) -> Self {
+        Self { gas }
+    }
+
+    pub fn gas(&self) -> u64 {
+        self.gas
+    }
+
+    /// Reserve gas for transaction number `txn` in block.
+    pub fn sub_gas(&mut self, txn: usize, required: u64) -> Result<(), ValidationError> {
+        self.gas =
+            self.gas
+                .checked_sub(required)
+                .ok_or(ValidationError::BlockGasLimitExceeded {
+                    txn,
+                    available: self.gas,
+                    required,
+                })?;
+
+        Ok(())
+    }
+
+    pub fn add_gas(&mut self, gas: u64) {
+        self.gas += gas;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gas_pool() {
+        let mut pool = GasPool::new(100_000);
+
+        pool.sub_gas(0, 60_000).unwrap();
+        assert_eq!(pool.gas(), 40_000);
+
+        assert_eq!(
+            pool.sub_gas(1, 50_000),
+            Err(ValidationError::BlockGasLimitExceeded {
+                txn: 1,
+                available: 40_000,
+                required: 50_000,
+            })
+        );
+        assert_eq!(pool.gas(), 40_000);
+
+        pool.add_gas(39_000);
+        pool.sub_gas(1, 50_000).unwrap();
+        assert_eq!(pool.gas(), 29_000);
+    }
+}
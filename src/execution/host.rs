@@ -0,0 +1,339 @@
+use crate::{models::*, u256_to_h256};
+use async_trait::async_trait;
+use bytes::Bytes;
+use evmodin::{
+    continuation::{interrupt::*, interrupt_data::*, resume_data::*, Interrupt},
+    host::*,
+    ExecutionState, OpCode, Output, StatusCode,
+};
+use std::sync::Arc;
+
+/// Change of execution state applied before the instruction it is returned for.
+pub type StateModifier = Option<Arc<dyn Fn(&mut ExecutionState) + Send + Sync>>;
+
+/// Everything the interpreter may suspend on.
+///
+/// Implement this to run EVM code against a custom backend, then pass started
+/// execution to [`run`] instead of matching on interrupts by hand.
+/// [`super::evm::execute`] drives the canonical implementation, backed by [`crate::IntraBlockState`] -
+/// use it with [`crate::state::Buffer`] to execute against a database transaction.
+#[async_trait]
+pub trait HostInterface: Send {
+    /// Called before each instruction if execution was started with instruction tracing.
+    fn instruction_start(
+        &mut self,
+        _depth: u16,
+        _pc: usize,
+        _opcode: OpCode,
+        _state: &ExecutionState,
+    ) -> StateModifier {
+        None
+    }
+
+    async fn account_exists(&mut self, address: Address) -> anyhow::Result<bool>;
+    async fn get_balance(&mut self, address: Address) -> anyhow::Result<U256>;
+    async fn get_code_size(&mut self, address: Address) -> anyhow::Result<U256>;
+    async fn get_storage(&mut self, address: Address, key: U256) -> anyhow::Result<U256>;
+    /// Write storage, updating refunds, and report how the slot changed.
+    async fn set_storage(
+        &mut self,
+        address: Address,
+        key: U256,
+        value: U256,
+    ) -> anyhow::Result<StorageStatus>;
+    async fn get_code_hash(&mut self, address: Address) -> anyhow::Result<U256>;
+    /// At most `max_size` bytes of code starting at `offset`, not padded.
+    async fn copy_code(
+        &mut self,
+        address: Address,
+        offset: usize,
+        max_size: usize,
+    ) -> anyhow::Result<Bytes>;
+    /// `SELFDESTRUCT` of `address`, whose balance goes to `beneficiary`.
+    async fn selfdestruct(&mut self, address: Address, beneficiary: Address) -> anyhow::Result<()>;
+    /// Execute nested message.
+    async fn call(&mut self, call: Call) -> anyhow::Result<Output>;
+    async fn get_tx_context(&mut self) -> anyhow::Result<TxContext>;
+    async fn get_block_hash(&mut self, block_number: u64) -> anyhow::Result<U256>;
+    /// Record emitted by one of `LOG0`..`LOG4`.
+    fn emit_log(&mut self, log: Log);
+    fn access_account(&mut self, address: Address) -> AccessStatus;
+    fn access_storage(&mut self, address: Address, key: U256) -> AccessStatus;
+}
+
+/// Serve interrupts of started execution at `depth` until it completes.
+pub async fn run<H: HostInterface + ?Sized>(
+    host: &mut H,
+    depth: u16,
+    mut interrupt: InterruptVariant,
+) -> anyhow::Result<Output> {
+    loop {
+        interrupt = match interrupt {
+            InterruptVariant::InstructionStart(data, i) => {
+                let modifier = host.instruction_start(depth, data.pc, data.opcode, &data.state);
+                i.resume(modifier)
+            }
+            InterruptVariant::AccountExists(data, i) => {
+                let exists = host.account_exists(data.address).await?;
+                i.resume(AccountExistsStatus { exists })
+            }
+            InterruptVariant::GetBalance(data, i) => {
+                let balance = host.get_balance(data.address).await?;
+                i.resume(Balance { balance })
+            }
+            InterruptVariant::GetCodeSize(data, i) => {
+                let code_size = host.get_code_size(data.address).await?;
+                i.resume(CodeSize { code_size })
+            }
+            InterruptVariant::GetStorage(data, i) => {
+                let value = host.get_storage(data.address, data.key).await?;
+                i.resume(StorageValue { value })
+            }
+            InterruptVariant::SetStorage(
+                SetStorage {
+                    address,
+                    key,
+                    value,
+                },
+                i,
+            ) => {
+                let status = host.set_storage(address, key, value).await?;
+                i.resume(StorageStatusInfo { status })
+            }
+            InterruptVariant::GetCodeHash(data, i) => {
+                let hash = host.get_code_hash(data.address).await?;
+                i.resume(CodeHash { hash })
+            }
+            InterruptVariant::CopyCode(
+                CopyCode {
+                    address,
+                    offset,
+                    max_size,
+                },
+                i,
+            ) => {
+                let code = host.copy_code(address, offset, max_size).await?;
+                i.resume(Code { code })
+            }
+            InterruptVariant::Selfdestruct(data, i) => {
+                host.selfdestruct(data.address, data.beneficiary).await?;
+                i.resume(())
+            }
+            InterruptVariant::Call(data, i) => {
+                let is_create = matches!(data, Call::Create(_));
+                let mut output = host.call(data).await?;
+
+                // https://eips.ethereum.org/EIPS/eip-211
+                if is_create && output.status_code != StatusCode::Revert {
+                    // geth returns CREATE output only in case of REVERT
+                    output.output_data = Default::default();
+                }
+
+                i.resume(CallOutput { output })
+            }
+            InterruptVariant::GetTxContext(i) => {
+                let context = host.get_tx_context().await?;
+                i.resume(TxContextData { context })
+            }
+            InterruptVariant::GetBlockHash(data, i) => {
+                let hash = host.get_block_hash(data.block_number).await?;
+                i.resume(BlockHash { hash })
+            }
+            InterruptVariant::EmitLog(data, i) => {
+                host.emit_log(Log {
+                    address: data.address,
+                    topics: data.topics.into_iter().map(u256_to_h256).collect(),
+                    data: data.data,
+                });
+                i.resume(())
+            }
+            InterruptVariant::AccessAccount(data, i) => {
+                let status = host.access_account(data.address);
+                i.resume(AccessAccountStatus { status })
+            }
+            InterruptVariant::AccessStorage(data, i) => {
+                let status = host.access_storage(data.address, data.key);
+                i.resume(AccessStorageStatus { status })
+            }
+            InterruptVariant::Complete(res, _) => {
+                return Ok(match res {
+                    Ok(output) => output.into(),
+                    Err(status_code) => Output {
+                        status_code,
+                        gas_left: 0,
+                        output_data: Bytes::new(),
+                        create_address: None,
+                    },
+                });
+            }
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use evmodin::{AnalyzedCode, CallKind, Message as EvmMessage, Revision};
+    use hex_literal::hex;
+    use std::collections::HashMap;
+
+    /// Bare storage of accounts without balance, recording logs and self-destructs.
+    #[derive(Default)]
+    struct StorageHost {
+        storage: HashMap<(Address, U256), U256>,
+        logs: Vec<Log>,
+        selfdestructs: Vec<(Address, Address)>,
+    }
+
+    #[async_trait]
+    impl HostInterface for StorageHost {
+        async fn account_exists(&mut self, _: Address) -> anyhow::Result<bool> {
+            unreachable!()
+        }
+        async fn get_balance(&mut self, _: Address) -> anyhow::Result<U256> {
+            Ok(U256::ZERO)
+        }
+        async fn get_code_size(&mut self, _: Address) -> anyhow::Result<U256> {
+            unreachable!()
+        }
+        async fn get_storage(&mut self, address: Address, key: U256) -> anyhow::Result<U256> {
+            Ok(self
+                .storage
+                .get(&(address, key))
+                .copied()
+                .unwrap_or(U256::ZERO))
+        }
+        async fn set_storage(
+            &mut self,
+            address: Address,
+            key: U256,
+            value: U256,
+        ) -> anyhow::Result<StorageStatus> {
+            Ok(match self.storage.insert((address, key), value) {
+                None => StorageStatus::Added,
+                Some(old) if old == value => StorageStatus::Unchanged,
+                Some(_) => StorageStatus::Modified,
+            })
+        }
+        async fn get_code_hash(&mut self, _: Address) -> anyhow::Result<U256> {
+            unreachable!()
+        }
+        async fn copy_code(&mut self, _: Address, _: usize, _: usize) -> anyhow::Result<Bytes> {
+            unreachable!()
+        }
+        async fn selfdestruct(
+            &mut self,
+            address: Address,
+            beneficiary: Address,
+        ) -> anyhow::Result<()> {
+            self.selfdestructs.push((address, beneficiary));
+            Ok(())
+        }
+        async fn call(&mut self, _: Call) -> anyhow::Result<Output> {
+            unreachable!()
+        }
+        async fn get_tx_context(&mut self) -> anyhow::Result<TxContext> {
+            unreachable!()
+        }
+        async fn get_block_hash(&mut self, _: u64) -> anyhow::Result<U256> {
+            unreachable!()
+        }
+        fn emit_log(&mut self, log: Log) {
+            self.logs.push(log);
+        }
+        fn access_account(&mut self, _: Address) -> AccessStatus {
+            AccessStatus::Warm
+        }
+        fn access_storage(&mut self, _: Address, _: U256) -> AccessStatus {
+            AccessStatus::Warm
+        }
+    }
+
+    #[tokio::test]
+    async fn custom_host() {
+        let contract = hex!("8b299e2b7d7f43c0ce3068263545309ff4ffb521").into();
+
+        // 0      PUSH1  => 2a
+        // 2      PUSH1  => 05
+        // 4      SSTORE
+        // 5      PUSH1  => 05
+        // 7      SLOAD
+        // 8      PUSH1  => 00
+        // 10     MSTORE
+        // 11     PUSH1  => 20
+        // 13     PUSH1  => 00
+        // 15     RETURN
+        let code = AnalyzedCode::analyze(hex!("602a60055560055460005260206000f3").to_vec());
+
+        let message = EvmMessage {
+            kind: CallKind::Call,
+            is_static: false,
+            depth: 0,
+            gas: 100_000,
+            recipient: contract,
+            code_address: contract,
+            sender: Address::zero(),
+            input_data: Bytes::new(),
+            value: U256::ZERO,
+        };
+
+        let mut host = StorageHost::default();
+        let interrupt = code
+            .execute_resumable(false, message, Revision::London)
+            .resume(());
+        let output = run(&mut host, 0, interrupt).await.unwrap();
+
+        assert_eq!(output.status_code, StatusCode::Success);
+        assert_eq!(
+            output.output_data,
+            Bytes::from(u256_to_h256(0x2a.as_u256()).0.to_vec())
+        );
+        assert_eq!(host.storage[&(contract, 5.as_u256())], 0x2a.as_u256());
+    }
+
+    #[tokio::test]
+    async fn log_and_selfdestruct() {
+        let contract = hex!("8b299e2b7d7f43c0ce3068263545309ff4ffb521").into();
+        let beneficiary = Address::from_low_u64_be(0xbe);
+
+        // 0      PUSH1  => 2a
+        // 2      PUSH1  => 00
+        // 4      MSTORE
+        // 5      PUSH1  => 01
+        // 7      PUSH1  => 20
+        // 9      PUSH1  => 00
+        // 11     LOG1
+        // 12     PUSH1  => be
+        // 14     SELFDESTRUCT
+        let code = AnalyzedCode::analyze(hex!("602a600052600160206000a160beff").to_vec());
+
+        let message = EvmMessage {
+            kind: CallKind::Call,
+            is_static: false,
+            depth: 0,
+            gas: 100_000,
+            recipient: contract,
+            code_address: contract,
+            sender: Address::zero(),
+            input_data: Bytes::new(),
+            value: U256::ZERO,
+        };
+
+        let mut host = StorageHost::default();
+        let interrupt = code
+            .execute_resumable(false, message, Revision::London)
+            .resume(());
+        let output = run(&mut host, 0, interrupt).await.unwrap();
+
+        assert_eq!(output.status_code, StatusCode::Success);
+        assert_eq!(
+            host.logs,
+            vec![Log {
+                address: contract,
+                topics: vec![u256_to_h256(0x01.as_u256())],
+                data: Bytes::from(u256_to_h256(0x2a.as_u256()).0.to_vec()),
+            }]
+        );
+        assert_eq!(host.selfdestructs, vec![(contract, beneficiary)]);
+    }
+}
@@ -3,9 +3,32 @@ use crate::{consensus, crypto::*, models::*, State};
 
 pub mod address;
 pub mod analysis_cache;
+pub mod call_tracer;
+pub mod contract_gas;
+pub mod custom_opcodes;
+pub mod eip3155;
+pub mod erc4337;
+pub mod estimate;
 pub mod evm;
+#[cfg(feature = "evmc")]
+pub mod evmc;
+pub mod flat_trace;
+pub mod gas_pool;
+pub mod host;
 pub mod precompiled;
+pub mod prestate_tracer;
 pub mod processor;
+pub mod profiler;
+#[cfg(feature = "node")]
+pub mod receipts;
+#[cfg(feature = "node")]
+pub mod replay;
+#[cfg(feature = "node")]
+pub mod side_chain;
+pub mod signatures;
+#[cfg(feature = "node")]
+pub mod simulate;
+pub mod struct_logger;
 pub mod tracer;
 
 pub async fn execute_block<S: State>(
@@ -35,7 +58,7 @@ mod tests {
     use super::{address::create_address, *};
     use crate::{
         chain::protocol_param::param, crypto::root_hash, res::chainspec::MAINNET,
-        util::test_util::run_test, InMemoryState,
+        util::test_util::run_test, InMemoryState, StateReader, StateWriter,
     };
     use hex_literal::hex;
     use sha3::{Digest, Keccak256};
@@ -89,6 +112,7 @@ mod tests {
                 number: block_number,
                 beneficiary: miner,
                 gas_limit: 100_000,
+                difficulty: U256::ONE,
                 gas_used,
                 receipts_root: root_hash(&receipts),
                 ..PartialHeader::empty()
@@ -143,6 +167,7 @@ mod tests {
                 &BlockBodyWithSenders {
                     transactions: vec![tx.clone()],
                     ommers: vec![],
+                    withdrawals: None,
                 },
             )
             .await
@@ -201,6 +226,7 @@ mod tests {
                 &BlockBodyWithSenders {
                     transactions: vec![tx],
                     ommers: vec![],
+                    withdrawals: None,
                 },
             )
             .await
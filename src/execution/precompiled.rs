@@ -1,7 +1,6 @@
-use crate::{chain::protocol_param::param, crypto::*, models::*, util::*};
+use crate::{crypto::*, models::*, util::*};
 use arrayref::array_ref;
 use bytes::{Buf, Bytes};
-use evmodin::Revision;
 use num_bigint::BigUint;
 use num_traits::Zero;
 use ripemd::*;
@@ -19,7 +18,7 @@ use std::{
 };
 use substrate_bn::*;
 
-pub type GasFunction = fn(Bytes, Revision) -> Option<u64>;
+pub type GasFunction = fn(Bytes, &PrecompilePricing) -> Option<u64>;
 pub type RunFunction = fn(Bytes) -> Option<Bytes>;
 
 pub struct Contract {
@@ -70,8 +69,8 @@ pub const NUM_OF_FRONTIER_CONTRACTS: usize = 4;
 pub const NUM_OF_BYZANTIUM_CONTRACTS: usize = 8;
 pub const NUM_OF_ISTANBUL_CONTRACTS: usize = 9;
 
-fn ecrecover_gas(_: Bytes, _: Revision) -> Option<u64> {
-    Some(3_000)
+fn ecrecover_gas(_: Bytes, pricing: &PrecompilePricing) -> Option<u64> {
+    Some(pricing.ecrecover)
 }
 
 fn ecrecover_run_inner(mut input: Bytes) -> Option<Bytes> {
@@ -118,15 +117,15 @@ fn ecrecover_run(input: Bytes) -> Option<Bytes> {
     Some(ecrecover_run_inner(input).unwrap_or_else(Bytes::new))
 }
 
-fn sha256_gas(input: Bytes, _: Revision) -> Option<u64> {
-    Some(60 + 12 * ((input.len() as u64 + 31) / 32))
+fn sha256_gas(input: Bytes, pricing: &PrecompilePricing) -> Option<u64> {
+    Some(pricing.sha256_base + pricing.sha256_word * ((input.len() as u64 + 31) / 32))
 }
 fn sha256_run(input: Bytes) -> Option<Bytes> {
     Some(Sha256::digest(&input).to_vec().into())
 }
 
-fn ripemd160_gas(input: Bytes, _: Revision) -> Option<u64> {
-    Some(600 + 120 * ((input.len() as u64 + 31) / 32))
+fn ripemd160_gas(input: Bytes, pricing: &PrecompilePricing) -> Option<u64> {
+    Some(pricing.ripemd160_base + pricing.ripemd160_word * ((input.len() as u64 + 31) / 32))
 }
 fn ripemd160_run(input: Bytes) -> Option<Bytes> {
     let mut b = [0; 32];
@@ -134,8 +133,8 @@ fn ripemd160_run(input: Bytes) -> Option<Bytes> {
     Some(b.to_vec().into())
 }
 
-fn id_gas(input: Bytes, _: Revision) -> Option<u64> {
-    Some(15 + 3 * ((input.len() as u64 + 31) / 32))
+fn id_gas(input: Bytes, pricing: &PrecompilePricing) -> Option<u64> {
+    Some(pricing.identity_base + pricing.identity_word * ((input.len() as u64 + 31) / 32))
 }
 fn id_run(input: Bytes) -> Option<Bytes> {
     Some(input)
@@ -157,8 +156,8 @@ fn mult_complexity_eip2565(max_length: U256) -> U256 {
     words * words
 }
 
-fn expmod_gas(mut input: Bytes, rev: Revision) -> Option<u64> {
-    let min_gas = if rev < Revision::Berlin { 0 } else { 200 };
+fn expmod_gas(mut input: Bytes, pricing: &PrecompilePricing) -> Option<u64> {
+    let min_gas = pricing.modexp.min_gas;
 
     input = right_pad(input, 3 * 32);
 
@@ -203,15 +202,11 @@ fn expmod_gas(mut input: Bytes, rev: Revision) -> Option<u64> {
 
     let max_length = std::cmp::max(mod_len256, base_len256);
 
-    let gas = {
-        if rev < Revision::Berlin {
-            mult_complexity_eip198(max_length) * adjusted_exponent_len
-                / U256::from(param::G_QUAD_DIVISOR_BYZANTIUM)
-        } else {
-            mult_complexity_eip2565(max_length) * adjusted_exponent_len
-                / U256::from(param::G_QUAD_DIVISOR_BERLIN)
-        }
+    let complexity = match pricing.modexp.complexity {
+        ModExpComplexity::Eip198 => mult_complexity_eip198(max_length),
+        ModExpComplexity::Eip2565 => mult_complexity_eip2565(max_length),
     };
+    let gas = complexity * adjusted_exponent_len / U256::from(pricing.modexp.quad_divisor.max(1));
 
     Some(std::cmp::max(min_gas, u64::try_from(gas).ok()?))
 }
@@ -248,14 +243,8 @@ fn expmod_run(input: Bytes) -> Option<Bytes> {
     Some(out.into())
 }
 
-fn bn_add_gas(_: Bytes, rev: Revision) -> Option<u64> {
-    Some({
-        if rev >= Revision::Istanbul {
-            150
-        } else {
-            500
-        }
-    })
+fn bn_add_gas(_: Bytes, pricing: &PrecompilePricing) -> Option<u64> {
+    Some(pricing.bn_add)
 }
 
 fn parse_fr_point(r: &mut impl Read) -> Option<substrate_bn::Fr> {
@@ -300,14 +289,8 @@ fn bn_add_run(input: Bytes) -> Option<Bytes> {
     Some(out.to_vec().into())
 }
 
-fn bn_mul_gas(_: Bytes, rev: Revision) -> Option<u64> {
-    Some({
-        if rev >= Revision::Istanbul {
-            6_000
-        } else {
-            40_000
-        }
-    })
+fn bn_mul_gas(_: Bytes, pricing: &PrecompilePricing) -> Option<u64> {
+    Some(pricing.bn_mul)
 }
 fn bn_mul_run(input: Bytes) -> Option<Bytes> {
     let mut input = Read::chain(input.as_ref(), repeat(0));
@@ -326,15 +309,9 @@ fn bn_mul_run(input: Bytes) -> Option<Bytes> {
 
 const SNARKV_STRIDE: u8 = 192;
 
-fn snarkv_gas(input: Bytes, rev: Revision) -> Option<u64> {
+fn snarkv_gas(input: Bytes, pricing: &PrecompilePricing) -> Option<u64> {
     let k = input.len() as u64 / SNARKV_STRIDE as u64;
-    Some({
-        if rev >= Revision::Istanbul {
-            34_000 * k + 45_000
-        } else {
-            80_000 * k + 100_000
-        }
-    })
+    Some(pricing.bn_pairing_point * k + pricing.bn_pairing_base)
 }
 fn snarkv_run(input: Bytes) -> Option<Bytes> {
     if input.len() % usize::from(SNARKV_STRIDE) != 0 {
@@ -380,12 +357,12 @@ fn snarkv_run(input: Bytes) -> Option<Bytes> {
     Some(ret_val.to_be_bytes().to_vec().into())
 }
 
-fn blake2_f_gas(input: Bytes, _: Revision) -> Option<u64> {
+fn blake2_f_gas(input: Bytes, pricing: &PrecompilePricing) -> Option<u64> {
     if input.len() < 4 {
         // blake2_f_run will fail anyway
         return Some(0);
     }
-    Some(u32::from_be_bytes(*array_ref!(input, 0, 4)).into())
+    u64::from(u32::from_be_bytes(*array_ref!(input, 0, 4))).checked_mul(pricing.blake2f_round)
 }
 
 fn blake2_f_run(input: Bytes) -> Option<Bytes> {
@@ -453,6 +430,7 @@ fn blake2_f_run(input: Bytes) -> Option<Bytes> {
 mod tests {
     use super::*;
     use bytes_literal::bytes;
+    use evmodin::Revision;
     use hex_literal::hex;
 
     #[test]
@@ -511,7 +489,10 @@ mod tests {
             "fffffffffffffffffffffffffffffffffffffffffffffffffffffffefffffc2f"
         );
         assert_eq!(
-            expmod_gas(input.to_vec().into(), Revision::Byzantium),
+            expmod_gas(
+                input.to_vec().into(),
+                &PrecompilePricing::for_revision(Revision::Byzantium)
+            ),
             Some(13056)
         );
 
@@ -540,8 +521,20 @@ mod tests {
             "fffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffe"
             "fffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffd"
         );
-        assert_eq!(expmod_gas(input.to_vec().into(), Revision::Byzantium), None);
-        assert_eq!(expmod_gas(input.to_vec().into(), Revision::Berlin), None);
+        assert_eq!(
+            expmod_gas(
+                input.to_vec().into(),
+                &PrecompilePricing::for_revision(Revision::Byzantium)
+            ),
+            None
+        );
+        assert_eq!(
+            expmod_gas(
+                input.to_vec().into(),
+                &PrecompilePricing::for_revision(Revision::Berlin)
+            ),
+            None
+        );
 
         let input = hex!(
             "0000000000000000000000000000000000000000000000000000000000000100"
@@ -562,11 +555,17 @@ mod tests {
             "b602c91f9b07e561fa2f54eb0f9f1984f3cbe728ec142cbed52f"
         );
         assert_eq!(
-            expmod_gas(input.to_vec().into(), Revision::Byzantium),
+            expmod_gas(
+                input.to_vec().into(),
+                &PrecompilePricing::for_revision(Revision::Byzantium)
+            ),
             Some(30310)
         );
         assert_eq!(
-            expmod_gas(input.to_vec().into(), Revision::Berlin),
+            expmod_gas(
+                input.to_vec().into(),
+                &PrecompilePricing::for_revision(Revision::Berlin)
+            ),
             Some(5461)
         );
     }
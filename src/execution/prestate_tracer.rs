@@ -0,0 +1,202 @@
+//! Accounts and storage touched by a transaction together with their values before it, in the
+//! format of geth's `prestateTracer`.
+//!
+//! Values are not known to the tracer itself: [`PrestateTracer`] only collects what was touched
+//! during execution, and [`PrestateTracer::read`] then reads it from the state before the
+//! transaction.
+
+use super::tracer::*;
+use crate::{hexbytes, models::*, state::IntraBlockState, u256_to_h256, State};
+use bytes::Bytes;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct PrestateAccount {
+    pub balance: U256,
+    #[serde(skip_serializing_if = "is_zero")]
+    pub nonce: u64,
+    #[serde(with = "hexbytes", skip_serializing_if = "Bytes::is_empty")]
+    pub code: Bytes,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub storage: BTreeMap<H256, H256>,
+}
+
+fn is_zero(v: &u64) -> bool {
+    *v == 0
+}
+
+/// Collects accounts and storage slots touched during execution.
+#[derive(Debug, Default)]
+pub struct PrestateTracer {
+    touched: BTreeMap<Address, BTreeSet<U256>>,
+}
+
+impl PrestateTracer {
+    /// Include `address` even if it is not touched by the EVM, e.g. block beneficiary.
+    pub fn add_account(&mut self, address: Address) {
+        self.touched.entry(address).or_default();
+    }
+
+    fn add_slot(&mut self, address: Address, location: U256) {
+        self.touched.entry(address).or_default().insert(location);
+    }
+
+    /// Values of touched accounts and slots in `state`.
+    pub async fn read<S: State>(
+        &self,
+        state: &mut IntraBlockState<'_, S>,
+    ) -> anyhow::Result<BTreeMap<Address, PrestateAccount>> {
+        let mut prestate = BTreeMap::new();
+        for (&address, locations) in &self.touched {
+            let mut account = PrestateAccount {
+                balance: state.get_balance(address).await?,
+                nonce: state.get_nonce(address).await?,
+                code: state.get_code(address).await?.unwrap_or_default(),
+                storage: BTreeMap::new(),
+            };
+            for &location in locations {
+                let value = state.get_current_storage(address, location).await?;
+                account
+                    .storage
+                    .insert(u256_to_h256(location), u256_to_h256(value));
+            }
+            prestate.insert(address, account);
+        }
+
+        Ok(prestate)
+    }
+}
+
+impl Tracer for PrestateTracer {
+    fn capture_start(
+        &mut self,
+        _: u16,
+        from: Address,
+        to: Address,
+        _: MessageKind,
+        _: Bytes,
+        _: u64,
+        _: U256,
+    ) {
+        self.add_account(from);
+        self.add_account(to);
+    }
+
+    fn capture_self_destruct(&mut self, caller: Address, beneficiary: Address, _: U256) {
+        self.add_account(caller);
+        self.add_account(beneficiary);
+    }
+
+    fn capture_account_read(&mut self, account: Address) {
+        self.add_account(account);
+    }
+
+    fn capture_account_write(&mut self, account: Address) {
+        self.add_account(account);
+    }
+
+    fn capture_storage_read(&mut self, address: Address, location: U256) {
+        self.add_slot(address, location);
+    }
+
+    fn capture_storage_write(&mut self, address: Address, location: U256) {
+        self.add_slot(address, location);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        execution::{analysis_cache::AnalysisCache, evm},
+        res::chainspec::MAINNET,
+        util::test_util::run_test,
+        InMemoryState,
+    };
+    use hex_literal::hex;
+
+    #[test]
+    fn reads_touched_state() {
+        run_test(async {
+            let header = PartialHeader {
+                number: 13_000_000.into(),
+                ..PartialHeader::empty()
+            };
+            let block_spec = MAINNET.collect_block_spec(header.number);
+
+            let contract = hex!("0a6bb546b9208cfab9e8fa2b9b2c042b18df7030").into();
+            let other = hex!("5ff137d4b0fdcd49dca30c7cf57e578a026d2789").into();
+            let sender = hex!("b685342b8c54347aad148e1f22eff3eb3eb29391").into();
+
+            // 0      PUSH20 => other
+            // 21     BALANCE
+            // 22     PUSH1  => 01
+            // 24     SSTORE
+            // 25     STOP
+            let mut code = vec![0x73];
+            code.extend_from_slice(other.as_bytes());
+            code.extend_from_slice(&hex!("3160015500"));
+            let code = Bytes::from(code);
+
+            let mut db = InMemoryState::default();
+            let mut state = IntraBlockState::new(&mut db);
+            state.set_code(contract, code.clone()).await.unwrap();
+            state
+                .set_storage(contract, 1.as_u256(), 7.as_u256())
+                .await
+                .unwrap();
+            state.add_to_balance(other, 5.as_u256()).await.unwrap();
+            state.add_to_balance(sender, ETHER).await.unwrap();
+            state.finalize_transaction();
+            state.write_to_db(BlockNumber(0)).await.unwrap();
+
+            let txn = MessageWithSender {
+                message: Message::Legacy {
+                    chain_id: Some(block_spec.params.chain_id),
+                    nonce: 0,
+                    gas_price: U256::ZERO,
+                    gas_limit: 100_000,
+                    action: TransactionAction::Call(contract),
+                    value: U256::ZERO,
+                    input: Bytes::new(),
+                },
+                sender,
+            };
+
+            let mut tracer = PrestateTracer::default();
+            evm::execute(
+                &mut IntraBlockState::new(&mut db),
+                Some(&mut tracer),
+                &mut AnalysisCache::default(),
+                &header,
+                &block_spec,
+                &txn,
+                100_000,
+            )
+            .await
+            .unwrap();
+
+            let prestate = tracer
+                .read(&mut IntraBlockState::new(&mut db))
+                .await
+                .unwrap();
+
+            assert_eq!(
+                prestate.keys().copied().collect::<BTreeSet<_>>(),
+                [contract, other, sender].into()
+            );
+            assert_eq!(
+                prestate[&contract],
+                PrestateAccount {
+                    balance: U256::ZERO,
+                    nonce: 0,
+                    code,
+                    storage: [(u256_to_h256(1.as_u256()), u256_to_h256(7.as_u256()))].into(),
+                }
+            );
+            assert_eq!(prestate[&other].balance, 5.as_u256());
+            assert_eq!(prestate[&sender].balance, U256::from(ETHER));
+        })
+    }
+}
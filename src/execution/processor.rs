@@ -1,17 +1,18 @@
-use super::{analysis_cache::AnalysisCache, root_hash, tracer::Tracer};
+use super::{analysis_cache::AnalysisCache, gas_pool::GasPool, root_hash, tracer::Tracer};
 use crate::{
     chain::{
         intrinsic_gas::*,
         protocol_param::{fee, param},
     },
     consensus::*,
-    execution::evm,
+    execution::evm::{self, CallResult},
     h256_to_u256,
     models::*,
     state::IntraBlockState,
     State,
 };
-use anyhow::Context;
+use anyhow::{format_err, Context};
+use bytes::Bytes;
 use evmodin::{Revision, StatusCode};
 use std::cmp::min;
 use TransactionAction;
@@ -27,7 +28,9 @@ where
     header: &'h PartialHeader,
     block: &'b BlockBodyWithSenders,
     block_spec: &'c BlockExecutionSpec,
+    gas_pool: GasPool,
     cumulative_gas_used: u64,
+    executed_transactions: usize,
 }
 
 impl<'r, 'tracer, 'analysis, 'e, 'h, 'b, 'c, S>
@@ -52,12 +55,25 @@ where
             header,
             block,
             block_spec,
+            gas_pool: GasPool::new(header.gas_limit),
             cumulative_gas_used: 0,
+            executed_transactions: 0,
         }
     }
 
-    fn available_gas(&self) -> u64 {
-        self.header.gas_limit - self.cumulative_gas_used
+    /// Trace further transactions with `tracer` instead.
+    pub fn set_tracer(&mut self, tracer: Option<&'tracer mut dyn Tracer>) {
+        self.tracer = tracer;
+    }
+
+    /// Gas left in block for further transactions.
+    pub fn available_gas(&self) -> u64 {
+        self.gas_pool.gas()
+    }
+
+    /// Price of blob gas in this block, burned rather than paid to the miner.
+    fn blob_base_fee(&self) -> U256 {
+        blob_base_fee(self.header.excess_blob_gas.unwrap_or(0))
     }
 
     pub(crate) fn state(&mut self) -> &mut IntraBlockState<'r, S> {
@@ -90,11 +106,21 @@ where
             .into());
         }
 
+        // https://eips.ethereum.org/EIPS/eip-4844
+        let blob_gas = tx.blob_gas();
+        if blob_gas > 0 && tx.max_fee_per_blob_gas() < self.blob_base_fee() {
+            return Err(ValidationError::MaxFeePerBlobGasLessThanBlobBase.into());
+        }
+
         // https://github.com/ethereum/EIPs/pull/3594
         let max_gas_cost = U512::from(tx.gas_limit())
             * U512::from(ethereum_types::U256::from(
                 tx.max_fee_per_gas().to_be_bytes(),
-            ));
+            ))
+            + U512::from(blob_gas)
+                * U512::from(ethereum_types::U256::from(
+                    tx.max_fee_per_blob_gas().to_be_bytes(),
+                ));
         // See YP, Eq (57) in Section 6.2 "Execution"
         let v0 = max_gas_cost + U512::from(ethereum_types::U256::from(tx.value().to_be_bytes()));
         let available_balance =
@@ -115,6 +141,7 @@ where
             // The sum of the transaction’s gas limit and the gas utilized in this block prior
             // must be no greater than the block’s gas limit.
             return Err(ValidationError::BlockGasLimitExceeded {
+                txn: self.executed_transactions,
                 available: available_gas,
                 required: tx.gas_limit(),
             }
@@ -124,9 +151,24 @@ where
         Ok(())
     }
 
-    async fn execute_transaction(&mut self, txn: &MessageWithSender) -> anyhow::Result<Receipt> {
+    /// Execute transaction on top of the previous ones. It must have passed [`Self::validate_transaction`].
+    pub async fn execute_transaction(
+        &mut self,
+        txn: &MessageWithSender,
+    ) -> anyhow::Result<Receipt> {
+        Ok(self.execute_transaction_with_output(txn).await?.0)
+    }
+
+    /// Same as [`Self::execute_transaction`], but also return the result of the top-level message.
+    pub async fn execute_transaction_with_output(
+        &mut self,
+        txn: &MessageWithSender,
+    ) -> anyhow::Result<(Receipt, CallResult)> {
         let rev = self.block_spec.revision;
 
+        self.gas_pool
+            .sub_gas(self.executed_transactions, txn.gas_limit())?;
+
         self.state.clear_journal_and_substate();
 
         self.state.access_account(txn.sender);
@@ -140,6 +182,14 @@ where
             )
             .await?;
 
+        // Blob gas is burned regardless of the execution result.
+        let blob_gas = txn.blob_gas();
+        if blob_gas > 0 {
+            self.state
+                .subtract_from_balance(txn.sender, U256::from(blob_gas) * self.blob_base_fee())
+                .await?;
+        }
+
         if let TransactionAction::Call(to) = txn.action() {
             self.state.access_account(to);
             // EVM itself increments the nonce for contract creation
@@ -153,12 +203,7 @@ where
             }
         }
 
-        let g0 = intrinsic_gas(txn, rev >= Revision::Homestead, rev >= Revision::Istanbul);
-        let gas = u128::from(txn.gas_limit())
-            .checked_sub(g0)
-            .ok_or(ValidationError::IntrinsicGas)?
-            .try_into()
-            .unwrap();
+        let gas = check_intrinsic_gas(txn, rev.into())?;
 
         let vm_res = evm::execute(
             &mut self.state,
@@ -173,7 +218,9 @@ where
         )
         .await?;
 
-        let gas_used = txn.gas_limit() - self.refund_gas(txn, vm_res.gas_left as u64).await?;
+        let gas_left = self.refund_gas(txn, vm_res.gas_left as u64).await?;
+        self.gas_pool.add_gas(gas_left);
+        let gas_used = txn.gas_limit() - gas_left;
 
         // award the miner
         let priority_fee_per_gas = txn.priority_fee_per_gas(base_fee_per_gas);
@@ -192,23 +239,98 @@ where
         self.state.finalize_transaction();
 
         self.cumulative_gas_used += gas_used;
+        self.executed_transactions += 1;
 
-        Ok(Receipt {
+        let receipt = Receipt {
             tx_type: txn.tx_type(),
             success: vm_res.status_code == StatusCode::Success,
             cumulative_gas_used: self.cumulative_gas_used,
             bloom: logs_bloom(self.state.logs()),
             logs: self.state.logs().to_vec(),
-        })
+        };
+
+        Ok((receipt, vm_res))
     }
 
-    pub async fn execute_block_no_post_validation(&mut self) -> anyhow::Result<Vec<Receipt>> {
-        let mut receipts = Vec::with_capacity(self.block.transactions.len());
+    /// Call `address` from [`param::SYSTEM_ADDRESS`] with [`param::SYSTEM_CALL_GAS`].
+    ///
+    /// Unlike transactions, system calls are not paid for, do not bump the nonce of the caller
+    /// and do not use gas of the block. Returns `None` if there is no code at `address`.
+    pub async fn execute_system_call(
+        &mut self,
+        address: Address,
+        input: Bytes,
+    ) -> anyhow::Result<Option<CallResult>> {
+        if self.state.get_code_hash(address).await? == EMPTY_HASH {
+            return Ok(None);
+        }
+
+        let txn = MessageWithSender {
+            message: Message::Legacy {
+                chain_id: None,
+                nonce: 0,
+                gas_price: U256::ZERO,
+                gas_limit: param::SYSTEM_CALL_GAS,
+                action: TransactionAction::Call(address),
+                value: U256::ZERO,
+                input,
+            },
+            sender: param::SYSTEM_ADDRESS,
+        };
 
+        self.state.clear_journal_and_substate();
+        self.state.access_account(address);
+
+        // Not traced: tracers expect a trace per transaction of the block.
+        let vm_res = evm::execute(
+            &mut self.state,
+            None,
+            self.analysis_cache,
+            self.header,
+            self.block_spec,
+            &txn,
+            param::SYSTEM_CALL_GAS,
+        )
+        .await?;
+
+        self.state.destruct_selfdestructs().await?;
+        // System address is created empty by the value transfer.
+        if self.block_spec.revision >= Revision::Spurious {
+            self.state.destruct_touched_dead().await?;
+        }
+        self.state.finalize_transaction();
+
+        Ok(Some(vm_res))
+    }
+
+    /// Apply changes that precede transactions of the block: irregular balance changes and
+    /// system calls.
+    pub async fn execute_block_prologue(&mut self) -> anyhow::Result<()> {
         for (&address, &balance) in &self.block_spec.balance_changes {
             self.state.set_balance(address, balance).await?;
         }
 
+        let block_spec = self.block_spec;
+        for call in &block_spec.system_calls {
+            let input = match &call.input {
+                SystemCallInput::Data(data) => data.clone(),
+                SystemCallInput::ParentHash => {
+                    Bytes::copy_from_slice(self.header.parent_hash.as_bytes())
+                }
+            };
+            self.execute_system_call(call.address, input)
+                .await
+                .with_context(|| format!("Failed system call to {:?}", call.address))?;
+        }
+
+        Ok(())
+    }
+
+    pub async fn execute_block_no_post_validation(&mut self) -> anyhow::Result<Vec<Receipt>> {
+        let mut receipts = Vec::with_capacity(self.block.transactions.len());
+
+        self.execute_block_prologue().await?;
+
         for (i, txn) in self.block.transactions.iter().enumerate() {
             self.validate_transaction(txn)
                 .await
@@ -216,19 +338,74 @@ where
             receipts.push(self.execute_transaction(txn).await?);
         }
 
+        self.execute_block_epilogue().await?;
+
+        Ok(receipts)
+    }
+
+    /// Apply changes that follow transactions of the block: rewards and withdrawals.
+    pub async fn execute_block_epilogue(&mut self) -> anyhow::Result<()> {
         for change in self
             .engine
             .finalize(self.header, &self.block.ommers, self.block_spec.revision)
             .await?
         {
             match change {
-                FinalizationChange::Reward { address, amount } => {
-                    self.state.add_to_balance(address, amount).await?;
+                FinalizationChange::Reward {
+                    address,
+                    amount,
+                    kind,
+                } => {
+                    let mut remaining = amount;
+                    for redirection in &self.block_spec.reward_redirections {
+                        if kind == RewardKind::Ommer && !redirection.ommers {
+                            continue;
+                        }
+
+                        let share = redirection.share_of(amount);
+                        if share == 0 {
+                            continue;
+                        }
+                        remaining = remaining.checked_sub(share).ok_or_else(|| {
+                            format_err!("reward redirections exceed reward of {:?}", address)
+                        })?;
+                        if let Some(tracer) = self.tracer.as_deref_mut() {
+                            tracer.capture_reward(redirection.address, share, kind);
+                        }
+                        self.state
+                            .add_to_balance(redirection.address, share)
+                            .await?;
+                    }
+
+                    if let Some(tracer) = self.tracer.as_deref_mut() {
+                        tracer.capture_reward(address, remaining, kind);
+                    }
+                    self.state.add_to_balance(address, remaining).await?;
                 }
             }
         }
 
-        Ok(receipts)
+        // https://eips.ethereum.org/EIPS/eip-4895
+        match (
+            &self.block.withdrawals,
+            self.block_spec.revision >= Revision::Shanghai,
+        ) {
+            (Some(withdrawals), true) => {
+                self.state.clear_journal_and_substate();
+                for withdrawal in withdrawals {
+                    self.state
+                        .add_to_balance(withdrawal.address, withdrawal.amount_in_wei())
+                        .await?;
+                }
+                // Zero withdrawals to empty accounts must not create them.
+                self.state.destruct_touched_dead().await?;
+            }
+            (None, false) => {}
+            (None, true) => return Err(ValidationError::MissingWithdrawals.into()),
+            (Some(_), false) => return Err(ValidationError::UnexpectedWithdrawals.into()),
+        }
+
+        Ok(())
     }
 
     pub async fn execute_and_write_block(mut self) -> anyhow::Result<Vec<Receipt>> {
@@ -320,7 +497,7 @@ mod tests {
     use super::*;
     use crate::{
         execution::address::create_address, res::chainspec::MAINNET, util::test_util::run_test,
-        InMemoryState,
+        InMemoryState, StateReader, StateWriter,
     };
     use bytes::Bytes;
     use bytes_literal::bytes;
@@ -372,6 +549,68 @@ mod tests {
         })
     }
 
+    #[test]
+    fn block_gas_limit_exceeded() {
+        run_test(async {
+            let header = PartialHeader {
+                number: 2_687_232.into(),
+                gas_limit: 50_000,
+                ..PartialHeader::empty()
+            };
+            let block = Default::default();
+
+            let sender = hex!("004512399a230565b99be5c3b0030a56f3ace68c").into();
+            let t = |nonce, gas_limit| MessageWithSender {
+                message: Message::Legacy {
+                    chain_id: None,
+                    nonce,
+                    gas_price: U256::ZERO,
+                    gas_limit,
+                    action: TransactionAction::Call(
+                        hex!("e5ef458d37212a06e3f59d40c454e76150ae7c32").into(),
+                    ),
+                    value: U256::ZERO,
+                    input: Bytes::new(),
+                },
+                sender,
+            };
+
+            let mut state = InMemoryState::default();
+            let mut analysis_cache = AnalysisCache::default();
+            let mut engine = engine_factory(MAINNET.clone()).unwrap();
+            let block_spec = MAINNET.collect_block_spec(header.number);
+            let mut processor = ExecutionProcessor::new(
+                &mut state,
+                None,
+                &mut analysis_cache,
+                &mut *engine,
+                &header,
+                &block,
+                &block_spec,
+            );
+
+            let txn = (t)(0, 25_000);
+            processor.validate_transaction(&txn).await.unwrap();
+            processor.execute_transaction(&txn).await.unwrap();
+            assert_eq!(processor.available_gas(), 29_000);
+
+            let txn = (t)(1, 30_000);
+            assert_eq!(
+                processor
+                    .validate_transaction(&txn)
+                    .await
+                    .unwrap_err()
+                    .downcast::<ValidationError>()
+                    .unwrap(),
+                ValidationError::BlockGasLimitExceeded {
+                    txn: 1,
+                    available: 29_000,
+                    required: 30_000,
+                }
+            );
+        })
+    }
+
     #[test]
     fn eip3607_reject_transactions_from_senders_with_deployed_code() {
         run_test(async {
@@ -808,4 +1047,226 @@ mod tests {
             assert_eq!(state.read_account(suicide_beneficiary).await.unwrap(), None);
         })
     }
+
+    #[test]
+    fn system_calls() {
+        run_test(async {
+            let header = PartialHeader {
+                parent_hash: H256::repeat_byte(0xaa),
+                number: 13_000_000.into(),
+                gas_limit: 30_000_000,
+                base_fee_per_gas: Some(U256::from(GIGA)),
+                ..PartialHeader::empty()
+            };
+            let block = Default::default();
+            let contract = hex!("0000f90827f1c53a10cb7a02335b175320002935").into();
+            let missing = Address::from_low_u64_be(0xdead);
+
+            let mut block_spec = MAINNET.collect_block_spec(header.number);
+            block_spec.system_calls = vec![
+                // No code, skipped.
+                SystemCall {
+                    address: missing,
+                    input: SystemCallInput::ParentHash,
+                },
+                SystemCall {
+                    address: contract,
+                    input: SystemCallInput::ParentHash,
+                },
+            ];
+
+            let mut state = InMemoryState::default();
+            let mut analysis_cache = AnalysisCache::default();
+            let mut engine = engine_factory(MAINNET.clone()).unwrap();
+            let mut processor = ExecutionProcessor::new(
+                &mut state,
+                None,
+                &mut analysis_cache,
+                &mut *engine,
+                &header,
+                &block,
+                &block_spec,
+            );
+
+            // 0      PUSH1  => 00
+            // 2      CALLDATALOAD
+            // 3      PUSH1  => 00
+            // 5      SSTORE
+            processor
+                .state()
+                .set_code(contract, hex!("600035600055").to_vec().into())
+                .await
+                .unwrap();
+
+            let receipts = processor.execute_block_no_post_validation().await.unwrap();
+            assert!(receipts.is_empty());
+            assert_eq!(processor.available_gas(), header.gas_limit);
+
+            let state = processor.state();
+            assert_eq!(
+                state
+                    .get_current_storage(contract, U256::ZERO)
+                    .await
+                    .unwrap(),
+                h256_to_u256(header.parent_hash)
+            );
+            assert!(!state.exists(missing).await.unwrap());
+            assert!(!state.exists(param::SYSTEM_ADDRESS).await.unwrap());
+        })
+    }
+
+    #[test]
+    fn withdrawals() {
+        run_test(async {
+            let header = PartialHeader {
+                number: 17_000_000.into(),
+                gas_limit: 30_000_000,
+                base_fee_per_gas: Some(U256::from(GIGA)),
+                withdrawals_root: Some(EMPTY_ROOT),
+                ..PartialHeader::empty()
+            };
+            let validator = Address::from_low_u64_be(0x10f0);
+            let empty = Address::from_low_u64_be(0x10f1);
+            let block = BlockBodyWithSenders {
+                withdrawals: Some(vec![
+                    Withdrawal {
+                        index: 0,
+                        validator_index: 1,
+                        address: validator,
+                        amount: 32,
+                    },
+                    Withdrawal {
+                        index: 1,
+                        validator_index: 2,
+                        address: empty,
+                        amount: 0,
+                    },
+                ]),
+                ..Default::default()
+            };
+
+            let mut block_spec = MAINNET.collect_block_spec(header.number);
+            let mut state = InMemoryState::default();
+            let mut analysis_cache = AnalysisCache::default();
+            let mut engine = engine_factory(MAINNET.clone()).unwrap();
+
+            // London rules do not know withdrawals.
+            assert_eq!(
+                ExecutionProcessor::new(
+                    &mut state,
+                    None,
+                    &mut analysis_cache,
+                    &mut *engine,
+                    &header,
+                    &block,
+                    &block_spec,
+                )
+                .execute_block_no_post_validation()
+                .await
+                .unwrap_err()
+                .downcast::<ValidationError>()
+                .unwrap(),
+                ValidationError::UnexpectedWithdrawals
+            );
+
+            block_spec.revision = Revision::Shanghai;
+            let mut processor = ExecutionProcessor::new(
+                &mut state,
+                None,
+                &mut analysis_cache,
+                &mut *engine,
+                &header,
+                &block,
+                &block_spec,
+            );
+            processor.execute_block_no_post_validation().await.unwrap();
+
+            let state = processor.state();
+            assert_eq!(
+                state.get_balance(validator).await.unwrap(),
+                U256::from(32 * GIGA)
+            );
+            assert!(!state.exists(empty).await.unwrap());
+        })
+    }
+
+    #[test]
+    fn reward_redirections() {
+        run_test(async {
+            let miner = Address::from_low_u64_be(0x1);
+            let uncle_miner = Address::from_low_u64_be(0x2);
+            let treasury = Address::from_low_u64_be(0x3);
+            let header = PartialHeader {
+                number: 13_000_000.into(),
+                beneficiary: miner,
+                gas_limit: 30_000_000,
+                difficulty: U256::ONE,
+                base_fee_per_gas: Some(U256::from(GIGA)),
+                ..PartialHeader::empty()
+            };
+            let block = BlockBodyWithSenders {
+                ommers: vec![BlockHeader::new(
+                    PartialHeader {
+                        number: 12_999_999.into(),
+                        beneficiary: uncle_miner,
+                        ..PartialHeader::empty()
+                    },
+                    EMPTY_LIST_HASH,
+                    EMPTY_ROOT,
+                )],
+                ..Default::default()
+            };
+
+            let mut block_spec = MAINNET.collect_block_spec(header.number);
+            block_spec.reward_redirections = vec![RewardRedirection {
+                address: treasury,
+                share_bps: 1_000,
+                ommers: false,
+            }];
+            let mut state = InMemoryState::default();
+            let mut analysis_cache = AnalysisCache::default();
+            let mut engine = engine_factory(MAINNET.clone()).unwrap();
+            let mut processor = ExecutionProcessor::new(
+                &mut state,
+                None,
+                &mut analysis_cache,
+                &mut *engine,
+                &header,
+                &block,
+                &block_spec,
+            );
+            processor.execute_block_no_post_validation().await.unwrap();
+
+            // Only the block reward of 2 ETH + 1/32 for the ommer is redirected.
+            let block_reward = U256::from(2 * ETHER + 2 * ETHER / 32);
+            let balances = processor.state();
+            assert_eq!(
+                balances.get_balance(treasury).await.unwrap(),
+                block_reward / 10
+            );
+            assert_eq!(
+                balances.get_balance(miner).await.unwrap(),
+                block_reward - block_reward / 10
+            );
+            assert_eq!(
+                balances.get_balance(uncle_miner).await.unwrap(),
+                U256::from(2 * ETHER * 7 / 8)
+            );
+
+            // Shares over 100% are rejected.
+            block_spec.reward_redirections[0].share_bps = 10_001;
+            assert!(ExecutionProcessor::new(
+                &mut state,
+                None,
+                &mut analysis_cache,
+                &mut *engine,
+                &header,
+                &block,
+                &block_spec,
+            )
+            .execute_block_no_post_validation()
+            .await
+            .is_err());
+        })
+    }
 }
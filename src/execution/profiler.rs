@@ -0,0 +1,238 @@
+//! Time spent per opcode, precompile and host function while executing blocks, aggregated into
+//! folded stacks for flamegraph tools, e.g. `inferno-flamegraph < profile.folded > profile.svg`.
+
+#[cfg(feature = "node")]
+use super::replay::ReplayBlock;
+use super::tracer::{CallKind, CodeKind, MessageKind, Tracer};
+use crate::models::*;
+#[cfg(feature = "node")]
+use crate::{accessors::chain, kv::traits::*};
+#[cfg(feature = "node")]
+use anyhow::format_err;
+use bytes::Bytes;
+use evmodin::{ExecutionState, OpCode, StatusCode};
+use std::{
+    collections::HashMap,
+    io::{self, Write},
+    time::{Duration, Instant},
+};
+
+/// Attributes time between tracer events to the stack of messages being executed, topped with
+/// the current opcode, and with the host function it has called if any.
+///
+/// Time of an opcode includes host functions it calls before they are reached, and interpreter
+/// overhead of dispatching it. Tracing itself adds to every opcode about equally.
+#[derive(Debug)]
+pub struct Profiler {
+    /// Messages being executed, outermost first.
+    frames: Vec<String>,
+    /// Opcode being executed, with the host function it has called.
+    leaf: Option<(OpCode, Option<&'static str>)>,
+    last_event: Instant,
+    samples: HashMap<String, Duration>,
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Self {
+            frames: vec![],
+            leaf: None,
+            last_event: Instant::now(),
+            samples: HashMap::new(),
+        }
+    }
+}
+
+impl Profiler {
+    /// Add time since the previous event to the current stack.
+    fn sample(&mut self) {
+        let now = Instant::now();
+        let elapsed = now - self.last_event;
+        self.last_event = now;
+
+        // Time outside of messages, e.g. between transactions, is not profiled.
+        if self.frames.is_empty() {
+            return;
+        }
+
+        let mut stack = self.frames.join(";");
+        if let Some((op, host_fn)) = self.leaf {
+            stack.push(';');
+            stack.push_str(&op.to_string());
+            if let Some(host_fn) = host_fn {
+                stack.push(';');
+                stack.push_str(host_fn);
+            }
+        }
+        *self.samples.entry(stack).or_default() += elapsed;
+    }
+
+    fn instruction_start(&mut self, op: OpCode) {
+        self.sample();
+        self.leaf = Some((op, None));
+    }
+
+    fn host_call(&mut self, host_fn: &'static str) {
+        self.sample();
+        if let Some((_, current)) = &mut self.leaf {
+            *current = Some(host_fn);
+        }
+    }
+
+    /// Total time recorded.
+    pub fn total(&self) -> Duration {
+        self.samples.values().sum()
+    }
+
+    /// Write stacks with time spent in them in microseconds, one per line, heaviest first.
+    pub fn write_folded(&self, mut w: impl Write) -> io::Result<()> {
+        let mut samples = self.samples.iter().collect::<Vec<_>>();
+        samples.sort_unstable_by(|(a_stack, a), (b_stack, b)| b.cmp(a).then(a_stack.cmp(b_stack)));
+        for (stack, elapsed) in samples {
+            let micros = elapsed.as_micros();
+            if micros > 0 {
+                writeln!(w, "{} {}", stack, micros)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Tracer for Profiler {
+    fn trace_instructions(&self) -> bool {
+        true
+    }
+
+    fn capture_start(
+        &mut self,
+        _: u16,
+        _: Address,
+        to: Address,
+        call_type: MessageKind,
+        _: Bytes,
+        _: u64,
+        _: U256,
+    ) {
+        self.sample();
+        self.frames.push(match call_type {
+            MessageKind::Create => "CREATE".to_string(),
+            MessageKind::Call {
+                code_kind: CodeKind::Precompile,
+                ..
+            } => format!("precompile_{}", to.to_low_u64_be()),
+            MessageKind::Call { call_kind, .. } => match call_kind {
+                CallKind::Call => "CALL",
+                CallKind::CallCode => "CALLCODE",
+                CallKind::DelegateCall => "DELEGATECALL",
+                CallKind::StaticCall => "STATICCALL",
+            }
+            .to_string(),
+        });
+        self.leaf = None;
+    }
+
+    fn capture_state(
+        &mut self,
+        _: &ExecutionState,
+        _: u64,
+        op: OpCode,
+        _: u64,
+        _: Bytes,
+        _: u16,
+        _: StatusCode,
+    ) {
+        self.instruction_start(op);
+    }
+
+    fn capture_end(&mut self, _: u16, _: Bytes, _: u64, _: StatusCode) {
+        self.sample();
+        self.frames.pop();
+        // Back in the opcode that made the call.
+        self.leaf = None;
+    }
+
+    fn capture_account_read(&mut self, _: Address) {
+        self.host_call("account_read");
+    }
+
+    fn capture_storage_read(&mut self, _: Address, _: U256) {
+        self.host_call("storage_read");
+    }
+
+    fn capture_storage_write(&mut self, _: Address, _: U256) {
+        self.host_call("storage_write");
+    }
+}
+
+/// Re-execute canonical blocks `from..=to` on top of historical state, recording time spent
+/// in `profiler`.
+#[cfg(feature = "node")]
+pub async fn profile_blocks<'db, Tx: Transaction<'db>>(
+    tx: &Tx,
+    from: BlockNumber,
+    to: BlockNumber,
+    profiler: &mut Profiler,
+) -> anyhow::Result<()> {
+    for number in from.0..=to.0 {
+        let hash = chain::canonical_hash::read(tx, number)
+            .await?
+            .ok_or_else(|| format_err!("no canonical block {}", number))?;
+        let block = ReplayBlock::load(tx, hash, BlockNumber(number)).await?;
+        block.replay_block(tx, profiler).await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn folded_stacks() {
+        let mut profiler = Profiler::default();
+        let contract = Address::from_low_u64_be(0x1234);
+        profiler.capture_start(
+            0,
+            Address::zero(),
+            contract,
+            MessageKind::Call {
+                call_kind: CallKind::Call,
+                code_kind: CodeKind::Bytecode(None),
+            },
+            Bytes::new(),
+            100_000,
+            U256::ZERO,
+        );
+        profiler.instruction_start(OpCode::SLOAD);
+        profiler.capture_storage_read(contract, U256::ZERO);
+        std::thread::sleep(Duration::from_millis(2));
+        profiler.capture_start(
+            1,
+            contract,
+            Address::from_low_u64_be(2),
+            MessageKind::Call {
+                call_kind: CallKind::StaticCall,
+                code_kind: CodeKind::Precompile,
+            },
+            Bytes::new(),
+            1_000,
+            U256::ZERO,
+        );
+        std::thread::sleep(Duration::from_millis(2));
+        profiler.capture_end(1, Bytes::new(), 900, StatusCode::Success);
+        profiler.capture_end(0, Bytes::new(), 90_000, StatusCode::Success);
+
+        let mut out = vec![];
+        profiler.write_folded(&mut out).unwrap();
+        let stacks = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|line| line.rsplit_once(' ').unwrap().0.to_string())
+            .collect::<Vec<_>>();
+        assert!(stacks.contains(&"CALL;SLOAD;storage_read".to_string()));
+        assert!(stacks.contains(&"CALL;precompile_2".to_string()));
+        assert!(profiler.total() >= Duration::from_millis(4));
+    }
+}
@@ -0,0 +1,246 @@
+//! Receipts of canonical blocks, recomputed by re-executing the block on demand.
+//!
+//! Only logs are kept in the database, so status and gas used of a transaction are only known
+//! after re-executing its block on top of the historical state of its parent.
+
+use super::{analysis_cache::AnalysisCache, processor::ExecutionProcessor};
+use crate::{
+    accessors::chain,
+    consensus,
+    kv::{tables, traits::*},
+    models::*,
+    state::Buffer,
+};
+use anyhow::{ensure, format_err};
+use lru::LruCache;
+use parking_lot::Mutex;
+use std::{fmt, sync::Arc};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReceiptsError {
+    /// History needed to re-execute the block is pruned.
+    Pruned {
+        block: BlockNumber,
+        available_from: Option<BlockNumber>,
+    },
+    /// Block is too far behind head to be re-executed on demand.
+    TooDeep {
+        block: BlockNumber,
+        head: BlockNumber,
+        max_depth: u64,
+    },
+}
+
+impl fmt::Display for ReceiptsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Pruned {
+                block,
+                available_from: Some(available_from),
+            } => write!(
+                f,
+                "receipts of block {} are beyond the prune horizon, history starts at block {}",
+                block, available_from
+            ),
+            Self::Pruned {
+                block,
+                available_from: None,
+            } => write!(
+                f,
+                "receipts of block {} are beyond the prune horizon",
+                block
+            ),
+            Self::TooDeep {
+                block,
+                head,
+                max_depth,
+            } => write!(
+                f,
+                "receipts of block {} are not available: more than {} blocks behind head {}",
+                block, max_depth, head
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ReceiptsError {}
+
+/// Receipts of recently requested blocks, by block hash.
+#[derive(Debug)]
+pub struct ReceiptsCache {
+    blocks: Mutex<LruCache<H256, Arc<Vec<Receipt>>>>,
+    /// How far behind head a block may be re-executed.
+    max_depth: u64,
+}
+
+impl ReceiptsCache {
+    /// Cache receipts of `capacity` blocks, re-executing blocks at most `max_depth` behind head.
+    pub fn new(capacity: usize, max_depth: u64) -> Self {
+        Self {
+            blocks: Mutex::new(LruCache::new(capacity)),
+            max_depth,
+        }
+    }
+
+    /// Receipts of canonical block `number` with hash `hash`.
+    pub async fn get<'db, Tx: Transaction<'db>>(
+        &self,
+        tx: &Tx,
+        head: BlockNumber,
+        hash: H256,
+        number: BlockNumber,
+    ) -> anyhow::Result<Arc<Vec<Receipt>>> {
+        if let Some(receipts) = self.blocks.lock().get(&hash) {
+            return Ok(receipts.clone());
+        }
+
+        if head.0.saturating_sub(number.0) > self.max_depth {
+            return Err(ReceiptsError::TooDeep {
+                block: number,
+                head,
+                max_depth: self.max_depth,
+            }
+            .into());
+        }
+
+        let receipts = Arc::new(execute_block_receipts(tx, hash, number).await?);
+        self.blocks.lock().put(hash, receipts.clone());
+
+        Ok(receipts)
+    }
+}
+
+/// First block whose changes are kept in history.
+pub(crate) async fn history_start<'db, Tx: Transaction<'db>>(
+    tx: &Tx,
+) -> anyhow::Result<Option<BlockNumber>> {
+    Ok(tx
+        .cursor(tables::AccountChangeSet)
+        .await?
+        .first()
+        .await?
+        .map(|(block_number, _)| block_number))
+}
+
+/// Re-execute canonical block `number` on top of the historical state of its parent.
+pub async fn execute_block_receipts<'db, Tx: Transaction<'db>>(
+    tx: &Tx,
+    hash: H256,
+    number: BlockNumber,
+) -> anyhow::Result<Vec<Receipt>> {
+    // Genesis is not executed.
+    if number.0 == 0 {
+        return Ok(vec![]);
+    }
+
+    // State of the parent is recovered by undoing changes of this and later blocks.
+    let available_from = history_start(tx).await?;
+    if available_from.map_or(true, |available_from| available_from > number) {
+        return Err(ReceiptsError::Pruned {
+            block: number,
+            available_from,
+        }
+        .into());
+    }
+
+    let chain_spec = chain::chain_spec::read(tx).await?;
+
+    let header = chain::header::read(tx, hash, number)
+        .await?
+        .ok_or_else(|| format_err!("header {}/{:?} not found", number, hash))?;
+    let block = chain::block_body::read_with_senders(tx, hash, number)
+        .await?
+        .ok_or_else(|| format_err!("body {}/{:?} not found", number, hash))?;
+
+    let partial_header = PartialHeader::from(header.clone());
+    let block_spec = chain_spec.collect_block_spec(number);
+    let mut buffer = Buffer::new(tx, BlockNumber(0), Some(BlockNumber(number.0 - 1)));
+    let mut analysis_cache = AnalysisCache::default();
+    let mut engine = consensus::engine_factory(chain_spec.clone())?;
+    let receipts = ExecutionProcessor::new(
+        &mut buffer,
+        None,
+        &mut analysis_cache,
+        &mut *engine,
+        &partial_header,
+        &block,
+        &block_spec,
+    )
+    .execute_block_no_post_validation()
+    .await?;
+
+    let gas_used = receipts.last().map_or(0, |r| r.cumulative_gas_used);
+    ensure!(
+        gas_used == header.gas_used,
+        "block {} re-executed with {} gas used instead of {}",
+        number,
+        gas_used,
+        header.gas_used
+    );
+
+    Ok(receipts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{kv::new_mem_database, util::test_util::run_test};
+
+    #[test]
+    fn pruned_and_too_deep_blocks() {
+        run_test(async {
+            let db = new_mem_database().unwrap();
+            let tx = db.begin().await.unwrap();
+
+            let cache = ReceiptsCache::new(16, 128);
+            let hash = H256::repeat_byte(1);
+
+            let err = cache
+                .get(&tx, BlockNumber(1_000), hash, BlockNumber(10))
+                .await
+                .unwrap_err()
+                .downcast::<ReceiptsError>()
+                .unwrap();
+            assert_eq!(
+                err,
+                ReceiptsError::TooDeep {
+                    block: BlockNumber(10),
+                    head: BlockNumber(1_000),
+                    max_depth: 128,
+                }
+            );
+
+            // No history at all.
+            let err = cache
+                .get(&tx, BlockNumber(1_000), hash, BlockNumber(900))
+                .await
+                .unwrap_err()
+                .downcast::<ReceiptsError>()
+                .unwrap();
+            assert_eq!(
+                err,
+                ReceiptsError::Pruned {
+                    block: BlockNumber(900),
+                    available_from: None,
+                }
+            );
+
+            // Cached receipts are returned without touching the database.
+            let receipts = Arc::new(vec![Receipt {
+                tx_type: TxType::Legacy,
+                success: true,
+                cumulative_gas_used: 21_000,
+                bloom: Bloom::zero(),
+                logs: vec![],
+            }]);
+            cache.blocks.lock().put(hash, receipts.clone());
+            assert_eq!(
+                cache
+                    .get(&tx, BlockNumber(1_000), hash, BlockNumber(900))
+                    .await
+                    .unwrap(),
+                receipts
+            );
+        })
+    }
+}
@@ -0,0 +1,304 @@
+//! Tracing of canonical transactions, as in `debug_traceTransaction`.
+//!
+//! Block of the transaction is re-executed on top of the historical state of its parent, with
+//! transactions preceding the traced one executed untraced.
+
+use super::{
+    analysis_cache::AnalysisCache,
+    call_tracer::{CallFrame, CallFrameTracer},
+    eip3155::Eip3155Tracer,
+    evm::CallResult,
+    prestate_tracer::{PrestateAccount, PrestateTracer},
+    processor::ExecutionProcessor,
+    receipts::history_start,
+    struct_logger::{StructLogger, StructLoggerConfig, StructLoggerResult},
+    tracer::Tracer,
+};
+use crate::{accessors::chain, consensus, kv::traits::*, models::*, state::Buffer};
+use anyhow::{bail, format_err, Context};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+pub enum TracerKind {
+    #[serde(rename = "callTracer")]
+    Call,
+    #[serde(rename = "prestateTracer")]
+    Prestate,
+    /// Steps in the format of EIP-3155, honoring options of the struct logger.
+    #[serde(rename = "eip3155Tracer")]
+    Eip3155,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct TracerConfig {
+    /// Only report the top-level call of `callTracer`.
+    pub only_top_call: bool,
+}
+
+/// Options of `debug_traceTransaction`. Without `tracer`, the default struct logger is used.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TraceOptions {
+    #[serde(default)]
+    pub tracer: Option<TracerKind>,
+    #[serde(default)]
+    pub tracer_config: TracerConfig,
+    #[serde(flatten)]
+    pub logger: StructLoggerConfig,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum TransactionTrace {
+    StructLogs(StructLoggerResult),
+    Call(CallFrame),
+    Prestate(BTreeMap<Address, PrestateAccount>),
+    /// EIP-3155 lines, the summary last.
+    Eip3155(Vec<serde_json::Value>),
+}
+
+/// Canonical block to be re-executed with tracers.
+pub(crate) struct ReplayBlock {
+    pub(crate) chain_spec: ChainSpec,
+    pub(crate) hash: H256,
+    pub(crate) header: BlockHeader,
+    pub(crate) body: BlockBodyWithSenders,
+}
+
+impl ReplayBlock {
+    pub(crate) async fn load<'db, Tx: Transaction<'db>>(
+        tx: &Tx,
+        hash: H256,
+        number: BlockNumber,
+    ) -> anyhow::Result<Self> {
+        // State of the parent is recovered by undoing changes of this and later blocks.
+        let available_from = history_start(tx).await?;
+        if number.0 == 0 || available_from.map_or(true, |available_from| available_from > number) {
+            bail!(
+                "state before block {} is not available, history starts at block {:?}",
+                number,
+                available_from
+            );
+        }
+
+        let chain_spec = chain::chain_spec::read(tx).await?;
+
+        let header = chain::header::read(tx, hash, number)
+            .await?
+            .ok_or_else(|| format_err!("header {}/{:?} not found", number, hash))?;
+        let body = chain::block_body::read_with_senders(tx, hash, number)
+            .await?
+            .ok_or_else(|| format_err!("body {}/{:?} not found", number, hash))?;
+
+        Ok(Self {
+            chain_spec,
+            hash,
+            header,
+            body,
+        })
+    }
+
+    /// Hashes of transactions of the block, in order.
+    pub(crate) async fn transaction_hashes<'db, Tx: Transaction<'db>>(
+        &self,
+        tx: &Tx,
+    ) -> anyhow::Result<Vec<H256>> {
+        Ok(
+            chain::block_body::read_without_senders(tx, self.hash, self.header.number)
+                .await?
+                .ok_or_else(|| {
+                    format_err!("body {}/{:?} not found", self.header.number, self.hash)
+                })?
+                .transactions
+                .iter()
+                .map(|txn| txn.hash())
+                .collect(),
+        )
+    }
+
+    /// Execute the whole block with `tracer`. Changes applied before transactions, such as system
+    /// calls, are not traced.
+    pub(crate) async fn replay_block<'db, Tx: Transaction<'db>>(
+        &self,
+        tx: &Tx,
+        tracer: &mut dyn Tracer,
+    ) -> anyhow::Result<Vec<Receipt>> {
+        let number = self.header.number;
+        let partial_header = PartialHeader::from(self.header.clone());
+        let block_spec = self.chain_spec.collect_block_spec(number);
+        let mut buffer = Buffer::new(tx, BlockNumber(0), Some(BlockNumber(number.0 - 1)));
+        let mut analysis_cache = AnalysisCache::default();
+        let mut engine = consensus::engine_factory(self.chain_spec.clone())?;
+        let mut processor = ExecutionProcessor::new(
+            &mut buffer,
+            None,
+            &mut analysis_cache,
+            &mut *engine,
+            &partial_header,
+            &self.body,
+            &block_spec,
+        );
+
+        processor.execute_block_prologue().await?;
+        processor.set_tracer(Some(tracer));
+
+        let mut receipts = Vec::with_capacity(self.body.transactions.len());
+        for (i, txn) in self.body.transactions.iter().enumerate() {
+            processor
+                .validate_transaction(txn)
+                .await
+                .with_context(|| format!("Failed to validate tx #{}", i))?;
+            receipts.push(processor.execute_transaction(txn).await?);
+        }
+
+        processor.execute_block_epilogue().await?;
+
+        Ok(receipts)
+    }
+
+    /// Execute transactions up to `index`, then transaction `index` with `tracer`.
+    ///
+    /// If `prestate` is set, values of accounts and slots it has collected are read before
+    /// transaction `index` is executed.
+    pub(crate) async fn replay<'db, Tx: Transaction<'db>>(
+        &self,
+        tx: &Tx,
+        index: usize,
+        tracer: Option<&mut dyn Tracer>,
+        prestate: Option<&PrestateTracer>,
+    ) -> anyhow::Result<(u64, CallResult, Option<BTreeMap<Address, PrestateAccount>>)> {
+        let number = self.header.number;
+        let txn = self
+            .body
+            .transactions
+            .get(index)
+            .ok_or_else(|| format_err!("no transaction #{} in block {}", index, number))?;
+
+        let partial_header = PartialHeader::from(self.header.clone());
+        let block_spec = self.chain_spec.collect_block_spec(number);
+        let mut buffer = Buffer::new(tx, BlockNumber(0), Some(BlockNumber(number.0 - 1)));
+        let mut analysis_cache = AnalysisCache::default();
+        let mut engine = consensus::engine_factory(self.chain_spec.clone())?;
+        let mut processor = ExecutionProcessor::new(
+            &mut buffer,
+            None,
+            &mut analysis_cache,
+            &mut *engine,
+            &partial_header,
+            &self.body,
+            &block_spec,
+        );
+
+        processor.execute_block_prologue().await?;
+
+        let mut cumulative_gas_used = 0;
+        for (i, txn) in self.body.transactions[..index].iter().enumerate() {
+            processor
+                .validate_transaction(txn)
+                .await
+                .with_context(|| format!("Failed to validate tx #{}", i))?;
+            cumulative_gas_used = processor
+                .execute_transaction(txn)
+                .await?
+                .cumulative_gas_used;
+        }
+
+        let prestate = match prestate {
+            Some(prestate) => Some(prestate.read(processor.state()).await?),
+            None => None,
+        };
+
+        processor.set_tracer(tracer);
+        processor.validate_transaction(txn).await?;
+        let (receipt, result) = processor.execute_transaction_with_output(txn).await?;
+
+        Ok((
+            receipt.cumulative_gas_used - cumulative_gas_used,
+            result,
+            prestate,
+        ))
+    }
+}
+
+/// Trace canonical transaction `hash` as requested by `options`.
+pub async fn trace_transaction<'db, Tx: Transaction<'db>>(
+    tx: &Tx,
+    hash: H256,
+    options: TraceOptions,
+) -> anyhow::Result<Option<TransactionTrace>> {
+    let (number, block_hash, index, _) = match chain::canonical_tx::read(tx, hash).await? {
+        Some(found) => found,
+        None => return Ok(None),
+    };
+    let block = ReplayBlock::load(tx, block_hash, number).await?;
+
+    Ok(Some(match options.tracer {
+        None => {
+            let mut tracer = StructLogger::new(options.logger);
+            let (gas_used, result, _) = block.replay(tx, index, Some(&mut tracer), None).await?;
+            TransactionTrace::StructLogs(tracer.into_result(gas_used, &result))
+        }
+        Some(TracerKind::Call) => {
+            let mut tracer = CallFrameTracer::default();
+            block.replay(tx, index, Some(&mut tracer), None).await?;
+            let mut root = tracer
+                .into_root()
+                .ok_or_else(|| format_err!("transaction {:?} was not executed", hash))?;
+            if options.tracer_config.only_top_call {
+                root.calls.clear();
+            }
+            TransactionTrace::Call(root)
+        }
+        Some(TracerKind::Prestate) => {
+            // Touched accounts are only known after execution, so the block is replayed twice.
+            let mut tracer = PrestateTracer::default();
+            tracer.add_account(block.header.beneficiary);
+            block.replay(tx, index, Some(&mut tracer), None).await?;
+            let (_, _, prestate) = block.replay(tx, index, None, Some(&tracer)).await?;
+            TransactionTrace::Prestate(prestate.unwrap_or_default())
+        }
+        Some(TracerKind::Eip3155) => {
+            let mut tracer = Eip3155Tracer::new(options.logger, vec![]);
+            let (gas_used, result, _) = block.replay(tx, index, Some(&mut tracer), None).await?;
+            let out = tracer.finish(gas_used, &result)?;
+            TransactionTrace::Eip3155(
+                serde_json::Deserializer::from_slice(&out)
+                    .into_iter()
+                    .collect::<Result<_, _>>()?,
+            )
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_options() {
+        let options: TraceOptions = serde_json::from_str("{}").unwrap();
+        assert_eq!(options, TraceOptions::default());
+
+        let options: TraceOptions =
+            serde_json::from_str(r#"{"tracer":"callTracer","tracerConfig":{"onlyTopCall":true}}"#)
+                .unwrap();
+        assert_eq!(options.tracer, Some(TracerKind::Call));
+        assert!(options.tracer_config.only_top_call);
+
+        let options: TraceOptions =
+            serde_json::from_str(r#"{"disableStack":true,"enableMemory":true}"#).unwrap();
+        assert_eq!(options.tracer, None);
+        assert!(options.logger.disable_stack);
+        assert!(options.logger.enable_memory);
+        assert!(!options.logger.disable_storage);
+
+        let options: TraceOptions =
+            serde_json::from_str(r#"{"tracer":"eip3155Tracer","disableStack":true}"#).unwrap();
+        assert_eq!(options.tracer, Some(TracerKind::Eip3155));
+        assert!(options.logger.disable_stack);
+
+        assert!(serde_json::from_str::<TraceOptions>(r#"{"tracer":"4byteTracer"}"#).is_err());
+    }
+}
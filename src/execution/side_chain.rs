@@ -0,0 +1,370 @@
+//! Execution of blocks that are not canonical, such as payloads of side chains received from
+//! the consensus layer before forkchoice selects them.
+//!
+//! Non-canonical ancestors of the block are executed on top of the historical state of the
+//! canonical block they fork from, accumulating their state in a [`Buffer`]. Canonical chain and
+//! its state are left as is, unless the block forks off the head of staged sync: then its state
+//! is written to compute the state root, in a transaction that is never committed.
+
+use super::{
+    analysis_cache::AnalysisCache, processor::ExecutionProcessor, receipts::history_start,
+};
+use crate::{
+    accessors::chain,
+    consensus::{self, Consensus, ValidationError},
+    kv::traits::*,
+    models::*,
+    stagedsync::stages::{EXECUTION, HASH_STATE, INTERMEDIATE_HASHES},
+    stages::{promote_accounts, promote_storage},
+    state::Buffer,
+    trie::increment_intermediate_hashes,
+    State,
+};
+use anyhow::format_err;
+use tempfile::TempDir;
+
+#[derive(Debug)]
+pub enum SideChainExecution {
+    /// Block and its non-canonical ancestors executed with results matching their headers,
+    /// state root of the block included.
+    Valid,
+    /// Block and its non-canonical ancestors executed with results matching their headers, but
+    /// state root was not computed: the block doesn't fork off the head of staged sync, which is
+    /// the only state intermediate hashes are kept for.
+    Executed,
+    /// Block or one of its non-canonical ancestors failed validation.
+    Invalid(anyhow::Error),
+    /// State the block builds on is not available yet or anymore: an ancestor is missing,
+    /// the fork point is not executed yet or its history is pruned.
+    Unavailable,
+}
+
+/// Validate and execute `block` on top of its chain without making it canonical.
+///
+/// Headers and bodies are validated by the consensus engine and execution results are checked.
+/// State root is only verified for blocks forking off the head of staged sync, state of the side
+/// chain is written into `tx` for that, so `tx` must never be committed.
+pub async fn execute_side_chain_block<'db, RwTx: MutableTransaction<'db>>(
+    tx: &RwTx,
+    etl_dir: &TempDir,
+    block: &Block,
+) -> anyhow::Result<SideChainExecution> {
+    let mut number = match block.header.number.0.checked_sub(1) {
+        Some(number) => BlockNumber(number),
+        None => return Ok(SideChainExecution::Invalid(format_err!("genesis block"))),
+    };
+    let mut hash = block.header.parent_hash;
+
+    // Ancestors from parent down to the fork point.
+    let mut ancestors = vec![];
+    while chain::canonical_hash::read(tx, number).await? != Some(hash) {
+        let header = match chain::header::read(tx, hash, number).await? {
+            Some(header) if number.0 > 0 => header,
+            _ => return Ok(SideChainExecution::Unavailable),
+        };
+        hash = header.parent_hash;
+        number = BlockNumber(number.0 - 1);
+        ancestors.push(header);
+    }
+
+    let fork_point = number;
+    let execution_progress = EXECUTION.get_progress(tx).await?.unwrap_or(BlockNumber(0));
+    if fork_point > execution_progress {
+        return Ok(SideChainExecution::Unavailable);
+    }
+    // State after the fork point is recovered by undoing changes of later canonical blocks.
+    if fork_point < execution_progress
+        && history_start(tx)
+            .await?
+            .map_or(true, |available_from| available_from.0 > fork_point.0 + 1)
+    {
+        return Ok(SideChainExecution::Unavailable);
+    }
+
+    let mut at_head = fork_point == execution_progress;
+    for stage in [HASH_STATE, INTERMEDIATE_HASHES] {
+        at_head &= stage.get_progress(tx).await?.unwrap_or_default() == fork_point;
+    }
+
+    let chain_spec = chain::chain_spec::read(tx).await?;
+
+    let mut buffer = if at_head {
+        // Changesets are what hashed state and intermediate hashes are updated from.
+        Buffer::new(tx, BlockNumber(0), None)
+    } else {
+        // Changes are never written, so there is no history to keep either.
+        Buffer::new(tx, BlockNumber(u64::MAX), Some(fork_point))
+    };
+    let mut engine = consensus::engine_factory(chain_spec.clone())?;
+    let mut analysis_cache = AnalysisCache::default();
+
+    for header in ancestors.into_iter().rev() {
+        let hash = header.hash();
+        let body = chain::block_body::read_without_senders(tx, hash, header.number)
+            .await?
+            .ok_or_else(|| format_err!("body {}/{:?} not found", header.number, hash))?;
+        let block = Block {
+            header,
+            transactions: body.transactions,
+            ommers: body.ommers,
+            withdrawals: body.withdrawals,
+        };
+        if let Err(e) = execute_block(
+            &mut buffer,
+            &mut *engine,
+            &mut analysis_cache,
+            &chain_spec,
+            &block,
+        )
+        .await
+        {
+            return invalid_or_err(e);
+        }
+    }
+
+    if let Err(e) = execute_block(
+        &mut buffer,
+        &mut *engine,
+        &mut analysis_cache,
+        &chain_spec,
+        block,
+    )
+    .await
+    {
+        return invalid_or_err(e);
+    }
+
+    if !at_head {
+        return Ok(SideChainExecution::Executed);
+    }
+
+    buffer.write_to_db().await?;
+    promote_accounts(tx, fork_point).await?;
+    promote_storage(tx, fork_point).await?;
+    let state_root = increment_intermediate_hashes(tx, etl_dir, fork_point, None).await?;
+    if state_root != block.header.state_root {
+        return Ok(SideChainExecution::Invalid(
+            ValidationError::WrongStateRoot {
+                expected: block.header.state_root,
+                got: state_root,
+            }
+            .into(),
+        ));
+    }
+
+    Ok(SideChainExecution::Valid)
+}
+
+/// Failed validation makes the block invalid, other errors are our own.
+fn invalid_or_err(e: anyhow::Error) -> anyhow::Result<SideChainExecution> {
+    if e.downcast_ref::<ValidationError>().is_some() {
+        Ok(SideChainExecution::Invalid(e))
+    } else {
+        Err(e)
+    }
+}
+
+async fn execute_block<S: State>(
+    state: &mut S,
+    engine: &mut dyn Consensus,
+    analysis_cache: &mut AnalysisCache,
+    chain_spec: &ChainSpec,
+    block: &Block,
+) -> anyhow::Result<()> {
+    // Timestamps of payloads are set by the consensus layer, no need to check them against ours.
+    engine
+        .validate_block_header(&block.header, state, false)
+        .await?;
+    engine.pre_validate_block(block, state).await?;
+
+    let transactions = block
+        .transactions
+        .iter()
+        .map(|txn| {
+            Ok(MessageWithSender {
+                message: txn.message.clone(),
+                sender: txn
+                    .recover_sender()
+                    .map_err(|_| ValidationError::InvalidSignature)?,
+            })
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let body = BlockBodyWithSenders {
+        transactions,
+        ommers: block.ommers.clone(),
+        withdrawals: block.withdrawals.clone(),
+    };
+
+    let header = PartialHeader::from(block.header.clone());
+    let block_spec = chain_spec.collect_block_spec(header.number);
+    ExecutionProcessor::new(
+        state,
+        None,
+        analysis_cache,
+        engine,
+        &header,
+        &body,
+        &block_spec,
+    )
+    .execute_and_write_block()
+    .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        genesis::initialize_genesis, kv::new_mem_database, res::chainspec::MAINNET,
+        util::test_util::run_test,
+    };
+    use hex_literal::hex;
+    use tempfile::TempDir;
+
+    #[test]
+    fn missing_ancestors() {
+        run_test(async {
+            let db = new_mem_database().unwrap();
+            let tx = db.begin_mutable().await.unwrap();
+            let etl_dir = TempDir::new().unwrap();
+
+            let block = |number| {
+                Block::new(
+                    PartialHeader {
+                        number: BlockNumber(number),
+                        parent_hash: H256::repeat_byte(1),
+                        ..PartialHeader::empty()
+                    },
+                    vec![],
+                    vec![],
+                )
+            };
+
+            assert!(matches!(
+                execute_side_chain_block(&tx, &etl_dir, &block(0))
+                    .await
+                    .unwrap(),
+                SideChainExecution::Invalid(_)
+            ));
+            // Parent is neither canonical nor stored.
+            assert!(matches!(
+                execute_side_chain_block(&tx, &etl_dir, &block(10))
+                    .await
+                    .unwrap(),
+                SideChainExecution::Unavailable
+            ));
+        })
+    }
+
+    #[test]
+    fn invalid_header() {
+        run_test(async {
+            let db = new_mem_database().unwrap();
+            let tx = db.begin_mutable().await.unwrap();
+            let etl_dir = TempDir::new().unwrap();
+            initialize_genesis(&tx, &etl_dir, MAINNET.clone())
+                .await
+                .unwrap();
+            let genesis = chain::header::read(
+                &tx,
+                chain::canonical_hash::read(&tx, BlockNumber(0))
+                    .await
+                    .unwrap()
+                    .unwrap(),
+                BlockNumber(0),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+
+            // Executes fine, but is not after its parent.
+            let block = Block::new(
+                PartialHeader {
+                    parent_hash: genesis.hash(),
+                    number: BlockNumber(1),
+                    difficulty: genesis.difficulty,
+                    gas_limit: genesis.gas_limit,
+                    timestamp: genesis.timestamp,
+                    ..PartialHeader::empty()
+                },
+                vec![],
+                vec![],
+            );
+            match execute_side_chain_block(&tx, &etl_dir, &block)
+                .await
+                .unwrap()
+            {
+                SideChainExecution::Invalid(e) => assert!(matches!(
+                    e.downcast::<ValidationError>().unwrap(),
+                    ValidationError::InvalidTimestamp { .. }
+                )),
+                other => panic!("unexpected {:?}", other),
+            }
+        })
+    }
+
+    #[test]
+    fn state_root() {
+        run_test(async {
+            let db = new_mem_database().unwrap();
+            let tx = db.begin_mutable().await.unwrap();
+            let etl_dir = TempDir::new().unwrap();
+            initialize_genesis(&tx, &etl_dir, MAINNET.clone())
+                .await
+                .unwrap();
+            let genesis_hash = chain::canonical_hash::read(&tx, BlockNumber(0))
+                .await
+                .unwrap()
+                .unwrap();
+            tx.commit().await.unwrap();
+
+            // Mainnet block 1, which only pays the block reward.
+            let block = |state_root| {
+                Block::new(
+                    PartialHeader {
+                        parent_hash: genesis_hash,
+                        beneficiary: hex!("05a56e2d52c817161883f50c441c3228cfe54d9f").into(),
+                        state_root,
+                        difficulty: 17_171_480_576_u64.as_u256(),
+                        number: BlockNumber(1),
+                        gas_limit: 5000,
+                        timestamp: 1_438_269_988,
+                        ..PartialHeader::empty()
+                    },
+                    vec![],
+                    vec![],
+                )
+            };
+
+            let tx = db.begin_mutable().await.unwrap();
+            assert!(matches!(
+                execute_side_chain_block(
+                    &tx,
+                    &etl_dir,
+                    &block(
+                        hex!("d67e4d450343046425ae4271474353857ab860dbc0a1dde64b41b5cd3a532bf3")
+                            .into()
+                    )
+                )
+                .await
+                .unwrap(),
+                SideChainExecution::Valid
+            ));
+            // State of the block is thrown away.
+            drop(tx);
+
+            let tx = db.begin_mutable().await.unwrap();
+            match execute_side_chain_block(&tx, &etl_dir, &block(H256::zero()))
+                .await
+                .unwrap()
+            {
+                SideChainExecution::Invalid(e) => assert!(matches!(
+                    e.downcast::<ValidationError>().unwrap(),
+                    ValidationError::WrongStateRoot { .. }
+                )),
+                other => panic!("unexpected {:?}", other),
+            }
+        })
+    }
+}
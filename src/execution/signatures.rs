@@ -0,0 +1,118 @@
+//! Signatures of widely used contract functions, to name calls in traces by their selector.
+//!
+//! Selectors are computed from the signatures on first use, so only the signatures are listed.
+
+use crate::crypto::keccak256;
+use ethereum_types::H32;
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+
+const SIGNATURES: &[&str] = &[
+    // ERC-20
+    "totalSupply()",
+    "balanceOf(address)",
+    "transfer(address,uint256)",
+    "transferFrom(address,address,uint256)",
+    "approve(address,uint256)",
+    "allowance(address,address)",
+    "name()",
+    "symbol()",
+    "decimals()",
+    "increaseAllowance(address,uint256)",
+    "decreaseAllowance(address,uint256)",
+    // EIP-2612
+    "permit(address,address,uint256,uint256,uint8,bytes32,bytes32)",
+    "nonces(address)",
+    "DOMAIN_SEPARATOR()",
+    // ERC-721 and ERC-1155
+    "ownerOf(uint256)",
+    "safeTransferFrom(address,address,uint256)",
+    "safeTransferFrom(address,address,uint256,bytes)",
+    "setApprovalForAll(address,bool)",
+    "isApprovedForAll(address,address)",
+    "getApproved(uint256)",
+    "tokenURI(uint256)",
+    "safeTransferFrom(address,address,uint256,uint256,bytes)",
+    "safeBatchTransferFrom(address,address,uint256[],uint256[],bytes)",
+    "balanceOfBatch(address[],uint256[])",
+    "onERC721Received(address,address,uint256,bytes)",
+    "onERC1155Received(address,address,uint256,uint256,bytes)",
+    // ERC-165
+    "supportsInterface(bytes4)",
+    // WETH
+    "deposit()",
+    "withdraw(uint256)",
+    // Ownable and proxies
+    "owner()",
+    "transferOwnership(address)",
+    "renounceOwnership()",
+    "implementation()",
+    "upgradeTo(address)",
+    "upgradeToAndCall(address,bytes)",
+    // Multicall
+    "multicall(bytes[])",
+    "aggregate((address,bytes)[])",
+    "tryAggregate(bool,(address,bytes)[])",
+    // Uniswap V2
+    "getReserves()",
+    "swap(uint256,uint256,address,bytes)",
+    "sync()",
+    "skim(address)",
+    "mint(address)",
+    "burn(address)",
+    "swapExactTokensForTokens(uint256,uint256,address[],address,uint256)",
+    "swapTokensForExactTokens(uint256,uint256,address[],address,uint256)",
+    "swapExactETHForTokens(uint256,address[],address,uint256)",
+    "swapETHForExactTokens(uint256,address[],address,uint256)",
+    "swapExactTokensForETH(uint256,uint256,address[],address,uint256)",
+    "swapTokensForExactETH(uint256,uint256,address[],address,uint256)",
+    "addLiquidity(address,address,uint256,uint256,uint256,uint256,address,uint256)",
+    "addLiquidityETH(address,uint256,uint256,uint256,address,uint256)",
+    "removeLiquidity(address,address,uint256,uint256,uint256,address,uint256)",
+    "removeLiquidityETH(address,uint256,uint256,uint256,address,uint256)",
+    "getAmountsOut(uint256,address[])",
+    "getAmountsIn(uint256,address[])",
+    // Uniswap V3
+    "swap(address,bool,int256,uint160,bytes)",
+    "uniswapV3SwapCallback(int256,int256,bytes)",
+    "slot0()",
+    "exactInputSingle((address,address,uint24,address,uint256,uint256,uint256,uint160))",
+    "exactInput((bytes,address,uint256,uint256,uint256))",
+    "exactOutputSingle((address,address,uint24,address,uint256,uint256,uint256,uint160))",
+    "exactOutput((bytes,address,uint256,uint256,uint256))",
+    // EIP-4337 entry point
+    "handleOps((address,uint256,bytes,bytes,uint256,uint256,uint256,uint256,uint256,bytes,bytes)[],address)",
+    "simulateValidation((address,uint256,bytes,bytes,uint256,uint256,uint256,uint256,uint256,bytes,bytes))",
+    "getNonce(address,uint192)",
+];
+
+static SELECTORS: Lazy<HashMap<H32, &'static str>> = Lazy::new(|| {
+    SIGNATURES
+        .iter()
+        .map(|&signature| (H32::from_slice(&keccak256(signature)[..4]), signature))
+        .collect()
+});
+
+/// Signature of a well-known function with selector `selector`.
+pub fn lookup(selector: H32) -> Option<&'static str> {
+    SELECTORS.get(&selector).copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hex_literal::hex;
+
+    #[test]
+    fn known_selectors() {
+        assert_eq!(
+            lookup(H32(hex!("a9059cbb"))),
+            Some("transfer(address,uint256)")
+        );
+        assert_eq!(
+            lookup(H32(hex!("095ea7b3"))),
+            Some("approve(address,uint256)")
+        );
+        assert_eq!(lookup(H32(hex!("deadbeef"))), None);
+    }
+}
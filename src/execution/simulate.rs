@@ -0,0 +1,431 @@
+//! Multi-block call simulation, as in `eth_simulateV1`.
+//!
+//! Blocks are built on top of a canonical block with [`ExecutionProcessor`], and their state
+//! is accumulated in a [`Buffer`] that is never written to the database.
+
+use super::{
+    analysis_cache::AnalysisCache, call_tracer::decode_revert_reason, processor::ExecutionProcessor,
+};
+use crate::{
+    accessors::chain,
+    consensus::{self, pre_validate_transaction},
+    h256_to_u256, hexbytes,
+    kv::traits::*,
+    models::*,
+    state::Buffer,
+    State,
+};
+use anyhow::{ensure, format_err};
+use bytes::Bytes;
+use evmodin::StatusCode;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::HashMap;
+
+/// Seconds between simulated blocks unless timestamp is overridden.
+const BLOCK_TIME: u64 = 12;
+
+fn deserialize_code<'de, D>(deserializer: D) -> Result<Option<Bytes>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    hexbytes::deserialize(deserializer).map(Some)
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockOverrides {
+    pub number: Option<BlockNumber>,
+    pub time: Option<U64>,
+    pub gas_limit: Option<U64>,
+    pub fee_recipient: Option<Address>,
+    pub prev_randao: Option<H256>,
+    pub base_fee_per_gas: Option<U256>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountOverride {
+    pub balance: Option<U256>,
+    pub nonce: Option<U64>,
+    #[serde(default, deserialize_with = "deserialize_code")]
+    pub code: Option<Bytes>,
+    /// Replaces the whole storage of the account.
+    pub state: Option<HashMap<H256, H256>>,
+    /// Replaces only the given slots.
+    pub state_diff: Option<HashMap<H256, H256>>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SimulatedCall {
+    pub from: Option<Address>,
+    pub to: Option<Address>,
+    pub gas: Option<U64>,
+    pub value: Option<U256>,
+    pub nonce: Option<U64>,
+    pub max_fee_per_gas: Option<U256>,
+    pub max_priority_fee_per_gas: Option<U256>,
+    #[serde(default, alias = "input", with = "hexbytes")]
+    pub data: Bytes,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockStateCalls {
+    #[serde(default)]
+    pub block_overrides: BlockOverrides,
+    #[serde(default)]
+    pub state_overrides: HashMap<Address, AccountOverride>,
+    #[serde(default)]
+    pub calls: Vec<SimulatedCall>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SimulatedCallError {
+    pub message: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SimulatedCallResult {
+    pub status: U64,
+    #[serde(with = "hexbytes")]
+    pub return_data: Bytes,
+    pub gas_used: U64,
+    pub logs: Vec<Log>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<SimulatedCallError>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SimulatedBlock {
+    pub number: BlockNumber,
+    /// Hash of the block with empty state and receipts roots, as they are not computed.
+    pub hash: H256,
+    pub timestamp: U64,
+    pub gas_limit: U64,
+    pub gas_used: U64,
+    pub fee_recipient: Address,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub base_fee_per_gas: Option<U256>,
+    pub calls: Vec<SimulatedCallResult>,
+}
+
+async fn apply_state_overrides<S: State>(
+    processor: &mut ExecutionProcessor<'_, '_, '_, '_, '_, '_, '_, S>,
+    overrides: &HashMap<Address, AccountOverride>,
+) -> anyhow::Result<()> {
+    let state = processor.state();
+    for (&address, account) in overrides {
+        if let Some(balance) = account.balance {
+            state.set_balance(address, balance).await?;
+        }
+        if let Some(nonce) = account.nonce {
+            state.set_nonce(address, nonce.as_u64()).await?;
+        }
+        if let Some(code) = &account.code {
+            state.set_code(address, code.clone()).await?;
+        }
+        for (&key, &value) in account.state.iter().chain(&account.state_diff).flatten() {
+            state
+                .set_storage(address, h256_to_u256(key), h256_to_u256(value))
+                .await?;
+        }
+    }
+    // Overrides are part of the pre-state, not of the first call.
+    state.finalize_transaction();
+
+    Ok(())
+}
+
+fn call_result(
+    receipt: Receipt,
+    gas_used: u64,
+    status_code: StatusCode,
+    output: Bytes,
+) -> SimulatedCallResult {
+    let error = match status_code {
+        StatusCode::Success => None,
+        StatusCode::Revert => Some(SimulatedCallError {
+            message: match decode_revert_reason(&output) {
+                Some(reason) => format!("execution reverted: {}", reason),
+                None => "execution reverted".to_string(),
+            },
+        }),
+        other => Some(SimulatedCallError {
+            message: format!("{:?}", other),
+        }),
+    };
+
+    SimulatedCallResult {
+        status: u64::from(receipt.success).into(),
+        return_data: output,
+        gas_used: gas_used.into(),
+        logs: receipt.logs,
+        error,
+    }
+}
+
+/// Simulate `blocks` on top of `parent`.
+///
+/// Unless `validation` is set, nonces, balances and fees are not checked and base fee defaults to zero,
+/// so that calls can be made from arbitrary accounts.
+pub async fn simulate<S: State>(
+    state: &mut S,
+    chain_spec: &ChainSpec,
+    parent: &BlockHeader,
+    blocks: Vec<BlockStateCalls>,
+    validation: bool,
+) -> anyhow::Result<Vec<SimulatedBlock>> {
+    let mut engine = consensus::engine_factory(chain_spec.clone())?;
+    let mut analysis_cache = AnalysisCache::default();
+    let mut parent_hash = parent.hash();
+    let mut parent = PartialHeader::from(parent.clone());
+    let mut out = Vec::with_capacity(blocks.len());
+
+    for block in blocks {
+        let overrides = block.block_overrides;
+
+        let number = overrides.number.unwrap_or(parent.number + 1);
+        ensure!(
+            number > parent.number,
+            "block number {} is not greater than {}",
+            number,
+            parent.number
+        );
+        let timestamp = overrides
+            .time
+            .map(|time| time.as_u64())
+            .unwrap_or(parent.timestamp + BLOCK_TIME);
+        ensure!(
+            timestamp > parent.timestamp,
+            "block timestamp {} is not greater than {}",
+            timestamp,
+            parent.timestamp
+        );
+
+        let header = PartialHeader {
+            parent_hash,
+            number,
+            timestamp,
+            beneficiary: overrides.fee_recipient.unwrap_or(parent.beneficiary),
+            gas_limit: overrides
+                .gas_limit
+                .map(|gas_limit| gas_limit.as_u64())
+                .unwrap_or(parent.gas_limit),
+            mix_hash: overrides.prev_randao.unwrap_or_default(),
+            base_fee_per_gas: overrides.base_fee_per_gas.or(if validation {
+                parent.base_fee_per_gas
+            } else {
+                parent.base_fee_per_gas.map(|_| U256::ZERO)
+            }),
+            ..PartialHeader::empty()
+        };
+        let block_spec = chain_spec.collect_block_spec(number);
+        let body = BlockBodyWithSenders::default();
+
+        state.begin_block(number);
+        for (&address, account) in &block.state_overrides {
+            if account.state.is_some() {
+                state.erase_storage(address).await?;
+            }
+        }
+
+        let mut processor = ExecutionProcessor::new(
+            state,
+            None,
+            &mut analysis_cache,
+            &mut *engine,
+            &header,
+            &body,
+            &block_spec,
+        );
+
+        apply_state_overrides(&mut processor, &block.state_overrides).await?;
+
+        let mut calls = Vec::with_capacity(block.calls.len());
+        let mut cumulative_gas_used = 0;
+        for (i, call) in block.calls.into_iter().enumerate() {
+            let sender = call.from.unwrap_or_else(Address::zero);
+            let nonce = match call.nonce {
+                Some(nonce) => nonce.as_u64(),
+                None => processor.state().get_nonce(sender).await?,
+            };
+            let txn = MessageWithSender {
+                message: Message::EIP1559 {
+                    chain_id: block_spec.params.chain_id,
+                    nonce,
+                    max_priority_fee_per_gas: call.max_priority_fee_per_gas.unwrap_or(U256::ZERO),
+                    max_fee_per_gas: call.max_fee_per_gas.unwrap_or(U256::ZERO),
+                    gas_limit: call
+                        .gas
+                        .map(|gas| gas.as_u64())
+                        .unwrap_or_else(|| processor.available_gas()),
+                    action: match call.to {
+                        Some(to) => TransactionAction::Call(to),
+                        None => TransactionAction::Create,
+                    },
+                    value: call.value.unwrap_or(U256::ZERO),
+                    input: call.data,
+                    access_list: Default::default(),
+                },
+                sender,
+            };
+
+            if validation {
+                pre_validate_transaction(&txn, block_spec.params.chain_id, header.base_fee_per_gas)
+                    .map_err(|e| format_err!("call #{} in block {}: {:?}", i, number, e))?;
+                processor.validate_transaction(&txn).await?;
+            }
+
+            let (receipt, res) = processor.execute_transaction_with_output(&txn).await?;
+            let gas_used = receipt.cumulative_gas_used - cumulative_gas_used;
+            cumulative_gas_used = receipt.cumulative_gas_used;
+
+            calls.push(call_result(
+                receipt,
+                gas_used,
+                res.status_code,
+                res.output_data,
+            ));
+        }
+
+        processor.into_state().write_to_db(number).await?;
+
+        let hash = BlockHeader::new(header.clone(), EMPTY_LIST_HASH, EMPTY_ROOT).hash();
+        out.push(SimulatedBlock {
+            number,
+            hash,
+            timestamp: timestamp.into(),
+            gas_limit: header.gas_limit.into(),
+            gas_used: cumulative_gas_used.into(),
+            fee_recipient: header.beneficiary,
+            base_fee_per_gas: header.base_fee_per_gas,
+            calls,
+        });
+
+        parent = header;
+        parent_hash = hash;
+    }
+
+    Ok(out)
+}
+
+/// Run [`simulate`] on top of the state after canonical block `block_number`.
+pub async fn simulate_blocks<'db, Tx: Transaction<'db>>(
+    tx: &Tx,
+    block_number: BlockNumber,
+    blocks: Vec<BlockStateCalls>,
+    validation: bool,
+) -> anyhow::Result<Vec<SimulatedBlock>> {
+    let chain_spec = chain::chain_spec::read(tx).await?;
+
+    let header = chain::header::read_canonical(tx, block_number).await?;
+
+    let mut buffer = Buffer::new(tx, BlockNumber(0), Some(block_number));
+
+    simulate(&mut buffer, &chain_spec, &header, blocks, validation).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        res::chainspec::MAINNET, u256_to_h256, util::test_util::run_test, InMemoryState,
+        StateReader,
+    };
+    use hex_literal::hex;
+
+    #[test]
+    fn state_carries_over_between_blocks() {
+        run_test(async {
+            let parent = BlockHeader {
+                number: 13_000_000.into(),
+                gas_limit: 30_000_000,
+                timestamp: 1_628_166_822,
+                base_fee_per_gas: Some(U256::from(GIGA)),
+                ..BlockHeader::empty()
+            };
+
+            let contract: Address = hex!("0a6bb546b9208cfab9e8fa2b9b2c042b18df7030").into();
+            let sender = hex!("b685342b8c54347aad148e1f22eff3eb3eb29391").into();
+
+            // 0      PUSH1  => 00
+            // 2      SLOAD
+            // 3      PUSH1  => 01
+            // 5      ADD
+            // 6      DUP1
+            // 7      PUSH1  => 00
+            // 9      SSTORE
+            // 10     PUSH1  => 00
+            // 12     MSTORE
+            // 13     PUSH1  => 20
+            // 15     PUSH1  => 00
+            // 17     RETURN
+            let code = hex!("6000546001018060005560005260206000f3");
+
+            let counter = |value: u64| Bytes::from(u256_to_h256(value.as_u256()).0.to_vec());
+            let call = SimulatedCall {
+                from: Some(sender),
+                to: Some(contract),
+                ..Default::default()
+            };
+
+            let blocks = vec![
+                BlockStateCalls {
+                    state_overrides: [(
+                        contract,
+                        AccountOverride {
+                            code: Some(code.to_vec().into()),
+                            state_diff: Some(
+                                [(H256::zero(), u256_to_h256(41.as_u256()))]
+                                    .into_iter()
+                                    .collect(),
+                            ),
+                            ..Default::default()
+                        },
+                    )]
+                    .into_iter()
+                    .collect(),
+                    calls: vec![call.clone()],
+                    ..Default::default()
+                },
+                BlockStateCalls {
+                    block_overrides: BlockOverrides {
+                        time: Some((parent.timestamp + 100).into()),
+                        ..Default::default()
+                    },
+                    calls: vec![call.clone(), call],
+                    ..Default::default()
+                },
+            ];
+
+            let mut state = InMemoryState::default();
+            let res = simulate(&mut state, &MAINNET, &parent, blocks, false)
+                .await
+                .unwrap();
+
+            assert_eq!(res.len(), 2);
+            assert_eq!(res[0].number, parent.number + 1);
+            assert_eq!(res[1].number, parent.number + 2);
+            assert_eq!(res[0].timestamp, (parent.timestamp + BLOCK_TIME).into());
+            assert_eq!(res[0].base_fee_per_gas, Some(U256::ZERO));
+            assert_eq!(res[0].calls[0].return_data, counter(42));
+
+            assert_eq!(res[1].timestamp, (parent.timestamp + 100).into());
+            assert_eq!(res[1].calls[0].return_data, counter(43));
+            assert_eq!(res[1].calls[1].return_data, counter(44));
+            assert_eq!(res[1].calls[1].status, U64::one());
+            assert_eq!(res[1].calls[1].error, None);
+            assert_eq!(
+                res[1].gas_used,
+                res[1].calls[0].gas_used + res[1].calls[1].gas_used
+            );
+
+            // Sender nonce is tracked across blocks.
+            assert_eq!(state.read_account(sender).await.unwrap().unwrap().nonce, 3);
+        })
+    }
+}
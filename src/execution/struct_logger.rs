@@ -0,0 +1,302 @@
+//! Instruction-level trace of a transaction, in the format of geth's default struct logger.
+//!
+//! Gas cost of an instruction is derived from gas left before the next instruction of the same
+//! frame, so for calls and creations it includes gas used by the callee.
+
+use super::{evm::CallResult, tracer::*};
+use crate::{hexbytes, models::*, u256_to_h256};
+use bytes::Bytes;
+use evmodin::{ExecutionState, OpCode, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+/// Options of geth's struct logger.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct StructLoggerConfig {
+    pub disable_stack: bool,
+    pub disable_storage: bool,
+    pub enable_memory: bool,
+    pub enable_return_data: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StructLog {
+    pub pc: u64,
+    pub op: String,
+    pub gas: u64,
+    pub gas_cost: u64,
+    /// Starts from 1 for the top-level message, as in geth.
+    pub depth: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Bottom to top.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stack: Option<Vec<U256>>,
+    /// 32-byte words in hex.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memory: Option<Vec<String>>,
+    /// Slots of the current contract accessed so far, set on `SLOAD` and `SSTORE`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub storage: Option<BTreeMap<H256, H256>>,
+    #[serde(with = "hexbytes", skip_serializing_if = "Bytes::is_empty")]
+    pub return_data: Bytes,
+}
+
+/// Result of `debug_traceTransaction` with the default tracer.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StructLoggerResult {
+    pub gas: u64,
+    pub failed: bool,
+    #[serde(with = "hexbytes")]
+    pub return_value: Bytes,
+    pub struct_logs: Vec<StructLog>,
+}
+
+#[derive(Debug)]
+struct Frame {
+    address: Address,
+    /// Index of the last log of this frame.
+    last_log: Option<usize>,
+    /// Key of `SLOAD` whose value is on the stack before the next instruction.
+    pending_load: Option<H256>,
+}
+
+#[derive(Debug, Default)]
+pub struct StructLogger {
+    config: StructLoggerConfig,
+    logs: Vec<StructLog>,
+    frames: Vec<Frame>,
+    storage: HashMap<Address, BTreeMap<H256, H256>>,
+}
+
+impl StructLogger {
+    pub fn new(config: StructLoggerConfig) -> Self {
+        Self {
+            config,
+            ..Default::default()
+        }
+    }
+
+    pub fn logs(&self) -> &[StructLog] {
+        &self.logs
+    }
+
+    /// Result of the traced transaction that used `gas_used` gas and ended with `result`.
+    pub fn into_result(self, gas_used: u64, result: &CallResult) -> StructLoggerResult {
+        StructLoggerResult {
+            gas: gas_used,
+            failed: result.status_code != StatusCode::Success,
+            return_value: result.output_data.clone(),
+            struct_logs: self.logs,
+        }
+    }
+
+    fn set_gas_cost(&mut self, index: usize, gas_left: u64) {
+        let log = &mut self.logs[index];
+        log.gas_cost = log.gas.saturating_sub(gas_left);
+    }
+}
+
+impl Tracer for StructLogger {
+    fn trace_instructions(&self) -> bool {
+        true
+    }
+
+    fn capture_start(
+        &mut self,
+        _: u16,
+        _: Address,
+        to: Address,
+        _: MessageKind,
+        _: Bytes,
+        _: u64,
+        _: U256,
+    ) {
+        self.frames.push(Frame {
+            address: to,
+            last_log: None,
+            pending_load: None,
+        });
+    }
+
+    fn capture_state(
+        &mut self,
+        env: &ExecutionState,
+        pc: u64,
+        op: OpCode,
+        _: u64,
+        return_data: Bytes,
+        depth: u16,
+        _: StatusCode,
+    ) {
+        let gas = env.gas_left.max(0) as u64;
+        let stack_len = env.stack.len();
+        let stack_item = |i: usize| *env.stack.get(i);
+
+        let frame = match self.frames.last_mut() {
+            Some(frame) => frame,
+            None => return,
+        };
+        let address = frame.address;
+        let last_log = frame.last_log.replace(self.logs.len());
+        let pending_load = frame.pending_load.take();
+
+        if let Some(index) = last_log {
+            self.set_gas_cost(index, gas);
+
+            // Value loaded by the previous instruction is now on top of the stack.
+            if let Some(key) = pending_load {
+                if stack_len > 0 {
+                    let value = u256_to_h256(stack_item(0));
+                    self.storage.entry(address).or_default().insert(key, value);
+                    if let Some(storage) = &mut self.logs[index].storage {
+                        storage.insert(key, value);
+                    }
+                }
+            }
+        }
+
+        let mut storage = None;
+        if !self.config.disable_storage {
+            if op == OpCode::SSTORE && stack_len >= 2 {
+                let contract_storage = self.storage.entry(address).or_default();
+                contract_storage.insert(u256_to_h256(stack_item(0)), u256_to_h256(stack_item(1)));
+                storage = Some(contract_storage.clone());
+            } else if op == OpCode::SLOAD && stack_len >= 1 {
+                if let Some(frame) = self.frames.last_mut() {
+                    frame.pending_load = Some(u256_to_h256(stack_item(0)));
+                }
+                storage = Some(self.storage.get(&address).cloned().unwrap_or_default());
+            }
+        }
+
+        self.logs.push(StructLog {
+            pc,
+            op: op.to_string(),
+            gas,
+            gas_cost: 0,
+            depth: depth + 1,
+            error: None,
+            stack: if self.config.disable_stack {
+                None
+            } else {
+                Some((0..stack_len).rev().map(stack_item).collect())
+            },
+            memory: if self.config.enable_memory {
+                Some(env.memory.chunks(32).map(hex::encode).collect())
+            } else {
+                None
+            },
+            storage,
+            return_data: if self.config.enable_return_data {
+                return_data
+            } else {
+                Bytes::new()
+            },
+        });
+    }
+
+    fn capture_end(&mut self, _: u16, _: Bytes, gas_left: u64, err: StatusCode) {
+        let frame = match self.frames.pop() {
+            Some(frame) => frame,
+            None => return,
+        };
+
+        if let Some(index) = frame.last_log {
+            self.set_gas_cost(index, gas_left);
+            if err != StatusCode::Success && err != StatusCode::Revert {
+                self.logs[index].error = Some(format!("{:?}", err));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        execution::{analysis_cache::AnalysisCache, evm},
+        res::chainspec::MAINNET,
+        state::IntraBlockState,
+        util::test_util::run_test,
+        InMemoryState,
+    };
+    use hex_literal::hex;
+
+    #[test]
+    fn logs_instructions() {
+        run_test(async {
+            let header = PartialHeader {
+                number: 13_000_000.into(),
+                ..PartialHeader::empty()
+            };
+            let block_spec = MAINNET.collect_block_spec(header.number);
+
+            let contract = hex!("0a6bb546b9208cfab9e8fa2b9b2c042b18df7030").into();
+            let sender = hex!("b685342b8c54347aad148e1f22eff3eb3eb29391").into();
+
+            let mut db = InMemoryState::default();
+            let mut state = IntraBlockState::new(&mut db);
+
+            // 0      PUSH1  => 2a
+            // 2      PUSH1  => 01
+            // 4      SSTORE
+            // 5      PUSH1  => 01
+            // 7      SLOAD
+            // 8      STOP
+            state
+                .set_code(contract, hex!("602a60015560015400").to_vec().into())
+                .await
+                .unwrap();
+
+            let txn = MessageWithSender {
+                message: Message::Legacy {
+                    chain_id: Some(block_spec.params.chain_id),
+                    nonce: 0,
+                    gas_price: U256::ZERO,
+                    gas_limit: 100_000,
+                    action: TransactionAction::Call(contract),
+                    value: U256::ZERO,
+                    input: Bytes::new(),
+                },
+                sender,
+            };
+
+            let mut tracer = StructLogger::new(StructLoggerConfig::default());
+            let res = evm::execute(
+                &mut state,
+                Some(&mut tracer),
+                &mut AnalysisCache::default(),
+                &header,
+                &block_spec,
+                &txn,
+                100_000,
+            )
+            .await
+            .unwrap();
+            assert_eq!(res.status_code, StatusCode::Success);
+
+            let logs = tracer.logs();
+            assert_eq!(
+                logs.iter().map(|log| log.op.as_str()).collect::<Vec<_>>(),
+                ["PUSH1", "PUSH1", "SSTORE", "PUSH1", "SLOAD", "STOP"]
+            );
+            assert!(logs.iter().all(|log| log.depth == 1));
+            assert_eq!(logs[2].stack, Some(vec![0x2a.as_u256(), 0x01.as_u256()]));
+
+            let slot = u256_to_h256(0x01.as_u256());
+            let value = u256_to_h256(0x2a.as_u256());
+            assert_eq!(logs[2].storage, Some([(slot, value)].into()));
+            assert_eq!(logs[4].storage, Some([(slot, value)].into()));
+            assert_eq!(logs[0].storage, None);
+
+            // SSTORE of a new slot is the most expensive instruction.
+            assert!(logs[2].gas_cost >= 20_000);
+            assert!(logs[2].gas_cost > logs[4].gas_cost);
+            assert_eq!(logs[5].gas_cost, 0);
+        })
+    }
+}
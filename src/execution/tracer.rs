@@ -1,4 +1,4 @@
-use crate::models::*;
+use crate::{consensus::RewardKind, models::*};
 use bytes::Bytes;
 use evmodin::{ExecutionState, OpCode, StatusCode};
 use std::collections::{BTreeMap, HashMap};
@@ -28,6 +28,10 @@ pub enum MessageKind {
 
 #[allow(unused, clippy::too_many_arguments)]
 pub trait Tracer: Send + 'static {
+    /// Whether `capture_state` should be called before each instruction. Slows down execution.
+    fn trace_instructions(&self) -> bool {
+        false
+    }
     fn capture_start(
         &mut self,
         depth: u16,
@@ -51,9 +55,156 @@ pub trait Tracer: Send + 'static {
     ) {
     }
     fn capture_end(&mut self, depth: u16, output: Bytes, gas_left: u64, err: StatusCode) {}
-    fn capture_self_destruct(&mut self, caller: Address, beneficiary: Address) {}
+    fn capture_self_destruct(&mut self, caller: Address, beneficiary: Address, balance: U256) {}
+    /// Block or ommer reward paid after all transactions of the block.
+    fn capture_reward(&mut self, beneficiary: Address, amount: U256, kind: RewardKind) {}
     fn capture_account_read(&mut self, account: Address) {}
     fn capture_account_write(&mut self, account: Address) {}
+    fn capture_storage_read(&mut self, address: Address, location: U256) {}
+    fn capture_storage_write(&mut self, address: Address, location: U256) {}
+}
+
+/// Runs both tracers on every event.
+impl<A: Tracer, B: Tracer> Tracer for (A, B) {
+    fn trace_instructions(&self) -> bool {
+        self.0.trace_instructions() || self.1.trace_instructions()
+    }
+    fn capture_start(
+        &mut self,
+        depth: u16,
+        from: Address,
+        to: Address,
+        call_type: MessageKind,
+        input: Bytes,
+        gas: u64,
+        value: U256,
+    ) {
+        self.0.capture_start(
+            depth,
+            from,
+            to,
+            call_type.clone(),
+            input.clone(),
+            gas,
+            value,
+        );
+        self.1
+            .capture_start(depth, from, to, call_type, input, gas, value);
+    }
+    fn capture_state(
+        &mut self,
+        env: &ExecutionState,
+        pc: u64,
+        op: OpCode,
+        cost: u64,
+        return_data: Bytes,
+        depth: u16,
+        err: StatusCode,
+    ) {
+        self.0
+            .capture_state(env, pc, op, cost, return_data.clone(), depth, err.clone());
+        self.1
+            .capture_state(env, pc, op, cost, return_data, depth, err);
+    }
+    fn capture_end(&mut self, depth: u16, output: Bytes, gas_left: u64, err: StatusCode) {
+        self.0
+            .capture_end(depth, output.clone(), gas_left, err.clone());
+        self.1.capture_end(depth, output, gas_left, err);
+    }
+    fn capture_self_destruct(&mut self, caller: Address, beneficiary: Address, balance: U256) {
+        self.0.capture_self_destruct(caller, beneficiary, balance);
+        self.1.capture_self_destruct(caller, beneficiary, balance);
+    }
+    fn capture_reward(&mut self, beneficiary: Address, amount: U256, kind: RewardKind) {
+        self.0.capture_reward(beneficiary, amount, kind);
+        self.1.capture_reward(beneficiary, amount, kind);
+    }
+    fn capture_account_read(&mut self, account: Address) {
+        self.0.capture_account_read(account);
+        self.1.capture_account_read(account);
+    }
+    fn capture_account_write(&mut self, account: Address) {
+        self.0.capture_account_write(account);
+        self.1.capture_account_write(account);
+    }
+    fn capture_storage_read(&mut self, address: Address, location: U256) {
+        self.0.capture_storage_read(address, location);
+        self.1.capture_storage_read(address, location);
+    }
+    fn capture_storage_write(&mut self, address: Address, location: U256) {
+        self.0.capture_storage_write(address, location);
+        self.1.capture_storage_write(address, location);
+    }
+}
+
+/// Tracer that can be turned off at runtime.
+impl<T: Tracer> Tracer for Option<T> {
+    fn trace_instructions(&self) -> bool {
+        self.as_ref().map_or(false, Tracer::trace_instructions)
+    }
+    fn capture_start(
+        &mut self,
+        depth: u16,
+        from: Address,
+        to: Address,
+        call_type: MessageKind,
+        input: Bytes,
+        gas: u64,
+        value: U256,
+    ) {
+        if let Some(tracer) = self {
+            tracer.capture_start(depth, from, to, call_type, input, gas, value);
+        }
+    }
+    fn capture_state(
+        &mut self,
+        env: &ExecutionState,
+        pc: u64,
+        op: OpCode,
+        cost: u64,
+        return_data: Bytes,
+        depth: u16,
+        err: StatusCode,
+    ) {
+        if let Some(tracer) = self {
+            tracer.capture_state(env, pc, op, cost, return_data, depth, err);
+        }
+    }
+    fn capture_end(&mut self, depth: u16, output: Bytes, gas_left: u64, err: StatusCode) {
+        if let Some(tracer) = self {
+            tracer.capture_end(depth, output, gas_left, err);
+        }
+    }
+    fn capture_self_destruct(&mut self, caller: Address, beneficiary: Address, balance: U256) {
+        if let Some(tracer) = self {
+            tracer.capture_self_destruct(caller, beneficiary, balance);
+        }
+    }
+    fn capture_reward(&mut self, beneficiary: Address, amount: U256, kind: RewardKind) {
+        if let Some(tracer) = self {
+            tracer.capture_reward(beneficiary, amount, kind);
+        }
+    }
+    fn capture_account_read(&mut self, account: Address) {
+        if let Some(tracer) = self {
+            tracer.capture_account_read(account);
+        }
+    }
+    fn capture_account_write(&mut self, account: Address) {
+        if let Some(tracer) = self {
+            tracer.capture_account_write(account);
+        }
+    }
+    fn capture_storage_read(&mut self, address: Address, location: U256) {
+        if let Some(tracer) = self {
+            tracer.capture_storage_read(address, location);
+        }
+    }
+    fn capture_storage_write(&mut self, address: Address, location: U256) {
+        if let Some(tracer) = self {
+            tracer.capture_storage_write(address, location);
+        }
+    }
 }
 
 #[derive(Clone, Copy, Debug, Default)]
@@ -82,10 +233,14 @@ impl Tracer for CallTracer {
         self.addresses.entry(to).or_default().to = true;
     }
 
-    fn capture_self_destruct(&mut self, caller: Address, beneficiary: Address) {
+    fn capture_self_destruct(&mut self, caller: Address, beneficiary: Address, _: U256) {
         self.addresses.entry(caller).or_default().from = true;
         self.addresses.entry(beneficiary).or_default().to = true;
     }
+
+    fn capture_reward(&mut self, beneficiary: Address, _: U256, _: RewardKind) {
+        self.addresses.entry(beneficiary).or_default().to = true;
+    }
 }
 
 impl CallTracer {
@@ -0,0 +1,171 @@
+use std::{
+    path::PathBuf,
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+use tracing::*;
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Warn once the data file takes this share of the maximum database size, in percent.
+const MAP_USAGE_WARNING: u64 = 90;
+
+static PAUSED: AtomicBool = AtomicBool::new(false);
+
+/// Whether writers are currently held back by [`DiskGuard::wait`].
+pub fn paused() -> bool {
+    PAUSED.load(Ordering::Relaxed)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DiskStatus {
+    Ok,
+    /// Free space on the database volume dropped below the threshold.
+    LowDiskSpace {
+        available: u64,
+        required: u64,
+    },
+    /// MDBX cannot grow the map by another step without exceeding the maximum database size.
+    MapFull {
+        size: u64,
+        max_size: u64,
+    },
+}
+
+/// Checks that the database can grow before writers commit.
+///
+/// MDBX grows the map by `growth_step` on its own, up to the maximum size set in its geometry.
+/// Running out of either the map or the disk fails a commit halfway through, so instead writers
+/// wait here until an operator makes room, or restarts the node with a larger maximum size.
+#[derive(Clone, Debug)]
+pub struct DiskGuard {
+    path: PathBuf,
+    min_free_space: u64,
+    max_size: u64,
+    growth_step: u64,
+}
+
+impl DiskGuard {
+    /// `path` is the database directory, sizes are in bytes.
+    pub fn new(
+        path: impl Into<PathBuf>,
+        min_free_space: u64,
+        max_size: u64,
+        growth_step: u64,
+    ) -> Self {
+        Self {
+            path: path.into(),
+            min_free_space,
+            max_size,
+            growth_step,
+        }
+    }
+
+    fn data_file_size(&self) -> u64 {
+        std::fs::metadata(self.path.join("mdbx.dat"))
+            .map(|m| m.len())
+            .unwrap_or(0)
+    }
+
+    pub fn check(&self) -> anyhow::Result<DiskStatus> {
+        let size = self.data_file_size();
+        if size.saturating_add(self.growth_step) > self.max_size {
+            return Ok(DiskStatus::MapFull {
+                size,
+                max_size: self.max_size,
+            });
+        }
+
+        let available = fs2::available_space(&self.path)?;
+        if available < self.min_free_space {
+            return Ok(DiskStatus::LowDiskSpace {
+                available,
+                required: self.min_free_space,
+            });
+        }
+
+        Ok(DiskStatus::Ok)
+    }
+
+    /// Whether the database can grow now. Reports the reason and marks sync paused if not.
+    pub fn has_room(&self) -> anyhow::Result<bool> {
+        match self.check()? {
+            DiskStatus::Ok => {
+                if PAUSED.swap(false, Ordering::Relaxed) {
+                    info!("Enough disk space, resuming");
+                }
+
+                let size = self.data_file_size();
+                if self.max_size > 0 && size * 100 / self.max_size >= MAP_USAGE_WARNING {
+                    warn!("Database takes {} of maximum {} bytes", size, self.max_size);
+                }
+
+                return Ok(true);
+            }
+            DiskStatus::LowDiskSpace {
+                available,
+                required,
+            } => {
+                error!(
+                    "Only {} bytes available on database volume, at least {} required - sync paused until space is freed",
+                    available, required
+                );
+            }
+            DiskStatus::MapFull { size, max_size } => {
+                error!(
+                    "Database of {} bytes cannot grow beyond maximum {} bytes - sync paused, restart with larger --db.max-size",
+                    size, max_size
+                );
+            }
+        }
+        PAUSED.store(true, Ordering::Relaxed);
+
+        Ok(false)
+    }
+
+    /// Return once there is room for the database to grow, checking periodically.
+    ///
+    /// Callers must not hold a transaction while waiting: a write transaction blocks every other
+    /// writer, and a read transaction keeps freed pages from being reused.
+    pub async fn wait(&self) -> anyhow::Result<()> {
+        while !self.has_room()? {
+            tokio::time::sleep(CHECK_INTERVAL).await;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_disk_status() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("mdbx.dat"), vec![0; 4096]).unwrap();
+
+        assert_eq!(
+            DiskGuard::new(dir.path(), 0, u64::MAX, 4096)
+                .check()
+                .unwrap(),
+            DiskStatus::Ok
+        );
+        assert!(matches!(
+            DiskGuard::new(dir.path(), u64::MAX, u64::MAX, 4096)
+                .check()
+                .unwrap(),
+            DiskStatus::LowDiskSpace {
+                required: u64::MAX,
+                ..
+            }
+        ));
+        assert_eq!(
+            DiskGuard::new(dir.path(), 0, 6000, 4096).check().unwrap(),
+            DiskStatus::MapFull {
+                size: 4096,
+                max_size: 6000
+            }
+        );
+    }
+}
@@ -1,8 +1,12 @@
-use crate::kv::{traits::*, *};
+use crate::kv::{
+    stats::{Operation, TableStats, WalkTracker},
+    traits::*,
+    *,
+};
 use ::mdbx::{DatabaseFlags, EnvironmentKind, TransactionKind, WriteFlags, RO, RW};
-use anyhow::Context;
+use anyhow::{format_err, Context};
 use async_trait::async_trait;
-use std::{collections::HashMap, ops::Deref, path::Path};
+use std::{borrow::Cow, collections::HashMap, ops::Deref, path::Path, sync::Arc};
 use tables::*;
 
 #[derive(Clone, Debug)]
@@ -22,6 +26,18 @@ where
     }
 }
 
+/// Value that is not needed, skipped without decoding.
+struct Skip;
+
+impl<'tx> ::mdbx::TableObject<'tx> for Skip {
+    fn decode(_: &[u8]) -> Result<Self, ::mdbx::Error>
+    where
+        Self: Sized,
+    {
+        Ok(Self)
+    }
+}
+
 #[derive(Debug)]
 pub struct Environment<E: EnvironmentKind> {
     inner: ::mdbx::Environment<E>,
@@ -159,6 +175,55 @@ where
 
         Ok(out)
     }
+
+    /// Copy every table of `chart` into `dst`, which should have no entries yet,
+    /// returning the number of entries copied per table.
+    ///
+    /// Entries are appended in key order, so pages of `dst` end up densely filled
+    /// and it has no free pages. `dst` is committed every `batch_size` entries.
+    pub fn copy_to<DE: EnvironmentKind>(
+        &self,
+        dst: &Environment<DE>,
+        chart: &DatabaseChart,
+        batch_size: usize,
+    ) -> anyhow::Result<HashMap<String, u64>> {
+        let mut out = HashMap::new();
+        let main_db = self.inner.open_db(None)?;
+        let mut tables = self.inner.cursor(&main_db)?;
+        while let Some((table, _)) = tables.next_nodup::<Vec<u8>, ()>()? {
+            let table = String::from_utf8(table)?;
+            let info = chart
+                .get(table.as_str())
+                .ok_or_else(|| format_err!("unknown table: {}", table))?;
+            let flags = if info.dup_sort {
+                WriteFlags::APPEND_DUP
+            } else {
+                WriteFlags::APPEND
+            };
+
+            let db = self
+                .inner
+                .open_db(Some(&table))
+                .with_context(|| format!("failed to open table: {}", table))?;
+            let mut cursor = self.inner.cursor(&db)?;
+            let mut entries = cursor.iter_start::<Cow<[u8]>, Cow<[u8]>>().peekable();
+            let mut copied = 0;
+            while entries.peek().is_some() {
+                let tx = dst.begin_rw_txn()?;
+                let dst_db = tx.open_db(Some(&table))?;
+                for entry in entries.by_ref().take(batch_size) {
+                    let (k, v) = entry?;
+                    tx.put(&dst_db, &k, &v, flags)?;
+                    copied += 1;
+                }
+                tx.commit()?;
+            }
+
+            out.insert(table, copied);
+        }
+
+        Ok(out)
+    }
 }
 
 #[async_trait]
@@ -184,7 +249,9 @@ where
             inner: self
                 .inner
                 .cursor(&self.inner.open_db(Some(table_name.as_ref()))?)?,
-            t: table.db_name(),
+            stats: table.stats(),
+            walk: WalkTracker::default(),
+            t: table_name,
         })
     }
 
@@ -201,12 +268,15 @@ where
         table: T,
         key: T::Key,
     ) -> anyhow::Result<Option<T::Value>> {
-        Ok(self
-            .inner
-            .get::<TableObjectWrapper<_>>(
-                &self.inner.open_db(Some(table.db_name().as_ref()))?,
-                key.encode().as_ref(),
-            )?
+        let table_name = table.db_name();
+        let db = self.inner.open_db(Some(table_name.as_ref()))?;
+        let key = key.encode();
+        Ok(table
+            .stats()
+            .record(table_name.as_ref(), Operation::Get, || {
+                self.inner.get::<TableObjectWrapper<_>>(&db, key.as_ref())
+            })
+            .0?
             .map(|v| v.0))
     }
 }
@@ -242,12 +312,15 @@ impl<'env, E: EnvironmentKind> MutableTransaction<'env> for MdbxTransaction<'env
     where
         T: Table,
     {
-        Ok(self.inner.put(
-            &self.inner.open_db(Some(table.db_name().as_ref()))?,
-            &k.encode(),
-            &v.encode(),
-            WriteFlags::UPSERT,
-        )?)
+        let table_name = table.db_name();
+        let db = self.inner.open_db(Some(table_name.as_ref()))?;
+        Ok(table
+            .stats()
+            .record(table_name.as_ref(), Operation::Put, || {
+                self.inner
+                    .put(&db, &k.encode(), &v.encode(), WriteFlags::UPSERT)
+            })
+            .0?)
     }
 
     async fn del<T>(&self, table: T, key: T::Key, value: Option<T::Value>) -> anyhow::Result<bool>
@@ -260,11 +333,14 @@ impl<'env, E: EnvironmentKind> MutableTransaction<'env> for MdbxTransaction<'env
         if let Some(v) = &value {
             vref = Some(v.as_ref());
         };
-        Ok(self.inner.del(
-            &self.inner.open_db(Some(table.db_name().as_ref()))?,
-            key.encode(),
-            vref,
-        )?)
+        let table_name = table.db_name();
+        let db = self.inner.open_db(Some(table_name.as_ref()))?;
+        Ok(table
+            .stats()
+            .record(table_name.as_ref(), Operation::Delete, || {
+                self.inner.del(&db, key.encode(), vref)
+            })
+            .0?)
     }
 
     async fn clear_table<T>(&self, table: T) -> anyhow::Result<()>
@@ -277,6 +353,32 @@ impl<'env, E: EnvironmentKind> MutableTransaction<'env> for MdbxTransaction<'env
         Ok(())
     }
 
+    async fn delete_range<T>(
+        &self,
+        table: T,
+        from: T::SeekKey,
+        to: Option<T::SeekKey>,
+    ) -> anyhow::Result<u64>
+    where
+        T: Table,
+    {
+        let to = to.map(TableEncode::encode);
+        self.delete_while(table, from.encode().as_ref(), |key| match &to {
+            Some(to) => key < to.as_ref(),
+            None => true,
+        })
+    }
+
+    async fn clear_prefix<T>(&self, table: T, prefix: T::SeekKey) -> anyhow::Result<u64>
+    where
+        T: Table,
+    {
+        let prefix = prefix.encode();
+        self.delete_while(table, prefix.as_ref(), |key| {
+            key.starts_with(prefix.as_ref())
+        })
+    }
+
     async fn commit(self) -> anyhow::Result<()> {
         self.inner.commit()?;
 
@@ -284,15 +386,94 @@ impl<'env, E: EnvironmentKind> MutableTransaction<'env> for MdbxTransaction<'env
     }
 }
 
+impl<'env, E: EnvironmentKind> MdbxTransaction<'env, RW, E> {
+    /// Delete keys starting from `from`, for as long as `pred` holds for them.
+    fn delete_while<T: Table>(
+        &self,
+        table: T,
+        from: &[u8],
+        pred: impl Fn(&[u8]) -> bool,
+    ) -> anyhow::Result<u64> {
+        let table_name = table.db_name();
+        let db = self.inner.open_db(Some(table_name.as_ref()))?;
+        let mut cursor = self.inner.cursor(&db)?;
+
+        table
+            .stats()
+            .record(table_name.as_ref(), Operation::Delete, || {
+                let mut deleted = 0;
+                let mut entry = cursor.set_range::<TableObjectWrapper<Vec<u8>>, Skip>(from)?;
+                while let Some((TableObjectWrapper(key), _)) = entry {
+                    if !pred(&key) {
+                        break;
+                    }
+
+                    cursor.del(WriteFlags::NO_DUP_DATA)?;
+                    deleted += 1;
+
+                    // Deleting moves the cursor onto the following key, which is then returned by next.
+                    entry = cursor.next_nodup::<TableObjectWrapper<Vec<u8>>, Skip>()?;
+                }
+
+                Ok(deleted)
+            })
+            .0
+    }
+}
+
 #[derive(Debug)]
 pub struct MdbxCursor<'txn, K>
 where
     K: TransactionKind,
 {
     inner: ::mdbx::Cursor<'txn, K>,
+    stats: Arc<TableStats>,
+    walk: WalkTracker,
     t: string::String<StaticBytes>,
 }
 
+impl<'txn, K> MdbxCursor<'txn, K>
+where
+    K: TransactionKind,
+{
+    /// Run an operation that positions the cursor, starting a new walk.
+    fn seek_op<R>(&mut self, f: impl FnOnce(&mut ::mdbx::Cursor<'txn, K>) -> R) -> R {
+        let (res, elapsed) = self
+            .stats
+            .record(self.t.as_ref(), Operation::Seek, || f(&mut self.inner));
+        self.walk.restart(self.t.as_ref(), elapsed);
+        res
+    }
+
+    /// Run an operation that moves the cursor relative to its current position.
+    fn step_op<R>(&mut self, f: impl FnOnce(&mut ::mdbx::Cursor<'txn, K>) -> R) -> R {
+        let (res, elapsed) = self
+            .stats
+            .record(self.t.as_ref(), Operation::Step, || f(&mut self.inner));
+        self.walk.record_step(elapsed);
+        res
+    }
+
+    fn write_op<R>(
+        &mut self,
+        op: Operation,
+        f: impl FnOnce(&mut ::mdbx::Cursor<'txn, K>) -> R,
+    ) -> R {
+        self.stats
+            .record(self.t.as_ref(), op, || f(&mut self.inner))
+            .0
+    }
+}
+
+impl<'txn, K> Drop for MdbxCursor<'txn, K>
+where
+    K: TransactionKind,
+{
+    fn drop(&mut self) {
+        self.walk.finish(self.t.as_ref());
+    }
+}
+
 fn map_res_inner<T, E>(
     v: Result<Option<(TableObjectWrapper<T::Key>, TableObjectWrapper<T::Value>)>, E>,
 ) -> anyhow::Result<Option<(T::Key, T::Value)>>
@@ -318,7 +499,7 @@ where
     where
         T::Key: TableDecode,
     {
-        Ok(map_res_inner::<T, _>(self.inner.first())?)
+        Ok(map_res_inner::<T, _>(self.seek_op(|c| c.first()))?)
     }
 
     async fn seek(&mut self, key: T::SeekKey) -> anyhow::Result<Option<(T::Key, T::Value)>>
@@ -326,7 +507,7 @@ where
         T::Key: TableDecode,
     {
         Ok(map_res_inner::<T, _>(
-            self.inner.set_range(key.encode().as_ref()),
+            self.seek_op(|c| c.set_range(key.encode().as_ref())),
         )?)
     }
 
@@ -335,7 +516,7 @@ where
         T::Key: TableDecode,
     {
         Ok(map_res_inner::<T, _>(
-            self.inner.set_key(key.encode().as_ref()),
+            self.seek_op(|c| c.set_key(key.encode().as_ref())),
         )?)
     }
 
@@ -343,28 +524,28 @@ where
     where
         T::Key: TableDecode,
     {
-        Ok(map_res_inner::<T, _>(self.inner.next())?)
+        Ok(map_res_inner::<T, _>(self.step_op(|c| c.next()))?)
     }
 
     async fn prev(&mut self) -> anyhow::Result<Option<(T::Key, T::Value)>>
     where
         T::Key: TableDecode,
     {
-        Ok(map_res_inner::<T, _>(self.inner.prev())?)
+        Ok(map_res_inner::<T, _>(self.step_op(|c| c.prev()))?)
     }
 
     async fn last(&mut self) -> anyhow::Result<Option<(T::Key, T::Value)>>
     where
         T::Key: TableDecode,
     {
-        Ok(map_res_inner::<T, _>(self.inner.last())?)
+        Ok(map_res_inner::<T, _>(self.seek_op(|c| c.last()))?)
     }
 
     async fn current(&mut self) -> anyhow::Result<Option<(T::Key, T::Value)>>
     where
         T::Key: TableDecode,
     {
-        Ok(map_res_inner::<T, _>(self.inner.get_current())?)
+        Ok(map_res_inner::<T, _>(self.step_op(|c| c.get_current()))?)
     }
 }
 
@@ -382,10 +563,12 @@ where
     where
         T::Key: Clone,
     {
-        let res = self.inner.get_both_range::<TableObjectWrapper<T::Value>>(
-            key.encode().as_ref(),
-            value.encode().as_ref(),
-        )?;
+        let res = self.seek_op(|c| {
+            c.get_both_range::<TableObjectWrapper<T::Value>>(
+                key.encode().as_ref(),
+                value.encode().as_ref(),
+            )
+        })?;
 
         if let Some(v) = res {
             return Ok(Some(v.0));
@@ -399,8 +582,7 @@ where
         T::Key: TableDecode,
     {
         Ok(self
-            .inner
-            .last_dup::<TableObjectWrapper<T::Value>>()?
+            .step_op(|c| c.last_dup::<TableObjectWrapper<T::Value>>())?
             .map(|v| v.0))
     }
 
@@ -408,21 +590,21 @@ where
     where
         T::Key: TableDecode,
     {
-        Ok(map_res_inner::<T, _>(self.inner.next_dup())?)
+        Ok(map_res_inner::<T, _>(self.step_op(|c| c.next_dup()))?)
     }
 
     async fn next_no_dup(&mut self) -> anyhow::Result<Option<(T::Key, T::Value)>>
     where
         T::Key: TableDecode,
     {
-        Ok(map_res_inner::<T, _>(self.inner.next_nodup())?)
+        Ok(map_res_inner::<T, _>(self.step_op(|c| c.next_nodup()))?)
     }
 
     async fn prev_dup(&mut self) -> anyhow::Result<Option<(T::Key, T::Value)>>
     where
         T::Key: TableDecode,
     {
-        Ok(map_res_inner::<T, _>(self.inner.prev_dup())?)
+        Ok(map_res_inner::<T, _>(self.step_op(|c| c.prev_dup()))?)
     }
 }
 
@@ -432,31 +614,37 @@ where
     T: Table,
 {
     async fn put(&mut self, key: T::Key, value: T::Value) -> anyhow::Result<()> {
-        Ok(self.inner.put(
-            key.encode().as_ref(),
-            value.encode().as_ref(),
-            WriteFlags::default(),
-        )?)
+        Ok(self.write_op(Operation::Put, |c| {
+            c.put(
+                key.encode().as_ref(),
+                value.encode().as_ref(),
+                WriteFlags::default(),
+            )
+        })?)
     }
 
     async fn upsert(&mut self, key: T::Key, value: T::Value) -> anyhow::Result<()> {
-        Ok(self.inner.put(
-            key.encode().as_ref(),
-            value.encode().as_ref(),
-            WriteFlags::UPSERT,
-        )?)
+        Ok(self.write_op(Operation::Put, |c| {
+            c.put(
+                key.encode().as_ref(),
+                value.encode().as_ref(),
+                WriteFlags::UPSERT,
+            )
+        })?)
     }
 
     async fn append(&mut self, key: T::Key, value: T::Value) -> anyhow::Result<()> {
-        Ok(self.inner.put(
-            key.encode().as_ref(),
-            value.encode().as_ref(),
-            WriteFlags::APPEND,
-        )?)
+        Ok(self.write_op(Operation::Append, |c| {
+            c.put(
+                key.encode().as_ref(),
+                value.encode().as_ref(),
+                WriteFlags::APPEND,
+            )
+        })?)
     }
 
     async fn delete_current(&mut self) -> anyhow::Result<()> {
-        self.inner.del(WriteFlags::CURRENT)?;
+        self.write_op(Operation::Delete, |c| c.del(WriteFlags::CURRENT))?;
 
         Ok(())
     }
@@ -468,13 +656,128 @@ where
     T: DupSort,
 {
     async fn delete_current_duplicates(&mut self) -> anyhow::Result<()> {
-        Ok(self.inner.del(WriteFlags::NO_DUP_DATA)?)
+        Ok(self.write_op(Operation::Delete, |c| c.del(WriteFlags::NO_DUP_DATA))?)
     }
     async fn append_dup(&mut self, key: T::Key, value: T::Value) -> anyhow::Result<()> {
-        Ok(self.inner.put(
-            key.encode().as_ref(),
-            value.encode().as_ref(),
-            WriteFlags::APPEND_DUP,
-        )?)
+        Ok(self.write_op(Operation::Append, |c| {
+            c.put(
+                key.encode().as_ref(),
+                value.encode().as_ref(),
+                WriteFlags::APPEND_DUP,
+            )
+        })?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        kv::{new_environment, new_mem_database},
+        models::*,
+    };
+    use byte_unit::n_mib_bytes;
+    use hex_literal::hex;
+
+    #[tokio::test]
+    async fn delete_ranges() {
+        let db = new_mem_database().unwrap();
+        let tx = db.begin_mutable().await.unwrap();
+
+        for block in 1..=10 {
+            tx.set(tables::TotalGas, BlockNumber(block), block)
+                .await
+                .unwrap();
+        }
+        assert_eq!(
+            tx.delete_range(tables::TotalGas, BlockNumber(4), Some(BlockNumber(7)))
+                .await
+                .unwrap(),
+            3
+        );
+        assert_eq!(
+            tx.delete_range(tables::TotalGas, BlockNumber(9), None)
+                .await
+                .unwrap(),
+            2
+        );
+        let mut remaining = vec![];
+        let mut cursor = tx.cursor(tables::TotalGas).await.unwrap();
+        let mut entry = cursor.first().await.unwrap();
+        while let Some((block, _)) = entry {
+            remaining.push(block.0);
+            entry = cursor.next().await.unwrap();
+        }
+        assert_eq!(remaining, vec![1, 2, 3, 7, 8]);
+
+        let a = Address::from(hex!("b000000000000000000000000000000000000001"));
+        let b = Address::from(hex!("b000000000000000000000000000000000000002"));
+        for address in [a, b] {
+            for slot in 0..3 {
+                tx.set(
+                    tables::Storage,
+                    address,
+                    (H256::from_low_u64_be(slot), U256::ONE),
+                )
+                .await
+                .unwrap();
+            }
+        }
+        assert_eq!(tx.clear_prefix(tables::Storage, a).await.unwrap(), 1);
+        assert!(tx.get(tables::Storage, a).await.unwrap().is_none());
+        assert_eq!(
+            tx.get(tables::Storage, b).await.unwrap(),
+            Some((H256::from_low_u64_be(0), U256::ONE))
+        );
+        assert_eq!(tx.clear_prefix(tables::Storage, a).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn copy_tables() {
+        let src_dir = tempfile::tempdir().unwrap();
+        let src = new_environment(src_dir.path(), n_mib_bytes!(64), None).unwrap();
+        let tx = src.begin_mutable().await.unwrap();
+        for block in 1..=10 {
+            tx.set(tables::TotalGas, BlockNumber(block), block)
+                .await
+                .unwrap();
+        }
+        let address = Address::repeat_byte(0xb0);
+        for slot in 0..3 {
+            tx.set(
+                tables::Storage,
+                address,
+                (H256::from_low_u64_be(slot), U256::ONE),
+            )
+            .await
+            .unwrap();
+        }
+        tx.commit().await.unwrap();
+
+        let dst_dir = tempfile::tempdir().unwrap();
+        let dst = new_environment(dst_dir.path(), n_mib_bytes!(64), None).unwrap();
+        let copied = src
+            .begin()
+            .await
+            .unwrap()
+            .copy_to(&dst, &CHAINDATA_TABLES, 4)
+            .unwrap();
+        assert_eq!(copied[tables::TotalGas::const_db_name()], 10);
+        assert_eq!(copied[tables::Storage::const_db_name()], 3);
+        assert_eq!(copied[tables::Header::const_db_name()], 0);
+
+        let tx = dst.begin().await.unwrap();
+        assert_eq!(
+            tx.get(tables::TotalGas, BlockNumber(7)).await.unwrap(),
+            Some(7)
+        );
+        let mut cursor = tx.cursor_dup_sort(tables::Storage).await.unwrap();
+        let mut slots = vec![];
+        let mut entry = cursor.seek_exact(address).await.unwrap();
+        while let Some((_, (slot, _))) = entry {
+            slots.push(slot.to_low_u64_be());
+            entry = cursor.next_dup().await.unwrap();
+        }
+        assert_eq!(slots, vec![0, 1, 2]);
     }
 }
@@ -1,8 +1,13 @@
+pub mod disk_guard;
 pub mod mdbx;
+pub mod prefetch;
+pub mod reader_pool;
 pub mod remote;
 pub mod server;
+pub mod stats;
 pub mod tables;
 pub mod traits;
+pub mod ttl;
 
 use self::traits::*;
 use crate::kv::tables::CHAINDATA_TABLES;
@@ -68,8 +73,21 @@ pub fn new_mem_database() -> anyhow::Result<impl traits::MutableKV> {
 }
 
 pub fn new_database(path: &std::path::Path) -> anyhow::Result<impl traits::MutableKV> {
+    new_database_with_geometry(path, n_tib_bytes!(4), n_gib_bytes!(4))
+}
+
+/// Open database that grows by `growth_step` bytes at a time, up to `max_size`.
+pub fn new_database_with_geometry(
+    path: &std::path::Path,
+    max_size: u128,
+    growth_step: u128,
+) -> anyhow::Result<impl traits::MutableKV> {
     Ok(MdbxWithDirHandle {
-        inner: new_environment(path, n_tib_bytes!(4), Some(n_gib_bytes!(4) as usize))?,
+        inner: new_environment(
+            path,
+            max_size,
+            Some(growth_step.try_into().unwrap_or(usize::MAX)),
+        )?,
         _tmpdir: None,
     })
 }
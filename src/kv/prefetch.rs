@@ -0,0 +1,161 @@
+use crate::kv::traits::*;
+use futures_core::Stream;
+use std::{
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+use tokio::{pin, sync::mpsc};
+use tokio_stream::StreamExt;
+
+/// Entries [`walk_prefetched`] reads ahead at a time, unless told otherwise.
+pub const DEFAULT_CHUNK_SIZE: usize = 1024;
+
+/// Entries of a table walked on the blocking thread pool, read ahead one chunk at a time.
+///
+/// Used as [`Stream`] from async code, and as [`Iterator`] from blocking code only,
+/// e.g. inside [`tokio::task::spawn_blocking`]. The walk stops once this is dropped.
+#[derive(Debug)]
+pub struct PrefetchedWalk<K, V> {
+    chunks: mpsc::Receiver<anyhow::Result<Vec<(K, V)>>>,
+    chunk: std::vec::IntoIter<(K, V)>,
+}
+
+// Neither field is pinned.
+impl<K, V> Unpin for PrefetchedWalk<K, V> {}
+
+impl<K, V> Stream for PrefetchedWalk<K, V> {
+    type Item = anyhow::Result<(K, V)>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            if let Some(entry) = this.chunk.next() {
+                return Poll::Ready(Some(Ok(entry)));
+            }
+
+            match this.chunks.poll_recv(cx) {
+                Poll::Ready(Some(Ok(chunk))) => this.chunk = chunk.into_iter(),
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Some(Err(e))),
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+impl<K, V> Iterator for PrefetchedWalk<K, V> {
+    type Item = anyhow::Result<(K, V)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(entry) = self.chunk.next() {
+                return Some(Ok(entry));
+            }
+
+            match self.chunks.blocking_recv()? {
+                Ok(chunk) => self.chunk = chunk.into_iter(),
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+/// Walk over `table` from `start_key` like [`walk`], but with cursor reads done on
+/// the blocking thread pool, so that walks over large tables do not stall the async runtime.
+///
+/// The next chunk of `chunk_size` entries is read while the current one is consumed.
+pub fn walk_prefetched<Tx, T>(
+    tx: Arc<Tx>,
+    table: T,
+    start_key: Option<T::SeekKey>,
+    chunk_size: usize,
+) -> PrefetchedWalk<T::Key, T::Value>
+where
+    Tx: Transaction<'static> + 'static,
+    T: Table,
+    T::Key: TableDecode + 'static,
+    T::Value: 'static,
+    T::SeekKey: 'static,
+{
+    let chunk_size = chunk_size.max(1);
+    let (sender, chunks) = mpsc::channel(1);
+    let handle = tokio::runtime::Handle::current();
+    tokio::task::spawn_blocking(move || {
+        handle.block_on(async {
+            let res = async {
+                let mut cursor = tx.cursor(table).await?;
+                let walker = walk(&mut cursor, start_key);
+                pin!(walker);
+
+                let mut chunk = Vec::with_capacity(chunk_size);
+                while let Some(entry) = walker.try_next().await? {
+                    chunk.push(entry);
+                    if chunk.len() == chunk_size {
+                        let chunk = std::mem::replace(&mut chunk, Vec::with_capacity(chunk_size));
+                        if sender.send(Ok(chunk)).await.is_err() {
+                            // Walk was dropped.
+                            return Ok(());
+                        }
+                    }
+                }
+                if !chunk.is_empty() {
+                    let _ = sender.send(Ok(chunk)).await;
+                }
+
+                Ok::<_, anyhow::Error>(())
+            }
+            .await;
+            if let Err(e) = res {
+                let _ = sender.send(Err(e)).await;
+            }
+        })
+    });
+
+    PrefetchedWalk {
+        chunks,
+        chunk: Vec::new().into_iter(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        kv::{new_mem_database, tables},
+        models::*,
+    };
+
+    #[tokio::test]
+    async fn prefetched_walk() {
+        let db: &'static _ = Box::leak(Box::new(new_mem_database().unwrap()));
+        let tx = db.begin_mutable().await.unwrap();
+        for block in 1..=10 {
+            tx.set(tables::TotalGas, BlockNumber(block), block * 100)
+                .await
+                .unwrap();
+        }
+        tx.commit().await.unwrap();
+
+        let tx = Arc::new(db.begin().await.unwrap());
+        let mut walker = walk_prefetched(tx.clone(), tables::TotalGas, Some(BlockNumber(3)), 3);
+        let mut walked = vec![];
+        while let Some((block, gas)) = walker.try_next().await.unwrap() {
+            walked.push((block.0, gas));
+        }
+        assert_eq!(
+            walked,
+            (3..=10)
+                .map(|block| (block, block * 100))
+                .collect::<Vec<_>>()
+        );
+
+        // Stops early, before the walk is finished.
+        let walked = tokio::task::spawn_blocking(move || {
+            Iterator::take(walk_prefetched(tx, tables::TotalGas, None, 4), 5)
+                .map(|entry| entry.map(|(block, _)| block.0))
+                .collect::<anyhow::Result<Vec<_>>>()
+        });
+        assert_eq!(walked.await.unwrap().unwrap(), vec![1, 2, 3, 4, 5]);
+    }
+}
@@ -0,0 +1,203 @@
+use crate::kv::traits::*;
+use parking_lot::Mutex;
+use std::{
+    fmt::Debug,
+    ops::Deref,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+#[derive(Debug)]
+struct Reader<Tx> {
+    tx: Tx,
+    generation: u64,
+    opened_at: Instant,
+}
+
+/// Read transactions kept open between readers, such as RPC requests.
+///
+/// A request checks a transaction out for its whole duration and returns it on drop of
+/// [`PooledReader`], so a transaction is never used by two requests at once. Like any transaction
+/// held across an await point it may move between threads while checked out, which MDBX allows
+/// for read transactions because the `mdbx` crate opens every environment with `MDBX_NOTLS`.
+///
+/// Up to `size` idle transactions are kept, so that requests don't open and close a reader
+/// slot each. Transactions are dropped instead of returned after [`Self::refresh`] (normally
+/// called on new head), and once they are older than `max_age`: a long-lived reader prevents
+/// MDBX from reusing pages freed after its snapshot and makes the database grow.
+#[derive(Debug)]
+pub struct ReaderPool<DB: KV> {
+    // Transactions borrow `db`, so this field has to be dropped first.
+    idle: Mutex<Vec<Reader<DB::Tx<'static>>>>,
+    size: usize,
+    generation: AtomicU64,
+    max_age: Duration,
+    db: Arc<DB>,
+}
+
+impl<DB: KV> ReaderPool<DB> {
+    pub fn new(db: Arc<DB>, size: usize, max_age: Duration) -> Self {
+        Self {
+            idle: Mutex::new(Vec::with_capacity(size)),
+            size,
+            generation: AtomicU64::new(0),
+            max_age,
+            db,
+        }
+    }
+
+    /// Check out a read transaction. It may lag behind the latest commit until the next refresh.
+    pub async fn get(self: &Arc<Self>) -> anyhow::Result<PooledReader<DB>> {
+        let generation = self.generation.load(Ordering::Acquire);
+
+        let mut reader = None;
+        {
+            let mut idle = self.idle.lock();
+            while let Some(candidate) = idle.pop() {
+                if self.is_fresh(&candidate, generation) {
+                    reader = Some(candidate);
+                    break;
+                }
+            }
+        }
+
+        let reader = match reader {
+            Some(reader) => reader,
+            None => {
+                // SAFETY: the environment is kept alive by `self.db` for as long as the pool
+                // exists, and the pool outlives every transaction: idle ones are dropped before
+                // `db`, and checked out ones hold a reference to the pool.
+                let db: &'static DB = unsafe { &*Arc::as_ptr(&self.db) };
+                Reader {
+                    tx: db.begin().await?,
+                    generation,
+                    opened_at: Instant::now(),
+                }
+            }
+        };
+
+        Ok(PooledReader {
+            reader: Some(reader),
+            pool: self.clone(),
+        })
+    }
+
+    /// Make subsequent [`Self::get`] calls open fresh transactions.
+    pub fn refresh(&self) {
+        self.generation.fetch_add(1, Ordering::AcqRel);
+        self.idle.lock().clear();
+    }
+
+    /// Number of idle transactions currently open.
+    pub fn open_readers(&self) -> usize {
+        self.idle.lock().len()
+    }
+
+    fn is_fresh(&self, reader: &Reader<DB::Tx<'static>>, generation: u64) -> bool {
+        reader.generation == generation && reader.opened_at.elapsed() < self.max_age
+    }
+
+    fn release(&self, reader: Reader<DB::Tx<'static>>) {
+        if self.is_fresh(&reader, self.generation.load(Ordering::Acquire)) {
+            let mut idle = self.idle.lock();
+            if idle.len() < self.size {
+                idle.push(reader);
+            }
+        }
+    }
+}
+
+/// Read transaction checked out of a [`ReaderPool`], returned to it on drop.
+#[derive(Debug)]
+pub struct PooledReader<DB: KV> {
+    reader: Option<Reader<DB::Tx<'static>>>,
+    pool: Arc<ReaderPool<DB>>,
+}
+
+impl<DB: KV> Deref for PooledReader<DB> {
+    type Target = DB::Tx<'static>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.reader.as_ref().unwrap().tx
+    }
+}
+
+impl<DB: KV> Drop for PooledReader<DB> {
+    fn drop(&mut self) {
+        if let Some(reader) = self.reader.take() {
+            self.pool.release(reader);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        kv::{new_mem_database, tables},
+        models::*,
+    };
+
+    #[tokio::test]
+    async fn reuses_transactions_until_refresh() {
+        let db = Arc::new(new_mem_database().unwrap());
+        let pool = Arc::new(ReaderPool::new(db.clone(), 2, Duration::from_secs(3600)));
+
+        let readers = vec![
+            pool.get().await.unwrap(),
+            pool.get().await.unwrap(),
+            pool.get().await.unwrap(),
+        ];
+        drop(readers);
+        // Only `size` transactions are kept.
+        assert_eq!(pool.open_readers(), 2);
+
+        let rwtx = db.begin_mutable().await.unwrap();
+        rwtx.set(tables::CanonicalHeader, BlockNumber(1), Default::default())
+            .await
+            .unwrap();
+        rwtx.commit().await.unwrap();
+
+        // Snapshot of a reused transaction is older than the commit.
+        let tx = pool.get().await.unwrap();
+        assert_eq!(
+            tx.get(tables::CanonicalHeader, BlockNumber(1))
+                .await
+                .unwrap(),
+            None
+        );
+        drop(tx);
+
+        pool.refresh();
+        assert_eq!(pool.open_readers(), 0);
+        let tx = pool.get().await.unwrap();
+        assert_eq!(
+            tx.get(tables::CanonicalHeader, BlockNumber(1))
+                .await
+                .unwrap(),
+            Some(Default::default())
+        );
+    }
+
+    #[tokio::test]
+    async fn checked_out_transactions_are_not_shared() {
+        let db = Arc::new(new_mem_database().unwrap());
+        let pool = Arc::new(ReaderPool::new(db, 1, Duration::from_secs(3600)));
+
+        let tx1 = pool.get().await.unwrap();
+        let tx2 = pool.get().await.unwrap();
+        assert!(!std::ptr::eq(&*tx1, &*tx2));
+    }
+
+    #[tokio::test]
+    async fn drops_old_transactions() {
+        let db = Arc::new(new_mem_database().unwrap());
+        let pool = Arc::new(ReaderPool::new(db, 1, Duration::ZERO));
+
+        drop(pool.get().await.unwrap());
+        assert_eq!(pool.open_readers(), 0);
+    }
+}
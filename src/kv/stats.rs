@@ -0,0 +1,244 @@
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use std::{
+    backtrace::Backtrace,
+    collections::{BTreeMap, HashMap},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use tracing::*;
+
+static REGISTRY: Lazy<RwLock<HashMap<String, Arc<TableStats>>>> = Lazy::new(Default::default);
+
+/// Zero means slow query logging is disabled.
+static SLOW_QUERY_THRESHOLD_NANOS: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Operation {
+    Get,
+    Seek,
+    Step,
+    Put,
+    /// Put past the last key of the table, which is much cheaper than [`Operation::Put`].
+    Append,
+    Delete,
+}
+
+/// Operation counters of a single table, shared by every transaction and cursor opened on it.
+#[derive(Debug, Default)]
+pub struct TableStats {
+    gets: AtomicU64,
+    seeks: AtomicU64,
+    steps: AtomicU64,
+    puts: AtomicU64,
+    appends: AtomicU64,
+    deletes: AtomicU64,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TableStatsSnapshot {
+    pub gets: u64,
+    pub seeks: u64,
+    pub steps: u64,
+    pub puts: u64,
+    pub appends: u64,
+    pub deletes: u64,
+}
+
+impl TableStatsSnapshot {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub fn saturating_sub(self, rhs: Self) -> Self {
+        Self {
+            gets: self.gets.saturating_sub(rhs.gets),
+            seeks: self.seeks.saturating_sub(rhs.seeks),
+            steps: self.steps.saturating_sub(rhs.steps),
+            puts: self.puts.saturating_sub(rhs.puts),
+            appends: self.appends.saturating_sub(rhs.appends),
+            deletes: self.deletes.saturating_sub(rhs.deletes),
+        }
+    }
+}
+
+impl TableStats {
+    fn counter(&self, op: Operation) -> &AtomicU64 {
+        match op {
+            Operation::Get => &self.gets,
+            Operation::Seek => &self.seeks,
+            Operation::Step => &self.steps,
+            Operation::Put => &self.puts,
+            Operation::Append => &self.appends,
+            Operation::Delete => &self.deletes,
+        }
+    }
+
+    pub fn snapshot(&self) -> TableStatsSnapshot {
+        TableStatsSnapshot {
+            gets: self.gets.load(Ordering::Relaxed),
+            seeks: self.seeks.load(Ordering::Relaxed),
+            steps: self.steps.load(Ordering::Relaxed),
+            puts: self.puts.load(Ordering::Relaxed),
+            appends: self.appends.load(Ordering::Relaxed),
+            deletes: self.deletes.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Count the operation and run it, reporting if it took longer than slow query threshold.
+    /// Returns time spent in the operation if slow query logging is enabled.
+    pub(crate) fn record<T>(
+        &self,
+        table: &str,
+        op: Operation,
+        f: impl FnOnce() -> T,
+    ) -> (T, Option<Duration>) {
+        self.counter(op).fetch_add(1, Ordering::Relaxed);
+
+        let threshold = match slow_query_threshold() {
+            Some(threshold) => threshold,
+            None => return (f(), None),
+        };
+
+        let started_at = Instant::now();
+        let res = f();
+        let elapsed = started_at.elapsed();
+        if elapsed >= threshold {
+            warn!(
+                table,
+                ?op,
+                backtrace = %Backtrace::force_capture(),
+                "Slow KV operation: {:?}",
+                elapsed
+            );
+        }
+
+        (res, Some(elapsed))
+    }
+}
+
+/// Get counters for the table, registering them on first access.
+///
+/// Takes a lock and hashes the name, so callers keep the handle instead of calling this per
+/// operation, see [`Table::stats`](crate::kv::traits::Table::stats).
+pub fn table_stats(table: &str) -> Arc<TableStats> {
+    if let Some(stats) = REGISTRY.read().get(table) {
+        return stats.clone();
+    }
+
+    REGISTRY
+        .write()
+        .entry(table.to_string())
+        .or_default()
+        .clone()
+}
+
+/// Current values of counters for all tables accessed so far.
+pub fn snapshot() -> BTreeMap<String, TableStatsSnapshot> {
+    REGISTRY
+        .read()
+        .iter()
+        .map(|(table, stats)| (table.clone(), stats.snapshot()))
+        .collect()
+}
+
+/// Counter increments between two snapshots, with idle tables omitted.
+pub fn diff(
+    before: &BTreeMap<String, TableStatsSnapshot>,
+    after: &BTreeMap<String, TableStatsSnapshot>,
+) -> BTreeMap<String, TableStatsSnapshot> {
+    after
+        .iter()
+        .filter_map(|(table, &stats)| {
+            let d = stats.saturating_sub(before.get(table).copied().unwrap_or_default());
+            (!d.is_empty()).then(|| (table.clone(), d))
+        })
+        .collect()
+}
+
+pub fn set_slow_query_threshold(threshold: Option<Duration>) {
+    SLOW_QUERY_THRESHOLD_NANOS.store(
+        threshold
+            .map(|t| u64::try_from(t.as_nanos()).unwrap_or(u64::MAX).max(1))
+            .unwrap_or(0),
+        Ordering::Relaxed,
+    );
+}
+
+pub fn slow_query_threshold() -> Option<Duration> {
+    match SLOW_QUERY_THRESHOLD_NANOS.load(Ordering::Relaxed) {
+        0 => None,
+        nanos => Some(Duration::from_nanos(nanos)),
+    }
+}
+
+/// Tracks a cursor walk: a positioning operation followed by steps over adjacent entries.
+/// Time is accumulated only while inside the cursor, so that slow consumers are not reported.
+#[derive(Debug, Default)]
+pub(crate) struct WalkTracker {
+    steps: u64,
+    busy: Duration,
+}
+
+impl WalkTracker {
+    pub(crate) fn record_step(&mut self, elapsed: Option<Duration>) {
+        self.steps += 1;
+        if let Some(elapsed) = elapsed {
+            self.busy += elapsed;
+        }
+    }
+
+    /// Finish current walk, report it if slow, and start a new one.
+    pub(crate) fn restart(&mut self, table: &str, elapsed: Option<Duration>) {
+        self.finish(table);
+        self.busy = elapsed.unwrap_or_default();
+    }
+
+    pub(crate) fn finish(&mut self, table: &str) {
+        if let Some(threshold) = slow_query_threshold() {
+            if self.steps > 0 && self.busy >= threshold {
+                // Walks end on the next seek or on drop of the cursor, both of which happen in
+                // the code that did the walk, so its stack is the one captured here.
+                warn!(
+                    table,
+                    steps = self.steps,
+                    backtrace = %Backtrace::force_capture(),
+                    "Slow cursor walk: {:?}",
+                    self.busy
+                );
+            }
+        }
+
+        *self = Self::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diff_omits_idle_tables() {
+        let before = snapshot();
+        let stats = table_stats("StatsTestTable");
+        stats.record("StatsTestTable", Operation::Get, || ());
+        stats.record("StatsTestTable", Operation::Put, || ());
+        stats.record("StatsTestTable", Operation::Put, || ());
+        stats.record("StatsTestTable", Operation::Append, || ());
+
+        let d = diff(&before, &snapshot());
+        assert_eq!(
+            d["StatsTestTable"],
+            TableStatsSnapshot {
+                gets: 1,
+                puts: 2,
+                appends: 1,
+                ..Default::default()
+            }
+        );
+        assert!(d.values().all(|s| !s.is_empty()));
+    }
+}
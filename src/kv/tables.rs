@@ -1,8 +1,8 @@
 use super::*;
+pub use crate::VariableVec;
 use crate::{models::*, zeroless_view, StageId};
 use anyhow::{bail, format_err};
 use arrayref::array_ref;
-use arrayvec::ArrayVec;
 use bytes::Bytes;
 use croaring::{treemap::NativeSerializer, Treemap as RoaringTreemap};
 use derive_more::*;
@@ -28,6 +28,10 @@ where
     fn db_name(&self) -> string::String<StaticBytes> {
         self.0.db_name()
     }
+
+    fn stats(&self) -> Arc<crate::kv::stats::TableStats> {
+        self.0.stats()
+    }
 }
 
 impl<T> ErasedTable<T>
@@ -76,6 +80,15 @@ macro_rules! decl_table {
                     ))
                 }
             }
+
+            fn stats(&self) -> std::sync::Arc<$crate::kv::stats::TableStats> {
+                static STATS: once_cell::sync::Lazy<std::sync::Arc<$crate::kv::stats::TableStats>> =
+                    once_cell::sync::Lazy::new(|| {
+                        $crate::kv::stats::table_stats($name::const_db_name())
+                    });
+
+                STATS.clone()
+            }
         }
 
         impl $name {
@@ -132,25 +145,6 @@ impl traits::TableDecode for Bytes {
     }
 }
 
-#[derive(Clone, Debug, Default, Deref, DerefMut, PartialEq, Eq, PartialOrd, Ord)]
-pub struct VariableVec<const LEN: usize> {
-    pub inner: ArrayVec<u8, LEN>,
-}
-
-impl<const LEN: usize> FromIterator<u8> for VariableVec<LEN> {
-    fn from_iter<T: IntoIterator<Item = u8>>(iter: T) -> Self {
-        Self {
-            inner: ArrayVec::from_iter(iter),
-        }
-    }
-}
-
-impl<const LEN: usize> AsRef<[u8]> for VariableVec<LEN> {
-    fn as_ref(&self) -> &[u8] {
-        self.inner.as_ref()
-    }
-}
-
 impl<const LEN: usize> traits::TableEncode for VariableVec<LEN> {
     type Encoded = Self;
 
@@ -167,12 +161,6 @@ impl<const LEN: usize> traits::TableDecode for VariableVec<LEN> {
     }
 }
 
-impl<const LEN: usize> From<VariableVec<LEN>> for Vec<u8> {
-    fn from(v: VariableVec<LEN>) -> Self {
-        v.to_vec()
-    }
-}
-
 #[derive(Clone, Debug)]
 pub struct InvalidLength<const EXPECTED: usize> {
     pub got: usize,
@@ -308,6 +296,7 @@ scale_table_object!(BodyForStorage);
 scale_table_object!(BlockHeader);
 scale_table_object!(MessageWithSignature);
 scale_table_object!(Vec<crate::models::Log>);
+scale_table_object!(crate::downloader::snap_progress::SnapSyncProgress);
 
 macro_rules! ron_table_object {
     ($ty:ident) => {
@@ -329,6 +318,20 @@ macro_rules! ron_table_object {
 
 ron_table_object!(ChainSpec);
 
+impl TableEncode for BlobSidecar {
+    type Encoded = Vec<u8>;
+
+    fn encode(self) -> Self::Encoded {
+        rlp::encode(&self).to_vec()
+    }
+}
+
+impl TableDecode for BlobSidecar {
+    fn decode(b: &[u8]) -> anyhow::Result<Self> {
+        Ok(rlp::decode(b)?)
+    }
+}
+
 impl TableEncode for Address {
     type Encoded = [u8; ADDRESS_LENGTH];
 
@@ -491,6 +494,32 @@ impl TableDecode for BitmapKey<Address> {
     }
 }
 
+impl TableEncode for BitmapKey<H256> {
+    type Encoded = [u8; KECCAK_LENGTH + BLOCK_NUMBER_LENGTH];
+
+    fn encode(self) -> Self::Encoded {
+        let mut out = [0; KECCAK_LENGTH + BLOCK_NUMBER_LENGTH];
+        out[..KECCAK_LENGTH].copy_from_slice(&self.inner.encode());
+        out[KECCAK_LENGTH..].copy_from_slice(&self.block_number.encode());
+        out
+    }
+}
+
+impl TableDecode for BitmapKey<H256> {
+    fn decode(b: &[u8]) -> anyhow::Result<Self> {
+        if b.len() != KECCAK_LENGTH + BLOCK_NUMBER_LENGTH {
+            return Err(
+                InvalidLength::<{ KECCAK_LENGTH + BLOCK_NUMBER_LENGTH }> { got: b.len() }.into(),
+            );
+        }
+
+        Ok(Self {
+            inner: H256::decode(&b[..KECCAK_LENGTH])?,
+            block_number: BlockNumber::decode(&b[KECCAK_LENGTH..])?,
+        })
+    }
+}
+
 impl TableEncode for BitmapKey<(Address, H256)> {
     type Encoded = [u8; ADDRESS_LENGTH + KECCAK_LENGTH + BLOCK_NUMBER_LENGTH];
 
@@ -753,6 +782,7 @@ decl_table!(HashedStorage => H256 => (H256, U256));
 decl_table!(AccountHistory => BitmapKey<Address> => RoaringTreemap);
 decl_table!(StorageHistory => BitmapKey<(Address, H256)> => RoaringTreemap);
 decl_table!(Code => H256 => Bytes);
+decl_table!(Preimage => H256 => Bytes);
 decl_table!(TrieAccount => Vec<u8> => Vec<u8>);
 decl_table!(TrieStorage => Vec<u8> => Vec<u8>);
 decl_table!(DbInfo => Vec<u8> => Vec<u8>);
@@ -767,12 +797,14 @@ decl_table!(BlockTransaction => TxIndex => MessageWithSignature);
 decl_table!(TotalGas => BlockNumber => u64);
 decl_table!(TotalTx => BlockNumber => u64);
 decl_table!(Log => (BlockNumber, TxIndex) => Vec<crate::models::Log>);
-decl_table!(LogTopicIndex => Vec<u8> => RoaringTreemap);
-decl_table!(LogAddressIndex => Vec<u8> => RoaringTreemap);
+decl_table!(LogTopicIndex => BitmapKey<H256> => RoaringTreemap);
+decl_table!(LogAddressIndex => BitmapKey<Address> => RoaringTreemap);
 decl_table!(CallTraceSet => BlockNumber => CallTraceSetEntry);
 decl_table!(CallFromIndex => BitmapKey<Address> => RoaringTreemap);
 decl_table!(CallToIndex => BitmapKey<Address> => RoaringTreemap);
 decl_table!(BlockTransactionLookup => H256 => TruncateStart<BlockNumber>);
+decl_table!(BlockTransactionOffset => H256 => u64);
+decl_table!(ContractGas => (BlockNumber, Address) => u64);
 decl_table!(Config => H256 => ChainSpec);
 decl_table!(SyncStage => StageId => BlockNumber);
 decl_table!(TxSender => HeaderKey => Vec<Address>);
@@ -781,6 +813,8 @@ decl_table!(Migration => Vec<u8> => Vec<u8>);
 decl_table!(Sequence => Vec<u8> => Vec<u8>);
 decl_table!(LastHeader => VariableVec<0> => H256);
 decl_table!(Issuance => Vec<u8> => Vec<u8>);
+decl_table!(SnapProgress => VariableVec<0> => crate::downloader::snap_progress::SnapSyncProgress);
+decl_table!(BlobSidecarCache => H256 => super::ttl::Expiring<BlobSidecar>);
 
 pub type DatabaseChart = Arc<HashMap<&'static str, TableInfo>>;
 
@@ -803,6 +837,7 @@ pub static CHAINDATA_TABLES: Lazy<Arc<HashMap<&'static str, TableInfo>>> = Lazy:
         AccountHistory::const_db_name() => TableInfo::default(),
         StorageHistory::const_db_name() => TableInfo::default(),
         Code::const_db_name() => TableInfo::default(),
+        Preimage::const_db_name() => TableInfo::default(),
         TrieAccount::const_db_name() => TableInfo::default(),
         TrieStorage::const_db_name() => TableInfo::default(),
         DbInfo::const_db_name() => TableInfo::default(),
@@ -825,6 +860,8 @@ pub static CHAINDATA_TABLES: Lazy<Arc<HashMap<&'static str, TableInfo>>> = Lazy:
         CallFromIndex::const_db_name() => TableInfo::default(),
         CallToIndex::const_db_name() => TableInfo::default(),
         BlockTransactionLookup::const_db_name() => TableInfo::default(),
+        BlockTransactionOffset::const_db_name() => TableInfo::default(),
+        ContractGas::const_db_name() => TableInfo::default(),
         Config::const_db_name() => TableInfo::default(),
         SyncStage::const_db_name() => TableInfo::default(),
         TxSender::const_db_name() => TableInfo::default(),
@@ -833,6 +870,8 @@ pub static CHAINDATA_TABLES: Lazy<Arc<HashMap<&'static str, TableInfo>>> = Lazy:
         Sequence::const_db_name() => TableInfo::default(),
         LastHeader::const_db_name() => TableInfo::default(),
         Issuance::const_db_name() => TableInfo::default(),
+        SnapProgress::const_db_name() => TableInfo::default(),
+        BlobSidecarCache::const_db_name() => TableInfo::default(),
     })
 });
 
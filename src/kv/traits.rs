@@ -1,8 +1,11 @@
-use super::*;
+use super::{
+    stats::{self, TableStats},
+    *,
+};
 use async_stream::try_stream;
 use async_trait::async_trait;
 use futures_core::Stream;
-use std::fmt::Debug;
+use std::{fmt::Debug, sync::Arc};
 
 #[async_trait]
 pub trait KV: Debug + Send + Sync + 'static {
@@ -38,6 +41,12 @@ pub trait Table: Send + Sync + Debug + 'static {
     type SeekKey: TableEncode;
 
     fn db_name(&self) -> string::String<StaticBytes>;
+
+    /// Operation counters of the table. Tables with a name known at compile time keep the handle
+    /// in a static, others look it up by name.
+    fn stats(&self) -> Arc<TableStats> {
+        stats::table_stats(self.db_name().as_ref())
+    }
 }
 pub trait DupSort: Table {
     type SeekBothKey: TableObject;
@@ -95,6 +104,22 @@ pub trait MutableTransaction<'db>: Transaction<'db> {
 
     async fn clear_table<T: Table>(&self, table: T) -> anyhow::Result<()>;
 
+    /// Delete all entries with encoded keys in `from..to`, or from `from` to the end of the table
+    /// if `to` is `None`, including all duplicates of dupsort keys. Values are not decoded.
+    ///
+    /// Returns number of deleted keys.
+    async fn delete_range<T: Table>(
+        &self,
+        table: T,
+        from: T::SeekKey,
+        to: Option<T::SeekKey>,
+    ) -> anyhow::Result<u64>;
+
+    /// Delete all entries with encoded keys starting with encoded `prefix`. Values are not decoded.
+    ///
+    /// Returns number of deleted keys.
+    async fn clear_prefix<T: Table>(&self, table: T, prefix: T::SeekKey) -> anyhow::Result<u64>;
+
     async fn commit(self) -> anyhow::Result<()>;
 }
 
@@ -0,0 +1,239 @@
+//! Tables of ephemeral data that expires on its own, such as blob sidecars.
+//!
+//! Values of such tables are stored as [`Expiring`], prefixed with their expiry time, so that
+//! [`Sweeper`] can delete expired entries of any table listed in [`TTL_TABLES`] without knowing
+//! its types. Entries are put with [`put`], which sets expiry from [`TtlTable::TTL`], and read
+//! with [`get`], which ignores entries that have expired but were not swept yet.
+
+use super::{tables, traits::*, CustomTable};
+use anyhow::bail;
+use arrayref::array_ref;
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tracing::*;
+
+/// Tables swept by [`Sweeper`].
+pub const TTL_TABLES: &[&str] = &[tables::BlobSidecarCache::const_db_name()];
+
+const SWEEP_INTERVAL: Duration = Duration::from_secs(600);
+
+/// Table whose entries expire after `TTL`. It must also be listed in [`TTL_TABLES`].
+pub trait TtlTable: Table {
+    const TTL: Duration;
+}
+
+/// Value of a [`TtlTable`], with its expiry time as UNIX timestamp in seconds.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Expiring<V> {
+    pub expires_at: u64,
+    pub value: V,
+}
+
+impl<V: TableEncode> TableEncode for Expiring<V> {
+    type Encoded = Vec<u8>;
+
+    fn encode(self) -> Self::Encoded {
+        let mut out = self.expires_at.to_be_bytes().to_vec();
+        out.extend_from_slice(self.value.encode().as_ref());
+        out
+    }
+}
+
+impl<V: TableDecode> TableDecode for Expiring<V> {
+    fn decode(b: &[u8]) -> anyhow::Result<Self> {
+        let expires_at = expires_at(b)?;
+        Ok(Self {
+            expires_at,
+            value: V::decode(&b[8..])?,
+        })
+    }
+}
+
+fn expires_at(b: &[u8]) -> anyhow::Result<u64> {
+    if b.len() < 8 {
+        bail!("expiring value too short: {} bytes", b.len());
+    }
+
+    Ok(u64::from_be_bytes(*array_ref!(b, 0, 8)))
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+impl TtlTable for tables::BlobSidecarCache {
+    /// Blob sidecars are served to peers for 4096 epochs.
+    const TTL: Duration = Duration::from_secs(4096 * 32 * 12);
+}
+
+/// Put `value` that expires after [`TtlTable::TTL`] from now.
+pub async fn put<'db, Tx, T, V>(tx: &Tx, table: T, key: T::Key, value: V) -> anyhow::Result<()>
+where
+    Tx: MutableTransaction<'db>,
+    T: TtlTable<Value = Expiring<V>>,
+{
+    tx.set(
+        table,
+        key,
+        Expiring {
+            expires_at: unix_now() + T::TTL.as_secs(),
+            value,
+        },
+    )
+    .await
+}
+
+/// Value of `key` unless it has expired.
+pub async fn get<'db, Tx, T, V>(tx: &Tx, table: T, key: T::Key) -> anyhow::Result<Option<V>>
+where
+    Tx: Transaction<'db>,
+    T: TtlTable<Value = Expiring<V>>,
+{
+    Ok(tx
+        .get(table, key)
+        .await?
+        .filter(|entry| entry.expires_at > unix_now())
+        .map(|entry| entry.value))
+}
+
+/// Delete entries of table `name` that expired by `now`. Returns number of deleted entries.
+pub async fn sweep<'db, Tx>(tx: &Tx, name: &str, now: u64) -> anyhow::Result<usize>
+where
+    Tx: MutableTransaction<'db>,
+{
+    let table = || CustomTable::from(name.to_string());
+
+    let mut expired = vec![];
+    let mut cursor = tx.cursor(table()).await?;
+    let mut entry = cursor.first().await?;
+    while let Some((key, value)) = entry {
+        if expires_at(&value)? <= now {
+            expired.push(key);
+        }
+        entry = cursor.next().await?;
+    }
+
+    let deleted = expired.len();
+    for key in expired {
+        tx.del(table(), key, None).await?;
+    }
+
+    Ok(deleted)
+}
+
+/// Periodically deletes expired entries of [`TTL_TABLES`].
+#[derive(Debug)]
+pub struct Sweeper<DB> {
+    db: Arc<DB>,
+    interval: Duration,
+}
+
+impl<DB> Sweeper<DB>
+where
+    DB: MutableKV,
+{
+    pub fn new(db: Arc<DB>) -> Self {
+        Self {
+            db,
+            interval: SWEEP_INTERVAL,
+        }
+    }
+
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Sweep all tables once. Returns number of deleted entries.
+    pub async fn sweep(&self) -> anyhow::Result<usize> {
+        let tx = self.db.begin_mutable().await?;
+        let now = unix_now();
+        let mut deleted = 0;
+        for name in TTL_TABLES {
+            let n = sweep(&tx, name, now).await?;
+            if n > 0 {
+                debug!("Swept {} expired entries of {}", n, name);
+            }
+            deleted += n;
+        }
+        tx.commit().await?;
+
+        Ok(deleted)
+    }
+
+    pub async fn run(self) {
+        loop {
+            if let Err(e) = self.sweep().await {
+                warn!("Failed to sweep expired entries: {:#}", e);
+            }
+
+            tokio::time::sleep(self.interval).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{kv::new_mem_database, models::*};
+    use bytes::Bytes;
+
+    #[tokio::test]
+    async fn sweeps_expired_entries() {
+        let db = new_mem_database().unwrap();
+        let tx = db.begin_mutable().await.unwrap();
+
+        let sidecar = BlobSidecar {
+            blobs: vec![Bytes::from_static(&[1; 4])],
+            commitments: vec![Bytes::from_static(&[2; 48])],
+            proofs: vec![Bytes::from_static(&[3; 48])],
+        };
+        let fresh = H256::from_low_u64_be(1);
+        let stale = H256::from_low_u64_be(2);
+
+        put(&tx, tables::BlobSidecarCache, fresh, sidecar.clone())
+            .await
+            .unwrap();
+        tx.set(
+            tables::BlobSidecarCache,
+            stale,
+            Expiring {
+                expires_at: 1,
+                value: sidecar.clone(),
+            },
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            get(&tx, tables::BlobSidecarCache, fresh).await.unwrap(),
+            Some(sidecar.clone())
+        );
+        assert_eq!(
+            get(&tx, tables::BlobSidecarCache, stale).await.unwrap(),
+            None
+        );
+
+        assert_eq!(
+            sweep(&tx, tables::BlobSidecarCache::const_db_name(), unix_now())
+                .await
+                .unwrap(),
+            1
+        );
+        assert!(tx
+            .get(tables::BlobSidecarCache, stale)
+            .await
+            .unwrap()
+            .is_none());
+        assert!(tx
+            .get(tables::BlobSidecarCache, fresh)
+            .await
+            .unwrap()
+            .is_some());
+    }
+}
@@ -1,4 +1,5 @@
 #![feature(
+    backtrace,
     bool_to_option,
     destructuring_assignment,
     entry_insert,
@@ -20,26 +21,54 @@
     clippy::unused_io_amount
 )]
 
+#[cfg(feature = "node")]
 pub mod accessors;
+#[cfg(feature = "node")]
+pub mod analytics;
+#[cfg(feature = "node")]
 #[doc(hidden)]
 pub mod binutil;
+#[cfg(feature = "node")]
 mod bitmapdb;
 pub mod chain;
 pub mod consensus;
 pub mod crypto;
+#[cfg(feature = "node")]
+pub mod diffsync;
+#[cfg(feature = "node")]
 pub mod downloader;
+#[cfg(feature = "node")]
+pub mod engine_api;
+#[cfg(feature = "node")]
 pub mod etl;
 pub mod execution;
+#[cfg(feature = "node")]
 pub mod kv;
+#[cfg(feature = "node")]
+pub mod metrics;
 pub mod models;
+#[cfg(feature = "node")]
+pub mod reload;
 pub mod res;
+#[cfg(feature = "node")]
+pub mod rpc;
+#[cfg(feature = "node")]
+pub mod rpc_compat;
+#[cfg(feature = "node")]
 pub mod sentry;
+#[cfg(feature = "node")]
+pub mod snapshot;
+#[cfg(feature = "node")]
 pub mod stagedsync;
+#[cfg(feature = "node")]
 pub mod stages;
 mod state;
+pub mod t8n;
 pub mod trie;
+pub mod txpool;
 pub(crate) mod util;
 
+#[cfg(feature = "node")]
 pub use stagedsync::stages::StageId;
 pub use state::*;
 pub use util::*;
@@ -0,0 +1,78 @@
+//! Node metrics in Prometheus text format.
+
+use crate::kv::{disk_guard, stats};
+use hyper::{
+    header::CONTENT_TYPE,
+    service::{make_service_fn, service_fn},
+    Body, Request, Response,
+};
+use std::{convert::Infallible, fmt::Write, net::SocketAddr};
+use tracing::*;
+
+/// Current values of all metrics.
+pub fn render() -> String {
+    let mut out = String::new();
+
+    out.push_str(
+        "# HELP akula_sync_paused Whether sync waits for room for the database to grow.\n",
+    );
+    out.push_str("# TYPE akula_sync_paused gauge\n");
+    writeln!(out, "akula_sync_paused {}", disk_guard::paused() as u8).unwrap();
+
+    out.push_str("# HELP akula_kv_operations_total KV operations per table.\n");
+    out.push_str("# TYPE akula_kv_operations_total counter\n");
+    for (table, s) in stats::snapshot() {
+        for (op, value) in [
+            ("get", s.gets),
+            ("seek", s.seeks),
+            ("step", s.steps),
+            ("put", s.puts),
+            ("append", s.appends),
+            ("delete", s.deletes),
+        ] {
+            writeln!(
+                out,
+                "akula_kv_operations_total{{table=\"{}\",op=\"{}\"}} {}",
+                table, op, value
+            )
+            .unwrap();
+        }
+    }
+
+    out
+}
+
+async fn handle(_: Request<Body>) -> Result<Response<Body>, Infallible> {
+    let mut res = Response::new(Body::from(render()));
+    res.headers_mut()
+        .insert(CONTENT_TYPE, "text/plain; version=0.0.4".parse().unwrap());
+    Ok(res)
+}
+
+/// Serve metrics on `addr` to any path.
+pub async fn serve(addr: SocketAddr) -> anyhow::Result<()> {
+    let make_service = make_service_fn(|_| async { Ok::<_, Infallible>(service_fn(handle)) });
+
+    let server = hyper::Server::try_bind(&addr)?.serve(make_service);
+    info!("Metrics listening on {}", server.local_addr());
+    server.await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_table_counters() {
+        let stats = stats::table_stats("MetricsTestTable");
+        stats.record("MetricsTestTable", stats::Operation::Seek, || ());
+
+        let out = render();
+        assert!(out.contains("akula_sync_paused 0\n"));
+        assert!(
+            out.contains("akula_kv_operations_total{table=\"MetricsTestTable\",op=\"seek\"} 1\n")
+        );
+    }
+}
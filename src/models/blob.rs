@@ -0,0 +1,224 @@
+use super::{transaction::typed_payload, *};
+use crate::{chain::protocol_param::param, crypto::TrieEncode};
+use bytes::{BufMut, Bytes, BytesMut};
+use rlp::{Decodable, DecoderError, Encodable, Rlp, RlpStream};
+use sha2::{Digest, Sha256};
+
+pub const FIELD_ELEMENTS_PER_BLOB: usize = 4096;
+pub const BYTES_PER_BLOB: usize = FIELD_ELEMENTS_PER_BLOB * 32;
+/// Length of KZG commitments and proofs, compressed G1 points.
+pub const BYTES_PER_KZG_POINT: usize = 48;
+
+/// Blob hash that blob transactions carry instead of `commitment`.
+///
+/// https://eips.ethereum.org/EIPS/eip-4844
+pub fn kzg_to_versioned_hash(commitment: &[u8]) -> H256 {
+    let mut hash = H256::from_slice(&Sha256::digest(commitment));
+    hash.0[0] = param::VERSIONED_HASH_VERSION_KZG;
+    hash
+}
+
+/// Approximates `factor * e ** (numerator / denominator)` using Taylor expansion.
+fn fake_exponential(factor: U256, numerator: U256, denominator: U256) -> U256 {
+    let mut i = U256::ONE;
+    let mut output = U256::ZERO;
+    let mut accum = factor * denominator;
+    while accum > U256::ZERO {
+        output += accum;
+        accum = accum * numerator / (denominator * i);
+        i += U256::ONE;
+    }
+    output / denominator
+}
+
+/// Price of blob gas in block with `excess_blob_gas`.
+pub fn blob_base_fee(excess_blob_gas: u64) -> U256 {
+    fake_exponential(
+        param::MIN_BLOB_BASE_FEE.into(),
+        excess_blob_gas.into(),
+        param::BLOB_BASE_FEE_UPDATE_FRACTION.into(),
+    )
+}
+
+/// Blobs of a transaction with their KZG commitments and proofs. They are not part of blocks,
+/// only of transactions gossiped to and submitted by peers.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BlobSidecar {
+    pub blobs: Vec<Bytes>,
+    pub commitments: Vec<Bytes>,
+    pub proofs: Vec<Bytes>,
+}
+
+impl BlobSidecar {
+    /// Versioned hashes of commitments, as expected in the transaction.
+    pub fn versioned_hashes(&self) -> Vec<H256> {
+        self.commitments
+            .iter()
+            .map(|commitment| kzg_to_versioned_hash(commitment))
+            .collect()
+    }
+}
+
+impl Encodable for BlobSidecar {
+    fn rlp_append(&self, s: &mut RlpStream) {
+        s.begin_list(3);
+        append_bytes_list(s, &self.blobs);
+        append_bytes_list(s, &self.commitments);
+        append_bytes_list(s, &self.proofs);
+    }
+}
+
+impl Decodable for BlobSidecar {
+    fn decode(rlp: &Rlp) -> Result<Self, DecoderError> {
+        if rlp.item_count()? != 3 {
+            return Err(DecoderError::RlpIncorrectListLen);
+        }
+
+        Ok(Self {
+            blobs: decode_bytes_list(&rlp.at(0)?)?,
+            commitments: decode_bytes_list(&rlp.at(1)?)?,
+            proofs: decode_bytes_list(&rlp.at(2)?)?,
+        })
+    }
+}
+
+/// Blob transaction in the network form, `0x03 || rlp([tx_payload, blobs, commitments, proofs])`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BlobTransaction {
+    pub transaction: MessageWithSignature,
+    pub sidecar: BlobSidecar,
+}
+
+impl BlobTransaction {
+    pub fn network_encode(&self) -> Bytes {
+        let mut b = BytesMut::with_capacity(1);
+        b.put_u8(TxType::EIP4844 as u8);
+        let mut s = RlpStream::new_list_with_buffer(b, 4);
+        // Transaction payload without its type byte.
+        s.append_raw(&self.transaction.trie_encode()[1..], 1);
+        append_bytes_list(&mut s, &self.sidecar.blobs);
+        append_bytes_list(&mut s, &self.sidecar.commitments);
+        append_bytes_list(&mut s, &self.sidecar.proofs);
+        s.out().freeze()
+    }
+
+    pub fn network_decode(slice: &[u8]) -> Result<Self, DecoderError> {
+        let first = *slice.get(0).ok_or(DecoderError::RlpIsTooShort)?;
+        if first != TxType::EIP4844 as u8 {
+            return Err(DecoderError::Custom("not a blob transaction"));
+        }
+
+        let rlp = typed_payload(&slice[1..])?;
+        if rlp.item_count()? != 4 {
+            return Err(DecoderError::RlpIncorrectListLen);
+        }
+
+        let mut payload = vec![TxType::EIP4844 as u8];
+        payload.extend_from_slice(rlp.at(0)?.as_raw());
+
+        Ok(Self {
+            transaction: MessageWithSignature::trie_decode(&payload)?,
+            sidecar: BlobSidecar {
+                blobs: decode_bytes_list(&rlp.at(1)?)?,
+                commitments: decode_bytes_list(&rlp.at(2)?)?,
+                proofs: decode_bytes_list(&rlp.at(3)?)?,
+            },
+        })
+    }
+}
+
+fn append_bytes_list(s: &mut RlpStream, items: &[Bytes]) {
+    s.begin_list(items.len());
+    for item in items {
+        s.append(&item.as_ref());
+    }
+}
+
+fn decode_bytes_list(rlp: &Rlp) -> Result<Vec<Bytes>, DecoderError> {
+    rlp.iter()
+        .map(|item| Ok(item.data()?.to_vec().into()))
+        .collect()
+}
+
+impl Encodable for BlobTransaction {
+    fn rlp_append(&self, s: &mut RlpStream) {
+        s.append(&self.network_encode().as_ref());
+    }
+}
+
+impl Decodable for BlobTransaction {
+    fn decode(rlp: &Rlp) -> Result<Self, DecoderError> {
+        Self::network_decode(rlp.data()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hex_literal::hex;
+
+    #[test]
+    fn blob_fee() {
+        assert_eq!(blob_base_fee(0), U256::ONE);
+        assert_eq!(blob_base_fee(param::TARGET_BLOB_GAS_PER_BLOCK), U256::ONE);
+        assert!(blob_base_fee(10_000_000) > U256::ONE);
+        assert!(blob_base_fee(20_000_000) > blob_base_fee(10_000_000));
+    }
+
+    #[test]
+    fn blob_transaction_encoding() {
+        let commitment = Bytes::from(vec![0xc0; BYTES_PER_KZG_POINT]);
+        let versioned_hash = kzg_to_versioned_hash(&commitment);
+        assert_eq!(versioned_hash.0[0], param::VERSIONED_HASH_VERSION_KZG);
+        assert_eq!(
+            versioned_hash.0[1..],
+            Sha256::digest(&commitment).as_slice()[1..]
+        );
+
+        let tx = BlobTransaction {
+            transaction: MessageWithSignature {
+                message: Message::EIP4844 {
+                    chain_id: ChainId(1),
+                    nonce: 0,
+                    max_priority_fee_per_gas: U256::ONE,
+                    max_fee_per_gas: U256::from(GIGA),
+                    gas_limit: 21_000,
+                    action: TransactionAction::Call(
+                        hex!("811a752c8cd697e3cb27279c330ed1ada745a8d7").into(),
+                    ),
+                    value: U256::ZERO,
+                    input: Bytes::new(),
+                    access_list: vec![],
+                    max_fee_per_blob_gas: U256::ONE,
+                    blob_versioned_hashes: vec![versioned_hash],
+                },
+                signature: MessageSignature::new(
+                    false,
+                    hex!("36b241b061a36a32ab7fe86c7aa9eb592dd59018cd0443adc0903590c16b02b0"),
+                    hex!("5edcc541b4741c5cc6dd347c5ed9577ef293a62787b4510465fadbfe39ee4094"),
+                )
+                .unwrap(),
+            },
+            sidecar: BlobSidecar {
+                blobs: vec![Bytes::from(vec![0; BYTES_PER_BLOB])],
+                commitments: vec![commitment],
+                proofs: vec![Bytes::from(vec![0xc0; BYTES_PER_KZG_POINT])],
+            },
+        };
+        assert_eq!(
+            tx.sidecar.versioned_hashes(),
+            tx.transaction.blob_versioned_hashes()
+        );
+
+        let encoded = tx.network_encode();
+        assert_eq!(encoded[0], 3);
+        assert_eq!(BlobTransaction::network_decode(&encoded).unwrap(), tx);
+        assert_eq!(
+            rlp::decode::<BlobTransaction>(&rlp::encode(&tx)).unwrap(),
+            tx
+        );
+
+        // Transaction without sidecar is not in the network form.
+        assert!(BlobTransaction::network_decode(&tx.transaction.trie_encode()).is_err());
+    }
+}
@@ -2,15 +2,46 @@ use super::*;
 use crate::crypto::*;
 use derive_more::Deref;
 use parity_scale_codec::*;
-use rlp_derive::*;
+use rlp::*;
 use sha3::*;
 use std::borrow::Borrow;
 
-#[derive(Clone, Debug, PartialEq, Eq, RlpEncodable, RlpDecodable)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Block {
     pub header: BlockHeader,
     pub transactions: Vec<MessageWithSignature>,
     pub ommers: Vec<BlockHeader>,
+    /// Present since Shanghai.
+    pub withdrawals: Option<Vec<Withdrawal>>,
+}
+
+impl Encodable for Block {
+    fn rlp_append(&self, s: &mut RlpStream) {
+        s.begin_list(3 + usize::from(self.withdrawals.is_some()));
+        s.append(&self.header);
+        s.append_list(&self.transactions);
+        s.append_list(&self.ommers);
+        if let Some(withdrawals) = &self.withdrawals {
+            s.append_list(withdrawals);
+        }
+    }
+}
+
+impl Decodable for Block {
+    fn decode(rlp: &Rlp) -> Result<Self, DecoderError> {
+        let withdrawals = match rlp.item_count()? {
+            3 => None,
+            4 => Some(rlp.list_at(3)?),
+            _ => return Err(DecoderError::RlpIncorrectListLen),
+        };
+
+        Ok(Self {
+            header: rlp.val_at(0)?,
+            transactions: rlp.list_at(1)?,
+            ommers: rlp.list_at(2)?,
+            withdrawals,
+        })
+    }
 }
 
 impl Block {
@@ -27,9 +58,18 @@ impl Block {
             header: BlockHeader::new(partial_header, ommers_hash, transactions_root),
             transactions,
             ommers,
+            withdrawals: None,
         }
     }
 
+    /// Attach Shanghai withdrawals, setting the withdrawals root of the header.
+    #[must_use]
+    pub fn with_withdrawals(mut self, withdrawals: Vec<Withdrawal>) -> Self {
+        self.header.withdrawals_root = Some(Self::withdrawals_root(&withdrawals));
+        self.withdrawals = Some(withdrawals);
+        self
+    }
+
     pub fn ommers_hash(ommers: &[BlockHeader]) -> H256 {
         H256::from_slice(Keccak256::digest(&rlp::encode_list(ommers)[..]).as_slice())
     }
@@ -39,6 +79,10 @@ impl Block {
     ) -> H256 {
         ordered_trie_root(iter.into_iter().map(|r| r.borrow().trie_encode()))
     }
+
+    pub fn withdrawals_root(withdrawals: &[Withdrawal]) -> H256 {
+        ordered_trie_root(withdrawals.iter().map(rlp::encode))
+    }
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -46,6 +90,7 @@ pub struct BlockWithSenders {
     pub header: PartialHeader,
     pub transactions: Vec<MessageWithSender>,
     pub ommers: Vec<BlockHeader>,
+    pub withdrawals: Option<Vec<Withdrawal>>,
 }
 
 impl From<Block> for BlockWithSenders {
@@ -67,14 +112,44 @@ impl From<Block> for BlockWithSenders {
             header: block.header.into(),
             transactions,
             ommers: block.ommers,
+            withdrawals: block.withdrawals,
         }
     }
 }
 
-#[derive(Clone, Debug, PartialEq, RlpEncodable, RlpDecodable)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct BlockBody {
     pub transactions: Vec<MessageWithSignature>,
     pub ommers: Vec<BlockHeader>,
+    /// Present since Shanghai.
+    pub withdrawals: Option<Vec<Withdrawal>>,
+}
+
+impl Encodable for BlockBody {
+    fn rlp_append(&self, s: &mut RlpStream) {
+        s.begin_list(2 + usize::from(self.withdrawals.is_some()));
+        s.append_list(&self.transactions);
+        s.append_list(&self.ommers);
+        if let Some(withdrawals) = &self.withdrawals {
+            s.append_list(withdrawals);
+        }
+    }
+}
+
+impl Decodable for BlockBody {
+    fn decode(rlp: &Rlp) -> Result<Self, DecoderError> {
+        let withdrawals = match rlp.item_count()? {
+            2 => None,
+            3 => Some(rlp.list_at(2)?),
+            _ => return Err(DecoderError::RlpIncorrectListLen),
+        };
+
+        Ok(Self {
+            transactions: rlp.list_at(0)?,
+            ommers: rlp.list_at(1)?,
+            withdrawals,
+        })
+    }
 }
 
 impl From<Block> for BlockBody {
@@ -82,6 +157,7 @@ impl From<Block> for BlockBody {
         Self {
             transactions: block.transactions,
             ommers: block.ommers,
+            withdrawals: block.withdrawals,
         }
     }
 }
@@ -90,13 +166,44 @@ impl From<Block> for BlockBody {
 pub struct BlockBodyWithSenders {
     pub transactions: Vec<MessageWithSender>,
     pub ommers: Vec<BlockHeader>,
+    pub withdrawals: Option<Vec<Withdrawal>>,
 }
 
-#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Encode, Decode, RlpDecodable)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Encode, Decode)]
 pub struct BodyForStorage {
     pub base_tx_id: TxIndex,
     pub tx_amount: u64,
     pub uncles: Vec<BlockHeader>,
+    pub withdrawals: Option<Vec<Withdrawal>>,
+}
+
+impl Encodable for BodyForStorage {
+    fn rlp_append(&self, s: &mut RlpStream) {
+        s.begin_list(3 + usize::from(self.withdrawals.is_some()));
+        s.append(&self.base_tx_id);
+        s.append(&self.tx_amount);
+        s.append_list(&self.uncles);
+        if let Some(withdrawals) = &self.withdrawals {
+            s.append_list(withdrawals);
+        }
+    }
+}
+
+impl Decodable for BodyForStorage {
+    fn decode(rlp: &Rlp) -> Result<Self, DecoderError> {
+        let withdrawals = match rlp.item_count()? {
+            3 => None,
+            4 => Some(rlp.list_at(3)?),
+            _ => return Err(DecoderError::RlpIncorrectListLen),
+        };
+
+        Ok(Self {
+            base_tx_id: rlp.val_at(0)?,
+            tx_amount: rlp.val_at(1)?,
+            uncles: rlp.list_at(2)?,
+            withdrawals,
+        })
+    }
 }
 
 #[derive(Clone, Debug, Deref, Default)]
@@ -134,6 +241,9 @@ mod tests {
             mix_hash: hex!("b26583e11ffc5d412b46d1ddb74e78c775fb54b049dc0cf0689e8430a45d9186").into(),
             nonce: hex!("596b98b5d0f8cc56").into(),
             base_fee_per_gas: Some(0x18aac2ec3d_u64.into()),
+            withdrawals_root: None,
+            blob_gas_used: None,
+            excess_blob_gas: None,
         };
 
         let ommers = vec![];
@@ -324,6 +434,9 @@ mod tests {
                     .into(),
                 nonce: hex!("68b769c5451a7aea").into(),
                 base_fee_per_gas: None,
+                withdrawals_root: None,
+                blob_gas_used: None,
+                excess_blob_gas: None,
             }]
         );
 
@@ -399,7 +512,11 @@ mod tests {
                     .into(),
                 nonce: hex!("0000000000000023").into(),
                 base_fee_per_gas: None,
+                withdrawals_root: None,
+                blob_gas_used: None,
+                excess_blob_gas: None,
             }],
+            withdrawals: None,
         };
 
         assert_eq!(rlp::decode::<BlockBody>(&rlp::encode(&body)).unwrap(), body);
@@ -465,4 +582,86 @@ mod tests {
 
         assert_eq!(rlp::decode::<BlockHeader>(&rlp::encode(&h)).unwrap(), h);
     }
+
+    #[test]
+    fn shanghai_block_rlp() {
+        let block = Block::new(
+            PartialHeader {
+                number: 17_034_870.into(),
+                base_fee_per_gas: Some(U256::from(GIGA)),
+                ..PartialHeader::empty()
+            },
+            vec![],
+            vec![],
+        )
+        .with_withdrawals(vec![Withdrawal {
+            index: 0,
+            validator_index: 1,
+            address: Address::from_low_u64_be(0x10f0),
+            amount: 32,
+        }]);
+
+        assert_eq!(
+            block.header.withdrawals_root,
+            Some(Block::withdrawals_root(block.withdrawals.as_ref().unwrap()))
+        );
+        assert_ne!(block.header.withdrawals_root, Some(EMPTY_ROOT));
+        assert_eq!(
+            rlp::decode::<BlockHeader>(&rlp::encode(&block.header)).unwrap(),
+            block.header
+        );
+        assert_eq!(rlp::decode::<Block>(&rlp::encode(&block)).unwrap(), block);
+
+        let body = BlockBody::from(block);
+        assert_eq!(rlp::decode::<BlockBody>(&rlp::encode(&body)).unwrap(), body);
+    }
+
+    #[test]
+    fn malformed_header() {
+        let header = BlockHeader::new(
+            PartialHeader {
+                number: 0x1234.into(),
+                base_fee_per_gas: Some(U256::from(GIGA)),
+                ..PartialHeader::empty()
+            },
+            EMPTY_LIST_HASH,
+            EMPTY_ROOT,
+        );
+        let encoded = rlp::encode(&header);
+        let fields = Rlp::new(&encoded)
+            .iter()
+            .map(|field| field.as_raw().to_vec())
+            .collect::<Vec<_>>();
+        let reencode = |fields: &[Vec<u8>]| {
+            let mut s = RlpStream::new_list(fields.len());
+            for field in fields {
+                s.append_raw(field, 1);
+            }
+            s.out()
+        };
+
+        assert_eq!(
+            rlp::decode::<BlockHeader>(&reencode(&fields)).unwrap(),
+            header
+        );
+        assert_eq!(
+            rlp::decode::<BlockHeader>(&reencode(&fields[..14])),
+            Err(DecoderError::RlpIncorrectListLen)
+        );
+
+        let mut trailing = fields.clone();
+        trailing.extend(std::iter::repeat(vec![0x80]).take(4));
+        assert_eq!(
+            rlp::decode::<BlockHeader>(&reencode(&trailing)),
+            Err(DecoderError::RlpIncorrectListLen)
+        );
+
+        // Block number with leading zero byte.
+        let mut non_canonical = fields;
+        non_canonical[8] = vec![0x83, 0x00, 0x12, 0x34];
+        assert_eq!(
+            rlp::decode::<BlockHeader>(&reencode(&non_canonical)),
+            Err(DecoderError::RlpInvalidIndirection)
+        );
+    }
 }
@@ -1,4 +1,4 @@
-use crate::{models::*, util::*};
+use crate::{chain::protocol_param::param, models::*, util::*};
 use bytes::Bytes;
 use evmodin::Revision;
 use serde::*;
@@ -9,6 +9,17 @@ use std::{
 
 type NodeUrl = String;
 
+/// Setting that changes at given blocks, each entry replacing the previous one from its block on.
+pub type BlockSchedule<T> = BTreeMap<BlockNumber, T>;
+
+/// Entry of `schedule` in effect at `block_number`.
+fn scheduled_at<T>(schedule: &BlockSchedule<T>, block_number: BlockNumber) -> Option<&T> {
+    schedule
+        .range(..=block_number)
+        .next_back()
+        .map(|(_, value)| value)
+}
+
 #[derive(Debug, PartialEq)]
 pub struct BlockExecutionSpec {
     pub revision: Revision,
@@ -16,6 +27,9 @@ pub struct BlockExecutionSpec {
     pub params: Params,
     pub system_contract_changes: HashMap<Address, Contract>,
     pub balance_changes: HashMap<Address, U256>,
+    pub system_calls: Vec<SystemCall>,
+    pub precompile_pricing: PrecompilePricing,
+    pub reward_redirections: Vec<RewardRedirection>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
@@ -30,6 +44,15 @@ pub struct ChainSpec {
     pub contracts: BTreeMap<BlockNumber, HashMap<Address, Contract>>,
     #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
     pub balances: BTreeMap<BlockNumber, HashMap<Address, U256>>,
+    /// Calls made at the start of every block.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub system_calls: BlockSchedule<Vec<SystemCall>>,
+    /// Custom precompile gas prices, used instead of the ones of the current fork.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub precompile_pricing: BlockSchedule<PrecompilePricing>,
+    /// Shares of block rewards paid to other addresses than their recipients.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub reward_redirections: BlockSchedule<Vec<RewardRedirection>>,
     pub p2p: P2PParams,
 }
 
@@ -39,6 +62,7 @@ impl ChainSpec {
         let mut revision = Revision::Frontier;
         let mut active_transitions = HashSet::new();
         for (fork, r) in [
+            (self.upgrades.shanghai, Revision::Shanghai),
             (self.upgrades.london, Revision::London),
             (self.upgrades.berlin, Revision::Berlin),
             (self.upgrades.istanbul, Revision::Istanbul),
@@ -84,6 +108,15 @@ impl ChainSpec {
                 .get(&block_number)
                 .cloned()
                 .unwrap_or_default(),
+            system_calls: scheduled_at(&self.system_calls, block_number)
+                .cloned()
+                .unwrap_or_default(),
+            precompile_pricing: scheduled_at(&self.precompile_pricing, block_number)
+                .copied()
+                .unwrap_or_else(|| PrecompilePricing::for_revision(revision)),
+            reward_redirections: scheduled_at(&self.reward_redirections, block_number)
+                .cloned()
+                .unwrap_or_default(),
         }
     }
 
@@ -98,14 +131,22 @@ impl ChainSpec {
             self.upgrades.istanbul,
             self.upgrades.berlin,
             self.upgrades.london,
+            self.upgrades.shanghai,
         ]
         .iter()
         .copied()
         .flatten()
         .chain(self.consensus.eip1559_block)
+        .chain(self.consensus.eip4844_block)
         .chain(self.consensus.seal_verification.gather_forks())
         .chain(self.contracts.keys().copied())
         .chain(self.balances.keys().copied())
+        // Like contract and balance changes, these change execution from their block on, so nodes
+        // that disagree on them must not peer. None of the built-in networks schedule any, so
+        // their fork ids are unaffected.
+        .chain(self.system_calls.keys().copied())
+        .chain(self.precompile_pricing.keys().copied())
+        .chain(self.reward_redirections.keys().copied())
         .collect::<BTreeSet<BlockNumber>>();
 
         forks.remove(&BlockNumber(0));
@@ -144,6 +185,19 @@ pub struct ConsensusParams {
         with = "::serde_with::rust::unwrap_or_skip"
     )]
     pub eip1559_block: Option<BlockNumber>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "::serde_with::rust::unwrap_or_skip"
+    )]
+    pub eip4844_block: Option<BlockNumber>,
+    /// Total difficulty at which the chain switches to proof-of-stake, see EIP-3675.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "::serde_with::rust::unwrap_or_skip"
+    )]
+    pub terminal_total_difficulty: Option<U256>,
 }
 
 pub fn switch_is_active(switch: Option<BlockNumber>, block_number: BlockNumber) -> bool {
@@ -266,6 +320,12 @@ pub struct Upgrades {
         with = "::serde_with::rust::unwrap_or_skip"
     )]
     pub london: Option<BlockNumber>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "::serde_with::rust::unwrap_or_skip"
+    )]
+    pub shanghai: Option<BlockNumber>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
@@ -275,6 +335,98 @@ pub struct Params {
     pub min_gas_limit: u64,
 }
 
+/// How `MODEXP` complexity is derived from the lengths of its operands.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum ModExpComplexity {
+    Eip198,
+    Eip2565,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ModExpPricing {
+    pub complexity: ModExpComplexity,
+    pub quad_divisor: u64,
+    pub min_gas: u64,
+}
+
+/// Gas prices of precompiled contracts.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct PrecompilePricing {
+    pub ecrecover: u64,
+    pub sha256_base: u64,
+    pub sha256_word: u64,
+    pub ripemd160_base: u64,
+    pub ripemd160_word: u64,
+    pub identity_base: u64,
+    pub identity_word: u64,
+    pub modexp: ModExpPricing,
+    pub bn_add: u64,
+    pub bn_mul: u64,
+    pub bn_pairing_base: u64,
+    pub bn_pairing_point: u64,
+    pub blake2f_round: u64,
+}
+
+const FRONTIER_PRECOMPILE_PRICING: PrecompilePricing = PrecompilePricing {
+    ecrecover: 3_000,
+    sha256_base: 60,
+    sha256_word: 12,
+    ripemd160_base: 600,
+    ripemd160_word: 120,
+    identity_base: 15,
+    identity_word: 3,
+    modexp: ModExpPricing {
+        complexity: ModExpComplexity::Eip198,
+        quad_divisor: param::G_QUAD_DIVISOR_BYZANTIUM,
+        min_gas: 0,
+    },
+    bn_add: 500,
+    bn_mul: 40_000,
+    bn_pairing_base: 100_000,
+    bn_pairing_point: 80_000,
+    blake2f_round: 1,
+};
+
+// EIP-1108
+const ISTANBUL_PRECOMPILE_PRICING: PrecompilePricing = PrecompilePricing {
+    bn_add: 150,
+    bn_mul: 6_000,
+    bn_pairing_base: 45_000,
+    bn_pairing_point: 34_000,
+    ..FRONTIER_PRECOMPILE_PRICING
+};
+
+// EIP-2565
+const BERLIN_PRECOMPILE_PRICING: PrecompilePricing = PrecompilePricing {
+    modexp: ModExpPricing {
+        complexity: ModExpComplexity::Eip2565,
+        quad_divisor: param::G_QUAD_DIVISOR_BERLIN,
+        min_gas: 200,
+    },
+    ..ISTANBUL_PRECOMPILE_PRICING
+};
+
+/// Precompile prices by the fork that introduced them.
+pub const PRECOMPILE_PRICING: &[(Revision, PrecompilePricing)] = &[
+    (Revision::Frontier, FRONTIER_PRECOMPILE_PRICING),
+    (Revision::Istanbul, ISTANBUL_PRECOMPILE_PRICING),
+    (Revision::Berlin, BERLIN_PRECOMPILE_PRICING),
+];
+
+impl PrecompilePricing {
+    /// Prices of the last repricing activated at `revision`.
+    pub fn for_revision(revision: Revision) -> Self {
+        PRECOMPILE_PRICING
+            .iter()
+            .rev()
+            .find(|(r, _)| *r <= revision)
+            .map(|(_, pricing)| *pricing)
+            .unwrap_or(FRONTIER_PRECOMPILE_PRICING)
+    }
+}
+
 #[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
 pub enum BlockScore {
     NoTurn = 1,
@@ -355,6 +507,39 @@ pub enum Contract {
     Precompile(Precompile),
 }
 
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub enum SystemCallInput {
+    Data(#[serde(with = "hexbytes")] Bytes),
+    /// Hash of the parent block, as in EIP-2935.
+    ParentHash,
+}
+
+/// Call from [`SYSTEM_ADDRESS`](crate::chain::protocol_param::param::SYSTEM_ADDRESS),
+/// not paid for and not counted against block gas limit.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct SystemCall {
+    pub address: Address,
+    pub input: SystemCallInput,
+}
+
+/// Share of rewards paid to `address` instead of their recipient, e.g. a treasury.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct RewardRedirection {
+    pub address: Address,
+    /// Share of each reward in basis points.
+    pub share_bps: u64,
+    /// Redirect ommer rewards too, not only rewards of the block author.
+    #[serde(default)]
+    pub ommers: bool,
+}
+
+impl RewardRedirection {
+    /// Part of `amount` redirected to [`Self::address`].
+    pub fn share_of(&self, amount: U256) -> U256 {
+        amount * U256::from(self.share_bps) / 10_000
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub enum ModExpVersion {
     ModExp198,
@@ -395,7 +580,7 @@ impl<'de> de::Visitor<'de> for DeserializePeriodAsDuration {
     where
         E: de::Error,
     {
-        Ok(Duration::from_millis(v))
+        Ok(Duration::from_secs(v))
     }
 }
 
@@ -427,10 +612,12 @@ mod tests {
                 name: "Rinkeby".into(),
                 consensus: ConsensusParams {
                     seal_verification: SealVerificationParams::Clique {
-                        period: Duration::from_millis(15),
+                        period: Duration::from_secs(15),
                         epoch: 30_000,
                     },
                     eip1559_block: Some(8897988.into()),
+                    eip4844_block: None,
+                    terminal_total_difficulty: None,
                 },
                 upgrades: Upgrades {
                     homestead: Some(1.into()),
@@ -442,6 +629,7 @@ mod tests {
                     istanbul: Some(5435345.into()),
                     berlin: Some(8290928.into()),
                     london: Some(8897988.into()),
+                    shanghai: None,
                 },
                 params: Params {
                     chain_id: ChainId(4),
@@ -477,6 +665,9 @@ mod tests {
                         )),
                     )].into_iter()).collect::<HashMap<Address, U256>>(),
                 },
+                system_calls: Default::default(),
+                precompile_pricing: Default::default(),
+                reward_redirections: Default::default(),
                 p2p: P2PParams {
                     bootnodes: vec![
                         "enode://a24ac7c5484ef4ed0c5eb2d36620ba4e4aa13b8c84684e1b4aab0cebea2ae45cb4d375b77eab56516d34bfbd3c1a833fc51296ff084b770b94fb9028c4d25ccf@52.169.42.101:30303",
@@ -490,6 +681,43 @@ mod tests {
         );
     }
 
+    #[test]
+    fn precompile_pricing() {
+        let mut spec = MAINNET.clone();
+        assert_eq!(
+            spec.collect_block_spec(9_068_999).precompile_pricing.bn_add,
+            500
+        );
+        assert_eq!(
+            spec.collect_block_spec(9_069_000).precompile_pricing.bn_add,
+            150
+        );
+        assert_eq!(
+            spec.collect_block_spec(12_244_000)
+                .precompile_pricing
+                .modexp
+                .min_gas,
+            200
+        );
+
+        spec.precompile_pricing.insert(
+            BlockNumber(13_000_000),
+            PrecompilePricing {
+                ecrecover: 1_000,
+                ..PrecompilePricing::for_revision(Revision::London)
+            },
+        );
+        let pricing = spec.collect_block_spec(13_000_000).precompile_pricing;
+        assert_eq!(pricing.ecrecover, 1_000);
+        assert_eq!(pricing.bn_add, 150);
+        assert_eq!(
+            spec.collect_block_spec(12_999_999)
+                .precompile_pricing
+                .ecrecover,
+            3_000
+        );
+    }
+
     #[test]
     fn distinct_block_numbers() {
         assert_eq!(
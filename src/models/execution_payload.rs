@@ -0,0 +1,226 @@
+use super::*;
+use crate::{crypto::TrieEncode, util::*};
+use anyhow::ensure;
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+
+/// Block as passed by consensus layer in `engine_newPayload` and returned to it by
+/// `engine_getPayload`. Withdrawals are only present in V2 payloads since Shanghai.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExecutionPayload {
+    pub parent_hash: H256,
+    pub fee_recipient: Address,
+    pub state_root: H256,
+    pub receipts_root: H256,
+    pub logs_bloom: Bloom,
+    #[serde(alias = "random")]
+    pub prev_randao: H256,
+    #[serde(
+        deserialize_with = "deserialize_hexstr_as_u64",
+        serialize_with = "serialize_u64_as_hexstr"
+    )]
+    pub block_number: u64,
+    #[serde(
+        deserialize_with = "deserialize_hexstr_as_u64",
+        serialize_with = "serialize_u64_as_hexstr"
+    )]
+    pub gas_limit: u64,
+    #[serde(
+        deserialize_with = "deserialize_hexstr_as_u64",
+        serialize_with = "serialize_u64_as_hexstr"
+    )]
+    pub gas_used: u64,
+    #[serde(
+        deserialize_with = "deserialize_hexstr_as_u64",
+        serialize_with = "serialize_u64_as_hexstr"
+    )]
+    pub timestamp: u64,
+    #[serde(with = "hexbytes")]
+    pub extra_data: Bytes,
+    #[serde(
+        deserialize_with = "deserialize_hexstr_as_u256",
+        serialize_with = "serialize_u256_as_hexstr"
+    )]
+    pub base_fee_per_gas: U256,
+    pub block_hash: H256,
+    #[serde(with = "hexbytes_seq")]
+    pub transactions: Vec<Bytes>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub withdrawals: Option<Vec<Withdrawal>>,
+}
+
+/// Argument of `engine_forkchoiceUpdatedV1`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ForkchoiceState {
+    pub head_block_hash: H256,
+    pub safe_block_hash: H256,
+    pub finalized_block_hash: H256,
+}
+
+/// Payload attributes of `engine_forkchoiceUpdated`, asking to build a payload on top of the
+/// new head.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PayloadAttributes {
+    #[serde(deserialize_with = "deserialize_hexstr_as_u64")]
+    pub timestamp: u64,
+    #[serde(alias = "random")]
+    pub prev_randao: H256,
+    pub suggested_fee_recipient: Address,
+    #[serde(default)]
+    pub withdrawals: Option<Vec<Withdrawal>>,
+}
+
+impl ExecutionPayload {
+    pub fn from_block(block: &Block) -> Self {
+        let header = &block.header;
+        Self {
+            parent_hash: header.parent_hash,
+            fee_recipient: header.beneficiary,
+            state_root: header.state_root,
+            receipts_root: header.receipts_root,
+            logs_bloom: header.logs_bloom,
+            prev_randao: header.mix_hash,
+            block_number: header.number.0,
+            gas_limit: header.gas_limit,
+            gas_used: header.gas_used,
+            timestamp: header.timestamp,
+            extra_data: header.extra_data.clone(),
+            base_fee_per_gas: header.base_fee_per_gas.unwrap_or(U256::ZERO),
+            block_hash: header.hash(),
+            transactions: block
+                .transactions
+                .iter()
+                .map(|tx| tx.trie_encode())
+                .collect(),
+            withdrawals: block.withdrawals.clone(),
+        }
+    }
+
+    /// Rebuild the full block, checking that it hashes to `block_hash`.
+    pub fn into_block(self) -> anyhow::Result<Block> {
+        let transactions = self
+            .transactions
+            .iter()
+            .map(|tx| MessageWithSignature::trie_decode(tx))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let header = BlockHeader {
+            parent_hash: self.parent_hash,
+            ommers_hash: EMPTY_LIST_HASH,
+            beneficiary: self.fee_recipient,
+            state_root: self.state_root,
+            transactions_root: Block::transactions_root(&transactions),
+            receipts_root: self.receipts_root,
+            logs_bloom: self.logs_bloom,
+            difficulty: U256::ZERO,
+            number: BlockNumber(self.block_number),
+            gas_limit: self.gas_limit,
+            gas_used: self.gas_used,
+            timestamp: self.timestamp,
+            extra_data: self.extra_data,
+            mix_hash: self.prev_randao,
+            nonce: H64::zero(),
+            base_fee_per_gas: Some(self.base_fee_per_gas),
+            withdrawals_root: self.withdrawals.as_deref().map(Block::withdrawals_root),
+            blob_gas_used: None,
+            excess_blob_gas: None,
+        };
+
+        let hash = header.hash();
+        ensure!(
+            hash == self.block_hash,
+            "block hash mismatch: payload {:?}, computed {:?}",
+            self.block_hash,
+            hash
+        );
+
+        Ok(Block {
+            header,
+            transactions,
+            ommers: vec![],
+            withdrawals: self.withdrawals,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hex_literal::hex;
+    use serde_json::json;
+
+    fn payload_json(block_hash: H256) -> serde_json::Value {
+        json!({
+            "parentHash": "0x3b8fb240d288781d4aac94d3fd16809ee413bc99294a085798a589dae51ddd4a",
+            "feeRecipient": "0xa94f5374fce5edbc8e2a8697c15331677e6ebf0b",
+            "stateRoot": "0xca3149fa9e37db08d1cd49c9061db1002ef1cd58db2210f2115c8c989b2bdf45",
+            "receiptsRoot": "0x56e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421",
+            "logsBloom": format!("0x{}", "00".repeat(256)),
+            "prevRandao": "0x0000000000000000000000000000000000000000000000000000000000000000",
+            "blockNumber": "0x1",
+            "gasLimit": "0x1c9c380",
+            "gasUsed": "0x0",
+            "timestamp": "0x5",
+            "extraData": "0x",
+            "baseFeePerGas": "0x7",
+            "blockHash": block_hash,
+            "transactions": []
+        })
+    }
+
+    #[test]
+    fn payload_to_block() {
+        let mut header = BlockHeader {
+            parent_hash: hex!("3b8fb240d288781d4aac94d3fd16809ee413bc99294a085798a589dae51ddd4a")
+                .into(),
+            ommers_hash: EMPTY_LIST_HASH,
+            beneficiary: hex!("a94f5374fce5edbc8e2a8697c15331677e6ebf0b").into(),
+            state_root: hex!("ca3149fa9e37db08d1cd49c9061db1002ef1cd58db2210f2115c8c989b2bdf45")
+                .into(),
+            transactions_root: EMPTY_ROOT,
+            receipts_root: EMPTY_ROOT,
+            logs_bloom: Bloom::zero(),
+            difficulty: U256::ZERO,
+            number: BlockNumber(1),
+            gas_limit: 30_000_000,
+            gas_used: 0,
+            timestamp: 5,
+            extra_data: Bytes::new(),
+            mix_hash: H256::zero(),
+            nonce: H64::zero(),
+            base_fee_per_gas: Some(7.as_u256()),
+            withdrawals_root: None,
+            blob_gas_used: None,
+            excess_blob_gas: None,
+        };
+
+        let payload =
+            serde_json::from_value::<ExecutionPayload>(payload_json(header.hash())).unwrap();
+        assert_eq!(
+            serde_json::to_value(&payload).unwrap(),
+            payload_json(header.hash())
+        );
+        let block = payload.clone().into_block().unwrap();
+        assert_eq!(block.header, header);
+        assert!(block.transactions.is_empty());
+        assert!(block.ommers.is_empty());
+        assert_eq!(ExecutionPayload::from_block(&block), payload);
+
+        header.timestamp += 1;
+        let payload =
+            serde_json::from_value::<ExecutionPayload>(payload_json(header.hash())).unwrap();
+        assert!(payload.into_block().is_err());
+
+        header.withdrawals_root = Some(EMPTY_ROOT);
+        let mut json = payload_json(header.hash());
+        json["withdrawals"] = json!([]);
+        let payload = serde_json::from_value::<ExecutionPayload>(json.clone()).unwrap();
+        assert_eq!(serde_json::to_value(&payload).unwrap(), json);
+        let block = payload.into_block().unwrap();
+        assert_eq!(block.header, header);
+        assert_eq!(block.withdrawals, Some(vec![]));
+    }
+}
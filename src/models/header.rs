@@ -24,17 +24,19 @@ pub struct BlockHeader {
     pub mix_hash: H256,
     pub nonce: H64,
     pub base_fee_per_gas: Option<U256>,
+    pub withdrawals_root: Option<H256>,
+    pub blob_gas_used: Option<u64>,
+    pub excess_blob_gas: Option<u64>,
 }
 
 impl Encodable for BlockHeader {
     fn rlp_append(&self, s: &mut RlpStream) {
-        s.begin_list({
-            if self.base_fee_per_gas.is_some() {
-                16
-            } else {
-                15
-            }
-        });
+        s.begin_list(
+            15 + usize::from(self.base_fee_per_gas.is_some())
+                + usize::from(self.withdrawals_root.is_some())
+                + usize::from(self.blob_gas_used.is_some())
+                + usize::from(self.excess_blob_gas.is_some()),
+        );
         s.append(&self.parent_hash);
         s.append(&self.ommers_hash);
         s.append(&self.beneficiary);
@@ -53,32 +55,46 @@ impl Encodable for BlockHeader {
         if let Some(base_fee_per_gas) = self.base_fee_per_gas {
             s.append(&base_fee_per_gas);
         }
+        if let Some(withdrawals_root) = self.withdrawals_root {
+            s.append(&withdrawals_root);
+        }
+        if let Some(blob_gas_used) = self.blob_gas_used {
+            s.append(&blob_gas_used);
+        }
+        if let Some(excess_blob_gas) = self.excess_blob_gas {
+            s.append(&excess_blob_gas);
+        }
     }
 }
 
 impl Decodable for BlockHeader {
     fn decode(rlp: &Rlp) -> Result<Self, DecoderError> {
-        let mut rlp = rlp.iter();
-        let parent_hash = rlp.next().ok_or(DecoderError::RlpInvalidLength)?.as_val()?;
-        let ommers_hash = rlp.next().ok_or(DecoderError::RlpInvalidLength)?.as_val()?;
-        let beneficiary = rlp.next().ok_or(DecoderError::RlpInvalidLength)?.as_val()?;
-        let state_root = rlp.next().ok_or(DecoderError::RlpInvalidLength)?.as_val()?;
-        let transactions_root = rlp.next().ok_or(DecoderError::RlpInvalidLength)?.as_val()?;
-        let receipts_root = rlp.next().ok_or(DecoderError::RlpInvalidLength)?.as_val()?;
-        let logs_bloom = rlp.next().ok_or(DecoderError::RlpInvalidLength)?.as_val()?;
-        let difficulty = rlp.next().ok_or(DecoderError::RlpInvalidLength)?.as_val()?;
-        let number = rlp.next().ok_or(DecoderError::RlpInvalidLength)?.as_val()?;
-        let gas_limit = rlp.next().ok_or(DecoderError::RlpInvalidLength)?.as_val()?;
-        let gas_used = rlp.next().ok_or(DecoderError::RlpInvalidLength)?.as_val()?;
-        let timestamp = rlp.next().ok_or(DecoderError::RlpInvalidLength)?.as_val()?;
-        let extra_data = rlp
-            .next()
-            .ok_or(DecoderError::RlpInvalidLength)?
-            .as_val::<Vec<u8>>()?
-            .into();
-        let mix_hash = rlp.next().ok_or(DecoderError::RlpInvalidLength)?.as_val()?;
-        let nonce = rlp.next().ok_or(DecoderError::RlpInvalidLength)?.as_val()?;
-        let base_fee_per_gas = rlp.next().map(|rlp| rlp.as_val()).transpose()?;
+        // 15 fields before London, then base fee, withdrawals root and blob gas fields
+        // appended by later forks.
+        let fields = rlp.item_count()?;
+        if !(15..=19).contains(&fields) {
+            return Err(DecoderError::RlpIncorrectListLen);
+        }
+
+        let parent_hash = rlp.val_at(0)?;
+        let ommers_hash = rlp.val_at(1)?;
+        let beneficiary = rlp.val_at(2)?;
+        let state_root = rlp.val_at(3)?;
+        let transactions_root = rlp.val_at(4)?;
+        let receipts_root = rlp.val_at(5)?;
+        let logs_bloom = rlp.val_at(6)?;
+        let difficulty = rlp.val_at(7)?;
+        let number = rlp.val_at(8)?;
+        let gas_limit = rlp.val_at(9)?;
+        let gas_used = rlp.val_at(10)?;
+        let timestamp = rlp.val_at(11)?;
+        let extra_data = rlp.val_at::<Vec<u8>>(12)?.into();
+        let mix_hash = rlp.val_at(13)?;
+        let nonce = rlp.val_at(14)?;
+        let base_fee_per_gas = (fields > 15).then(|| rlp.val_at(15)).transpose()?;
+        let withdrawals_root = (fields > 16).then(|| rlp.val_at(16)).transpose()?;
+        let blob_gas_used = (fields > 17).then(|| rlp.val_at(17)).transpose()?;
+        let excess_blob_gas = (fields > 18).then(|| rlp.val_at(18)).transpose()?;
 
         Ok(Self {
             parent_hash,
@@ -97,6 +113,9 @@ impl Decodable for BlockHeader {
             mix_hash,
             nonce,
             base_fee_per_gas,
+            withdrawals_root,
+            blob_gas_used,
+            excess_blob_gas,
         })
     }
 }
@@ -121,6 +140,9 @@ impl BlockHeader {
             mix_hash: partial_header.mix_hash,
             nonce: partial_header.nonce,
             base_fee_per_gas: partial_header.base_fee_per_gas,
+            withdrawals_root: partial_header.withdrawals_root,
+            blob_gas_used: partial_header.blob_gas_used,
+            excess_blob_gas: partial_header.excess_blob_gas,
         }
     }
 
@@ -143,6 +165,9 @@ impl BlockHeader {
             mix_hash: H256::zero(),
             nonce: H64::zero(),
             base_fee_per_gas: None,
+            withdrawals_root: None,
+            blob_gas_used: None,
+            excess_blob_gas: None,
         }
     }
 
@@ -236,6 +261,9 @@ pub struct PartialHeader {
     pub mix_hash: H256,
     pub nonce: H64,
     pub base_fee_per_gas: Option<U256>,
+    pub withdrawals_root: Option<H256>,
+    pub blob_gas_used: Option<u64>,
+    pub excess_blob_gas: Option<u64>,
 }
 
 impl From<BlockHeader> for PartialHeader {
@@ -255,12 +283,14 @@ impl From<BlockHeader> for PartialHeader {
             mix_hash: header.mix_hash,
             nonce: header.nonce,
             base_fee_per_gas: header.base_fee_per_gas,
+            withdrawals_root: header.withdrawals_root,
+            blob_gas_used: header.blob_gas_used,
+            excess_blob_gas: header.excess_blob_gas,
         }
     }
 }
 
 impl PartialHeader {
-    #[cfg(test)]
     pub(crate) const fn empty() -> Self {
         Self {
             parent_hash: H256::zero(),
@@ -277,6 +307,9 @@ impl PartialHeader {
             mix_hash: H256::zero(),
             nonce: H64::zero(),
             base_fee_per_gas: None,
+            withdrawals_root: None,
+            blob_gas_used: None,
+            excess_blob_gas: None,
         }
     }
 }
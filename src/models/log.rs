@@ -22,25 +22,14 @@ impl Encodable for Log {
 
 impl Decodable for Log {
     fn decode(rlp: &Rlp) -> Result<Self, DecoderError> {
-        let mut rlp = rlp.iter();
-        let address = rlp
-            .next()
-            .ok_or(DecoderError::RlpIncorrectListLen)?
-            .as_val()?;
-        let topics = rlp
-            .next()
-            .ok_or(DecoderError::RlpIncorrectListLen)?
-            .as_list()?;
-        let data = rlp
-            .next()
-            .ok_or(DecoderError::RlpIncorrectListLen)?
-            .as_val::<Vec<u8>>()?
-            .into();
+        if rlp.item_count()? != 3 {
+            return Err(DecoderError::RlpIncorrectListLen);
+        }
 
         Ok(Self {
-            address,
-            topics,
-            data,
+            address: rlp.val_at(0)?,
+            topics: rlp.list_at(1)?,
+            data: rlp.val_at::<Vec<u8>>(2)?.into(),
         })
     }
 }
@@ -1,14 +1,18 @@
 mod account;
+mod blob;
 mod block;
 mod bloom;
 mod chainspec;
+mod execution_payload;
 mod header;
 mod log;
 mod receipt;
 mod transaction;
+mod withdrawal;
 
 pub use self::{
-    account::*, block::*, bloom::*, chainspec::*, header::*, log::*, receipt::*, transaction::*,
+    account::*, blob::*, block::*, bloom::*, chainspec::*, execution_payload::*, header::*, log::*,
+    receipt::*, transaction::*, withdrawal::*,
 };
 
 use derive_more::*;
@@ -35,7 +35,7 @@ impl Receipt {
                 l.append(&self.bloom);
                 l.append_list(&self.logs);
             }
-            TxType::EIP2930 | TxType::EIP1559 => {
+            TxType::EIP2930 | TxType::EIP1559 | TxType::EIP4844 => {
                 let mut b = BytesMut::with_capacity(1);
                 b.put_u8(self.tx_type as u8);
                 let mut l = RlpStream::new_list_with_buffer(b, 4);
@@ -1,4 +1,5 @@
 use crate::{
+    chain::protocol_param::param,
     crypto::{is_valid_signature, TrieEncode},
     models::*,
     util::*,
@@ -22,6 +23,7 @@ pub enum TxType {
     Legacy = 0,
     EIP2930 = 1,
     EIP1559 = 2,
+    EIP4844 = 3,
 }
 
 impl TryFrom<u8> for TxType {
@@ -31,6 +33,7 @@ impl TryFrom<u8> for TxType {
             0 => Ok(TxType::Legacy),
             1 => Ok(TxType::EIP2930),
             2 => Ok(TxType::EIP1559),
+            3 => Ok(TxType::EIP4844),
             _ => Err(DecoderError::Custom("Invalid tx type")),
         }
     }
@@ -281,11 +284,36 @@ pub enum Message {
         input: Bytes,
         access_list: Vec<AccessListItem>,
     },
+    /// Transaction carrying blobs, which cannot create contracts.
+    ///
+    /// https://eips.ethereum.org/EIPS/eip-4844
+    EIP4844 {
+        #[codec(compact)]
+        chain_id: ChainId,
+        #[codec(compact)]
+        nonce: u64,
+        #[codec(compact)]
+        max_priority_fee_per_gas: U256,
+        #[codec(compact)]
+        max_fee_per_gas: U256,
+        #[codec(compact)]
+        gas_limit: u64,
+        action: TransactionAction,
+        #[codec(compact)]
+        value: U256,
+        #[educe(Debug(method = "write_hex_string"))]
+        input: Bytes,
+        access_list: Vec<AccessListItem>,
+        #[codec(compact)]
+        max_fee_per_blob_gas: U256,
+        blob_versioned_hashes: Vec<H256>,
+    },
 }
 
 impl Message {
-    pub fn hash(&self) -> H256 {
-        let msg = match self {
+    /// Unsigned transaction as hashed for signing.
+    pub fn signing_payload(&self) -> Bytes {
+        match self {
             Message::Legacy {
                 chain_id,
                 nonce,
@@ -368,9 +396,42 @@ impl Message {
                 s.append_list(access_list);
                 s.out()
             }
-        };
+            Message::EIP4844 {
+                chain_id,
+                nonce,
+                max_priority_fee_per_gas,
+                max_fee_per_gas,
+                gas_limit,
+                action,
+                value,
+                input,
+                access_list,
+                max_fee_per_blob_gas,
+                blob_versioned_hashes,
+            } => {
+                let mut b = BytesMut::with_capacity(1);
+                b.put_u8(3);
+                let mut s = RlpStream::new_with_buffer(b);
+                s.begin_list(11);
+                s.append(chain_id);
+                s.append(nonce);
+                s.append(max_priority_fee_per_gas);
+                s.append(max_fee_per_gas);
+                s.append(gas_limit);
+                s.append(action);
+                s.append(value);
+                s.append(&input.as_ref());
+                s.append_list(access_list);
+                s.append(max_fee_per_blob_gas);
+                s.append_list(blob_versioned_hashes);
+                s.out()
+            }
+        }
+        .freeze()
+    }
 
-        H256::from_slice(Keccak256::digest(&msg.freeze()).as_slice())
+    pub fn hash(&self) -> H256 {
+        H256::from_slice(Keccak256::digest(&self.signing_payload()).as_slice())
     }
 }
 
@@ -479,8 +540,93 @@ impl MessageWithSignature {
                     s.append(&s1.out());
                 }
             }
+            Message::EIP4844 {
+                chain_id,
+                nonce,
+                max_priority_fee_per_gas,
+                max_fee_per_gas,
+                gas_limit,
+                action,
+                value,
+                input,
+                access_list,
+                max_fee_per_blob_gas,
+                blob_versioned_hashes,
+            } => {
+                let mut b = BytesMut::with_capacity(1);
+                b.put_u8(3);
+                let mut s1 = RlpStream::new_list_with_buffer(b, 14);
+                s1.append(chain_id);
+                s1.append(nonce);
+                s1.append(max_priority_fee_per_gas);
+                s1.append(max_fee_per_gas);
+                s1.append(gas_limit);
+                s1.append(action);
+                s1.append(value);
+                s1.append(&input.as_ref());
+                s1.append_list(access_list);
+                s1.append(max_fee_per_blob_gas);
+                s1.append_list(blob_versioned_hashes);
+                s1.append(&self.signature.odd_y_parity);
+                s1.append(&U256::from_be_bytes(self.signature.r.0));
+                s1.append(&U256::from_be_bytes(self.signature.s.0));
+                if standalone {
+                    s.append_raw(&*s1.out().freeze(), 1);
+                } else {
+                    s.append(&s1.out());
+                }
+            }
         }
     }
+
+    /// Fields of EIP-4844 transaction, following its type byte.
+    fn decode_eip4844(rlp: &Rlp) -> Result<Self, DecoderError> {
+        if rlp.item_count()? != 14 {
+            return Err(DecoderError::RlpIncorrectListLen);
+        }
+
+        let action = rlp.val_at(5)?;
+        if action == TransactionAction::Create {
+            return Err(DecoderError::Custom(
+                "blob transaction cannot create contract",
+            ));
+        }
+
+        Ok(Self {
+            message: Message::EIP4844 {
+                chain_id: rlp.val_at(0)?,
+                nonce: rlp.val_at(1)?,
+                max_priority_fee_per_gas: rlp.val_at(2)?,
+                max_fee_per_gas: rlp.val_at(3)?,
+                gas_limit: rlp.val_at(4)?,
+                action,
+                value: rlp.val_at(6)?,
+                input: rlp.val_at::<Vec<u8>>(7)?.into(),
+                access_list: rlp.list_at(8)?,
+                max_fee_per_blob_gas: rlp.val_at(9)?,
+                blob_versioned_hashes: rlp.list_at(10)?,
+            },
+            signature: MessageSignature::new(
+                rlp.val_at(11)?,
+                H256(rlp.val_at::<U256>(12)?.to_be_bytes()),
+                H256(rlp.val_at::<U256>(13)?.to_be_bytes()),
+            )
+            .ok_or(DecoderError::Custom("Invalid transaction signature format"))?,
+        })
+    }
+}
+
+/// Payload of typed transaction following its type byte, a list which must span the rest of
+/// the envelope.
+pub(crate) fn typed_payload(s: &[u8]) -> Result<Rlp, DecoderError> {
+    let rlp = Rlp::new(s);
+    if !rlp.is_list() {
+        return Err(DecoderError::RlpExpectedToBeList);
+    }
+    if rlp.payload_info()?.total() < s.len() {
+        return Err(DecoderError::RlpIsTooBig);
+    }
+    Ok(rlp)
 }
 
 impl TrieEncode for MessageWithSignature {
@@ -493,11 +639,11 @@ impl TrieEncode for MessageWithSignature {
 
 impl MessageWithSignature {
     pub fn trie_decode(slice: &[u8]) -> Result<MessageWithSignature, DecoderError> {
-        let first = *slice.get(0).ok_or(DecoderError::Custom("empty slice"))?;
+        let first = *slice.get(0).ok_or(DecoderError::RlpIsTooShort)?;
 
         if first == 0x01 {
             let s = slice.get(1..).ok_or(DecoderError::Custom("no tx body"))?;
-            let rlp = Rlp::new(s);
+            let rlp = typed_payload(s)?;
             if rlp.item_count()? != 11 {
                 return Err(DecoderError::RlpIncorrectListLen);
             }
@@ -524,7 +670,7 @@ impl MessageWithSignature {
 
         if first == 0x02 {
             let s = slice.get(1..).ok_or(DecoderError::Custom("no tx body"))?;
-            let rlp = Rlp::new(s);
+            let rlp = typed_payload(s)?;
             if rlp.item_count()? != 12 {
                 return Err(DecoderError::RlpIncorrectListLen);
             }
@@ -550,6 +696,11 @@ impl MessageWithSignature {
             });
         }
 
+        if first == 0x03 {
+            let s = slice.get(1..).ok_or(DecoderError::Custom("no tx body"))?;
+            return Self::decode_eip4844(&typed_payload(s)?);
+        }
+
         let rlp = Rlp::new(slice);
         if rlp.is_list() {
             if rlp.item_count()? != 9 {
@@ -580,7 +731,7 @@ impl MessageWithSignature {
             });
         }
 
-        Err(DecoderError::Custom("invalid tx type"))
+        Err(DecoderError::Custom("unknown transaction type"))
     }
 }
 
@@ -594,7 +745,7 @@ impl Decodable for MessageWithSignature {
     fn decode(rlp: &Rlp) -> Result<Self, DecoderError> {
         let slice = rlp.data()?;
 
-        let first = *slice.get(0).ok_or(DecoderError::Custom("empty slice"))?;
+        let first = *slice.get(0).ok_or(DecoderError::RlpIsTooShort)?;
 
         if rlp.is_list() {
             if rlp.item_count()? != 9 {
@@ -628,7 +779,7 @@ impl Decodable for MessageWithSignature {
         let s = slice.get(1..).ok_or(DecoderError::Custom("no tx body"))?;
 
         if first == 0x01 {
-            let rlp = Rlp::new(s);
+            let rlp = typed_payload(s)?;
             if rlp.item_count()? != 11 {
                 return Err(DecoderError::RlpIncorrectListLen);
             }
@@ -654,7 +805,7 @@ impl Decodable for MessageWithSignature {
         }
 
         if first == 0x02 {
-            let rlp = Rlp::new(s);
+            let rlp = typed_payload(s)?;
             if rlp.item_count()? != 12 {
                 return Err(DecoderError::RlpIncorrectListLen);
             }
@@ -680,7 +831,11 @@ impl Decodable for MessageWithSignature {
             });
         }
 
-        Err(DecoderError::Custom("invalid tx type"))
+        if first == 0x03 {
+            return Self::decode_eip4844(&typed_payload(s)?);
+        }
+
+        Err(DecoderError::Custom("unknown transaction type"))
     }
 }
 
@@ -690,6 +845,7 @@ impl Message {
             Self::Legacy { .. } => TxType::Legacy,
             Self::EIP2930 { .. } => TxType::EIP2930,
             Self::EIP1559 { .. } => TxType::EIP1559,
+            Self::EIP4844 { .. } => TxType::EIP4844,
         }
     }
 
@@ -697,7 +853,7 @@ impl Message {
         match *self {
             Self::Legacy { chain_id, .. } => chain_id,
             Self::EIP2930 { chain_id, .. } => Some(chain_id),
-            Self::EIP1559 { chain_id, .. } => Some(chain_id),
+            Self::EIP1559 { chain_id, .. } | Self::EIP4844 { chain_id, .. } => Some(chain_id),
         }
     }
 
@@ -705,7 +861,8 @@ impl Message {
         match *self {
             Self::Legacy { nonce, .. }
             | Self::EIP2930 { nonce, .. }
-            | Self::EIP1559 { nonce, .. } => nonce,
+            | Self::EIP1559 { nonce, .. }
+            | Self::EIP4844 { nonce, .. } => nonce,
         }
     }
 
@@ -715,6 +872,10 @@ impl Message {
             Self::EIP1559 {
                 max_priority_fee_per_gas,
                 ..
+            }
+            | Self::EIP4844 {
+                max_priority_fee_per_gas,
+                ..
             } => max_priority_fee_per_gas,
         }
     }
@@ -724,15 +885,45 @@ impl Message {
             Self::Legacy { gas_price, .. } | Self::EIP2930 { gas_price, .. } => gas_price,
             Self::EIP1559 {
                 max_fee_per_gas, ..
+            }
+            | Self::EIP4844 {
+                max_fee_per_gas, ..
             } => max_fee_per_gas,
         }
     }
 
+    /// Most the sender pays per unit of blob gas, zero if the transaction has no blobs.
+    pub const fn max_fee_per_blob_gas(&self) -> U256 {
+        match *self {
+            Self::EIP4844 {
+                max_fee_per_blob_gas,
+                ..
+            } => max_fee_per_blob_gas,
+            _ => U256::ZERO,
+        }
+    }
+
+    pub fn blob_versioned_hashes(&self) -> &[H256] {
+        match self {
+            Self::EIP4844 {
+                blob_versioned_hashes,
+                ..
+            } => blob_versioned_hashes,
+            _ => &[],
+        }
+    }
+
+    /// Blob gas consumed by the blobs of the transaction.
+    pub fn blob_gas(&self) -> u64 {
+        self.blob_versioned_hashes().len() as u64 * param::GAS_PER_BLOB
+    }
+
     pub const fn gas_limit(&self) -> u64 {
         match *self {
             Self::Legacy { gas_limit, .. }
             | Self::EIP2930 { gas_limit, .. }
-            | Self::EIP1559 { gas_limit, .. } => gas_limit,
+            | Self::EIP1559 { gas_limit, .. }
+            | Self::EIP4844 { gas_limit, .. } => gas_limit,
         }
     }
 
@@ -740,7 +931,8 @@ impl Message {
         match *self {
             Self::Legacy { action, .. }
             | Self::EIP2930 { action, .. }
-            | Self::EIP1559 { action, .. } => action,
+            | Self::EIP1559 { action, .. }
+            | Self::EIP4844 { action, .. } => action,
         }
     }
 
@@ -748,7 +940,8 @@ impl Message {
         match *self {
             Self::Legacy { value, .. }
             | Self::EIP2930 { value, .. }
-            | Self::EIP1559 { value, .. } => value,
+            | Self::EIP1559 { value, .. }
+            | Self::EIP4844 { value, .. } => value,
         }
     }
 
@@ -756,16 +949,17 @@ impl Message {
         match self {
             Self::Legacy { input, .. }
             | Self::EIP2930 { input, .. }
-            | Self::EIP1559 { input, .. } => input,
+            | Self::EIP1559 { input, .. }
+            | Self::EIP4844 { input, .. } => input,
         }
     }
 
     pub const fn access_list(&self) -> Cow<'_, AccessList> {
         match self {
             Self::Legacy { .. } => Cow::Owned(AccessList::new()),
-            Self::EIP2930 { access_list, .. } | Self::EIP1559 { access_list, .. } => {
-                Cow::Borrowed(access_list)
-            }
+            Self::EIP2930 { access_list, .. }
+            | Self::EIP1559 { access_list, .. }
+            | Self::EIP4844 { access_list, .. } => Cow::Borrowed(access_list),
         }
     }
 
@@ -817,9 +1011,122 @@ impl MessageWithSignature {
     }
 }
 
+/// Every field of a signed transaction, along with its hash and sender.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DecodedTransaction {
+    #[serde(rename = "type")]
+    pub tx_type: U64,
+    pub hash: H256,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from: Option<Address>,
+    /// Why sender could not be recovered.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sender_error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chain_id: Option<ChainId>,
+    pub nonce: U64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gas_price: Option<U256>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_priority_fee_per_gas: Option<U256>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_fee_per_gas: Option<U256>,
+    pub gas: U64,
+    pub to: Option<Address>,
+    pub value: U256,
+    #[serde(with = "hexbytes")]
+    pub input: Bytes,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub access_list: Option<AccessList>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_fee_per_blob_gas: Option<U256>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub blob_versioned_hashes: Option<Vec<H256>>,
+    pub v: U64,
+    pub r: H256,
+    pub s: H256,
+}
+
+impl DecodedTransaction {
+    /// Decode transaction in the form accepted by `eth_sendRawTransaction`.
+    pub fn decode(raw: &[u8]) -> anyhow::Result<Self> {
+        let txn = MessageWithSignature::trie_decode(raw)?;
+
+        let (from, sender_error) = match txn.recover_sender() {
+            Ok(sender) => (Some(sender), None),
+            Err(e) => (None, Some(e.to_string())),
+        };
+
+        let (gas_price, max_priority_fee_per_gas, max_fee_per_gas, v) = match &txn.message {
+            Message::Legacy {
+                chain_id,
+                gas_price,
+                ..
+            } => (
+                Some(*gas_price),
+                None,
+                None,
+                YParityAndChainId {
+                    odd_y_parity: txn.signature.odd_y_parity(),
+                    chain_id: *chain_id,
+                }
+                .v(),
+            ),
+            Message::EIP2930 { gas_price, .. } => (Some(*gas_price), None, None, txn.v().into()),
+            Message::EIP1559 {
+                max_priority_fee_per_gas,
+                max_fee_per_gas,
+                ..
+            }
+            | Message::EIP4844 {
+                max_priority_fee_per_gas,
+                max_fee_per_gas,
+                ..
+            } => (
+                None,
+                Some(*max_priority_fee_per_gas),
+                Some(*max_fee_per_gas),
+                txn.v().into(),
+            ),
+        };
+
+        Ok(Self {
+            tx_type: (txn.tx_type() as u64).into(),
+            hash: txn.hash(),
+            from,
+            sender_error,
+            chain_id: txn.chain_id(),
+            nonce: txn.nonce().into(),
+            gas_price,
+            max_priority_fee_per_gas,
+            max_fee_per_gas,
+            gas: txn.gas_limit().into(),
+            to: match txn.action() {
+                TransactionAction::Call(to) => Some(to),
+                TransactionAction::Create => None,
+            },
+            value: txn.value(),
+            input: txn.input().clone(),
+            access_list: match txn.tx_type() {
+                TxType::Legacy => None,
+                _ => Some(txn.access_list().into_owned()),
+            },
+            max_fee_per_blob_gas: (txn.tx_type() == TxType::EIP4844)
+                .then(|| txn.max_fee_per_blob_gas()),
+            blob_versioned_hashes: (txn.tx_type() == TxType::EIP4844)
+                .then(|| txn.blob_versioned_hashes().to_vec()),
+            v: v.into(),
+            r: txn.r(),
+            s: txn.s(),
+        })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::crypto::keccak256;
     use hex_literal::hex;
 
     #[test]
@@ -829,6 +1136,25 @@ mod tests {
         rlp::decode::<MessageWithSignature>(&bytes).unwrap();
     }
 
+    #[test]
+    fn decode_signed_transaction() {
+        let bytes = hex!("f901e48080831000008080b90196608060405234801561001057600080fd5b50336000806101000a81548173ffffffffffffffffffffffffffffffffffffffff021916908373ffffffffffffffffffffffffffffffffffffffff1602179055507fc68045c3c562488255b55aa2c4c7849de001859ff0d8a36a75c2d5ed80100fb660405180806020018281038252600d8152602001807f48656c6c6f2c20776f726c64210000000000000000000000000000000000000081525060200191505060405180910390a160cf806100c76000396000f3fe6080604052348015600f57600080fd5b506004361060285760003560e01c80638da5cb5b14602d575b600080fd5b60336075565b604051808273ffffffffffffffffffffffffffffffffffffffff1673ffffffffffffffffffffffffffffffffffffffff16815260200191505060405180910390f35b6000809054906101000a900473ffffffffffffffffffffffffffffffffffffffff168156fea265627a7a72315820fae816ad954005c42bea7bc7cb5b19f7fd5d3a250715ca2023275c9ca7ce644064736f6c634300050f003278a04cab43609092a99cf095d458b61b47189d1bbab64baed10a0fd7b7d2de2eb960a011ab1bcda76dfed5e733219beb83789f9887b2a7b2e61759c7c90f7d40403201");
+
+        let decoded = DecodedTransaction::decode(&bytes).unwrap();
+        assert_eq!(decoded.tx_type, U64::zero());
+        assert_eq!(decoded.hash, keccak256(&bytes));
+        assert!(decoded.from.is_some());
+        assert_eq!(decoded.chain_id, Some(ChainId(42)));
+        assert_eq!(decoded.v, U64::from(0x78));
+        assert_eq!(decoded.nonce, U64::zero());
+        assert_eq!(decoded.gas, U64::from(0x100000));
+        assert_eq!(decoded.to, None);
+        assert_eq!(decoded.input.len(), 0x196);
+        assert_eq!(decoded.access_list, None);
+
+        assert!(DecodedTransaction::decode(&bytes[..100]).is_err());
+    }
+
     #[test]
     fn transaction_legacy() {
         let tx = MessageWithSignature {
@@ -951,6 +1277,72 @@ mod tests {
             tx,
             MessageWithSignature::trie_decode(&tx.trie_encode()).unwrap()
         );
+
+        let mut trailing = tx.trie_encode().to_vec();
+        trailing.push(0x80);
+        assert_eq!(
+            MessageWithSignature::trie_decode(&trailing),
+            Err(DecoderError::RlpIsTooBig)
+        );
+        assert_eq!(
+            MessageWithSignature::trie_decode(&[]),
+            Err(DecoderError::RlpIsTooShort)
+        );
+    }
+
+    #[test]
+    fn transaction_eip4844() {
+        let mut tx = MessageWithSignature {
+            message: Message::EIP4844 {
+                chain_id: ChainId(1),
+                nonce: 3,
+                max_priority_fee_per_gas: 1_000_000_000_u64.into(),
+                max_fee_per_gas: 30_000_000_000_u64.into(),
+                gas_limit: 21_000,
+                action: TransactionAction::Call(
+                    hex!("811a752c8cd697e3cb27279c330ed1ada745a8d7").into(),
+                ),
+                value: U256::ZERO,
+                input: Bytes::new(),
+                access_list: vec![],
+                max_fee_per_blob_gas: 100_u64.into(),
+                blob_versioned_hashes: vec![
+                    hex!("01b0a4cdd5f55589f5c5b4d46c76704bb6ce95c0a8c09f77f197a57808dded28").into(),
+                    hex!("01ac32c4a1b5c2f3d3e6f7e8d9c0b1a2938475665748392a1b0c9d8e7f6a5b4c").into(),
+                ],
+            },
+            signature: MessageSignature::new(
+                true,
+                hex!("36b241b061a36a32ab7fe86c7aa9eb592dd59018cd0443adc0903590c16b02b0"),
+                hex!("5edcc541b4741c5cc6dd347c5ed9577ef293a62787b4510465fadbfe39ee4094"),
+            )
+            .unwrap(),
+        };
+
+        assert_eq!(tx.tx_type(), TxType::EIP4844);
+        assert_eq!(tx.blob_gas(), 2 * param::GAS_PER_BLOB);
+        assert_eq!(tx.message.signing_payload()[0], 3);
+        assert_eq!(tx.trie_encode()[0], 3);
+        assert_eq!(
+            tx,
+            rlp::decode::<MessageWithSignature>(&rlp::encode(&tx)).unwrap()
+        );
+        assert_eq!(
+            tx,
+            MessageWithSignature::trie_decode(&tx.trie_encode()).unwrap()
+        );
+
+        let decoded = DecodedTransaction::decode(&tx.trie_encode()).unwrap();
+        assert_eq!(decoded.max_fee_per_blob_gas, Some(100_u64.into()));
+        assert_eq!(
+            decoded.blob_versioned_hashes.as_deref(),
+            Some(tx.blob_versioned_hashes())
+        );
+
+        if let Message::EIP4844 { action, .. } = &mut tx.message {
+            *action = TransactionAction::Create;
+        }
+        assert!(MessageWithSignature::trie_decode(&tx.trie_encode()).is_err());
     }
 
     #[test]
@@ -0,0 +1,91 @@
+use super::*;
+use crate::util::*;
+use parity_scale_codec::*;
+use rlp_derive::*;
+use serde::*;
+
+/// Withdrawal of validator balance from the beacon chain, credited to `address` after the
+/// transactions of the block.
+///
+/// https://eips.ethereum.org/EIPS/eip-4895
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    Serialize,
+    Deserialize,
+    Encode,
+    Decode,
+    RlpEncodable,
+    RlpDecodable,
+)]
+#[serde(rename_all = "camelCase")]
+pub struct Withdrawal {
+    #[serde(
+        deserialize_with = "deserialize_hexstr_as_u64",
+        serialize_with = "serialize_u64_as_hexstr"
+    )]
+    pub index: u64,
+    #[serde(
+        deserialize_with = "deserialize_hexstr_as_u64",
+        serialize_with = "serialize_u64_as_hexstr"
+    )]
+    pub validator_index: u64,
+    pub address: Address,
+    /// Amount in Gwei.
+    #[serde(
+        deserialize_with = "deserialize_hexstr_as_u64",
+        serialize_with = "serialize_u64_as_hexstr"
+    )]
+    pub amount: u64,
+}
+
+impl Withdrawal {
+    pub fn amount_in_wei(&self) -> U256 {
+        U256::from(self.amount) * U256::from(GIGA)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hex_literal::hex;
+    use serde_json::json;
+
+    #[test]
+    fn withdrawal_encoding() {
+        let withdrawal = Withdrawal {
+            index: 0x0f,
+            validator_index: 0x2a,
+            address: hex!("00000000000000000000000000000000000010f0").into(),
+            amount: 0x1bc16d674ec80000,
+        };
+
+        assert_eq!(
+            rlp::encode(&withdrawal).to_vec(),
+            hex!("e00f2a9400000000000000000000000000000000000010f0881bc16d674ec80000")
+        );
+        assert_eq!(
+            rlp::decode::<Withdrawal>(&rlp::encode(&withdrawal)).unwrap(),
+            withdrawal
+        );
+
+        let json = json!({
+            "index": "0xf",
+            "validatorIndex": "0x2a",
+            "address": "0x00000000000000000000000000000000000010f0",
+            "amount": "0x1bc16d674ec80000",
+        });
+        assert_eq!(serde_json::to_value(&withdrawal).unwrap(), json);
+        assert_eq!(
+            serde_json::from_value::<Withdrawal>(json).unwrap(),
+            withdrawal
+        );
+        assert_eq!(
+            withdrawal.amount_in_wei(),
+            U256::from(0x1bc16d674ec80000_u64) * U256::from(GIGA)
+        );
+    }
+}
@@ -0,0 +1,210 @@
+//! Settings that can be changed without restarting the node and dropping its peers.
+//!
+//! They are read from a TOML file at startup and read again on SIGHUP or on request of an admin
+//! RPC. Components subscribe to changes and apply them on the fly, settings missing from the
+//! file keep their defaults.
+
+use crate::txpool::policy::AdmissionPolicy;
+use anyhow::{format_err, Context};
+use serde::{Deserialize, Serialize};
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use tokio::{
+    signal::unix::{signal, SignalKind},
+    sync::watch,
+};
+use tracing::*;
+use tracing_subscriber::{reload, EnvFilter, Registry};
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ReloadableConfig {
+    /// Log filter in `RUST_LOG` syntax, the filter set at startup is used if not set.
+    pub log: Option<String>,
+    pub rpc: RpcLimits,
+    pub txpool: AdmissionPolicy,
+}
+
+impl ReloadableConfig {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read config {}", path.display()))?;
+        let config: Self = toml::from_str(&text)
+            .with_context(|| format!("failed to parse config {}", path.display()))?;
+        if let Some(log) = &config.log {
+            EnvFilter::try_new(log).with_context(|| format!("invalid log filter {:?}", log))?;
+        }
+        Ok(config)
+    }
+}
+
+/// Limits of expensive RPC methods.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct RpcLimits {
+    /// Most points `akula_getBalanceHistory` returns in one call.
+    pub max_history_points: u64,
+    /// Most blocks `akula_getStorageDiff` walks changesets of in one call.
+    pub max_storage_diff_blocks: u64,
+    /// Most blocks `akula_getTopGasConsumers` looks back.
+    pub max_gas_consumers_blocks: u64,
+    /// Most logs `akula_getLogsPage` returns in one call.
+    pub max_logs_page_size: u64,
+    /// Most logs `eth_getLogs` and `eth_getFilterLogs` return, larger results fail.
+    pub max_logs: u64,
+    /// Most addresses `akula_getBalances` looks up in one call.
+    pub max_balances: u64,
+}
+
+impl Default for RpcLimits {
+    fn default() -> Self {
+        Self {
+            max_history_points: 10_000,
+            max_storage_diff_blocks: 100_000,
+            max_gas_consumers_blocks: 1_000_000,
+            max_logs_page_size: 10_000,
+            max_logs: 10_000,
+            max_balances: 10_000,
+        }
+    }
+}
+
+/// Holds the current config and publishes it to subscribers whenever it is reloaded.
+#[derive(Debug)]
+pub struct ConfigReloader {
+    path: Option<PathBuf>,
+    sender: watch::Sender<Arc<ReloadableConfig>>,
+    // Keeps the channel open, so that reloads never fail for lack of subscribers.
+    receiver: watch::Receiver<Arc<ReloadableConfig>>,
+}
+
+impl ConfigReloader {
+    /// Load config from `path`, or use defaults if there is no config file.
+    pub fn new(path: Option<PathBuf>) -> anyhow::Result<Self> {
+        let config = match &path {
+            Some(path) => ReloadableConfig::load(path)?,
+            None => ReloadableConfig::default(),
+        };
+        let (sender, receiver) = watch::channel(Arc::new(config));
+        Ok(Self {
+            path,
+            sender,
+            receiver,
+        })
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    pub fn current(&self) -> Arc<ReloadableConfig> {
+        self.receiver.borrow().clone()
+    }
+
+    pub fn subscribe(&self) -> watch::Receiver<Arc<ReloadableConfig>> {
+        self.receiver.clone()
+    }
+
+    /// Read the config file again. Invalid config is rejected as a whole and the current one stays.
+    pub fn reload(&self) -> anyhow::Result<Arc<ReloadableConfig>> {
+        let path = self
+            .path
+            .as_ref()
+            .ok_or_else(|| format_err!("no config file to reload"))?;
+        let config = Arc::new(ReloadableConfig::load(path)?);
+        self.sender.send(config.clone())?;
+        Ok(config)
+    }
+
+    /// Reload config on every SIGHUP for as long as the process runs.
+    pub fn reload_on_sighup(self: Arc<Self>) -> anyhow::Result<()> {
+        let mut hangups = signal(SignalKind::hangup())?;
+        tokio::spawn(async move {
+            while hangups.recv().await.is_some() {
+                match self.reload() {
+                    Ok(_) => info!("Reloaded config"),
+                    Err(e) => warn!("Failed to reload config: {:#}", e),
+                }
+            }
+        });
+        Ok(())
+    }
+}
+
+/// Handle of the reloadable log filter, which must be the first layer of the subscriber.
+pub type LogFilterHandle = reload::Handle<EnvFilter, Registry>;
+
+/// Apply log filter of the current and every reloaded config, falling back to `startup_filter`
+/// if it is not set.
+pub fn follow_log_filter(
+    reloader: &ConfigReloader,
+    handle: LogFilterHandle,
+    startup_filter: String,
+) {
+    let mut config = reloader.subscribe();
+    tokio::spawn(async move {
+        loop {
+            let directives = config
+                .borrow()
+                .log
+                .clone()
+                .unwrap_or_else(|| startup_filter.clone());
+            // Directives are validated when the config is loaded.
+            if let Err(e) = handle.reload(EnvFilter::new(&directives)) {
+                warn!("Failed to apply log filter {:?}: {}", directives, e);
+            }
+
+            if config.changed().await.is_err() {
+                break;
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn reload_config() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        write!(
+            file,
+            r#"
+log = "akula=debug"
+
+[rpc]
+maxLogsPageSize = 500
+"#
+        )
+        .unwrap();
+
+        let reloader = ConfigReloader::new(Some(file.path().to_path_buf())).unwrap();
+        let changes = reloader.subscribe();
+        let config = reloader.current();
+        assert_eq!(config.log.as_deref(), Some("akula=debug"));
+        assert_eq!(config.rpc.max_logs_page_size, 500);
+        assert_eq!(
+            config.rpc.max_history_points,
+            RpcLimits::default().max_history_points
+        );
+        assert_eq!(config.txpool, AdmissionPolicy::default());
+
+        // Invalid config is rejected and the current one is kept.
+        std::fs::write(file.path(), "log = \"akula=nonsense=\"").unwrap();
+        assert!(reloader.reload().is_err());
+        assert_eq!(reloader.current(), config);
+
+        std::fs::write(file.path(), "[txpool]\nmaxNonceGap = 16").unwrap();
+        let reloaded = reloader.reload().unwrap();
+        assert_eq!(*changes.borrow(), reloaded);
+        assert_eq!(reloaded.log, None);
+        assert_eq!(reloaded.rpc, RpcLimits::default());
+        assert_eq!(reloaded.txpool.max_nonce_gap, Some(16));
+
+        assert!(ConfigReloader::new(None).unwrap().reload().is_err());
+    }
+}
@@ -0,0 +1,447 @@
+use crate::{chain::protocol_param::param, models::*, util::*};
+use anyhow::{bail, ensure, Context};
+use bytes::Bytes;
+use serde::{de::IntoDeserializer, Deserialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::Path,
+    time::Duration,
+};
+
+/// Genesis file in the format of `geth init`, as written by Hive and most devnet tooling.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GethGenesis {
+    config: GethChainConfig,
+    #[serde(default, deserialize_with = "deserialize_hexstr_as_u64")]
+    nonce: u64,
+    #[serde(default, deserialize_with = "deserialize_hexstr_as_u64")]
+    timestamp: u64,
+    #[serde(default, deserialize_with = "hexbytes::deserialize")]
+    extra_data: Bytes,
+    #[serde(deserialize_with = "deserialize_hexstr_as_u64")]
+    gas_limit: u64,
+    #[serde(deserialize_with = "deserialize_hexstr_as_u256")]
+    difficulty: U256,
+    #[serde(default)]
+    mix_hash: H256,
+    #[serde(default)]
+    coinbase: Address,
+    #[serde(default, deserialize_with = "deserialize_hexstr_as_u64")]
+    number: u64,
+    #[serde(default, deserialize_with = "deserialize_opt_hexstr_as_u256")]
+    base_fee_per_gas: Option<U256>,
+    #[serde(default)]
+    alloc: HashMap<Address, GethAccount>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GethChainConfig {
+    chain_id: u64,
+    homestead_block: Option<BlockNumber>,
+    #[serde(default)]
+    dao_fork_support: bool,
+    eip150_block: Option<BlockNumber>,
+    eip158_block: Option<BlockNumber>,
+    byzantium_block: Option<BlockNumber>,
+    constantinople_block: Option<BlockNumber>,
+    petersburg_block: Option<BlockNumber>,
+    istanbul_block: Option<BlockNumber>,
+    muir_glacier_block: Option<BlockNumber>,
+    berlin_block: Option<BlockNumber>,
+    london_block: Option<BlockNumber>,
+    arrow_glacier_block: Option<BlockNumber>,
+    gray_glacier_block: Option<BlockNumber>,
+    shanghai_time: Option<u64>,
+    #[serde(default, deserialize_with = "deserialize_opt_number_as_u256")]
+    terminal_total_difficulty: Option<U256>,
+    clique: Option<GethCliqueConfig>,
+}
+
+#[derive(Deserialize)]
+struct GethCliqueConfig {
+    period: u64,
+    epoch: u64,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GethAccount {
+    #[serde(deserialize_with = "deserialize_hexstr_as_u256")]
+    balance: U256,
+    #[serde(default, deserialize_with = "hexbytes::deserialize")]
+    code: Bytes,
+    #[serde(default)]
+    storage: HashMap<H256, H256>,
+    #[serde(default, deserialize_with = "deserialize_hexstr_as_u64")]
+    nonce: u64,
+}
+
+fn deserialize_opt_hexstr_as_u256<'de, D>(deserializer: D) -> Result<Option<U256>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    deserialize_hexstr_as_u256(deserializer).map(Some)
+}
+
+/// Geth writes terminal total difficulty as a JSON number, which only fits `u64` without arbitrary
+/// precision. Larger values are accepted as decimal or hex strings.
+fn deserialize_opt_number_as_u256<'de, D>(deserializer: D) -> Result<Option<U256>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum NumberOrString {
+        Number(u64),
+        String(String),
+    }
+
+    Ok(Some(match NumberOrString::deserialize(deserializer)? {
+        NumberOrString::Number(n) => U256::from(n),
+        NumberOrString::String(s) => {
+            deserialize_hexstr_as_u256(IntoDeserializer::<D::Error>::into_deserializer(s))?
+        }
+    }))
+}
+
+/// Convert a geth-style genesis file into a chain spec named `name`.
+///
+/// Only what Akula's genesis can express is accepted: allocations with code, storage or
+/// nonces, the DAO fork and timestamp-based forks after genesis are rejected.
+pub fn from_geth_genesis(name: &str, json: &str) -> anyhow::Result<ChainSpec> {
+    let genesis: GethGenesis = serde_json::from_str(json)?;
+    let config = genesis.config;
+
+    ensure!(!config.dao_fork_support, "DAO fork is not supported");
+
+    let shanghai = match config.shanghai_time {
+        None => None,
+        Some(time) if time <= genesis.timestamp => Some(BlockNumber(genesis.number)),
+        Some(time) => bail!(
+            "Shanghai at timestamp {} after genesis is not supported",
+            time
+        ),
+    };
+    // Geth enables Petersburg along with Constantinople unless told otherwise.
+    let petersburg = config.petersburg_block.or(config.constantinople_block);
+
+    let (seal_verification, seal) = if let Some(clique) = config.clique {
+        let extra_data = &genesis.extra_data;
+        ensure!(
+            extra_data.len() >= 32 + 65 && (extra_data.len() - 32 - 65) % 20 == 0,
+            "invalid Clique genesis extra data"
+        );
+        let score = if genesis.difficulty == U256::from(BlockScore::NoTurn as u8) {
+            BlockScore::NoTurn
+        } else if genesis.difficulty == U256::from(BlockScore::InTurn as u8) {
+            BlockScore::InTurn
+        } else {
+            bail!("invalid Clique genesis difficulty {}", genesis.difficulty)
+        };
+        let seal = Seal::Clique {
+            vanity: H256::from_slice(&extra_data[..32]),
+            score,
+            signers: extra_data[32..extra_data.len() - 65]
+                .chunks(20)
+                .map(Address::from_slice)
+                .collect(),
+        };
+        ensure!(
+            seal.extra_data() == *extra_data,
+            "Clique genesis extra data must end with an empty seal"
+        );
+
+        (
+            SealVerificationParams::Clique {
+                period: Duration::from_secs(clique.period),
+                epoch: clique.epoch,
+            },
+            seal,
+        )
+    } else {
+        let mut block_reward = BTreeMap::new();
+        block_reward.insert(BlockNumber(0), U256::from(5 * ETHER));
+        let mut delays = BTreeMap::new();
+        for (block, reward, delay) in [
+            (config.byzantium_block, Some(3 * ETHER), 3_000_000),
+            (config.constantinople_block, Some(2 * ETHER), 5_000_000),
+            (config.muir_glacier_block, None, 9_000_000),
+            (config.london_block, None, 9_700_000),
+            (config.arrow_glacier_block, None, 10_700_000),
+            (config.gray_glacier_block, None, 11_400_000),
+        ] {
+            if let Some(block) = block {
+                if let Some(reward) = reward {
+                    block_reward.insert(block, U256::from(reward));
+                }
+                delays.insert(block, BlockNumber(delay));
+            }
+        }
+
+        (
+            SealVerificationParams::Ethash {
+                duration_limit: 13,
+                block_reward,
+                homestead_formula: config.homestead_block,
+                byzantium_formula: config.byzantium_block,
+                difficulty_bomb: Some(DifficultyBomb { delays }),
+                skip_pow_verification: false,
+            },
+            Seal::Ethash {
+                vanity: genesis.extra_data,
+                difficulty: genesis.difficulty,
+                nonce: H64::from_low_u64_be(genesis.nonce),
+                mix_hash: genesis.mix_hash,
+            },
+        )
+    };
+
+    if let Some(base_fee) = genesis.base_fee_per_gas {
+        ensure!(
+            config.london_block == Some(BlockNumber(genesis.number)),
+            "genesis base fee is only allowed with London at genesis"
+        );
+        ensure!(
+            base_fee == U256::from(param::INITIAL_BASE_FEE),
+            "genesis base fee must be {}",
+            param::INITIAL_BASE_FEE
+        );
+    }
+
+    let mut balances = HashMap::new();
+    for (address, account) in genesis.alloc {
+        ensure!(
+            account.code.is_empty() && account.storage.is_empty() && account.nonce == 0,
+            "allocation of {:?}: only balances are supported in genesis",
+            address
+        );
+        balances.insert(address, account.balance);
+    }
+
+    Ok(ChainSpec {
+        name: name.to_string(),
+        consensus: ConsensusParams {
+            seal_verification,
+            eip1559_block: config.london_block,
+            eip4844_block: None,
+            terminal_total_difficulty: config.terminal_total_difficulty,
+        },
+        upgrades: Upgrades {
+            homestead: config.homestead_block,
+            tangerine: config.eip150_block,
+            spurious: config.eip158_block,
+            byzantium: config.byzantium_block,
+            constantinople: config.constantinople_block,
+            petersburg,
+            istanbul: config.istanbul_block,
+            berlin: config.berlin_block,
+            london: config.london_block,
+            shanghai,
+        },
+        params: Params {
+            chain_id: ChainId(config.chain_id),
+            network_id: NetworkId(config.chain_id),
+            min_gas_limit: 5000,
+        },
+        genesis: Genesis {
+            number: BlockNumber(genesis.number),
+            author: genesis.coinbase,
+            gas_limit: genesis.gas_limit,
+            timestamp: genesis.timestamp,
+            seal,
+        },
+        contracts: BTreeMap::new(),
+        balances: if balances.is_empty() {
+            BTreeMap::new()
+        } else {
+            BTreeMap::from([(BlockNumber(genesis.number), balances)])
+        },
+        system_calls: BTreeMap::new(),
+        precompile_pricing: BTreeMap::new(),
+        reward_redirections: BTreeMap::new(),
+        p2p: P2PParams {
+            bootnodes: vec![],
+            preverified_hashes: vec![],
+        },
+    })
+}
+
+/// Load a chain spec from a file: Akula's own format if it has the `.ron` extension,
+/// otherwise a geth-style genesis named after the file.
+pub fn load(path: &Path) -> anyhow::Result<ChainSpec> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read chain spec {}", path.display()))?;
+    if path.extension().and_then(|e| e.to_str()) == Some("ron") {
+        return ron::from_str(&text)
+            .with_context(|| format!("invalid chain spec {}", path.display()));
+    }
+
+    let name = path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("genesis");
+    from_geth_genesis(name, &text)
+        .with_context(|| format!("invalid genesis file {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::genesis::GenesisState;
+    use hex_literal::hex;
+
+    #[test]
+    fn ethash_genesis() {
+        let spec = from_geth_genesis(
+            "hive",
+            r#"{
+                "config": {
+                    "chainId": 1337,
+                    "homesteadBlock": 0,
+                    "eip150Block": 0,
+                    "eip155Block": 0,
+                    "eip158Block": 0,
+                    "byzantiumBlock": 0,
+                    "constantinopleBlock": 0,
+                    "istanbulBlock": 0,
+                    "berlinBlock": 0,
+                    "londonBlock": 10,
+                    "terminalTotalDifficulty": 131072,
+                    "ethash": {}
+                },
+                "nonce": "0x0000000000000042",
+                "timestamp": "0x0",
+                "extraData": "0x",
+                "gasLimit": "0x1c9c380",
+                "difficulty": "0x20000",
+                "alloc": {
+                    "0x7e5f4552091a69125d5dfcb7b8c2659029395bdf": {
+                        "balance": "1000000000000000000"
+                    },
+                    "8a8eafb1cf62bfbeb1741769dae1a9dd47996192": {
+                        "balance": "0x1"
+                    }
+                }
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(spec.params.chain_id, ChainId(1337));
+        assert_eq!(spec.upgrades.petersburg, Some(BlockNumber(0)));
+        assert_eq!(spec.upgrades.london, Some(BlockNumber(10)));
+        assert_eq!(spec.consensus.eip1559_block, Some(BlockNumber(10)));
+        assert_eq!(spec.upgrades.shanghai, None);
+        assert_eq!(
+            spec.consensus.terminal_total_difficulty,
+            Some(U256::from(131_072_u64))
+        );
+        match &spec.consensus.seal_verification {
+            SealVerificationParams::Ethash {
+                block_reward,
+                difficulty_bomb,
+                ..
+            } => {
+                assert_eq!(
+                    block_reward,
+                    &BTreeMap::from([(BlockNumber(0), U256::from(2 * ETHER))])
+                );
+                assert_eq!(
+                    difficulty_bomb
+                        .as_ref()
+                        .unwrap()
+                        .get_delay_to(BlockNumber(10)),
+                    BlockNumber(9_700_000)
+                );
+            }
+            other => panic!("unexpected seal verification {:?}", other),
+        }
+        assert_eq!(spec.genesis.seal.nonce(), H64::from_low_u64_be(0x42));
+        assert_eq!(spec.genesis.seal.difficulty(), U256::from(0x20000_u64));
+        assert_eq!(
+            spec.balances[&BlockNumber(0)]
+                [&Address::from(hex!("7e5f4552091a69125d5dfcb7b8c2659029395bdf"))],
+            U256::from(ETHER)
+        );
+
+        // Matches the genesis block that the spec produces.
+        let genesis = GenesisState::new(spec);
+        let header = genesis.header(&genesis.initial_state());
+        assert_eq!(header.gas_limit, 30_000_000);
+        assert_eq!(header.base_fee_per_gas, None);
+    }
+
+    #[test]
+    fn clique_genesis() {
+        let signer = hex!("7e5f4552091a69125d5dfcb7b8c2659029395bdf");
+        let extra_data = format!(
+            "0x{}{}{}",
+            "00".repeat(32),
+            hex::encode(signer),
+            "00".repeat(65)
+        );
+        let json = format!(
+            r#"{{
+                "config": {{
+                    "chainId": 7,
+                    "londonBlock": 0,
+                    "shanghaiTime": 0,
+                    "clique": {{ "period": 5, "epoch": 30000 }}
+                }},
+                "extraData": "{}",
+                "gasLimit": "0x1c9c380",
+                "difficulty": "0x1",
+                "baseFeePerGas": "0x3b9aca00"
+            }}"#,
+            extra_data
+        );
+        let spec = from_geth_genesis("hive", &json).unwrap();
+
+        assert_eq!(
+            spec.consensus.seal_verification,
+            SealVerificationParams::Clique {
+                period: Duration::from_secs(5),
+                epoch: 30000,
+            }
+        );
+        assert_eq!(spec.upgrades.shanghai, Some(BlockNumber(0)));
+        assert_eq!(
+            spec.genesis.seal,
+            Seal::Clique {
+                vanity: H256::zero(),
+                score: BlockScore::NoTurn,
+                signers: vec![Address::from(signer)],
+            }
+        );
+
+        let genesis = GenesisState::new(spec);
+        let header = genesis.header(&genesis.initial_state());
+        assert_eq!(
+            header.base_fee_per_gas,
+            Some(U256::from(param::INITIAL_BASE_FEE))
+        );
+    }
+
+    #[test]
+    fn unsupported_genesis() {
+        for json in [
+            r#"{
+                "config": { "chainId": 1, "shanghaiTime": 100 },
+                "gasLimit": "0x1", "difficulty": "0x1"
+            }"#,
+            r#"{
+                "config": { "chainId": 1 },
+                "gasLimit": "0x1", "difficulty": "0x1",
+                "alloc": {
+                    "0x7e5f4552091a69125d5dfcb7b8c2659029395bdf": {
+                        "balance": "0x0",
+                        "code": "0x00"
+                    }
+                }
+            }"#,
+        ] {
+            assert!(from_geth_genesis("hive", json).is_err());
+        }
+    }
+}
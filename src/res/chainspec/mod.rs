@@ -1,3 +1,7 @@
+mod geth;
+
+pub use self::geth::{from_geth_genesis, load};
+
 use crate::models::ChainSpec;
 use once_cell::sync::Lazy;
 
@@ -0,0 +1,37 @@
+use super::error::RpcError;
+use crate::reload::{ConfigReloader, ReloadableConfig};
+use async_trait::async_trait;
+use jsonrpsee::{core::RpcResult, proc_macros::rpc};
+use std::sync::Arc;
+use tracing::*;
+
+#[rpc(server, namespace = "admin")]
+pub trait AdminApi {
+    /// Read the config file again, same as sending SIGHUP, and return the applied config.
+    #[method(name = "reloadConfig")]
+    async fn reload_config(&self) -> RpcResult<ReloadableConfig>;
+}
+
+pub struct AdminApiServerImpl {
+    pub config: Arc<ConfigReloader>,
+    pub admin_api: bool,
+}
+
+#[async_trait]
+impl AdminApiServer for AdminApiServerImpl {
+    async fn reload_config(&self) -> RpcResult<ReloadableConfig> {
+        if !self.admin_api {
+            return Err(RpcError::NotSupported("admin API is disabled".into()).into());
+        }
+        if self.config.path().is_none() {
+            return Err(RpcError::NotSupported("no config file to reload".into()).into());
+        }
+
+        let config = self
+            .config
+            .reload()
+            .map_err(|e| RpcError::InvalidParams(format!("{:#}", e)))?;
+        info!("Reloaded config");
+        Ok((*config).clone())
+    }
+}
@@ -0,0 +1,430 @@
+use super::{error::RpcError, types::*};
+use crate::{
+    accessors::logs,
+    execution::contract_gas::top_gas_consumers,
+    kv::{
+        prefetch::{walk_prefetched, DEFAULT_CHUNK_SIZE},
+        reader_pool::ReaderPool,
+        tables,
+        traits::*,
+    },
+    models::*,
+    reload::ReloadableConfig,
+    stagedsync::stages::*,
+    u256_to_h256,
+};
+use async_trait::async_trait;
+use jsonrpsee::{core::RpcResult, proc_macros::rpc};
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
+};
+use tokio::sync::watch;
+use tokio_stream::StreamExt;
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BalanceHistoryEntry {
+    pub block_number: BlockNumber,
+    pub balance: U256,
+    pub nonce: U64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StorageSlotDiff {
+    pub slot: H256,
+    pub before: H256,
+    pub after: H256,
+}
+
+/// Blocks `akula_getTopGasConsumers` looks back by default.
+const DEFAULT_GAS_CONSUMERS_BLOCKS: u64 = 10_000;
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GasConsumer {
+    pub address: Address,
+    pub gas_used: U64,
+}
+
+/// Logs `akula_getLogsPage` returns in one call unless asked for fewer.
+const DEFAULT_LOGS_PAGE_SIZE: u64 = 1_000;
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LogsPage {
+    pub logs: Vec<RpcLog>,
+    /// Pass to the next call with the same filter to get the next page, absent on the last page.
+    pub continuation: Option<String>,
+}
+
+/// Indices that are built by stages, reported by `akula_capabilities` if present.
+const INDEX_STAGES: &[StageId] = &[
+    ACCOUNT_HISTORY_INDEX,
+    STORAGE_HISTORY_INDEX,
+    LOG_INDEX,
+    CALL_TRACES,
+    TX_LOOKUP,
+];
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PruneMode {
+    /// History of every block is kept.
+    Archive,
+    /// History before `historyStart` is pruned.
+    Pruned,
+}
+
+/// What this node can serve, for clients to adapt their queries to.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeCapabilities {
+    /// Enabled RPC namespaces.
+    pub namespaces: Vec<String>,
+    pub prune_mode: PruneMode,
+    /// First block whose state changes are kept, historical state queries before it fail.
+    pub history_start: BlockNumber,
+    /// Blocks further behind head are not re-executed to compute receipts.
+    pub receipts_max_depth: U64,
+    /// Built indices and the blocks they cover.
+    pub indices: BTreeMap<String, BlockNumber>,
+    /// Whether `akula_getKeyPreimage` has data to serve.
+    pub preimages: bool,
+    /// Whether `akula_getTopGasConsumers` has data to serve.
+    pub contract_gas: bool,
+}
+
+#[rpc(server, namespace = "akula")]
+pub trait AkulaApi {
+    /// Decode raw transaction as accepted by `eth_sendRawTransaction`, without submitting it.
+    #[method(name = "decodeTransaction")]
+    fn decode_transaction(&self, raw: String) -> RpcResult<DecodedTransaction>;
+    /// Balance and nonce of `address` after every `step`-th block from `from_block` to `to_block`.
+    #[method(name = "getBalanceHistory")]
+    async fn get_balance_history(
+        &self,
+        address: Address,
+        from_block: BlockNumber,
+        to_block: BlockNumber,
+        step: U64,
+    ) -> RpcResult<Vec<BalanceHistoryEntry>>;
+    /// Balances of `addresses` after `block`, in the same order, read in one transaction.
+    #[method(name = "getBalances")]
+    async fn get_balances(&self, addresses: Vec<Address>, block: BlockId) -> RpcResult<Vec<U256>>;
+    /// Storage slots of `address` that differ between the states after `block_a` and `block_b`.
+    #[method(name = "getStorageDiff")]
+    async fn get_storage_diff(
+        &self,
+        address: Address,
+        block_a: BlockNumber,
+        block_b: BlockNumber,
+    ) -> RpcResult<Vec<StorageSlotDiff>>;
+    /// Contracts that used the most gas in the last `blocks` executed blocks.
+    ///
+    /// Only available if the node records them with `--execution-contract-gas-top`,
+    /// and only counts contracts that made the top of their execution batches.
+    #[method(name = "getTopGasConsumers")]
+    async fn get_top_gas_consumers(
+        &self,
+        count: U64,
+        blocks: Option<U64>,
+    ) -> RpcResult<Vec<GasConsumer>>;
+    /// `eth_getLogs` returning at most `page_size` logs and a token to request the rest with.
+    #[method(name = "getLogsPage")]
+    async fn get_logs_page(
+        &self,
+        filter: RpcLogFilter,
+        continuation: Option<String>,
+        page_size: Option<U64>,
+    ) -> RpcResult<LogsPage>;
+    /// Address or storage location whose keccak256 hash is `hash`, as used as a key of state trie.
+    ///
+    /// Only available if the node stores preimages with `--hash-state-preimages`.
+    #[method(name = "getKeyPreimage")]
+    async fn get_key_preimage(&self, hash: H256) -> RpcResult<Option<String>>;
+    /// Enabled namespaces, prune mode, history depth and available indices of this node.
+    #[method(name = "capabilities")]
+    async fn capabilities(&self) -> RpcResult<NodeCapabilities>;
+}
+
+pub struct AkulaApiServerImpl<DB>
+where
+    DB: KV,
+{
+    pub readers: Arc<ReaderPool<DB>>,
+    pub config: watch::Receiver<Arc<ReloadableConfig>>,
+    pub namespaces: Vec<String>,
+    pub receipts_max_depth: u64,
+}
+
+#[async_trait]
+impl<DB> AkulaApiServer for AkulaApiServerImpl<DB>
+where
+    DB: KV,
+{
+    fn decode_transaction(&self, raw: String) -> RpcResult<DecodedTransaction> {
+        let raw = hex::decode(raw.strip_prefix("0x").unwrap_or(&raw))
+            .map_err(|e| RpcError::InvalidParams(format!("invalid hex: {}", e)))?;
+
+        Ok(DecodedTransaction::decode(&raw).map_err(|e| RpcError::InvalidParams(e.to_string()))?)
+    }
+
+    async fn get_balance_history(
+        &self,
+        address: Address,
+        from_block: BlockNumber,
+        to_block: BlockNumber,
+        step: U64,
+    ) -> RpcResult<Vec<BalanceHistoryEntry>> {
+        let step = step.as_u64();
+        if step == 0 {
+            return Err(RpcError::InvalidParams("step must be positive".into()).into());
+        }
+        if to_block < from_block {
+            return Err(RpcError::InvalidParams("toBlock is before fromBlock".into()).into());
+        }
+        let limits = self.config.borrow().rpc;
+        if (*to_block - *from_block) / step >= limits.max_history_points {
+            return Err(RpcError::LimitExceeded(format!(
+                "range yields more than {} points, increase step",
+                limits.max_history_points
+            ))
+            .into());
+        }
+
+        Ok(crate::accessors::state::account::read_history(
+            &*self.readers.get().await?,
+            address,
+            from_block..=to_block,
+            step,
+        )
+        .await?
+        .into_iter()
+        .map(|(block_number, account)| {
+            let account = account.unwrap_or_default();
+            BalanceHistoryEntry {
+                block_number,
+                balance: account.balance,
+                nonce: account.nonce.into(),
+            }
+        })
+        .collect())
+    }
+
+    async fn get_balances(&self, addresses: Vec<Address>, block: BlockId) -> RpcResult<Vec<U256>> {
+        let limits = self.config.borrow().rpc;
+        if addresses.len() as u64 > limits.max_balances {
+            return Err(RpcError::LimitExceeded(format!(
+                "more than {} addresses",
+                limits.max_balances
+            ))
+            .into());
+        }
+
+        let tx = self.readers.get().await?;
+        let block_number = match block {
+            BlockId::Tag(BlockTag::Latest | BlockTag::Pending) => None,
+            _ => {
+                let block_number = block.resolve(&*tx).await?;
+                if tx
+                    .cursor(tables::AccountChangeSet)
+                    .await?
+                    .first()
+                    .await?
+                    .map_or(false, |(history_start, _)| history_start > block_number)
+                {
+                    return Err(RpcError::Unavailable(format!(
+                        "history of block {} is frozen, use eth_getBalance",
+                        block_number
+                    ))
+                    .into());
+                }
+                Some(block_number)
+            }
+        };
+
+        Ok(
+            crate::accessors::state::account::read_many(&*tx, &addresses, block_number)
+                .await?
+                .into_iter()
+                .map(|account| account.map_or(U256::ZERO, |account| account.balance))
+                .collect(),
+        )
+    }
+
+    async fn get_storage_diff(
+        &self,
+        address: Address,
+        block_a: BlockNumber,
+        block_b: BlockNumber,
+    ) -> RpcResult<Vec<StorageSlotDiff>> {
+        if block_b < block_a {
+            return Err(RpcError::InvalidParams("blockB is before blockA".into()).into());
+        }
+        let limits = self.config.borrow().rpc;
+        if *block_b - *block_a > limits.max_storage_diff_blocks {
+            return Err(RpcError::LimitExceeded(format!(
+                "range spans more than {} blocks",
+                limits.max_storage_diff_blocks
+            ))
+            .into());
+        }
+
+        Ok(crate::accessors::state::changeset::storage_between(
+            &*self.readers.get().await?,
+            address,
+            block_a,
+            block_b,
+        )
+        .await?
+        .into_iter()
+        .map(|(slot, (before, after))| StorageSlotDiff {
+            slot,
+            before: u256_to_h256(before),
+            after: u256_to_h256(after),
+        })
+        .collect())
+    }
+
+    async fn get_top_gas_consumers(
+        &self,
+        count: U64,
+        blocks: Option<U64>,
+    ) -> RpcResult<Vec<GasConsumer>> {
+        let blocks = blocks.map_or(DEFAULT_GAS_CONSUMERS_BLOCKS, |blocks| blocks.as_u64());
+        let limits = self.config.borrow().rpc;
+        if blocks > limits.max_gas_consumers_blocks {
+            return Err(RpcError::LimitExceeded(format!(
+                "range spans more than {} blocks",
+                limits.max_gas_consumers_blocks
+            ))
+            .into());
+        }
+
+        let tx = self.readers.get().await?;
+        let executed = EXECUTION
+            .get_progress(&*tx)
+            .await?
+            .unwrap_or(BlockNumber(0));
+        let from = BlockNumber((executed.0 + 1).saturating_sub(blocks));
+
+        let mut gas_used = HashMap::<Address, u64>::new();
+        let mut walker = walk_prefetched(
+            tx,
+            tables::ContractGas,
+            Some((from, Address::zero())),
+            DEFAULT_CHUNK_SIZE,
+        );
+        while let Some(((_, address), gas)) = walker.try_next().await? {
+            *gas_used.entry(address).or_default() += gas;
+        }
+
+        Ok(top_gas_consumers(gas_used, count.as_usize())
+            .into_iter()
+            .map(|(address, gas_used)| GasConsumer {
+                address,
+                gas_used: gas_used.into(),
+            })
+            .collect())
+    }
+
+    async fn get_logs_page(
+        &self,
+        filter: RpcLogFilter,
+        continuation: Option<String>,
+        page_size: Option<U64>,
+    ) -> RpcResult<LogsPage> {
+        let page_size = page_size.map_or(DEFAULT_LOGS_PAGE_SIZE, |size| size.as_u64());
+        if page_size == 0 {
+            return Err(RpcError::InvalidParams("page size must be positive".into()).into());
+        }
+        let limits = self.config.borrow().rpc;
+        if page_size > limits.max_logs_page_size {
+            return Err(RpcError::LimitExceeded(format!(
+                "page size is larger than {}",
+                limits.max_logs_page_size
+            ))
+            .into());
+        }
+
+        let tx = self.readers.get().await?;
+        let head = FINISH.get_progress(&*tx).await?.unwrap_or(BlockNumber(0));
+        let (from_block, to_block) = filter.block_range(&*tx, head).await?;
+        let from = match continuation {
+            Some(token) => {
+                let from = logs::LogPosition::from_token(&token)
+                    .map_err(|e| RpcError::InvalidParams(e.to_string()))?;
+                if from.block_number < from_block {
+                    return Err(RpcError::InvalidParams(
+                        "continuation token does not belong to the filter".into(),
+                    )
+                    .into());
+                }
+                from
+            }
+            None => logs::LogPosition::start_of(from_block),
+        };
+
+        let (found, next) = logs::read_page(
+            &*tx,
+            &filter.log_filter(),
+            from,
+            to_block,
+            page_size as usize,
+        )
+        .await?;
+
+        Ok(LogsPage {
+            logs: rpc_logs(&*tx, found).await?,
+            continuation: next.map(logs::LogPosition::to_token),
+        })
+    }
+
+    async fn get_key_preimage(&self, hash: H256) -> RpcResult<Option<String>> {
+        Ok(self
+            .readers
+            .get()
+            .await?
+            .get(tables::Preimage, hash)
+            .await?
+            .map(|preimage| format!("0x{}", hex::encode(preimage))))
+    }
+
+    async fn capabilities(&self) -> RpcResult<NodeCapabilities> {
+        let tx = self.readers.get().await?;
+
+        // Change sets of genesis are never written, so archive history starts at block 1.
+        let (prune_mode, history_start) =
+            match tx.cursor(tables::AccountChangeSet).await?.first().await? {
+                Some((block_number, _)) if block_number > BlockNumber(1) => {
+                    (PruneMode::Pruned, block_number)
+                }
+                _ => (PruneMode::Archive, BlockNumber(0)),
+            };
+
+        let mut indices = BTreeMap::new();
+        for stage in INDEX_STAGES {
+            if let Some(progress) = stage.get_progress(&*tx).await? {
+                indices.insert(stage.0.to_string(), progress);
+            }
+        }
+
+        Ok(NodeCapabilities {
+            namespaces: self.namespaces.clone(),
+            prune_mode,
+            history_start,
+            receipts_max_depth: self.receipts_max_depth.into(),
+            indices,
+            preimages: tx.cursor(tables::Preimage).await?.first().await?.is_some(),
+            contract_gas: tx
+                .cursor(tables::ContractGas)
+                .await?
+                .first()
+                .await?
+                .is_some(),
+        })
+    }
+}
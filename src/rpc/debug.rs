@@ -0,0 +1,134 @@
+use super::{error::RpcError, types::CallRequest};
+use crate::{
+    execution::{
+        call_tracer::CallFrame,
+        erc4337::ValidationViolation,
+        replay::{TraceOptions, TransactionTrace},
+    },
+    hexbytes,
+    kv::{reader_pool::ReaderPool, traits::*},
+    models::*,
+    stagedsync::stages::FINISH,
+};
+use async_trait::async_trait;
+use bytes::Bytes;
+use jsonrpsee::{core::RpcResult, proc_macros::rpc};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SimulateValidationRequest {
+    pub entry_point: Address,
+    pub sender: Address,
+    /// Encoded `simulateValidation` call.
+    #[serde(with = "hexbytes")]
+    pub data: Bytes,
+    pub gas: Option<U64>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SimulateValidationResult {
+    pub status: String,
+    #[serde(with = "hexbytes")]
+    pub return_data: Bytes,
+    pub violations: Vec<ValidationViolation>,
+}
+
+#[rpc(server, namespace = "debug")]
+pub trait DebugApi {
+    #[method(name = "simulateValidation")]
+    async fn simulate_validation(
+        &self,
+        request: SimulateValidationRequest,
+    ) -> RpcResult<SimulateValidationResult>;
+    /// Call tree of the message executed on top of `block_number` (latest by default),
+    /// with function selectors and decoded revert reasons.
+    #[method(name = "traceCall")]
+    async fn trace_call(
+        &self,
+        request: CallRequest,
+        block_number: Option<BlockNumber>,
+    ) -> RpcResult<CallFrame>;
+    /// Trace of a canonical transaction re-executed in its block, in the format of geth's
+    /// struct logger, `callTracer` or `prestateTracer`.
+    #[method(name = "traceTransaction")]
+    async fn trace_transaction(
+        &self,
+        hash: H256,
+        options: Option<TraceOptions>,
+    ) -> RpcResult<Option<TransactionTrace>>;
+}
+
+pub struct DebugApiServerImpl<DB>
+where
+    DB: KV,
+{
+    pub readers: Arc<ReaderPool<DB>>,
+    pub bundler_api: bool,
+}
+
+#[async_trait]
+impl<DB> DebugApiServer for DebugApiServerImpl<DB>
+where
+    DB: KV,
+{
+    async fn simulate_validation(
+        &self,
+        request: SimulateValidationRequest,
+    ) -> RpcResult<SimulateValidationResult> {
+        if !self.bundler_api {
+            return Err(RpcError::NotSupported("bundler API is disabled".into()).into());
+        }
+
+        let tx = self.readers.get().await?;
+        let block_number = FINISH.get_progress(&*tx).await?.unwrap_or(BlockNumber(0));
+
+        let res = crate::execution::erc4337::simulate_validation(
+            &*tx,
+            block_number,
+            request.entry_point,
+            request.sender,
+            request.data,
+            request.gas.map(|gas| gas.as_u64()).unwrap_or(10_000_000),
+        )
+        .await?;
+
+        Ok(SimulateValidationResult {
+            status: format!("{:?}", res.status_code),
+            return_data: res.output,
+            violations: res.violations,
+        })
+    }
+
+    async fn trace_call(
+        &self,
+        request: CallRequest,
+        block_number: Option<BlockNumber>,
+    ) -> RpcResult<CallFrame> {
+        let tx = self.readers.get().await?;
+        let block_number = match block_number {
+            Some(block_number) => block_number,
+            None => FINISH.get_progress(&*tx).await?.unwrap_or(BlockNumber(0)),
+        };
+
+        Ok(
+            crate::execution::call_tracer::trace_call(&*tx, block_number, &request.into_message())
+                .await?,
+        )
+    }
+
+    async fn trace_transaction(
+        &self,
+        hash: H256,
+        options: Option<TraceOptions>,
+    ) -> RpcResult<Option<TransactionTrace>> {
+        let tx = self.readers.get().await?;
+
+        Ok(
+            crate::execution::replay::trace_transaction(&*tx, hash, options.unwrap_or_default())
+                .await?,
+        )
+    }
+}
@@ -0,0 +1,84 @@
+use crate::execution::{call_tracer::Reverted, receipts::ReceiptsError};
+use jsonrpsee::types::error::CallError;
+
+/// Errors returned to RPC clients, with EIP-1474 codes and geth-compatible `error.data`.
+///
+/// Errors that are not classified here keep the generic `-32000` server error code.
+#[derive(Debug)]
+pub enum RpcError {
+    /// Execution ended with `REVERT`, its output is returned as `error.data`.
+    Reverted(Reverted),
+    /// Malformed or inconsistent request parameters.
+    InvalidParams(String),
+    /// Requested block or transaction does not exist.
+    NotFound(String),
+    /// Requested data exists but is not available on this node, e.g. it is pruned.
+    Unavailable(String),
+    /// Request exceeds a limit of this node.
+    LimitExceeded(String),
+    /// Method is disabled on this node.
+    NotSupported(String),
+    /// Any other failure.
+    Server(anyhow::Error),
+}
+
+impl RpcError {
+    fn code(&self) -> i32 {
+        match self {
+            Self::Reverted(_) => 3,
+            Self::InvalidParams(_) => -32602,
+            Self::Server(_) => -32000,
+            Self::NotFound(_) => -32001,
+            Self::Unavailable(_) => -32002,
+            Self::NotSupported(_) => -32004,
+            Self::LimitExceeded(_) => -32005,
+        }
+    }
+}
+
+impl std::fmt::Display for RpcError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Reverted(reverted) => write!(f, "{}", reverted),
+            Self::InvalidParams(message)
+            | Self::NotFound(message)
+            | Self::Unavailable(message)
+            | Self::LimitExceeded(message)
+            | Self::NotSupported(message) => write!(f, "{}", message),
+            Self::Server(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for RpcError {}
+
+impl From<anyhow::Error> for RpcError {
+    fn from(e: anyhow::Error) -> Self {
+        let e = match e.downcast::<Reverted>() {
+            Ok(reverted) => return Self::Reverted(reverted),
+            Err(e) => e,
+        };
+        match e.downcast::<ReceiptsError>() {
+            Ok(e @ ReceiptsError::Pruned { .. }) => Self::Unavailable(e.to_string()),
+            Ok(e @ ReceiptsError::TooDeep { .. }) => Self::LimitExceeded(e.to_string()),
+            Err(e) => Self::Server(e),
+        }
+    }
+}
+
+impl From<RpcError> for jsonrpsee::core::Error {
+    fn from(e: RpcError) -> Self {
+        // Clients decode custom errors from the hex-encoded revert data, like with geth.
+        let data = match &e {
+            RpcError::Reverted(Reverted { output }) => {
+                serde_json::value::to_raw_value(&format!("0x{}", hex::encode(output))).ok()
+            }
+            _ => None,
+        };
+        Self::Call(CallError::Custom {
+            code: e.code(),
+            message: e.to_string(),
+            data,
+        })
+    }
+}
@@ -0,0 +1,705 @@
+use super::{error::RpcError, miner::MinerConfig, types::*};
+use crate::{
+    accessors::{canonical_cache::CanonicalCache, chain},
+    execution::{
+        receipts::ReceiptsCache,
+        simulate::{BlockStateCalls, SimulatedBlock},
+    },
+    hexbytes,
+    kv::{reader_pool::ReaderPool, tables, traits::*},
+    models::*,
+    sentry::messages::ETH_PROTOCOL_VERSION,
+    stagedsync::stages::FINISH,
+    state::history_files::HistoryFiles,
+    txpool::pool::TxPool,
+};
+use anyhow::format_err;
+use async_trait::async_trait;
+use bytes::Bytes;
+use ethereum_interfaces::txpool::{txpool_client::TxpoolClient, NonceRequest};
+use jsonrpsee::{core::RpcResult, proc_macros::rpc};
+use parking_lot::{Mutex, RwLock};
+use serde::{Deserialize, Serialize};
+use std::{path::PathBuf, sync::Arc};
+use tokio::sync::broadcast;
+use tonic::transport::Channel;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SimulatePayload {
+    pub block_state_calls: Vec<BlockStateCalls>,
+    #[serde(default)]
+    pub validation: bool,
+}
+
+/// Transaction to be filled, only sender is mandatory.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FillTransactionRequest {
+    pub from: Address,
+    pub to: Option<Address>,
+    pub gas: Option<U64>,
+    /// Makes legacy transaction, or EIP-2930 one if access list is set.
+    pub gas_price: Option<U256>,
+    pub max_fee_per_gas: Option<U256>,
+    pub max_priority_fee_per_gas: Option<U256>,
+    pub value: Option<U256>,
+    pub nonce: Option<U64>,
+    #[serde(default, with = "hexbytes", alias = "input")]
+    pub data: Bytes,
+    pub access_list: Option<Vec<RpcAccessListItem>>,
+    pub chain_id: Option<U64>,
+}
+
+/// Transaction ready to be signed.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RpcUnsignedTransaction {
+    #[serde(rename = "type")]
+    pub tx_type: U64,
+    pub chain_id: Option<U64>,
+    pub nonce: U64,
+    pub from: Address,
+    pub to: Option<Address>,
+    pub value: U256,
+    pub gas: U64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gas_price: Option<U256>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_fee_per_gas: Option<U256>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_priority_fee_per_gas: Option<U256>,
+    #[serde(with = "hexbytes")]
+    pub input: Bytes,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub access_list: Option<Vec<RpcAccessListItem>>,
+}
+
+impl RpcUnsignedTransaction {
+    fn new(message: &Message, from: Address) -> Self {
+        let is_eip1559 = matches!(message.tx_type(), TxType::EIP1559 | TxType::EIP4844);
+
+        Self {
+            tx_type: (message.tx_type() as u8).into(),
+            chain_id: message.chain_id().map(|chain_id| chain_id.0.into()),
+            nonce: message.nonce().into(),
+            from,
+            to: match message.action() {
+                TransactionAction::Call(to) => Some(to),
+                TransactionAction::Create => None,
+            },
+            value: message.value(),
+            gas: message.gas_limit().into(),
+            gas_price: (!is_eip1559).then(|| message.max_fee_per_gas()),
+            max_fee_per_gas: is_eip1559.then(|| message.max_fee_per_gas()),
+            max_priority_fee_per_gas: is_eip1559.then(|| message.max_priority_fee_per_gas()),
+            input: message.input().clone(),
+            access_list: (message.tx_type() != TxType::Legacy).then(|| {
+                message
+                    .access_list()
+                    .iter()
+                    .map(|item| RpcAccessListItem {
+                        address: item.address,
+                        storage_keys: item.slots.clone(),
+                    })
+                    .collect()
+            }),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct FilledTransaction {
+    /// RLP of the unsigned transaction, as hashed for signing.
+    #[serde(with = "hexbytes")]
+    pub raw: Bytes,
+    pub tx: RpcUnsignedTransaction,
+}
+
+#[rpc(server, namespace = "eth")]
+pub trait EthApi {
+    #[method(name = "chainId")]
+    async fn chain_id(&self) -> RpcResult<U64>;
+    /// Latest eth protocol version spoken by this node.
+    #[method(name = "protocolVersion")]
+    async fn protocol_version(&self) -> RpcResult<U64>;
+    #[method(name = "blockNumber")]
+    async fn block_number(&self) -> RpcResult<BlockNumber>;
+    #[method(name = "coinbase")]
+    async fn coinbase(&self) -> RpcResult<Address>;
+    #[method(name = "getBalance")]
+    async fn get_balance(&self, address: Address, block: BlockId) -> RpcResult<U256>;
+    /// For `pending`, also counts transactions of the sender queued in the transaction pool.
+    #[method(name = "getTransactionCount")]
+    async fn get_transaction_count(&self, address: Address, block: BlockId) -> RpcResult<U64>;
+    #[method(name = "getBlockByHash")]
+    async fn get_block_by_hash(
+        &self,
+        hash: H256,
+        full_tx_objects: bool,
+    ) -> RpcResult<Option<RpcBlock>>;
+    #[method(name = "getTransactionByHash")]
+    async fn get_transaction_by_hash(&self, hash: H256) -> RpcResult<Option<RpcTransaction>>;
+    /// Receipt of a canonical transaction, by re-executing its block unless it is cached.
+    #[method(name = "getTransactionReceipt")]
+    async fn get_transaction_receipt(&self, hash: H256) -> RpcResult<Option<RpcReceipt>>;
+    #[method(name = "getUncleByBlockHashAndIndex")]
+    async fn get_uncle_by_block_hash_and_index(
+        &self,
+        hash: H256,
+        index: U64,
+    ) -> RpcResult<Option<RpcBlock>>;
+    #[method(name = "getUncleByBlockNumberAndIndex")]
+    async fn get_uncle_by_block_number_and_index(
+        &self,
+        block: BlockId,
+        index: U64,
+    ) -> RpcResult<Option<RpcBlock>>;
+    #[method(name = "getUncleCountByBlockHash")]
+    async fn get_uncle_count_by_block_hash(&self, hash: H256) -> RpcResult<Option<U64>>;
+    #[method(name = "getUncleCountByBlockNumber")]
+    async fn get_uncle_count_by_block_number(&self, block: BlockId) -> RpcResult<Option<U64>>;
+    /// Output of the message executed on top of `block` (latest by default), without fees.
+    #[method(name = "call")]
+    async fn call(&self, request: CallRequest, block: Option<BlockId>) -> RpcResult<String>;
+    /// Lowest gas limit the message succeeds with on top of `block` (latest by default),
+    /// up to the block gas limit unless `gas` is given.
+    #[method(name = "estimateGas")]
+    async fn estimate_gas(&self, request: CallRequest, block: Option<BlockId>) -> RpcResult<U64>;
+    /// Execute calls in a sequence of blocks built on top of `block_number` (latest by default).
+    #[method(name = "simulateV1")]
+    async fn simulate_v1(
+        &self,
+        payload: SimulatePayload,
+        block_number: Option<BlockNumber>,
+    ) -> RpcResult<Vec<SimulatedBlock>>;
+    /// Fill in nonce, gas limit and fees of a transaction on top of the latest block,
+    /// for it to be signed elsewhere.
+    #[method(name = "fillTransaction")]
+    async fn fill_transaction(
+        &self,
+        request: FillTransactionRequest,
+    ) -> RpcResult<FilledTransaction>;
+    /// Validate transaction against the latest state and add it to the transaction pool.
+    #[method(name = "sendRawTransaction")]
+    async fn send_raw_transaction(&self, raw: String) -> RpcResult<H256>;
+}
+
+pub struct EthApiServerImpl<DB>
+where
+    DB: KV,
+{
+    pub readers: Arc<ReaderPool<DB>>,
+    pub canonical: Arc<CanonicalCache>,
+    pub txpool: Option<TxpoolClient<Channel>>,
+    pub pool: Arc<Mutex<TxPool>>,
+    pub receipts: Arc<ReceiptsCache>,
+    pub miner: Arc<RwLock<MinerConfig>>,
+    pub chain_id: ChainId,
+    pub history_dir: PathBuf,
+    pub history: Arc<RwLock<Arc<HistoryFiles>>>,
+    pub pending_transactions: broadcast::Sender<H256>,
+}
+
+impl<DB> EthApiServerImpl<DB>
+where
+    DB: KV,
+{
+    /// History files covering `block_number`, if it is frozen.
+    async fn history<'db, Tx: Transaction<'db>>(
+        &self,
+        tx: &Tx,
+        block_number: BlockNumber,
+    ) -> anyhow::Result<Arc<HistoryFiles>> {
+        let mut history = self.history.read().clone();
+        // Files of steps frozen since they were opened are picked up once their changesets
+        // are gone.
+        if history
+            .last_block()
+            .map_or(true, |last| last < block_number)
+            && tx
+                .cursor(tables::AccountChangeSet)
+                .await?
+                .first()
+                .await?
+                .map_or(false, |(history_start, _)| history_start > block_number)
+        {
+            history = Arc::new(HistoryFiles::open(&self.history_dir)?);
+            *self.history.write() = history.clone();
+        }
+
+        Ok(history)
+    }
+
+    /// Account at `block_number`, looked up in history files before changesets.
+    async fn read_account(
+        &self,
+        address: Address,
+        block_number: Option<BlockNumber>,
+    ) -> anyhow::Result<Option<Account>> {
+        let tx = self.readers.get().await?;
+        if let Some(block_number) = block_number {
+            let history = self.history(&*tx, block_number).await?;
+            if let Some(account) = history.account(address, block_number)? {
+                return Ok(account);
+            }
+        }
+
+        crate::accessors::state::account::read(&*tx, address, block_number).await
+    }
+
+    async fn canonical_hash(&self, block: BlockId) -> anyhow::Result<Option<H256>> {
+        let block_number = match (block, self.canonical.head()) {
+            (BlockId::Tag(BlockTag::Latest | BlockTag::Pending), Some(head)) => head,
+            (BlockId::Number(block_number), _) => block_number,
+            (BlockId::Quantity(block_number), _) => BlockNumber(block_number.as_u64()),
+            _ => {
+                let tx = self.readers.get().await?;
+                let block_number = block.resolve(&*tx).await?;
+                return chain::canonical_hash::read(&*tx, block_number).await;
+            }
+        };
+        if let Some(block) = self.canonical.by_number(block_number) {
+            return Ok(Some(block.hash));
+        }
+        chain::canonical_hash::read(&*self.readers.get().await?, block_number).await
+    }
+}
+
+#[async_trait]
+impl<DB> EthApiServer for EthApiServerImpl<DB>
+where
+    DB: KV,
+{
+    async fn chain_id(&self) -> RpcResult<U64> {
+        Ok(self.chain_id.0.into())
+    }
+
+    async fn protocol_version(&self) -> RpcResult<U64> {
+        Ok((ETH_PROTOCOL_VERSION as u64).into())
+    }
+
+    async fn block_number(&self) -> RpcResult<BlockNumber> {
+        if let Some(head) = self.canonical.head() {
+            return Ok(head);
+        }
+        Ok(FINISH
+            .get_progress(&*self.readers.get().await?)
+            .await?
+            .unwrap_or(BlockNumber(0)))
+    }
+
+    async fn coinbase(&self) -> RpcResult<Address> {
+        Ok(self
+            .miner
+            .read()
+            .etherbase
+            .ok_or_else(|| format_err!("etherbase must be explicitly specified"))?)
+    }
+
+    async fn get_balance(&self, address: Address, block: BlockId) -> RpcResult<U256> {
+        let block_number = match block {
+            BlockId::Tag(BlockTag::Latest | BlockTag::Pending) => None,
+            _ => Some(block.resolve(&*self.readers.get().await?).await?),
+        };
+
+        Ok(self
+            .read_account(address, block_number)
+            .await?
+            .map(|acc| acc.balance)
+            .unwrap_or(U256::ZERO))
+    }
+
+    async fn get_transaction_count(&self, address: Address, block: BlockId) -> RpcResult<U64> {
+        let block_number = match block {
+            BlockId::Number(block_number) => Some(block_number),
+            BlockId::Quantity(block_number) => Some(BlockNumber(block_number.as_u64())),
+            BlockId::Tag(BlockTag::Earliest) => Some(BlockNumber(0)),
+            BlockId::Tag(BlockTag::Latest | BlockTag::Pending) => None,
+            BlockId::Hash { .. } => Some(block.resolve(&*self.readers.get().await?).await?),
+        };
+
+        let mut nonce = self
+            .read_account(address, block_number)
+            .await?
+            .map(|acc| acc.nonce)
+            .unwrap_or(0);
+
+        if let (BlockId::Tag(BlockTag::Pending), Some(txpool)) = (block, &self.txpool) {
+            // Pool reports the highest nonce among the sender's transactions that can be executed in sequence.
+            let reply = txpool
+                .clone()
+                .nonce(NonceRequest {
+                    address: Some(address.into()),
+                })
+                .await
+                .map_err(|status| format_err!("txpool: {}", status))?
+                .into_inner();
+            if reply.found {
+                nonce = nonce.max(reply.nonce + 1);
+            }
+        }
+        if let BlockId::Tag(BlockTag::Pending) = block {
+            if let Some(next_nonce) = self.pool.lock().next_nonce(address) {
+                nonce = nonce.max(next_nonce);
+            }
+        }
+
+        Ok(nonce.into())
+    }
+
+    async fn get_block_by_hash(
+        &self,
+        hash: H256,
+        full_tx_objects: bool,
+    ) -> RpcResult<Option<RpcBlock>> {
+        let tx = self.readers.get().await?;
+        let (header, body, total_difficulty) = match self.canonical.by_hash(hash) {
+            Some(block) => {
+                let number = block.header.number;
+                match chain::block_body::read_without_senders(&*tx, hash, number).await? {
+                    Some(body) => (block.header, body, Some(block.total_difficulty)),
+                    None => return Ok(None),
+                }
+            }
+            None => match read_block(&*tx, hash).await? {
+                Some((header, body)) => {
+                    let total_difficulty = chain::td::read(&*tx, hash, header.number).await?;
+                    (header, body, total_difficulty)
+                }
+                None => return Ok(None),
+            },
+        };
+        let block_number = header.number;
+
+        let transactions = if full_tx_objects {
+            let mut senders = chain::tx_sender::read(&*tx, hash, block_number).await?;
+            if senders.len() != body.transactions.len() {
+                // Not recovered yet, or a frozen block served from snapshots.
+                senders = body
+                    .transactions
+                    .iter()
+                    .map(MessageWithSignature::recover_sender)
+                    .collect::<anyhow::Result<_>>()?;
+            }
+            RpcBlockTransactions::Full(
+                body.transactions
+                    .iter()
+                    .zip(senders)
+                    .enumerate()
+                    .map(|(i, (txn, sender))| RpcTransaction::new(&header, hash, i, txn, sender))
+                    .collect(),
+            )
+        } else {
+            RpcBlockTransactions::Hashes(body.transactions.iter().map(|txn| txn.hash()).collect())
+        };
+        let uncles = body.ommers.iter().map(|ommer| ommer.hash()).collect();
+        let size = rlp::encode(&Block {
+            header: header.clone(),
+            transactions: body.transactions,
+            ommers: body.ommers,
+            withdrawals: body.withdrawals,
+        })
+        .len();
+
+        Ok(Some(RpcBlock {
+            total_difficulty,
+            size: size.into(),
+            transactions,
+            uncles,
+            ..RpcBlock::from_header(header)
+        }))
+    }
+
+    async fn get_transaction_by_hash(&self, hash: H256) -> RpcResult<Option<RpcTransaction>> {
+        let tx = self.readers.get().await?;
+        let (block_number, block_hash, index, txn) =
+            match chain::canonical_tx::read(&*tx, hash).await? {
+                Some(found) => found,
+                None => return Ok(None),
+            };
+        let header = match self.canonical.by_hash(block_hash) {
+            Some(block) => block.header,
+            None => chain::header::read(&*tx, block_hash, block_number)
+                .await?
+                .ok_or_else(|| format_err!("header {}/{:?} not found", block_number, block_hash))?,
+        };
+        let sender = chain::tx_sender::read(&*tx, block_hash, block_number)
+            .await?
+            .get(index)
+            .copied()
+            .ok_or_else(|| format_err!("senders for block {} not recovered", block_number))?;
+
+        Ok(Some(RpcTransaction::new(
+            &header, block_hash, index, &txn, sender,
+        )))
+    }
+
+    async fn get_transaction_receipt(&self, hash: H256) -> RpcResult<Option<RpcReceipt>> {
+        let tx = self.readers.get().await?;
+        let (block_number, block_hash, index, txn) =
+            match chain::canonical_tx::read(&*tx, hash).await? {
+                Some(found) => found,
+                None => return Ok(None),
+            };
+        let header = chain::header::read(&*tx, block_hash, block_number)
+            .await?
+            .ok_or_else(|| format_err!("header {}/{:?} not found", block_number, block_hash))?;
+        let sender = chain::tx_sender::read(&*tx, block_hash, block_number)
+            .await?
+            .get(index)
+            .copied()
+            .ok_or_else(|| format_err!("senders for block {} not recovered", block_number))?;
+
+        let head = FINISH.get_progress(&*tx).await?.unwrap_or(BlockNumber(0));
+        let receipts = self
+            .receipts
+            .get(&*tx, head, block_hash, block_number)
+            .await
+            .map_err(RpcError::from)?;
+
+        Ok(Some(RpcReceipt::new(
+            &header, block_hash, index, &txn, sender, &receipts,
+        )))
+    }
+
+    async fn get_uncle_by_block_hash_and_index(
+        &self,
+        hash: H256,
+        index: U64,
+    ) -> RpcResult<Option<RpcBlock>> {
+        Ok(read_ommers(&*self.readers.get().await?, hash)
+            .await?
+            .and_then(|mut ommers| {
+                let index = index.as_usize();
+                (index < ommers.len()).then(|| RpcBlock::from_header(ommers.swap_remove(index)))
+            }))
+    }
+
+    async fn get_uncle_by_block_number_and_index(
+        &self,
+        block: BlockId,
+        index: U64,
+    ) -> RpcResult<Option<RpcBlock>> {
+        match self.canonical_hash(block).await? {
+            Some(hash) => self.get_uncle_by_block_hash_and_index(hash, index).await,
+            None => Ok(None),
+        }
+    }
+
+    async fn get_uncle_count_by_block_hash(&self, hash: H256) -> RpcResult<Option<U64>> {
+        Ok(read_ommers(&*self.readers.get().await?, hash)
+            .await?
+            .map(|ommers| ommers.len().into()))
+    }
+
+    async fn get_uncle_count_by_block_number(&self, block: BlockId) -> RpcResult<Option<U64>> {
+        match self.canonical_hash(block).await? {
+            Some(hash) => self.get_uncle_count_by_block_hash(hash).await,
+            None => Ok(None),
+        }
+    }
+
+    async fn simulate_v1(
+        &self,
+        payload: SimulatePayload,
+        block_number: Option<BlockNumber>,
+    ) -> RpcResult<Vec<SimulatedBlock>> {
+        let tx = self.readers.get().await?;
+        let block_number = match block_number {
+            Some(block_number) => block_number,
+            None => FINISH.get_progress(&*tx).await?.unwrap_or(BlockNumber(0)),
+        };
+
+        Ok(crate::execution::simulate::simulate_blocks(
+            &*tx,
+            block_number,
+            payload.block_state_calls,
+            payload.validation,
+        )
+        .await?)
+    }
+
+    async fn call(&self, request: CallRequest, block: Option<BlockId>) -> RpcResult<String> {
+        let tx = self.readers.get().await?;
+        let block_number = block
+            .unwrap_or(BlockId::Tag(BlockTag::Latest))
+            .resolve(&*tx)
+            .await?;
+        let history = self.history(&*tx, block_number).await?;
+
+        let output = crate::execution::estimate::call_at(
+            &*tx,
+            block_number,
+            Some(history),
+            &request.into_message(),
+        )
+        .await
+        .map_err(RpcError::from)?;
+
+        Ok(format!("0x{}", hex::encode(output)))
+    }
+
+    async fn estimate_gas(&self, request: CallRequest, block: Option<BlockId>) -> RpcResult<U64> {
+        let tx = self.readers.get().await?;
+        let block_number = block
+            .unwrap_or(BlockId::Tag(BlockTag::Latest))
+            .resolve(&*tx)
+            .await?;
+        let history = self.history(&*tx, block_number).await?;
+
+        // Without gas given, search up to the block gas limit.
+        let request = CallRequest {
+            gas: Some(request.gas.unwrap_or_else(|| u64::MAX.into())),
+            ..request
+        };
+        let gas = crate::execution::estimate::estimate_gas_at(
+            &*tx,
+            block_number,
+            Some(history),
+            &request.into_message(),
+        )
+        .await
+        .map_err(RpcError::from)?;
+
+        Ok(gas.into())
+    }
+
+    async fn fill_transaction(
+        &self,
+        request: FillTransactionRequest,
+    ) -> RpcResult<FilledTransaction> {
+        let nonce = match request.nonce {
+            Some(nonce) => nonce.as_u64(),
+            None => self
+                .get_transaction_count(request.from, BlockId::Tag(BlockTag::Pending))
+                .await?
+                .as_u64(),
+        };
+
+        let tx = self.readers.get().await?;
+        let block_number = FINISH.get_progress(&*tx).await?.unwrap_or(BlockNumber(0));
+        let chain_spec = chain::chain_spec::read(&*tx).await?;
+        let block_hash = chain::canonical_hash::read(&*tx, block_number)
+            .await?
+            .ok_or_else(|| RpcError::NotFound(format!("no canonical block {}", block_number)))?;
+        let header = chain::header::read(&*tx, block_hash, block_number)
+            .await?
+            .ok_or_else(|| format_err!("header {}/{:?} not found", block_number, block_hash))?;
+
+        let chain_id = chain_spec.params.chain_id;
+        if let Some(requested) = request.chain_id {
+            if requested.as_u64() != chain_id.0 {
+                return Err(RpcError::InvalidParams(format!(
+                    "chain id {} does not match {}",
+                    requested, chain_id.0
+                ))
+                .into());
+            }
+        }
+
+        let action = match request.to {
+            Some(to) => TransactionAction::Call(to),
+            None => TransactionAction::Create,
+        };
+        let value = request.value.unwrap_or(U256::ZERO);
+        let input = request.data;
+        let access_list = request.access_list.map(|access_list| {
+            access_list
+                .into_iter()
+                .map(|item| AccessListItem {
+                    address: item.address,
+                    slots: item.storage_keys,
+                })
+                .collect::<Vec<_>>()
+        });
+
+        let priority_fee = match request.max_priority_fee_per_gas.or(request.gas_price) {
+            Some(fee) => fee,
+            None => crate::execution::estimate::suggest_priority_fee(&*tx, block_number).await?,
+        };
+        let make_message = |gas_limit| match (request.gas_price, header.base_fee_per_gas) {
+            // London is active, base fee may double in the meantime.
+            (None, Some(base_fee_per_gas)) => Message::EIP1559 {
+                chain_id,
+                nonce,
+                max_priority_fee_per_gas: priority_fee,
+                max_fee_per_gas: request
+                    .max_fee_per_gas
+                    .unwrap_or(base_fee_per_gas * U256::from(2_u8) + priority_fee),
+                gas_limit,
+                action,
+                value,
+                input: input.clone(),
+                access_list: access_list.clone().unwrap_or_default(),
+            },
+            (gas_price, _) => match access_list.clone() {
+                Some(access_list) => Message::EIP2930 {
+                    chain_id,
+                    nonce,
+                    gas_price: gas_price.unwrap_or(priority_fee),
+                    gas_limit,
+                    action,
+                    value,
+                    input: input.clone(),
+                    access_list,
+                },
+                None => Message::Legacy {
+                    chain_id: Some(chain_id),
+                    nonce,
+                    gas_price: gas_price.unwrap_or(priority_fee),
+                    gas_limit,
+                    action,
+                    value,
+                    input: input.clone(),
+                },
+            },
+        };
+
+        let gas_limit = match request.gas {
+            Some(gas) => gas.as_u64(),
+            None => crate::execution::estimate::estimate_gas_at(
+                &*tx,
+                block_number,
+                None,
+                &MessageWithSender {
+                    message: make_message(header.gas_limit),
+                    sender: request.from,
+                },
+            )
+            .await
+            .map_err(RpcError::from)?,
+        };
+
+        let message = make_message(gas_limit);
+        Ok(FilledTransaction {
+            raw: message.signing_payload(),
+            tx: RpcUnsignedTransaction::new(&message, request.from),
+        })
+    }
+
+    async fn send_raw_transaction(&self, raw: String) -> RpcResult<H256> {
+        let raw = hex::decode(raw.strip_prefix("0x").unwrap_or(&raw))
+            .map_err(|e| RpcError::InvalidParams(format!("invalid hex: {}", e)))?;
+        let txn = MessageWithSignature::trie_decode(&raw)
+            .map_err(|e| RpcError::InvalidParams(format!("invalid transaction: {}", e)))?;
+        let sender = txn
+            .recover_sender()
+            .map_err(|e| RpcError::InvalidParams(format!("invalid sender: {}", e)))?;
+
+        let account =
+            crate::accessors::state::account::read(&*self.readers.get().await?, sender, None)
+                .await?
+                .unwrap_or_default();
+
+        let hash = self
+            .pool
+            .lock()
+            .add(txn, sender, account)
+            .map_err(|e| RpcError::Server(e.into()))?;
+        // Fails only if nobody is subscribed.
+        let _ = self.pending_transactions.send(hash);
+
+        Ok(hash)
+    }
+}
@@ -0,0 +1,186 @@
+use super::{error::RpcError, types::*};
+use crate::{
+    accessors::logs,
+    kv::{reader_pool::ReaderPool, traits::*},
+    models::*,
+    reload::ReloadableConfig,
+    stagedsync::stages::FINISH,
+};
+use async_trait::async_trait;
+use jsonrpsee::{core::RpcResult, proc_macros::rpc};
+use parking_lot::Mutex;
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use tokio::sync::watch;
+
+/// Log filters are uninstalled if not polled for this long.
+const FILTER_TIMEOUT: Duration = Duration::from_secs(300);
+
+#[rpc(server, namespace = "eth")]
+pub trait EthFilterApi {
+    /// Logs matching `filter`, found with the log index where it covers the range.
+    #[method(name = "getLogs")]
+    async fn get_logs(&self, filter: RpcLogFilter) -> RpcResult<Vec<RpcLog>>;
+    /// Install log filter, whose new logs are returned by `eth_getFilterChanges`.
+    #[method(name = "newFilter")]
+    async fn new_filter(&self, filter: RpcLogFilter) -> RpcResult<U64>;
+    /// Logs of blocks added since the filter was installed or last polled.
+    #[method(name = "getFilterChanges")]
+    async fn get_filter_changes(&self, id: U64) -> RpcResult<Vec<RpcLog>>;
+    /// All logs matching an installed filter, as `eth_getLogs` would return.
+    #[method(name = "getFilterLogs")]
+    async fn get_filter_logs(&self, id: U64) -> RpcResult<Vec<RpcLog>>;
+    #[method(name = "uninstallFilter")]
+    async fn uninstall_filter(&self, id: U64) -> RpcResult<bool>;
+}
+
+struct InstalledFilter {
+    filter: RpcLogFilter,
+    /// First block whose logs are not returned by `eth_getFilterChanges` yet.
+    next_block: BlockNumber,
+    last_poll: Instant,
+}
+
+pub struct EthFilterApiServerImpl<DB>
+where
+    DB: KV,
+{
+    readers: Arc<ReaderPool<DB>>,
+    config: watch::Receiver<Arc<ReloadableConfig>>,
+    filters: Mutex<HashMap<U64, InstalledFilter>>,
+    next_filter_id: AtomicU64,
+}
+
+impl<DB> EthFilterApiServerImpl<DB>
+where
+    DB: KV,
+{
+    pub fn new(
+        readers: Arc<ReaderPool<DB>>,
+        config: watch::Receiver<Arc<ReloadableConfig>>,
+    ) -> Self {
+        Self {
+            readers,
+            config,
+            filters: Default::default(),
+            next_filter_id: AtomicU64::new(1),
+        }
+    }
+
+    /// All logs matching `filter` in blocks `from_block..=to_block`, up to the configured limit.
+    async fn read_logs<'db, Tx: Transaction<'db>>(
+        &self,
+        tx: &Tx,
+        filter: &RpcLogFilter,
+        from_block: BlockNumber,
+        to_block: BlockNumber,
+    ) -> RpcResult<Vec<RpcLog>> {
+        let max_logs = self.config.borrow().rpc.max_logs;
+        let (found, next) = logs::read_page(
+            tx,
+            &filter.log_filter(),
+            logs::LogPosition::start_of(from_block),
+            to_block,
+            max_logs as usize,
+        )
+        .await?;
+        if next.is_some() {
+            return Err(RpcError::LimitExceeded(format!(
+                "query returns more than {} logs, use akula_getLogsPage",
+                max_logs
+            ))
+            .into());
+        }
+
+        Ok(rpc_logs(tx, found).await?)
+    }
+
+    fn filter_not_found(id: U64) -> RpcError {
+        RpcError::NotFound(format!("filter {:#x} not found", id))
+    }
+}
+
+#[async_trait]
+impl<DB> EthFilterApiServer for EthFilterApiServerImpl<DB>
+where
+    DB: KV,
+{
+    async fn get_logs(&self, filter: RpcLogFilter) -> RpcResult<Vec<RpcLog>> {
+        let tx = self.readers.get().await?;
+        let head = FINISH.get_progress(&*tx).await?.unwrap_or(BlockNumber(0));
+        let (from_block, to_block) = filter.block_range(&*tx, head).await?;
+
+        self.read_logs(&*tx, &filter, from_block, to_block).await
+    }
+
+    async fn new_filter(&self, filter: RpcLogFilter) -> RpcResult<U64> {
+        let tx = self.readers.get().await?;
+        let head = FINISH.get_progress(&*tx).await?.unwrap_or(BlockNumber(0));
+        // Validates the filter.
+        filter.block_range(&*tx, head).await?;
+
+        let id = U64::from(self.next_filter_id.fetch_add(1, Ordering::Relaxed));
+        let mut filters = self.filters.lock();
+        filters.retain(|_, installed| installed.last_poll.elapsed() < FILTER_TIMEOUT);
+        filters.insert(
+            id,
+            InstalledFilter {
+                filter,
+                next_block: head + 1,
+                last_poll: Instant::now(),
+            },
+        );
+
+        Ok(id)
+    }
+
+    async fn get_filter_changes(&self, id: U64) -> RpcResult<Vec<RpcLog>> {
+        let (filter, next_block) = self
+            .filters
+            .lock()
+            .get(&id)
+            .map(|installed| (installed.filter.clone(), installed.next_block))
+            .ok_or_else(|| Self::filter_not_found(id))?;
+
+        let tx = self.readers.get().await?;
+        let head = FINISH.get_progress(&*tx).await?.unwrap_or(BlockNumber(0));
+        let (from_block, to_block) = filter.block_range(&*tx, head).await?;
+        let from_block = from_block.max(next_block);
+        let logs = if from_block <= to_block {
+            self.read_logs(&*tx, &filter, from_block, to_block).await?
+        } else {
+            vec![]
+        };
+
+        if let Some(installed) = self.filters.lock().get_mut(&id) {
+            installed.next_block = installed.next_block.max(head + 1);
+            installed.last_poll = Instant::now();
+        }
+
+        Ok(logs)
+    }
+
+    async fn get_filter_logs(&self, id: U64) -> RpcResult<Vec<RpcLog>> {
+        let filter = self
+            .filters
+            .lock()
+            .get_mut(&id)
+            .map(|installed| {
+                installed.last_poll = Instant::now();
+                installed.filter.clone()
+            })
+            .ok_or_else(|| Self::filter_not_found(id))?;
+
+        self.get_logs(filter).await
+    }
+
+    async fn uninstall_filter(&self, id: U64) -> RpcResult<bool> {
+        Ok(self.filters.lock().remove(&id).is_some())
+    }
+}
@@ -0,0 +1,114 @@
+use super::error::RpcError;
+use crate::{crypto::TrieEncode, hexbytes, hexbytes_seq, models::*, txpool::pool::TxPool};
+use async_trait::async_trait;
+use bytes::Bytes;
+use jsonrpsee::{core::RpcResult, proc_macros::rpc};
+use parking_lot::{Mutex, RwLock};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// Maximum size of header extra data.
+pub const MAX_EXTRA_DATA_BYTES: usize = 32;
+
+/// Parameters of blocks built by this node, changed at runtime with the `miner` namespace.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MinerConfig {
+    pub etherbase: Option<Address>,
+    #[serde(with = "hexbytes")]
+    pub extra_data: Bytes,
+    pub min_tip: U256,
+}
+
+impl MinerConfig {
+    pub fn set_extra_data(&mut self, extra_data: Bytes) -> Result<(), RpcError> {
+        if extra_data.len() > MAX_EXTRA_DATA_BYTES {
+            return Err(RpcError::InvalidParams(format!(
+                "extra data is {} bytes, more than {}",
+                extra_data.len(),
+                MAX_EXTRA_DATA_BYTES
+            )));
+        }
+        self.extra_data = extra_data;
+        Ok(())
+    }
+}
+
+/// What the node fills blocks it builds with, see `miner_blockTemplate`.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockTemplate {
+    #[serde(with = "hexbytes")]
+    pub extra_data: Bytes,
+    /// Pending pool transactions, most profitable first, encoded as in blocks.
+    #[serde(with = "hexbytes_seq")]
+    pub transactions: Vec<Bytes>,
+}
+
+#[rpc(server, namespace = "miner")]
+pub trait MinerApi {
+    /// Current parameters of built blocks.
+    #[method(name = "getConfig")]
+    async fn get_config(&self) -> RpcResult<MinerConfig>;
+    #[method(name = "setEtherbase")]
+    async fn set_etherbase(&self, etherbase: Address) -> RpcResult<bool>;
+    /// Set extra data of built blocks, given as text.
+    #[method(name = "setExtra")]
+    async fn set_extra(&self, extra: String) -> RpcResult<bool>;
+    /// Set lowest priority fee of transactions included in built blocks and accepted into pool.
+    #[method(name = "setGasPrice")]
+    async fn set_gas_price(&self, min_tip: U256) -> RpcResult<bool>;
+    /// Pool transactions for a block with `base_fee_per_gas` and `gas_limit`, for the node to
+    /// build payloads with.
+    #[method(name = "blockTemplate")]
+    async fn block_template(
+        &self,
+        base_fee_per_gas: Option<U256>,
+        gas_limit: U64,
+    ) -> RpcResult<BlockTemplate>;
+}
+
+pub struct MinerApiServerImpl {
+    pub miner: Arc<RwLock<MinerConfig>>,
+    pub pool: Arc<Mutex<TxPool>>,
+}
+
+#[async_trait]
+impl MinerApiServer for MinerApiServerImpl {
+    async fn get_config(&self) -> RpcResult<MinerConfig> {
+        Ok(self.miner.read().clone())
+    }
+
+    async fn set_etherbase(&self, etherbase: Address) -> RpcResult<bool> {
+        self.miner.write().etherbase = Some(etherbase);
+        Ok(true)
+    }
+
+    async fn set_extra(&self, extra: String) -> RpcResult<bool> {
+        self.miner.write().set_extra_data(extra.into())?;
+        Ok(true)
+    }
+
+    async fn set_gas_price(&self, min_tip: U256) -> RpcResult<bool> {
+        self.miner.write().min_tip = min_tip;
+        self.pool.lock().set_min_tip(min_tip);
+        Ok(true)
+    }
+
+    async fn block_template(
+        &self,
+        base_fee_per_gas: Option<U256>,
+        gas_limit: U64,
+    ) -> RpcResult<BlockTemplate> {
+        Ok(BlockTemplate {
+            extra_data: self.miner.read().extra_data.clone(),
+            transactions: self
+                .pool
+                .lock()
+                .best(base_fee_per_gas, gas_limit.as_u64())
+                .into_iter()
+                .map(|tx| tx.transaction.trie_encode())
+                .collect(),
+        })
+    }
+}
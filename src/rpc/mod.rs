@@ -0,0 +1,29 @@
+//! JSON-RPC APIs served by `akula-rpc` from a read-only view of the database.
+//!
+//! Every namespace is a separate module merged into one server, shared types of requests and
+//! responses live in [`types`].
+
+pub mod admin;
+pub mod akula;
+pub mod debug;
+pub mod error;
+pub mod eth;
+pub mod filter;
+pub mod miner;
+pub mod pubsub;
+pub mod trace;
+pub mod txpool;
+pub mod types;
+
+pub use self::{
+    admin::{AdminApiServer, AdminApiServerImpl},
+    akula::{AkulaApiServer, AkulaApiServerImpl},
+    debug::{DebugApiServer, DebugApiServerImpl},
+    error::RpcError,
+    eth::{EthApiServer, EthApiServerImpl},
+    filter::{EthFilterApiServer, EthFilterApiServerImpl},
+    miner::{BlockTemplate, MinerApiServer, MinerApiServerImpl, MinerConfig},
+    pubsub::{EthPubSubApiServer, EthPubSubApiServerImpl},
+    trace::{TraceApiServer, TraceApiServerImpl},
+    txpool::{TxpoolApiServer, TxpoolApiServerImpl},
+};
@@ -0,0 +1,286 @@
+use super::types::*;
+use crate::{
+    accessors::{chain, logs},
+    kv::{reader_pool::ReaderPool, traits::*},
+    models::*,
+    stagedsync::stages::*,
+};
+use jsonrpsee::{
+    core::{server::rpc_module::SubscriptionSink, RpcResult},
+    proc_macros::rpc,
+};
+use serde::{Deserialize, Serialize};
+use std::{sync::Arc, time::Duration};
+use tokio::sync::{broadcast, watch};
+use tracing::*;
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SubscriptionKind {
+    NewHeads,
+    /// Logs of new blocks matching the filter given as second parameter.
+    Logs,
+    NewPendingTransactions,
+    Syncing,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "event", rename_all = "camelCase")]
+pub enum SyncEvent {
+    /// Stage advanced or was unwound to a new block.
+    #[serde(rename_all = "camelCase")]
+    StageProgress {
+        stage: &'static str,
+        block_number: BlockNumber,
+    },
+    /// Node started or stopped syncing.
+    #[serde(rename_all = "camelCase")]
+    Syncing {
+        syncing: bool,
+        current_block: BlockNumber,
+        highest_block: BlockNumber,
+    },
+}
+
+/// Sync events buffered for subscribers that fall behind.
+pub const SYNC_EVENTS_CAPACITY: usize = 256;
+
+/// New headers buffered for `newHeads` and `logs` subscribers that fall behind.
+pub const NEW_HEADS_CAPACITY: usize = 128;
+
+/// Hashes of new pool transactions buffered for subscribers that fall behind.
+pub const PENDING_TRANSACTIONS_CAPACITY: usize = 4096;
+
+/// Publish headers of blocks after `old_head` up to `new_head` to `newHeads` and `logs` subscribers.
+///
+/// If head moved back, e.g. after a reorg, only the new head is published.
+pub async fn publish_new_heads<'db, Tx: Transaction<'db>>(
+    tx: &Tx,
+    new_heads: &broadcast::Sender<BlockHeader>,
+    old_head: BlockNumber,
+    new_head: BlockNumber,
+) -> anyhow::Result<()> {
+    let from = if old_head < new_head {
+        (old_head + 1).max(BlockNumber(
+            new_head.0.saturating_sub(NEW_HEADS_CAPACITY as u64 - 1),
+        ))
+    } else {
+        new_head
+    };
+    for block_number in from.0..=new_head.0 {
+        let header = chain::header::read_canonical(tx, block_number).await?;
+        // Fails only if nobody is subscribed.
+        let _ = new_heads.send(header);
+    }
+
+    Ok(())
+}
+
+/// Stages whose progress is reported to `syncing` subscribers, in pipeline order.
+const SYNC_STAGES: &[StageId] = &[
+    HEADERS,
+    TOTAL_GAS_INDEX,
+    BLOCK_HASHES,
+    BODIES,
+    TOTAL_TX_INDEX,
+    SENDERS,
+    EXECUTION,
+    HASH_STATE,
+    INTERMEDIATE_HASHES,
+    LOG_INDEX,
+    CALL_TRACES,
+    FINISH,
+];
+
+/// Poll stage progress and publish changes as sync events.
+///
+/// Node is syncing while the last stage is behind the highest known header.
+pub async fn watch_sync_status<DB: KV>(
+    db: Arc<DB>,
+    events: broadcast::Sender<SyncEvent>,
+    status: watch::Sender<Option<SyncEvent>>,
+) {
+    let mut progress = vec![None; SYNC_STAGES.len()];
+    let mut syncing = None;
+    loop {
+        let res = async {
+            let tx = db.begin().await?;
+            for (stage, progress) in SYNC_STAGES.iter().zip(&mut progress) {
+                let new_progress = stage.get_progress(&tx).await?;
+                if new_progress != *progress {
+                    *progress = new_progress;
+                    if let Some(block_number) = new_progress {
+                        // Fails only if nobody is subscribed.
+                        let _ = events.send(SyncEvent::StageProgress {
+                            stage: stage.0,
+                            block_number,
+                        });
+                    }
+                }
+            }
+
+            let current_block = FINISH.get_progress(&tx).await?.unwrap_or(BlockNumber(0));
+            let highest_block = HEADERS.get_progress(&tx).await?.unwrap_or(BlockNumber(0));
+            let new_syncing = current_block < highest_block;
+            if syncing != Some(new_syncing) {
+                syncing = Some(new_syncing);
+                let event = SyncEvent::Syncing {
+                    syncing: new_syncing,
+                    current_block,
+                    highest_block,
+                };
+                let _ = events.send(event.clone());
+                let _ = status.send(Some(event));
+            }
+
+            Ok::<_, anyhow::Error>(())
+        }
+        .await;
+        if let Err(e) = res {
+            warn!("Failed to check sync status: {}", e);
+        }
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+}
+
+#[rpc(server, namespace = "eth")]
+pub trait EthPubSubApi {
+    #[subscription(
+        name = "subscribe" => "subscription",
+        unsubscribe = "unsubscribe",
+        item = serde_json::Value
+    )]
+    fn subscribe(&self, kind: SubscriptionKind, filter: Option<RpcLogFilter>);
+}
+
+pub struct EthPubSubApiServerImpl<DB>
+where
+    DB: KV,
+{
+    pub readers: Arc<ReaderPool<DB>>,
+    pub new_heads: broadcast::Sender<BlockHeader>,
+    pub pending_transactions: broadcast::Sender<H256>,
+    pub sync_events: broadcast::Sender<SyncEvent>,
+    pub sync_status: watch::Receiver<Option<SyncEvent>>,
+}
+
+/// Next item of a subscription, skipping items it lagged behind on. `None` once closed.
+async fn recv_subscribed<T: Clone>(
+    receiver: &mut broadcast::Receiver<T>,
+    kind: SubscriptionKind,
+) -> Option<T> {
+    loop {
+        match receiver.recv().await {
+            Ok(item) => return Some(item),
+            Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                debug!("{:?} subscriber lagged behind by {} items", kind, skipped);
+            }
+            Err(broadcast::error::RecvError::Closed) => return None,
+        }
+    }
+}
+
+impl<DB> EthPubSubApiServerImpl<DB>
+where
+    DB: KV,
+{
+    fn subscribe_new_heads(&self, mut sink: SubscriptionSink) {
+        let mut new_heads = self.new_heads.subscribe();
+        tokio::spawn(async move {
+            while let Some(header) =
+                recv_subscribed(&mut new_heads, SubscriptionKind::NewHeads).await
+            {
+                if sink.send(&RpcBlock::from_header(header)).is_err() {
+                    return;
+                }
+            }
+        });
+    }
+
+    fn subscribe_logs(&self, mut sink: SubscriptionSink, filter: RpcLogFilter) {
+        let readers = self.readers.clone();
+        let mut new_heads = self.new_heads.subscribe();
+        tokio::spawn(async move {
+            let log_filter = filter.log_filter();
+            while let Some(header) = recv_subscribed(&mut new_heads, SubscriptionKind::Logs).await {
+                let res = async {
+                    let tx = readers.get().await?;
+                    let (found, _) = logs::read_page(
+                        &*tx,
+                        &log_filter,
+                        logs::LogPosition::start_of(header.number),
+                        header.number,
+                        usize::MAX,
+                    )
+                    .await?;
+                    rpc_logs(&*tx, found).await
+                }
+                .await;
+                match res {
+                    Ok(logs) => {
+                        for log in logs {
+                            if sink.send(&log).is_err() {
+                                return;
+                            }
+                        }
+                    }
+                    Err(e) => warn!("Failed to read logs of block {}: {}", header.number, e),
+                }
+            }
+        });
+    }
+
+    fn subscribe_pending_transactions(&self, mut sink: SubscriptionSink) {
+        let mut pending_transactions = self.pending_transactions.subscribe();
+        tokio::spawn(async move {
+            while let Some(hash) = recv_subscribed(
+                &mut pending_transactions,
+                SubscriptionKind::NewPendingTransactions,
+            )
+            .await
+            {
+                if sink.send(&hash).is_err() {
+                    return;
+                }
+            }
+        });
+    }
+
+    fn subscribe_syncing(&self, mut sink: SubscriptionSink) {
+        let mut events = self.sync_events.subscribe();
+        let status = self.sync_status.borrow().clone();
+        tokio::spawn(async move {
+            // Current status first, then changes.
+            if let Some(status) = status {
+                if sink.send(&status).is_err() {
+                    return;
+                }
+            }
+            while let Some(event) = recv_subscribed(&mut events, SubscriptionKind::Syncing).await {
+                if sink.send(&event).is_err() {
+                    return;
+                }
+            }
+        });
+    }
+}
+
+impl<DB> EthPubSubApiServer for EthPubSubApiServerImpl<DB>
+where
+    DB: KV,
+{
+    fn subscribe(
+        &self,
+        sink: SubscriptionSink,
+        kind: SubscriptionKind,
+        filter: Option<RpcLogFilter>,
+    ) -> RpcResult<()> {
+        match kind {
+            SubscriptionKind::NewHeads => self.subscribe_new_heads(sink),
+            SubscriptionKind::Logs => self.subscribe_logs(sink, filter.unwrap_or_default()),
+            SubscriptionKind::NewPendingTransactions => self.subscribe_pending_transactions(sink),
+            SubscriptionKind::Syncing => self.subscribe_syncing(sink),
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,155 @@
+use super::{error::RpcError, types::*};
+use crate::{
+    execution::flat_trace::{self, FlatTrace, LocatedTrace, TraceFilter},
+    hexbytes,
+    kv::{reader_pool::ReaderPool, traits::*},
+    models::*,
+};
+use async_trait::async_trait;
+use bytes::Bytes;
+use jsonrpsee::{core::RpcResult, proc_macros::rpc};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// Blocks `trace_filter` replays at most when no addresses are given to narrow them down by the
+/// call trace index.
+const MAX_UNINDEXED_TRACE_FILTER_BLOCKS: u64 = 1_000;
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RpcTraceFilter {
+    pub from_block: Option<BlockId>,
+    pub to_block: Option<BlockId>,
+    #[serde(default)]
+    pub from_address: Vec<Address>,
+    #[serde(default)]
+    pub to_address: Vec<Address>,
+    #[serde(default)]
+    pub after: usize,
+    pub count: Option<usize>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TraceCallResult {
+    #[serde(with = "hexbytes")]
+    pub output: Bytes,
+    pub trace: Vec<FlatTrace>,
+    /// Not supported.
+    pub state_diff: Option<()>,
+    /// Not supported.
+    pub vm_trace: Option<()>,
+}
+
+/// Traces in the format of OpenEthereum.
+#[rpc(server, namespace = "trace")]
+pub trait TraceApi {
+    #[method(name = "transaction")]
+    async fn transaction(&self, hash: H256) -> RpcResult<Option<Vec<LocatedTrace>>>;
+    #[method(name = "block")]
+    async fn block(&self, block: BlockId) -> RpcResult<Option<Vec<LocatedTrace>>>;
+    #[method(name = "filter")]
+    async fn filter(&self, filter: RpcTraceFilter) -> RpcResult<Vec<LocatedTrace>>;
+    /// Only `trace` of trace types is supported.
+    #[method(name = "call")]
+    async fn call(
+        &self,
+        request: CallRequest,
+        trace_types: Vec<String>,
+        block: Option<BlockId>,
+    ) -> RpcResult<TraceCallResult>;
+}
+
+pub struct TraceApiServerImpl<DB>
+where
+    DB: KV,
+{
+    pub readers: Arc<ReaderPool<DB>>,
+}
+
+#[async_trait]
+impl<DB> TraceApiServer for TraceApiServerImpl<DB>
+where
+    DB: KV,
+{
+    async fn transaction(&self, hash: H256) -> RpcResult<Option<Vec<LocatedTrace>>> {
+        let tx = self.readers.get().await?;
+
+        Ok(flat_trace::trace_transaction(&*tx, hash).await?)
+    }
+
+    async fn block(&self, block: BlockId) -> RpcResult<Option<Vec<LocatedTrace>>> {
+        let tx = self.readers.get().await?;
+        let block_number = block.resolve(&*tx).await?;
+
+        Ok(flat_trace::trace_block(&*tx, block_number).await?)
+    }
+
+    async fn filter(&self, filter: RpcTraceFilter) -> RpcResult<Vec<LocatedTrace>> {
+        let tx = self.readers.get().await?;
+        let from_block = match filter.from_block {
+            Some(block) => block.resolve(&*tx).await?,
+            None => BlockNumber(0),
+        };
+        let to_block = filter
+            .to_block
+            .unwrap_or(BlockId::Tag(BlockTag::Latest))
+            .resolve(&*tx)
+            .await?;
+
+        if filter.from_address.is_empty()
+            && filter.to_address.is_empty()
+            && to_block.0.saturating_sub(from_block.0) >= MAX_UNINDEXED_TRACE_FILTER_BLOCKS
+        {
+            return Err(RpcError::LimitExceeded(format!(
+                "at most {} blocks can be filtered without addresses",
+                MAX_UNINDEXED_TRACE_FILTER_BLOCKS
+            ))
+            .into());
+        }
+
+        Ok(flat_trace::trace_filter(
+            &*tx,
+            &TraceFilter {
+                from_block,
+                to_block,
+                from_address: filter.from_address,
+                to_address: filter.to_address,
+                after: filter.after,
+                count: filter.count,
+            },
+        )
+        .await?)
+    }
+
+    async fn call(
+        &self,
+        request: CallRequest,
+        trace_types: Vec<String>,
+        block: Option<BlockId>,
+    ) -> RpcResult<TraceCallResult> {
+        if let Some(trace_type) = trace_types.iter().find(|t| *t != "trace") {
+            return Err(RpcError::NotSupported(format!(
+                "trace type {} is not supported",
+                trace_type
+            ))
+            .into());
+        }
+
+        let tx = self.readers.get().await?;
+        let block_number = block
+            .unwrap_or(BlockId::Tag(BlockTag::Latest))
+            .resolve(&*tx)
+            .await?;
+
+        let (output, trace) =
+            flat_trace::trace_call(&*tx, block_number, &request.into_message()).await?;
+
+        Ok(TraceCallResult {
+            output,
+            trace,
+            state_diff: None,
+            vm_trace: None,
+        })
+    }
+}
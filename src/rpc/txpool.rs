@@ -0,0 +1,157 @@
+use super::types::RpcTransaction;
+use crate::{
+    accessors::chain,
+    consensus::expected_excess_blob_gas,
+    kv::traits::*,
+    models::*,
+    txpool::pool::{PooledTransaction, TxPool},
+};
+use anyhow::format_err;
+use async_trait::async_trait;
+use jsonrpsee::{core::RpcResult, proc_macros::rpc};
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::{collections::BTreeMap, sync::Arc};
+
+/// Pool transactions by sender and nonce.
+pub type TxpoolTransactions<T> = BTreeMap<Address, BTreeMap<String, T>>;
+
+fn txpool_transactions<T>(
+    transactions: BTreeMap<Address, Vec<&PooledTransaction>>,
+    f: impl Fn(&PooledTransaction) -> T,
+) -> TxpoolTransactions<T> {
+    transactions
+        .into_iter()
+        .map(|(sender, transactions)| {
+            (
+                sender,
+                transactions
+                    .into_iter()
+                    .map(|tx| (tx.transaction.nonce().to_string(), f(tx)))
+                    .collect(),
+            )
+        })
+        .collect()
+}
+
+#[derive(Serialize)]
+pub struct TxpoolContent {
+    pub pending: TxpoolTransactions<RpcTransaction>,
+    pub queued: TxpoolTransactions<RpcTransaction>,
+}
+
+#[derive(Serialize)]
+pub struct TxpoolInspect {
+    pub pending: TxpoolTransactions<String>,
+    pub queued: TxpoolTransactions<String>,
+}
+
+#[derive(Serialize)]
+pub struct TxpoolStatus {
+    pub pending: U64,
+    pub queued: U64,
+}
+
+#[rpc(server, namespace = "txpool")]
+pub trait TxpoolApi {
+    /// Pending and queued transactions.
+    #[method(name = "content")]
+    async fn content(&self) -> RpcResult<TxpoolContent>;
+    /// Pending and queued transactions, summarized as text.
+    #[method(name = "inspect")]
+    async fn inspect(&self) -> RpcResult<TxpoolInspect>;
+    /// Numbers of pending and queued transactions.
+    #[method(name = "status")]
+    async fn status(&self) -> RpcResult<TxpoolStatus>;
+}
+
+pub struct TxpoolApiServerImpl {
+    pub pool: Arc<Mutex<TxPool>>,
+}
+
+#[async_trait]
+impl TxpoolApiServer for TxpoolApiServerImpl {
+    async fn content(&self) -> RpcResult<TxpoolContent> {
+        let pool = self.pool.lock();
+        let content = |tx: &PooledTransaction| RpcTransaction::pooled(&tx.transaction, tx.sender);
+        Ok(TxpoolContent {
+            pending: txpool_transactions(pool.pending(), content),
+            queued: txpool_transactions(pool.queued(), content),
+        })
+    }
+
+    async fn inspect(&self) -> RpcResult<TxpoolInspect> {
+        let pool = self.pool.lock();
+        let summary = |tx: &PooledTransaction| {
+            let to = match tx.transaction.action() {
+                TransactionAction::Call(to) => format!("{:?}", to),
+                TransactionAction::Create => "contract creation".to_string(),
+            };
+            format!(
+                "{}: {} wei + {} gas × {} wei",
+                to,
+                tx.transaction.value(),
+                tx.transaction.gas_limit(),
+                tx.transaction.max_fee_per_gas()
+            )
+        };
+        Ok(TxpoolInspect {
+            pending: txpool_transactions(pool.pending(), summary),
+            queued: txpool_transactions(pool.queued(), summary),
+        })
+    }
+
+    async fn status(&self) -> RpcResult<TxpoolStatus> {
+        let pool = self.pool.lock();
+        let count = |transactions: BTreeMap<Address, Vec<&PooledTransaction>>| {
+            U64::from(transactions.values().map(Vec::len).sum::<usize>())
+        };
+        Ok(TxpoolStatus {
+            pending: count(pool.pending()),
+            queued: count(pool.queued()),
+        })
+    }
+}
+
+/// Check new pool transactions against the rules of block `head`,
+/// and drop transactions of senders whose nonces moved past them
+/// and blob transactions that can not pay for blobs in the next block.
+pub async fn refresh_pool<'db, Tx: Transaction<'db>>(
+    tx: &Tx,
+    pool: &Mutex<TxPool>,
+    chain_spec: &ChainSpec,
+    head: BlockNumber,
+) -> anyhow::Result<()> {
+    let hash = chain::canonical_hash::read(tx, head)
+        .await?
+        .ok_or_else(|| format_err!("no canonical block {}", head))?;
+    let header = chain::header::read(tx, hash, head)
+        .await?
+        .ok_or_else(|| format_err!("header {}/{:?} not found", head, hash))?;
+
+    // Accounts are read without holding the pool lock.
+    let senders = pool.lock().senders();
+    let mut accounts = Vec::with_capacity(senders.len());
+    for sender in senders {
+        let account = crate::accessors::state::account::read(tx, sender, None)
+            .await?
+            .unwrap_or_default();
+        accounts.push((sender, account));
+    }
+
+    let blob_base_fee =
+        expected_excess_blob_gas(chain_spec.consensus.eip4844_block, head + 1, &header)
+            .map(blob_base_fee);
+
+    let mut pool = pool.lock();
+    pool.set_head(
+        chain_spec.collect_block_spec(head).revision,
+        header.gas_limit,
+        blob_base_fee,
+    );
+    for (sender, account) in accounts {
+        pool.update_account(sender, account);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,683 @@
+use super::error::RpcError;
+use crate::{
+    accessors::{chain, logs},
+    hexbytes,
+    kv::traits::*,
+    models::*,
+    stagedsync::stages::FINISH,
+    Buffer, ChainReader,
+};
+use anyhow::format_err;
+use bytes::Bytes;
+use jsonrpsee::core::RpcResult;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BlockTag {
+    Earliest,
+    Latest,
+    Pending,
+}
+
+/// Block number, as is or hex-encoded, or one of the standard tags.
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(untagged)]
+pub enum BlockId {
+    Number(BlockNumber),
+    Quantity(U64),
+    Tag(BlockTag),
+    /// EIP-1898 block hash object.
+    #[serde(rename_all = "camelCase")]
+    Hash {
+        block_hash: H256,
+    },
+}
+
+impl BlockId {
+    pub async fn resolve<'db, Tx: Transaction<'db>>(self, tx: &Tx) -> anyhow::Result<BlockNumber> {
+        Ok(match self {
+            BlockId::Number(block_number) => block_number,
+            BlockId::Quantity(block_number) => BlockNumber(block_number.as_u64()),
+            BlockId::Tag(BlockTag::Earliest) => BlockNumber(0),
+            BlockId::Tag(BlockTag::Latest | BlockTag::Pending) => {
+                FINISH.get_progress(tx).await?.unwrap_or(BlockNumber(0))
+            }
+            BlockId::Hash { block_hash } => Buffer::new(tx, BlockNumber(0), None)
+                .canonical_number(block_hash)
+                .await?
+                .ok_or_else(|| format_err!("block {:?} not found or not canonical", block_hash))?,
+        })
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RpcAccessListItem {
+    pub address: Address,
+    pub storage_keys: Vec<H256>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RpcTransaction {
+    /// Not set for transactions in the pool.
+    pub block_hash: Option<H256>,
+    pub block_number: Option<U64>,
+    pub transaction_index: Option<U64>,
+    pub hash: H256,
+    #[serde(rename = "type")]
+    pub tx_type: U64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chain_id: Option<U64>,
+    pub nonce: U64,
+    pub from: Address,
+    pub to: Option<Address>,
+    pub value: U256,
+    pub gas: U64,
+    /// Price actually paid, for EIP-1559 transactions as well.
+    pub gas_price: U256,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_fee_per_gas: Option<U256>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_priority_fee_per_gas: Option<U256>,
+    #[serde(with = "hexbytes")]
+    pub input: Bytes,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub access_list: Option<Vec<RpcAccessListItem>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_fee_per_blob_gas: Option<U256>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub blob_versioned_hashes: Option<Vec<H256>>,
+    pub v: U64,
+    pub r: H256,
+    pub s: H256,
+}
+
+impl RpcTransaction {
+    pub fn new(
+        header: &BlockHeader,
+        block_hash: H256,
+        index: usize,
+        txn: &MessageWithSignature,
+        sender: Address,
+    ) -> Self {
+        let base_fee_per_gas = header.base_fee_per_gas.unwrap_or(U256::ZERO);
+        Self {
+            block_hash: Some(block_hash),
+            block_number: Some(header.number.0.into()),
+            transaction_index: Some(index.into()),
+            gas_price: txn
+                .max_fee_per_gas()
+                .min(base_fee_per_gas + txn.max_priority_fee_per_gas()),
+            ..Self::pooled(txn, sender)
+        }
+    }
+
+    /// Transaction that is not included yet, its gas price is the most it may pay.
+    pub fn pooled(txn: &MessageWithSignature, sender: Address) -> Self {
+        let chain_id = txn.chain_id();
+        let v = match (txn.tx_type(), chain_id) {
+            (TxType::Legacy, Some(chain_id)) => chain_id.0 * 2 + 35 + txn.v() as u64,
+            (TxType::Legacy, None) => 27 + txn.v() as u64,
+            _ => txn.v() as u64,
+        };
+        let is_eip1559 = matches!(txn.tx_type(), TxType::EIP1559 | TxType::EIP4844);
+
+        Self {
+            block_hash: None,
+            block_number: None,
+            transaction_index: None,
+            hash: txn.hash(),
+            tx_type: (txn.tx_type() as u8).into(),
+            chain_id: chain_id.map(|chain_id| chain_id.0.into()),
+            nonce: txn.nonce().into(),
+            from: sender,
+            to: match txn.action() {
+                TransactionAction::Call(to) => Some(to),
+                TransactionAction::Create => None,
+            },
+            value: txn.value(),
+            gas: txn.gas_limit().into(),
+            gas_price: txn.max_fee_per_gas(),
+            max_fee_per_gas: is_eip1559.then(|| txn.max_fee_per_gas()),
+            max_priority_fee_per_gas: is_eip1559.then(|| txn.max_priority_fee_per_gas()),
+            input: txn.input().clone(),
+            access_list: (txn.tx_type() != TxType::Legacy).then(|| {
+                txn.access_list()
+                    .iter()
+                    .map(|item| RpcAccessListItem {
+                        address: item.address,
+                        storage_keys: item.slots.clone(),
+                    })
+                    .collect()
+            }),
+            max_fee_per_blob_gas: (txn.tx_type() == TxType::EIP4844)
+                .then(|| txn.max_fee_per_blob_gas()),
+            blob_versioned_hashes: (txn.tx_type() == TxType::EIP4844)
+                .then(|| txn.blob_versioned_hashes().to_vec()),
+            v: v.into(),
+            r: txn.r(),
+            s: txn.s(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(untagged)]
+pub enum RpcBlockTransactions {
+    Hashes(Vec<H256>),
+    Full(Vec<RpcTransaction>),
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RpcBlock {
+    pub number: U64,
+    pub hash: H256,
+    pub parent_hash: H256,
+    pub nonce: H64,
+    pub mix_hash: H256,
+    #[serde(rename = "sha3Uncles")]
+    pub sha3_uncles: H256,
+    pub logs_bloom: Bloom,
+    pub transactions_root: H256,
+    pub state_root: H256,
+    pub receipts_root: H256,
+    pub miner: Address,
+    pub difficulty: U256,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total_difficulty: Option<U256>,
+    #[serde(with = "hexbytes")]
+    pub extra_data: Bytes,
+    pub size: U64,
+    pub gas_limit: U64,
+    pub gas_used: U64,
+    pub timestamp: U64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub base_fee_per_gas: Option<U256>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub blob_gas_used: Option<U64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub excess_blob_gas: Option<U64>,
+    pub transactions: RpcBlockTransactions,
+    pub uncles: Vec<H256>,
+}
+
+impl RpcBlock {
+    /// Block without transactions and uncles, as uncles are returned.
+    pub fn from_header(header: BlockHeader) -> Self {
+        Self {
+            number: header.number.0.into(),
+            hash: header.hash(),
+            size: rlp::encode(&header).len().into(),
+            parent_hash: header.parent_hash,
+            nonce: header.nonce,
+            mix_hash: header.mix_hash,
+            sha3_uncles: header.ommers_hash,
+            logs_bloom: header.logs_bloom,
+            transactions_root: header.transactions_root,
+            state_root: header.state_root,
+            receipts_root: header.receipts_root,
+            miner: header.beneficiary,
+            difficulty: header.difficulty,
+            total_difficulty: None,
+            extra_data: header.extra_data,
+            gas_limit: header.gas_limit.into(),
+            gas_used: header.gas_used.into(),
+            timestamp: header.timestamp.into(),
+            base_fee_per_gas: header.base_fee_per_gas,
+            blob_gas_used: header.blob_gas_used.map(From::from),
+            excess_blob_gas: header.excess_blob_gas.map(From::from),
+            transactions: RpcBlockTransactions::Hashes(vec![]),
+            uncles: vec![],
+        }
+    }
+}
+
+/// Header and body of block `hash`, canonical or not.
+pub(crate) async fn read_block<'db, Tx: Transaction<'db>>(
+    tx: &Tx,
+    hash: H256,
+) -> anyhow::Result<Option<(BlockHeader, BlockBody)>> {
+    let block_number = match chain::header_number::read(tx, hash).await? {
+        Some(block_number) => block_number,
+        None => return Ok(None),
+    };
+    let header = match chain::header::read(tx, hash, block_number).await? {
+        Some(header) => header,
+        None => return Ok(None),
+    };
+    let body = match chain::block_body::read_without_senders(tx, hash, block_number).await? {
+        Some(body) => body,
+        None => return Ok(None),
+    };
+
+    Ok(Some((header, body)))
+}
+
+/// Ommers of block `hash`, or `None` if it is not known.
+pub(crate) async fn read_ommers<'db, Tx: Transaction<'db>>(
+    tx: &Tx,
+    hash: H256,
+) -> anyhow::Result<Option<Vec<BlockHeader>>> {
+    let block_number = match chain::header_number::read(tx, hash).await? {
+        Some(block_number) => block_number,
+        None => return Ok(None),
+    };
+
+    Ok(chain::storage_body::read(tx, hash, block_number)
+        .await?
+        .map(|body| body.uncles))
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RpcLog {
+    pub address: Address,
+    pub topics: Vec<H256>,
+    #[serde(with = "hexbytes")]
+    pub data: Bytes,
+    pub block_hash: H256,
+    pub block_number: U64,
+    pub transaction_hash: H256,
+    pub transaction_index: U64,
+    pub log_index: U64,
+    pub removed: bool,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RpcReceipt {
+    pub transaction_hash: H256,
+    pub transaction_index: U64,
+    pub block_hash: H256,
+    pub block_number: U64,
+    pub from: Address,
+    pub to: Option<Address>,
+    pub cumulative_gas_used: U64,
+    pub gas_used: U64,
+    pub effective_gas_price: U256,
+    pub contract_address: Option<Address>,
+    pub logs: Vec<RpcLog>,
+    pub logs_bloom: Bloom,
+    #[serde(rename = "type")]
+    pub tx_type: U64,
+    pub status: U64,
+}
+
+impl RpcReceipt {
+    /// Receipt of transaction `index` of a block, given receipts of all its transactions.
+    pub fn new(
+        header: &BlockHeader,
+        block_hash: H256,
+        index: usize,
+        txn: &MessageWithSignature,
+        sender: Address,
+        receipts: &[Receipt],
+    ) -> Self {
+        let receipt = &receipts[index];
+        let hash = txn.hash();
+        let block_number = header.number;
+        let base_fee_per_gas = header.base_fee_per_gas.unwrap_or(U256::ZERO);
+        let cumulative_gas_used_before = index
+            .checked_sub(1)
+            .map_or(0, |prev| receipts[prev].cumulative_gas_used);
+        let log_index_before = receipts[..index]
+            .iter()
+            .map(|receipt| receipt.logs.len())
+            .sum::<usize>();
+
+        Self {
+            transaction_hash: hash,
+            transaction_index: index.into(),
+            block_hash,
+            block_number: block_number.0.into(),
+            from: sender,
+            to: match txn.action() {
+                TransactionAction::Call(to) => Some(to),
+                TransactionAction::Create => None,
+            },
+            cumulative_gas_used: receipt.cumulative_gas_used.into(),
+            gas_used: (receipt.cumulative_gas_used - cumulative_gas_used_before).into(),
+            effective_gas_price: txn
+                .max_fee_per_gas()
+                .min(base_fee_per_gas + txn.max_priority_fee_per_gas()),
+            contract_address: match txn.action() {
+                TransactionAction::Call(_) => None,
+                TransactionAction::Create => Some(crate::execution::address::create_address(
+                    sender,
+                    txn.nonce(),
+                )),
+            },
+            logs: receipt
+                .logs
+                .iter()
+                .enumerate()
+                .map(|(i, log)| RpcLog {
+                    address: log.address,
+                    topics: log.topics.clone(),
+                    data: log.data.clone(),
+                    block_hash,
+                    block_number: block_number.0.into(),
+                    transaction_hash: hash,
+                    transaction_index: index.into(),
+                    log_index: (log_index_before + i).into(),
+                    removed: false,
+                })
+                .collect(),
+            logs_bloom: receipt.bloom,
+            tx_type: (receipt.tx_type as u8).into(),
+            status: (receipt.success as u8).into(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(untagged)]
+pub enum ValueOrArray<T> {
+    Value(T),
+    Array(Vec<T>),
+}
+
+impl<T: Eq + std::hash::Hash> ValueOrArray<T> {
+    fn into_set(self) -> HashSet<T> {
+        match self {
+            Self::Value(value) => [value].into_iter().collect(),
+            Self::Array(values) => values.into_iter().collect(),
+        }
+    }
+}
+
+/// Filter of `eth_getLogs`.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct RpcLogFilter {
+    pub from_block: Option<BlockId>,
+    pub to_block: Option<BlockId>,
+    /// Single block to search, instead of `from_block` and `to_block`.
+    pub block_hash: Option<H256>,
+    pub address: Option<ValueOrArray<Address>>,
+    pub topics: Vec<Option<ValueOrArray<H256>>>,
+}
+
+impl RpcLogFilter {
+    /// First and last block to search, defaulting to and capped by `head`.
+    pub(crate) async fn block_range<'db, Tx: Transaction<'db>>(
+        &self,
+        tx: &Tx,
+        head: BlockNumber,
+    ) -> RpcResult<(BlockNumber, BlockNumber)> {
+        if let Some(block_hash) = self.block_hash {
+            if self.from_block.is_some() || self.to_block.is_some() {
+                return Err(RpcError::InvalidParams(
+                    "blockHash cannot be combined with fromBlock or toBlock".into(),
+                )
+                .into());
+            }
+            let block_number = BlockId::Hash { block_hash }.resolve(tx).await?;
+            return Ok((block_number, block_number));
+        }
+
+        let from_block = match self.from_block {
+            Some(block) => block.resolve(tx).await?,
+            None => head,
+        };
+        let to_block = match self.to_block {
+            Some(block) => block.resolve(tx).await?,
+            None => head,
+        }
+        .min(head);
+
+        Ok((from_block, to_block))
+    }
+
+    pub(crate) fn log_filter(&self) -> logs::LogFilter {
+        logs::LogFilter {
+            addresses: self.address.clone().map(ValueOrArray::into_set),
+            topics: self
+                .topics
+                .iter()
+                .map(|topics| topics.clone().map(ValueOrArray::into_set))
+                .collect(),
+        }
+    }
+}
+
+/// Logs found by [`logs::read_page`] with hashes of their blocks and transactions.
+pub(crate) async fn rpc_logs<'db, Tx: Transaction<'db>>(
+    tx: &Tx,
+    found: Vec<logs::FoundLog>,
+) -> anyhow::Result<Vec<RpcLog>> {
+    // Logs of a block are adjacent, so every block is only read once.
+    let mut block: Option<(BlockNumber, H256, Vec<H256>)> = None;
+    let mut out = Vec::with_capacity(found.len());
+    for found in found {
+        let block_number = found.position.block_number;
+        if block.as_ref().map(|(number, ..)| *number) != Some(block_number) {
+            let hash = chain::canonical_hash::read(tx, block_number)
+                .await?
+                .ok_or_else(|| format_err!("no canonical block {}", block_number))?;
+            let body = chain::block_body::read_without_senders(tx, hash, block_number)
+                .await?
+                .ok_or_else(|| format_err!("body {}/{:?} not found", block_number, hash))?;
+            let tx_hashes = body.transactions.iter().map(|txn| txn.hash()).collect();
+            block = Some((block_number, hash, tx_hashes));
+        }
+        let (_, block_hash, tx_hashes) = block.as_ref().unwrap();
+        let transaction_hash = *tx_hashes
+            .get(found.position.tx_index.0 as usize)
+            .ok_or_else(|| {
+                format_err!(
+                    "log of missing transaction {} in block {}",
+                    found.position.tx_index.0,
+                    block_number
+                )
+            })?;
+
+        out.push(RpcLog {
+            address: found.log.address,
+            topics: found.log.topics,
+            data: found.log.data,
+            block_hash: *block_hash,
+            block_number: block_number.0.into(),
+            transaction_hash,
+            transaction_index: found.position.tx_index.0.into(),
+            log_index: found.block_log_index.into(),
+            removed: false,
+        });
+    }
+
+    Ok(out)
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CallRequest {
+    pub from: Option<Address>,
+    pub to: Option<Address>,
+    pub gas: Option<U64>,
+    pub value: Option<U256>,
+    #[serde(default, with = "hexbytes")]
+    pub data: Bytes,
+}
+
+impl CallRequest {
+    /// Unsigned message from `from` (zero address by default) with gas limit of 10M by default.
+    pub fn into_message(self) -> MessageWithSender {
+        MessageWithSender {
+            message: Message::Legacy {
+                chain_id: None,
+                nonce: 0,
+                gas_price: U256::ZERO,
+                gas_limit: self.gas.map(|gas| gas.as_u64()).unwrap_or(10_000_000),
+                action: match self.to {
+                    Some(to) => TransactionAction::Call(to),
+                    None => TransactionAction::Create,
+                },
+                value: self.value.unwrap_or(U256::ZERO),
+                input: self.data,
+            },
+            sender: self.from.unwrap_or_else(Address::zero),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    fn header(number: u64, base_fee_per_gas: Option<u64>) -> BlockHeader {
+        BlockHeader::new(
+            PartialHeader {
+                number: BlockNumber(number),
+                beneficiary: Address::repeat_byte(0xcc),
+                difficulty: 1_000_u64.as_u256(),
+                gas_limit: 30_000_000,
+                gas_used: 42_000,
+                timestamp: 1_000,
+                base_fee_per_gas: base_fee_per_gas.map(|fee| fee.as_u256()),
+                ..PartialHeader::empty()
+            },
+            EMPTY_LIST_HASH,
+            EMPTY_ROOT,
+        )
+    }
+
+    fn txn(nonce: u64, action: TransactionAction) -> MessageWithSignature {
+        MessageWithSignature {
+            message: Message::EIP1559 {
+                chain_id: ChainId(1),
+                nonce,
+                max_priority_fee_per_gas: 10_u64.as_u256(),
+                max_fee_per_gas: 100_u64.as_u256(),
+                gas_limit: 21_000,
+                action,
+                value: U256::ZERO,
+                input: Bytes::new(),
+                access_list: vec![],
+            },
+            signature: MessageSignature::new(false, H256::repeat_byte(2), H256::repeat_byte(3))
+                .unwrap(),
+        }
+    }
+
+    fn hex<T: std::fmt::Debug>(value: T) -> Value {
+        json!(format!("{:?}", value))
+    }
+
+    #[test]
+    fn full_block() {
+        let header = header(5, Some(7));
+        let hash = header.hash();
+        let txn = txn(0, TransactionAction::Call(Address::repeat_byte(1)));
+        let sender = Address::repeat_byte(0xaa);
+        let ommer = header.clone();
+
+        let block = serde_json::to_value(RpcBlock {
+            total_difficulty: Some(5_000_u64.as_u256()),
+            transactions: RpcBlockTransactions::Full(vec![RpcTransaction::new(
+                &header, hash, 0, &txn, sender,
+            )]),
+            uncles: vec![ommer.hash()],
+            ..RpcBlock::from_header(header)
+        })
+        .unwrap();
+
+        assert_eq!(block["number"], json!("0x5"));
+        assert_eq!(block["hash"], hex(hash));
+        assert_eq!(block["sha3Uncles"], hex(EMPTY_LIST_HASH));
+        assert_eq!(block["miner"], hex(Address::repeat_byte(0xcc)));
+        assert_eq!(block["gasUsed"], json!("0xa410"));
+        assert_eq!(
+            block["totalDifficulty"],
+            serde_json::to_value(5_000_u64.as_u256()).unwrap()
+        );
+        assert_eq!(block["uncles"], json!([hex(ommer.hash())]));
+
+        let transactions = block["transactions"].as_array().unwrap();
+        assert_eq!(transactions.len(), 1);
+        let transaction = &transactions[0];
+        assert_eq!(transaction["hash"], hex(txn.hash()));
+        assert_eq!(transaction["blockHash"], hex(hash));
+        assert_eq!(transaction["blockNumber"], json!("0x5"));
+        assert_eq!(transaction["transactionIndex"], json!("0x0"));
+        assert_eq!(transaction["from"], hex(sender));
+        assert_eq!(transaction["type"], json!("0x2"));
+        assert_eq!(transaction["chainId"], json!("0x1"));
+        // Base fee plus priority fee, below the fee cap.
+        assert_eq!(
+            transaction["gasPrice"],
+            serde_json::to_value(17_u64.as_u256()).unwrap()
+        );
+        assert_eq!(transaction["accessList"], json!([]));
+        assert!(transaction.get("maxFeePerBlobGas").is_none());
+    }
+
+    #[test]
+    fn uncle() {
+        let ommer = header(4, None);
+        let size = rlp::encode(&ommer).len();
+        let hash = ommer.hash();
+
+        let uncle = serde_json::to_value(RpcBlock::from_header(ommer)).unwrap();
+
+        assert_eq!(uncle["number"], json!("0x4"));
+        assert_eq!(uncle["hash"], hex(hash));
+        assert_eq!(uncle["size"], json!(format!("{:#x}", size)));
+        assert_eq!(uncle["transactions"], json!([]));
+        assert_eq!(uncle["uncles"], json!([]));
+        // Not known for uncles, and not set before London.
+        assert!(uncle.get("totalDifficulty").is_none());
+        assert!(uncle.get("baseFeePerGas").is_none());
+    }
+
+    #[test]
+    fn receipt() {
+        let header = header(5, Some(7));
+        let block_hash = header.hash();
+        let sender = Address::repeat_byte(0xaa);
+        let txn = txn(1, TransactionAction::Create);
+        let log = |byte| Log {
+            address: Address::repeat_byte(byte),
+            topics: vec![H256::repeat_byte(byte)],
+            data: Bytes::from_static(&[byte]),
+        };
+        let receipts = vec![
+            Receipt::new(TxType::EIP1559, true, 21_000, vec![log(1), log(2)]),
+            Receipt::new(TxType::EIP1559, true, 74_000, vec![log(3)]),
+        ];
+
+        let receipt = serde_json::to_value(RpcReceipt::new(
+            &header, block_hash, 1, &txn, sender, &receipts,
+        ))
+        .unwrap();
+
+        assert_eq!(receipt["transactionHash"], hex(txn.hash()));
+        assert_eq!(receipt["transactionIndex"], json!("0x1"));
+        assert_eq!(receipt["blockHash"], hex(block_hash));
+        assert_eq!(receipt["blockNumber"], json!("0x5"));
+        assert_eq!(receipt["from"], hex(sender));
+        assert_eq!(receipt["to"], Value::Null);
+        assert_eq!(
+            receipt["contractAddress"],
+            hex(crate::execution::address::create_address(sender, 1))
+        );
+        assert_eq!(receipt["cumulativeGasUsed"], json!("0x12110"));
+        assert_eq!(receipt["gasUsed"], json!("0xcf08"));
+        assert_eq!(receipt["type"], json!("0x2"));
+        assert_eq!(receipt["status"], json!("0x1"));
+        assert_eq!(receipt["logsBloom"], hex(receipts[1].bloom));
+
+        let logs = receipt["logs"].as_array().unwrap();
+        assert_eq!(logs.len(), 1);
+        assert_eq!(logs[0]["address"], hex(Address::repeat_byte(3)));
+        assert_eq!(logs[0]["data"], json!("0x03"));
+        // Numbered within the block, after logs of the previous transaction.
+        assert_eq!(logs[0]["logIndex"], json!("0x2"));
+        assert_eq!(logs[0]["transactionIndex"], json!("0x1"));
+        assert_eq!(logs[0]["removed"], json!(false));
+    }
+}
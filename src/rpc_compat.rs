@@ -0,0 +1,646 @@
+//! Runner of JSON-RPC compatibility fixtures in the format of the execution-apis `rpc-compat`
+//! suite, against a small synthetic chain.
+//!
+//! Fixtures live in `<method>/<name>.io` files. Each exchange is a request line starting with
+//! `>> ` followed by its response line starting with `<< `, lines starting with `//` are comments.
+//! Like hive, errors only have to be present, their codes and messages are not compared.
+
+use crate::{
+    accessors::chain,
+    consensus::{engine_factory, expected_base_fee_per_gas},
+    crypto::root_hash,
+    execution::{analysis_cache::AnalysisCache, processor::ExecutionProcessor},
+    genesis::{initialize_genesis, GenesisState},
+    kv::{tables, traits::*},
+    models::*,
+    res::chainspec::MAINNET,
+    stagedsync::{stage::*, stages::*},
+    stages::*,
+    StageId,
+};
+use anyhow::{bail, format_err, Context};
+use bytes::Bytes;
+use hex_literal::hex;
+use jsonrpsee::core::server::rpc_module::Methods;
+use maplit::{btreemap, hashmap};
+use secp256k1::{Message as SecpMessage, SecretKey, SECP256K1};
+use serde_json::Value;
+use std::{
+    fmt::Write as _,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Instant,
+};
+use tempfile::TempDir;
+use tracing::*;
+
+/// Number of blocks on top of genesis in the synthetic chain.
+pub const SYNTHETIC_CHAIN_LENGTH: u64 = 8;
+
+/// Sender of every transaction of the synthetic chain, the address of secret key `1`.
+pub const SYNTHETIC_SENDER: Address = H160(hex!("7e5f4552091a69125d5dfcb7b8c2659029395bdf"));
+const SYNTHETIC_SENDER_KEY: [u8; 32] =
+    hex!("0000000000000000000000000000000000000000000000000000000000000001");
+/// Receives `n` wei in block `n` of the synthetic chain.
+pub const SYNTHETIC_RECIPIENT: Address = H160([0xee; 20]);
+const SYNTHETIC_MINER: Address = H160([0xc0; 20]);
+
+/// Request and the response expected for it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Exchange {
+    pub request: String,
+    pub response: Value,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TestCase {
+    /// Path of the fixture relative to the suite directory, e.g. `eth_chainId/get-chain-id`.
+    pub name: String,
+    pub path: PathBuf,
+    pub exchanges: Vec<Exchange>,
+}
+
+impl TestCase {
+    pub fn parse(name: String, path: PathBuf, text: &str) -> anyhow::Result<Self> {
+        let mut exchanges = vec![];
+        let mut request = None;
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with("//") {
+                continue;
+            }
+
+            if let Some(line) = line.strip_prefix(">>") {
+                if request.is_some() {
+                    bail!("line {}: request without response", i + 1);
+                }
+                request = Some(line.trim().to_string());
+            } else if let Some(line) = line.strip_prefix("<<") {
+                let request = request
+                    .take()
+                    .ok_or_else(|| format_err!("line {}: response without request", i + 1))?;
+                let response = serde_json::from_str(line.trim())
+                    .with_context(|| format!("line {}: invalid response", i + 1))?;
+                exchanges.push(Exchange { request, response });
+            } else {
+                bail!("line {}: neither request nor response", i + 1);
+            }
+        }
+        if request.is_some() {
+            bail!("last request has no response");
+        }
+
+        Ok(Self {
+            name,
+            path,
+            exchanges,
+        })
+    }
+
+    /// Fixture text, with `responses` in place of the expected ones if given.
+    pub fn to_io_string(&self, responses: Option<&[Value]>) -> String {
+        let mut out = String::new();
+        for (i, exchange) in self.exchanges.iter().enumerate() {
+            let response = responses
+                .and_then(|responses| responses.get(i))
+                .unwrap_or(&exchange.response);
+            let _ = writeln!(out, ">> {}", exchange.request);
+            let _ = writeln!(out, "<< {}", response);
+        }
+        out
+    }
+}
+
+/// All fixtures of the suite in `dir`, sorted by name.
+pub fn load_tests(dir: &Path) -> anyhow::Result<Vec<TestCase>> {
+    let mut tests = vec![];
+    for entry in walkdir::WalkDir::new(dir).sort_by_file_name() {
+        let entry = entry?;
+        let path = entry.path();
+        if !entry.file_type().is_file()
+            || path.extension().and_then(|ext| ext.to_str()) != Some("io")
+        {
+            continue;
+        }
+
+        let name = path
+            .strip_prefix(dir)?
+            .with_extension("")
+            .to_string_lossy()
+            .into_owned();
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        tests.push(
+            TestCase::parse(name, path.to_path_buf(), &text)
+                .with_context(|| format!("failed to parse {}", path.display()))?,
+        );
+    }
+
+    Ok(tests)
+}
+
+/// Check `got` against `expected`. Results must be equal, errors only have to be present on
+/// both sides.
+pub fn compare_responses(expected: &Value, got: &Value) -> anyhow::Result<()> {
+    if expected.get("id") != got.get("id") {
+        bail!(
+            "id mismatch: expected {:?}, got {:?}",
+            expected.get("id"),
+            got.get("id")
+        );
+    }
+
+    match (expected.get("error"), got.get("error")) {
+        (Some(_), Some(_)) => Ok(()),
+        (Some(_), None) => bail!("expected error, got {}", got),
+        (None, Some(error)) => bail!("unexpected error {}", error),
+        (None, None) => {
+            if expected.get("result") != got.get("result") {
+                bail!(
+                    "result mismatch: expected {}, got {}",
+                    expected.get("result").unwrap_or(&Value::Null),
+                    got.get("result").unwrap_or(&Value::Null)
+                );
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Outcome of one fixture.
+#[derive(Debug)]
+pub struct TestResult {
+    pub name: String,
+    /// Reason of failure, if any.
+    pub error: Option<anyhow::Error>,
+    /// Responses received, one per exchange unless a request could not be made at all.
+    pub responses: Vec<Value>,
+}
+
+/// Send all requests of `test` to `methods` in order, reporting the first mismatch.
+pub async fn run_test(methods: &Methods, test: &TestCase) -> TestResult {
+    let mut responses = vec![];
+    let mut error = None;
+    for (i, exchange) in test.exchanges.iter().enumerate() {
+        let res = async {
+            let (response, _) = methods
+                .raw_json_request(&exchange.request)
+                .await
+                .map_err(|e| format_err!("{}", e))?;
+            let response = serde_json::from_str::<Value>(&response)
+                .with_context(|| format!("invalid response {}", response))?;
+            responses.push(response.clone());
+            compare_responses(&exchange.response, &response)
+        }
+        .await;
+        if let Err(e) = res {
+            error.get_or_insert(e.context(format!("exchange #{}", i + 1)));
+        }
+    }
+
+    TestResult {
+        name: test.name.clone(),
+        error,
+        responses,
+    }
+}
+
+/// Run all fixtures in `dir`, rewriting failed ones with the received responses if `update`.
+pub async fn run_suite(
+    methods: &Methods,
+    dir: &Path,
+    update: bool,
+) -> anyhow::Result<Vec<TestResult>> {
+    let mut results = vec![];
+    for test in load_tests(dir)? {
+        let result = run_test(methods, &test).await;
+        match &result.error {
+            None => debug!("PASS {}", result.name),
+            Some(e) => {
+                warn!("FAIL {}: {:#}", result.name, e);
+                if update && result.responses.len() == test.exchanges.len() {
+                    std::fs::write(&test.path, test.to_io_string(Some(&result.responses)))?;
+                    info!("Updated {}", test.path.display());
+                }
+            }
+        }
+        results.push(result);
+    }
+
+    Ok(results)
+}
+
+/// Chain spec of the synthetic chain: every fork up to Berlin from genesis, London from block 1,
+/// since genesis has no base fee.
+pub fn synthetic_chain_spec() -> ChainSpec {
+    let mut chain_spec = MAINNET.clone();
+    chain_spec.name = "Synthetic".into();
+    chain_spec.consensus = ConsensusParams {
+        seal_verification: SealVerificationParams::Ethash {
+            duration_limit: 13,
+            block_reward: btreemap! { BlockNumber(0) => U256::from(2 * ETHER) },
+            homestead_formula: Some(BlockNumber(0)),
+            byzantium_formula: Some(BlockNumber(0)),
+            difficulty_bomb: None,
+            skip_pow_verification: true,
+        },
+        eip1559_block: Some(BlockNumber(1)),
+        eip4844_block: None,
+        terminal_total_difficulty: None,
+    };
+    chain_spec.upgrades = Upgrades {
+        homestead: Some(BlockNumber(0)),
+        tangerine: Some(BlockNumber(0)),
+        spurious: Some(BlockNumber(0)),
+        byzantium: Some(BlockNumber(0)),
+        constantinople: Some(BlockNumber(0)),
+        petersburg: Some(BlockNumber(0)),
+        istanbul: Some(BlockNumber(0)),
+        berlin: Some(BlockNumber(0)),
+        london: Some(BlockNumber(1)),
+        shanghai: None,
+    };
+    chain_spec.params = Params {
+        chain_id: ChainId(1337),
+        network_id: NetworkId(1337),
+        min_gas_limit: 5000,
+    };
+    chain_spec.genesis = Genesis {
+        number: BlockNumber(0),
+        author: Address::zero(),
+        gas_limit: 30_000_000,
+        timestamp: 0,
+        seal: Seal::Ethash {
+            vanity: Bytes::new(),
+            difficulty: U256::ONE,
+            nonce: H64::zero(),
+            mix_hash: H256::zero(),
+        },
+    };
+    chain_spec.contracts = Default::default();
+    chain_spec.balances = btreemap! {
+        BlockNumber(0) => hashmap! { SYNTHETIC_SENDER => U256::from(1000 * ETHER) },
+    };
+    chain_spec.system_calls = Default::default();
+    chain_spec.precompile_pricing = Default::default();
+    chain_spec.reward_redirections = Default::default();
+    chain_spec.p2p = P2PParams {
+        bootnodes: vec![],
+        preverified_hashes: vec![],
+    };
+    chain_spec
+}
+
+/// Blocks `1..=length` of the synthetic chain, each with one transfer from
+/// [`SYNTHETIC_SENDER`] to [`SYNTHETIC_RECIPIENT`].
+pub async fn synthetic_chain(chain_spec: &ChainSpec, length: u64) -> anyhow::Result<Vec<Block>> {
+    let genesis = GenesisState::new(chain_spec.clone());
+    let mut state = genesis.initial_state();
+    let mut parent = genesis.header(&state);
+
+    let key = SecretKey::from_slice(&SYNTHETIC_SENDER_KEY)?;
+    let mut engine = engine_factory(chain_spec.clone())?;
+    let mut analysis_cache = AnalysisCache::default();
+
+    let mut blocks = vec![];
+    for number in 1..=length {
+        let number = BlockNumber(number);
+        let base_fee_per_gas =
+            expected_base_fee_per_gas(chain_spec.consensus.eip1559_block, number, &parent);
+
+        let message = Message::EIP1559 {
+            chain_id: chain_spec.params.chain_id,
+            nonce: number.0 - 1,
+            max_priority_fee_per_gas: U256::from(GIGA),
+            max_fee_per_gas: base_fee_per_gas.unwrap_or_default() * 2 + U256::from(GIGA),
+            gas_limit: 21_000,
+            action: TransactionAction::Call(SYNTHETIC_RECIPIENT),
+            value: U256::from(number.0),
+            input: Bytes::new(),
+            access_list: vec![],
+        };
+        let (recovery_id, signature) = SECP256K1
+            .sign_ecdsa_recoverable(&SecpMessage::from_slice(message.hash().as_bytes())?, &key)
+            .serialize_compact();
+        let transaction = MessageWithSignature {
+            message: message.clone(),
+            signature: MessageSignature::new(
+                recovery_id.to_i32() != 0,
+                H256::from_slice(&signature[..32]),
+                H256::from_slice(&signature[32..]),
+            )
+            .ok_or_else(|| format_err!("invalid signature"))?,
+        };
+
+        let mut header = PartialHeader {
+            parent_hash: parent.hash(),
+            beneficiary: SYNTHETIC_MINER,
+            difficulty: U256::ONE,
+            number,
+            gas_limit: parent.gas_limit,
+            timestamp: parent.timestamp + 12,
+            base_fee_per_gas,
+            ..PartialHeader::empty()
+        };
+        let body = BlockBodyWithSenders {
+            transactions: vec![MessageWithSender {
+                message,
+                sender: SYNTHETIC_SENDER,
+            }],
+            ommers: vec![],
+            withdrawals: None,
+        };
+        let block_spec = chain_spec.collect_block_spec(number);
+
+        // Dry run fills in the receipt fields of the header, which the real run checks.
+        let receipts = ExecutionProcessor::new(
+            &mut state.clone(),
+            None,
+            &mut analysis_cache,
+            &mut *engine,
+            &header,
+            &body,
+            &block_spec,
+        )
+        .execute_block_no_post_validation()
+        .await?;
+        header.gas_used = receipts.last().map(|r| r.cumulative_gas_used).unwrap_or(0);
+        header.receipts_root = root_hash(&receipts);
+        header.logs_bloom = receipts
+            .iter()
+            .fold(Bloom::zero(), |bloom, r| bloom | r.bloom);
+
+        ExecutionProcessor::new(
+            &mut state,
+            None,
+            &mut analysis_cache,
+            &mut *engine,
+            &header,
+            &body,
+            &block_spec,
+        )
+        .execute_and_write_block()
+        .await?;
+        header.state_root = state.state_root_hash();
+
+        let block = Block::new(header, vec![transaction], vec![]);
+        parent = block.header.clone();
+        blocks.push(block);
+    }
+
+    Ok(blocks)
+}
+
+/// Execute `stage` until it is done, with `previous` as its previous stage at `head`.
+async fn run_stage<'db, RwTx, S>(
+    tx: &mut RwTx,
+    mut stage: S,
+    previous: StageId,
+    head: BlockNumber,
+) -> anyhow::Result<()>
+where
+    RwTx: MutableTransaction<'db>,
+    S: Stage<'db, RwTx>,
+{
+    let stage_id = stage.id();
+    loop {
+        let stage_progress = stage_id.get_progress(tx).await?;
+        match stage
+            .execute(
+                tx,
+                StageInput {
+                    restarted: false,
+                    first_started_at: (Instant::now(), stage_progress),
+                    previous_stage: Some((previous, head)),
+                    stage_progress,
+                },
+            )
+            .await
+            .with_context(|| format!("stage {} failed", stage_id))?
+        {
+            ExecOutput::Progress {
+                stage_progress,
+                done,
+            } => {
+                stage_id.save_progress(tx, stage_progress).await?;
+                if done {
+                    return Ok(());
+                }
+            }
+            ExecOutput::Unwind { unwind_to } => {
+                bail!("stage {} requested unwind to {}", stage_id, unwind_to)
+            }
+        }
+    }
+}
+
+/// Write genesis and `blocks` of `chain_spec` into an empty database, and run them through the
+/// stages that RPC reads from.
+pub async fn write_synthetic_chain<'db, RwTx: MutableTransaction<'db>>(
+    tx: &mut RwTx,
+    etl_temp_dir: Arc<TempDir>,
+    chain_spec: ChainSpec,
+    blocks: &[Block],
+) -> anyhow::Result<()> {
+    if !initialize_genesis(tx, &etl_temp_dir, chain_spec).await? {
+        bail!("database is not empty");
+    }
+
+    let mut td = U256::ONE;
+    let mut base_tx_id = TxIndex(0);
+    let mut head = BlockNumber(0);
+    for block in blocks {
+        let number = block.header.number;
+        let hash = block.header.hash();
+        td += block.header.difficulty;
+
+        tx.set(tables::Header, (number, hash), block.header.clone())
+            .await?;
+        chain::canonical_hash::write(tx, number, hash).await?;
+        tx.set(tables::HeadersTotalDifficulty, (number, hash), td)
+            .await?;
+        chain::storage_body::write(
+            tx,
+            hash,
+            number,
+            &BodyForStorage {
+                base_tx_id,
+                tx_amount: block.transactions.len() as u64,
+                uncles: vec![],
+                withdrawals: None,
+            },
+        )
+        .await?;
+        chain::tx::write(tx, base_tx_id, &block.transactions).await?;
+        tx.set(tables::LastHeader, Default::default(), hash).await?;
+
+        base_tx_id.0 += block.transactions.len() as u64;
+        head = number;
+    }
+    HEADERS.save_progress(tx, head).await?;
+    BODIES.save_progress(tx, head).await?;
+
+    run_stage(tx, TotalGasIndex, HEADERS, head).await?;
+    run_stage(
+        tx,
+        BlockHashes {
+            temp_dir: etl_temp_dir.clone(),
+        },
+        TOTAL_GAS_INDEX,
+        head,
+    )
+    .await?;
+    run_stage(tx, TotalTxIndex, BODIES, head).await?;
+    run_stage(
+        tx,
+        SenderRecovery { batch_size: 50_000 },
+        TOTAL_TX_INDEX,
+        head,
+    )
+    .await?;
+    run_stage(
+        tx,
+        Execution {
+            batch_size: 5_000_000_000_000,
+            history_batch_size: 250_000_000_000,
+            exit_after_batch: false,
+            batch_until: None,
+            commit_every: None,
+            prune_from: BlockNumber(0),
+            analysis_cache: AnalysisCache::default(),
+            contract_gas_top: None,
+        },
+        SENDERS,
+        head,
+    )
+    .await?;
+    run_stage(
+        tx,
+        HashState::new(etl_temp_dir.clone(), None),
+        EXECUTION,
+        head,
+    )
+    .await?;
+    run_stage(
+        tx,
+        Interhashes::new(etl_temp_dir.clone(), None),
+        HASH_STATE,
+        head,
+    )
+    .await?;
+    run_stage(
+        tx,
+        LogIndex {
+            temp_dir: etl_temp_dir.clone(),
+            flush_interval: 50_000,
+        },
+        INTERMEDIATE_HASHES,
+        head,
+    )
+    .await?;
+    run_stage(
+        tx,
+        CallTraceIndex {
+            temp_dir: etl_temp_dir,
+            flush_interval: 50_000,
+        },
+        LOG_INDEX,
+        head,
+    )
+    .await?;
+    FINISH.save_progress(tx, head).await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{accessors::state, kv::new_mem_database};
+    use serde_json::json;
+
+    #[test]
+    fn parse_fixture() {
+        let text = r#"// Returns the chain id.
+>> {"jsonrpc":"2.0","id":1,"method":"eth_chainId"}
+<< {"jsonrpc":"2.0","id":1,"result":"0x539"}
+
+>> {"jsonrpc":"2.0","id":2,"method":"eth_chainId"}
+<< {"jsonrpc":"2.0","id":2,"result":"0x539"}
+"#;
+        let test = TestCase::parse("eth_chainId/get".into(), PathBuf::new(), text).unwrap();
+        assert_eq!(test.exchanges.len(), 2);
+        assert_eq!(
+            test.exchanges[0].request,
+            r#"{"jsonrpc":"2.0","id":1,"method":"eth_chainId"}"#
+        );
+        assert_eq!(
+            test.exchanges[1].response,
+            json!({"jsonrpc": "2.0", "id": 2, "result": "0x539"})
+        );
+        assert_eq!(
+            TestCase::parse("".into(), PathBuf::new(), &test.to_io_string(None)).unwrap(),
+            TestCase {
+                name: "".into(),
+                ..test
+            }
+        );
+
+        assert!(TestCase::parse("".into(), PathBuf::new(), ">> {}").is_err());
+        assert!(TestCase::parse("".into(), PathBuf::new(), "<< {}").is_err());
+    }
+
+    #[test]
+    fn compare() {
+        let result = |id, result| json!({"jsonrpc": "2.0", "id": id, "result": result});
+        let error = |id, code| {
+            let error = json!({"code": code, "message": "oops"});
+            json!({"jsonrpc": "2.0", "id": id, "error": error})
+        };
+
+        assert!(compare_responses(&result(1, "0x1"), &result(1, "0x1")).is_ok());
+        assert!(compare_responses(&result(1, "0x1"), &result(1, "0x2")).is_err());
+        assert!(compare_responses(&result(1, "0x1"), &result(2, "0x1")).is_err());
+        assert!(compare_responses(&result(1, "0x1"), &error(1, -32000)).is_err());
+        assert!(compare_responses(&error(1, -32000), &result(1, "0x1")).is_err());
+        assert!(compare_responses(&error(1, -32000), &error(1, -32602)).is_ok());
+    }
+
+    #[tokio::test]
+    async fn synthetic_chain_is_synced() {
+        let chain_spec = synthetic_chain_spec();
+        let blocks = synthetic_chain(&chain_spec, 3).await.unwrap();
+
+        let db = new_mem_database().unwrap();
+        let mut tx = db.begin_mutable().await.unwrap();
+        write_synthetic_chain(
+            &mut tx,
+            Arc::new(TempDir::new().unwrap()),
+            chain_spec,
+            &blocks,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            FINISH.get_progress(&tx).await.unwrap(),
+            Some(BlockNumber(3))
+        );
+        assert_eq!(
+            state::account::read(&tx, SYNTHETIC_RECIPIENT, None)
+                .await
+                .unwrap()
+                .unwrap()
+                .balance,
+            U256::from(1 + 2 + 3)
+        );
+        assert_eq!(
+            state::account::read(&tx, SYNTHETIC_SENDER, None)
+                .await
+                .unwrap()
+                .unwrap()
+                .nonce,
+            3
+        );
+    }
+}
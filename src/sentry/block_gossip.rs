@@ -0,0 +1,383 @@
+//! Propagation of new chain heads on networks that are not merged yet,
+//! where miners announce blocks with `NewBlock` and `NewBlockHashes`.
+//!
+//! As in the eth protocol, a new block is forwarded in full to a few peers once its header and
+//! seal are valid on top of a known parent, and its hash is announced to all peers only after
+//! sync imports it.
+
+use super::{
+    messages::{BlockHashAndNumber, EthMessageId, Message, NewBlockHashesMessage, NewBlockMessage},
+    sentry_client::{MessageFromPeer, PeerFilter, PeerId, Status},
+    sentry_client_reactor::SentryClientReactorShared,
+};
+use crate::{
+    consensus::{Consensus, ValidationError},
+    kv::traits::*,
+    models::*,
+    Buffer,
+};
+use lru::LruCache;
+use std::sync::Arc;
+use tokio::sync::watch;
+use tokio_stream::StreamExt;
+use tracing::*;
+
+/// Number of random peers a new block is forwarded to in full.
+pub const MAX_FORWARD_PEERS: u64 = 8;
+/// Number of recent block hashes remembered to not propagate the same block twice.
+const SEEN_BLOCKS_CAPACITY: usize = 1024;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Verdict {
+    /// Block is a new head, propagate it.
+    Forward,
+    /// Block is not heavier than our head.
+    Ignore,
+    /// Block is malformed, peer that sent it should be penalized.
+    Penalize(&'static str),
+}
+
+/// Forwards new blocks announced by peers and announces our own new heads.
+pub struct BlockGossip<DB: KV> {
+    sentry: SentryClientReactorShared,
+    status: watch::Receiver<Status>,
+    db: Arc<DB>,
+    engine: Box<dyn Consensus>,
+    seen: LruCache<H256, ()>,
+    /// Last head announced to peers.
+    announced: H256,
+}
+
+impl<DB: KV> BlockGossip<DB> {
+    pub fn new(
+        sentry: SentryClientReactorShared,
+        status: watch::Receiver<Status>,
+        db: Arc<DB>,
+        engine: Box<dyn Consensus>,
+    ) -> Self {
+        Self {
+            sentry,
+            status,
+            db,
+            engine,
+            seen: LruCache::new(SEEN_BLOCKS_CAPACITY),
+            announced: H256::zero(),
+        }
+    }
+
+    async fn check_header(&self, header: &BlockHeader) -> Verdict {
+        let res = async {
+            let tx = self.db.begin().await?;
+            check_new_block_header(&tx, &*self.engine, header).await
+        }
+        .await;
+        res.unwrap_or_else(|e| {
+            warn!("Failed to check header of block {}: {}", header.number, e);
+            Verdict::Ignore
+        })
+    }
+
+    async fn on_new_block(&mut self, message: NewBlockMessage, from_peer_id: Option<PeerId>) {
+        if self.seen.put(message.block.header.hash(), ()).is_some() {
+            return;
+        }
+
+        let local_total_difficulty = self.status.borrow().total_difficulty;
+        let mut verdict = check_new_block(&message, local_total_difficulty);
+        if verdict == Verdict::Forward {
+            verdict = self.check_header(&message.block.header).await;
+        }
+        match verdict {
+            Verdict::Forward => {
+                let number = message.block.header.number;
+                debug!(
+                    "Forwarding new block {}/{:?}",
+                    number,
+                    message.block.header.hash()
+                );
+
+                // Hash is announced to the rest of peers once the block is imported.
+                if let Err(e) = self
+                    .sentry
+                    .read()
+                    .await
+                    .send_message(
+                        Message::NewBlock(message),
+                        PeerFilter::Random(MAX_FORWARD_PEERS),
+                    )
+                    .await
+                {
+                    debug!("Failed to forward block {}: {}", number, e);
+                }
+            }
+            Verdict::Ignore => {}
+            Verdict::Penalize(reason) => {
+                debug!("Invalid NewBlock from {:?}: {}", from_peer_id, reason);
+                if let Some(peer_id) = from_peer_id {
+                    if let Err(e) = self.sentry.read().await.penalize_peer(peer_id).await {
+                        debug!("Failed to penalize peer {:?}: {}", peer_id, e);
+                    }
+                }
+            }
+        }
+    }
+
+    async fn on_new_head(&mut self) {
+        let status = self.status.borrow().clone();
+        if status.best_hash.is_zero() || status.best_hash == self.announced {
+            return;
+        }
+        self.announced = status.best_hash;
+        self.seen.put(status.best_hash, ());
+
+        debug!(
+            "Announcing new head {}/{:?}",
+            status.max_block, status.best_hash
+        );
+        let message = Message::NewBlockHashes(NewBlockHashesMessage {
+            ids: vec![BlockHashAndNumber {
+                hash: status.best_hash,
+                number: status.max_block,
+            }],
+        });
+        if let Err(e) = self
+            .sentry
+            .read()
+            .await
+            .send_message(message, PeerFilter::All)
+            .await
+        {
+            debug!("Failed to announce new head: {}", e);
+        }
+    }
+
+    /// Run until sentry reactor or status provider stops.
+    pub async fn run(mut self) -> anyhow::Result<()> {
+        let (mut new_blocks, mut new_block_hashes) = {
+            let sentry = self.sentry.read().await;
+            (
+                sentry.receive_messages(EthMessageId::NewBlock)?,
+                sentry.receive_messages(EthMessageId::NewBlockHashes)?,
+            )
+        };
+
+        loop {
+            tokio::select! {
+                message = new_blocks.next() => match message {
+                    Some(MessageFromPeer {
+                        message: Message::NewBlock(message),
+                        from_peer_id,
+                    }) => self.on_new_block(message, from_peer_id).await,
+                    Some(_) => {}
+                    None => break,
+                },
+                message = new_block_hashes.next() => match message {
+                    Some(MessageFromPeer {
+                        message: Message::NewBlockHashes(message),
+                        ..
+                    }) => {
+                        // Announced blocks will be downloaded by sync, no need to forward them.
+                        for id in message.ids {
+                            self.seen.put(id.hash, ());
+                        }
+                    }
+                    Some(_) => {}
+                    None => break,
+                },
+                changed = self.status.changed() => match changed {
+                    Ok(()) => self.on_new_head().await,
+                    Err(_) => break,
+                },
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Check `NewBlock` message against our head with given total difficulty.
+///
+/// Only consistency of the message itself is checked here, see [`check_new_block_header`] for
+/// the header. The block is fully verified once it is downloaded by sync.
+pub fn check_new_block(message: &NewBlockMessage, local_total_difficulty: U256) -> Verdict {
+    let block = &message.block;
+    if block.header.difficulty == U256::ZERO {
+        return Verdict::Penalize("block without difficulty");
+    }
+    if message.total_difficulty < block.header.difficulty {
+        return Verdict::Penalize("total difficulty below block difficulty");
+    }
+    if block.header.ommers_hash != Block::ommers_hash(&block.ommers)
+        || block.header.transactions_root != Block::transactions_root(&block.transactions)
+    {
+        return Verdict::Penalize("body does not match header");
+    }
+
+    if message.total_difficulty <= local_total_difficulty {
+        return Verdict::Ignore;
+    }
+
+    Verdict::Forward
+}
+
+/// Check header of a new block, including its seal, against its parent in `tx`.
+///
+/// Blocks with unknown parent are not forwarded: they can not be validated, and we could not
+/// import them either.
+pub async fn check_new_block_header<'db, Tx: Transaction<'db>>(
+    tx: &Tx,
+    engine: &dyn Consensus,
+    header: &BlockHeader,
+) -> anyhow::Result<Verdict> {
+    let mut state = Buffer::new(tx, BlockNumber(0), None);
+    let res = match engine.validate_block_header(header, &mut state, true).await {
+        Ok(()) => engine.validate_seal(header).await,
+        Err(e) => Err(e),
+    };
+
+    Ok(match res {
+        Ok(()) => Verdict::Forward,
+        Err(e) => match e.downcast::<ValidationError>()? {
+            ValidationError::UnknownParent => Verdict::Ignore,
+            e => {
+                debug!("Invalid header of block {}: {:?}", header.number, e);
+                Verdict::Penalize("invalid header")
+            }
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        accessors::chain, consensus::engine_factory, kv::new_mem_database, res::chainspec::MAINNET,
+        state::genesis::initialize_genesis,
+    };
+    use hex_literal::hex;
+    use tempfile::TempDir;
+
+    fn new_block(difficulty: u64, total_difficulty: u64) -> NewBlockMessage {
+        NewBlockMessage {
+            block: Box::new(Block::new(
+                PartialHeader {
+                    number: BlockNumber(100),
+                    difficulty: U256::from(difficulty),
+                    ..PartialHeader::empty()
+                },
+                vec![],
+                vec![],
+            )),
+            total_difficulty: U256::from(total_difficulty),
+        }
+    }
+
+    #[test]
+    fn checks_new_blocks() {
+        let local_total_difficulty = U256::from(1_000_u64);
+
+        assert_eq!(
+            check_new_block(&new_block(10, 1_010), local_total_difficulty),
+            Verdict::Forward
+        );
+        // Not heavier than our head.
+        assert_eq!(
+            check_new_block(&new_block(11, 1_000), local_total_difficulty),
+            Verdict::Ignore
+        );
+        assert_eq!(
+            check_new_block(&new_block(0, 2_000), local_total_difficulty),
+            Verdict::Penalize("block without difficulty")
+        );
+        assert_eq!(
+            check_new_block(&new_block(2_000, 1_999), local_total_difficulty),
+            Verdict::Penalize("total difficulty below block difficulty")
+        );
+
+        let mut message = new_block(12, 2_000);
+        message.block.ommers.push(message.block.header.clone());
+        assert_eq!(
+            check_new_block(&message, local_total_difficulty),
+            Verdict::Penalize("body does not match header")
+        );
+    }
+
+    #[tokio::test]
+    async fn checks_new_block_headers() {
+        let db = new_mem_database().unwrap();
+        let tx = db.begin_mutable().await.unwrap();
+        initialize_genesis(&tx, &TempDir::new().unwrap(), MAINNET.clone())
+            .await
+            .unwrap();
+        let genesis_hash = chain::canonical_hash::read(&tx, BlockNumber(0))
+            .await
+            .unwrap()
+            .unwrap();
+        let genesis = chain::header::read(&tx, genesis_hash, BlockNumber(0))
+            .await
+            .unwrap()
+            .unwrap();
+        let engine = engine_factory(MAINNET.clone()).unwrap();
+
+        // Mainnet block 1, with valid seal.
+        let block1 = BlockHeader {
+            parent_hash: genesis_hash,
+            ommers_hash: EMPTY_LIST_HASH,
+            beneficiary: hex!("05a56e2d52c817161883f50c441c3228cfe54d9f").into(),
+            state_root: hex!("d67e4d450343046425ae4271474353857ab860dbc0a1dde64b41b5cd3a532bf3")
+                .into(),
+            transactions_root: EMPTY_ROOT,
+            receipts_root: EMPTY_ROOT,
+            difficulty: U256::from(17_171_480_576_u64),
+            number: BlockNumber(1),
+            gas_limit: 5_000,
+            timestamp: 1_438_269_988,
+            extra_data: hex!("476574682f76312e302e302f6c696e75782f676f312e342e32")
+                .to_vec()
+                .into(),
+            mix_hash: hex!("969b900de27b6ac6a67742365dd65f55a0526c41fd18e1b16f1a1215c2e66f59")
+                .into(),
+            nonce: hex!("539bd4979fef1ec4").into(),
+            ..genesis
+        };
+        assert_eq!(
+            block1.hash(),
+            H256::from(hex!(
+                "88e96d4537bea4d9c05d12549907b32561d3bf31f45aae734cdc119f13406cb6"
+            ))
+        );
+        assert_eq!(
+            check_new_block_header(&tx, &*engine, &block1)
+                .await
+                .unwrap(),
+            Verdict::Forward
+        );
+
+        let mut forged = block1.clone();
+        forged.nonce = H64::zero();
+        assert_eq!(
+            check_new_block_header(&tx, &*engine, &forged)
+                .await
+                .unwrap(),
+            Verdict::Penalize("invalid header")
+        );
+
+        let mut wrong_difficulty = block1.clone();
+        wrong_difficulty.difficulty += U256::ONE;
+        assert_eq!(
+            check_new_block_header(&tx, &*engine, &wrong_difficulty)
+                .await
+                .unwrap(),
+            Verdict::Penalize("invalid header")
+        );
+
+        let mut orphan = block1;
+        orphan.parent_hash = H256::repeat_byte(1);
+        assert_eq!(
+            check_new_block_header(&tx, &*engine, &orphan)
+                .await
+                .unwrap(),
+            Verdict::Ignore
+        );
+    }
+}
@@ -0,0 +1,224 @@
+//! Nodes found by discovery and the outcomes of dialing them, kept in a file, so that after a
+//! restart the sentry is offered peers that connected before, instead of waiting for lookups
+//! from bootnodes to find some.
+
+use super::NodeRecord;
+use crate::sentry::sentry_client::PeerId;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs, io,
+    net::SocketAddr,
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
+
+/// Nodes kept at most, the ones failing most dials are forgotten first.
+pub const MAX_KNOWN_NODES: usize = 2048;
+/// Nodes offered first after a restart.
+pub const RESTART_NODES: usize = 64;
+/// Nodes not connected for this long are not offered after a restart.
+pub const RESTART_MAX_AGE: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct KnownNode {
+    peer_id: PeerId,
+    addr: SocketAddr,
+    /// Seconds since the Unix epoch of the last time discovery found the node.
+    found: u64,
+    /// Seconds since the Unix epoch of the last time the node became our peer after a dial.
+    connected: Option<u64>,
+    /// Dials since the last one that connected.
+    failed_dials: u32,
+}
+
+fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[derive(Debug)]
+pub struct KnownNodes {
+    nodes: HashMap<PeerId, KnownNode>,
+    path: PathBuf,
+}
+
+impl KnownNodes {
+    /// Nodes loaded from `path`, which [`Self::save`] writes them back to.
+    pub fn load(path: PathBuf) -> anyhow::Result<Self> {
+        let nodes = match fs::read(&path) {
+            Ok(data) => serde_json::from_slice::<Vec<KnownNode>>(&data)?
+                .into_iter()
+                .map(|node| (node.peer_id, node))
+                .collect(),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(Self { nodes, path })
+    }
+
+    pub fn save(&self) -> anyhow::Result<()> {
+        let mut nodes = self.nodes.values().collect::<Vec<_>>();
+        nodes.sort_by_key(|node| node.peer_id);
+        fs::write(&self.path, serde_json::to_vec_pretty(&nodes)?)?;
+
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn found(&mut self, node: NodeRecord, now: SystemTime) {
+        let entry = self.nodes.entry(node.id).or_insert_with(|| KnownNode {
+            peer_id: node.id,
+            addr: node.addr,
+            found: 0,
+            connected: None,
+            failed_dials: 0,
+        });
+        // Address of a node that moved is not worth keeping its history for.
+        if entry.addr != node.addr {
+            entry.addr = node.addr;
+            entry.connected = None;
+            entry.failed_dials = 0;
+        }
+        entry.found = unix_secs(now);
+
+        if self.nodes.len() > MAX_KNOWN_NODES {
+            self.forget_worst();
+        }
+    }
+
+    /// Count a dial of `peer_id` as failed until [`Self::connected`] says otherwise.
+    pub fn dialed(&mut self, peer_id: PeerId) {
+        if let Some(node) = self.nodes.get_mut(&peer_id) {
+            node.failed_dials = node.failed_dials.saturating_add(1);
+        }
+    }
+
+    pub fn connected(&mut self, peer_id: PeerId, now: SystemTime) {
+        if let Some(node) = self.nodes.get_mut(&peer_id) {
+            node.connected = Some(unix_secs(now));
+            node.failed_dials = 0;
+        }
+    }
+
+    /// Up to `limit` nodes worth dialing first, out of ones that connected within `max_age`:
+    /// with fewest failed dials since, then connected most recently.
+    pub fn best(&self, limit: usize, max_age: Duration, now: SystemTime) -> Vec<NodeRecord> {
+        let oldest = unix_secs(now).saturating_sub(max_age.as_secs());
+        let mut nodes = self
+            .nodes
+            .values()
+            .filter(|node| {
+                node.connected
+                    .map_or(false, |connected| connected >= oldest)
+            })
+            .collect::<Vec<_>>();
+        nodes.sort_by_key(|node| (node.failed_dials, std::cmp::Reverse(node.connected)));
+
+        nodes
+            .into_iter()
+            .take(limit)
+            .map(|node| NodeRecord {
+                addr: node.addr,
+                id: node.peer_id,
+            })
+            .collect()
+    }
+
+    fn forget_worst(&mut self) {
+        if let Some(peer_id) = self
+            .nodes
+            .values()
+            .max_by_key(|node| {
+                (
+                    node.failed_dials,
+                    std::cmp::Reverse(node.connected),
+                    std::cmp::Reverse(node.found),
+                )
+            })
+            .map(|node| node.peer_id)
+        {
+            self.nodes.remove(&peer_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(byte: u8) -> NodeRecord {
+        NodeRecord {
+            addr: SocketAddr::from(([10, 0, 0, byte], 30303)),
+            id: PeerId::repeat_byte(byte),
+        }
+    }
+
+    #[test]
+    fn dial_history_outlives_restarts() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("known-nodes.json");
+        let now = SystemTime::now();
+        let day = Duration::from_secs(24 * 60 * 60);
+
+        let mut known = KnownNodes::load(path.clone()).unwrap();
+        for byte in 1..=4 {
+            known.found(node(byte), now);
+            known.dialed(node(byte).id);
+        }
+        known.connected(node(1).id, now - Duration::from_secs(60));
+        known.connected(node(2).id, now);
+        known.connected(node(3).id, now - 2 * day);
+        // Failed after connecting.
+        known.dialed(node(1).id);
+        known.save().unwrap();
+
+        let mut known = KnownNodes::load(path).unwrap();
+        assert_eq!(known.len(), 4);
+        assert_eq!(known.best(10, day, now), vec![node(2), node(1)]);
+        assert_eq!(known.best(1, day, now), vec![node(2)]);
+
+        // Moved node starts over.
+        let moved = NodeRecord {
+            addr: SocketAddr::from(([10, 0, 1, 2], 30303)),
+            ..node(2)
+        };
+        known.found(moved, now);
+        assert_eq!(known.best(10, day, now), vec![node(1)]);
+    }
+
+    #[test]
+    fn forgets_failing_nodes_first() {
+        let dir = tempfile::tempdir().unwrap();
+        let now = SystemTime::now();
+
+        let mut known = KnownNodes::load(dir.path().join("known-nodes.json")).unwrap();
+        let failing = NodeRecord {
+            addr: SocketAddr::from(([10, 1, 0, 0], 30303)),
+            id: PeerId::from_low_u64_be(u64::MAX),
+        };
+        known.found(failing, now);
+        known.dialed(failing.id);
+        for i in 0..MAX_KNOWN_NODES as u64 {
+            known.found(
+                NodeRecord {
+                    addr: SocketAddr::from(([10, 2, 0, 0], 30303)),
+                    id: PeerId::from_low_u64_be(i),
+                },
+                now,
+            );
+        }
+
+        assert_eq!(known.len(), MAX_KNOWN_NODES);
+        assert!(!known.nodes.contains_key(&failing.id));
+    }
+}
@@ -0,0 +1,266 @@
+//! Discovery of nodes for the sentry to dial.
+//!
+//! Every discovery service is a stream of nodes it finds. [`Candidates`] merges the streams of
+//! all services, such as [`v4::Discv4`] and [`v5::Discv5`], and skips nodes offered recently,
+//! and [`PeerDiscovery`] hands the rest to the sentry while it has free peer slots. Outcomes of
+//! dials are kept in [`KnownNodes`], whose best nodes are offered first after a restart.
+
+pub mod known_nodes;
+pub mod v4;
+pub mod v5;
+
+use self::known_nodes::KnownNodes;
+use super::{sentry_client::PeerId, sentry_client_reactor::SentryClientReactorShared};
+use anyhow::{bail, format_err};
+use futures_core::Stream;
+use lru::LruCache;
+use std::{
+    fmt,
+    net::SocketAddr,
+    pin::Pin,
+    str::FromStr,
+    time::{Duration, Instant, SystemTime},
+};
+use tokio_stream::{StreamExt, StreamMap};
+use tracing::*;
+
+/// Number of nodes remembered to not offer the same node twice within the redial interval.
+const DIALED_CAPACITY: usize = 4096;
+/// Nodes found by a service and not taken yet, beyond which its lookups pause.
+const LOOKUP_CACHE: usize = 256;
+/// Interval after which a node is offered to the sentry again.
+pub const REDIAL_INTERVAL: Duration = Duration::from_secs(30 * 60);
+/// Dialed node not connected within this time counts as a failed dial.
+const DIAL_TIMEOUT: Duration = Duration::from_secs(30);
+/// Interval of saving known nodes.
+const SAVE_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// Node found by discovery: its RLPx listening address and id.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct NodeRecord {
+    pub addr: SocketAddr,
+    pub id: PeerId,
+}
+
+/// Enode URL of the node.
+impl fmt::Display for NodeRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "enode://{}@{}", hex::encode(self.id), self.addr)
+    }
+}
+
+impl FromStr for NodeRecord {
+    type Err = anyhow::Error;
+
+    /// Parse enode URL, such as of chain spec bootnodes. Discovery port in the query is ignored.
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let s = s
+            .strip_prefix("enode://")
+            .ok_or_else(|| format_err!("not an enode URL: {}", s))?;
+        let (id, addr) = s
+            .split_once('@')
+            .ok_or_else(|| format_err!("no address in enode URL: {}", s))?;
+        let addr = addr.split_once('?').map_or(addr, |(addr, _)| addr);
+
+        let id = hex::decode(id)?;
+        if id.len() != PeerId::len_bytes() {
+            bail!("invalid node id length {}", id.len());
+        }
+
+        Ok(Self {
+            addr: addr.parse()?,
+            id: PeerId::from_slice(&id),
+        })
+    }
+}
+
+pub type Discovery = Pin<Box<dyn Stream<Item = anyhow::Result<NodeRecord>> + Send>>;
+
+/// Nodes found by all discovery services, each offered at most once per redial interval.
+pub struct Candidates {
+    sources: StreamMap<&'static str, Discovery>,
+    dialed: LruCache<PeerId, Instant>,
+    redial_interval: Duration,
+}
+
+impl Candidates {
+    pub fn new(redial_interval: Duration) -> Self {
+        Self {
+            sources: StreamMap::new(),
+            dialed: LruCache::new(DIALED_CAPACITY),
+            redial_interval,
+        }
+    }
+
+    pub fn add_source(&mut self, name: &'static str, discovery: Discovery) {
+        self.sources.insert(name, discovery);
+    }
+
+    /// Next node to dial, `None` once all services stop.
+    pub async fn next(&mut self) -> Option<NodeRecord> {
+        while let Some((source, res)) = self.sources.next().await {
+            let node = match res {
+                Ok(node) => node,
+                Err(e) => {
+                    debug!("Discovery {} failed: {}", source, e);
+                    continue;
+                }
+            };
+
+            let now = Instant::now();
+            if let Some(dialed_at) = self.dialed.get(&node.id) {
+                if now.duration_since(*dialed_at) < self.redial_interval {
+                    continue;
+                }
+            }
+            self.dialed.put(node.id, now);
+
+            trace!("Discovery {} found {}", source, node);
+            return Some(node);
+        }
+
+        None
+    }
+}
+
+/// Feeds nodes found by discovery to the sentry while it has less than `max_peers` peers,
+/// and records which of them connect in `known`.
+pub struct PeerDiscovery {
+    sentry: SentryClientReactorShared,
+    candidates: Candidates,
+    max_peers: usize,
+    known: KnownNodes,
+    /// Nodes dialed within the dial timeout that have not connected yet.
+    dialing: Vec<(PeerId, Instant)>,
+}
+
+impl PeerDiscovery {
+    pub fn new(
+        sentry: SentryClientReactorShared,
+        candidates: Candidates,
+        max_peers: usize,
+        known: KnownNodes,
+    ) -> Self {
+        Self {
+            sentry,
+            candidates,
+            max_peers,
+            known,
+            dialing: Vec::new(),
+        }
+    }
+
+    /// Record dials that connected since the last check.
+    async fn check_dials(&mut self) {
+        let sentry = self.sentry.read().await;
+        let now = Instant::now();
+        let known = &mut self.known;
+        self.dialing.retain(|&(peer_id, dialed_at)| {
+            if sentry.peer_version(peer_id).is_some() {
+                known.connected(peer_id, SystemTime::now());
+                return false;
+            }
+            now.duration_since(dialed_at) < DIAL_TIMEOUT
+        });
+    }
+
+    pub async fn run(mut self) -> anyhow::Result<()> {
+        let mut saved_at = Instant::now();
+        while let Some(node) = self.candidates.next().await {
+            self.check_dials().await;
+            while self.sentry.read().await.peer_count() >= self.max_peers {
+                tokio::time::sleep(Duration::from_secs(1)).await;
+                self.check_dials().await;
+            }
+
+            self.known.found(node, SystemTime::now());
+            self.sentry.read().await.add_peer(node).await?;
+            self.known.dialed(node.id);
+            self.dialing.push((node.id, Instant::now()));
+
+            if saved_at.elapsed() >= SAVE_INTERVAL {
+                self.known.save()?;
+                saved_at = Instant::now();
+            }
+        }
+
+        self.known.save()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hex_literal::hex;
+
+    fn node(byte: u8) -> NodeRecord {
+        NodeRecord {
+            addr: SocketAddr::from(([10, 0, 0, byte], 30303)),
+            id: PeerId::repeat_byte(byte),
+        }
+    }
+
+    #[test]
+    fn enode_url() {
+        let url = "enode://a24ac7c5484ef4ed0c5eb2d36620ba4e4aa13b8c84684e1b4aab0cebea2ae45cb4d375b77eab56516d34bfbd3c1a833fc51296ff084b770b94fb9028c4d25ccf@52.169.42.101:30303";
+        let node = url.parse::<NodeRecord>().unwrap();
+        assert_eq!(node.addr, SocketAddr::from(([52, 169, 42, 101], 30303)));
+        assert_eq!(
+            node.id,
+            PeerId::from(hex!("a24ac7c5484ef4ed0c5eb2d36620ba4e4aa13b8c84684e1b4aab0cebea2ae45cb4d375b77eab56516d34bfbd3c1a833fc51296ff084b770b94fb9028c4d25ccf"))
+        );
+        assert_eq!(node.to_string(), url);
+        assert_eq!(
+            format!("{}?discport=30301", url)
+                .parse::<NodeRecord>()
+                .unwrap(),
+            node
+        );
+
+        assert!("enode://abcd@52.169.42.101:30303"
+            .parse::<NodeRecord>()
+            .is_err());
+        assert!("52.169.42.101:30303".parse::<NodeRecord>().is_err());
+    }
+
+    #[tokio::test]
+    async fn merges_sources_and_skips_recent() {
+        let mut candidates = Candidates::new(Duration::from_secs(3600));
+        candidates.add_source(
+            "a",
+            Box::pin(tokio_stream::iter(vec![Ok(node(1)), Ok(node(2))])),
+        );
+        candidates.add_source(
+            "b",
+            Box::pin(tokio_stream::iter(vec![
+                Err(format_err!("lookup failed")),
+                Ok(node(2)),
+                Ok(node(3)),
+            ])),
+        );
+
+        let mut found = vec![];
+        while let Some(node) = candidates.next().await {
+            found.push(node.id);
+        }
+        found.sort();
+        assert_eq!(
+            found,
+            vec![
+                PeerId::repeat_byte(1),
+                PeerId::repeat_byte(2),
+                PeerId::repeat_byte(3)
+            ]
+        );
+
+        // Offered again once the interval passes.
+        let mut candidates = Candidates::new(Duration::ZERO);
+        candidates.add_source(
+            "a",
+            Box::pin(tokio_stream::iter(vec![Ok(node(1)), Ok(node(1))])),
+        );
+        assert_eq!(candidates.next().await, Some(node(1)));
+        assert_eq!(candidates.next().await, Some(node(1)));
+        assert_eq!(candidates.next().await, None);
+    }
+}
@@ -0,0 +1,69 @@
+//! Node discovery v4 ([spec](https://github.com/ethereum/devp2p/blob/master/discv4.md)), which
+//! every Ethereum node speaks. Its records say nothing about the chain of a node.
+
+use super::{NodeRecord, LOOKUP_CACHE};
+use futures_core::Stream;
+use secp256k1::SecretKey;
+use std::{
+    net::SocketAddr,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+use tokio::sync::mpsc;
+
+/// Nodes found by random lookups of a discv4 node.
+pub struct Discv4 {
+    receiver: mpsc::Receiver<anyhow::Result<NodeRecord>>,
+}
+
+impl Discv4 {
+    /// Start discovery on UDP address `listen`, advertising the sentry at `tcp_port`.
+    pub async fn start(
+        secret_key: SecretKey,
+        listen: SocketAddr,
+        tcp_port: u16,
+        bootnodes: &[NodeRecord],
+    ) -> anyhow::Result<Self> {
+        let bootnodes = bootnodes
+            .iter()
+            .map(|node| discv4::NodeRecord {
+                address: node.addr.ip(),
+                tcp_port: node.addr.port(),
+                udp_port: node.addr.port(),
+                id: node.id,
+            })
+            .collect();
+        let node = discv4::Node::new(listen, secret_key, bootnodes, None, true, tcp_port).await?;
+
+        Ok(Self::new(node))
+    }
+
+    pub fn new(node: Arc<discv4::Node>) -> Self {
+        let (sender, receiver) = mpsc::channel(LOOKUP_CACHE);
+
+        tokio::spawn(async move {
+            loop {
+                for record in node.lookup(rand::random()).await {
+                    let node = NodeRecord {
+                        addr: SocketAddr::new(record.address, record.tcp_port),
+                        id: record.id,
+                    };
+                    if sender.send(Ok(node)).await.is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+
+        Self { receiver }
+    }
+}
+
+impl Stream for Discv4 {
+    type Item = anyhow::Result<NodeRecord>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.receiver.poll_recv(cx)
+    }
+}
@@ -0,0 +1,140 @@
+//! Node discovery v5 ([spec](https://github.com/ethereum/devp2p/blob/master/discv5/discv5.md)).
+//! Nodes are found with their ENR, whose `eth` entry lets us skip nodes of other chains or
+//! forks before they are dialed, see [`EnrForkFilter`].
+
+use super::{NodeRecord, LOOKUP_CACHE};
+use crate::sentry::{
+    chain_config::ChainConfig,
+    enr::{EnrForkFilter, EthEntry, ETH_ENR_KEY},
+    sentry_client::{PeerId, Status},
+};
+use anyhow::format_err;
+use discv5::{
+    enr::{CombinedKey, CombinedPublicKey, EnrBuilder, NodeId},
+    Discv5ConfigBuilder, Enr,
+};
+use futures_core::Stream;
+use secp256k1::{PublicKey, SecretKey};
+use std::{
+    net::{IpAddr, SocketAddr},
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tokio::sync::{mpsc, watch};
+use tracing::*;
+
+/// Nodes of our chain found by random lookups of a discv5 node.
+pub struct Discv5 {
+    receiver: mpsc::Receiver<anyhow::Result<NodeRecord>>,
+}
+
+impl Discv5 {
+    /// Start discovery on UDP address `listen`. Our record advertises the sentry at `tcp_port`
+    /// of `public_ip`, with the fork id of our head at start.
+    pub async fn start(
+        secret_key: SecretKey,
+        listen: SocketAddr,
+        public_ip: Option<IpAddr>,
+        tcp_port: u16,
+        bootnodes: Vec<Enr>,
+        chain_config: ChainConfig,
+        status: watch::Receiver<Status>,
+    ) -> anyhow::Result<Self> {
+        let key = CombinedKey::secp256k1_from_bytes(&mut secret_key.secret_bytes())?;
+        let fork_id = EnrForkFilter::new(&chain_config, status.borrow().max_block).current();
+
+        let mut builder = EnrBuilder::new("v4");
+        if let Some(ip) = public_ip {
+            builder.ip(ip).tcp(tcp_port).udp(listen.port());
+        }
+        builder.add_value(ETH_ENR_KEY, &EthEntry(fork_id));
+        let enr = builder.build(&key)?;
+
+        let mut disc = discv5::Discv5::new(enr, key, Discv5ConfigBuilder::new().build())
+            .map_err(|e| format_err!("{}", e))?;
+        for bootnode in bootnodes {
+            disc.add_enr(bootnode).map_err(|e| format_err!("{}", e))?;
+        }
+        disc.start(listen)
+            .await
+            .map_err(|e| format_err!("{:?}", e))?;
+
+        Ok(Self::new(disc, chain_config, status))
+    }
+
+    pub fn new(
+        disc: discv5::Discv5,
+        chain_config: ChainConfig,
+        status: watch::Receiver<Status>,
+    ) -> Self {
+        let (sender, receiver) = mpsc::channel(LOOKUP_CACHE);
+
+        tokio::spawn(async move {
+            loop {
+                let enrs = match disc.find_node(NodeId::random()).await {
+                    Ok(enrs) => enrs,
+                    Err(e) => {
+                        if sender
+                            .send(Err(format_err!("lookup failed: {:?}", e)))
+                            .await
+                            .is_err()
+                        {
+                            return;
+                        }
+                        continue;
+                    }
+                };
+
+                // Head moves between lookups, and so may the fork id we accept.
+                let filter = EnrForkFilter::new(&chain_config, status.borrow().max_block);
+                for enr in enrs {
+                    let node = match dialable(&enr, &filter) {
+                        Some(node) => node,
+                        None => continue,
+                    };
+                    if sender.send(Ok(node)).await.is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+
+        Self { receiver }
+    }
+}
+
+/// RLPx address of the node of `enr`, if it has one and follows our chain.
+fn dialable(enr: &Enr, filter: &EnrForkFilter) -> Option<NodeRecord> {
+    let compatible = enr
+        .get(ETH_ENR_KEY)
+        .map_or(false, |entry| filter.is_compatible(entry));
+    if !compatible {
+        trace!("Skipping node {} of another chain", enr.node_id());
+        return None;
+    }
+
+    let addr = match (enr.ip(), enr.tcp()) {
+        (Some(ip), Some(port)) => SocketAddr::new(ip.into(), port),
+        _ => match (enr.ip6(), enr.tcp6()) {
+            (Some(ip), Some(port)) => SocketAddr::new(ip.into(), port),
+            _ => return None,
+        },
+    };
+    let id = match enr.public_key() {
+        CombinedPublicKey::Secp256k1(key) => PublicKey::from_slice(&key.to_bytes()).ok()?,
+        _ => return None,
+    };
+
+    Some(NodeRecord {
+        addr,
+        id: PeerId::from_slice(&id.serialize_uncompressed()[1..]),
+    })
+}
+
+impl Stream for Discv5 {
+    type Item = anyhow::Result<NodeRecord>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.receiver.poll_recv(cx)
+    }
+}
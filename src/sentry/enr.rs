@@ -0,0 +1,88 @@
+//! Filtering of node records found by discovery, by the `eth` entry of their ENR
+//! ([EIP-778](https://eips.ethereum.org/EIPS/eip-778)), so that peers of other chains, or
+//! stuck at old forks, are not dialed at all.
+//!
+//! The entry is `[[fork_hash, fork_next], ...]`, with fork id as in
+//! [EIP-2124](https://eips.ethereum.org/EIPS/eip-2124).
+
+use super::chain_config::ChainConfig;
+use crate::models::BlockNumber;
+use ethereum_forkid::{ForkFilter, ForkId};
+use rlp::{DecoderError, Encodable, Rlp, RlpStream};
+
+/// Key of the `eth` entry in node records.
+pub const ETH_ENR_KEY: &str = "eth";
+
+/// Value of the `eth` entry of our own node record.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EthEntry(pub ForkId);
+
+impl Encodable for EthEntry {
+    fn rlp_append(&self, s: &mut RlpStream) {
+        s.begin_list(1);
+        s.append(&self.0);
+    }
+}
+
+/// Fork id advertised in value of the `eth` entry of a node record.
+pub fn eth_entry_fork_id(value: &[u8]) -> Result<ForkId, DecoderError> {
+    let rlp = Rlp::new(value);
+    // Later elements are reserved for extensions.
+    if rlp.item_count()? == 0 {
+        return Err(DecoderError::RlpIncorrectListLen);
+    }
+    rlp.val_at(0)
+}
+
+/// Accepts node records of peers that may follow our chain at our head.
+#[derive(Debug)]
+pub struct EnrForkFilter(ForkFilter);
+
+impl EnrForkFilter {
+    pub fn new(chain_config: &ChainConfig, head: BlockNumber) -> Self {
+        Self(ForkFilter::new(
+            head.0,
+            chain_config.genesis_block_hash(),
+            chain_config.fork_block_numbers().into_iter().map(|n| n.0),
+        ))
+    }
+
+    /// Fork id for the `eth` entry of our own node record.
+    pub fn current(&self) -> ForkId {
+        self.0.current()
+    }
+
+    /// Check value of the `eth` entry. Records without one are rejected by the caller, since
+    /// only nodes of other networks leave it out.
+    pub fn is_compatible(&self, eth_entry: &[u8]) -> bool {
+        eth_entry_fork_id(eth_entry).map_or(false, |fork_id| self.0.validate(fork_id).is_ok())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethereum_forkid::ForkHash;
+
+    fn eth_entry(fork_id: ForkId) -> Vec<u8> {
+        rlp::encode(&EthEntry(fork_id)).to_vec()
+    }
+
+    #[test]
+    fn filter_by_fork_id() {
+        let chain_config = ChainConfig::new(crate::res::chainspec::MAINNET.clone());
+        let head = BlockNumber(15_050_000);
+        let filter = EnrForkFilter::new(&chain_config, head);
+
+        let ours = filter.current();
+        assert_eq!(eth_entry_fork_id(&eth_entry(ours)).unwrap(), ours);
+        assert!(filter.is_compatible(&eth_entry(ours)));
+
+        assert!(!filter.is_compatible(&eth_entry(ForkId {
+            hash: ForkHash([0xde, 0xad, 0xbe, 0xef]),
+            next: 0,
+        })));
+        assert!(!filter.is_compatible(&[0xc0]));
+        assert!(!filter.is_compatible(&[]));
+    }
+}
@@ -1,5 +1,75 @@
 use super::messages::*;
-use crate::sentry::messages::Message;
+use crate::{models::MessageWithSignature, sentry::messages::Message};
+use rayon::prelude::*;
+use rlp::{DecoderError, Rlp};
+
+/// Responses with fewer transactions than this are decoded on the calling thread.
+const PARALLEL_DECODE_MIN_TRANSACTIONS: usize = 256;
+
+/// Decode `BlockBodies` message, decoding transactions of all bodies in parallel.
+///
+/// Item boundaries are found first, which is cheap, then transactions are decoded
+/// from their own slices on the rayon pool.
+fn decode_block_bodies(message_bytes: &[u8]) -> Result<BlockBodiesMessage, DecoderError> {
+    let rlp = Rlp::new(message_bytes);
+    if !rlp.is_list() {
+        return Err(DecoderError::RlpExpectedToBeList);
+    }
+    let request_id = rlp.val_at(0)?;
+
+    let mut bodies = vec![];
+    let mut raw_transactions = vec![];
+    for body in rlp.at(1)?.iter() {
+        if !body.is_list() {
+            return Err(DecoderError::RlpExpectedToBeList);
+        }
+        let transactions = body.at(0)?;
+        if !transactions.is_list() {
+            return Err(DecoderError::RlpExpectedToBeList);
+        }
+        let start = raw_transactions.len();
+        for txn in transactions.iter() {
+            raw_transactions.push(txn.as_raw());
+        }
+        bodies.push((start..raw_transactions.len(), body.list_at(1)?));
+    }
+
+    let decode = |raw: &&[u8]| rlp::decode::<MessageWithSignature>(raw);
+    let mut transactions = if raw_transactions.len() < PARALLEL_DECODE_MIN_TRANSACTIONS {
+        raw_transactions
+            .iter()
+            .map(decode)
+            .collect::<Result<Vec<_>, _>>()?
+    } else {
+        raw_transactions
+            .par_iter()
+            .map(decode)
+            .collect::<Result<Vec<_>, _>>()?
+    }
+    .into_iter();
+
+    Ok(BlockBodiesMessage {
+        request_id,
+        block_bodies: bodies
+            .into_iter()
+            .map(|(range, ommers)| BlockBodyType {
+                transactions: transactions.by_ref().take(range.len()).collect(),
+                ommers,
+            })
+            .collect(),
+    })
+}
+
+/// Decode transaction announcement of either eth/66 and eth/67, a list of hashes, or eth/68,
+/// a list of types, sizes and hashes. Both share the message id, so tell them apart by shape.
+fn decode_new_pooled_transaction_hashes(message_bytes: &[u8]) -> Result<Message, DecoderError> {
+    let rlp = Rlp::new(message_bytes);
+    if rlp.item_count()? == 3 && rlp.at(1)?.is_list() {
+        Ok(Message::NewPooledTransactionHashes68(rlp.as_val()?))
+    } else {
+        Ok(Message::NewPooledTransactionHashes(rlp.as_val()?))
+    }
+}
 
 pub fn decode_rlp_message(id: EthMessageId, message_bytes: &[u8]) -> anyhow::Result<Message> {
     let message: Message = match id {
@@ -13,15 +83,13 @@ pub fn decode_rlp_message(id: EthMessageId, message_bytes: &[u8]) -> anyhow::Res
             Message::BlockHeaders(rlp::decode::<BlockHeadersMessage>(message_bytes)?)
         }
         EthMessageId::NewBlock => Message::NewBlock(rlp::decode::<NewBlockMessage>(message_bytes)?),
-        EthMessageId::NewPooledTransactionHashes => Message::NewPooledTransactionHashes(
-            rlp::decode::<NewPooledTransactionHashesMessage>(message_bytes)?,
-        ),
+        EthMessageId::NewPooledTransactionHashes => {
+            decode_new_pooled_transaction_hashes(message_bytes)?
+        }
         EthMessageId::GetBlockBodies => {
             Message::GetBlockBodies(rlp::decode::<GetBlockBodiesMessage>(message_bytes)?)
         }
-        EthMessageId::BlockBodies => {
-            Message::BlockBodies(rlp::decode::<BlockBodiesMessage>(message_bytes)?)
-        }
+        EthMessageId::BlockBodies => Message::BlockBodies(decode_block_bodies(message_bytes)?),
         EthMessageId::GetNodeData => {
             Message::GetNodeData(rlp::decode::<GetNodeDataMessage>(message_bytes)?)
         }
@@ -52,6 +120,7 @@ impl rlp::Encodable for Message {
             Message::BlockHeaders(message) => message.rlp_append(stream),
             Message::NewBlock(message) => message.rlp_append(stream),
             Message::NewPooledTransactionHashes(message) => message.rlp_append(stream),
+            Message::NewPooledTransactionHashes68(message) => message.rlp_append(stream),
             Message::Transactions(message) => message.rlp_append(stream),
             Message::GetBlockBodies(message) => message.rlp_append(stream),
             Message::BlockBodies(message) => message.rlp_append(stream),
@@ -74,7 +143,7 @@ mod tests {
             BlockHeader, BlockNumber, ChainId, Message as TxMessage, MessageSignature,
             MessageWithSignature, Receipt, TransactionAction,
         },
-        sentry::messages::Message,
+        sentry::{messages::Message, sentry_client::DecodeFault},
     };
 
     use bytes::Bytes;
@@ -499,4 +568,126 @@ mod tests {
 
         assert_eq!(some_message, msg);
     }
+
+    #[test]
+    fn decode_block_bodies_in_parallel() {
+        let txn = |nonce: u64| MessageWithSignature {
+            message: TxMessage::EIP1559 {
+                chain_id: ChainId(1),
+                nonce,
+                max_priority_fee_per_gas: U256::from(nonce),
+                max_fee_per_gas: U256::from(nonce * 2),
+                gas_limit: 21_000,
+                action: TransactionAction::Call(H160::repeat_byte(0x35)),
+                value: U256::from(nonce),
+                input: Bytes::from(vec![nonce as u8; nonce as usize % 64]),
+                access_list: vec![],
+            },
+            signature: MessageSignature::new(
+                nonce % 2 == 0,
+                H256(hex!(
+                    "64b1702d9298fee62dfeccc57d322a463ad55ca201256d01f62b45b2e1c21c12"
+                )),
+                H256(hex!(
+                    "64b1702d9298fee62dfeccc57d322a463ad55ca201256d01f62b45b2e1c21c10"
+                )),
+            )
+            .unwrap(),
+        };
+
+        let mut nonce = 0;
+        let message = BlockBodiesMessage {
+            request_id: 1111,
+            block_bodies: [0, 300, 1, 0, 500]
+                .into_iter()
+                .map(|count| BlockBodyType {
+                    transactions: (0..count)
+                        .map(|_| {
+                            nonce += 1;
+                            txn(nonce)
+                        })
+                        .collect(),
+                    ommers: vec![BlockHeader::empty(); count as usize % 2],
+                })
+                .collect(),
+        };
+        let bytes = rlp::encode(&message);
+
+        assert_eq!(
+            decode_rlp_message(EthMessageId::BlockBodies, &bytes).unwrap(),
+            Message::BlockBodies(message)
+        );
+
+        // Truncated response.
+        assert!(decode_rlp_message(EthMessageId::BlockBodies, &bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn decode_new_pooled_transaction_hashes() {
+        let hashes = vec![
+            H256::repeat_byte(1),
+            H256::repeat_byte(2),
+            H256::repeat_byte(3),
+        ];
+
+        let eth66 = NewPooledTransactionHashesMessage {
+            ids: hashes.clone(),
+        };
+        assert_eq!(
+            decode_rlp_message(
+                EthMessageId::NewPooledTransactionHashes,
+                &rlp::encode(&eth66)
+            )
+            .unwrap(),
+            Message::NewPooledTransactionHashes(eth66)
+        );
+
+        let eth68 = NewPooledTransactionHashes68Message {
+            types: vec![0, 2, 1],
+            sizes: vec![110, 250, 100_000],
+            hashes,
+        };
+        let bytes = rlp::encode(&eth68);
+        let message = decode_rlp_message(EthMessageId::NewPooledTransactionHashes, &bytes).unwrap();
+        assert_eq!(&*rlp::encode(&message), &*bytes);
+        assert_eq!(
+            message,
+            Message::NewPooledTransactionHashes68(eth68.clone())
+        );
+
+        let mismatched = NewPooledTransactionHashes68Message {
+            types: vec![0],
+            ..eth68
+        };
+        assert!(decode_rlp_message(
+            EthMessageId::NewPooledTransactionHashes,
+            &rlp::encode(&mismatched)
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn decode_faults() {
+        let fault = |bytes: &[u8]| {
+            DecodeFault::from(
+                decode_rlp_message(EthMessageId::GetBlockHeaders, bytes)
+                    .unwrap_err()
+                    .downcast_ref::<rlp::DecoderError>()
+                    .unwrap(),
+            )
+        };
+
+        // Truncated.
+        assert_eq!(
+            fault(&hex!("ca820457c682270f0505")),
+            DecodeFault::Corruption
+        );
+        // Request id with leading zero byte.
+        assert_eq!(
+            fault(&hex!("cb83000457c682270f050580")),
+            DecodeFault::ProtocolViolation
+        );
+        // Not a list.
+        assert_eq!(fault(&hex!("820457")), DecodeFault::ProtocolViolation);
+    }
 }
@@ -104,7 +104,7 @@ pub struct BlockHeadersMessage {
 #[derive(RlpEncodable, RlpDecodable, Clone, PartialEq, Debug)]
 pub struct NewBlockMessage {
     pub block: Box<BlockType>,
-    pub total_difficulty: u64,
+    pub total_difficulty: crate::models::U256,
 }
 
 #[derive(RlpEncodableWrapper, RlpDecodableWrapper, Clone, PartialEq, Debug)]
@@ -112,6 +112,45 @@ pub struct NewPooledTransactionHashesMessage {
     pub ids: Vec<H256>,
 }
 
+/// Transaction announcement of eth/68, which also carries type and encoded size of every
+/// announced transaction, so that peers can skip fetching ones they would not accept.
+#[derive(Clone, PartialEq, Debug)]
+pub struct NewPooledTransactionHashes68Message {
+    pub types: Vec<u8>,
+    pub sizes: Vec<u32>,
+    pub hashes: Vec<H256>,
+}
+
+impl rlp::Encodable for NewPooledTransactionHashes68Message {
+    fn rlp_append(&self, s: &mut rlp::RlpStream) {
+        s.begin_list(3);
+        s.append(&self.types);
+        s.append_list(&self.sizes);
+        s.append_list(&self.hashes);
+    }
+}
+
+impl rlp::Decodable for NewPooledTransactionHashes68Message {
+    fn decode(rlp: &rlp::Rlp) -> Result<Self, rlp::DecoderError> {
+        if rlp.item_count()? != 3 {
+            return Err(rlp::DecoderError::RlpIncorrectListLen);
+        }
+        let message = Self {
+            types: rlp.val_at(0)?,
+            sizes: rlp.list_at(1)?,
+            hashes: rlp.list_at(2)?,
+        };
+        if message.types.len() != message.hashes.len()
+            || message.sizes.len() != message.hashes.len()
+        {
+            return Err(rlp::DecoderError::Custom(
+                "announced types, sizes and hashes differ in length",
+            ));
+        }
+        Ok(message)
+    }
+}
+
 #[derive(
     rlp_derive::RlpEncodableWrapper, rlp_derive::RlpDecodableWrapper, Clone, PartialEq, Debug,
 )]
@@ -172,6 +211,54 @@ pub struct ForkIdentifier {
     pub fork_next: u64,
 }
 
+/// Latest version of eth protocol understood by message decoder.
+pub const ETH_PROTOCOL_VERSION: usize = EthVersion::Eth68 as usize;
+
+/// Version of eth protocol spoken with a peer, the highest one both sides advertise.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum EthVersion {
+    Eth66 = 66,
+    Eth67 = 67,
+    Eth68 = 68,
+}
+
+impl EthVersion {
+    pub const ALL: [Self; 3] = [Self::Eth66, Self::Eth67, Self::Eth68];
+
+    /// Highest version we speak among capabilities of a peer, such as `["eth/66", "eth/68"]`.
+    pub fn negotiate<S: AsRef<str>>(capabilities: &[S]) -> Option<Self> {
+        capabilities
+            .iter()
+            .filter_map(|capability| {
+                let version = capability
+                    .as_ref()
+                    .strip_prefix("eth/")?
+                    .parse::<usize>()
+                    .ok()?;
+                Self::ALL
+                    .into_iter()
+                    .find(|known| *known as usize == version)
+            })
+            .max()
+    }
+
+    /// Whether messages with `id` exist in this version.
+    pub fn supports(self, id: EthMessageId) -> bool {
+        // See EIP-4938.
+        self < Self::Eth67 || !matches!(id, EthMessageId::GetNodeData | EthMessageId::NodeData)
+    }
+
+    /// Whether `message` is valid for a peer speaking this version.
+    pub fn accepts(self, message: &Message) -> bool {
+        self.supports(message.eth_id())
+            && match message {
+                Message::NewPooledTransactionHashes(_) => self < Self::Eth68,
+                Message::NewPooledTransactionHashes68(_) => self >= Self::Eth68,
+                _ => true,
+            }
+    }
+}
+
 #[derive(rlp_derive::RlpEncodable, rlp_derive::RlpDecodable, Clone, PartialEq, Debug)]
 pub struct StatusMessage {
     pub protocol_version: usize,
@@ -188,6 +275,8 @@ pub struct StatusMessage {
 /// A full description of the [Eth/66 protocol](https://github.com/ethereum/devp2p/blob/master/caps/eth.md)
 /// can be found on the Ethereum foundation's Github.
 /// Eth/66 is an update to Eth/65. The changes from 65 to 66 are detailed in [EIP-2481](https://eips.ethereum.org/EIPS/eip-2481)
+/// Eth/67 only drops `GetNodeData` and `NodeData`, eth/68 changes transaction announcements,
+/// see [EIP-5793](https://eips.ethereum.org/EIPS/eip-5793).
 pub enum Message {
     Status(StatusMessage),
     NewBlockHashes(NewBlockHashesMessage),
@@ -195,6 +284,7 @@ pub enum Message {
     BlockHeaders(BlockHeadersMessage),
     NewBlock(NewBlockMessage),
     NewPooledTransactionHashes(NewPooledTransactionHashesMessage),
+    NewPooledTransactionHashes68(NewPooledTransactionHashes68Message),
     Transactions(TransactionsMessage),
     GetBlockBodies(GetBlockBodiesMessage),
     BlockBodies(BlockBodiesMessage),
@@ -214,7 +304,9 @@ impl Message {
             Message::GetBlockHeaders(_) => EthMessageId::GetBlockHeaders,
             Message::BlockHeaders(_) => EthMessageId::BlockHeaders,
             Message::NewBlock(_) => EthMessageId::NewBlock,
-            Message::NewPooledTransactionHashes(_) => EthMessageId::NewPooledTransactionHashes,
+            Message::NewPooledTransactionHashes(_) | Message::NewPooledTransactionHashes68(_) => {
+                EthMessageId::NewPooledTransactionHashes
+            }
             Message::Transactions(_) => EthMessageId::Transactions,
             Message::GetBlockBodies(_) => EthMessageId::GetBlockBodies,
             Message::BlockBodies(_) => EthMessageId::BlockBodies,
@@ -226,4 +318,82 @@ impl Message {
             Message::Receipts(_) => EthMessageId::Receipts,
         }
     }
+
+    /// Whether encoding or existence of this message depends on eth version of the peer.
+    pub fn is_versioned(&self) -> bool {
+        matches!(
+            self,
+            Message::NewPooledTransactionHashes(_)
+                | Message::NewPooledTransactionHashes68(_)
+                | Message::GetNodeData(_)
+                | Message::NodeData(_)
+        )
+    }
+
+    /// This message as sent to a peer speaking `version`, `None` if it can not be expressed
+    /// there.
+    ///
+    /// Transaction announcements of eth/68 lose their types and sizes for older peers. Plain
+    /// announcements are not sent to eth/68 peers, which get transactions in full instead.
+    pub fn for_version(&self, version: EthVersion) -> Option<Message> {
+        if !version.supports(self.eth_id()) {
+            return None;
+        }
+        match self {
+            Message::NewPooledTransactionHashes68(message) if version < EthVersion::Eth68 => Some(
+                Message::NewPooledTransactionHashes(NewPooledTransactionHashesMessage {
+                    ids: message.hashes.clone(),
+                }),
+            ),
+            Message::NewPooledTransactionHashes(_) if version >= EthVersion::Eth68 => None,
+            _ => Some(self.clone()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn negotiates_highest_common_version() {
+        assert_eq!(
+            EthVersion::negotiate(&["eth/66", "snap/1", "eth/68", "eth/69"]),
+            Some(EthVersion::Eth68)
+        );
+        assert_eq!(
+            EthVersion::negotiate(&["eth/65", "eth/66"]),
+            Some(EthVersion::Eth66)
+        );
+        assert_eq!(EthVersion::negotiate(&["eth/65", "les/4"]), None);
+    }
+
+    #[test]
+    fn translates_messages_between_versions() {
+        let eth68 = Message::NewPooledTransactionHashes68(NewPooledTransactionHashes68Message {
+            types: vec![2],
+            sizes: vec![120],
+            hashes: vec![H256::repeat_byte(1)],
+        });
+        let eth66 = Message::NewPooledTransactionHashes(NewPooledTransactionHashesMessage {
+            ids: vec![H256::repeat_byte(1)],
+        });
+        assert_eq!(eth68.for_version(EthVersion::Eth68), Some(eth68.clone()));
+        assert_eq!(eth68.for_version(EthVersion::Eth67), Some(eth66.clone()));
+        assert_eq!(eth66.for_version(EthVersion::Eth68), None);
+        assert!(EthVersion::Eth68.accepts(&eth68));
+        assert!(!EthVersion::Eth68.accepts(&eth66));
+        assert!(!EthVersion::Eth66.accepts(&eth68));
+
+        let get_node_data = Message::GetNodeData(GetNodeDataMessage {
+            request_id: 1,
+            hashes: vec![],
+        });
+        assert_eq!(
+            get_node_data.for_version(EthVersion::Eth66),
+            Some(get_node_data.clone())
+        );
+        assert_eq!(get_node_data.for_version(EthVersion::Eth67), None);
+        assert!(!EthVersion::Eth67.accepts(&get_node_data));
+    }
 }
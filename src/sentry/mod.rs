@@ -1,10 +1,16 @@
+pub mod block_gossip;
 pub mod block_id;
 pub mod chain_config;
-mod message_decoder;
+pub mod discovery;
+pub mod enr;
+pub mod message_decoder;
 pub mod messages;
+pub mod reputation;
+pub mod send_queue;
 pub mod sentry_address;
 pub mod sentry_client;
 pub mod sentry_client_connector;
 pub mod sentry_client_impl;
 pub mod sentry_client_mock;
 pub mod sentry_client_reactor;
+pub mod snap;
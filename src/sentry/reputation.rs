@@ -0,0 +1,218 @@
+//! Reputation of peers, lowered for every misbehaviour and slowly recovering over time.
+//!
+//! Peers whose score drops to [`BAN_THRESHOLD`] are disconnected and banned for
+//! [`BAN_DURATION`]. Bans are kept in a file, so that restarting the node does not let banned
+//! peers right back in.
+
+use super::sentry_client::PeerId;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fmt, fs, io,
+    path::PathBuf,
+    time::{Duration, Instant, SystemTime},
+};
+use tracing::*;
+
+/// Score of peers we know nothing bad about.
+const INITIAL_SCORE: i64 = 0;
+/// Peers scoring this or lower are banned.
+pub const BAN_THRESHOLD: i64 = -100;
+/// How long a banned peer stays banned.
+pub const BAN_DURATION: Duration = Duration::from_secs(60 * 60);
+/// Score regained per minute, up to the initial score.
+const RECOVERY_PER_MINUTE: i64 = 5;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Misbehaviour {
+    /// Headers that failed to verify.
+    InvalidHeaders,
+    /// Bodies not matching their headers.
+    InvalidBodies,
+    /// No response to a request addressed to the peer.
+    Timeout,
+    /// Malformed message, or one not allowed by the protocol.
+    ProtocolViolation,
+}
+
+impl Misbehaviour {
+    pub fn penalty(self) -> i64 {
+        match self {
+            Self::InvalidHeaders | Self::InvalidBodies => 50,
+            Self::Timeout => 10,
+            Self::ProtocolViolation => 100,
+        }
+    }
+}
+
+impl fmt::Display for Misbehaviour {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidHeaders => write!(f, "invalid headers"),
+            Self::InvalidBodies => write!(f, "invalid bodies"),
+            Self::Timeout => write!(f, "request timeout"),
+            Self::ProtocolViolation => write!(f, "protocol violation"),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Score {
+    value: i64,
+    updated: Instant,
+}
+
+impl Score {
+    fn recover(&mut self, now: Instant) {
+        let minutes = now.saturating_duration_since(self.updated).as_secs() / 60;
+        if minutes > 0 {
+            self.value = self
+                .value
+                .saturating_add(RECOVERY_PER_MINUTE.saturating_mul(minutes as i64))
+                .min(INITIAL_SCORE);
+            self.updated += Duration::from_secs(minutes * 60);
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct BannedPeer {
+    peer_id: PeerId,
+    /// Seconds since the Unix epoch.
+    until: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct PeerReputation {
+    scores: HashMap<PeerId, Score>,
+    bans: HashMap<PeerId, SystemTime>,
+    /// File the ban list is kept in, if any.
+    ban_list: Option<PathBuf>,
+}
+
+impl PeerReputation {
+    /// Reputation with bans loaded from `path`, saved back to it whenever they change.
+    pub fn load(path: PathBuf) -> anyhow::Result<Self> {
+        let bans = match fs::read(&path) {
+            Ok(data) => serde_json::from_slice::<Vec<BannedPeer>>(&data)?
+                .into_iter()
+                .map(|banned| {
+                    (
+                        banned.peer_id,
+                        SystemTime::UNIX_EPOCH + Duration::from_secs(banned.until),
+                    )
+                })
+                .collect(),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(Self {
+            scores: HashMap::new(),
+            bans,
+            ban_list: Some(path),
+        })
+    }
+
+    fn save(&self) -> anyhow::Result<()> {
+        if let Some(path) = &self.ban_list {
+            let bans = self
+                .bans
+                .iter()
+                .map(|(&peer_id, until)| BannedPeer {
+                    peer_id,
+                    until: until
+                        .duration_since(SystemTime::UNIX_EPOCH)
+                        .unwrap_or_default()
+                        .as_secs(),
+                })
+                .collect::<Vec<_>>();
+            fs::write(path, serde_json::to_vec_pretty(&bans)?)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn score(&mut self, peer: PeerId, now: Instant) -> i64 {
+        match self.scores.get_mut(&peer) {
+            Some(score) => {
+                score.recover(now);
+                score.value
+            }
+            None => INITIAL_SCORE,
+        }
+    }
+
+    pub fn is_banned(&self, peer: PeerId, now: SystemTime) -> bool {
+        self.bans.get(&peer).map_or(false, |until| now < *until)
+    }
+
+    /// Lower score of `peer` for `misbehaviour`, returns true if that got the peer banned.
+    pub fn report(
+        &mut self,
+        peer: PeerId,
+        misbehaviour: Misbehaviour,
+        now: Instant,
+        system_now: SystemTime,
+    ) -> bool {
+        let score = self.scores.entry(peer).or_insert(Score {
+            value: INITIAL_SCORE,
+            updated: now,
+        });
+        score.recover(now);
+        score.value = score.value.saturating_sub(misbehaviour.penalty());
+        if score.value > BAN_THRESHOLD {
+            return false;
+        }
+
+        // Banned peer starts over once the ban is lifted.
+        self.scores.remove(&peer);
+        self.bans.retain(|_, until| system_now < *until);
+        self.bans.insert(peer, system_now + BAN_DURATION);
+        // Ban holds until restart even if it could not be saved.
+        if let Err(e) = self.save() {
+            warn!("Failed to save ban list: {}", e);
+        }
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ban_and_recover() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("banned-peers.json");
+        let now = Instant::now();
+        let system_now = SystemTime::now();
+
+        let mut reputation = PeerReputation::load(path.clone()).unwrap();
+        let (slow, bad) = (PeerId::repeat_byte(1), PeerId::repeat_byte(2));
+
+        for _ in 0..4 {
+            assert!(!reputation.report(slow, Misbehaviour::Timeout, now, system_now));
+        }
+        assert_eq!(reputation.score(slow, now), -40);
+        // Recovers, but not above the initial score.
+        assert_eq!(
+            reputation.score(slow, now + Duration::from_secs(5 * 60)),
+            -15
+        );
+        assert_eq!(
+            reputation.score(slow, now + Duration::from_secs(60 * 60)),
+            0
+        );
+
+        assert!(reputation.report(bad, Misbehaviour::ProtocolViolation, now, system_now));
+        assert!(reputation.is_banned(bad, system_now));
+        assert!(!reputation.is_banned(slow, system_now));
+
+        // Ban outlives restarts, but not its duration.
+        let reputation = PeerReputation::load(path).unwrap();
+        assert!(reputation.is_banned(bad, system_now));
+        assert!(!reputation.is_banned(bad, system_now + BAN_DURATION + Duration::from_secs(1)));
+    }
+}
@@ -0,0 +1,365 @@
+use super::{messages::EthMessageId, sentry_client::PeerId};
+use parking_lot::Mutex;
+use std::collections::{HashMap, VecDeque};
+use tokio::sync::Notify;
+
+/// Priority class of an outbound message, highest first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum SendPriority {
+    /// Our own sync requests, block announcements and peer penalties.
+    Consensus = 0,
+    /// Responses serving headers, bodies and other data to peers.
+    Serving = 1,
+    /// Transaction gossip and peers to dial.
+    Gossip = 2,
+}
+
+/// What to do with a message when its queue is full.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DropPolicy {
+    /// Keep the message, the sender waits for a free slot.
+    Wait,
+    /// Drop the new message.
+    DropNewest,
+    /// Drop the oldest queued message to make room for the new one.
+    DropOldest,
+}
+
+impl SendPriority {
+    const ALL: [Self; 3] = [Self::Consensus, Self::Serving, Self::Gossip];
+
+    pub fn drop_policy(self) -> DropPolicy {
+        match self {
+            Self::Consensus => DropPolicy::Wait,
+            // A slow peer can re-request the data from someone else.
+            Self::Serving => DropPolicy::DropNewest,
+            // Newer gossip supersedes older one.
+            Self::Gossip => DropPolicy::DropOldest,
+        }
+    }
+
+    /// Number of messages queued per peer (or per broadcast) before the drop policy kicks in.
+    pub fn capacity(self) -> usize {
+        match self {
+            Self::Consensus => 16,
+            Self::Serving => 128,
+            Self::Gossip => 1024,
+        }
+    }
+}
+
+impl From<EthMessageId> for SendPriority {
+    fn from(id: EthMessageId) -> Self {
+        match id {
+            EthMessageId::Status
+            | EthMessageId::NewBlockHashes
+            | EthMessageId::NewBlock
+            | EthMessageId::GetBlockHeaders
+            | EthMessageId::GetBlockBodies
+            | EthMessageId::GetPooledTransactions
+            | EthMessageId::GetNodeData
+            | EthMessageId::GetReceipts => Self::Consensus,
+            EthMessageId::BlockHeaders
+            | EthMessageId::BlockBodies
+            | EthMessageId::PooledTransactions
+            | EthMessageId::NodeData
+            | EthMessageId::Receipts => Self::Serving,
+            EthMessageId::Transactions | EthMessageId::NewPooledTransactionHashes => Self::Gossip,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Pushed<T> {
+    Queued,
+    /// Queued, the returned oldest message was dropped to make room.
+    Replaced(T),
+    /// Queue is full, the message was not queued.
+    Full(T),
+    /// Queue is closed, the message was not queued.
+    Closed(T),
+}
+
+/// Queues of a single priority class, keyed by target peer
+/// (`None` for messages not aimed at a specific peer).
+struct Class<T> {
+    queues: HashMap<Option<PeerId>, VecDeque<T>>,
+    /// Keys with non-empty queues in round-robin order.
+    ready: VecDeque<Option<PeerId>>,
+}
+
+impl<T> Default for Class<T> {
+    fn default() -> Self {
+        Self {
+            queues: HashMap::new(),
+            ready: VecDeque::new(),
+        }
+    }
+}
+
+impl<T> Class<T> {
+    fn len(&self, peer: Option<PeerId>) -> usize {
+        self.queues.get(&peer).map(VecDeque::len).unwrap_or(0)
+    }
+
+    fn push(&mut self, peer: Option<PeerId>, item: T) {
+        let queue = self.queues.entry(peer).or_default();
+        if queue.is_empty() {
+            self.ready.push_back(peer);
+        }
+        queue.push_back(item);
+    }
+
+    fn pop(&mut self) -> Option<T> {
+        let peer = self.ready.pop_front()?;
+        let queue = self.queues.get_mut(&peer)?;
+        let item = queue.pop_front();
+        if queue.is_empty() {
+            self.queues.remove(&peer);
+        } else {
+            self.ready.push_back(peer);
+        }
+        item
+    }
+}
+
+struct Queues<T> {
+    classes: [Class<T>; 3],
+    closed: bool,
+}
+
+/// Outbound messages, split into priority classes with a bounded queue per peer.
+///
+/// [`Self::pop`] always takes the highest priority message available, round-robin across peers
+/// within a class, so that serving data to slow peers or gossiping never delays our own sync
+/// requests.
+pub struct SendQueue<T> {
+    queues: Mutex<Queues<T>>,
+    pushed: Notify,
+    popped: Notify,
+}
+
+impl<T> Default for SendQueue<T> {
+    fn default() -> Self {
+        Self {
+            queues: Mutex::new(Queues {
+                classes: Default::default(),
+                closed: false,
+            }),
+            pushed: Notify::new(),
+            popped: Notify::new(),
+        }
+    }
+}
+
+impl<T> SendQueue<T> {
+    /// Queue message without waiting, applying the drop policy of its priority if queue is full.
+    pub fn try_push(&self, priority: SendPriority, peer: Option<PeerId>, item: T) -> Pushed<T> {
+        let mut queues = self.queues.lock();
+        if queues.closed {
+            return Pushed::Closed(item);
+        }
+
+        let class = &mut queues.classes[priority as usize];
+        let pushed = if class.len(peer) < priority.capacity() {
+            class.push(peer, item);
+            Pushed::Queued
+        } else {
+            match priority.drop_policy() {
+                DropPolicy::Wait | DropPolicy::DropNewest => return Pushed::Full(item),
+                DropPolicy::DropOldest => {
+                    let queue = class.queues.get_mut(&peer).unwrap();
+                    let oldest = queue.pop_front().unwrap();
+                    queue.push_back(item);
+                    Pushed::Replaced(oldest)
+                }
+            }
+        };
+        drop(queues);
+
+        self.pushed.notify_waiters();
+        pushed
+    }
+
+    /// Queue message, waiting for a free slot if its priority class does not allow dropping.
+    pub async fn push(
+        &self,
+        priority: SendPriority,
+        peer: Option<PeerId>,
+        mut item: T,
+    ) -> Pushed<T> {
+        loop {
+            let popped = self.popped.notified();
+            match self.try_push(priority, peer, item) {
+                Pushed::Full(rejected) if priority.drop_policy() == DropPolicy::Wait => {
+                    item = rejected;
+                    popped.await;
+                }
+                other => return other,
+            }
+        }
+    }
+
+    /// Wait until a message of given priority for given peer can be queued without dropping
+    /// or waiting.
+    /// Returns `false` if the queue is closed.
+    pub async fn wait_for_capacity(&self, priority: SendPriority, peer: Option<PeerId>) -> bool {
+        loop {
+            let popped = self.popped.notified();
+            {
+                let queues = self.queues.lock();
+                if queues.closed {
+                    return false;
+                }
+                if queues.classes[priority as usize].len(peer) < priority.capacity() {
+                    return true;
+                }
+            }
+            popped.await;
+        }
+    }
+
+    /// Take the highest priority message without waiting.
+    pub fn try_pop(&self) -> Option<T> {
+        let item = {
+            let mut queues = self.queues.lock();
+            SendPriority::ALL
+                .iter()
+                .find_map(|&priority| queues.classes[priority as usize].pop())
+        };
+        if item.is_some() {
+            self.popped.notify_waiters();
+        }
+        item
+    }
+
+    /// Wait for the highest priority message. Returns `None` once the queue is closed.
+    pub async fn pop(&self) -> Option<T> {
+        loop {
+            let pushed = self.pushed.notified();
+            if let Some(item) = self.try_pop() {
+                return Some(item);
+            }
+            if self.is_closed() {
+                return None;
+            }
+            pushed.await;
+        }
+    }
+
+    /// Stop accepting messages and wake up everyone waiting.
+    pub fn close(&self) {
+        self.queues.lock().closed = true;
+        self.pushed.notify_waiters();
+        self.popped.notify_waiters();
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.queues.lock().closed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn classifies_messages() {
+        assert_eq!(
+            SendPriority::from(EthMessageId::GetBlockHeaders),
+            SendPriority::Consensus
+        );
+        assert_eq!(
+            SendPriority::from(EthMessageId::BlockBodies),
+            SendPriority::Serving
+        );
+        assert_eq!(
+            SendPriority::from(EthMessageId::Transactions),
+            SendPriority::Gossip
+        );
+    }
+
+    #[test]
+    fn pops_by_priority_then_round_robin() {
+        let queue = SendQueue::default();
+        let (a, b) = (Some(PeerId::repeat_byte(1)), Some(PeerId::repeat_byte(2)));
+
+        queue.try_push(SendPriority::Gossip, None, "gossip");
+        queue.try_push(SendPriority::Serving, a, "a1");
+        queue.try_push(SendPriority::Serving, a, "a2");
+        queue.try_push(SendPriority::Serving, b, "b1");
+        queue.try_push(SendPriority::Consensus, None, "request");
+
+        let popped = std::iter::from_fn(|| queue.try_pop()).collect::<Vec<_>>();
+        assert_eq!(popped, ["request", "a1", "b1", "a2", "gossip"]);
+    }
+
+    #[test]
+    fn drop_policies() {
+        let queue = SendQueue::default();
+        let slow = Some(PeerId::repeat_byte(1));
+
+        for i in 0..SendPriority::Serving.capacity() {
+            assert_eq!(
+                queue.try_push(SendPriority::Serving, slow, i),
+                Pushed::Queued
+            );
+        }
+        assert_eq!(
+            queue.try_push(SendPriority::Serving, slow, 1000),
+            Pushed::Full(1000)
+        );
+        // Other peers are not affected by the slow one.
+        assert_eq!(
+            queue.try_push(SendPriority::Serving, Some(PeerId::repeat_byte(2)), 1000),
+            Pushed::Queued
+        );
+
+        for i in 0..SendPriority::Gossip.capacity() {
+            assert_eq!(
+                queue.try_push(SendPriority::Gossip, None, i),
+                Pushed::Queued
+            );
+        }
+        assert_eq!(
+            queue.try_push(SendPriority::Gossip, None, 1000),
+            Pushed::Replaced(0)
+        );
+
+        for i in 0..SendPriority::Consensus.capacity() {
+            assert_eq!(
+                queue.try_push(SendPriority::Consensus, None, i),
+                Pushed::Queued
+            );
+        }
+        assert_eq!(
+            queue.try_push(SendPriority::Consensus, None, 1000),
+            Pushed::Full(1000)
+        );
+    }
+
+    #[tokio::test]
+    async fn consensus_push_waits_for_capacity() {
+        let queue = SendQueue::default();
+        for i in 0..SendPriority::Consensus.capacity() {
+            queue.try_push(SendPriority::Consensus, None, i);
+        }
+
+        let push = queue.push(SendPriority::Consensus, None, 1000);
+        tokio::pin!(push);
+        assert!(tokio::time::timeout(Duration::from_millis(50), &mut push)
+            .await
+            .is_err());
+
+        assert_eq!(queue.pop().await, Some(0));
+        assert_eq!(push.await, Pushed::Queued);
+
+        queue.close();
+        assert_eq!(
+            queue.try_push(SendPriority::Gossip, None, 0),
+            Pushed::Closed(0)
+        );
+        assert!(!queue.wait_for_capacity(SendPriority::Consensus, None).await);
+    }
+}
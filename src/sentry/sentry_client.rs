@@ -1,11 +1,15 @@
 use super::{
     chain_config::ChainConfig,
-    messages::{EthMessageId, Message},
+    messages::{EthMessageId, EthVersion, Message},
 };
 use crate::models::*;
 use async_trait::async_trait;
 use futures_core::Stream;
-use std::{fmt::Debug, pin::Pin};
+use rlp::DecoderError;
+use std::{
+    fmt::{self, Debug},
+    pin::Pin,
+};
 
 #[derive(Clone, Debug)]
 pub struct Status {
@@ -25,15 +29,75 @@ pub enum PeerFilter {
     All,
 }
 
+/// Change in peers of the sentry.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PeerEvent {
+    /// Peer connected, or was already connected when we subscribed, and speaks `version`.
+    Connected {
+        peer_id: PeerId,
+        version: EthVersion,
+    },
+    Disconnected(PeerId),
+}
+
 #[derive(Clone, Debug)]
 pub struct MessageFromPeer {
     pub message: Message,
     pub from_peer_id: Option<PeerId>,
 }
 
+/// What a message failing to decode says about the peer that sent it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DecodeFault {
+    /// Well-framed RLP of the wrong shape or with non-canonical encoding, which no honest peer
+    /// sends.
+    ProtocolViolation,
+    /// Declared lengths not matching the data, as in a damaged or truncated message.
+    Corruption,
+}
+
+impl From<&DecoderError> for DecodeFault {
+    fn from(error: &DecoderError) -> Self {
+        match error {
+            DecoderError::RlpIsTooShort
+            | DecoderError::RlpInconsistentLengthAndData
+            | DecoderError::RlpInvalidLength => Self::Corruption,
+            _ => Self::ProtocolViolation,
+        }
+    }
+}
+
+/// Message received from a peer which could not be decoded.
+#[derive(Debug)]
+pub struct InvalidMessage {
+    pub id: EthMessageId,
+    pub from_peer_id: Option<PeerId>,
+    pub error: DecoderError,
+}
+
+impl InvalidMessage {
+    pub fn fault(&self) -> DecodeFault {
+        DecodeFault::from(&self.error)
+    }
+}
+
+impl fmt::Display for InvalidMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid {:?} message from {:?}: {}",
+            self.id, self.from_peer_id, self.error
+        )
+    }
+}
+
+impl std::error::Error for InvalidMessage {}
+
 pub type MessageFromPeerStream =
     Pin<Box<dyn Stream<Item = anyhow::Result<MessageFromPeer>> + Send>>;
 
+pub type PeerEventStream = Pin<Box<dyn Stream<Item = anyhow::Result<PeerEvent>> + Send>>;
+
 #[async_trait]
 pub trait SentryClient: Send + Debug {
     async fn set_status(&mut self, status: Status) -> anyhow::Result<()>;
@@ -50,4 +114,10 @@ pub trait SentryClient: Send + Debug {
         &mut self,
         filter_ids: &[EthMessageId],
     ) -> anyhow::Result<MessageFromPeerStream>;
+
+    /// Peers connected at the time of the call, followed by later connects and disconnects.
+    async fn peer_events(&mut self) -> anyhow::Result<PeerEventStream>;
+
+    /// Ask the sentry to dial the node with `enode` URL. Returns whether it was accepted.
+    async fn add_peer(&mut self, enode: String) -> anyhow::Result<bool>;
 }
@@ -145,7 +145,16 @@ impl SentryClient for SentryClientImpl {
                     let grpc_peer_id: Option<grpc_types::H512> = inbound_message.peer_id;
                     let peer_id: Option<PeerId> = grpc_peer_id.map(H512::from);
                     let message_bytes: bytes::Bytes = inbound_message.data;
-                    let message = message_decoder::decode_rlp_message(message_id, message_bytes.as_ref())?;
+                    let message = message_decoder::decode_rlp_message(message_id, message_bytes.as_ref())
+                        .map_err(|error| match error.downcast::<rlp::DecoderError>() {
+                            Ok(error) => anyhow::Error::new(InvalidMessage {
+                                id: message_id,
+                                from_peer_id: peer_id,
+                                error,
+                            }),
+                            // Not a message we decode.
+                            Err(error) => error,
+                        })?;
                     let message_from_peer = MessageFromPeer {
                         message,
                         from_peer_id: peer_id,
@@ -166,6 +175,94 @@ impl SentryClient for SentryClientImpl {
         });
         Ok(Box::pin(stream))
     }
+
+    async fn peer_events(&mut self) -> anyhow::Result<PeerEventStream> {
+        // Subscribe before listing peers, so that no peer connecting in between is missed.
+        let mut events = self
+            .client
+            .peer_events(tonic::Request::new(grpc_sentry::PeerEventsRequest {}))
+            .await?
+            .into_inner();
+        let peers = self
+            .client
+            .peers(tonic::Request::new(()))
+            .await?
+            .into_inner()
+            .peers;
+        let mut client = self.client.clone();
+
+        let stream = async_stream::stream! {
+            for peer in &peers {
+                if let Some(event) = connected_event(peer) {
+                    yield Ok(event);
+                }
+            }
+
+            loop {
+                let event = match events.message().await {
+                    Ok(Some(event)) => event,
+                    Ok(None) => break,
+                    Err(status) => {
+                        yield Err(anyhow::Error::new(status));
+                        break;
+                    }
+                };
+                let peer_id = match event.peer_id {
+                    Some(peer_id) => H512::from(peer_id),
+                    None => continue,
+                };
+                match grpc_sentry::peer_event::PeerEventId::from_i32(event.event_id) {
+                    Some(grpc_sentry::peer_event::PeerEventId::Connect) => {
+                        let request = grpc_sentry::PeerByIdRequest {
+                            peer_id: Some(grpc_types::H512::from(peer_id)),
+                        };
+                        match client.peer_by_id(tonic::Request::new(request)).await {
+                            Ok(reply) => {
+                                if let Some(event) = reply.into_inner().peer.as_ref().and_then(connected_event) {
+                                    yield Ok(event);
+                                }
+                            }
+                            Err(status) => {
+                                yield Err(anyhow::Error::new(status));
+                                break;
+                            }
+                        }
+                    }
+                    Some(grpc_sentry::peer_event::PeerEventId::Disconnect) => {
+                        yield Ok(PeerEvent::Disconnected(peer_id));
+                    }
+                    None => {
+                        debug!("SentryClient peer_events got an invalid PeerEventId {}", event.event_id);
+                    }
+                }
+            }
+        };
+        Ok(Box::pin(stream))
+    }
+
+    async fn add_peer(&mut self, enode: String) -> anyhow::Result<bool> {
+        let request = grpc_sentry::AddPeerRequest { url: enode };
+        let reply = self
+            .client
+            .add_peer(tonic::Request::new(request))
+            .await?
+            .into_inner();
+        Ok(reply.success)
+    }
+}
+
+/// `Connected` event of `peer`, if it speaks a version of eth protocol we know.
+fn connected_event(peer: &grpc_types::PeerInfo) -> Option<PeerEvent> {
+    // enode://<public key>@<address>
+    let peer_id = peer
+        .enode
+        .strip_prefix("enode://")?
+        .split('@')
+        .next()?
+        .parse::<PeerId>()
+        .ok()?;
+    let version = EthVersion::negotiate(&peer.caps)?;
+    Some(PeerEvent::Connected { peer_id, version })
 }
 
 fn tonic_stream_fuse_on_error<T: 'static + Send>(
@@ -1,6 +1,8 @@
 use super::{
     messages::{EthMessageId, Message},
-    sentry_client::{MessageFromPeer, MessageFromPeerStream, PeerFilter, SentryClient, Status},
+    sentry_client::{
+        MessageFromPeer, MessageFromPeerStream, PeerEventStream, PeerFilter, SentryClient, Status,
+    },
 };
 use crate::{
     models::{BlockHeader, BlockNumber},
@@ -36,7 +38,9 @@ impl SentryClientMock {
     }
 
     pub fn add_block_headers(&mut self, headers: Vec<BlockHeader>) {
-        let Some(first_header) = headers.first() else { return };
+        let Some(first_header) = headers.first() else {
+            return;
+        };
         let start_block_num = first_header.number;
         self.block_headers.insert(start_block_num, headers);
     }
@@ -59,6 +63,10 @@ impl SentryClient for SentryClientMock {
         Ok(())
     }
 
+    async fn add_peer(&mut self, _enode: String) -> anyhow::Result<bool> {
+        Ok(true)
+    }
+
     async fn send_message(
         &mut self,
         message: Message,
@@ -71,7 +79,9 @@ impl SentryClient for SentryClientMock {
         match message {
             Message::GetBlockHeaders(request) => {
                 let BlockId::Number(start_block_num) = request.params.start_block else {
-                    anyhow::bail!("SentryClientMock::send_message unsupported GetBlockHeaders by hash");
+                    anyhow::bail!(
+                        "SentryClientMock::send_message unsupported GetBlockHeaders by hash"
+                    );
                 };
                 let block_headers = &mut self.block_headers;
                 let Some(headers) = block_headers.remove(&start_block_num) else {
@@ -134,6 +144,10 @@ impl SentryClient for SentryClientMock {
             anyhow::bail!("SentryClientMock::receive_messages supports only one receiver")
         }
     }
+
+    async fn peer_events(&mut self) -> anyhow::Result<PeerEventStream> {
+        Ok(Box::pin(tokio_stream::empty()))
+    }
 }
 
 impl Default for SentryClientMock {
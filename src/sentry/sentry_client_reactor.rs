@@ -1,17 +1,23 @@
 use super::{
-    messages::{EthMessageId, Message},
+    discovery::NodeRecord,
+    messages::{EthMessageId, EthVersion, Message},
+    reputation::{Misbehaviour, PeerReputation},
+    send_queue::{Pushed, SendPriority, SendQueue},
     sentry_client::*,
     sentry_client_connector,
 };
 use futures_core::{Future, Stream};
-use futures_util::{FutureExt, TryStreamExt};
-use parking_lot::RwLock;
+use futures_util::TryStreamExt;
+use parking_lot::{Mutex as SyncMutex, RwLock};
+use rand::seq::IteratorRandom;
 use std::{
     collections::HashMap,
     fmt,
     fmt::{Debug, Formatter},
+    path::PathBuf,
     pin::Pin,
     sync::Arc,
+    time::{Instant, SystemTime},
 };
 use strum::IntoEnumIterator;
 use tokio::{
@@ -29,9 +35,14 @@ pub type SentryClientReactorShared = Arc<tokio::sync::RwLock<SentryClientReactor
 type ReceiveMessagesSenders =
     Arc<RwLock<HashMap<EthMessageId, broadcast::Sender<MessageFromPeer>>>>;
 
+/// Eth protocol versions of connected peers, as reported by the sentry.
+type PeerVersions = Arc<RwLock<HashMap<PeerId, EthVersion>>>;
+
 pub struct SentryClientReactor {
-    send_message_sender: mpsc::Sender<SentryCommand>,
+    send_queue: Arc<SendQueue<SentryCommand>>,
     receive_messages_senders: ReceiveMessagesSenders,
+    reputation: Arc<SyncMutex<PeerReputation>>,
+    peer_versions: PeerVersions,
     event_loop: Mutex<Option<SentryClientReactorEventLoop>>,
     event_loop_handle: Option<JoinHandle<()>>,
     stop_signal_sender: mpsc::Sender<()>,
@@ -39,8 +50,10 @@ pub struct SentryClientReactor {
 
 struct SentryClientReactorEventLoop {
     sentry_connector: sentry_client_connector::SentryClientConnectorStream,
-    send_message_receiver: mpsc::Receiver<SentryCommand>,
+    send_queue: Arc<SendQueue<SentryCommand>>,
     receive_messages_senders: ReceiveMessagesSenders,
+    reputation: Arc<SyncMutex<PeerReputation>>,
+    peer_versions: PeerVersions,
     stop_signal_receiver: mpsc::Receiver<()>,
 }
 
@@ -48,6 +61,28 @@ struct SentryClientReactorEventLoop {
 enum SentryCommand {
     SendMessage(SendMessageParams),
     PenalizePeer(PeerId),
+    AddPeer(NodeRecord),
+}
+
+impl SentryCommand {
+    fn priority(&self) -> SendPriority {
+        match self {
+            Self::SendMessage(params) => params.message.eth_id().into(),
+            Self::PenalizePeer(_) => SendPriority::Consensus,
+            Self::AddPeer(_) => SendPriority::Gossip,
+        }
+    }
+
+    /// Peer whose queue this command goes to, `None` if it is not aimed at a specific peer.
+    fn peer(&self) -> Option<PeerId> {
+        match self {
+            Self::SendMessage(SendMessageParams {
+                peer_filter: PeerFilter::PeerId(peer_id),
+                ..
+            }) => Some(*peer_id),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -78,7 +113,7 @@ impl SentryClientReactor {
         let sentry_connector_stream =
             sentry_client_connector::make_connector_stream(sentry_connector, current_status_stream);
 
-        let (send_message_sender, send_message_receiver) = mpsc::channel::<SentryCommand>(1);
+        let send_queue = Arc::new(SendQueue::default());
 
         let mut receive_messages_senders =
             HashMap::<EthMessageId, broadcast::Sender<MessageFromPeer>>::new();
@@ -88,24 +123,37 @@ impl SentryClientReactor {
         }
         let receive_messages_senders = Arc::new(RwLock::new(receive_messages_senders));
 
+        let reputation = Arc::new(SyncMutex::new(PeerReputation::default()));
+        let peer_versions = PeerVersions::default();
+
         let (stop_signal_sender, stop_signal_receiver) = mpsc::channel::<()>(1);
 
         let event_loop = SentryClientReactorEventLoop {
             sentry_connector: sentry_connector_stream,
-            send_message_receiver,
+            send_queue: Arc::clone(&send_queue),
             receive_messages_senders: Arc::clone(&receive_messages_senders),
+            reputation: Arc::clone(&reputation),
+            peer_versions: Arc::clone(&peer_versions),
             stop_signal_receiver,
         };
 
         Self {
-            send_message_sender,
+            send_queue,
             receive_messages_senders: Arc::clone(&receive_messages_senders),
+            reputation,
+            peer_versions,
             event_loop: Mutex::new(Some(event_loop)),
             event_loop_handle: None,
             stop_signal_sender,
         }
     }
 
+    /// Keep bans of misbehaving peers in `path`, and load the ones made before.
+    pub fn with_ban_list(self, path: PathBuf) -> anyhow::Result<Self> {
+        *self.reputation.lock() = PeerReputation::load(path)?;
+        Ok(self)
+    }
+
     pub fn into_shared(self) -> SentryClientReactorShared {
         Arc::new(tokio::sync::RwLock::new(self))
     }
@@ -141,16 +189,82 @@ impl SentryClientReactor {
     }
 
     fn is_stopped(&self) -> bool {
-        matches!(
-            self.send_message_sender.try_reserve(),
-            Err(mpsc::error::TrySendError::Closed(_))
-        )
+        self.send_queue.is_closed()
+    }
+
+    fn handle_pushed(pushed: Pushed<SentryCommand>) -> anyhow::Result<()> {
+        match pushed {
+            Pushed::Queued => Ok(()),
+            Pushed::Replaced(dropped) => {
+                debug!(
+                    "SentryClientReactor send queue full, dropping oldest {:?}",
+                    dropped
+                );
+                Ok(())
+            }
+            Pushed::Full(command) => match command.priority() {
+                SendPriority::Consensus => Err(anyhow::Error::new(SendMessageError::SendQueueFull)),
+                _ => {
+                    debug!(
+                        "SentryClientReactor send queue of slow peer {:?} full, dropping {:?}",
+                        command.peer(),
+                        command
+                    );
+                    Ok(())
+                }
+            },
+            Pushed::Closed(_) => Err(anyhow::Error::new(SendMessageError::ReactorStopped)),
+        }
+    }
+
+    async fn send_command(&self, command: SentryCommand) -> anyhow::Result<()> {
+        let pushed = self
+            .send_queue
+            .push(command.priority(), command.peer(), command)
+            .await;
+        Self::handle_pushed(pushed)
+    }
+
+    /// Version of eth protocol spoken with `peer_id`, if it is connected and known.
+    pub fn peer_version(&self, peer_id: PeerId) -> Option<EthVersion> {
+        self.peer_versions.read().get(&peer_id).copied()
     }
 
     pub async fn penalize_peer(&self, peer_id: PeerId) -> anyhow::Result<()> {
-        let command = SentryCommand::PenalizePeer(peer_id);
-        let result = self.send_message_sender.send(command).await;
-        result.map_err(|_| anyhow::Error::new(SendMessageError::ReactorStopped))
+        self.send_command(SentryCommand::PenalizePeer(peer_id))
+            .await
+    }
+
+    /// Number of peers connected to the sentry.
+    pub fn peer_count(&self) -> usize {
+        self.peer_versions.read().len()
+    }
+
+    /// Ask the sentry to dial `node`, unless it is connected already or banned.
+    pub async fn add_peer(&self, node: NodeRecord) -> anyhow::Result<()> {
+        if self.peer_versions.read().contains_key(&node.id)
+            || self.reputation.lock().is_banned(node.id, SystemTime::now())
+        {
+            return Ok(());
+        }
+        self.send_command(SentryCommand::AddPeer(node)).await
+    }
+
+    /// Lower reputation of `peer_id`, disconnecting it if that gets it banned.
+    pub async fn report_peer(
+        &self,
+        peer_id: PeerId,
+        misbehaviour: Misbehaviour,
+    ) -> anyhow::Result<()> {
+        let banned =
+            self.reputation
+                .lock()
+                .report(peer_id, misbehaviour, Instant::now(), SystemTime::now());
+        if banned {
+            info!("Banning peer {:?} for {}", peer_id, misbehaviour);
+            self.penalize_peer(peer_id).await?;
+        }
+        Ok(())
     }
 
     pub async fn send_message(
@@ -162,9 +276,7 @@ impl SentryClientReactor {
             message,
             peer_filter,
         };
-        let command = SentryCommand::SendMessage(params);
-        let result = self.send_message_sender.send(command).await;
-        result.map_err(|_| anyhow::Error::new(SendMessageError::ReactorStopped))
+        self.send_command(SentryCommand::SendMessage(params)).await
     }
 
     pub fn try_send_message(
@@ -177,26 +289,27 @@ impl SentryClientReactor {
             peer_filter,
         };
         let command = SentryCommand::SendMessage(params);
-        let result = self.send_message_sender.try_send(command);
-        match result {
-            Err(mpsc::error::TrySendError::Full(_)) => {
-                Err(anyhow::Error::new(SendMessageError::SendQueueFull))
-            }
-            Err(mpsc::error::TrySendError::Closed(_)) => {
-                Err(anyhow::Error::new(SendMessageError::ReactorStopped))
-            }
-            Ok(_) => Ok(()),
-        }
+        Self::handle_pushed(
+            self.send_queue
+                .try_push(command.priority(), command.peer(), command),
+        )
     }
 
+    /// Wait until a request not aimed at a specific peer can be sent without waiting.
     pub fn reserve_capacity_in_send_queue(
         &self,
     ) -> Pin<Box<dyn Future<Output = anyhow::Result<()>> + Send>> {
-        let sender = self.send_message_sender.clone();
-        Box::pin(sender.reserve_owned().map(|result| match result {
-            Ok(_) => Ok(()),
-            Err(_) => Err(anyhow::Error::new(SendMessageError::ReactorStopped)),
-        }))
+        let send_queue = Arc::clone(&self.send_queue);
+        Box::pin(async move {
+            if send_queue
+                .wait_for_capacity(SendPriority::Consensus, None)
+                .await
+            {
+                Ok(())
+            } else {
+                Err(anyhow::Error::new(SendMessageError::ReactorStopped))
+            }
+        })
     }
 
     pub fn receive_messages(
@@ -261,6 +374,7 @@ enum EventLoopStreamId {
     Sentry,
     Send,
     Receive,
+    PeerEvents,
     Stop,
 }
 
@@ -269,9 +383,51 @@ enum EventLoopStreamResult {
     Sentry(Box<dyn SentryClient>),
     Send(u32),
     Receive(MessageFromPeer),
+    PeerEvent(PeerEvent),
     Stop(()),
 }
 
+/// Sends to the sentry that sending `message` to `peer_filter` takes, given eth versions of
+/// connected peers.
+///
+/// Messages that are the same in every version are passed through. The rest are sent to every
+/// peer separately in the form of its version, or not at all if its version lacks them. Peers
+/// whose version is not known yet are taken to speak eth/66. Block heights of peers are not
+/// known here, so `MinBlock` picks a random peer.
+fn versioned_sends(
+    message: Message,
+    peer_filter: PeerFilter,
+    peer_versions: &HashMap<PeerId, EthVersion>,
+) -> Vec<(Message, PeerFilter)> {
+    if !message.is_versioned() {
+        return vec![(message, peer_filter)];
+    }
+
+    let connected = peer_versions
+        .iter()
+        .map(|(peer_id, version)| (*peer_id, *version));
+    let peers = match peer_filter {
+        PeerFilter::PeerId(peer_id) => vec![(
+            peer_id,
+            peer_versions
+                .get(&peer_id)
+                .copied()
+                .unwrap_or(EthVersion::Eth66),
+        )],
+        PeerFilter::MinBlock(_) => connected.choose_multiple(&mut rand::thread_rng(), 1),
+        PeerFilter::Random(max_peers) => {
+            connected.choose_multiple(&mut rand::thread_rng(), max_peers as usize)
+        }
+        PeerFilter::All => connected.collect(),
+    };
+    peers
+        .into_iter()
+        .filter_map(|(peer_id, version)| {
+            Some((message.for_version(version)?, PeerFilter::PeerId(peer_id)))
+        })
+        .collect()
+}
+
 type EventLoopStream = Pin<Box<dyn Stream<Item = anyhow::Result<EventLoopStreamResult>> + Send>>;
 
 // dropping this causes dropping the senders and triggers an end of stream event on the receivers end
@@ -300,9 +456,17 @@ impl Drop for EventLoopReceiveMessagesSendersDropper {
     }
 }
 
+// closing the send queue makes pending and future sends fail with ReactorStopped
+struct EventLoopSendQueueCloser(Arc<SendQueue<SentryCommand>>);
+
+impl Drop for EventLoopSendQueueCloser {
+    fn drop(&mut self) {
+        self.0.close();
+    }
+}
+
 mod stream_factory {
     use super::super::{sentry_client::MessageFromPeerStream, sentry_client_reactor::*};
-    use tokio::sync::Mutex;
 
     fn make_receive_stream(
         mut stream: MessageFromPeerStream,
@@ -327,35 +491,40 @@ mod stream_factory {
     async fn send_sentry_command(
         command: SentryCommand,
         sentry: &mut Box<dyn SentryClient>,
+        peer_versions: &PeerVersions,
     ) -> anyhow::Result<u32> {
         match command {
             SentryCommand::SendMessage(params) => {
-                sentry
-                    .send_message(params.message, params.peer_filter)
-                    .await
+                let sends =
+                    versioned_sends(params.message, params.peer_filter, &peer_versions.read());
+                let mut sent_peers_count = 0;
+                for (message, peer_filter) in sends {
+                    sent_peers_count += sentry.send_message(message, peer_filter).await?;
+                }
+                Ok(sent_peers_count)
             }
             SentryCommand::PenalizePeer(peer_id) => {
                 // this is sent to a single peer (1)
                 sentry.penalize_peer(peer_id).await.map(|_| 1)
             }
+            SentryCommand::AddPeer(node) => {
+                let added = sentry.add_peer(node.to_string()).await?;
+                if !added {
+                    debug!("Sentry did not accept peer {}", node);
+                }
+                Ok(added as u32)
+            }
         }
     }
 
     fn make_send_stream(
-        send_message_receiver: Arc<Mutex<mpsc::Receiver<SentryCommand>>>,
+        send_queue: Arc<SendQueue<SentryCommand>>,
         mut sentry: Box<dyn SentryClient>,
+        peer_versions: PeerVersions,
     ) -> EventLoopStream {
         let send_stream = async_stream::stream! {
-            let receiver_lock_result = send_message_receiver.try_lock();
-            if receiver_lock_result.is_err() {
-                error!("SentryClientReactor.EventLoop send_stream failed to access send_message_receiver");
-                assert!(false);
-                return;
-            }
-
-            let mut receiver = receiver_lock_result.ok().unwrap();
-            while let Some(command) = receiver.recv().await {
-                let send_result = send_sentry_command(command, &mut sentry).await;
+            while let Some(command) = send_queue.pop().await {
+                let send_result = send_sentry_command(command, &mut sentry, &peer_versions).await;
                 yield send_result;
             }
         };
@@ -364,19 +533,65 @@ mod stream_factory {
 
     pub(super) async fn make_sentry_streams(
         mut sentry: Box<dyn SentryClient>,
-        send_message_receiver: Arc<Mutex<mpsc::Receiver<SentryCommand>>>,
+        send_queue: Arc<SendQueue<SentryCommand>>,
         receive_messages_senders_dropper: EventLoopReceiveMessagesSendersDropper,
-    ) -> anyhow::Result<(EventLoopStream, EventLoopStream)> {
+        peer_versions: PeerVersions,
+    ) -> anyhow::Result<(EventLoopStream, EventLoopStream, EventLoopStream)> {
         // subscribe to incoming messages
         let stream = sentry.receive_messages(&[]).await?;
         let receive_stream = make_receive_stream(stream, receive_messages_senders_dropper);
 
-        let send_stream = make_send_stream(send_message_receiver, sentry);
-        Ok((send_stream, receive_stream))
+        // Without peer events every peer is taken to speak eth/66.
+        let peer_events_stream: EventLoopStream = match sentry.peer_events().await {
+            Ok(stream) => Box::pin(stream.map_ok(EventLoopStreamResult::PeerEvent)),
+            Err(error) => {
+                warn!(
+                    "SentryClientReactor failed to subscribe to peer events: {}",
+                    error
+                );
+                Box::pin(tokio_stream::empty())
+            }
+        };
+
+        let send_stream = make_send_stream(send_queue, sentry, peer_versions);
+        Ok((send_stream, receive_stream, peer_events_stream))
     }
 }
 
 impl SentryClientReactorEventLoop {
+    /// Lower reputation of `peer_id` for a message it should not have sent, kicking it if that
+    /// gets it banned.
+    async fn report_protocol_violation(
+        reputation: &SyncMutex<PeerReputation>,
+        send_queue: &SendQueue<SentryCommand>,
+        peer_id: PeerId,
+        reason: &dyn fmt::Display,
+    ) {
+        let banned = reputation.lock().report(
+            peer_id,
+            Misbehaviour::ProtocolViolation,
+            Instant::now(),
+            SystemTime::now(),
+        );
+        if banned {
+            info!("Banning peer {:?} for {}", peer_id, reason);
+            Self::kick_peer(send_queue, peer_id).await;
+        }
+    }
+
+    async fn kick_peer(send_queue: &SendQueue<SentryCommand>, peer_id: PeerId) {
+        let command = SentryCommand::PenalizePeer(peer_id);
+        let pushed = send_queue
+            .push(command.priority(), command.peer(), command)
+            .await;
+        if let Err(error) = SentryClientReactor::handle_pushed(pushed) {
+            debug!(
+                "SentryClientReactor.EventLoop failed to kick {:?}: {}",
+                peer_id, error
+            );
+        }
+    }
+
     async fn run(self) -> anyhow::Result<()> {
         // When the reactor loop stops/aborts (e.g. after calling stop())
         // we need to ensure unblocking the subscribers which called receive_messages().
@@ -385,7 +600,7 @@ impl SentryClientReactorEventLoop {
             is_auto: true,
         };
 
-        let send_message_receiver = Arc::new(Mutex::new(self.send_message_receiver));
+        let _send_queue_closer = EventLoopSendQueueCloser(Arc::clone(&self.send_queue));
 
         let stop_stream = Box::pin(
             ReceiverStream::new(self.stop_signal_receiver)
@@ -407,15 +622,20 @@ impl SentryClientReactorEventLoop {
                         sentry_stream_holder =
                             Some(stream.remove(&EventLoopStreamId::Sentry).unwrap());
 
-                        let (send_stream, receive_stream) = stream_factory::make_sentry_streams(
-                            sentry,
-                            send_message_receiver.clone(),
-                            receive_messages_senders_dropper.clone(),
-                        )
-                        .await?;
+                        // New sentry lists its connected peers again.
+                        self.peer_versions.write().clear();
+                        let (send_stream, receive_stream, peer_events_stream) =
+                            stream_factory::make_sentry_streams(
+                                sentry,
+                                Arc::clone(&self.send_queue),
+                                receive_messages_senders_dropper.clone(),
+                                Arc::clone(&self.peer_versions),
+                            )
+                            .await?;
 
                         stream.insert(EventLoopStreamId::Send, send_stream);
                         stream.insert(EventLoopStreamId::Receive, receive_stream);
+                        stream.insert(EventLoopStreamId::PeerEvents, peer_events_stream);
                     }
                     Ok(_) => panic!("unexpected result {:?}", result),
                     Err(error) => {
@@ -444,6 +664,7 @@ impl SentryClientReactorEventLoop {
                                 info!("SentryClientReactor.EventLoop reconnecting sentry streams");
                                 stream.remove(&EventLoopStreamId::Send);
                                 stream.remove(&EventLoopStreamId::Receive);
+                                stream.remove(&EventLoopStreamId::PeerEvents);
                                 match sentry_stream_holder.take() {
                                     Some(sentry_stream) => {
                                         stream.insert(EventLoopStreamId::Sentry, sentry_stream);
@@ -460,6 +681,37 @@ impl SentryClientReactorEventLoop {
                     // process an incoming message that was received
                     match result {
                         Ok(EventLoopStreamResult::Receive(message_from_peer)) => {
+                            if let Some(peer_id) = message_from_peer.from_peer_id {
+                                if self.reputation.lock().is_banned(peer_id, SystemTime::now()) {
+                                    // Got back in after being kicked, e.g. through another sentry.
+                                    Self::kick_peer(&self.send_queue, peer_id).await;
+                                    continue;
+                                }
+
+                                let version = self.peer_versions.read().get(&peer_id).copied();
+                                if let Some(version) = version {
+                                    if !version.accepts(&message_from_peer.message) {
+                                        let reason = format!(
+                                            "{:?} message not valid in {:?}",
+                                            message_from_peer.message.eth_id(),
+                                            version
+                                        );
+                                        debug!(
+                                            "SentryClientReactor.EventLoop {} from {:?}",
+                                            reason, peer_id
+                                        );
+                                        Self::report_protocol_violation(
+                                            &self.reputation,
+                                            &self.send_queue,
+                                            peer_id,
+                                            &reason,
+                                        )
+                                        .await;
+                                        continue;
+                                    }
+                                }
+                            }
+
                             let id = message_from_peer.message.eth_id();
                             debug!("SentryClientReactor.EventLoop incoming message: {:?}", id);
 
@@ -478,6 +730,23 @@ impl SentryClientReactorEventLoop {
                             }
                         }
                         Ok(_) => panic!("unexpected result {:?}", result),
+                        Err(error) if error.is::<InvalidMessage>() => {
+                            let invalid = error.downcast_ref::<InvalidMessage>().unwrap();
+                            debug!("SentryClientReactor.EventLoop {}", invalid);
+                            // Corrupted messages may be damaged on the way, only penalize
+                            // peers sending malformed ones on purpose.
+                            if let (DecodeFault::ProtocolViolation, Some(peer_id)) =
+                                (invalid.fault(), invalid.from_peer_id)
+                            {
+                                Self::report_protocol_violation(
+                                    &self.reputation,
+                                    &self.send_queue,
+                                    peer_id,
+                                    invalid,
+                                )
+                                .await;
+                            }
+                        }
                         Err(error) => {
                             error!(
                                 "SentryClientReactor.EventLoop receive message error: {}",
@@ -487,6 +756,7 @@ impl SentryClientReactorEventLoop {
                                 info!("SentryClientReactor.EventLoop reconnecting sentry streams");
                                 stream.remove(&EventLoopStreamId::Send);
                                 stream.remove(&EventLoopStreamId::Receive);
+                                stream.remove(&EventLoopStreamId::PeerEvents);
                                 match sentry_stream_holder.take() {
                                     Some(sentry_stream) => {
                                         stream.insert(EventLoopStreamId::Sentry, sentry_stream);
@@ -499,6 +769,27 @@ impl SentryClientReactorEventLoop {
                         }
                     }
                 }
+                EventLoopStreamId::PeerEvents => match result {
+                    Ok(EventLoopStreamResult::PeerEvent(PeerEvent::Connected {
+                        peer_id,
+                        version,
+                    })) => {
+                        debug!(
+                            "SentryClientReactor.EventLoop peer {:?} connected with {:?}",
+                            peer_id, version
+                        );
+                        self.peer_versions.write().insert(peer_id, version);
+                    }
+                    Ok(EventLoopStreamResult::PeerEvent(PeerEvent::Disconnected(peer_id))) => {
+                        self.peer_versions.write().remove(&peer_id);
+                    }
+                    Ok(_) => panic!("unexpected result {:?}", result),
+                    Err(error) => {
+                        // Peers keep their last known versions, new ones are taken to speak
+                        // eth/66 until the sentry reconnects.
+                        warn!("SentryClientReactor.EventLoop peer events error: {}", error);
+                    }
+                },
                 EventLoopStreamId::Stop => {
                     break;
                 }
@@ -519,4 +810,56 @@ mod tests {
     fn assert_sync(sentry: SentryClientReactor) {
         let _ = SyncTester(sentry);
     }
+
+    #[test]
+    fn sends_messages_in_peer_versions() {
+        use super::super::messages::*;
+        use crate::models::H256;
+
+        let (old, new, unknown) = (
+            PeerId::repeat_byte(1),
+            PeerId::repeat_byte(2),
+            PeerId::repeat_byte(3),
+        );
+        let peer_versions = HashMap::from([(old, EthVersion::Eth66), (new, EthVersion::Eth68)]);
+        let eth68 = Message::NewPooledTransactionHashes68(NewPooledTransactionHashes68Message {
+            types: vec![2],
+            sizes: vec![120],
+            hashes: vec![H256::repeat_byte(1)],
+        });
+        let eth66 = Message::NewPooledTransactionHashes(NewPooledTransactionHashesMessage {
+            ids: vec![H256::repeat_byte(1)],
+        });
+
+        let mut sends = versioned_sends(eth68.clone(), PeerFilter::All, &peer_versions);
+        sends.sort_by_key(|(_, peer_filter)| match peer_filter {
+            PeerFilter::PeerId(peer_id) => *peer_id,
+            _ => unreachable!(),
+        });
+        assert_eq!(
+            sends
+                .into_iter()
+                .map(|(message, _)| message)
+                .collect::<Vec<_>>(),
+            vec![eth66.clone(), eth68.clone()]
+        );
+        assert_eq!(
+            versioned_sends(eth68, PeerFilter::PeerId(unknown), &peer_versions)
+                .into_iter()
+                .map(|(message, _)| message)
+                .collect::<Vec<_>>(),
+            vec![eth66.clone()]
+        );
+        assert!(versioned_sends(eth66, PeerFilter::PeerId(new), &peer_versions).is_empty());
+
+        // Messages of every version pass through.
+        let get_block_bodies = Message::GetBlockBodies(GetBlockBodiesMessage {
+            request_id: 1,
+            block_hashes: vec![],
+        });
+        assert!(matches!(
+            &versioned_sends(get_block_bodies.clone(), PeerFilter::Random(1), &peer_versions)[..],
+            [(message, PeerFilter::Random(1))] if *message == get_block_bodies
+        ));
+    }
 }
@@ -0,0 +1,553 @@
+//! Messages of the [snap/1 protocol](https://github.com/ethereum/devp2p/blob/master/caps/snap.md),
+//! which serves ranges of state at recent blocks together with proofs, so that state can be
+//! downloaded without executing all blocks from genesis.
+
+use super::sentry_client::PeerId;
+use crate::models::*;
+use async_trait::async_trait;
+use bytes::Bytes;
+use rlp::{Decodable, DecoderError, Encodable, Rlp, RlpStream};
+use std::fmt::Debug;
+
+pub const SNAP_PROTOCOL_VERSION: usize = 1;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, strum::EnumIter)]
+pub enum SnapMessageId {
+    GetAccountRange = 0,
+    AccountRange = 1,
+    GetStorageRanges = 2,
+    StorageRanges = 3,
+    GetByteCodes = 4,
+    ByteCodes = 5,
+    GetTrieNodes = 6,
+    TrieNodes = 7,
+}
+
+/// Account in the slim format of snap, with empty storage root and code hash omitted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SlimAccount {
+    pub nonce: u64,
+    pub balance: U256,
+    pub storage_root: H256,
+    pub code_hash: H256,
+}
+
+impl SlimAccount {
+    pub fn account(&self) -> Account {
+        Account {
+            nonce: self.nonce,
+            balance: self.balance,
+            code_hash: self.code_hash,
+        }
+    }
+
+    /// Account as stored in the state trie.
+    pub fn to_rlp(&self) -> RlpAccount {
+        self.account().to_rlp(self.storage_root)
+    }
+}
+
+impl Encodable for SlimAccount {
+    fn rlp_append(&self, s: &mut RlpStream) {
+        s.begin_list(4);
+        s.append(&self.nonce);
+        s.append(&self.balance);
+        if self.storage_root == EMPTY_ROOT {
+            s.append_empty_data();
+        } else {
+            s.append(&self.storage_root);
+        }
+        if self.code_hash == EMPTY_HASH {
+            s.append_empty_data();
+        } else {
+            s.append(&self.code_hash);
+        }
+    }
+}
+
+impl Decodable for SlimAccount {
+    fn decode(rlp: &Rlp) -> Result<Self, DecoderError> {
+        if rlp.item_count()? != 4 {
+            return Err(DecoderError::RlpIncorrectListLen);
+        }
+        let hash_or = |i: usize, empty: H256| -> Result<H256, DecoderError> {
+            let item = rlp.at(i)?;
+            if item.is_empty() {
+                Ok(empty)
+            } else {
+                item.as_val()
+            }
+        };
+
+        Ok(Self {
+            nonce: rlp.val_at(0)?,
+            balance: rlp.val_at(1)?,
+            storage_root: hash_or(2, EMPTY_ROOT)?,
+            code_hash: hash_or(3, EMPTY_HASH)?,
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AccountData {
+    pub hash: H256,
+    pub account: SlimAccount,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StorageData {
+    pub hash: H256,
+    pub value: U256,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GetAccountRangeMessage {
+    pub request_id: u64,
+    pub root_hash: H256,
+    pub starting_hash: H256,
+    pub limit_hash: H256,
+    pub response_bytes: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AccountRangeMessage {
+    pub request_id: u64,
+    pub accounts: Vec<AccountData>,
+    pub proof: Vec<Bytes>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GetStorageRangesMessage {
+    pub request_id: u64,
+    pub root_hash: H256,
+    pub account_hashes: Vec<H256>,
+    /// Only applies to the first account.
+    pub starting_hash: H256,
+    /// Only applies to the last account.
+    pub limit_hash: H256,
+    pub response_bytes: u64,
+}
+
+/// Slots of requested accounts in order. Only the last one may be incomplete, in which case
+/// `proof` proves its range.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StorageRangesMessage {
+    pub request_id: u64,
+    pub slots: Vec<Vec<StorageData>>,
+    pub proof: Vec<Bytes>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GetByteCodesMessage {
+    pub request_id: u64,
+    pub hashes: Vec<H256>,
+    pub response_bytes: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ByteCodesMessage {
+    pub request_id: u64,
+    pub codes: Vec<Bytes>,
+}
+
+/// Trie node paths, as nibbles from the root of their trie.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TrieNodePaths {
+    /// Hashed address of the account whose storage trie contains the nodes, `None` for the
+    /// account trie.
+    pub account: Option<H256>,
+    pub paths: Vec<Vec<u8>>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GetTrieNodesMessage {
+    pub request_id: u64,
+    pub root_hash: H256,
+    pub paths: Vec<TrieNodePaths>,
+    pub response_bytes: u64,
+}
+
+/// Requested nodes in order. Peers may stop early, but not skip nodes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TrieNodesMessage {
+    pub request_id: u64,
+    pub nodes: Vec<Bytes>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SnapMessage {
+    GetAccountRange(GetAccountRangeMessage),
+    AccountRange(AccountRangeMessage),
+    GetStorageRanges(GetStorageRangesMessage),
+    StorageRanges(StorageRangesMessage),
+    GetByteCodes(GetByteCodesMessage),
+    ByteCodes(ByteCodesMessage),
+    GetTrieNodes(GetTrieNodesMessage),
+    TrieNodes(TrieNodesMessage),
+}
+
+impl SnapMessage {
+    pub fn snap_id(&self) -> SnapMessageId {
+        match self {
+            SnapMessage::GetAccountRange(_) => SnapMessageId::GetAccountRange,
+            SnapMessage::AccountRange(_) => SnapMessageId::AccountRange,
+            SnapMessage::GetStorageRanges(_) => SnapMessageId::GetStorageRanges,
+            SnapMessage::StorageRanges(_) => SnapMessageId::StorageRanges,
+            SnapMessage::GetByteCodes(_) => SnapMessageId::GetByteCodes,
+            SnapMessage::ByteCodes(_) => SnapMessageId::ByteCodes,
+            SnapMessage::GetTrieNodes(_) => SnapMessageId::GetTrieNodes,
+            SnapMessage::TrieNodes(_) => SnapMessageId::TrieNodes,
+        }
+    }
+
+    pub fn request_id(&self) -> u64 {
+        match self {
+            SnapMessage::GetAccountRange(m) => m.request_id,
+            SnapMessage::AccountRange(m) => m.request_id,
+            SnapMessage::GetStorageRanges(m) => m.request_id,
+            SnapMessage::StorageRanges(m) => m.request_id,
+            SnapMessage::GetByteCodes(m) => m.request_id,
+            SnapMessage::ByteCodes(m) => m.request_id,
+            SnapMessage::GetTrieNodes(m) => m.request_id,
+            SnapMessage::TrieNodes(m) => m.request_id,
+        }
+    }
+}
+
+fn append_bytes_list(s: &mut RlpStream, items: &[Bytes]) {
+    s.begin_list(items.len());
+    for item in items {
+        s.append(&item.as_ref());
+    }
+}
+
+fn decode_bytes_list(rlp: &Rlp) -> Result<Vec<Bytes>, DecoderError> {
+    rlp.iter()
+        .map(|item| Ok(item.data()?.to_vec().into()))
+        .collect()
+}
+
+/// Compact encoding of a path, as in trie nodes, but without the leaf flag.
+fn encode_path(nibbles: &[u8]) -> Vec<u8> {
+    let odd = nibbles.len() % 2 == 1;
+    let mut out = vec![if odd { 0x10 | nibbles[0] } else { 0 }];
+    for pair in nibbles[odd as usize..].chunks(2) {
+        out.push(pair[0] << 4 | pair[1]);
+    }
+    out
+}
+
+fn decode_path(encoded: &[u8]) -> Result<Vec<u8>, DecoderError> {
+    let (&first, rest) = encoded
+        .split_first()
+        .ok_or(DecoderError::Custom("empty path"))?;
+    let mut nibbles = vec![];
+    if first & 0x10 != 0 {
+        nibbles.push(first & 0x0f);
+    }
+    for b in rest {
+        nibbles.push(b >> 4);
+        nibbles.push(b & 0x0f);
+    }
+    Ok(nibbles)
+}
+
+fn check_len(rlp: &Rlp, len: usize) -> Result<(), DecoderError> {
+    if rlp.item_count()? != len {
+        return Err(DecoderError::RlpIncorrectListLen);
+    }
+    Ok(())
+}
+
+impl Encodable for SnapMessage {
+    fn rlp_append(&self, s: &mut RlpStream) {
+        match self {
+            SnapMessage::GetAccountRange(m) => {
+                s.begin_list(5);
+                s.append(&m.request_id);
+                s.append(&m.root_hash);
+                s.append(&m.starting_hash);
+                s.append(&m.limit_hash);
+                s.append(&m.response_bytes);
+            }
+            SnapMessage::AccountRange(m) => {
+                s.begin_list(3);
+                s.append(&m.request_id);
+                s.begin_list(m.accounts.len());
+                for data in &m.accounts {
+                    s.begin_list(2);
+                    s.append(&data.hash);
+                    s.append(&data.account);
+                }
+                append_bytes_list(s, &m.proof);
+            }
+            SnapMessage::GetStorageRanges(m) => {
+                s.begin_list(6);
+                s.append(&m.request_id);
+                s.append(&m.root_hash);
+                s.append_list(&m.account_hashes);
+                s.append(&m.starting_hash);
+                s.append(&m.limit_hash);
+                s.append(&m.response_bytes);
+            }
+            SnapMessage::StorageRanges(m) => {
+                s.begin_list(3);
+                s.append(&m.request_id);
+                s.begin_list(m.slots.len());
+                for slots in &m.slots {
+                    s.begin_list(slots.len());
+                    for slot in slots {
+                        s.begin_list(2);
+                        s.append(&slot.hash);
+                        s.append(&rlp::encode(&slot.value).as_ref());
+                    }
+                }
+                append_bytes_list(s, &m.proof);
+            }
+            SnapMessage::GetByteCodes(m) => {
+                s.begin_list(3);
+                s.append(&m.request_id);
+                s.append_list(&m.hashes);
+                s.append(&m.response_bytes);
+            }
+            SnapMessage::ByteCodes(m) => {
+                s.begin_list(2);
+                s.append(&m.request_id);
+                append_bytes_list(s, &m.codes);
+            }
+            SnapMessage::GetTrieNodes(m) => {
+                s.begin_list(4);
+                s.append(&m.request_id);
+                s.append(&m.root_hash);
+                s.begin_list(m.paths.len());
+                for paths in &m.paths {
+                    s.begin_list(paths.paths.len() + paths.account.is_some() as usize);
+                    if let Some(account) = &paths.account {
+                        s.append(&account.as_bytes());
+                    }
+                    for path in &paths.paths {
+                        s.append(&encode_path(path));
+                    }
+                }
+                s.append(&m.response_bytes);
+            }
+            SnapMessage::TrieNodes(m) => {
+                s.begin_list(2);
+                s.append(&m.request_id);
+                append_bytes_list(s, &m.nodes);
+            }
+        }
+    }
+}
+
+/// Decode message `id` of snap protocol.
+pub fn decode_snap_message(id: SnapMessageId, data: &[u8]) -> Result<SnapMessage, DecoderError> {
+    let rlp = Rlp::new(data);
+    Ok(match id {
+        SnapMessageId::GetAccountRange => {
+            check_len(&rlp, 5)?;
+            SnapMessage::GetAccountRange(GetAccountRangeMessage {
+                request_id: rlp.val_at(0)?,
+                root_hash: rlp.val_at(1)?,
+                starting_hash: rlp.val_at(2)?,
+                limit_hash: rlp.val_at(3)?,
+                response_bytes: rlp.val_at(4)?,
+            })
+        }
+        SnapMessageId::AccountRange => {
+            check_len(&rlp, 3)?;
+            SnapMessage::AccountRange(AccountRangeMessage {
+                request_id: rlp.val_at(0)?,
+                accounts: rlp
+                    .at(1)?
+                    .iter()
+                    .map(|item| {
+                        check_len(&item, 2)?;
+                        Ok(AccountData {
+                            hash: item.val_at(0)?,
+                            account: item.val_at(1)?,
+                        })
+                    })
+                    .collect::<Result<_, DecoderError>>()?,
+                proof: decode_bytes_list(&rlp.at(2)?)?,
+            })
+        }
+        SnapMessageId::GetStorageRanges => {
+            check_len(&rlp, 6)?;
+            // Empty starting and limit hashes stand for the whole range.
+            let hash_or = |i: usize, empty: H256| -> Result<H256, DecoderError> {
+                let item = rlp.at(i)?;
+                if item.is_empty() {
+                    Ok(empty)
+                } else {
+                    item.as_val()
+                }
+            };
+            SnapMessage::GetStorageRanges(GetStorageRangesMessage {
+                request_id: rlp.val_at(0)?,
+                root_hash: rlp.val_at(1)?,
+                account_hashes: rlp.list_at(2)?,
+                starting_hash: hash_or(3, H256::zero())?,
+                limit_hash: hash_or(4, H256::repeat_byte(0xff))?,
+                response_bytes: rlp.val_at(5)?,
+            })
+        }
+        SnapMessageId::StorageRanges => {
+            check_len(&rlp, 3)?;
+            SnapMessage::StorageRanges(StorageRangesMessage {
+                request_id: rlp.val_at(0)?,
+                slots: rlp
+                    .at(1)?
+                    .iter()
+                    .map(|slots| {
+                        slots
+                            .iter()
+                            .map(|item| {
+                                check_len(&item, 2)?;
+                                Ok(StorageData {
+                                    hash: item.val_at(0)?,
+                                    value: rlp::decode(item.at(1)?.data()?)?,
+                                })
+                            })
+                            .collect::<Result<Vec<_>, DecoderError>>()
+                    })
+                    .collect::<Result<_, _>>()?,
+                proof: decode_bytes_list(&rlp.at(2)?)?,
+            })
+        }
+        SnapMessageId::GetByteCodes => {
+            check_len(&rlp, 3)?;
+            SnapMessage::GetByteCodes(GetByteCodesMessage {
+                request_id: rlp.val_at(0)?,
+                hashes: rlp.list_at(1)?,
+                response_bytes: rlp.val_at(2)?,
+            })
+        }
+        SnapMessageId::ByteCodes => {
+            check_len(&rlp, 2)?;
+            SnapMessage::ByteCodes(ByteCodesMessage {
+                request_id: rlp.val_at(0)?,
+                codes: decode_bytes_list(&rlp.at(1)?)?,
+            })
+        }
+        SnapMessageId::GetTrieNodes => {
+            check_len(&rlp, 4)?;
+            SnapMessage::GetTrieNodes(GetTrieNodesMessage {
+                request_id: rlp.val_at(0)?,
+                root_hash: rlp.val_at(1)?,
+                paths: rlp
+                    .at(2)?
+                    .iter()
+                    .map(|set| {
+                        let items = decode_bytes_list(&set)?;
+                        Ok(match items.len() {
+                            0 => return Err(DecoderError::Custom("empty path set")),
+                            // Single path is in the account trie.
+                            1 => TrieNodePaths {
+                                account: None,
+                                paths: vec![decode_path(&items[0])?],
+                            },
+                            _ => {
+                                if items[0].len() != KECCAK_LENGTH {
+                                    return Err(DecoderError::Custom("invalid account hash"));
+                                }
+                                TrieNodePaths {
+                                    account: Some(H256::from_slice(&items[0])),
+                                    paths: items[1..]
+                                        .iter()
+                                        .map(|path| decode_path(path))
+                                        .collect::<Result<_, _>>()?,
+                                }
+                            }
+                        })
+                    })
+                    .collect::<Result<_, _>>()?,
+                response_bytes: rlp.val_at(3)?,
+            })
+        }
+        SnapMessageId::TrieNodes => {
+            check_len(&rlp, 2)?;
+            SnapMessage::TrieNodes(TrieNodesMessage {
+                request_id: rlp.val_at(0)?,
+                nodes: decode_bytes_list(&rlp.at(1)?)?,
+            })
+        }
+    })
+}
+
+/// Peers serving snap protocol.
+#[async_trait]
+pub trait SnapClient: Send + Debug {
+    /// Send `request` to a peer and wait for its response with the same request id.
+    async fn request(&mut self, request: SnapMessage) -> anyhow::Result<(PeerId, SnapMessage)>;
+
+    /// Stop requesting from a peer that has sent an invalid response.
+    async fn penalize_peer(&mut self, peer_id: PeerId) -> anyhow::Result<()>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roundtrip(message: SnapMessage) {
+        let encoded = rlp::encode(&message);
+        assert_eq!(
+            decode_snap_message(message.snap_id(), &encoded).unwrap(),
+            message
+        );
+    }
+
+    #[test]
+    fn messages_roundtrip() {
+        let account = SlimAccount {
+            nonce: 3,
+            balance: ETHER.into(),
+            storage_root: EMPTY_ROOT,
+            code_hash: H256::repeat_byte(0xcc),
+        };
+        // Empty storage root is omitted.
+        assert_eq!(rlp::encode(&account).len(), 1 + 1 + 9 + 1 + 33);
+
+        roundtrip(SnapMessage::GetAccountRange(GetAccountRangeMessage {
+            request_id: 1,
+            root_hash: H256::repeat_byte(0xaa),
+            starting_hash: H256::zero(),
+            limit_hash: H256::repeat_byte(0xff),
+            response_bytes: 512 * 1024,
+        }));
+        roundtrip(SnapMessage::AccountRange(AccountRangeMessage {
+            request_id: 1,
+            accounts: vec![AccountData {
+                hash: H256::repeat_byte(0x01),
+                account,
+            }],
+            proof: vec![Bytes::from_static(&[0xc0])],
+        }));
+        roundtrip(SnapMessage::StorageRanges(StorageRangesMessage {
+            request_id: 2,
+            slots: vec![
+                vec![StorageData {
+                    hash: H256::repeat_byte(0x02),
+                    value: 0x2a.as_u256(),
+                }],
+                vec![],
+            ],
+            proof: vec![],
+        }));
+        roundtrip(SnapMessage::GetTrieNodes(GetTrieNodesMessage {
+            request_id: 3,
+            root_hash: H256::repeat_byte(0xaa),
+            paths: vec![
+                TrieNodePaths {
+                    account: None,
+                    paths: vec![vec![0x1, 0x2, 0x3]],
+                },
+                TrieNodePaths {
+                    account: Some(H256::repeat_byte(0x01)),
+                    paths: vec![vec![], vec![0xf, 0x0]],
+                },
+            ],
+            response_bytes: 1024,
+        }));
+    }
+}
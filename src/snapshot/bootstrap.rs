@@ -0,0 +1,289 @@
+use super::{downloader::SnapshotDownloader, segment::*};
+use crate::{
+    accessors::chain,
+    consensus::verify_body_roots,
+    kv::{tables, traits::*},
+    models::*,
+    stagedsync::stages::*,
+};
+use anyhow::{ensure, format_err};
+use tokio::pin;
+use tokio_stream::StreamExt;
+use tracing::*;
+
+/// Import the block at the tip of the local chain, which must be the child of `parent`.
+async fn import_block<'db, RwTx: MutableTransaction<'db>>(
+    tx: &RwTx,
+    parent: &mut (BlockHeader, U256, BodyForStorage),
+    block: Block,
+) -> anyhow::Result<()> {
+    let (parent_header, parent_td, parent_body) = parent;
+    let Block {
+        header,
+        transactions,
+        ommers,
+        withdrawals,
+    } = block;
+
+    ensure!(
+        header.parent_hash == parent_header.hash(),
+        "block {} is not a child of {:?}",
+        header.number,
+        parent_header.hash()
+    );
+    verify_body_roots(&header, &transactions, &ommers, withdrawals.as_deref())?;
+
+    let hash = header.hash();
+    let number = header.number;
+    let td = *parent_td + header.difficulty;
+    let storage_body = BodyForStorage {
+        base_tx_id: parent_body.base_tx_id + parent_body.tx_amount,
+        tx_amount: transactions.len().try_into()?,
+        uncles: ommers,
+        withdrawals,
+    };
+
+    tx.set(tables::Header, (number, hash), header.clone())
+        .await?;
+    tx.set(tables::HeaderNumber, hash, number).await?;
+    tx.set(tables::CanonicalHeader, number, hash).await?;
+    tx.set(tables::HeadersTotalDifficulty, (number, hash), td)
+        .await?;
+    chain::storage_body::write(tx, hash, number, &storage_body).await?;
+    chain::tx::write(tx, storage_body.base_tx_id, &transactions).await?;
+
+    *parent = (header, td, storage_body);
+
+    Ok(())
+}
+
+/// Bootstrap a node that has nothing but genesis, or an earlier bootstrap interrupted between
+/// segments, with headers and bodies of snapshots published by `publisher`.
+///
+/// Every segment is committed on its own along with headers and bodies stage progress, so that
+/// the rest of the stages take it from there. Returns the last imported block.
+pub async fn bootstrap<DB: MutableKV>(
+    db: &DB,
+    downloader: &SnapshotDownloader,
+    publisher: Address,
+) -> anyhow::Result<Option<BlockNumber>> {
+    let manifest = downloader.fetch_manifest(publisher).await?;
+
+    let txn = db.begin().await?;
+    let genesis_hash = chain::canonical_hash::read(&txn, BlockNumber(0))
+        .await?
+        .ok_or_else(|| format_err!("no genesis block"))?;
+    ensure!(
+        manifest.genesis_hash == genesis_hash,
+        "snapshots are for genesis {:?}, not {:?}",
+        manifest.genesis_hash,
+        genesis_hash
+    );
+    let progress = HEADERS.get_progress(&txn).await?.unwrap_or_default();
+    drop(txn);
+
+    if progress >= manifest.last_block() {
+        info!(
+            "Local chain at block {} is ahead of snapshots, skipping",
+            progress
+        );
+        return Ok(None);
+    }
+    if progress > BlockNumber(0) && !manifest.segments.iter().any(|s| s.to == progress) {
+        warn!(
+            "Local chain at block {} does not end at a snapshot segment, skipping",
+            progress
+        );
+        return Ok(None);
+    }
+
+    let mut last_imported = None;
+    let segments = downloader.segments(
+        manifest
+            .segments
+            .into_iter()
+            .filter(|s| s.to > progress)
+            .collect(),
+    );
+    pin!(segments);
+    while let Some((segment, dir)) = segments.try_next().await? {
+        info!(
+            "Importing snapshot segment {}..={}",
+            segment.from, segment.to
+        );
+
+        let txn = db.begin_mutable().await?;
+        // First segment starts with genesis, which is there already.
+        let parent_number = BlockNumber(segment.from.0.saturating_sub(1));
+        let parent_hash = chain::canonical_hash::read(&txn, parent_number)
+            .await?
+            .ok_or_else(|| format_err!("no canonical block {}", parent_number))?;
+        let mut parent = (
+            chain::header::read(&txn, parent_hash, parent_number)
+                .await?
+                .ok_or_else(|| format_err!("no header for block {}", parent_number))?,
+            txn.get(tables::HeadersTotalDifficulty, (parent_number, parent_hash))
+                .await?
+                .ok_or_else(|| format_err!("no total difficulty for block {}", parent_number))?,
+            chain::storage_body::read(&txn, parent_hash, parent_number)
+                .await?
+                .ok_or_else(|| format_err!("no body for block {}", parent_number))?,
+        );
+
+        let blocks = Segment::open(&dir, segment.from, segment.to)?;
+        for number in segment.from..=segment.to {
+            let block = blocks
+                .block(number)?
+                .ok_or_else(|| format_err!("no block {} in segment", number))?;
+            if number == parent_number {
+                ensure!(
+                    block.header.hash() == parent_hash,
+                    "block {} of segment is not {:?}",
+                    number,
+                    parent_hash
+                );
+                continue;
+            }
+            import_block(&txn, &mut parent, block).await?;
+        }
+        drop(blocks);
+
+        txn.set(tables::LastHeader, Default::default(), parent.0.hash())
+            .await?;
+        HEADERS.save_progress(&txn, segment.to).await?;
+        BODIES.save_progress(&txn, segment.to).await?;
+        txn.commit().await?;
+
+        downloader.discard(&segment, &dir);
+        last_imported = Some(segment.to);
+    }
+
+    Ok(last_imported)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        crypto::{generate_key, pubkey_to_address, to_pubkey},
+        kv::new_mem_database,
+        res::chainspec::MAINNET,
+        snapshot::{downloader::SnapshotSource, manifest::*, publisher::write_segment},
+        state::genesis::initialize_genesis,
+    };
+
+    #[tokio::test]
+    async fn publish_and_bootstrap() {
+        let key = generate_key();
+        let publisher = pubkey_to_address(&to_pubkey(&key));
+        let publish_dir = tempfile::tempdir().unwrap();
+        let download_dir = tempfile::tempdir().unwrap();
+        let etl_dir = tempfile::tempdir().unwrap();
+
+        // Source node with empty blocks on top of genesis.
+        let source_db = new_mem_database().unwrap();
+        let txn = source_db.begin_mutable().await.unwrap();
+        initialize_genesis(&txn, &etl_dir, MAINNET.clone())
+            .await
+            .unwrap();
+        let genesis_hash = chain::canonical_hash::read(&txn, BlockNumber(0))
+            .await
+            .unwrap()
+            .unwrap();
+        let mut parent = (
+            chain::header::read(&txn, genesis_hash, BlockNumber(0))
+                .await
+                .unwrap()
+                .unwrap(),
+            U256::ZERO,
+            chain::storage_body::read(&txn, genesis_hash, BlockNumber(0))
+                .await
+                .unwrap()
+                .unwrap(),
+        );
+        for number in 1..4000 {
+            let header = BlockHeader {
+                parent_hash: parent.0.hash(),
+                number: BlockNumber(number),
+                ..parent.0.clone()
+            };
+            import_block(
+                &txn,
+                &mut parent,
+                Block {
+                    header,
+                    transactions: vec![],
+                    ommers: vec![],
+                    withdrawals: None,
+                },
+            )
+            .await
+            .unwrap();
+        }
+
+        let mut manifest = Manifest::new(genesis_hash);
+        for (from, to) in [(0, 1999), (2000, 3999)] {
+            manifest
+                .push(
+                    write_segment(&txn, publish_dir.path(), BlockNumber(from), BlockNumber(to))
+                        .await
+                        .unwrap(),
+                )
+                .unwrap();
+        }
+        let head_hash = parent.0.hash();
+        drop(txn);
+        manifest
+            .sign(&key)
+            .unwrap()
+            .store(&publish_dir.path().join(MANIFEST_FILE))
+            .unwrap();
+
+        // Mirror that serves corrupted segments, which must be skipped for the good one.
+        let bad_dir = tempfile::tempdir().unwrap();
+        for entry in std::fs::read_dir(publish_dir.path()).unwrap() {
+            let path = entry.unwrap().path();
+            let mut data = std::fs::read(&path).unwrap();
+            if path.extension().unwrap() == "seg" {
+                *data.last_mut().unwrap() ^= 1;
+            }
+            std::fs::write(bad_dir.path().join(path.file_name().unwrap()), data).unwrap();
+        }
+        let downloader = SnapshotDownloader::new(
+            vec![
+                SnapshotSource::Dir(bad_dir.path().to_path_buf()),
+                SnapshotSource::Dir(publish_dir.path().to_path_buf()),
+            ],
+            download_dir.path().to_path_buf(),
+        );
+        let db = new_mem_database().unwrap();
+        let txn = db.begin_mutable().await.unwrap();
+        initialize_genesis(&txn, &etl_dir, MAINNET.clone())
+            .await
+            .unwrap();
+        txn.commit().await.unwrap();
+
+        // Snapshots of somebody else are rejected.
+        assert!(bootstrap(&db, &downloader, Address::zero()).await.is_err());
+
+        assert_eq!(
+            bootstrap(&db, &downloader, publisher).await.unwrap(),
+            Some(BlockNumber(3999))
+        );
+        let txn = db.begin().await.unwrap();
+        assert_eq!(
+            chain::canonical_hash::read(&txn, BlockNumber(3999))
+                .await
+                .unwrap(),
+            Some(head_hash)
+        );
+        assert_eq!(
+            HEADERS.get_progress(&txn).await.unwrap(),
+            Some(BlockNumber(3999))
+        );
+        drop(txn);
+
+        // Nothing left to import.
+        assert_eq!(bootstrap(&db, &downloader, publisher).await.unwrap(), None);
+    }
+}
@@ -0,0 +1,633 @@
+//! Word files of Erigon's `compress` package, which its `.seg` snapshot files are.
+//!
+//! A file is a sequence of words, arbitrary byte strings, laid out as:
+//!
+//! - number of words and number of empty ones among them, big endian `u64` each;
+//! - size of the pattern dictionary as big endian `u64`, then Huffman code depth, length and
+//!   bytes of every pattern, the numbers as uvarints;
+//! - size of the position dictionary as big endian `u64`, then Huffman code depth and value of
+//!   every position as uvarints;
+//! - words, each starting at a byte boundary: code of its length plus one, then for every pattern
+//!   in the word the code of its position relative to the previous pattern plus one and the code
+//!   of the pattern itself, then code of zero position, padding to a byte and all bytes of the word
+//!   not covered by patterns. Empty words have nothing but the length code.
+//!
+//! Codes are read starting from the least significant bit, and are assigned to dictionary
+//! entries in their order, every entry taking the leftmost free code of its depth.
+//!
+//! [`Compressor`] writes words without a pattern dictionary, [`Decompressor`] reads files that
+//! have one as well.
+
+use anyhow::{bail, ensure, format_err, Context};
+use parking_lot::Mutex;
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap},
+    fs::File,
+    io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+const MAX_CODE_DEPTH: u32 = 64;
+
+fn write_uvarint(out: &mut Vec<u8>, mut v: u64) {
+    while v >= 0x80 {
+        out.push(v as u8 | 0x80);
+        v >>= 7;
+    }
+    out.push(v as u8);
+}
+
+fn read_uvarint(data: &[u8], pos: &mut usize) -> anyhow::Result<u64> {
+    let mut v = 0_u64;
+    for shift in (0..64).step_by(7) {
+        let byte = *data
+            .get(*pos)
+            .ok_or_else(|| format_err!("truncated uvarint"))?;
+        *pos += 1;
+        v |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(v);
+        }
+    }
+    bail!("uvarint overflow")
+}
+
+fn read_u64(reader: &mut impl Read) -> anyhow::Result<u64> {
+    let mut buf = [0; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(u64::from_be_bytes(buf))
+}
+
+/// Huffman code depths of symbols used `uses` times each. A single symbol gets an empty code.
+fn huffman_depths(uses: &[u64]) -> Vec<u32> {
+    let mut depths = vec![0; uses.len()];
+    let mut heap = uses
+        .iter()
+        .enumerate()
+        .map(|(i, &uses)| Reverse((uses, i, vec![i])))
+        .collect::<BinaryHeap<_>>();
+    let mut tie_breaker = uses.len();
+    while heap.len() > 1 {
+        let Reverse((uses0, _, mut leaves)) = heap.pop().unwrap();
+        let Reverse((uses1, _, leaves1)) = heap.pop().unwrap();
+        leaves.extend(leaves1);
+        for &leaf in &leaves {
+            depths[leaf] += 1;
+        }
+        heap.push(Reverse((uses0 + uses1, tie_breaker, leaves)));
+        tie_breaker += 1;
+    }
+    depths
+}
+
+/// Assign codes to entries with code `depths`, which have to be ascending, the way Erigon does:
+/// every entry takes the leftmost free code of its depth, where going right sets bit `bits`.
+fn assign_codes(
+    depths: &[u32],
+    codes: &mut Vec<(u32, u64)>,
+    code: u64,
+    bits: u32,
+) -> anyhow::Result<usize> {
+    let depth = match depths.first() {
+        Some(depth) => *depth,
+        None => return Ok(0),
+    };
+    if depth == bits {
+        codes.push((bits, code));
+        return Ok(1);
+    }
+    ensure!(
+        depth > bits && bits < MAX_CODE_DEPTH,
+        "invalid Huffman code depths"
+    );
+
+    let left = assign_codes(depths, codes, code, bits + 1)?;
+    let right = assign_codes(&depths[left..], codes, code | 1 << bits, bits + 1)?;
+    Ok(left + right)
+}
+
+fn codes_of(depths: &[u32]) -> anyhow::Result<Vec<(u32, u64)>> {
+    let mut codes = Vec::with_capacity(depths.len());
+    let assigned = assign_codes(depths, &mut codes, 0, 0)?;
+    ensure!(assigned == depths.len(), "invalid Huffman code depths");
+    Ok(codes)
+}
+
+struct BitReader<R> {
+    inner: R,
+    byte: u8,
+    /// Bits of `byte` read already, 8 once it is used up.
+    bit: u32,
+}
+
+impl<R: Read> BitReader<R> {
+    fn new(inner: R) -> Self {
+        Self {
+            inner,
+            byte: 0,
+            bit: 8,
+        }
+    }
+
+    fn read_bit(&mut self) -> anyhow::Result<bool> {
+        if self.bit == 8 {
+            let mut byte = [0];
+            self.inner.read_exact(&mut byte)?;
+            self.byte = byte[0];
+            self.bit = 0;
+        }
+        let bit = (self.byte >> self.bit) & 1 == 1;
+        self.bit += 1;
+        Ok(bit)
+    }
+
+    /// Skip the rest of the current byte.
+    fn align(&mut self) {
+        self.bit = 8;
+    }
+}
+
+struct BitWriter<W> {
+    inner: W,
+    byte: u8,
+    bits: u32,
+}
+
+impl<W: Write> BitWriter<W> {
+    fn new(inner: W) -> Self {
+        Self {
+            inner,
+            byte: 0,
+            bits: 0,
+        }
+    }
+
+    fn write_code(&mut self, (bits, code): (u32, u64)) -> anyhow::Result<()> {
+        for i in 0..bits {
+            if (code >> i) & 1 == 1 {
+                self.byte |= 1 << self.bits;
+            }
+            self.bits += 1;
+            if self.bits == 8 {
+                self.flush()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Pad the current byte with zeros and write it out.
+    fn flush(&mut self) -> anyhow::Result<()> {
+        if self.bits > 0 {
+            self.inner.write_all(&[self.byte])?;
+            self.byte = 0;
+            self.bits = 0;
+        }
+        Ok(())
+    }
+}
+
+/// Huffman-coded dictionary.
+#[derive(Debug)]
+struct Dictionary<T> {
+    values: HashMap<(u32, u64), T>,
+}
+
+impl<T> Dictionary<T> {
+    fn new(entries: Vec<(u32, T)>) -> anyhow::Result<Self> {
+        let depths = entries.iter().map(|(depth, _)| *depth).collect::<Vec<_>>();
+        let codes = codes_of(&depths)?;
+        Ok(Self {
+            values: codes
+                .into_iter()
+                .zip(entries.into_iter().map(|(_, value)| value))
+                .collect(),
+        })
+    }
+
+    /// Read dictionary of `size` bytes, parsing every entry after its code depth with `parse`.
+    fn read(
+        reader: &mut impl Read,
+        size: u64,
+        parse: impl Fn(&[u8], &mut usize) -> anyhow::Result<T>,
+    ) -> anyhow::Result<Self> {
+        let mut data = Vec::new();
+        reader.take(size).read_to_end(&mut data)?;
+        ensure!(data.len() as u64 == size, "truncated dictionary");
+
+        let mut entries = Vec::new();
+        let mut pos = 0;
+        while pos < data.len() {
+            let depth = read_uvarint(&data, &mut pos)?;
+            ensure!(
+                depth <= MAX_CODE_DEPTH.into(),
+                "Huffman code depth {} is too large",
+                depth
+            );
+            entries.push((depth as u32, parse(&data, &mut pos)?));
+        }
+        Self::new(entries)
+    }
+
+    fn decode(&self, reader: &mut BitReader<impl Read>) -> anyhow::Result<&T> {
+        let mut code = 0;
+        for bits in 0..=MAX_CODE_DEPTH {
+            if let Some(value) = self.values.get(&(bits, code)) {
+                return Ok(value);
+            }
+            if bits < MAX_CODE_DEPTH && reader.read_bit()? {
+                code |= 1 << bits;
+            }
+        }
+        bail!("unknown Huffman code")
+    }
+}
+
+/// Writes words into an Erigon word file at `path`. Words are buffered in a temporary file,
+/// since dictionaries that precede them are only known once all words are in.
+#[derive(Debug)]
+pub struct Compressor {
+    path: PathBuf,
+    words_path: PathBuf,
+    words_file: BufWriter<File>,
+    /// Uses of every position: lengths of words plus one, and zero ending every non-empty word.
+    positions: HashMap<u64, u64>,
+    words: u64,
+    empty_words: u64,
+}
+
+impl Compressor {
+    pub fn create(path: &Path) -> anyhow::Result<Self> {
+        let words_path = path.with_extension("words.tmp");
+        let words_file = BufWriter::new(
+            File::create(&words_path)
+                .with_context(|| format!("failed to create {}", words_path.display()))?,
+        );
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            words_path,
+            words_file,
+            positions: HashMap::new(),
+            words: 0,
+            empty_words: 0,
+        })
+    }
+
+    pub fn add_word(&mut self, word: &[u8]) -> anyhow::Result<()> {
+        self.words_file
+            .write_all(&u32::try_from(word.len())?.to_be_bytes())?;
+        self.words_file.write_all(word)?;
+
+        *self.positions.entry(word.len() as u64 + 1).or_default() += 1;
+        if word.is_empty() {
+            self.empty_words += 1;
+        } else {
+            *self.positions.entry(0).or_default() += 1;
+        }
+        self.words += 1;
+
+        Ok(())
+    }
+
+    /// Write out the file, replacing any previous one at the path only once it is complete.
+    pub fn finish(mut self) -> anyhow::Result<()> {
+        self.words_file.flush()?;
+
+        let mut positions = self.positions.into_iter().collect::<Vec<_>>();
+        positions.sort_unstable();
+        let depths = huffman_depths(&positions.iter().map(|(_, uses)| *uses).collect::<Vec<_>>());
+        let mut positions = positions
+            .into_iter()
+            .zip(depths)
+            .map(|((position, _), depth)| (depth, position))
+            .collect::<Vec<_>>();
+        positions.sort_unstable();
+        let codes = positions
+            .iter()
+            .map(|(_, position)| *position)
+            .zip(codes_of(
+                &positions
+                    .iter()
+                    .map(|(depth, _)| *depth)
+                    .collect::<Vec<_>>(),
+            )?)
+            .collect::<HashMap<_, _>>();
+
+        let mut position_dictionary = Vec::new();
+        for (depth, position) in &positions {
+            write_uvarint(&mut position_dictionary, (*depth).into());
+            write_uvarint(&mut position_dictionary, *position);
+        }
+
+        let tmp_path = self.path.with_extension("seg.tmp");
+        let mut out = BufWriter::new(
+            File::create(&tmp_path)
+                .with_context(|| format!("failed to create {}", tmp_path.display()))?,
+        );
+        out.write_all(&self.words.to_be_bytes())?;
+        out.write_all(&self.empty_words.to_be_bytes())?;
+        // No patterns.
+        out.write_all(&0_u64.to_be_bytes())?;
+        out.write_all(&(position_dictionary.len() as u64).to_be_bytes())?;
+        out.write_all(&position_dictionary)?;
+
+        let mut words = BufReader::new(File::open(&self.words_path)?);
+        let mut out = BitWriter::new(out);
+        let mut word = Vec::new();
+        for _ in 0..self.words {
+            let mut len = [0; 4];
+            words.read_exact(&mut len)?;
+            word.resize(u32::from_be_bytes(len) as usize, 0);
+            words.read_exact(&mut word)?;
+
+            out.write_code(codes[&(word.len() as u64 + 1)])?;
+            if !word.is_empty() {
+                out.write_code(codes[&0])?;
+            }
+            out.flush()?;
+            out.inner.write_all(&word)?;
+        }
+        drop(words);
+        std::fs::remove_file(&self.words_path)?;
+
+        let mut out = out.inner;
+        out.flush()?;
+        out.get_ref().sync_all()?;
+        std::fs::rename(&tmp_path, &self.path)?;
+
+        Ok(())
+    }
+}
+
+/// Erigon word file opened for random access, with offsets of its words collected on open.
+#[derive(Debug)]
+pub struct Decompressor {
+    file: Mutex<File>,
+    patterns: Dictionary<Vec<u8>>,
+    positions: Dictionary<u64>,
+    /// Offsets of every word, followed by the end of the last one.
+    offsets: Vec<u64>,
+}
+
+impl Decompressor {
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        let mut file = BufReader::new(
+            File::open(path).with_context(|| format!("failed to open {}", path.display()))?,
+        );
+
+        let words = read_u64(&mut file)?;
+        let empty_words = read_u64(&mut file)?;
+        let size = read_u64(&mut file)?;
+        let patterns = Dictionary::read(&mut file, size, |data, pos| {
+            let len = usize::try_from(read_uvarint(data, pos)?)?;
+            let pattern = data
+                .get(*pos..pos.saturating_add(len))
+                .ok_or_else(|| format_err!("truncated pattern"))?
+                .to_vec();
+            *pos += len;
+            Ok(pattern)
+        })
+        .with_context(|| format!("invalid pattern dictionary in {}", path.display()))?;
+        let positions_size = read_u64(&mut file)?;
+        let positions = Dictionary::read(&mut file, positions_size, read_uvarint)
+            .with_context(|| format!("invalid position dictionary in {}", path.display()))?;
+
+        let mut s = Self {
+            file: Mutex::new(File::open(path)?),
+            patterns,
+            positions,
+            offsets: Vec::with_capacity(words.min(1 << 20) as usize + 1),
+        };
+
+        // Walk all words to find out where they are.
+        let mut offset = 8 * 4 + size + positions_size;
+        let mut reader = BitReader::new(CountingReader {
+            inner: file,
+            count: 0,
+        });
+        let mut empty = 0;
+        for _ in 0..words {
+            s.offsets.push(offset + reader.inner.count);
+            let word = s
+                .read_word(&mut reader)
+                .with_context(|| format!("{} is truncated", path.display()))?;
+            if word.is_empty() {
+                empty += 1;
+            }
+        }
+        offset += reader.inner.count;
+        s.offsets.push(offset);
+        ensure!(
+            empty == empty_words,
+            "{} has {} empty words, expected {}",
+            path.display(),
+            empty,
+            empty_words
+        );
+        ensure!(
+            reader.inner.read(&mut [0])? == 0,
+            "{} has data after the last word",
+            path.display()
+        );
+
+        Ok(s)
+    }
+
+    /// Number of words.
+    pub fn len(&self) -> usize {
+        self.offsets.len() - 1
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Word `index`, or `None` if there are not that many words.
+    pub fn word(&self, index: usize) -> anyhow::Result<Option<Vec<u8>>> {
+        let (start, end) = match (self.offsets.get(index), self.offsets.get(index + 1)) {
+            (Some(start), Some(end)) => (*start, *end),
+            _ => return Ok(None),
+        };
+
+        let mut data = vec![0; usize::try_from(end - start)?];
+        {
+            let mut file = self.file.lock();
+            file.seek(SeekFrom::Start(start))?;
+            file.read_exact(&mut data)?;
+        }
+        self.read_word(&mut BitReader::new(&data[..])).map(Some)
+    }
+
+    /// Decode the word at the current position of `reader`, which has to be at a byte boundary.
+    fn read_word(&self, reader: &mut BitReader<impl Read>) -> anyhow::Result<Vec<u8>> {
+        let len = usize::try_from(*self.positions.decode(reader)?)?
+            .checked_sub(1)
+            .ok_or_else(|| format_err!("invalid word length"))?;
+        let mut word = vec![0; len];
+        if len == 0 {
+            reader.align();
+            return Ok(word);
+        }
+
+        let mut patterns = Vec::new();
+        let mut pos = 0_usize;
+        loop {
+            let delta = *self.positions.decode(reader)?;
+            if delta == 0 {
+                break;
+            }
+            pos = pos
+                .checked_add(usize::try_from(delta - 1)?)
+                .ok_or_else(|| format_err!("invalid pattern position"))?;
+            let pattern = self.patterns.decode(reader)?;
+            word.get_mut(pos..pos + pattern.len())
+                .ok_or_else(|| format_err!("pattern does not fit into word"))?
+                .copy_from_slice(pattern);
+            patterns.push((pos, pos + pattern.len()));
+        }
+        reader.align();
+
+        // The rest of the word follows in order.
+        let mut uncovered = 0;
+        for (start, end) in patterns.into_iter().chain([(len, len)]) {
+            if start > uncovered {
+                reader.inner.read_exact(&mut word[uncovered..start])?;
+            }
+            uncovered = end;
+        }
+
+        Ok(word)
+    }
+}
+
+struct CountingReader<R> {
+    inner: R,
+    count: u64,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.count += read as u64;
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_read() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("words.seg");
+
+        let words = [
+            b"".to_vec(),
+            b"a".to_vec(),
+            vec![0xaa; 300],
+            b"bc".to_vec(),
+            b"".to_vec(),
+            b"a".to_vec(),
+            b"a".to_vec(),
+        ];
+        let mut compressor = Compressor::create(&path).unwrap();
+        for word in &words {
+            compressor.add_word(word).unwrap();
+        }
+        compressor.finish().unwrap();
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+
+        let decompressor = Decompressor::open(&path).unwrap();
+        assert_eq!(decompressor.len(), words.len());
+        for (i, word) in words.iter().enumerate() {
+            assert_eq!(decompressor.word(i).unwrap().as_ref(), Some(word));
+        }
+        assert_eq!(decompressor.word(words.len()).unwrap(), None);
+
+        let mut data = std::fs::read(&path).unwrap();
+        data.truncate(data.len() - 1);
+        std::fs::write(&path, data).unwrap();
+        assert!(Decompressor::open(&path).is_err());
+    }
+
+    #[test]
+    fn read_patterns() {
+        // Words "hello world" and "hello", with patterns "hello" and "wor", and the rest of the
+        // first word stored as is.
+        let patterns = [(1_u32, b"hello".to_vec()), (1, b"wor".to_vec())];
+        // Lengths plus one 12 and 6, relative positions plus one 1 and 7, and terminating 0.
+        let positions = [(2_u32, 0_u64), (2, 1), (2, 6), (3, 7), (3, 12)];
+
+        let mut data = Vec::new();
+        data.extend_from_slice(&2_u64.to_be_bytes());
+        data.extend_from_slice(&0_u64.to_be_bytes());
+        for dictionary in [
+            patterns
+                .iter()
+                .flat_map(|(depth, pattern)| {
+                    let mut entry = Vec::new();
+                    write_uvarint(&mut entry, (*depth).into());
+                    write_uvarint(&mut entry, pattern.len() as u64);
+                    entry.extend_from_slice(pattern);
+                    entry
+                })
+                .collect::<Vec<_>>(),
+            positions
+                .iter()
+                .flat_map(|(depth, position)| {
+                    let mut entry = Vec::new();
+                    write_uvarint(&mut entry, (*depth).into());
+                    write_uvarint(&mut entry, *position);
+                    entry
+                })
+                .collect(),
+        ] {
+            data.extend_from_slice(&(dictionary.len() as u64).to_be_bytes());
+            data.extend_from_slice(&dictionary);
+        }
+
+        let pattern_codes = codes_of(&[1, 1]).unwrap();
+        let position_codes = codes_of(&[2, 2, 2, 3, 3]).unwrap();
+        let mut words = BitWriter::new(Vec::new());
+        // hello world: length, "hello" at 0, "wor" at 6, end, then " " and "ld".
+        for code in [
+            position_codes[4],
+            position_codes[1],
+            pattern_codes[0],
+            position_codes[3],
+            pattern_codes[1],
+            position_codes[0],
+        ] {
+            words.write_code(code).unwrap();
+        }
+        words.flush().unwrap();
+        words.inner.extend_from_slice(b" ld");
+        // hello: length, "hello" at 0, end.
+        for code in [
+            position_codes[2],
+            position_codes[1],
+            pattern_codes[0],
+            position_codes[0],
+        ] {
+            words.write_code(code).unwrap();
+        }
+        words.flush().unwrap();
+        data.extend_from_slice(&words.inner);
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("words.seg");
+        std::fs::write(&path, data).unwrap();
+
+        let decompressor = Decompressor::open(&path).unwrap();
+        assert_eq!(
+            decompressor.word(0).unwrap().as_deref(),
+            Some(&b"hello world"[..])
+        );
+        assert_eq!(
+            decompressor.word(1).unwrap().as_deref(),
+            Some(&b"hello"[..])
+        );
+    }
+}
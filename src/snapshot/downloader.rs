@@ -0,0 +1,195 @@
+use super::{manifest::*, segment::*};
+use crate::models::*;
+use anyhow::{bail, ensure, format_err, Context};
+use futures_core::Stream;
+use futures_util::stream::{self, StreamExt};
+use hyper::body::HttpBody;
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+use tracing::*;
+
+/// Where published snapshots are fetched from: a plain HTTP mirror of the publication directory,
+/// e.g. a torrent webseed, or a local copy of it. Neither is trusted, the manifest signature and
+/// segment hashes are.
+#[derive(Clone, Debug)]
+pub enum SnapshotSource {
+    Http(String),
+    Dir(PathBuf),
+}
+
+impl FromStr for SnapshotSource {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.starts_with("http://") {
+            Ok(Self::Http(s.trim_end_matches('/').to_string()))
+        } else if s.contains("://") {
+            bail!("unsupported snapshot source {}", s)
+        } else {
+            Ok(Self::Dir(PathBuf::from(s)))
+        }
+    }
+}
+
+impl SnapshotSource {
+    async fn download(url: &str, path: &Path) -> anyhow::Result<()> {
+        let mut res = hyper::Client::new().get(url.parse()?).await?;
+        ensure!(
+            res.status().is_success(),
+            "failed to download {}: {}",
+            url,
+            res.status()
+        );
+
+        // Partial file never gets the final name, so an interrupted download is not mistaken
+        // for a complete one.
+        let part_path = path.with_extension("part");
+        let mut file = std::fs::File::create(&part_path)?;
+        while let Some(chunk) = res.body_mut().data().await {
+            file.write_all(&chunk?)?;
+        }
+        file.sync_all()?;
+        std::fs::rename(&part_path, path)?;
+
+        Ok(())
+    }
+
+    /// Directory with the files `names` of the publication, downloaded into `download_dir` if
+    /// needed.
+    async fn fetch(&self, names: &[String], download_dir: &Path) -> anyhow::Result<PathBuf> {
+        match self {
+            Self::Http(base) => {
+                for name in names {
+                    let url = format!("{}/{}", base, name);
+                    Self::download(&url, &download_dir.join(name))
+                        .await
+                        .with_context(|| format!("failed to download {}", url))?;
+                }
+                Ok(download_dir.to_path_buf())
+            }
+            Self::Dir(dir) => Ok(dir.clone()),
+        }
+    }
+}
+
+/// Fetches published snapshots, falling back to the next source whenever one fails or serves
+/// a file that does not verify, and downloads several segments at a time ahead of import.
+#[derive(Debug)]
+pub struct SnapshotDownloader {
+    sources: Vec<SnapshotSource>,
+    download_dir: PathBuf,
+    concurrency: usize,
+}
+
+impl SnapshotDownloader {
+    pub fn new(sources: Vec<SnapshotSource>, download_dir: PathBuf) -> Self {
+        Self {
+            sources,
+            download_dir,
+            concurrency: 4,
+        }
+    }
+
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Remove files of a segment in `dir` once imported, unless they belong to a local source.
+    pub fn discard(&self, segment: &SegmentInfo, dir: &Path) {
+        if dir.starts_with(&self.download_dir) {
+            for kind in SegmentKind::ALL {
+                let _ = std::fs::remove_file(dir.join(segment.file_name(kind)));
+            }
+        }
+    }
+
+    /// Newest manifest signed by `publisher` among the sources.
+    pub async fn fetch_manifest(&self, publisher: Address) -> anyhow::Result<Manifest> {
+        std::fs::create_dir_all(&self.download_dir)?;
+
+        let mut best: Option<Manifest> = None;
+        for source in &self.sources {
+            let res = async {
+                let dir = source
+                    .fetch(&[MANIFEST_FILE.to_string()], &self.download_dir)
+                    .await?;
+                SignedManifest::load(&dir.join(MANIFEST_FILE))?.verify(publisher)
+            }
+            .await;
+            match res {
+                Ok(manifest) => {
+                    if best
+                        .as_ref()
+                        .map(|best| manifest.last_block() > best.last_block())
+                        .unwrap_or(true)
+                    {
+                        best = Some(manifest);
+                    }
+                }
+                Err(e) => warn!("Skipping snapshot manifest of {:?}: {:#}", source, e),
+            }
+        }
+
+        best.ok_or_else(|| format_err!("no valid snapshot manifest in any source"))
+    }
+
+    async fn fetch_segment(&self, segment: SegmentInfo) -> anyhow::Result<PathBuf> {
+        for source in &self.sources {
+            let res = async {
+                let names = SegmentKind::ALL
+                    .iter()
+                    .map(|kind| segment.file_name(*kind))
+                    .collect::<Vec<_>>();
+                let dir = match source.fetch(&names, &self.download_dir).await {
+                    Ok(dir) => dir,
+                    Err(e) => {
+                        self.discard(&segment, &self.download_dir);
+                        return Err(e);
+                    }
+                };
+                let verified = {
+                    let segment = segment.clone();
+                    let dir = dir.clone();
+                    tokio::task::spawn_blocking(move || segment.verify(&dir)).await?
+                };
+                if let Err(e) = verified {
+                    self.discard(&segment, &dir);
+                    return Err(e);
+                }
+                Ok(dir)
+            }
+            .await;
+            match res {
+                Ok(path) => return Ok(path),
+                Err(e) => warn!(
+                    "Failed to fetch snapshot segment {}..={} from {:?}: {:#}",
+                    segment.from, segment.to, source, e
+                ),
+            }
+        }
+
+        bail!(
+            "snapshot segment {}..={} is not available from any source",
+            segment.from,
+            segment.to
+        )
+    }
+
+    /// Verified segments in their order along with directories of their files, fetching up to
+    /// `concurrency` of them at a time.
+    pub fn segments(
+        &self,
+        segments: Vec<SegmentInfo>,
+    ) -> impl Stream<Item = anyhow::Result<(SegmentInfo, PathBuf)>> + '_ {
+        stream::iter(segments)
+            .map(move |segment| async move {
+                let path = self.fetch_segment(segment.clone()).await?;
+                Ok((segment, path))
+            })
+            .buffered(self.concurrency)
+    }
+}
@@ -0,0 +1,190 @@
+use super::segment::*;
+use crate::{
+    crypto::{keccak256, pubkey_to_address},
+    models::*,
+};
+use anyhow::{ensure, Context};
+use ethereum_types::H520;
+use secp256k1::{
+    ecdsa::{RecoverableSignature, RecoveryId},
+    Message, SecretKey, SECP256K1,
+};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+/// List of published segments, which together cover all blocks from genesis up to the last one.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Manifest {
+    pub genesis_hash: H256,
+    pub segments: Vec<SegmentInfo>,
+}
+
+impl Manifest {
+    pub fn new(genesis_hash: H256) -> Self {
+        Self {
+            genesis_hash,
+            segments: vec![],
+        }
+    }
+
+    /// Last block covered by segments, genesis if there are none.
+    pub fn last_block(&self) -> BlockNumber {
+        self.segments
+            .last()
+            .map(|segment| segment.to)
+            .unwrap_or(BlockNumber(0))
+    }
+
+    /// First block of the next segment, genesis if there are none.
+    pub fn next_block(&self) -> BlockNumber {
+        self.segments
+            .last()
+            .map(|segment| segment.to + 1)
+            .unwrap_or(BlockNumber(0))
+    }
+
+    pub fn push(&mut self, segment: SegmentInfo) -> anyhow::Result<()> {
+        ensure!(
+            segment.from == self.next_block(),
+            "segment {}..={} does not start at block {}",
+            segment.from,
+            segment.to,
+            self.next_block()
+        );
+        self.segments.push(segment);
+        Ok(())
+    }
+
+    /// Check that segments follow each other from genesis without gaps or overlaps.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let mut next_block = BlockNumber(0);
+        for segment in &self.segments {
+            ensure!(
+                segment.from == next_block && segment.from <= segment.to,
+                "segment {}..={} does not start at block {}",
+                segment.from,
+                segment.to,
+                next_block
+            );
+            next_block = segment.to + 1;
+        }
+        Ok(())
+    }
+
+    fn digest(&self) -> anyhow::Result<Message> {
+        Ok(Message::from_slice(
+            keccak256(serde_json::to_vec(self)?).as_bytes(),
+        )?)
+    }
+
+    pub fn sign(self, key: &SecretKey) -> anyhow::Result<SignedManifest> {
+        let (recovery_id, sig) = SECP256K1
+            .sign_ecdsa_recoverable(&self.digest()?, key)
+            .serialize_compact();
+
+        let mut signature = H520::zero();
+        signature.0[..64].copy_from_slice(&sig);
+        signature.0[64] = recovery_id.to_i32() as u8;
+
+        Ok(SignedManifest {
+            manifest: self,
+            signature,
+        })
+    }
+}
+
+/// Manifest along with the recoverable signature of its publisher.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SignedManifest {
+    pub manifest: Manifest,
+    pub signature: H520,
+}
+
+impl SignedManifest {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let data =
+            std::fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+        serde_json::from_slice(&data)
+            .with_context(|| format!("failed to parse manifest {}", path.display()))
+    }
+
+    /// Write the manifest so that readers never see it half-written.
+    pub fn store(&self, path: &Path) -> anyhow::Result<()> {
+        let tmp_path = path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, serde_json::to_vec_pretty(self)?)?;
+        std::fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+
+    pub fn signer(&self) -> anyhow::Result<Address> {
+        let signature = RecoverableSignature::from_compact(
+            &self.signature.0[..64],
+            RecoveryId::from_i32(self.signature.0[64].into())?,
+        )?;
+        let public = SECP256K1.recover_ecdsa(&self.manifest.digest()?, &signature)?;
+        Ok(pubkey_to_address(&public))
+    }
+
+    /// Manifest, if it is valid and signed by `publisher`.
+    pub fn verify(self, publisher: Address) -> anyhow::Result<Manifest> {
+        let signer = self.signer()?;
+        ensure!(
+            signer == publisher,
+            "manifest is signed by {:?}, expected {:?}",
+            signer,
+            publisher
+        );
+        self.manifest.validate()?;
+        Ok(self.manifest)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::{generate_key, to_pubkey};
+
+    #[test]
+    fn sign_and_verify() {
+        let key = generate_key();
+        let publisher = pubkey_to_address(&to_pubkey(&key));
+
+        let segment = |from, to| {
+            let file = FileInfo {
+                hash: H256::repeat_byte(from as u8),
+                size: 1000,
+            };
+            SegmentInfo {
+                from: BlockNumber(from),
+                to: BlockNumber(to),
+                headers: file.clone(),
+                bodies: file.clone(),
+                transactions: file,
+            }
+        };
+
+        let mut manifest = Manifest::new(H256::repeat_byte(0xaa));
+        assert!(manifest.push(segment(1, 999)).is_err());
+        for (from, to) in [(0, 999), (1000, 1999)] {
+            manifest.push(segment(from, to)).unwrap();
+        }
+        assert!(manifest.push(segment(3000, 3999)).is_err());
+        assert_eq!(manifest.last_block(), BlockNumber(1999));
+
+        let signed = manifest.clone().sign(&key).unwrap();
+        let decoded =
+            serde_json::from_slice::<SignedManifest>(&serde_json::to_vec(&signed).unwrap())
+                .unwrap();
+        assert_eq!(decoded.signer().unwrap(), publisher);
+        assert_eq!(decoded.clone().verify(publisher).unwrap(), manifest);
+        assert!(decoded.clone().verify(Address::zero()).is_err());
+
+        let mut tampered = decoded;
+        tampered.manifest.segments[1].bodies.hash = H256::zero();
+        assert!(tampered.verify(publisher).is_err());
+    }
+}
@@ -0,0 +1,18 @@
+//! Snapshots of frozen history: segment files of canonical blocks in Erigon's `.seg` format,
+//! listed along with their hashes in a manifest signed by the publishing node, from which new
+//! nodes can bootstrap.
+
+pub mod bootstrap;
+pub mod compress;
+pub mod downloader;
+pub mod manifest;
+pub mod provider;
+pub mod publisher;
+pub mod segment;
+
+pub use bootstrap::bootstrap;
+pub use downloader::{SnapshotDownloader, SnapshotSource};
+pub use manifest::{Manifest, SignedManifest};
+pub use provider::{block_provider, set_block_provider, BlockProvider, Snapshots};
+pub use publisher::{load_key, SnapshotPublication};
+pub use segment::SegmentInfo;
@@ -0,0 +1,322 @@
+use super::{manifest::*, segment::*};
+use crate::models::*;
+use anyhow::format_err;
+use once_cell::sync::OnceCell;
+use parking_lot::RwLock;
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+static BLOCK_PROVIDER: OnceCell<BlockProvider> = OnceCell::new();
+
+/// Make chain accessors of this process fall back to `provider` for headers, bodies,
+/// transactions and senders that are not in the database.
+pub fn set_block_provider(provider: BlockProvider) -> anyhow::Result<()> {
+    BLOCK_PROVIDER
+        .set(provider)
+        .map_err(|_| format_err!("block provider already set"))
+}
+
+/// Block provider set with [`set_block_provider`], if any.
+pub fn block_provider() -> Option<&'static BlockProvider> {
+    BLOCK_PROVIDER.get()
+}
+
+/// Segments of frozen canonical blocks kept locally, laid out as published: a manifest and the
+/// segment files it lists. A directory without a manifest has no segments yet.
+#[derive(Debug, Default)]
+pub struct Snapshots {
+    segments: Vec<Arc<Segment>>,
+}
+
+impl Snapshots {
+    pub fn open(dir: &Path) -> anyhow::Result<Self> {
+        Self::default().reopen(dir)
+    }
+
+    /// Snapshots of `dir`, with segments that are open already taken from `self`.
+    fn reopen(&self, dir: &Path) -> anyhow::Result<Self> {
+        let manifest_path = dir.join(MANIFEST_FILE);
+        if !manifest_path.exists() {
+            return Ok(Self::default());
+        }
+        let manifest = SignedManifest::load(&manifest_path)?.manifest;
+        manifest.validate()?;
+
+        let mut segments = Vec::with_capacity(manifest.segments.len());
+        for info in &manifest.segments {
+            segments.push(
+                match self
+                    .segments
+                    .iter()
+                    .find(|segment| segment.from() == info.from && segment.to() == info.to)
+                {
+                    Some(segment) => segment.clone(),
+                    None => Arc::new(Segment::open(dir, info.from, info.to)?),
+                },
+            );
+        }
+
+        Ok(Self { segments })
+    }
+
+    /// Last block covered by segments, genesis if there are none.
+    pub fn last_block(&self) -> BlockNumber {
+        self.segments
+            .last()
+            .map(|segment| segment.to())
+            .unwrap_or(BlockNumber(0))
+    }
+
+    fn segment(&self, number: BlockNumber) -> Option<&Segment> {
+        let index = self
+            .segments
+            .partition_point(|segment| segment.to() < number);
+        self.segments.get(index).map(|segment| &**segment)
+    }
+
+    /// Header of block `number`, if it is frozen and is `hash`.
+    pub fn header(&self, hash: H256, number: BlockNumber) -> anyhow::Result<Option<BlockHeader>> {
+        Ok(match self.segment(number) {
+            Some(segment) => segment
+                .header(number)?
+                .filter(|header| header.hash() == hash),
+            None => None,
+        })
+    }
+
+    /// Body of block `number`, if it is frozen and is `hash`.
+    pub fn body(&self, hash: H256, number: BlockNumber) -> anyhow::Result<Option<BodyForStorage>> {
+        if self.header(hash, number)?.is_none() {
+            return Ok(None);
+        }
+
+        match self.segment(number) {
+            Some(segment) => segment.body(number),
+            None => Ok(None),
+        }
+    }
+
+    /// `amount` transactions with their senders starting from `base_tx_id`, if they are all
+    /// frozen.
+    pub fn transactions(
+        &self,
+        base_tx_id: TxIndex,
+        amount: u64,
+    ) -> anyhow::Result<Option<Vec<(MessageWithSignature, Address)>>> {
+        let index = self
+            .segments
+            .partition_point(|segment| segment.tx_ids().1 <= base_tx_id);
+        match self.segments.get(index) {
+            Some(segment) => segment.transactions(base_tx_id, amount),
+            None => Ok(None),
+        }
+    }
+
+    /// Canonical block `number`, or `None` if it is not frozen.
+    pub fn block(&self, number: BlockNumber) -> anyhow::Result<Option<Block>> {
+        match self.segment(number) {
+            Some(segment) => segment.block(number),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Frozen blocks of a snapshot directory, that chain accessors read when they are not in the
+/// database, so that callers do not care where ancient blocks live. See [`set_block_provider`].
+///
+/// Segments published into the directory later are picked up on [`Self::reload`].
+#[derive(Debug)]
+pub struct BlockProvider {
+    dir: PathBuf,
+    snapshots: RwLock<Arc<Snapshots>>,
+}
+
+impl BlockProvider {
+    pub fn open(dir: &Path) -> anyhow::Result<Self> {
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            snapshots: RwLock::new(Arc::new(Snapshots::open(dir)?)),
+        })
+    }
+
+    pub fn reload(&self) -> anyhow::Result<()> {
+        let snapshots = self.snapshots().reopen(&self.dir)?;
+        *self.snapshots.write() = Arc::new(snapshots);
+        Ok(())
+    }
+
+    pub fn snapshots(&self) -> Arc<Snapshots> {
+        self.snapshots.read().clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        accessors::chain,
+        crypto::generate_key,
+        kv::{new_mem_database, tables, traits::*},
+        res::chainspec::MAINNET,
+        state::genesis::initialize_genesis,
+    };
+    use bytes::Bytes;
+
+    #[tokio::test]
+    async fn accessors_read_db_then_snapshots() {
+        let snapshot_dir = tempfile::tempdir().unwrap();
+        let etl_dir = tempfile::tempdir().unwrap();
+
+        let db = new_mem_database().unwrap();
+        let txn = db.begin_mutable().await.unwrap();
+        initialize_genesis(&txn, &etl_dir, MAINNET.clone())
+            .await
+            .unwrap();
+        let genesis_hash = chain::canonical_hash::read(&txn, BlockNumber(0))
+            .await
+            .unwrap()
+            .unwrap();
+
+        // Frozen blocks that are not in the database, with one transaction each, numbered far
+        // from anything other tests of this process have.
+        let base_tx_id = TxIndex(1 << 40);
+        let sender = Address::repeat_byte(0xee);
+        let blocks = (0..1000)
+            .map(|number| Block {
+                header: BlockHeader {
+                    number: BlockNumber(number),
+                    gas_limit: 0xfeed,
+                    ..BlockHeader::empty()
+                },
+                transactions: vec![MessageWithSignature {
+                    message: Message::Legacy {
+                        chain_id: None,
+                        nonce: number,
+                        gas_price: U256::ONE,
+                        gas_limit: 21_000,
+                        action: TransactionAction::Call(Address::repeat_byte(1)),
+                        value: U256::ZERO,
+                        input: Bytes::new(),
+                    },
+                    signature: MessageSignature::new(
+                        false,
+                        H256::repeat_byte(2),
+                        H256::repeat_byte(3),
+                    )
+                    .unwrap(),
+                }],
+                ommers: vec![],
+                withdrawals: None,
+            })
+            .collect::<Vec<_>>();
+        let mut writer =
+            SegmentWriter::create(snapshot_dir.path(), BlockNumber(0), BlockNumber(999)).unwrap();
+        for block in &blocks {
+            writer
+                .push(
+                    &block.header,
+                    &BodyForStorage {
+                        base_tx_id: base_tx_id + block.header.number.0,
+                        tx_amount: 1,
+                        uncles: vec![],
+                        withdrawals: None,
+                    },
+                    &block.transactions,
+                    &[sender],
+                )
+                .unwrap();
+        }
+        let mut manifest = Manifest::new(genesis_hash);
+        manifest.push(writer.finish().unwrap()).unwrap();
+
+        let provider = BlockProvider::open(snapshot_dir.path()).unwrap();
+        assert_eq!(provider.snapshots().last_block(), BlockNumber(0));
+
+        manifest
+            .sign(&generate_key())
+            .unwrap()
+            .store(&snapshot_dir.path().join(MANIFEST_FILE))
+            .unwrap();
+        provider.reload().unwrap();
+        assert_eq!(provider.snapshots().last_block(), BlockNumber(999));
+        set_block_provider(provider).unwrap();
+
+        // Database takes precedence.
+        assert_eq!(
+            chain::header::read(&txn, genesis_hash, 0)
+                .await
+                .unwrap()
+                .unwrap()
+                .hash(),
+            genesis_hash
+        );
+        assert!(
+            chain::block_body::read_without_senders(&txn, genesis_hash, 0)
+                .await
+                .unwrap()
+                .unwrap()
+                .transactions
+                .is_empty()
+        );
+
+        let hash = blocks[2].header.hash();
+        assert_eq!(
+            chain::header::read(&txn, hash, 2).await.unwrap().as_ref(),
+            Some(&blocks[2].header)
+        );
+        assert_eq!(
+            chain::block_body::read_without_senders(&txn, hash, 2)
+                .await
+                .unwrap()
+                .unwrap()
+                .transactions,
+            blocks[2].transactions
+        );
+        let body = chain::block_body::read_with_senders(&txn, hash, 2)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(body.transactions.len(), 1);
+        assert_eq!(body.transactions[0].sender, sender);
+        assert_eq!(
+            chain::tx::read(&txn, base_tx_id + 3, 2).await.unwrap(),
+            vec![
+                blocks[3].transactions[0].clone(),
+                blocks[4].transactions[0].clone()
+            ]
+        );
+
+        // Frozen blocks are only returned for their own hashes.
+        assert_eq!(chain::header::read(&txn, hash, 3).await.unwrap(), None);
+        assert_eq!(
+            chain::storage_body::read(&txn, hash, 3).await.unwrap(),
+            None
+        );
+        assert!(chain::tx_sender::read(&txn, hash, 3)
+            .await
+            .unwrap()
+            .is_empty());
+
+        // Transactions in the database are not mixed up with frozen ones.
+        txn.set(
+            tables::BlockTransaction,
+            base_tx_id + 3,
+            blocks[5].transactions[0].clone(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            chain::tx::read(&txn, base_tx_id + 3, 2).await.unwrap(),
+            vec![
+                blocks[3].transactions[0].clone(),
+                blocks[4].transactions[0].clone()
+            ]
+        );
+        assert_eq!(
+            chain::tx::read(&txn, base_tx_id + 3, 1).await.unwrap(),
+            vec![blocks[5].transactions[0].clone()]
+        );
+    }
+}
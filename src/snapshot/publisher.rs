@@ -0,0 +1,219 @@
+use super::{manifest::*, provider::block_provider, segment::*};
+use crate::{
+    accessors::chain,
+    kv::{tables, traits::*},
+    models::*,
+    stagedsync::{stage::*, stages::*},
+    StageId,
+};
+use anyhow::{bail, ensure, format_err, Context};
+use async_trait::async_trait;
+use educe::*;
+use secp256k1::SecretKey;
+use std::path::{Path, PathBuf};
+use tracing::*;
+
+/// Read publisher key from a file with its hex encoding.
+pub fn load_key(path: &Path) -> anyhow::Result<SecretKey> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read key {}", path.display()))?;
+    let text = text.trim();
+    Ok(SecretKey::from_slice(&hex::decode(
+        text.strip_prefix("0x").unwrap_or(text),
+    )?)?)
+}
+
+/// Write blocks `from..=to` of the canonical chain into a new segment in `dir`. Senders that are
+/// not in the database are recovered.
+pub async fn write_segment<'db, Tx: Transaction<'db>>(
+    tx: &Tx,
+    dir: &Path,
+    from: BlockNumber,
+    to: BlockNumber,
+) -> anyhow::Result<SegmentInfo> {
+    let mut writer = SegmentWriter::create(dir, from, to)?;
+    for block_number in from..=to {
+        let hash = chain::canonical_hash::read(tx, block_number)
+            .await?
+            .ok_or_else(|| format_err!("no canonical hash for block {}", block_number))?;
+        let header = chain::header::read(tx, hash, block_number)
+            .await?
+            .ok_or_else(|| format_err!("no header for block {}/{:?}", block_number, hash))?;
+        let body = chain::storage_body::read(tx, hash, block_number)
+            .await?
+            .ok_or_else(|| format_err!("no body for block {}/{:?}", block_number, hash))?;
+        let transactions = chain::tx::read(tx, body.base_tx_id, body.tx_amount.try_into()?).await?;
+        let mut senders = chain::tx_sender::read(tx, hash, block_number).await?;
+        if senders.len() != transactions.len() {
+            senders = transactions
+                .iter()
+                .map(MessageWithSignature::recover_sender)
+                .collect::<anyhow::Result<_>>()?;
+        }
+
+        writer.push(&header, &body, &transactions, &senders)?;
+    }
+    writer.finish()
+}
+
+/// Delete bodies, transactions and senders of blocks `from..=to` from the database, once
+/// canonical ones are in a segment. Headers are kept, and so is the genesis body.
+pub async fn delete_bodies<'db, RwTx: MutableTransaction<'db>>(
+    tx: &RwTx,
+    from: BlockNumber,
+    to: BlockNumber,
+) -> anyhow::Result<()> {
+    let from = from.max(BlockNumber(1));
+    if from > to {
+        return Ok(());
+    }
+
+    let canonical_body = |block_number: BlockNumber| async move {
+        let hash = chain::canonical_hash::read(tx, block_number)
+            .await?
+            .ok_or_else(|| format_err!("no canonical hash for block {}", block_number))?;
+        chain::storage_body::read(tx, hash, block_number).await
+    };
+    if let (Some(first), Some(last)) = (canonical_body(from).await?, canonical_body(to).await?) {
+        tx.delete_range(
+            tables::BlockTransaction,
+            first.base_tx_id,
+            Some(last.base_tx_id + last.tx_amount),
+        )
+        .await?;
+    }
+    tx.delete_range(tables::BlockBody, from, Some(to + 1))
+        .await?;
+    tx.delete_range(
+        tables::TxSender,
+        (from, H256::zero()),
+        Some((to + 1, H256::zero())),
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Every `segment_size` blocks that are at least `freeze_depth` blocks deep, starting from
+/// genesis, writes them into a segment in `dir` and publishes an updated manifest there, signed with `key`. With
+/// `delete_bodies`, bodies of these blocks are then deleted from the database, and only served
+/// from segments, e.g. by `akula-rpc --snapshot.dir`.
+///
+/// The directory is meant to be served over HTTP or seeded as a torrent, it is the source of
+/// truth of what has been published already.
+#[derive(Educe)]
+#[educe(Debug)]
+pub struct SnapshotPublication {
+    pub dir: PathBuf,
+    pub segment_size: u64,
+    pub freeze_depth: u64,
+    pub delete_bodies: bool,
+    #[educe(Debug(ignore))]
+    pub key: SecretKey,
+}
+
+impl SnapshotPublication {
+    fn manifest_path(&self) -> PathBuf {
+        self.dir.join(MANIFEST_FILE)
+    }
+}
+
+#[async_trait]
+impl<'db, RwTx> Stage<'db, RwTx> for SnapshotPublication
+where
+    RwTx: MutableTransaction<'db>,
+{
+    fn id(&self) -> StageId {
+        SNAPSHOT_PUBLICATION
+    }
+
+    async fn execute<'tx>(
+        &mut self,
+        tx: &'tx mut RwTx,
+        input: StageInput,
+    ) -> anyhow::Result<ExecOutput>
+    where
+        'db: 'tx,
+    {
+        let max_block = input
+            .previous_stage
+            .map(|(_, v)| v)
+            .ok_or_else(|| format_err!("Cannot be the first stage"))?;
+
+        ensure!(
+            self.segment_size > 0 && self.segment_size % SEGMENT_STEP == 0,
+            "segment size {} is not a multiple of {}",
+            self.segment_size,
+            SEGMENT_STEP
+        );
+
+        std::fs::create_dir_all(&self.dir)?;
+        let genesis_hash = chain::canonical_hash::read(tx, BlockNumber(0))
+            .await?
+            .ok_or_else(|| format_err!("no genesis block"))?;
+        let mut manifest = if self.manifest_path().exists() {
+            let manifest = SignedManifest::load(&self.manifest_path())?.manifest;
+            if manifest.genesis_hash != genesis_hash {
+                bail!(
+                    "{} is for genesis {:?}, not {:?}",
+                    self.manifest_path().display(),
+                    manifest.genesis_hash,
+                    genesis_hash
+                );
+            }
+            manifest
+        } else {
+            Manifest::new(genesis_hash)
+        };
+
+        let frozen = max_block.0.saturating_sub(self.freeze_depth);
+        while manifest.next_block().0 + self.segment_size <= frozen + 1 {
+            let from = manifest.next_block();
+            let to = from + (self.segment_size - 1);
+            info!("Writing snapshot segment {}..={}", from, to);
+
+            let segment = write_segment(tx, &self.dir, from, to).await?;
+            manifest.push(segment)?;
+            manifest
+                .clone()
+                .sign(&self.key)?
+                .store(&self.manifest_path())?;
+            if let Some(provider) = block_provider() {
+                provider.reload()?;
+            }
+
+            if self.delete_bodies {
+                delete_bodies(tx, from, to).await?;
+            }
+        }
+
+        Ok(ExecOutput::Progress {
+            stage_progress: max_block,
+            done: true,
+        })
+    }
+
+    async fn unwind<'tx>(
+        &mut self,
+        _tx: &'tx mut RwTx,
+        input: UnwindInput,
+    ) -> anyhow::Result<UnwindOutput>
+    where
+        'db: 'tx,
+    {
+        // Published segments cannot be taken back, which is what freeze depth is for.
+        if let Ok(signed) = SignedManifest::load(&self.manifest_path()) {
+            if signed.manifest.last_block() > input.unwind_to {
+                warn!(
+                    "Unwinding to block {} below published snapshots up to block {}",
+                    input.unwind_to,
+                    signed.manifest.last_block()
+                );
+            }
+        }
+
+        Ok(UnwindOutput {
+            stage_progress: input.unwind_to,
+        })
+    }
+}
@@ -0,0 +1,557 @@
+use super::compress::*;
+use crate::{crypto::TrieEncode, models::*};
+use anyhow::{ensure, format_err, Context};
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Keccak256};
+use std::{
+    fs::File,
+    io::{BufReader, Read},
+    path::{Path, PathBuf},
+};
+
+/// Segment boundaries are multiples of this many blocks, the unit of block numbers in file names.
+pub const SEGMENT_STEP: u64 = 1000;
+
+/// Files a segment is made of, same as Erigon's block snapshots.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SegmentKind {
+    /// First byte of the block hash followed by the RLP of the header, for every block.
+    Headers,
+    /// RLP of [`BodyForStorage`] of every block.
+    Bodies,
+    /// First byte of the transaction hash, sender and the transaction in the form it has in
+    /// the transaction trie, for every transaction.
+    Transactions,
+}
+
+impl SegmentKind {
+    pub const ALL: [Self; 3] = [Self::Headers, Self::Bodies, Self::Transactions];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Headers => "headers",
+            Self::Bodies => "bodies",
+            Self::Transactions => "transactions",
+        }
+    }
+}
+
+/// Name of `kind` file of segment `from..=to` as Erigon has it: version, first block and the
+/// block after the last one in thousands, and kind.
+pub fn file_name(from: BlockNumber, to: BlockNumber, kind: SegmentKind) -> String {
+    format!(
+        "v1-{:06}-{:06}-{}.seg",
+        from.0 / SEGMENT_STEP,
+        (to.0 + 1) / SEGMENT_STEP,
+        kind.as_str()
+    )
+}
+
+/// Keccak-256 and size of a file.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileInfo {
+    pub hash: H256,
+    pub size: u64,
+}
+
+impl FileInfo {
+    pub fn of(path: &Path) -> anyhow::Result<Self> {
+        let mut file = BufReader::new(
+            File::open(path).with_context(|| format!("failed to open {}", path.display()))?,
+        );
+        let mut hasher = Keccak256::new();
+        let mut size = 0;
+        let mut buf = vec![0; 1 << 16];
+        loop {
+            let read = file.read(&mut buf)?;
+            if read == 0 {
+                break;
+            }
+            hasher.update(&buf[..read]);
+            size += read as u64;
+        }
+
+        Ok(Self {
+            hash: H256::from_slice(&hasher.finalize()),
+            size,
+        })
+    }
+
+    /// Check that the file at `path` is exactly the one described by this info.
+    pub fn verify(&self, path: &Path) -> anyhow::Result<()> {
+        let info = Self::of(path)?;
+        ensure!(
+            info.size == self.size,
+            "{} has size {}, expected {}",
+            path.display(),
+            info.size,
+            self.size
+        );
+        ensure!(
+            info.hash == self.hash,
+            "{} has hash {:?}, expected {:?}",
+            path.display(),
+            info.hash,
+            self.hash
+        );
+
+        Ok(())
+    }
+}
+
+/// Segment of consecutive canonical blocks, kept in Erigon's headers, bodies and transactions
+/// `.seg` files, see [`SegmentKind`] for their words.
+///
+/// Transactions are numbered as in the database of the publisher, with bodies referring to them
+/// by these numbers. Unlike Erigon, no numbers are reserved for system transactions.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SegmentInfo {
+    pub from: BlockNumber,
+    /// Last block of the segment, inclusive.
+    pub to: BlockNumber,
+    pub headers: FileInfo,
+    pub bodies: FileInfo,
+    pub transactions: FileInfo,
+}
+
+impl SegmentInfo {
+    pub fn file_name(&self, kind: SegmentKind) -> String {
+        file_name(self.from, self.to, kind)
+    }
+
+    pub fn file(&self, kind: SegmentKind) -> &FileInfo {
+        match kind {
+            SegmentKind::Headers => &self.headers,
+            SegmentKind::Bodies => &self.bodies,
+            SegmentKind::Transactions => &self.transactions,
+        }
+    }
+
+    /// Check that files of the segment in `dir` are exactly the ones described by this info.
+    pub fn verify(&self, dir: &Path) -> anyhow::Result<()> {
+        for kind in SegmentKind::ALL {
+            self.file(kind).verify(&dir.join(self.file_name(kind)))?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes blocks `from..=to` into segment files in `dir`.
+#[derive(Debug)]
+pub struct SegmentWriter {
+    dir: PathBuf,
+    from: BlockNumber,
+    to: BlockNumber,
+    next: BlockNumber,
+    next_tx_id: Option<TxIndex>,
+    headers: Compressor,
+    bodies: Compressor,
+    transactions: Compressor,
+}
+
+impl SegmentWriter {
+    pub fn create(dir: &Path, from: BlockNumber, to: BlockNumber) -> anyhow::Result<Self> {
+        ensure!(from <= to, "empty segment {}..={}", from, to);
+        ensure!(
+            from.0 % SEGMENT_STEP == 0 && (to.0 + 1) % SEGMENT_STEP == 0,
+            "segment {}..={} is not aligned to {} blocks",
+            from,
+            to,
+            SEGMENT_STEP
+        );
+
+        let compressor = |kind| Compressor::create(&dir.join(file_name(from, to, kind)));
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            from,
+            to,
+            next: from,
+            next_tx_id: None,
+            headers: compressor(SegmentKind::Headers)?,
+            bodies: compressor(SegmentKind::Bodies)?,
+            transactions: compressor(SegmentKind::Transactions)?,
+        })
+    }
+
+    /// Add the next block, with `transactions` and their `senders`.
+    pub fn push(
+        &mut self,
+        header: &BlockHeader,
+        body: &BodyForStorage,
+        transactions: &[MessageWithSignature],
+        senders: &[Address],
+    ) -> anyhow::Result<()> {
+        ensure!(
+            header.number == self.next && self.next <= self.to,
+            "block {} out of order in segment {}..={}, expected {}",
+            header.number,
+            self.from,
+            self.to,
+            self.next
+        );
+        ensure!(
+            body.tx_amount == transactions.len() as u64 && transactions.len() == senders.len(),
+            "block {} has {} transactions and {} senders, expected {}",
+            header.number,
+            transactions.len(),
+            senders.len(),
+            body.tx_amount
+        );
+        if let Some(next_tx_id) = self.next_tx_id {
+            ensure!(
+                body.base_tx_id == next_tx_id,
+                "transactions of block {} start at {}, expected {}",
+                header.number,
+                body.base_tx_id,
+                next_tx_id
+            );
+        }
+
+        let mut word = vec![header.hash()[0]];
+        word.extend_from_slice(&rlp::encode(header));
+        self.headers.add_word(&word)?;
+
+        self.bodies.add_word(&rlp::encode(body))?;
+
+        for (transaction, sender) in transactions.iter().zip(senders) {
+            let mut word = vec![transaction.hash()[0]];
+            word.extend_from_slice(sender.as_bytes());
+            word.extend_from_slice(&transaction.trie_encode());
+            self.transactions.add_word(&word)?;
+        }
+
+        self.next.0 += 1;
+        self.next_tx_id = Some(body.base_tx_id + body.tx_amount);
+
+        Ok(())
+    }
+
+    pub fn finish(self) -> anyhow::Result<SegmentInfo> {
+        ensure!(
+            self.next == self.to + 1,
+            "segment {}..={} is incomplete, next block {}",
+            self.from,
+            self.to,
+            self.next
+        );
+
+        self.headers.finish()?;
+        self.bodies.finish()?;
+        self.transactions.finish()?;
+
+        let info = |kind| FileInfo::of(&self.dir.join(file_name(self.from, self.to, kind)));
+        Ok(SegmentInfo {
+            from: self.from,
+            to: self.to,
+            headers: info(SegmentKind::Headers)?,
+            bodies: info(SegmentKind::Bodies)?,
+            transactions: info(SegmentKind::Transactions)?,
+        })
+    }
+}
+
+/// Segment files opened for random access. Contents are trusted, so verify the files first.
+#[derive(Debug)]
+pub struct Segment {
+    from: BlockNumber,
+    to: BlockNumber,
+    headers: Decompressor,
+    bodies: Decompressor,
+    transactions: Decompressor,
+    first_tx_id: TxIndex,
+}
+
+impl Segment {
+    pub fn open(dir: &Path, from: BlockNumber, to: BlockNumber) -> anyhow::Result<Self> {
+        let open = |kind| {
+            let path = dir.join(file_name(from, to, kind));
+            Decompressor::open(&path)
+                .with_context(|| format!("failed to open segment {}", path.display()))
+        };
+        let mut s = Self {
+            from,
+            to,
+            headers: open(SegmentKind::Headers)?,
+            bodies: open(SegmentKind::Bodies)?,
+            transactions: open(SegmentKind::Transactions)?,
+            first_tx_id: TxIndex(0),
+        };
+
+        let blocks = to.0 - from.0 + 1;
+        ensure!(
+            s.headers.len() as u64 == blocks && s.bodies.len() as u64 == blocks,
+            "segment {}..={} has {} headers and {} bodies",
+            from,
+            to,
+            s.headers.len(),
+            s.bodies.len()
+        );
+        let first = s
+            .body(from)?
+            .ok_or_else(|| format_err!("no body of block {} in segment", from))?;
+        let last = s
+            .body(to)?
+            .ok_or_else(|| format_err!("no body of block {} in segment", to))?;
+        s.first_tx_id = first.base_tx_id;
+        ensure!(
+            last.base_tx_id.0 + last.tx_amount - first.base_tx_id.0 == s.transactions.len() as u64,
+            "segment {}..={} has {} transactions, bodies refer to {}..{}",
+            from,
+            to,
+            s.transactions.len(),
+            first.base_tx_id,
+            last.base_tx_id + last.tx_amount
+        );
+
+        Ok(s)
+    }
+
+    pub fn from(&self) -> BlockNumber {
+        self.from
+    }
+
+    /// Last block of the segment, inclusive.
+    pub fn to(&self) -> BlockNumber {
+        self.to
+    }
+
+    /// Transactions of the segment, first one and the one after the last.
+    pub fn tx_ids(&self) -> (TxIndex, TxIndex) {
+        (
+            self.first_tx_id,
+            self.first_tx_id + self.transactions.len() as u64,
+        )
+    }
+
+    fn block_word(
+        &self,
+        file: &Decompressor,
+        number: BlockNumber,
+    ) -> anyhow::Result<Option<Vec<u8>>> {
+        match number.0.checked_sub(self.from.0) {
+            Some(index) if number <= self.to => file.word(index.try_into()?),
+            _ => Ok(None),
+        }
+    }
+
+    /// Header of block `number`, or `None` if it is outside of the segment.
+    pub fn header(&self, number: BlockNumber) -> anyhow::Result<Option<BlockHeader>> {
+        let word = match self.block_word(&self.headers, number)? {
+            Some(word) => word,
+            None => return Ok(None),
+        };
+
+        let decode = || {
+            let header = rlp::decode::<BlockHeader>(
+                word.get(1..).ok_or_else(|| format_err!("empty word"))?,
+            )?;
+            ensure!(
+                header.number == number && header.hash()[0] == word[0],
+                "header does not match its block"
+            );
+            Ok(header)
+        };
+
+        decode()
+            .map(Some)
+            .map_err(|e: anyhow::Error| format_err!("failed to read header {}: {}", number, e))
+    }
+
+    /// Body of block `number`, or `None` if it is outside of the segment.
+    pub fn body(&self, number: BlockNumber) -> anyhow::Result<Option<BodyForStorage>> {
+        match self.block_word(&self.bodies, number)? {
+            Some(word) => {
+                Ok(Some(rlp::decode(&word).map_err(|e| {
+                    format_err!("failed to read body {}: {}", number, e)
+                })?))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// `amount` transactions with their senders starting from `base_tx_id`, or `None` if they
+    /// are not all in the segment.
+    pub fn transactions(
+        &self,
+        base_tx_id: TxIndex,
+        amount: u64,
+    ) -> anyhow::Result<Option<Vec<(MessageWithSignature, Address)>>> {
+        let (first, end) = self.tx_ids();
+        if base_tx_id < first || base_tx_id.0 + amount > end.0 {
+            return Ok(None);
+        }
+
+        (base_tx_id.0..base_tx_id.0 + amount)
+            .map(|id| {
+                let word = self
+                    .transactions
+                    .word((id - first.0).try_into()?)?
+                    .ok_or_else(|| format_err!("no transaction {}", id))?;
+                ensure!(
+                    word.len() > 1 + Address::len_bytes(),
+                    "short transaction word"
+                );
+                let transaction =
+                    MessageWithSignature::trie_decode(&word[1 + Address::len_bytes()..])?;
+                ensure!(
+                    transaction.hash()[0] == word[0],
+                    "transaction {} does not match",
+                    id
+                );
+                Ok((
+                    transaction,
+                    Address::from_slice(&word[1..1 + Address::len_bytes()]),
+                ))
+            })
+            .collect::<anyhow::Result<_>>()
+            .map(Some)
+            .map_err(|e| {
+                format_err!(
+                    "failed to read transactions {}+{}: {}",
+                    base_tx_id,
+                    amount,
+                    e
+                )
+            })
+    }
+
+    /// Block `number`, or `None` if it is outside of the segment.
+    pub fn block(&self, number: BlockNumber) -> anyhow::Result<Option<Block>> {
+        let (header, body) = match (self.header(number)?, self.body(number)?) {
+            (Some(header), Some(body)) => (header, body),
+            _ => return Ok(None),
+        };
+        let transactions = self
+            .transactions(body.base_tx_id, body.tx_amount)?
+            .ok_or_else(|| format_err!("transactions of block {} are not in segment", number))?;
+
+        Ok(Some(Block {
+            header,
+            transactions: transactions
+                .into_iter()
+                .map(|(transaction, _)| transaction)
+                .collect(),
+            ommers: body.uncles,
+            withdrawals: body.withdrawals,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+
+    fn txn(nonce: u64) -> MessageWithSignature {
+        MessageWithSignature {
+            message: Message::EIP1559 {
+                chain_id: ChainId(1),
+                nonce,
+                max_priority_fee_per_gas: U256::ONE,
+                max_fee_per_gas: U256::ONE,
+                gas_limit: 21_000,
+                action: TransactionAction::Call(Address::repeat_byte(1)),
+                value: U256::ZERO,
+                input: Bytes::new(),
+                access_list: vec![],
+            },
+            signature: MessageSignature::new(false, H256::repeat_byte(2), H256::repeat_byte(3))
+                .unwrap(),
+        }
+    }
+
+    #[test]
+    fn write_verify_read() {
+        let dir = tempfile::tempdir().unwrap();
+        let (from, to) = (BlockNumber(1000), BlockNumber(1999));
+
+        // Every tenth block has a transaction.
+        let mut next_tx_id = 500;
+        let blocks = (from..=to)
+            .map(|number| {
+                let transactions = if number.0 % 10 == 0 {
+                    vec![txn(number.0)]
+                } else {
+                    vec![]
+                };
+                let body = BodyForStorage {
+                    base_tx_id: TxIndex(next_tx_id),
+                    tx_amount: transactions.len() as u64,
+                    uncles: vec![],
+                    withdrawals: None,
+                };
+                next_tx_id += body.tx_amount;
+                let header = BlockHeader {
+                    number,
+                    ..BlockHeader::empty()
+                };
+                (header, body, transactions)
+            })
+            .collect::<Vec<_>>();
+        let senders =
+            |number: BlockNumber, amount| vec![Address::from_low_u64_be(number.0); amount];
+
+        assert!(SegmentWriter::create(dir.path(), BlockNumber(1), to).is_err());
+        let mut writer = SegmentWriter::create(dir.path(), from, to).unwrap();
+        let (header, body, transactions) = &blocks[1];
+        assert!(writer
+            .push(
+                header,
+                body,
+                transactions,
+                &senders(header.number, transactions.len())
+            )
+            .is_err());
+        for (header, body, transactions) in &blocks {
+            writer
+                .push(
+                    header,
+                    body,
+                    transactions,
+                    &senders(header.number, transactions.len()),
+                )
+                .unwrap();
+        }
+        let info = writer.finish().unwrap();
+        assert_eq!(
+            info.file_name(SegmentKind::Bodies),
+            "v1-000001-000002-bodies.seg"
+        );
+        info.verify(dir.path()).unwrap();
+
+        let segment = Segment::open(dir.path(), from, to).unwrap();
+        assert_eq!(segment.tx_ids(), (TxIndex(500), TxIndex(600)));
+        for (header, body, transactions) in &blocks {
+            assert_eq!(
+                segment.header(header.number).unwrap().as_ref(),
+                Some(header)
+            );
+            assert_eq!(segment.body(header.number).unwrap().as_ref(), Some(body));
+            assert_eq!(
+                segment
+                    .transactions(body.base_tx_id, body.tx_amount)
+                    .unwrap(),
+                Some(
+                    transactions
+                        .iter()
+                        .cloned()
+                        .zip(senders(header.number, transactions.len()))
+                        .collect()
+                )
+            );
+        }
+        let block = segment.block(BlockNumber(1010)).unwrap().unwrap();
+        assert_eq!(block.header, blocks[10].0);
+        assert_eq!(block.transactions, blocks[10].2);
+        assert_eq!(segment.header(BlockNumber(999)).unwrap(), None);
+        assert_eq!(segment.body(BlockNumber(2000)).unwrap(), None);
+        assert_eq!(segment.transactions(TxIndex(599), 2).unwrap(), None);
+
+        let path = dir.path().join(info.file_name(SegmentKind::Transactions));
+        let mut data = std::fs::read(&path).unwrap();
+        *data.last_mut().unwrap() ^= 1;
+        std::fs::write(&path, data).unwrap();
+        assert!(info.verify(dir.path()).is_err());
+    }
+}
@@ -1,8 +1,15 @@
 pub mod stage;
 pub mod stages;
 
-use self::stage::{Stage, StageInput, UnwindInput};
-use crate::{kv::traits::*, models::BlockNumber, stagedsync::stage::*};
+use self::{
+    stage::{Stage, StageInput, UnwindInput},
+    stages::UNWIND_TARGET,
+};
+use crate::{
+    kv::{disk_guard::DiskGuard, stats as kv_stats, tables, traits::*},
+    models::BlockNumber,
+    stagedsync::stage::*,
+};
 use std::time::{Duration, Instant};
 use tracing::*;
 
@@ -21,9 +28,11 @@ use tracing::*;
 pub struct StagedSync<'db, DB: MutableKV> {
     stages: Vec<Box<dyn Stage<'db, DB::MutableTx<'db>>>>,
     min_progress_to_commit_after_stage: u64,
+    unwind_batch_size: Option<u64>,
     max_block: Option<BlockNumber>,
     exit_after_sync: bool,
     delay_after_sync: Option<Duration>,
+    disk_guard: Option<DiskGuard>,
 }
 
 impl<'db, DB: MutableKV> Default for StagedSync<'db, DB> {
@@ -37,9 +46,11 @@ impl<'db, DB: MutableKV> StagedSync<'db, DB> {
         Self {
             stages: Vec::new(),
             min_progress_to_commit_after_stage: 0,
+            unwind_batch_size: None,
             max_block: None,
             exit_after_sync: false,
             delay_after_sync: None,
+            disk_guard: None,
         }
     }
 
@@ -55,6 +66,12 @@ impl<'db, DB: MutableKV> StagedSync<'db, DB> {
         self
     }
 
+    /// Unwind each stage by at most this many blocks per transaction, committing in between.
+    pub fn set_unwind_batch_size(&mut self, v: Option<u64>) -> &mut Self {
+        self.unwind_batch_size = v;
+        self
+    }
+
     pub fn set_max_block(&mut self, v: Option<BlockNumber>) -> &mut Self {
         self.max_block = v;
         self
@@ -70,19 +87,29 @@ impl<'db, DB: MutableKV> StagedSync<'db, DB> {
         self
     }
 
+    /// Pause before running stages while the database has no room to grow.
+    pub fn set_disk_guard(&mut self, v: Option<DiskGuard>) -> &mut Self {
+        self.disk_guard = v;
+        self
+    }
+
     /// Run staged sync loop.
     /// Invokes each loaded stage, and does unwinds if necessary.
     ///
     /// NOTE: it should never return, except if the loop or any stage fails with error.
     pub async fn run(&mut self, db: &'db DB) -> anyhow::Result<()> {
         let num_stages = self.stages.len();
+        let unwind_batch_size = self.unwind_batch_size;
 
-        let mut unwind_to = None;
+        // Resume unwind interrupted by restart.
+        let mut unwind_to = UNWIND_TARGET.get_progress(&db.begin().await?).await?;
         'run_loop: loop {
             let mut tx = db.begin_mutable().await?;
 
             // Start with unwinding if it's been requested.
             if let Some(to) = unwind_to.take() {
+                UNWIND_TARGET.save_progress(&tx, to).await?;
+
                 // Unwind stages in reverse order.
                 for (stage_index, stage) in self.stages.iter_mut().enumerate().rev() {
                     let stage_id = stage.id();
@@ -94,15 +121,24 @@ impl<'db, DB: MutableKV> StagedSync<'db, DB> {
                             stage_id.get_progress(&tx).await?.unwrap_or_default();
 
                         if stage_progress > to {
-                            info!("UNWINDING from {}", stage_progress);
+                            info!("UNWINDING from {} to {}", stage_progress, to);
 
+                            let start_time = Instant::now();
+                            let start_progress = stage_progress;
                             while stage_progress > to {
+                                let unwind_to = match unwind_batch_size {
+                                    Some(batch_size) => std::cmp::max(
+                                        to,
+                                        BlockNumber(stage_progress.saturating_sub(batch_size)),
+                                    ),
+                                    None => to,
+                                };
                                 let unwind_output = stage
                                     .unwind(
                                         &mut tx,
                                         UnwindInput {
                                             stage_progress,
-                                            unwind_to: to,
+                                            unwind_to,
                                         },
                                     )
                                     .await?;
@@ -110,9 +146,28 @@ impl<'db, DB: MutableKV> StagedSync<'db, DB> {
                                 stage_progress = unwind_output.stage_progress;
 
                                 stage_id.save_progress(&tx, stage_progress).await?;
+
+                                if unwind_batch_size.is_some() && stage_progress > to {
+                                    // Commit, so that progress survives restart.
+                                    tx.commit().await?;
+                                    tx = db.begin_mutable().await?;
+
+                                    let unwound = start_progress.saturating_sub(*stage_progress);
+                                    let elapsed = Instant::now() - start_time;
+                                    info!(
+                                        "Unwound to {} ({} blocks left, {:.0} blocks/s)",
+                                        stage_progress,
+                                        stage_progress.saturating_sub(*to),
+                                        unwound as f64 / elapsed.as_secs_f64()
+                                    );
+                                }
                             }
 
-                            info!("DONE @ {}", stage_progress);
+                            info!(
+                                "DONE @ {} in {}",
+                                stage_progress,
+                                format_duration(Instant::now() - start_time, true)
+                            );
                         } else {
                             debug!(
                                 unwind_point = *to,
@@ -136,6 +191,7 @@ impl<'db, DB: MutableKV> StagedSync<'db, DB> {
                     res?;
                 }
 
+                tx.del(tables::SyncStage, UNWIND_TARGET, None).await?;
                 tx.commit().await?;
             } else {
                 // Now that we're done with unwind, let's roll.
@@ -153,6 +209,7 @@ impl<'db, DB: MutableKV> StagedSync<'db, DB> {
 
                     let start_time = Instant::now();
                     let start_progress = stage_id.get_progress(&tx).await?;
+                    let start_kv_stats = kv_stats::snapshot();
 
                     // Re-invoke the stage until it reports `StageOutput::done`.
                     let done_progress = loop {
@@ -160,6 +217,16 @@ impl<'db, DB: MutableKV> StagedSync<'db, DB> {
 
                         let stage_id = stage.id();
 
+                        if let Some(disk_guard) = &self.disk_guard {
+                            if !disk_guard.has_room()? {
+                                // Abort uncommitted progress rather than hold the write
+                                // transaction, and the pages it pins, for as long as we wait.
+                                drop(tx);
+                                disk_guard.wait().await?;
+                                continue 'run_loop;
+                            }
+                        }
+
                         let exec_output: anyhow::Result<_> = async {
                             if restarted {
                                 debug!(
@@ -284,6 +351,20 @@ impl<'db, DB: MutableKV> StagedSync<'db, DB> {
                     };
                     timings.push((stage_id, Instant::now() - start_time));
 
+                    for (table, usage) in kv_stats::diff(&start_kv_stats, &kv_stats::snapshot()) {
+                        debug!(
+                            stage = %stage_id,
+                            table = table.as_str(),
+                            gets = usage.gets,
+                            seeks = usage.seeks,
+                            steps = usage.steps,
+                            puts = usage.puts,
+                            appends = usage.appends,
+                            deletes = usage.deletes,
+                            "Table usage"
+                        );
+                    }
+
                     previous_stage = Some((stage_id, done_progress))
                 }
                 tx.commit().await?;
@@ -12,6 +12,7 @@ pub const HEADERS: StageId = StageId("Headers");
 pub const BLOCK_HASHES: StageId = StageId("BlockHashes");
 pub const BODIES: StageId = StageId("Bodies");
 pub const SENDERS: StageId = StageId("SenderRecovery");
+pub const SNAP_SYNC: StageId = StageId("SnapSync");
 pub const TOTAL_GAS_INDEX: StageId = StageId("TotalGasIndex");
 pub const TOTAL_TX_INDEX: StageId = StageId("TotalTxIndex");
 pub const EXECUTION: StageId = StageId("Execution");
@@ -21,10 +22,23 @@ pub const ACCOUNT_HISTORY_INDEX: StageId = StageId("AccountHistoryIndex");
 pub const STORAGE_HISTORY_INDEX: StageId = StageId("StorageHistoryIndex");
 pub const LOG_INDEX: StageId = StageId("LogIndex");
 pub const CALL_TRACES: StageId = StageId("CallTraces");
+pub const HISTORY_FREEZE: StageId = StageId("HistoryFreeze");
+pub const PRUNE: StageId = StageId("Prune");
 pub const TX_LOOKUP: StageId = StageId("TxLookup");
 pub const TX_POOL: StageId = StageId("TxPool");
+pub const SNAPSHOT_PUBLICATION: StageId = StageId("SnapshotPublication");
 pub const FINISH: StageId = StageId("Finish");
 
+/// Not a stage: target of the unwind in progress, kept until all stages are unwound
+/// so that an interrupted unwind is resumed after restart.
+pub const UNWIND_TARGET: StageId = StageId("UnwindTarget");
+
+/// Not stages: first blocks whose data is kept by the prune stage, for every kind of data.
+pub const PRUNED_HISTORY: StageId = StageId("PrunedHistory");
+pub const PRUNED_RECEIPTS: StageId = StageId("PrunedReceipts");
+pub const PRUNED_TX_LOOKUP: StageId = StageId("PrunedTxLookup");
+pub const PRUNED_CALL_TRACES: StageId = StageId("PrunedCallTraces");
+
 impl AsRef<str> for StageId {
     fn as_ref(&self) -> &str {
         self.0
@@ -77,7 +77,7 @@ where
 
         while let Some((block_num, block_hash)) = walker.try_next().await? {
             if block_num > input.unwind_to {
-                if header_number_cur.seek(block_hash).await?.is_some() {
+                if header_number_cur.seek_exact(block_hash).await?.is_some() {
                     header_number_cur.delete_current().await?;
                 }
             } else {
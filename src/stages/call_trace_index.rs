@@ -1,8 +1,8 @@
 use crate::{
-    bitmapdb::{self, CHUNK_LIMIT},
+    bitmapdb,
     etl::collector::*,
     kv::{
-        tables::{self, BitmapKey, CallTraceSetEntry},
+        tables::{self, CallTraceSetEntry},
         traits::*,
     },
     models::*,
@@ -11,7 +11,6 @@ use crate::{
 };
 use anyhow::format_err;
 use async_trait::async_trait;
-use itertools::Itertools;
 use std::{
     collections::{BTreeSet, HashMap},
     sync::Arc,
@@ -103,12 +102,12 @@ where
         flush(&mut froms_collector, &mut froms);
         flush(&mut tos_collector, &mut tos);
 
-        load_call_traces(
+        bitmapdb::load(
             &mut tx.mutable_cursor(tables::CallFromIndex).await?,
             froms_collector,
         )
         .await?;
-        load_call_traces(
+        bitmapdb::load(
             &mut tx.mutable_cursor(tables::CallToIndex).await?,
             tos_collector,
         )
@@ -145,13 +144,13 @@ where
             }
         }
 
-        unwind_call_traces(
+        bitmapdb::unwind(
             &mut tx.mutable_cursor(tables::CallFromIndex).await?,
             from_addresses,
             input.unwind_to,
         )
         .await?;
-        unwind_call_traces(
+        bitmapdb::unwind(
             &mut tx.mutable_cursor(tables::CallToIndex).await?,
             to_addresses,
             input.unwind_to,
@@ -164,117 +163,6 @@ where
     }
 }
 
-async fn load_call_traces<'tx, 'tmp, C, T>(
-    cursor: &mut C,
-    mut collector: Collector<'tmp, Address, croaring::Treemap>,
-) -> anyhow::Result<()>
-where
-    C: MutableCursor<'tx, T>,
-    T: Table<Key = BitmapKey<Address>, Value = croaring::Treemap>,
-{
-    for res in collector
-        .iter()
-        .map(|res| {
-            let (address, bitmap) = res?;
-
-            let address = Address::decode(&address)?;
-            let bitmap = croaring::Treemap::decode(&bitmap)?;
-
-            Ok::<_, anyhow::Error>((address, bitmap))
-        })
-        .coalesce(|prev, current| match (prev, current) {
-            (Ok((prev_address, prev_bitmap)), Ok((current_address, current_bitmap))) => {
-                if prev_address == current_address {
-                    Ok(Ok((prev_address, prev_bitmap | current_bitmap)))
-                } else {
-                    Err((
-                        Ok((prev_address, prev_bitmap)),
-                        Ok((current_address, current_bitmap)),
-                    ))
-                }
-            }
-            err => Err(err),
-        })
-    {
-        let (address, mut total_bitmap) = res?;
-
-        if !total_bitmap.is_empty() {
-            if let Some((_, last_bitmap)) = cursor
-                .seek_exact(BitmapKey {
-                    inner: address,
-                    block_number: BlockNumber(u64::MAX),
-                })
-                .await?
-            {
-                total_bitmap |= last_bitmap;
-            }
-
-            for (block_number, bitmap) in
-                bitmapdb::Chunks::new(total_bitmap, CHUNK_LIMIT).with_keys()
-            {
-                cursor
-                    .put(
-                        BitmapKey {
-                            inner: address,
-                            block_number,
-                        },
-                        bitmap,
-                    )
-                    .await?;
-            }
-        }
-    }
-
-    Ok(())
-}
-
-async fn unwind_call_traces<'tx, C, T>(
-    cursor: &mut C,
-    addresses: BTreeSet<Address>,
-    unwind_to: BlockNumber,
-) -> anyhow::Result<()>
-where
-    C: MutableCursor<'tx, T>,
-    T: Table<Key = BitmapKey<Address>, Value = croaring::Treemap>,
-{
-    for address in addresses {
-        let mut bm = cursor
-            .seek_exact(BitmapKey {
-                inner: address,
-                block_number: BlockNumber(u64::MAX),
-            })
-            .await?
-            .map(|(_, bm)| bm);
-
-        while let Some(b) = bm {
-            cursor.delete_current().await?;
-
-            let new_bm = b
-                .iter()
-                .take_while(|&v| v <= *unwind_to)
-                .collect::<croaring::Treemap>();
-
-            if new_bm.cardinality() > 0 {
-                cursor
-                    .upsert(
-                        BitmapKey {
-                            inner: address,
-                            block_number: BlockNumber(u64::MAX),
-                        },
-                        new_bm,
-                    )
-                    .await?;
-            }
-
-            bm = cursor.prev().await?.and_then(
-                |(BitmapKey { inner, .. }, b)| if inner == address { Some(b) } else { None },
-            );
-        }
-    }
-
-    Ok(())
-}
-
 #[cfg(test)]
 mod tests {
     use std::time::Instant;
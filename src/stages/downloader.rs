@@ -10,7 +10,7 @@ use crate::{
     StageId,
 };
 use async_trait::async_trait;
-use std::sync::Arc;
+use std::{path::Path, sync::Arc};
 use tokio::sync::Mutex as AsyncMutex;
 
 /// Download of headers
@@ -25,6 +25,7 @@ pub struct HeaderDownload {
 impl HeaderDownload {
     pub fn new(
         chain_config: ChainConfig,
+        preverified_hashes_file: Option<&Path>,
         mem_limit: usize,
         batch_size: usize,
         sentry: SentryClientReactorShared,
@@ -32,7 +33,13 @@ impl HeaderDownload {
     ) -> anyhow::Result<Self> {
         let verifier = crate::downloader::header_slice_verifier::make_ethash_verifier();
 
-        let downloader = HeadersDownloader::new(chain_config, verifier, mem_limit, sentry)?;
+        let downloader = HeadersDownloader::new(
+            chain_config,
+            verifier,
+            preverified_hashes_file,
+            mem_limit,
+            sentry,
+        )?;
 
         let instance = Self {
             downloader,
@@ -3,6 +3,7 @@ use crate::{
     consensus::engine_factory,
     execution::{
         analysis_cache::AnalysisCache,
+        contract_gas::{top_gas_consumers, ContractGasTracer},
         processor::ExecutionProcessor,
         tracer::{CallTracer, CallTracerFlags},
     },
@@ -12,12 +13,19 @@ use crate::{
         traits::*,
     },
     models::*,
-    stagedsync::{format_duration, stage::*, stages::EXECUTION},
+    stagedsync::{
+        format_duration,
+        stage::*,
+        stages::{EXECUTION, SNAP_SYNC},
+    },
     upsert_storage_value, Buffer,
 };
 use anyhow::{format_err, Context};
 use async_trait::async_trait;
-use std::time::{Duration, Instant};
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
 use tracing::*;
 
 /// Execution of blocks through EVM
@@ -29,6 +37,11 @@ pub struct Execution {
     pub batch_until: Option<BlockNumber>,
     pub commit_every: Option<Duration>,
     pub prune_from: BlockNumber,
+    /// Analyzed code of contracts shared by all batches, so that hot contracts are not analyzed
+    /// again in every batch.
+    pub analysis_cache: AnalysisCache,
+    /// If set, record this many callees that used the most gas in every batch.
+    pub contract_gas_top: Option<usize>,
 }
 
 #[allow(clippy::too_many_arguments)]
@@ -43,10 +56,20 @@ async fn execute_batch_of_blocks<'db, Tx: MutableTransaction<'db>>(
     starting_block: BlockNumber,
     first_started_at: (Instant, Option<BlockNumber>),
     prune_from: BlockNumber,
+    analysis_cache: &mut AnalysisCache,
+    contract_gas_top: Option<usize>,
 ) -> anyhow::Result<BlockNumber> {
     let mut buffer = Buffer::new(tx, prune_from, None);
+    // State at snap sync pivot is only in hashed state.
+    if SNAP_SYNC
+        .get_progress(tx)
+        .await?
+        .map_or(false, |pivot| pivot.0 > 0)
+    {
+        buffer = buffer.with_hashed_state_fallback();
+    }
+    buffer.load_recent_hashes(starting_block).await?;
     let mut consensus_engine = engine_factory(chain_config.clone())?;
-    let mut analysis_cache = AnalysisCache::default();
 
     let mut block_number = starting_block;
     let mut gas_since_start = 0;
@@ -62,6 +85,7 @@ async fn execute_batch_of_blocks<'db, Tx: MutableTransaction<'db>>(
         .unwrap();
     let mut last_message = Instant::now();
     let mut printed_at_least_once = false;
+    let mut contract_gas = HashMap::<Address, u64>::new();
     loop {
         let block_hash = accessors::chain::canonical_hash::read(tx, block_number)
             .await?
@@ -78,11 +102,16 @@ async fn execute_batch_of_blocks<'db, Tx: MutableTransaction<'db>>(
 
         let block_spec = chain_config.collect_block_spec(block_number);
 
-        let mut call_tracer = CallTracer::default();
+        buffer.prefetch_access_lists(&block.transactions).await?;
+
+        let mut tracer = (
+            CallTracer::default(),
+            contract_gas_top.map(|_| ContractGasTracer::default()),
+        );
         let receipts = ExecutionProcessor::new(
             &mut buffer,
-            Some(&mut call_tracer),
-            &mut analysis_cache,
+            Some(&mut tracer),
+            analysis_cache,
             &mut *consensus_engine,
             &header,
             &block,
@@ -98,6 +127,14 @@ async fn execute_batch_of_blocks<'db, Tx: MutableTransaction<'db>>(
         })?;
 
         buffer.insert_receipts(block_number, receipts);
+        buffer.push_recent_hash(block_number, block_hash);
+
+        let (call_tracer, contract_gas_tracer) = tracer;
+        if let Some(contract_gas_tracer) = contract_gas_tracer {
+            for (&address, &gas_used) in contract_gas_tracer.gas_used() {
+                *contract_gas.entry(address).or_default() += gas_used;
+            }
+        }
 
         {
             let mut c = tx.mutable_cursor_dupsort(tables::CallTraceSet).await?;
@@ -179,6 +216,20 @@ async fn execute_batch_of_blocks<'db, Tx: MutableTransaction<'db>>(
 
     buffer.write_to_db().await?;
 
+    if let Some(n) = contract_gas_top {
+        let top = top_gas_consumers(contract_gas, n);
+        if let Some((address, gas_used)) = top.first() {
+            info!(
+                "Top gas consumer up to block {}: {:?} with {} gas",
+                block_number, address, gas_used
+            );
+        }
+        let mut cursor = tx.mutable_cursor(tables::ContractGas).await?;
+        for (address, gas_used) in top {
+            cursor.put((block_number, address), gas_used).await?;
+        }
+    }
+
     Ok(block_number)
 }
 
@@ -198,14 +249,7 @@ impl<'db, RwTx: MutableTransaction<'db>> Stage<'db, RwTx> for Execution {
     {
         let _ = tx;
 
-        let genesis_hash = tx
-            .get(tables::CanonicalHeader, BlockNumber(0))
-            .await?
-            .ok_or_else(|| format_err!("Genesis block absent"))?;
-        let chain_config = tx
-            .get(tables::Config, genesis_hash)
-            .await?
-            .ok_or_else(|| format_err!("No chain config for genesis block {:?}", genesis_hash))?;
+        let chain_config = accessors::chain::chain_spec::read(tx).await?;
 
         let prev_progress = input.stage_progress.unwrap_or_default();
         let starting_block = prev_progress + 1;
@@ -224,6 +268,8 @@ impl<'db, RwTx: MutableTransaction<'db>> Stage<'db, RwTx> for Execution {
                 starting_block,
                 input.first_started_at,
                 self.prune_from,
+                &mut self.analysis_cache,
+                self.contract_gas_top,
             )
             .await?;
 
@@ -294,24 +340,20 @@ impl<'db, RwTx: MutableTransaction<'db>> Stage<'db, RwTx> for Execution {
         }
 
         info!("Unwinding logs");
-        let mut log_cursor = tx.mutable_cursor(tables::Log).await?;
-        while let Some(((block_number, _), _)) = log_cursor.last().await? {
-            if block_number <= input.unwind_to {
-                break;
-            }
-
-            log_cursor.delete_current().await?;
-        }
+        tx.delete_range(tables::Log, (input.unwind_to + 1, TxIndex(0)), None)
+            .await?;
 
         info!("Unwinding call trace sets");
-        let mut call_trace_set_cursor = tx.mutable_cursor_dupsort(tables::CallTraceSet).await?;
-        while let Some((block_number, _)) = call_trace_set_cursor.last().await? {
-            if block_number <= input.unwind_to {
-                break;
-            }
+        tx.delete_range(tables::CallTraceSet, input.unwind_to + 1, None)
+            .await?;
 
-            call_trace_set_cursor.delete_current_duplicates().await?;
-        }
+        info!("Unwinding contract gas usage");
+        tx.delete_range(
+            tables::ContractGas,
+            (input.unwind_to + 1, Address::zero()),
+            None,
+        )
+        .await?;
 
         Ok(UnwindOutput {
             stage_progress: input.unwind_to,
@@ -0,0 +1,113 @@
+use crate::{
+    accessors::chain,
+    kv::{tables, traits::*},
+    models::*,
+    stagedsync::{stage::*, stages::*},
+    StageId,
+};
+use anyhow::format_err;
+use async_trait::async_trait;
+use tokio::sync::watch;
+
+/// Headers stage that makes the chain ending with forkchoice head canonical.
+///
+/// Headers and bodies of the chain are expected to be stored already, e.g. from Engine API
+/// payloads. Head is followed as it is updated, nothing is done until there is one.
+#[derive(Debug)]
+pub struct ForkchoiceHeaders {
+    head: watch::Receiver<Option<(BlockNumber, H256)>>,
+}
+
+impl ForkchoiceHeaders {
+    pub fn new(head: watch::Receiver<Option<(BlockNumber, H256)>>) -> Self {
+        Self { head }
+    }
+
+    /// Sync to a head that never changes.
+    pub fn fixed(head: (BlockNumber, H256)) -> Self {
+        let (_, head) = watch::channel(Some(head));
+        Self { head }
+    }
+}
+
+#[async_trait]
+impl<'db, RwTx> Stage<'db, RwTx> for ForkchoiceHeaders
+where
+    RwTx: MutableTransaction<'db>,
+{
+    fn id(&self) -> StageId {
+        HEADERS
+    }
+
+    async fn execute<'tx>(
+        &mut self,
+        tx: &'tx mut RwTx,
+        input: StageInput,
+    ) -> anyhow::Result<ExecOutput>
+    where
+        'db: 'tx,
+    {
+        let stage_progress = input.stage_progress.unwrap_or(BlockNumber(0));
+
+        let head = *self.head.borrow();
+        let (head_number, head_hash) = match head {
+            Some(head) => head,
+            None => {
+                return Ok(ExecOutput::Progress {
+                    stage_progress,
+                    done: true,
+                })
+            }
+        };
+
+        let mut branch = vec![];
+        let (mut number, mut hash) = (head_number, head_hash);
+        while tx.get(tables::CanonicalHeader, number).await? != Some(hash) {
+            let header = tx
+                .get(tables::Header, (number, hash))
+                .await?
+                .ok_or_else(|| format_err!("header {}/{:?} not found", number, hash))?;
+            branch.push((number, hash));
+            number = BlockNumber(number.0.checked_sub(1).ok_or_else(|| {
+                format_err!("forkchoice head {:?} is not on our chain", head_hash)
+            })?);
+            hash = header.parent_hash;
+        }
+
+        if number < stage_progress {
+            return Ok(ExecOutput::Unwind { unwind_to: number });
+        }
+
+        for (number, hash) in branch.into_iter().rev() {
+            chain::canonical_hash::write(tx, number, hash).await?;
+        }
+
+        Ok(ExecOutput::Progress {
+            stage_progress: head_number,
+            done: true,
+        })
+    }
+
+    async fn unwind<'tx>(
+        &mut self,
+        tx: &'tx mut RwTx,
+        input: UnwindInput,
+    ) -> anyhow::Result<UnwindOutput>
+    where
+        'db: 'tx,
+    {
+        // Headers themselves are kept since they may become canonical again on a later forkchoice.
+        let mut canonical_cur = tx.mutable_cursor(tables::CanonicalHeader).await?;
+        while let Some((block_num, _)) = canonical_cur.last().await? {
+            if block_num <= input.unwind_to {
+                break;
+            }
+
+            canonical_cur.delete_current().await?;
+        }
+
+        Ok(UnwindOutput {
+            stage_progress: input.unwind_to,
+        })
+    }
+}
@@ -1,5 +1,5 @@
 use crate::{
-    crypto::keccak256,
+    crypto::{keccak256, keccak256_batch},
     etl::collector::*,
     kv::{tables, traits::*},
     models::*,
@@ -9,12 +9,16 @@ use crate::{
 };
 use anyhow::format_err;
 use async_trait::async_trait;
+use bytes::Bytes;
 use std::sync::Arc;
 use tempfile::TempDir;
 use tokio::pin;
 use tokio_stream::StreamExt;
 use tracing::*;
 
+/// Entries hashed at a time by clean promotion.
+const HASH_BATCH_SIZE: usize = 4096;
+
 pub async fn promote_clean_accounts<'db, Tx>(txn: &Tx, temp_dir: &TempDir) -> anyhow::Result<()>
 where
     Tx: MutableTransaction<'db>,
@@ -29,12 +33,31 @@ where
     let mut i = 0;
     let walker = walk(&mut src, None);
     pin!(walker);
-    while let Some((address, account)) = walker.try_next().await? {
-        collector_account.push(keccak256(address), account);
+    let mut batch = Vec::with_capacity(HASH_BATCH_SIZE);
+    loop {
+        let entry = walker.try_next().await?;
+        let done = entry.is_none();
+        batch.extend(entry);
+
+        if batch.len() == HASH_BATCH_SIZE || done {
+            let hashes = keccak256_batch(
+                &batch
+                    .iter()
+                    .map(|(address, _)| *address)
+                    .collect::<Vec<Address>>(),
+            );
+            for (hashed_address, (_, account)) in hashes.into_iter().zip(batch.drain(..)) {
+                collector_account.push(hashed_address, account);
+
+                i += 1;
+                if i % 5_000_000 == 0 {
+                    debug!("Converted {} entries", i);
+                }
+            }
+        }
 
-        i += 1;
-        if i % 5_000_000 == 0 {
-            debug!("Converted {} entries", i);
+        if done {
+            break;
         }
     }
 
@@ -59,12 +82,44 @@ where
     let mut i = 0;
     let walker = walk(&mut src, None);
     pin!(walker);
-    while let Some((address, (location, value))) = walker.try_next().await? {
-        collector_storage.push(keccak256(address), (keccak256(location), value));
+    let mut batch = Vec::with_capacity(HASH_BATCH_SIZE);
+    // Storage is sorted by address, so every address only needs to be hashed once.
+    let mut address_hash: Option<(Address, H256)> = None;
+    loop {
+        let entry = walker.try_next().await?;
+        let done = entry.is_none();
+        batch.extend(entry);
+
+        if batch.len() == HASH_BATCH_SIZE || done {
+            let hashes = keccak256_batch(
+                &batch
+                    .iter()
+                    .map(|(_, (location, _))| *location)
+                    .collect::<Vec<H256>>(),
+            );
+            for (hashed_location, (address, (_, value))) in hashes.into_iter().zip(batch.drain(..))
+            {
+                let hashed_address = match address_hash {
+                    Some((last_address, hashed_address)) if last_address == address => {
+                        hashed_address
+                    }
+                    _ => {
+                        let hashed_address = keccak256(address);
+                        address_hash = Some((address, hashed_address));
+                        hashed_address
+                    }
+                };
+                collector_storage.push(hashed_address, (hashed_location, value));
+
+                i += 1;
+                if i % 5_000_000 == 0 {
+                    debug!("Converted {} entries", i);
+                }
+            }
+        }
 
-        i += 1;
-        if i % 5_000_000 == 0 {
-            debug!("Converted {} entries", i);
+        if done {
+            break;
         }
     }
 
@@ -75,7 +130,136 @@ where
     Ok(())
 }
 
-async fn promote_accounts<'db, Tx>(tx: &Tx, stage_progress: BlockNumber) -> anyhow::Result<()>
+/// Store preimages of all hashed addresses and storage locations.
+pub async fn promote_clean_preimages<'db, Tx>(txn: &Tx, temp_dir: &TempDir) -> anyhow::Result<()>
+where
+    Tx: MutableTransaction<'db>,
+{
+    let mut collector = TableCollector::<tables::Preimage>::new(temp_dir, OPTIMAL_BUFFER_CAPACITY);
+
+    let mut src = txn.cursor(tables::Account).await?;
+    let walker = walk(&mut src, None);
+    pin!(walker);
+    while let Some((address, _)) = walker.try_next().await? {
+        collector.push(
+            keccak256(address),
+            Bytes::copy_from_slice(address.as_bytes()),
+        );
+    }
+
+    let mut src = txn.cursor(tables::Storage).await?;
+    let walker = walk(&mut src, None);
+    pin!(walker);
+    while let Some((_, (location, _))) = walker.try_next().await? {
+        collector.push(
+            keccak256(location),
+            Bytes::copy_from_slice(location.as_bytes()),
+        );
+    }
+
+    debug!("Loading preimages");
+    let mut dst = txn.mutable_cursor(tables::Preimage.erased()).await?;
+    collector.load(&mut dst).await?;
+
+    Ok(())
+}
+
+/// Store preimages of addresses and storage locations changed after `stage_progress`.
+///
+/// Preimages are never removed, they stay valid whatever the chain unwinds to.
+/// Restore hashed accounts and storage to their state after block `unwind_to`,
+/// from changes of later blocks. Repeating it is harmless.
+pub async fn unwind_hashed_state<'db, Tx>(tx: &Tx, unwind_to: BlockNumber) -> anyhow::Result<()>
+where
+    Tx: MutableTransaction<'db>,
+{
+    info!("Unwinding hashed accounts");
+    let mut hashed_account_cur = tx.mutable_cursor(tables::HashedAccount).await?;
+    let mut account_cs_cur = tx.cursor(tables::AccountChangeSet).await?;
+    let walker = walk_back(&mut account_cs_cur, None);
+    pin!(walker);
+    while let Some((block_number, tables::AccountChange { address, account })) =
+        walker.try_next().await?
+    {
+        if block_number > unwind_to {
+            let hashed_address = keccak256(address);
+
+            if let Some(account) = account {
+                hashed_account_cur.put(hashed_address, account).await?
+            } else if hashed_account_cur.seek(hashed_address).await?.is_some() {
+                hashed_account_cur.delete_current().await?
+            }
+        } else {
+            break;
+        }
+    }
+
+    info!("Unwinding hashed storage");
+    let mut hashed_storage_cur = tx.mutable_cursor_dupsort(tables::HashedStorage).await?;
+    let mut storage_cs_cur = tx.cursor(tables::StorageChangeSet).await?;
+    let walker = walk_back(&mut storage_cs_cur, None);
+    pin!(walker);
+    while let Some((
+        tables::StorageChangeKey {
+            block_number,
+            address,
+        },
+        tables::StorageChange { location, value },
+    )) = walker.try_next().await?
+    {
+        if block_number > unwind_to {
+            let hashed_address = keccak256(address);
+            let hashed_location = keccak256(location);
+            upsert_hashed_storage_value(
+                &mut hashed_storage_cur,
+                hashed_address,
+                hashed_location,
+                value,
+            )
+            .await?;
+        } else {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+async fn promote_preimages<'db, Tx>(tx: &Tx, stage_progress: BlockNumber) -> anyhow::Result<()>
+where
+    Tx: MutableTransaction<'db>,
+{
+    let mut target_table = tx.mutable_cursor(tables::Preimage).await?;
+    let starting_block = stage_progress + 1;
+
+    let mut changeset_table = tx.cursor(tables::AccountChangeSet).await?;
+    let walker = walk(&mut changeset_table, Some(starting_block));
+    pin!(walker);
+    while let Some((_, tables::AccountChange { address, .. })) = walker.try_next().await? {
+        target_table
+            .upsert(
+                keccak256(address),
+                Bytes::copy_from_slice(address.as_bytes()),
+            )
+            .await?;
+    }
+
+    let mut changeset_table = tx.cursor(tables::StorageChangeSet).await?;
+    let walker = walk(&mut changeset_table, Some(starting_block));
+    pin!(walker);
+    while let Some((_, tables::StorageChange { location, .. })) = walker.try_next().await? {
+        target_table
+            .upsert(
+                keccak256(location),
+                Bytes::copy_from_slice(location.as_bytes()),
+            )
+            .await?;
+    }
+
+    Ok(())
+}
+
+pub async fn promote_accounts<'db, Tx>(tx: &Tx, stage_progress: BlockNumber) -> anyhow::Result<()>
 where
     Tx: MutableTransaction<'db>,
 {
@@ -100,7 +284,7 @@ where
     Ok(())
 }
 
-async fn promote_storage<'db, Tx>(tx: &Tx, stage_progress: BlockNumber) -> anyhow::Result<()>
+pub async fn promote_storage<'db, Tx>(tx: &Tx, stage_progress: BlockNumber) -> anyhow::Result<()>
 where
     Tx: MutableTransaction<'db>,
 {
@@ -138,6 +322,7 @@ where
 pub struct HashState {
     temp_dir: Arc<TempDir>,
     clean_promotion_threshold: u64,
+    preimages: bool,
 }
 
 impl HashState {
@@ -146,8 +331,15 @@ impl HashState {
             temp_dir,
             clean_promotion_threshold: clean_promotion_threshold
                 .unwrap_or(30_000_000_u64 * 1_000_000_u64),
+            preimages: false,
         }
     }
+
+    /// Also store preimages of hashed addresses and storage locations.
+    pub fn with_preimages(mut self, preimages: bool) -> Self {
+        self.preimages = preimages;
+        self
+    }
 }
 
 #[async_trait]
@@ -187,11 +379,19 @@ where
             promote_clean_accounts(tx, &*self.temp_dir).await?;
             info!("Generating hashed storage");
             promote_clean_storage(tx, &*self.temp_dir).await?;
+            if self.preimages {
+                info!("Generating preimages");
+                promote_clean_preimages(tx, &*self.temp_dir).await?;
+            }
         } else {
             info!("Incrementally hashing accounts");
             promote_accounts(tx, past_progress).await?;
             info!("Incrementally hashing storage");
             promote_storage(tx, past_progress).await?;
+            if self.preimages {
+                info!("Incrementally storing preimages");
+                promote_preimages(tx, past_progress).await?;
+            }
         }
 
         Ok(ExecOutput::Progress {
@@ -208,54 +408,7 @@ where
     where
         'db: 'tx,
     {
-        info!("Unwinding hashed accounts");
-        let mut hashed_account_cur = tx.mutable_cursor(tables::HashedAccount).await?;
-        let mut account_cs_cur = tx.cursor(tables::AccountChangeSet).await?;
-        let walker = walk_back(&mut account_cs_cur, None);
-        pin!(walker);
-        while let Some((block_number, tables::AccountChange { address, account })) =
-            walker.try_next().await?
-        {
-            if block_number > input.unwind_to {
-                let hashed_address = keccak256(address);
-
-                if let Some(account) = account {
-                    hashed_account_cur.put(hashed_address, account).await?
-                } else if hashed_account_cur.seek(hashed_address).await?.is_some() {
-                    hashed_account_cur.delete_current().await?
-                }
-            } else {
-                break;
-            }
-        }
-
-        info!("Unwinding hashed storage");
-        let mut hashed_storage_cur = tx.mutable_cursor_dupsort(tables::HashedStorage).await?;
-        let mut storage_cs_cur = tx.cursor(tables::StorageChangeSet).await?;
-        let walker = walk_back(&mut storage_cs_cur, None);
-        pin!(walker);
-        while let Some((
-            tables::StorageChangeKey {
-                block_number,
-                address,
-            },
-            tables::StorageChange { location, value },
-        )) = walker.try_next().await?
-        {
-            if block_number > input.unwind_to {
-                let hashed_address = keccak256(address);
-                let hashed_location = keccak256(location);
-                upsert_hashed_storage_value(
-                    &mut hashed_storage_cur,
-                    hashed_address,
-                    hashed_location,
-                    value,
-                )
-                .await?;
-            } else {
-                break;
-            }
-        }
+        unwind_hashed_state(tx, input.unwind_to).await?;
 
         Ok(UnwindOutput {
             stage_progress: input.unwind_to,
@@ -270,7 +423,7 @@ mod tests {
         execution::{address::*, *},
         kv::new_mem_database,
         res::chainspec::MAINNET,
-        u256_to_h256, Buffer, State,
+        u256_to_h256, Buffer, StateWriter,
     };
     use hex_literal::*;
     use std::time::Instant;
@@ -324,6 +477,7 @@ mod tests {
                 deployment_code.into_iter().chain(contract_code).collect(),
             )],
             ommers: vec![],
+            withdrawals: None,
         };
 
         let mut buffer = Buffer::new(&tx, BlockNumber(0), None);
@@ -406,6 +560,7 @@ mod tests {
             HashState {
                 temp_dir: Arc::new(TempDir::new().unwrap()),
                 clean_promotion_threshold: u64::MAX,
+                preimages: true,
             }
             .execute(
                 &mut tx,
@@ -456,5 +611,22 @@ mod tests {
         }
 
         assert!(walker.try_next().await.unwrap().is_none());
+
+        // ---------------------------------------
+        // Check preimages
+        // ---------------------------------------
+
+        for preimage in [
+            Bytes::copy_from_slice(sender.as_bytes()),
+            Bytes::copy_from_slice(contract_address.as_bytes()),
+            Bytes::copy_from_slice(&u256_to_h256(1.as_u256()).0),
+        ] {
+            assert_eq!(
+                tx.get(tables::Preimage, keccak256(&preimage))
+                    .await
+                    .unwrap(),
+                Some(preimage)
+            );
+        }
     }
 }
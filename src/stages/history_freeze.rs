@@ -0,0 +1,93 @@
+use crate::{
+    kv::traits::*,
+    stagedsync::{stage::*, stages::*},
+    state::history_files::{self, HistoryFiles},
+    StageId,
+};
+use anyhow::{bail, format_err};
+use async_trait::async_trait;
+use std::{path::PathBuf, sync::Arc};
+use tempfile::TempDir;
+use tracing::*;
+
+/// Moves history of every step of blocks that are at least `freeze_depth` blocks deep from
+/// changesets into history files in `dir`, and deletes those changesets.
+///
+/// Blocks with frozen history cannot be unwound, which is what freeze depth is for.
+#[derive(Debug)]
+pub struct HistoryFreeze {
+    pub dir: PathBuf,
+    pub temp_dir: Arc<TempDir>,
+    pub freeze_depth: u64,
+}
+
+#[async_trait]
+impl<'db, RwTx> Stage<'db, RwTx> for HistoryFreeze
+where
+    RwTx: MutableTransaction<'db>,
+{
+    fn id(&self) -> StageId {
+        HISTORY_FREEZE
+    }
+
+    async fn execute<'tx>(
+        &mut self,
+        tx: &'tx mut RwTx,
+        input: StageInput,
+    ) -> anyhow::Result<ExecOutput>
+    where
+        'db: 'tx,
+    {
+        let max_block = input
+            .previous_stage
+            .map(|(_, v)| v)
+            .ok_or_else(|| format_err!("Cannot be the first stage"))?;
+
+        std::fs::create_dir_all(&self.dir)?;
+        let mut steps = HistoryFiles::open(&self.dir)?.steps();
+
+        let frozen = max_block.0.saturating_sub(self.freeze_depth);
+        while history_files::step_blocks(steps).end().0 <= frozen {
+            info!(
+                "Writing history files of blocks {:?}",
+                history_files::step_blocks(steps)
+            );
+            history_files::write_step(tx, &self.temp_dir, &self.dir, steps).await?;
+            steps += 1;
+        }
+
+        // Files may have been written by a run whose transaction was not committed.
+        if let Some(step) = steps.checked_sub(1) {
+            history_files::delete_frozen_changesets(tx, *history_files::step_blocks(step).end())
+                .await?;
+        }
+
+        Ok(ExecOutput::Progress {
+            stage_progress: max_block,
+            done: true,
+        })
+    }
+
+    async fn unwind<'tx>(
+        &mut self,
+        _tx: &'tx mut RwTx,
+        input: UnwindInput,
+    ) -> anyhow::Result<UnwindOutput>
+    where
+        'db: 'tx,
+    {
+        if let Some(last_block) = HistoryFiles::open(&self.dir)?.last_block() {
+            if input.unwind_to < last_block {
+                bail!(
+                    "Cannot unwind to block {}: history up to block {} is frozen",
+                    input.unwind_to,
+                    last_block
+                );
+            }
+        }
+
+        Ok(UnwindOutput {
+            stage_progress: input.unwind_to,
+        })
+    }
+}
@@ -1,16 +1,16 @@
 use crate::{
     accessors,
-    kv::{tables, traits::MutableTransaction},
+    kv::traits::{MutableTransaction, Transaction},
     models::*,
     stagedsync::{
         stage::{ExecOutput, Stage, StageInput, UnwindInput, UnwindOutput},
         stages::*,
     },
-    stages::stage_util::should_do_clean_promotion,
+    stages::{stage_util::should_do_clean_promotion, unwind_hashed_state, StateRootDiffDump},
     trie::{increment_intermediate_hashes, regenerate_intermediate_hashes},
     StageId,
 };
-use anyhow::{format_err, Context};
+use anyhow::{bail, format_err, Context};
 use async_trait::async_trait;
 use std::{cmp, sync::Arc};
 use tempfile::TempDir;
@@ -21,6 +21,8 @@ use tracing::*;
 pub struct Interhashes {
     temp_dir: Arc<TempDir>,
     clean_promotion_threshold: u64,
+    diff_dump: Option<StateRootDiffDump>,
+    verification_interval: Option<u64>,
 }
 
 impl Interhashes {
@@ -28,8 +30,53 @@ impl Interhashes {
         Self {
             temp_dir,
             clean_promotion_threshold: clean_promotion_threshold.unwrap_or(1_000_000_000_000),
+            diff_dump: None,
+            verification_interval: None,
         }
     }
+
+    /// On state root mismatch, dump changed accounts and slots before failing.
+    pub fn with_diff_dump(mut self, diff_dump: StateRootDiffDump) -> Self {
+        self.diff_dump = Some(diff_dump);
+        self
+    }
+
+    /// Until caught up with headers, only update the trie and check the state root once a
+    /// multiple of `interval` blocks is passed. Blocks in between are verified together at that
+    /// point, so a bad block is found late, and the diff dump covers the whole range.
+    ///
+    /// Unsafe: stages after this one only advance at such points, and trust the state until then.
+    pub fn with_deferred_verification(mut self, interval: u64) -> Self {
+        self.verification_interval = Some(interval.max(1));
+        self
+    }
+
+    async fn defer_verification<'db, Tx>(
+        &self,
+        tx: &Tx,
+        past_progress: BlockNumber,
+        max_block: BlockNumber,
+    ) -> anyhow::Result<bool>
+    where
+        Tx: Transaction<'db>,
+    {
+        let interval = if let Some(interval) = self.verification_interval {
+            interval
+        } else {
+            return Ok(false);
+        };
+
+        if max_block.0 / interval > past_progress.0 / interval {
+            return Ok(false);
+        }
+
+        // Verify every block once at the tip.
+        Ok(HEADERS
+            .get_progress(tx)
+            .await?
+            .map(|headers| max_block < headers)
+            .unwrap_or(false))
+    }
 }
 
 #[async_trait]
@@ -57,17 +104,31 @@ where
         let past_progress = input.stage_progress.unwrap_or(genesis);
 
         if max_block > past_progress {
-            let block_state_root = accessors::chain::header::read(
-                tx,
-                accessors::chain::canonical_hash::read(tx, max_block)
-                    .await?
-                    .ok_or_else(|| format_err!("No canonical hash for block {}", max_block))?,
-                max_block,
-            )
-            .await?
-            .ok_or_else(|| format_err!("No header for block {}", max_block))?
-            .state_root;
+            if self
+                .defer_verification(tx, past_progress, max_block)
+                .await?
+            {
+                debug!(
+                    "Deferring state root verification of blocks {}..={}",
+                    past_progress + 1,
+                    max_block
+                );
 
+                return Ok(ExecOutput::Progress {
+                    stage_progress: past_progress,
+                    done: true,
+                });
+            }
+
+            let block_state_root = read_state_root(tx, max_block).await?;
+
+            // Compare here instead of in the trie so that the mismatching state can be dumped first.
+            // Trie updates are not committed either way, since the stage fails.
+            let expected_root = if self.diff_dump.is_some() {
+                None
+            } else {
+                Some(block_state_root)
+            };
             let trie_root = if should_do_clean_promotion(
                 tx,
                 genesis,
@@ -78,7 +139,7 @@ where
             .await?
             {
                 debug!("Regenerating intermediate hashes");
-                regenerate_intermediate_hashes(tx, self.temp_dir.as_ref(), Some(block_state_root))
+                regenerate_intermediate_hashes(tx, self.temp_dir.as_ref(), expected_root)
                     .await
                     .with_context(|| "Failed to generate interhashes")?
             } else {
@@ -87,12 +148,37 @@ where
                     tx,
                     self.temp_dir.as_ref(),
                     past_progress,
-                    Some(block_state_root),
+                    expected_root,
                 )
                 .await
                 .with_context(|| "Failed to update interhashes")?
             };
 
+            if trie_root != block_state_root {
+                if let Some(diff_dump) = &self.diff_dump {
+                    match diff_dump
+                        .dump(
+                            tx,
+                            past_progress + 1,
+                            max_block,
+                            block_state_root,
+                            trie_root,
+                        )
+                        .await
+                    {
+                        Ok(path) => error!("State root diff dumped to {}", path.display()),
+                        Err(e) => error!("Failed to dump state root diff: {}", e),
+                    }
+                }
+
+                bail!(
+                    "Wrong state root for block {}: expected {}, got {}",
+                    max_block,
+                    block_state_root,
+                    trie_root
+                );
+            }
+
             info!("Block #{} state root OK: {:?}", max_block, trie_root)
         };
 
@@ -110,13 +196,57 @@ where
     where
         'db: 'tx,
     {
-        let _ = input;
-        // TODO: proper unwind
-        tx.clear_table(tables::TrieAccount).await?;
-        tx.clear_table(tables::TrieStorage).await?;
+        // Stages are unwound in reverse order, so hashed state is still at `stage_progress`.
+        // Unwind it here first, HashState repeating it afterwards does no harm.
+        unwind_hashed_state(tx, input.unwind_to).await?;
+
+        let block_state_root = read_state_root(tx, input.unwind_to).await?;
+        let trie_root = if should_do_clean_promotion(
+            tx,
+            BlockNumber(0),
+            input.unwind_to,
+            input.stage_progress,
+            self.clean_promotion_threshold,
+        )
+        .await?
+        {
+            debug!("Regenerating intermediate hashes");
+            regenerate_intermediate_hashes(tx, self.temp_dir.as_ref(), Some(block_state_root))
+                .await
+                .with_context(|| "Failed to generate interhashes")?
+        } else {
+            // Nodes on paths changed after `unwind_to` are recomputed from the unwound hashed state.
+            debug!("Unwinding intermediate hashes");
+            increment_intermediate_hashes(
+                tx,
+                self.temp_dir.as_ref(),
+                input.unwind_to,
+                Some(block_state_root),
+            )
+            .await
+            .with_context(|| "Failed to unwind interhashes")?
+        };
+
+        info!("Block #{} state root OK: {:?}", input.unwind_to, trie_root);
 
         Ok(UnwindOutput {
-            stage_progress: BlockNumber(0),
+            stage_progress: input.unwind_to,
         })
     }
 }
+
+async fn read_state_root<'db, Tx>(tx: &Tx, block_number: BlockNumber) -> anyhow::Result<H256>
+where
+    Tx: Transaction<'db>,
+{
+    Ok(accessors::chain::header::read(
+        tx,
+        accessors::chain::canonical_hash::read(tx, block_number)
+            .await?
+            .ok_or_else(|| format_err!("No canonical hash for block {}", block_number))?,
+        block_number,
+    )
+    .await?
+    .ok_or_else(|| format_err!("No header for block {}", block_number))?
+    .state_root)
+}
@@ -0,0 +1,274 @@
+use crate::{
+    bitmapdb,
+    etl::collector::*,
+    kv::{tables, traits::*},
+    models::*,
+    stagedsync::{stage::*, stages::*},
+    StageId,
+};
+use anyhow::format_err;
+use async_trait::async_trait;
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::Arc,
+};
+use tempfile::TempDir;
+use tokio::pin;
+use tokio_stream::StreamExt;
+
+/// Generate indices of blocks with logs of every address and topic
+#[derive(Debug)]
+pub struct LogIndex {
+    pub temp_dir: Arc<TempDir>,
+    pub flush_interval: u64,
+}
+
+#[async_trait]
+impl<'db, RwTx> Stage<'db, RwTx> for LogIndex
+where
+    RwTx: MutableTransaction<'db>,
+{
+    fn id(&self) -> StageId {
+        LOG_INDEX
+    }
+
+    async fn execute<'tx>(
+        &mut self,
+        tx: &'tx mut RwTx,
+        input: StageInput,
+    ) -> anyhow::Result<ExecOutput>
+    where
+        'db: 'tx,
+    {
+        let starting_block = input.stage_progress.unwrap_or(BlockNumber(0));
+        let max_block = input
+            .previous_stage
+            .ok_or_else(|| format_err!("Log index generation cannot be the first stage"))?
+            .1;
+
+        let mut log_cursor = tx.cursor(tables::Log).await?;
+        let walker = walk(&mut log_cursor, Some((starting_block + 1, TxIndex(0))));
+        pin!(walker);
+
+        let mut addresses = HashMap::<Address, croaring::Treemap>::new();
+        let mut topics = HashMap::<H256, croaring::Treemap>::new();
+
+        let mut addresses_collector =
+            Collector::<Address, croaring::Treemap>::new(&*self.temp_dir, OPTIMAL_BUFFER_CAPACITY);
+        let mut topics_collector =
+            Collector::<H256, croaring::Treemap>::new(&*self.temp_dir, OPTIMAL_BUFFER_CAPACITY);
+
+        fn flush<K>(
+            collector: &mut Collector<K, croaring::Treemap>,
+            src: &mut HashMap<K, croaring::Treemap>,
+        ) where
+            K: TableEncode,
+            <K as TableEncode>::Encoded: Ord,
+            Vec<u8>: From<<K as TableEncode>::Encoded>,
+        {
+            for (key, index) in src.drain() {
+                collector.push(key, index);
+            }
+        }
+
+        let mut highest_block = starting_block;
+        let mut last_flush = starting_block;
+        while let Some(((block_number, _), logs)) = walker.try_next().await? {
+            if block_number > max_block {
+                break;
+            }
+
+            for log in logs {
+                addresses
+                    .entry(log.address)
+                    .or_default()
+                    .add(block_number.0);
+                for topic in log.topics {
+                    topics.entry(topic).or_default().add(block_number.0);
+                }
+            }
+
+            if highest_block != block_number {
+                highest_block = block_number;
+
+                if highest_block.0 - last_flush.0 >= self.flush_interval {
+                    flush(&mut addresses_collector, &mut addresses);
+                    flush(&mut topics_collector, &mut topics);
+
+                    last_flush = highest_block;
+                }
+            }
+        }
+
+        flush(&mut addresses_collector, &mut addresses);
+        flush(&mut topics_collector, &mut topics);
+
+        bitmapdb::load(
+            &mut tx.mutable_cursor(tables::LogAddressIndex).await?,
+            addresses_collector,
+        )
+        .await?;
+        bitmapdb::load(
+            &mut tx.mutable_cursor(tables::LogTopicIndex).await?,
+            topics_collector,
+        )
+        .await?;
+
+        Ok(ExecOutput::Progress {
+            stage_progress: max_block,
+            done: true,
+        })
+    }
+
+    async fn unwind<'tx>(
+        &mut self,
+        tx: &'tx mut RwTx,
+        input: UnwindInput,
+    ) -> anyhow::Result<UnwindOutput>
+    where
+        'db: 'tx,
+    {
+        let mut addresses = BTreeSet::<Address>::new();
+        let mut topics = BTreeSet::<H256>::new();
+
+        let mut log_cursor = tx.cursor(tables::Log).await?;
+        let walker = walk(&mut log_cursor, Some((input.unwind_to + 1, TxIndex(0))));
+        pin!(walker);
+        while let Some((_, logs)) = walker.try_next().await? {
+            for log in logs {
+                addresses.insert(log.address);
+                topics.extend(log.topics);
+            }
+        }
+
+        bitmapdb::unwind(
+            &mut tx.mutable_cursor(tables::LogAddressIndex).await?,
+            addresses,
+            input.unwind_to,
+        )
+        .await?;
+        bitmapdb::unwind(
+            &mut tx.mutable_cursor(tables::LogTopicIndex).await?,
+            topics,
+            input.unwind_to,
+        )
+        .await?;
+
+        Ok(UnwindOutput {
+            stage_progress: input.unwind_to,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use std::time::Instant;
+
+    #[tokio::test]
+    async fn log_index() {
+        let db = crate::kv::new_mem_database().unwrap();
+
+        let mut tx = db.begin_mutable().await.unwrap();
+
+        for i in 1..=30 {
+            tx.set(
+                tables::Log,
+                (BlockNumber(i), TxIndex(0)),
+                vec![Log {
+                    address: Address::repeat_byte(u8::try_from(i % 5).unwrap()),
+                    topics: vec![H256::repeat_byte(u8::try_from(i % 3).unwrap())],
+                    data: Bytes::new(),
+                }],
+            )
+            .await
+            .unwrap();
+        }
+
+        async fn blocks<'db, Tx: MutableTransaction<'db>>(
+            tx: &Tx,
+            address: Address,
+            topic: H256,
+        ) -> (Vec<u64>, Vec<u64>) {
+            let range = BlockNumber(0)..=BlockNumber(30);
+            (
+                bitmapdb::get(tx, tables::LogAddressIndex, address, range.clone())
+                    .await
+                    .unwrap()
+                    .iter()
+                    .collect(),
+                bitmapdb::get(tx, tables::LogTopicIndex, topic, range)
+                    .await
+                    .unwrap()
+                    .iter()
+                    .collect(),
+            )
+        }
+
+        let stage = || LogIndex {
+            temp_dir: Arc::new(TempDir::new().unwrap()),
+            flush_interval: 0,
+        };
+        let address = Address::repeat_byte(1);
+        let topic = H256::repeat_byte(2);
+
+        assert_eq!(
+            stage()
+                .execute(
+                    &mut tx,
+                    StageInput {
+                        restarted: false,
+                        first_started_at: (Instant::now(), Some(BlockNumber(0))),
+                        previous_stage: Some((EXECUTION, BlockNumber(20))),
+                        stage_progress: None,
+                    },
+                )
+                .await
+                .unwrap(),
+            ExecOutput::Progress {
+                stage_progress: BlockNumber(20),
+                done: true,
+            }
+        );
+        assert_eq!(
+            blocks(&tx, address, topic).await,
+            (vec![1, 6, 11, 16], vec![2, 5, 8, 11, 14, 17, 20])
+        );
+
+        stage()
+            .unwind(
+                &mut tx,
+                UnwindInput {
+                    stage_progress: BlockNumber(20),
+                    unwind_to: BlockNumber(10),
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            blocks(&tx, address, topic).await,
+            (vec![1, 6], vec![2, 5, 8])
+        );
+
+        stage()
+            .execute(
+                &mut tx,
+                StageInput {
+                    restarted: false,
+                    first_started_at: (Instant::now(), Some(BlockNumber(10))),
+                    previous_stage: Some((EXECUTION, BlockNumber(30))),
+                    stage_progress: Some(BlockNumber(10)),
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            blocks(&tx, address, topic).await,
+            (
+                vec![1, 6, 11, 16, 21, 26],
+                vec![2, 5, 8, 11, 14, 17, 20, 23, 26, 29]
+            )
+        );
+    }
+}
@@ -2,10 +2,16 @@ mod block_hashes;
 mod call_trace_index;
 mod downloader;
 mod execution;
+mod forkchoice;
 mod hashstate;
+mod history_freeze;
 mod interhashes;
+mod log_index;
+mod prune;
 mod sender_recovery;
+mod snap_sync;
 mod stage_util;
+mod state_root_diff;
 mod total_gas_index;
 mod total_tx_index;
 mod tx_lookup;
@@ -14,8 +20,17 @@ pub use block_hashes::BlockHashes;
 pub use call_trace_index::CallTraceIndex;
 pub use downloader::HeaderDownload;
 pub use execution::Execution;
-pub use hashstate::{promote_clean_accounts, promote_clean_storage, HashState};
+pub use forkchoice::ForkchoiceHeaders;
+pub use hashstate::{
+    promote_accounts, promote_clean_accounts, promote_clean_preimages, promote_clean_storage,
+    promote_storage, unwind_hashed_state, HashState,
+};
+pub use history_freeze::HistoryFreeze;
 pub use interhashes::Interhashes;
+pub use log_index::LogIndex;
+pub use prune::{Prune, PruneMode, DEFAULT_PRUNE_DISTANCE};
 pub use sender_recovery::SenderRecovery;
+pub use snap_sync::SnapSync;
+pub use state_root_diff::{StateRootDiff, StateRootDiffDump};
 pub use total_gas_index::TotalGasIndex;
 pub use total_tx_index::TotalTxIndex;
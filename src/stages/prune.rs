@@ -0,0 +1,301 @@
+use crate::{
+    accessors::chain,
+    kv::{tables, traits::*},
+    models::*,
+    stagedsync::{stage::*, stages::*},
+    StageId,
+};
+use anyhow::{bail, format_err};
+use async_trait::async_trait;
+use std::str::FromStr;
+use tracing::*;
+
+/// Blocks below the head that are kept by default.
+pub const DEFAULT_PRUNE_DISTANCE: u64 = 90_000;
+
+/// Data that is pruned, with the number of blocks below the head to keep for each.
+///
+/// Parsed from letters as in Erigon's `--prune`: `h` for history (changesets), `r` for receipts
+/// (logs), `t` for transaction lookups and `c` for call traces, each kept for
+/// [`DEFAULT_PRUNE_DISTANCE`] blocks.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PruneMode {
+    pub history: Option<u64>,
+    pub receipts: Option<u64>,
+    pub tx_lookup: Option<u64>,
+    pub call_traces: Option<u64>,
+}
+
+impl FromStr for PruneMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut mode = Self::default();
+        for letter in s.chars() {
+            let distance = match letter {
+                'h' => &mut mode.history,
+                'r' => &mut mode.receipts,
+                't' => &mut mode.tx_lookup,
+                'c' => &mut mode.call_traces,
+                other => bail!("unknown prune mode {:?}, expected any of \"hrtc\"", other),
+            };
+            *distance = Some(DEFAULT_PRUNE_DISTANCE);
+        }
+
+        Ok(mode)
+    }
+}
+
+impl PruneMode {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Blocks `from..to` that have fallen out of retention of `distance` blocks below `head` since
+/// the last run, as recorded under `id`.
+async fn blocks_to_prune<'db, Tx: Transaction<'db>>(
+    tx: &Tx,
+    id: StageId,
+    head: BlockNumber,
+    distance: Option<u64>,
+) -> anyhow::Result<Option<(BlockNumber, BlockNumber)>> {
+    let to = match distance {
+        Some(distance) => BlockNumber(head.0.saturating_sub(distance)),
+        None => return Ok(None),
+    };
+    let from = id.get_progress(tx).await?.unwrap_or(BlockNumber(0));
+
+    Ok(if from < to { Some((from, to)) } else { None })
+}
+
+/// Deletes data of blocks that are further below the head than allowed by [`PruneMode`].
+///
+/// Pruning is incremental: blocks pruned in earlier runs are remembered, so that every run only
+/// goes through blocks that have fallen out of retention since.
+#[derive(Debug)]
+pub struct Prune {
+    pub mode: PruneMode,
+}
+
+impl Prune {
+    async fn prune_tx_lookup<'db, RwTx: MutableTransaction<'db>>(
+        tx: &RwTx,
+        from: BlockNumber,
+        to: BlockNumber,
+    ) -> anyhow::Result<()> {
+        for block_number in from..to {
+            let hash = match chain::canonical_hash::read(tx, block_number).await? {
+                Some(hash) => hash,
+                None => continue,
+            };
+            let body = match chain::block_body::read_without_senders(tx, hash, block_number).await?
+            {
+                Some(body) => body,
+                None => continue,
+            };
+            for txn in &body.transactions {
+                let hash = txn.hash();
+                tx.del(tables::BlockTransactionLookup, hash, None).await?;
+                tx.del(tables::BlockTransactionOffset, hash, None).await?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<'db, RwTx> Stage<'db, RwTx> for Prune
+where
+    RwTx: MutableTransaction<'db>,
+{
+    fn id(&self) -> StageId {
+        PRUNE
+    }
+
+    async fn execute<'tx>(
+        &mut self,
+        tx: &'tx mut RwTx,
+        input: StageInput,
+    ) -> anyhow::Result<ExecOutput>
+    where
+        'db: 'tx,
+    {
+        let head = input
+            .previous_stage
+            .map(|(_, v)| v)
+            .ok_or_else(|| format_err!("Cannot be the first stage"))?;
+
+        if let Some((from, to)) =
+            blocks_to_prune(tx, PRUNED_HISTORY, head, self.mode.history).await?
+        {
+            debug!("Pruning history of blocks {}..{}", from, to);
+            tx.delete_range(tables::AccountChangeSet, from, Some(to))
+                .await?;
+            tx.delete_range(tables::StorageChangeSet, from, Some(to))
+                .await?;
+            PRUNED_HISTORY.save_progress(tx, to).await?;
+        }
+
+        if let Some((from, to)) =
+            blocks_to_prune(tx, PRUNED_RECEIPTS, head, self.mode.receipts).await?
+        {
+            debug!("Pruning receipts of blocks {}..{}", from, to);
+            tx.delete_range(tables::Log, (from, TxIndex(0)), Some((to, TxIndex(0))))
+                .await?;
+            PRUNED_RECEIPTS.save_progress(tx, to).await?;
+        }
+
+        if let Some((from, to)) =
+            blocks_to_prune(tx, PRUNED_TX_LOOKUP, head, self.mode.tx_lookup).await?
+        {
+            debug!("Pruning transaction lookups of blocks {}..{}", from, to);
+            Self::prune_tx_lookup(tx, from, to).await?;
+            PRUNED_TX_LOOKUP.save_progress(tx, to).await?;
+        }
+
+        if let Some((from, to)) =
+            blocks_to_prune(tx, PRUNED_CALL_TRACES, head, self.mode.call_traces).await?
+        {
+            debug!("Pruning call traces of blocks {}..{}", from, to);
+            tx.delete_range(tables::CallTraceSet, from, Some(to))
+                .await?;
+            PRUNED_CALL_TRACES.save_progress(tx, to).await?;
+        }
+
+        Ok(ExecOutput::Progress {
+            stage_progress: head,
+            done: true,
+        })
+    }
+
+    async fn unwind<'tx>(
+        &mut self,
+        tx: &'tx mut RwTx,
+        input: UnwindInput,
+    ) -> anyhow::Result<UnwindOutput>
+    where
+        'db: 'tx,
+    {
+        if let Some(pruned) = PRUNED_HISTORY.get_progress(tx).await? {
+            if input.unwind_to < pruned {
+                bail!(
+                    "Cannot unwind to block {}: history before block {} is pruned",
+                    input.unwind_to,
+                    pruned
+                );
+            }
+        }
+
+        Ok(UnwindOutput {
+            stage_progress: input.unwind_to,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kv::new_mem_database;
+    use std::time::Instant;
+
+    #[test]
+    fn parse_mode() {
+        assert_eq!("".parse::<PruneMode>().unwrap(), PruneMode::default());
+        assert_eq!(
+            "hc".parse::<PruneMode>().unwrap(),
+            PruneMode {
+                history: Some(DEFAULT_PRUNE_DISTANCE),
+                call_traces: Some(DEFAULT_PRUNE_DISTANCE),
+                ..Default::default()
+            }
+        );
+        assert!("hx".parse::<PruneMode>().is_err());
+    }
+
+    fn input(head: BlockNumber, stage_progress: Option<BlockNumber>) -> StageInput {
+        StageInput {
+            restarted: false,
+            first_started_at: (Instant::now(), None),
+            previous_stage: Some((CALL_TRACES, head)),
+            stage_progress,
+        }
+    }
+
+    async fn first_change<'db, Tx: Transaction<'db>>(tx: &Tx) -> Option<BlockNumber> {
+        tx.cursor(tables::AccountChangeSet)
+            .await
+            .unwrap()
+            .first()
+            .await
+            .unwrap()
+            .map(|(block_number, _)| block_number)
+    }
+
+    #[tokio::test]
+    async fn prunes_incrementally() {
+        let db = new_mem_database().unwrap();
+        let mut tx = db.begin_mutable().await.unwrap();
+
+        for block_number in 1..=20 {
+            tx.set(
+                tables::AccountChangeSet,
+                BlockNumber(block_number),
+                tables::AccountChange {
+                    address: Address::from_low_u64_be(block_number),
+                    account: None,
+                },
+            )
+            .await
+            .unwrap();
+            tx.set(tables::Log, (BlockNumber(block_number), TxIndex(0)), vec![])
+                .await
+                .unwrap();
+        }
+
+        let mut stage = Prune {
+            mode: PruneMode {
+                history: Some(10),
+                receipts: Some(15),
+                ..Default::default()
+            },
+        };
+        stage
+            .execute(&mut tx, input(BlockNumber(15), None))
+            .await
+            .unwrap();
+        assert_eq!(first_change(&tx).await, Some(BlockNumber(5)));
+        assert_eq!(
+            tx.cursor(tables::Log)
+                .await
+                .unwrap()
+                .first()
+                .await
+                .unwrap()
+                .map(|((block_number, _), _)| block_number),
+            Some(BlockNumber(1))
+        );
+
+        stage
+            .execute(&mut tx, input(BlockNumber(20), Some(BlockNumber(15))))
+            .await
+            .unwrap();
+        assert_eq!(first_change(&tx).await, Some(BlockNumber(10)));
+        assert_eq!(
+            PRUNED_RECEIPTS.get_progress(&tx).await.unwrap(),
+            Some(BlockNumber(5))
+        );
+
+        assert!(stage
+            .unwind(
+                &mut tx,
+                UnwindInput {
+                    stage_progress: BlockNumber(20),
+                    unwind_to: BlockNumber(9),
+                },
+            )
+            .await
+            .is_err());
+    }
+}
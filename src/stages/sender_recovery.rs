@@ -176,15 +176,8 @@ where
     where
         'db: 'tx,
     {
-        let mut senders_cur = tx.mutable_cursor(tables::TxSender).await?;
-
-        while let Some(((block_number, _), _)) = senders_cur.last().await? {
-            if block_number > input.unwind_to {
-                senders_cur.delete_current().await?;
-            } else {
-                break;
-            }
-        }
+        tx.delete_range(tables::TxSender, (input.unwind_to + 1, H256::zero()), None)
+            .await?;
 
         Ok(UnwindOutput {
             stage_progress: input.unwind_to,
@@ -216,6 +209,7 @@ mod tests {
             base_tx_id: 1.into(),
             tx_amount: 2,
             uncles: vec![],
+            withdrawals: None,
         };
 
         let tx1_1 = MessageWithSignature {
@@ -266,6 +260,7 @@ mod tests {
             base_tx_id: 3.into(),
             tx_amount: 3,
             uncles: vec![],
+            withdrawals: None,
         };
 
         let tx2_1 = MessageWithSignature {
@@ -338,6 +333,7 @@ mod tests {
             base_tx_id: 6.into(),
             tx_amount: 0,
             uncles: vec![],
+            withdrawals: None,
         };
 
         let hash1 = H256::random();
@@ -0,0 +1,806 @@
+use crate::{
+    accessors::chain,
+    crypto::keccak256,
+    downloader::snap_progress::{AccountRange, HealTask, SnapSyncProgress},
+    h256_to_u256,
+    kv::{tables, traits::*},
+    models::*,
+    sentry::{sentry_client::PeerId, snap::*},
+    stagedsync::{stage::*, stages::*},
+    state::database::upsert_hashed_storage_value,
+    trie::{
+        proof::{node_references, verify_range_proof, NodeReference},
+        regenerate_intermediate_hashes, stored_node_hash,
+    },
+    u256_to_h256, StageId,
+};
+use anyhow::{bail, format_err};
+use async_trait::async_trait;
+use std::{collections::HashSet, sync::Arc};
+use tempfile::TempDir;
+use tracing::*;
+
+/// How far behind the head the state is downloaded, so that peers still serve it.
+const PIVOT_DISTANCE: u64 = 64;
+const ACCOUNT_RANGES: usize = 16;
+const RESPONSE_BYTES: u64 = 512 * 1024;
+const MAX_CODES_PER_REQUEST: usize = 64;
+const MAX_NODES_PER_REQUEST: usize = 256;
+/// Requests sent for the same data before giving up.
+const MAX_ATTEMPTS: usize = 8;
+const MAX_HEAL_ROUNDS: usize = 8;
+
+/// Stages after which state at the pivot block is in place once snap sync is complete.
+const STATE_STAGES: &[StageId] = &[
+    EXECUTION,
+    HASH_STATE,
+    INTERMEDIATE_HASHES,
+    ACCOUNT_HISTORY_INDEX,
+    STORAGE_HISTORY_INDEX,
+    LOG_INDEX,
+    CALL_TRACES,
+];
+
+/// Download of state at a recent pivot block over snap protocol, instead of executing all blocks
+/// before it.
+///
+/// Account and storage ranges are verified against the pivot state root with range proofs and
+/// written to hashed state only. State changes while ranges are downloaded, or the pivot moves,
+/// so the trie is then healed: nodes that differ from the local trie are requested by hash until
+/// the state root matches. Execution then continues from the pivot, reading hashed state for
+/// accounts and slots that have no plain state entries.
+///
+/// Runs only once: if blocks have already been executed, the stage does nothing.
+#[derive(Debug)]
+pub struct SnapSync<C> {
+    client: C,
+    temp_dir: Arc<TempDir>,
+    account_ranges: usize,
+    next_request_id: u64,
+}
+
+impl<C> SnapSync<C>
+where
+    C: SnapClient + Sync,
+{
+    pub fn new(client: C, temp_dir: Arc<TempDir>) -> Self {
+        Self {
+            client,
+            temp_dir,
+            account_ranges: ACCOUNT_RANGES,
+            next_request_id: 0,
+        }
+    }
+
+    async fn request(
+        &mut self,
+        request: impl FnOnce(u64) -> SnapMessage,
+    ) -> anyhow::Result<Response> {
+        self.next_request_id += 1;
+        let request = request(self.next_request_id);
+        let id = request.snap_id();
+        let (peer_id, response) = self.client.request(request).await?;
+        if response.request_id() != self.next_request_id || response.snap_id() != response_id(id) {
+            debug!(
+                "Peer {:?} sent unexpected response {:?}",
+                peer_id,
+                response.snap_id()
+            );
+            self.client.penalize_peer(peer_id).await?;
+            return Ok(Response::Invalid);
+        }
+
+        Ok(Response::Valid(peer_id, response))
+    }
+
+    async fn download_range<'db, RwTx>(
+        &mut self,
+        tx: &RwTx,
+        progress: &mut SnapSyncProgress,
+        range: AccountRange,
+    ) -> anyhow::Result<()>
+    where
+        RwTx: MutableTransaction<'db>,
+    {
+        let root = progress.state_root;
+        for _ in 0..MAX_ATTEMPTS {
+            let (peer_id, response) = match self
+                .request(|request_id| {
+                    SnapMessage::GetAccountRange(GetAccountRangeMessage {
+                        request_id,
+                        root_hash: root,
+                        starting_hash: range.next,
+                        limit_hash: range.limit,
+                        response_bytes: RESPONSE_BYTES,
+                    })
+                })
+                .await?
+            {
+                Response::Valid(peer_id, SnapMessage::AccountRange(response)) => {
+                    (peer_id, response)
+                }
+                _ => continue,
+            };
+
+            let keys = response.accounts.iter().map(|a| a.hash).collect::<Vec<_>>();
+            let values = response
+                .accounts
+                .iter()
+                .map(|a| rlp::encode(&a.account.to_rlp()))
+                .collect::<Vec<_>>();
+            let more = match verify_range_proof(root, range.next, &keys, &values, &response.proof) {
+                Ok(more) => more,
+                Err(e) => {
+                    debug!("Invalid account range from {:?}: {}", peer_id, e);
+                    self.client.penalize_peer(peer_id).await?;
+                    continue;
+                }
+            };
+
+            let mut codes = vec![];
+            for AccountData { hash, account } in &response.accounts {
+                tx.set(tables::HashedAccount, *hash, account.account())
+                    .await?;
+                if account.storage_root != EMPTY_ROOT {
+                    self.download_storage(tx, root, *hash, account.storage_root)
+                        .await?;
+                }
+                codes.push(account.code_hash);
+            }
+            self.download_codes(tx, codes).await?;
+
+            progress.account_range_done(range.next, keys.last().copied(), more);
+            return Ok(());
+        }
+
+        bail!("No valid response for accounts from {:?}", range.next)
+    }
+
+    async fn download_storage<'db, RwTx>(
+        &mut self,
+        tx: &RwTx,
+        root: H256,
+        account: H256,
+        storage_root: H256,
+    ) -> anyhow::Result<()>
+    where
+        RwTx: MutableTransaction<'db>,
+    {
+        let mut origin = H256::zero();
+        let mut attempts = 0;
+        loop {
+            let (peer_id, response) = match self
+                .request(|request_id| {
+                    SnapMessage::GetStorageRanges(GetStorageRangesMessage {
+                        request_id,
+                        root_hash: root,
+                        account_hashes: vec![account],
+                        starting_hash: origin,
+                        limit_hash: H256::repeat_byte(0xff),
+                        response_bytes: RESPONSE_BYTES,
+                    })
+                })
+                .await?
+            {
+                Response::Valid(peer_id, SnapMessage::StorageRanges(response)) => {
+                    (peer_id, response)
+                }
+                _ => {
+                    attempts += 1;
+                    if attempts == MAX_ATTEMPTS {
+                        bail!("No valid response for storage of {:?}", account);
+                    }
+                    continue;
+                }
+            };
+
+            let slots = response.slots.into_iter().next().unwrap_or_default();
+            let keys = slots.iter().map(|s| s.hash).collect::<Vec<_>>();
+            let values = slots
+                .iter()
+                .map(|s| rlp::encode(&s.value))
+                .collect::<Vec<_>>();
+            let more =
+                match verify_range_proof(storage_root, origin, &keys, &values, &response.proof) {
+                    Ok(more) => more,
+                    Err(e) => {
+                        debug!("Invalid storage range from {:?}: {}", peer_id, e);
+                        self.client.penalize_peer(peer_id).await?;
+                        attempts += 1;
+                        if attempts == MAX_ATTEMPTS {
+                            bail!("No valid response for storage of {:?}", account);
+                        }
+                        continue;
+                    }
+                };
+
+            let mut cursor = tx.mutable_cursor_dupsort(tables::HashedStorage).await?;
+            for slot in &slots {
+                upsert_hashed_storage_value(&mut cursor, account, slot.hash, slot.value).await?;
+            }
+
+            match keys.last() {
+                Some(&last) if more => origin = u256_to_h256(h256_to_u256(last) + U256::ONE),
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    /// Download code with `hashes` that is not stored yet.
+    async fn download_codes<'db, RwTx>(
+        &mut self,
+        tx: &RwTx,
+        hashes: Vec<H256>,
+    ) -> anyhow::Result<()>
+    where
+        RwTx: MutableTransaction<'db>,
+    {
+        let mut missing = vec![];
+        for hash in hashes.into_iter().collect::<HashSet<_>>() {
+            if hash != EMPTY_HASH && tx.get(tables::Code, hash).await?.is_none() {
+                missing.push(hash);
+            }
+        }
+
+        for chunk in missing.chunks(MAX_CODES_PER_REQUEST) {
+            let mut chunk = chunk.iter().copied().collect::<HashSet<_>>();
+            for _ in 0..MAX_ATTEMPTS {
+                if chunk.is_empty() {
+                    break;
+                }
+
+                let hashes = chunk.iter().copied().collect::<Vec<_>>();
+                let (peer_id, response) = match self
+                    .request(|request_id| {
+                        SnapMessage::GetByteCodes(GetByteCodesMessage {
+                            request_id,
+                            hashes,
+                            response_bytes: RESPONSE_BYTES,
+                        })
+                    })
+                    .await?
+                {
+                    Response::Valid(peer_id, SnapMessage::ByteCodes(response)) => {
+                        (peer_id, response)
+                    }
+                    _ => continue,
+                };
+
+                let before = chunk.len();
+                for code in response.codes {
+                    let hash = keccak256(&code);
+                    if chunk.remove(&hash) {
+                        tx.set(tables::Code, hash, code).await?;
+                    }
+                }
+                if chunk.len() == before {
+                    self.client.penalize_peer(peer_id).await?;
+                }
+            }
+
+            if !chunk.is_empty() {
+                bail!("No valid response for {} codes", chunk.len());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Request queued trie nodes until the local trie has all nodes of the state trie.
+    async fn heal<'db, RwTx>(
+        &mut self,
+        tx: &RwTx,
+        progress: &mut SnapSyncProgress,
+    ) -> anyhow::Result<()>
+    where
+        RwTx: MutableTransaction<'db>,
+    {
+        let root = progress.state_root;
+        let mut attempts = 0;
+        while !progress.heal_queue.is_empty() {
+            let tasks = progress.next_missing(MAX_NODES_PER_REQUEST).to_vec();
+            let paths = tasks
+                .iter()
+                .map(|task| TrieNodePaths {
+                    account: task.account,
+                    paths: vec![task.path.clone()],
+                })
+                .collect();
+            let (peer_id, response) = match self
+                .request(|request_id| {
+                    SnapMessage::GetTrieNodes(GetTrieNodesMessage {
+                        request_id,
+                        root_hash: root,
+                        paths,
+                        response_bytes: RESPONSE_BYTES,
+                    })
+                })
+                .await?
+            {
+                Response::Valid(peer_id, SnapMessage::TrieNodes(response)) => (peer_id, response),
+                _ => {
+                    attempts += 1;
+                    if attempts == MAX_ATTEMPTS {
+                        bail!("No valid response for trie nodes");
+                    }
+                    continue;
+                }
+            };
+
+            // Nodes are served in order of requested paths, possibly truncated.
+            let mut healed = vec![];
+            let mut missing = vec![];
+            let mut codes = vec![];
+            for (task, node) in tasks.iter().zip(&response.nodes) {
+                if keccak256(node) != task.hash {
+                    break;
+                }
+
+                for reference in node_references(&task.path, node)? {
+                    self.apply_reference(tx, task.account, reference, &mut missing, &mut codes)
+                        .await?;
+                }
+                healed.push(task.hash);
+            }
+
+            if healed.is_empty() {
+                debug!("Peer {:?} sent no requested trie nodes", peer_id);
+                self.client.penalize_peer(peer_id).await?;
+                attempts += 1;
+                if attempts == MAX_ATTEMPTS {
+                    bail!("No valid response for trie nodes");
+                }
+                continue;
+            }
+            attempts = 0;
+
+            self.download_codes(tx, codes).await?;
+            progress.nodes_healed(&healed, missing);
+            progress.save(tx).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn apply_reference<'db, RwTx>(
+        &mut self,
+        tx: &RwTx,
+        account: Option<H256>,
+        reference: NodeReference,
+        missing: &mut Vec<HealTask>,
+        codes: &mut Vec<H256>,
+    ) -> anyhow::Result<()>
+    where
+        RwTx: MutableTransaction<'db>,
+    {
+        match reference {
+            NodeReference::Hash(path, hash) => {
+                if stored_node_hash(tx, account, &path).await? != Some(hash) {
+                    missing.push(HealTask {
+                        account,
+                        path,
+                        hash,
+                    });
+                }
+            }
+            NodeReference::Leaf(key, value) => match account {
+                None => {
+                    let account = rlp::decode::<RlpAccount>(&value)?;
+                    tx.set(
+                        tables::HashedAccount,
+                        key,
+                        Account {
+                            nonce: account.nonce,
+                            balance: account.balance,
+                            code_hash: account.code_hash,
+                        },
+                    )
+                    .await?;
+                    codes.push(account.code_hash);
+
+                    if account.storage_root == EMPTY_ROOT {
+                        delete_storage(tx, key, H256::zero(), H256::repeat_byte(0xff)).await?;
+                    } else if stored_node_hash(tx, Some(key), &[]).await?
+                        != Some(account.storage_root)
+                    {
+                        missing.push(HealTask {
+                            account: Some(key),
+                            path: vec![],
+                            hash: account.storage_root,
+                        });
+                    }
+                }
+                Some(address) => {
+                    let value = rlp::decode::<U256>(&value)?;
+                    let mut cursor = tx.mutable_cursor_dupsort(tables::HashedStorage).await?;
+                    upsert_hashed_storage_value(&mut cursor, address, key, value).await?;
+                }
+            },
+            NodeReference::Absent(lo, hi) => match account {
+                None => {
+                    let mut cursor = tx.mutable_cursor(tables::HashedAccount).await?;
+                    let mut deleted = vec![];
+                    let mut entry = cursor.seek(lo).await?;
+                    while let Some((key, _)) = entry {
+                        if key > hi {
+                            break;
+                        }
+                        deleted.push(key);
+                        entry = cursor.next().await?;
+                    }
+
+                    for key in deleted {
+                        tx.del(tables::HashedAccount, key, None).await?;
+                        delete_storage(tx, key, H256::zero(), H256::repeat_byte(0xff)).await?;
+                    }
+                }
+                Some(address) => delete_storage(tx, address, lo, hi).await?,
+            },
+        }
+
+        Ok(())
+    }
+}
+
+enum Response {
+    Valid(PeerId, SnapMessage),
+    Invalid,
+}
+
+fn response_id(request: SnapMessageId) -> SnapMessageId {
+    match request {
+        SnapMessageId::GetAccountRange => SnapMessageId::AccountRange,
+        SnapMessageId::GetStorageRanges => SnapMessageId::StorageRanges,
+        SnapMessageId::GetByteCodes => SnapMessageId::ByteCodes,
+        SnapMessageId::GetTrieNodes => SnapMessageId::TrieNodes,
+        other => other,
+    }
+}
+
+/// Delete slots `lo..=hi` of `account` from hashed state.
+async fn delete_storage<'db, RwTx>(
+    tx: &RwTx,
+    account: H256,
+    lo: H256,
+    hi: H256,
+) -> anyhow::Result<()>
+where
+    RwTx: MutableTransaction<'db>,
+{
+    let mut cursor = tx.mutable_cursor_dupsort(tables::HashedStorage).await?;
+    while let Some((location, _)) = cursor.seek_both_range(account, lo).await? {
+        if location > hi {
+            break;
+        }
+        cursor.delete_current().await?;
+    }
+
+    Ok(())
+}
+
+#[async_trait]
+impl<'db, RwTx, C> Stage<'db, RwTx> for SnapSync<C>
+where
+    RwTx: MutableTransaction<'db>,
+    C: SnapClient + Sync,
+{
+    fn id(&self) -> StageId {
+        SNAP_SYNC
+    }
+
+    async fn execute<'tx>(
+        &mut self,
+        tx: &'tx mut RwTx,
+        input: StageInput,
+    ) -> anyhow::Result<ExecOutput>
+    where
+        'db: 'tx,
+    {
+        if let Some(stage_progress) = input.stage_progress {
+            return Ok(ExecOutput::Progress {
+                stage_progress,
+                done: true,
+            });
+        }
+
+        let head = input
+            .previous_stage
+            .map(|(_, progress)| progress)
+            .ok_or_else(|| format_err!("Cannot be first stage"))?;
+        let executed = EXECUTION.get_progress(tx).await?.unwrap_or(BlockNumber(0));
+        if executed.0 > 0 || head.0 <= PIVOT_DISTANCE {
+            return Ok(ExecOutput::Progress {
+                stage_progress: BlockNumber(0),
+                done: true,
+            });
+        }
+
+        let pivot = BlockNumber(head.0 - PIVOT_DISTANCE);
+        let hash = chain::canonical_hash::read(tx, pivot)
+            .await?
+            .ok_or_else(|| format_err!("No canonical hash for block {}", pivot))?;
+        let state_root = chain::header::read(tx, hash, pivot)
+            .await?
+            .ok_or_else(|| format_err!("No header for block {}", pivot))?
+            .state_root;
+
+        let mut progress =
+            SnapSyncProgress::resume(tx, pivot, state_root, self.account_ranges).await?;
+        info!(
+            "Snap syncing state at block {}, {} account ranges left",
+            pivot,
+            progress.pending_ranges.len()
+        );
+
+        while let Some(&range) = progress.pending_ranges.first() {
+            self.download_range(tx, &mut progress, range).await?;
+            progress.save(tx).await?;
+        }
+
+        let mut root = regenerate_intermediate_hashes(tx, &self.temp_dir, None).await?;
+        let mut rounds = 0;
+        while root != state_root {
+            if rounds == MAX_HEAL_ROUNDS {
+                bail!(
+                    "State root mismatch after healing: expected {:?}, got {:?}",
+                    state_root,
+                    root
+                );
+            }
+            rounds += 1;
+
+            info!("Healing state trie, round {}", rounds);
+            progress.start_healing();
+            self.heal(tx, &mut progress).await?;
+            root = regenerate_intermediate_hashes(tx, &self.temp_dir, None).await?;
+        }
+
+        SnapSyncProgress::clear(tx).await?;
+        for stage in STATE_STAGES {
+            stage.save_progress(tx, pivot).await?;
+        }
+        info!("Snap sync complete at block {}", pivot);
+
+        Ok(ExecOutput::Progress {
+            stage_progress: pivot,
+            done: true,
+        })
+    }
+
+    async fn unwind<'tx>(
+        &mut self,
+        _: &'tx mut RwTx,
+        input: UnwindInput,
+    ) -> anyhow::Result<UnwindOutput>
+    where
+        'db: 'tx,
+    {
+        if input.unwind_to < input.stage_progress {
+            bail!(
+                "Cannot unwind to block {}: state before snap sync pivot {} is not available",
+                input.unwind_to,
+                input.stage_progress
+            );
+        }
+
+        Ok(UnwindOutput {
+            stage_progress: input.stage_progress,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{kv::new_mem_database, trie::proof::nodes_by_path};
+    use bytes::Bytes;
+    use std::{collections::HashMap, time::Instant};
+
+    /// Peers serving whole ranges with empty proofs and nodes of a fixed state.
+    #[derive(Debug)]
+    struct MockPeers {
+        accounts: Vec<AccountData>,
+        storage: HashMap<H256, Vec<StorageData>>,
+        codes: HashMap<H256, Bytes>,
+        account_nodes: HashMap<Vec<u8>, Vec<u8>>,
+        storage_nodes: HashMap<H256, HashMap<Vec<u8>, Vec<u8>>>,
+    }
+
+    #[async_trait]
+    impl SnapClient for MockPeers {
+        async fn request(&mut self, request: SnapMessage) -> anyhow::Result<(PeerId, SnapMessage)> {
+            let response = match request {
+                SnapMessage::GetAccountRange(m) => SnapMessage::AccountRange(AccountRangeMessage {
+                    request_id: m.request_id,
+                    accounts: self
+                        .accounts
+                        .iter()
+                        .filter(|a| a.hash >= m.starting_hash)
+                        .cloned()
+                        .collect(),
+                    proof: vec![],
+                }),
+                SnapMessage::GetStorageRanges(m) => {
+                    SnapMessage::StorageRanges(StorageRangesMessage {
+                        request_id: m.request_id,
+                        slots: m
+                            .account_hashes
+                            .iter()
+                            .map(|hash| self.storage.get(hash).cloned().unwrap_or_default())
+                            .collect(),
+                        proof: vec![],
+                    })
+                }
+                SnapMessage::GetByteCodes(m) => SnapMessage::ByteCodes(ByteCodesMessage {
+                    request_id: m.request_id,
+                    codes: m
+                        .hashes
+                        .iter()
+                        .filter_map(|hash| self.codes.get(hash).cloned())
+                        .collect(),
+                }),
+                SnapMessage::GetTrieNodes(m) => SnapMessage::TrieNodes(TrieNodesMessage {
+                    request_id: m.request_id,
+                    nodes: m
+                        .paths
+                        .iter()
+                        .flat_map(|paths| {
+                            let nodes = match paths.account {
+                                None => &self.account_nodes,
+                                Some(account) => &self.storage_nodes[&account],
+                            };
+                            paths
+                                .paths
+                                .iter()
+                                .map(move |path| Bytes::from(nodes[path].clone()))
+                        })
+                        .collect(),
+                }),
+                other => bail!("unexpected request {:?}", other.snap_id()),
+            };
+
+            Ok((PeerId::zero(), response))
+        }
+
+        async fn penalize_peer(&mut self, peer_id: PeerId) -> anyhow::Result<()> {
+            bail!("penalized {:?}", peer_id)
+        }
+    }
+
+    #[tokio::test]
+    async fn snap_sync_with_healing() {
+        let db = new_mem_database().unwrap();
+        let mut tx = db.begin_mutable().await.unwrap();
+
+        let code = Bytes::from_static(&[0x60, 0x00, 0x60, 0x00, 0xf3]);
+        let code_hash = keccak256(&code);
+        let contract = keccak256(H256::from_low_u64_be(1));
+        let mut slots = (1..=3_u64)
+            .map(|i| StorageData {
+                hash: keccak256(H256::from_low_u64_be(i)),
+                value: U256::from(i * 100),
+            })
+            .collect::<Vec<_>>();
+        slots.sort_by_key(|s| s.hash);
+        let storage_entries = slots
+            .iter()
+            .map(|s| (s.hash, rlp::encode(&s.value).to_vec()))
+            .collect::<Vec<_>>();
+        let storage_nodes = nodes_by_path(&storage_entries);
+        let storage_root = keccak256(&storage_nodes[&vec![]]);
+
+        let mut accounts = (2..=6_u64)
+            .map(|i| AccountData {
+                hash: keccak256(H256::from_low_u64_be(i)),
+                account: SlimAccount {
+                    nonce: i,
+                    balance: U256::from(i as u128 * ETHER),
+                    storage_root: EMPTY_ROOT,
+                    code_hash: EMPTY_HASH,
+                },
+            })
+            .collect::<Vec<_>>();
+        accounts.push(AccountData {
+            hash: contract,
+            account: SlimAccount {
+                nonce: 1,
+                balance: U256::ZERO,
+                storage_root,
+                code_hash,
+            },
+        });
+        accounts.sort_by_key(|a| a.hash);
+        let account_entries = accounts
+            .iter()
+            .map(|a| (a.hash, rlp::encode(&a.account.to_rlp()).to_vec()))
+            .collect::<Vec<_>>();
+        let account_nodes = nodes_by_path(&account_entries);
+        let state_root = keccak256(&account_nodes[&vec![]]);
+
+        // Left over from an earlier pivot, absent in the state served by peers.
+        let stale = keccak256(H256::from_low_u64_be(100));
+        tx.set(tables::HashedAccount, stale, Account::default())
+            .await
+            .unwrap();
+        tx.set(
+            tables::HashedStorage,
+            stale,
+            (H256::repeat_byte(1), U256::ONE),
+        )
+        .await
+        .unwrap();
+
+        let head = BlockNumber(100);
+        let pivot = BlockNumber(head.0 - PIVOT_DISTANCE);
+        let header = BlockHeader {
+            number: pivot,
+            state_root,
+            ..BlockHeader::empty()
+        };
+        let hash = header.hash();
+        tx.set(tables::CanonicalHeader, pivot, hash).await.unwrap();
+        tx.set(tables::Header, (pivot, hash), header).await.unwrap();
+
+        let peers = MockPeers {
+            accounts: accounts.clone(),
+            storage: [(contract, slots.clone())].into(),
+            codes: [(code_hash, code.clone())].into(),
+            account_nodes,
+            storage_nodes: [(contract, storage_nodes)].into(),
+        };
+        let mut stage = SnapSync::new(peers, Arc::new(TempDir::new().unwrap()));
+        stage.account_ranges = 1;
+
+        let output = stage
+            .execute(
+                &mut tx,
+                StageInput {
+                    restarted: false,
+                    first_started_at: (Instant::now(), None),
+                    previous_stage: Some((SENDERS, head)),
+                    stage_progress: None,
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            output,
+            ExecOutput::Progress {
+                stage_progress: pivot,
+                done: true,
+            }
+        );
+
+        let mut cursor = tx.cursor(tables::HashedAccount).await.unwrap();
+        let mut stored = vec![];
+        let mut entry = cursor.first().await.unwrap();
+        while let Some((key, account)) = entry {
+            stored.push((key, account));
+            entry = cursor.next().await.unwrap();
+        }
+        assert_eq!(
+            stored,
+            accounts
+                .iter()
+                .map(|a| (a.hash, a.account.account()))
+                .collect::<Vec<_>>()
+        );
+
+        let mut storage = tx.cursor_dup_sort(tables::HashedStorage).await.unwrap();
+        for slot in &slots {
+            assert_eq!(
+                storage.seek_both_range(contract, slot.hash).await.unwrap(),
+                Some((slot.hash, slot.value))
+            );
+        }
+        assert_eq!(storage.seek_exact(stale).await.unwrap(), None);
+
+        assert_eq!(tx.get(tables::Code, code_hash).await.unwrap(), Some(code));
+        assert_eq!(EXECUTION.get_progress(&tx).await.unwrap(), Some(pivot));
+        assert!(tx
+            .get(tables::SnapProgress, Default::default())
+            .await
+            .unwrap()
+            .is_none());
+    }
+}
@@ -0,0 +1,396 @@
+use crate::{
+    crypto::keccak256,
+    kv::{tables, traits::*},
+    models::*,
+};
+use jsonrpsee::{core::client::ClientT, http_client::HttpClientBuilder, rpc_params};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, path::PathBuf};
+use tokio_stream::StreamExt;
+use tracing::*;
+
+/// Where to dump accounts and slots that may have caused a state root mismatch.
+#[derive(Clone, Debug)]
+pub struct StateRootDiffDump {
+    pub dir: PathBuf,
+    /// JSON-RPC endpoint of a trusted node. If set, only entries whose values differ from
+    /// the ones it returns from `eth_getProof` are dumped.
+    pub reference_node: Option<String>,
+    /// Most accounts plus slots to dump.
+    pub max_entries: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountState {
+    pub nonce: U64,
+    pub balance: U256,
+    pub code_hash: H256,
+}
+
+impl From<Option<Account>> for AccountState {
+    fn from(account: Option<Account>) -> Self {
+        let account = account.unwrap_or_default();
+        Self {
+            nonce: account.nonce.into(),
+            balance: account.balance,
+            code_hash: account.code_hash,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountDiff {
+    pub address: Address,
+    pub hashed_address: H256,
+    pub local: AccountState,
+    pub reference: Option<AccountState>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StorageDiff {
+    pub address: Address,
+    pub hashed_address: H256,
+    pub location: H256,
+    pub hashed_location: H256,
+    pub local: U256,
+    pub reference: Option<U256>,
+}
+
+/// Accounts and slots changed in the blocks whose state root did not match, ordered by hashed key
+/// as in the trie. Without a reference node these are all candidates, otherwise only divergent ones.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StateRootDiff {
+    pub block_number: BlockNumber,
+    pub expected_root: H256,
+    pub computed_root: H256,
+    pub reference_node: Option<String>,
+    pub accounts: Vec<AccountDiff>,
+    pub storage: Vec<StorageDiff>,
+    /// More entries were found than dumped.
+    pub truncated: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ProofResponse {
+    balance: U256,
+    nonce: U64,
+    code_hash: H256,
+    storage_proof: Vec<StorageProof>,
+}
+
+#[derive(Deserialize)]
+struct StorageProof {
+    value: U256,
+}
+
+struct ChangedAccount {
+    address: Address,
+    /// Hashed location => location
+    locations: BTreeMap<H256, H256>,
+}
+
+/// Accounts and slots changed in `blocks`, keyed by hashed address.
+async fn changed_state<'db, Tx: Transaction<'db>>(
+    tx: &Tx,
+    from: BlockNumber,
+    to: BlockNumber,
+) -> anyhow::Result<BTreeMap<H256, ChangedAccount>> {
+    let mut out = BTreeMap::<H256, ChangedAccount>::new();
+
+    let mut cursor = tx.cursor_dup_sort(tables::AccountChangeSet).await?;
+    let walker = walk(&mut cursor, Some(from));
+    tokio::pin!(walker);
+    while let Some((block_number, tables::AccountChange { address, .. })) =
+        walker.try_next().await?
+    {
+        if block_number > to {
+            break;
+        }
+        out.entry(keccak256(address))
+            .or_insert_with(|| ChangedAccount {
+                address,
+                locations: BTreeMap::new(),
+            });
+    }
+
+    let mut cursor = tx.cursor_dup_sort(tables::StorageChangeSet).await?;
+    let walker = walk(&mut cursor, Some(from));
+    tokio::pin!(walker);
+    while let Some((
+        tables::StorageChangeKey {
+            block_number,
+            address,
+        },
+        tables::StorageChange { location, .. },
+    )) = walker.try_next().await?
+    {
+        if block_number > to {
+            break;
+        }
+        out.entry(keccak256(address))
+            .or_insert_with(|| ChangedAccount {
+                address,
+                locations: BTreeMap::new(),
+            })
+            .locations
+            .insert(keccak256(location), location);
+    }
+
+    Ok(out)
+}
+
+impl StateRootDiffDump {
+    /// Collect state changed in blocks `from..=to` as hashed for the trie, compare it against
+    /// the reference node if configured, and write the diff to a JSON file in the dump directory.
+    pub async fn dump<'db, Tx: Transaction<'db>>(
+        &self,
+        tx: &Tx,
+        from: BlockNumber,
+        to: BlockNumber,
+        expected_root: H256,
+        computed_root: H256,
+    ) -> anyhow::Result<PathBuf> {
+        let diff = self
+            .diff(tx, from, to, expected_root, computed_root)
+            .await?;
+
+        std::fs::create_dir_all(&self.dir)?;
+        let path = self.dir.join(format!("state-root-mismatch-{}.json", to.0));
+        serde_json::to_writer_pretty(std::fs::File::create(&path)?, &diff)?;
+
+        Ok(path)
+    }
+
+    pub async fn diff<'db, Tx: Transaction<'db>>(
+        &self,
+        tx: &Tx,
+        from: BlockNumber,
+        to: BlockNumber,
+        expected_root: H256,
+        computed_root: H256,
+    ) -> anyhow::Result<StateRootDiff> {
+        let reference = self
+            .reference_node
+            .as_deref()
+            .map(|url| HttpClientBuilder::default().build(url))
+            .transpose()?;
+
+        let mut out = StateRootDiff {
+            block_number: to,
+            expected_root,
+            computed_root,
+            reference_node: self.reference_node.clone(),
+            accounts: vec![],
+            storage: vec![],
+            truncated: false,
+        };
+
+        let mut hashed_storage = tx.cursor_dup_sort(tables::HashedStorage).await?;
+        for (hashed_address, changed) in changed_state(tx, from, to).await? {
+            if out.accounts.len() + out.storage.len() >= self.max_entries {
+                out.truncated = true;
+                break;
+            }
+
+            let local = AccountState::from(tx.get(tables::HashedAccount, hashed_address).await?);
+            let mut local_storage = Vec::with_capacity(changed.locations.len());
+            for (&hashed_location, &location) in &changed.locations {
+                let value = hashed_storage
+                    .seek_both_range(hashed_address, hashed_location)
+                    .await?
+                    .filter(|&(l, _)| l == hashed_location)
+                    .map(|(_, v)| v)
+                    .unwrap_or(U256::ZERO);
+                local_storage.push((hashed_location, location, value));
+            }
+
+            let (reference_account, reference_storage) = if let Some(client) = &reference {
+                let proof = client
+                    .request::<ProofResponse>(
+                        "eth_getProof",
+                        rpc_params![
+                            changed.address,
+                            changed.locations.values().collect::<Vec<_>>(),
+                            format!("0x{:x}", to.0)
+                        ],
+                    )
+                    .await?;
+                let account = AccountState {
+                    nonce: proof.nonce,
+                    balance: proof.balance,
+                    // Some nodes return zero hash for accounts that do not exist.
+                    code_hash: if proof.code_hash.is_zero() {
+                        EMPTY_HASH
+                    } else {
+                        proof.code_hash
+                    },
+                };
+                let storage = proof
+                    .storage_proof
+                    .into_iter()
+                    .map(|proof| Some(proof.value))
+                    .collect::<Vec<_>>();
+                (Some(account), storage)
+            } else {
+                (None, vec![None; local_storage.len()])
+            };
+
+            if reference_account != Some(local) {
+                out.accounts.push(AccountDiff {
+                    address: changed.address,
+                    hashed_address,
+                    local,
+                    reference: reference_account,
+                });
+            }
+
+            for ((hashed_location, location, local), reference) in
+                local_storage.into_iter().zip(reference_storage)
+            {
+                if reference == Some(local) {
+                    continue;
+                }
+                if out.accounts.len() + out.storage.len() >= self.max_entries {
+                    out.truncated = true;
+                    break;
+                }
+                out.storage.push(StorageDiff {
+                    address: changed.address,
+                    hashed_address,
+                    location,
+                    hashed_location,
+                    local,
+                    reference,
+                });
+            }
+        }
+
+        debug!(
+            "State root diff for block {}: {} accounts, {} slots",
+            to,
+            out.accounts.len(),
+            out.storage.len()
+        );
+
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kv::new_mem_database;
+    use hex_literal::hex;
+
+    #[tokio::test]
+    async fn dumps_changed_state() {
+        let db = new_mem_database().unwrap();
+        let tx = db.begin_mutable().await.unwrap();
+
+        let a = Address::from(hex!("b000000000000000000000000000000000000001"));
+        let b = Address::from(hex!("b000000000000000000000000000000000000002"));
+        let location = H256::from_low_u64_be(1);
+
+        for (block, address) in [(1, a), (2, b), (3, a)] {
+            tx.set(
+                tables::AccountChangeSet,
+                BlockNumber(block),
+                tables::AccountChange {
+                    address,
+                    account: None,
+                },
+            )
+            .await
+            .unwrap();
+        }
+        tx.set(
+            tables::StorageChangeSet,
+            tables::StorageChangeKey {
+                block_number: BlockNumber(2),
+                address: b,
+            },
+            tables::StorageChange {
+                location,
+                value: U256::ZERO,
+            },
+        )
+        .await
+        .unwrap();
+
+        let account = Account {
+            nonce: 1,
+            balance: 10_u64.as_u256(),
+            ..Default::default()
+        };
+        tx.set(tables::HashedAccount, keccak256(b), account)
+            .await
+            .unwrap();
+        tx.set(
+            tables::HashedStorage,
+            keccak256(b),
+            (keccak256(location), 5_u64.as_u256()),
+        )
+        .await
+        .unwrap();
+
+        let dump = StateRootDiffDump {
+            dir: PathBuf::new(),
+            reference_node: None,
+            max_entries: 100,
+        };
+        let diff = dump
+            .diff(
+                &tx,
+                BlockNumber(2),
+                BlockNumber(2),
+                H256::zero(),
+                H256::zero(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(
+            diff.accounts,
+            vec![AccountDiff {
+                address: b,
+                hashed_address: keccak256(b),
+                local: Some(account).into(),
+                reference: None,
+            }]
+        );
+        assert_eq!(
+            diff.storage,
+            vec![StorageDiff {
+                address: b,
+                hashed_address: keccak256(b),
+                location,
+                hashed_location: keccak256(location),
+                local: 5_u64.as_u256(),
+                reference: None,
+            }]
+        );
+        assert!(!diff.truncated);
+
+        let dump = StateRootDiffDump {
+            max_entries: 1,
+            ..dump
+        };
+        let diff = dump
+            .diff(
+                &tx,
+                BlockNumber(1),
+                BlockNumber(3),
+                H256::zero(),
+                H256::zero(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(diff.accounts.len() + diff.storage.len(), 1);
+        assert!(diff.truncated);
+    }
+}
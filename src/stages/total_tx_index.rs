@@ -75,15 +75,8 @@ where
     where
         'db: 'tx,
     {
-        let mut cumulative_index_cur = tx.mutable_cursor(tables::TotalTx).await?;
-
-        while let Some((block_num, _)) = cumulative_index_cur.last().await? {
-            if block_num > input.unwind_to {
-                cumulative_index_cur.delete_current().await?;
-            } else {
-                break;
-            }
-        }
+        tx.delete_range(tables::TotalTx, input.unwind_to + 1, None)
+            .await?;
 
         Ok(UnwindOutput {
             stage_progress: input.unwind_to,
@@ -12,7 +12,8 @@ use tokio::pin;
 use tokio_stream::StreamExt;
 use tracing::*;
 
-/// Generation of TransactionHash => BlockNumber mapping
+/// Generation of TransactionHash => BlockNumber mapping,
+/// along with position of the transaction in its block
 #[derive(Debug)]
 pub struct TxLookup {
     temp_dir: Arc<TempDir>,
@@ -39,10 +40,14 @@ where
         let mut tx_hash_cursor = tx
             .mutable_cursor(tables::BlockTransactionLookup.erased())
             .await?;
+        let mut tx_offset_cursor = tx
+            .mutable_cursor(tables::BlockTransactionOffset.erased())
+            .await?;
 
         let mut block_txs_cursor = tx.cursor(tables::BlockTransaction).await?;
 
         let mut collector = TableCollector::new(&*self.temp_dir, OPTIMAL_BUFFER_CAPACITY);
+        let mut offset_collector = TableCollector::new(&*self.temp_dir, OPTIMAL_BUFFER_CAPACITY);
 
         let last_processed_block_number = tx
             .mutable_cursor(tables::BlockTransactionLookup)
@@ -64,12 +69,17 @@ where
                 walk(&mut block_txs_cursor, Some(tx_base_id)).take(tx_count.try_into()?);
             pin!(walker_block_txs);
 
+            let mut offset = 0_u64;
             while let Some((_, tx)) = walker_block_txs.try_next().await? {
-                collector.push(tx.hash(), tables::TruncateStart(block_number));
+                let hash = tx.hash();
+                collector.push(hash, tables::TruncateStart(block_number));
+                offset_collector.push(hash, offset);
+                offset += 1;
             }
         }
 
         collector.load(&mut tx_hash_cursor).await?;
+        offset_collector.load(&mut tx_offset_cursor).await?;
         info!("Processed");
         Ok(ExecOutput::Progress {
             stage_progress: input
@@ -90,6 +100,7 @@ where
     {
         let mut bodies_cursor = tx.mutable_cursor(tables::BlockBody).await?;
         let mut tx_hash_cursor = tx.mutable_cursor(tables::BlockTransactionLookup).await?;
+        let mut tx_offset_cursor = tx.mutable_cursor(tables::BlockTransactionOffset).await?;
         let mut block_txs_cursor = tx.cursor(tables::BlockTransaction).await?;
 
         let start_block_number = input.unwind_to + 1;
@@ -121,9 +132,13 @@ where
                     break;
                 }
 
-                if tx_hash_cursor.seek(tx_value.hash()).await?.is_some() {
+                let hash = tx_value.hash();
+                if tx_hash_cursor.seek_exact(hash).await?.is_some() {
                     tx_hash_cursor.delete_current().await?;
                 }
+                if tx_offset_cursor.seek_exact(hash).await?.is_some() {
+                    tx_offset_cursor.delete_current().await?;
+                }
                 num_txs += 1;
             }
         }
@@ -159,6 +174,7 @@ mod tests {
             base_tx_id: 1.into(),
             tx_amount: 2,
             uncles: vec![],
+            withdrawals: None,
         };
 
         let tx1_1 = MessageWithSignature {
@@ -211,6 +227,7 @@ mod tests {
             base_tx_id: 3.into(),
             tx_amount: 3,
             uncles: vec![],
+            withdrawals: None,
         };
 
         let tx2_1 = MessageWithSignature {
@@ -289,6 +306,7 @@ mod tests {
             base_tx_id: 6.into(),
             tx_amount: 0,
             uncles: vec![],
+            withdrawals: None,
         };
 
         let hash1 = H256::random();
@@ -333,17 +351,21 @@ mod tests {
             }
         );
 
-        for (hashed_tx, block_number) in [
-            (hash1_1, 1),
-            (hash1_2, 1),
-            (hash2_1, 2),
-            (hash2_2, 2),
-            (hash2_3, 2),
+        for (hashed_tx, block_number, offset) in [
+            (hash1_1, 1, 0),
+            (hash1_2, 1, 1),
+            (hash2_1, 2, 0),
+            (hash2_2, 2, 1),
+            (hash2_3, 2, 2),
         ] {
             assert_eq!(
                 dbg!(chain::tl::read(&tx, hashed_tx).await.unwrap().unwrap()),
                 block_number.into()
             );
+            assert_eq!(
+                chain::tx_offset::read(&tx, hashed_tx).await.unwrap(),
+                Some(offset)
+            );
         }
     }
 
@@ -385,6 +407,7 @@ mod tests {
             base_tx_id: 1.into(),
             tx_amount: 2,
             uncles: vec![],
+            withdrawals: None,
         };
 
         let tx1_1 = MessageWithSignature {
@@ -437,6 +460,7 @@ mod tests {
             base_tx_id: 3.into(),
             tx_amount: 3,
             uncles: vec![],
+            withdrawals: None,
         };
 
         let tx2_1 = MessageWithSignature {
@@ -515,6 +539,7 @@ mod tests {
             base_tx_id: 6.into(),
             tx_amount: 0,
             uncles: vec![],
+            withdrawals: None,
         };
 
         let hash1 = H256::random();
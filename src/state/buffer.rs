@@ -1,23 +1,29 @@
 use crate::{
-    accessors, h256_to_u256,
+    accessors,
+    crypto::keccak256,
+    h256_to_u256,
     kv::{
         tables::{self, AccountChange, StorageChange, StorageChangeKey},
         traits::*,
     },
     models::*,
-    state::database::*,
-    u256_to_h256, State,
+    state::{database::*, history_files::HistoryFiles},
+    u256_to_h256, ChainReader, StateReader, StateWriter,
 };
 use async_trait::async_trait;
 use bytes::Bytes;
 use std::{
-    collections::{BTreeMap, HashMap, HashSet},
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque},
     marker::PhantomData,
+    sync::Arc,
 };
 use tokio::pin;
 use tokio_stream::StreamExt;
 use tracing::*;
 
+/// Blocks back from the current one BLOCKHASH can look up.
+const BLOCK_HASH_WINDOW: u64 = 256;
+
 // address -> storage-encoded initial value
 pub type AccountChanges = BTreeMap<Address, Option<Account>>;
 
@@ -41,6 +47,8 @@ where
 
     prune_from: BlockNumber,
     historical_block: Option<BlockNumber>,
+    hashed_state_fallback: bool,
+    history_files: Option<Arc<HistoryFiles>>,
 
     accounts: HashMap<Address, Option<Account>>,
 
@@ -53,6 +61,13 @@ where
     hash_to_code: BTreeMap<H256, Bytes>,
     logs: BTreeMap<(BlockNumber, TxIndex), Vec<Log>>,
 
+    // Database values read ahead of execution, overlay takes precedence over them.
+    prefetched_accounts: HashMap<Address, Option<Account>>,
+    prefetched_storage: HashMap<(Address, U256), U256>,
+
+    // Canonical hashes of consecutive blocks BLOCKHASH may ask for, oldest first.
+    recent_hashes: VecDeque<(BlockNumber, H256)>,
+
     // Current block stuff
     block_number: BlockNumber,
     changed_storage: HashSet<Address>,
@@ -72,6 +87,8 @@ where
             txn,
             prune_from,
             historical_block,
+            hashed_state_fallback: false,
+            history_files: None,
             _marker: PhantomData,
             accounts: Default::default(),
             storage: Default::default(),
@@ -79,11 +96,178 @@ where
             storage_changes: Default::default(),
             hash_to_code: Default::default(),
             logs: Default::default(),
+            prefetched_accounts: Default::default(),
+            prefetched_storage: Default::default(),
+            recent_hashes: Default::default(),
             block_number: Default::default(),
             changed_storage: Default::default(),
         }
     }
 
+    /// Read-only overlay of state after block `block_number`, reconstructed from changesets and
+    /// `history_files`. Writes only go to the overlay and are dropped with it.
+    pub fn historical(
+        txn: &'tx Tx,
+        block_number: BlockNumber,
+        history_files: Option<Arc<HistoryFiles>>,
+    ) -> Self {
+        let buffer = Self::new(txn, BlockNumber(0), Some(block_number));
+        match history_files {
+            Some(history_files) => buffer.with_history_files(history_files),
+            None => buffer,
+        }
+    }
+
+    /// Read accounts and slots absent from plain state from hashed state, which is all there is
+    /// for state downloaded by snap sync. Accounts and slots deleted from plain state are deleted
+    /// from hashed state as well, so that they are not read from there again.
+    ///
+    /// Slots of accounts whose storage is erased are recorded in changesets only if they are in
+    /// plain state, since locations cannot be recovered from their hashes.
+    pub fn with_hashed_state_fallback(mut self) -> Self {
+        self.hashed_state_fallback = true;
+        self
+    }
+
+    /// Look up historical state in `history_files` before changesets, which no longer have
+    /// history moved to files.
+    pub fn with_history_files(mut self, history_files: Arc<HistoryFiles>) -> Self {
+        self.history_files = Some(history_files);
+        self
+    }
+
+    /// Load canonical hashes of blocks within BLOCKHASH reach of block `block_number`, so that
+    /// looking them up does not read the database. Hashes loaded before are dropped, as they
+    /// may be from before an unwind.
+    pub async fn load_recent_hashes(&mut self, block_number: BlockNumber) -> anyhow::Result<()> {
+        self.recent_hashes.clear();
+
+        let txn = self.txn;
+        let mut cursor = txn.cursor(tables::CanonicalHeader).await?;
+        let walker = walk(
+            &mut cursor,
+            Some(BlockNumber(
+                block_number.0.saturating_sub(BLOCK_HASH_WINDOW),
+            )),
+        );
+        pin!(walker);
+        while let Some((number, hash)) = walker.try_next().await? {
+            if number >= block_number {
+                break;
+            }
+            self.push_recent_hash(number, hash);
+        }
+
+        Ok(())
+    }
+
+    /// Remember canonical hash of an executed block, for BLOCKHASH of the blocks after it.
+    pub fn push_recent_hash(&mut self, block_number: BlockNumber, block_hash: H256) {
+        if let Some(&(last, _)) = self.recent_hashes.back() {
+            if last.0 + 1 != block_number.0 {
+                self.recent_hashes.clear();
+            }
+        }
+        self.recent_hashes.push_back((block_number, block_hash));
+        if self.recent_hashes.len() > BLOCK_HASH_WINDOW as usize {
+            self.recent_hashes.pop_front();
+        }
+    }
+
+    fn recent_hash(&self, block_number: BlockNumber) -> Option<H256> {
+        let &(first, _) = self.recent_hashes.front()?;
+        let index = block_number.0.checked_sub(first.0)?;
+        self.recent_hashes
+            .get(index as usize)
+            .map(|&(_, block_hash)| block_hash)
+    }
+
+    async fn read_db_account(&self, address: Address) -> anyhow::Result<Option<Account>> {
+        if let (Some(files), Some(block_number)) = (&self.history_files, self.historical_block) {
+            if let Some(account) = files.account(address, block_number)? {
+                return Ok(account);
+            }
+        }
+
+        let account =
+            accessors::state::account::read(self.txn, address, self.historical_block).await?;
+        if account.is_none() && self.hashed_state_fallback && self.historical_block.is_none() {
+            return self
+                .txn
+                .get(tables::HashedAccount, keccak256(address))
+                .await;
+        }
+
+        Ok(account)
+    }
+
+    async fn read_db_storage(&self, address: Address, location: U256) -> anyhow::Result<U256> {
+        if let (Some(files), Some(block_number)) = (&self.history_files, self.historical_block) {
+            if let Some(value) = files.storage(address, location, block_number)? {
+                return Ok(value);
+            }
+        }
+
+        if self.hashed_state_fallback && self.historical_block.is_none() {
+            if let Some(value) =
+                read_account_storage(self.txn, address, u256_to_h256(location)).await?
+            {
+                return Ok(value);
+            }
+
+            return Ok(seek_hashed_storage_key(
+                &mut self.txn.cursor_dup_sort(tables::HashedStorage).await?,
+                keccak256(address),
+                keccak256(u256_to_h256(location)),
+            )
+            .await?
+            .unwrap_or_default());
+        }
+
+        accessors::state::storage::read(self.txn, address, location, self.historical_block).await
+    }
+
+    /// Read accounts and storage slots declared in access lists of `transactions` in one pass
+    /// ordered by key, so that execution does not stop to read each of them on first access.
+    ///
+    /// Values prefetched for previous calls are dropped.
+    pub async fn prefetch_access_lists(
+        &mut self,
+        transactions: &[MessageWithSender],
+    ) -> anyhow::Result<()> {
+        let mut accounts = BTreeSet::new();
+        let mut slots = BTreeSet::new();
+        for txn in transactions {
+            for item in txn.access_list().iter() {
+                if !self.accounts.contains_key(&item.address) {
+                    accounts.insert(item.address);
+                }
+                let overlay = self.storage.get(&item.address);
+                for &slot in &item.slots {
+                    let location = h256_to_u256(slot);
+                    if overlay.map_or(true, |overlay| {
+                        !overlay.erased && !overlay.slots.contains_key(&location)
+                    }) {
+                        slots.insert((item.address, location));
+                    }
+                }
+            }
+        }
+
+        self.prefetched_accounts.clear();
+        self.prefetched_storage.clear();
+        for address in accounts {
+            let account = self.read_db_account(address).await?;
+            self.prefetched_accounts.insert(address, account);
+        }
+        for (address, location) in slots {
+            let value = self.read_db_storage(address, location).await?;
+            self.prefetched_storage.insert((address, location), value);
+        }
+
+        Ok(())
+    }
+
     pub fn insert_receipts(&mut self, block_number: BlockNumber, receipts: Vec<Receipt>) {
         for (i, receipt) in receipts.into_iter().enumerate() {
             self.logs
@@ -93,7 +277,7 @@ where
 }
 
 #[async_trait]
-impl<'db, 'tx, Tx> State for Buffer<'db, 'tx, Tx>
+impl<'db, 'tx, Tx> StateReader for Buffer<'db, 'tx, Tx>
 where
     'db: 'tx,
     Tx: Transaction<'db>,
@@ -103,7 +287,11 @@ where
             return Ok(*account);
         }
 
-        accessors::state::account::read(self.txn, address, self.historical_block).await
+        if let Some(account) = self.prefetched_accounts.get(&address) {
+            return Ok(*account);
+        }
+
+        self.read_db_account(address).await
     }
 
     async fn read_code(&self, code_hash: H256) -> anyhow::Result<Bytes> {
@@ -128,9 +316,63 @@ where
             }
         }
 
-        accessors::state::storage::read(self.txn, address, location, self.historical_block).await
+        if let Some(value) = self.prefetched_storage.get(&(address, location)) {
+            return Ok(*value);
+        }
+
+        self.read_db_storage(address, location).await
+    }
+
+    async fn read_header(
+        &self,
+        block_number: BlockNumber,
+        block_hash: H256,
+    ) -> anyhow::Result<Option<BlockHeader>> {
+        accessors::chain::header::read(self.txn, block_hash, block_number).await
+    }
+
+    async fn read_body(
+        &self,
+        block_number: BlockNumber,
+        block_hash: H256,
+    ) -> anyhow::Result<Option<BlockBody>> {
+        accessors::chain::block_body::read_without_senders(self.txn, block_hash, block_number).await
+    }
+
+    async fn total_difficulty(
+        &self,
+        block_number: BlockNumber,
+        block_hash: H256,
+    ) -> anyhow::Result<Option<U256>> {
+        accessors::chain::td::read(self.txn, block_hash, block_number).await
+    }
+}
+
+#[async_trait]
+impl<'db, 'tx, Tx> ChainReader for Buffer<'db, 'tx, Tx>
+where
+    'db: 'tx,
+    Tx: Transaction<'db>,
+{
+    async fn canonical_hash(&self, block_number: BlockNumber) -> anyhow::Result<Option<H256>> {
+        if let Some(block_hash) = self.recent_hash(block_number) {
+            return Ok(Some(block_hash));
+        }
+
+        accessors::chain::canonical_hash::read(self.txn, block_number).await
+    }
+
+    async fn header_number(&self, block_hash: H256) -> anyhow::Result<Option<BlockNumber>> {
+        accessors::chain::header_number::read(self.txn, block_hash).await
     }
+}
 
+#[async_trait]
+impl<'db, 'tx, Tx> StateWriter for Buffer<'db, 'tx, Tx>
+where
+    'db: 'tx,
+    Tx: Transaction<'db>,
+{
     async fn erase_storage(&mut self, address: Address) -> anyhow::Result<()> {
         let mut mark_database_as_discarded = false;
         let overlay_storage = self.storage.entry(address).or_insert_with(|| {
@@ -186,35 +428,6 @@ where
         Ok(())
     }
 
-    async fn read_header(
-        &self,
-        block_number: BlockNumber,
-        block_hash: H256,
-    ) -> anyhow::Result<Option<BlockHeader>> {
-        accessors::chain::header::read(self.txn, block_hash, block_number).await
-    }
-
-    async fn read_body(
-        &self,
-        block_number: BlockNumber,
-        block_hash: H256,
-    ) -> anyhow::Result<Option<BlockBody>> {
-        accessors::chain::block_body::read_without_senders(self.txn, block_hash, block_number).await
-    }
-
-    async fn total_difficulty(
-        &self,
-        block_number: BlockNumber,
-        block_hash: H256,
-    ) -> anyhow::Result<Option<U256>> {
-        accessors::chain::td::read(self.txn, block_hash, block_number).await
-    }
-
-    /// State changes
-    /// Change sets are backward changes of the state, i.e. account/storage values _at the beginning of a block_.
-
-    /// Mark the beggining of a new block.
-    /// Must be called prior to calling update_account/update_account_code/update_storage.
     fn begin_block(&mut self, block_number: BlockNumber) {
         self.block_number = block_number;
         self.changed_storage.clear();
@@ -266,12 +479,14 @@ where
 
         if self.block_number >= self.prune_from {
             self.changed_storage.insert(address);
+            // Storage erased earlier in this block already recorded the value before the block.
             self.storage_changes
                 .entry(self.block_number)
                 .or_default()
                 .entry(address)
                 .or_default()
-                .insert(location, initial);
+                .entry(location)
+                .or_insert(initial);
         }
 
         self.storage
@@ -342,6 +557,10 @@ where
         // Write to state tables
         let mut account_table = self.txn.mutable_cursor(tables::Account).await?;
         let mut storage_table = self.txn.mutable_cursor_dupsort(tables::Storage).await?;
+        let mut hashed_storage_table = self
+            .txn
+            .mutable_cursor_dupsort(tables::HashedStorage)
+            .await?;
 
         debug!("Writing accounts");
         let mut account_addresses = self.accounts.keys().collect::<Vec<_>>();
@@ -352,8 +571,15 @@ where
 
             if let Some(account) = account {
                 account_table.upsert(address, account).await?;
-            } else if account_table.seek_exact(address).await?.is_some() {
-                account_table.delete_current().await?;
+            } else {
+                if account_table.seek_exact(address).await?.is_some() {
+                    account_table.delete_current().await?;
+                }
+                if self.hashed_state_fallback {
+                    self.txn
+                        .del(tables::HashedAccount, keccak256(address), None)
+                        .await?;
+                }
             }
 
             written_accounts += 1;
@@ -377,9 +603,27 @@ where
             if overlay_storage.erased && storage_table.seek_exact(address).await?.is_some() {
                 storage_table.delete_current_duplicates().await?;
             }
+            if self.hashed_state_fallback
+                && overlay_storage.erased
+                && hashed_storage_table
+                    .seek_exact(keccak256(address))
+                    .await?
+                    .is_some()
+            {
+                hashed_storage_table.delete_current_duplicates().await?;
+            }
 
             for (&k, &v) in &overlay_storage.slots {
                 upsert_storage_value(&mut storage_table, address, k, v).await?;
+                if self.hashed_state_fallback && v == 0 {
+                    upsert_hashed_storage_value(
+                        &mut hashed_storage_table,
+                        keccak256(address),
+                        keccak256(u256_to_h256(k)),
+                        U256::ZERO,
+                    )
+                    .await?;
+                }
 
                 written_slots += 1;
                 if written_slots % 500_000 == 0 {
@@ -474,4 +718,257 @@ mod tests {
         .unwrap();
         assert_eq!(db_value_b, value_b);
     }
+
+    #[tokio::test]
+    async fn storage_rewritten_after_erase() {
+        let db = new_mem_database().unwrap();
+        let txn = db.begin_mutable().await.unwrap();
+
+        let address: Address = hex!("be00000000000000000000000000000000000000").into();
+        let location_a = H256::from_low_u64_be(1);
+        let location_b = H256::from_low_u64_be(2);
+
+        txn.set(tables::Storage, address, (location_a, 0x6b.as_u256()))
+            .await
+            .unwrap();
+        txn.set(tables::Storage, address, (location_b, 0x85.as_u256()))
+            .await
+            .unwrap();
+
+        // Contract is destroyed and created again at the same address in block 2.
+        let mut buffer = Buffer::new(&txn, 0.into(), None);
+        buffer.begin_block(2.into());
+        buffer.erase_storage(address).await.unwrap();
+        buffer
+            .update_storage(
+                address,
+                h256_to_u256(location_a),
+                U256::ZERO,
+                0x132.as_u256(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            buffer
+                .read_storage(address, h256_to_u256(location_b))
+                .await
+                .unwrap(),
+            U256::ZERO
+        );
+        buffer.write_to_db().await.unwrap();
+
+        let mut storage = txn.cursor_dup_sort(tables::Storage).await.unwrap();
+        assert_eq!(
+            seek_storage_key(&mut storage, address, h256_to_u256(location_a))
+                .await
+                .unwrap(),
+            Some(0x132.as_u256())
+        );
+        assert_eq!(
+            seek_storage_key(&mut storage, address, h256_to_u256(location_b))
+                .await
+                .unwrap(),
+            None
+        );
+
+        // Changeset keeps values from before the block, not from after the erase.
+        let mut changeset = txn.cursor_dup_sort(tables::StorageChangeSet).await.unwrap();
+        let key = StorageChangeKey {
+            block_number: 2.into(),
+            address,
+        };
+        assert_eq!(
+            changeset.seek_exact(key).await.unwrap().map(|(_, v)| v),
+            Some(StorageChange {
+                location: location_a,
+                value: 0x6b.as_u256(),
+            })
+        );
+        assert_eq!(
+            changeset.next_dup().await.unwrap().map(|(_, v)| v),
+            Some(StorageChange {
+                location: location_b,
+                value: 0x85.as_u256(),
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn prefetch_access_lists() {
+        let db = new_mem_database().unwrap();
+        let txn = db.begin_mutable().await.unwrap();
+
+        let address: Address = hex!("be00000000000000000000000000000000000000").into();
+        let location_a = H256::from_low_u64_be(1);
+        let location_b = H256::from_low_u64_be(2);
+        let account = Account {
+            nonce: 1,
+            ..Default::default()
+        };
+
+        txn.set(tables::Account, address, account).await.unwrap();
+        txn.set(tables::Storage, address, (location_a, 0x6b.as_u256()))
+            .await
+            .unwrap();
+        txn.set(tables::Storage, address, (location_b, 0x85.as_u256()))
+            .await
+            .unwrap();
+
+        let mut buffer = Buffer::new(&txn, 0.into(), None);
+        buffer
+            .prefetch_access_lists(&[MessageWithSender {
+                message: Message::EIP2930 {
+                    chain_id: ChainId(1),
+                    nonce: 0,
+                    gas_price: U256::ZERO,
+                    gas_limit: 100_000,
+                    action: TransactionAction::Call(address),
+                    value: U256::ZERO,
+                    input: Bytes::new(),
+                    access_list: vec![AccessListItem {
+                        address,
+                        slots: vec![location_a, location_b],
+                    }],
+                },
+                sender: Address::zero(),
+            }])
+            .await
+            .unwrap();
+
+        // Prefetched values are served without reading the database again.
+        txn.del(tables::Account, address, None).await.unwrap();
+        txn.del(tables::Storage, address, None).await.unwrap();
+        assert_eq!(buffer.read_account(address).await.unwrap(), Some(account));
+        assert_eq!(
+            buffer
+                .read_storage(address, h256_to_u256(location_a))
+                .await
+                .unwrap(),
+            0x6b.as_u256()
+        );
+
+        // Changes made during execution take precedence.
+        buffer
+            .update_storage(
+                address,
+                h256_to_u256(location_b),
+                0x85.as_u256(),
+                0x132.as_u256(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            buffer
+                .read_storage(address, h256_to_u256(location_b))
+                .await
+                .unwrap(),
+            0x132.as_u256()
+        );
+    }
+
+    #[tokio::test]
+    async fn hashed_state_fallback() {
+        let db = new_mem_database().unwrap();
+        let txn = db.begin_mutable().await.unwrap();
+
+        let address: Address = hex!("be00000000000000000000000000000000000000").into();
+        let location_a = 1.as_u256();
+        let location_b = 2.as_u256();
+        let account = Account {
+            nonce: 1,
+            ..Default::default()
+        };
+
+        let hashed_address = keccak256(address);
+        txn.set(tables::HashedAccount, hashed_address, account)
+            .await
+            .unwrap();
+        for (location, value) in [(location_a, 0x6b.as_u256()), (location_b, 0x85.as_u256())] {
+            txn.set(
+                tables::HashedStorage,
+                hashed_address,
+                (keccak256(u256_to_h256(location)), value),
+            )
+            .await
+            .unwrap();
+        }
+
+        assert_eq!(
+            Buffer::new(&txn, 0.into(), None)
+                .read_account(address)
+                .await
+                .unwrap(),
+            None
+        );
+
+        let mut buffer = Buffer::new(&txn, 0.into(), None).with_hashed_state_fallback();
+        assert_eq!(buffer.read_account(address).await.unwrap(), Some(account));
+        assert_eq!(
+            buffer.read_storage(address, location_a).await.unwrap(),
+            0x6b.as_u256()
+        );
+
+        // Zeroed slots are deleted from hashed state too, so that they are not read from there.
+        buffer.begin_block(1.into());
+        buffer
+            .update_storage(address, location_a, 0x6b.as_u256(), U256::ZERO)
+            .await
+            .unwrap();
+        buffer.write_to_db().await.unwrap();
+
+        let buffer = Buffer::new(&txn, 0.into(), None).with_hashed_state_fallback();
+        assert_eq!(
+            buffer.read_storage(address, location_a).await.unwrap(),
+            U256::ZERO
+        );
+        assert_eq!(
+            buffer.read_storage(address, location_b).await.unwrap(),
+            0x85.as_u256()
+        );
+    }
+
+    #[tokio::test]
+    async fn recent_hashes() {
+        let db = new_mem_database().unwrap();
+        let txn = db.begin_mutable().await.unwrap();
+
+        let hash = |n: u64| H256::from_low_u64_be(n + 1);
+        for n in 0..=300 {
+            txn.set(tables::CanonicalHeader, BlockNumber(n), hash(n))
+                .await
+                .unwrap();
+        }
+
+        let mut buffer = Buffer::new(&txn, 0.into(), None);
+        buffer.load_recent_hashes(BlockNumber(300)).await.unwrap();
+
+        // Served from loaded hashes, not the database.
+        txn.set(tables::CanonicalHeader, BlockNumber(100), H256::zero())
+            .await
+            .unwrap();
+        assert_eq!(
+            buffer.canonical_hash(BlockNumber(100)).await.unwrap(),
+            Some(hash(100))
+        );
+        assert_eq!(
+            buffer
+                .ancestor_hash(BlockNumber(299), hash(299), 255)
+                .await
+                .unwrap(),
+            Some(hash(44))
+        );
+
+        // Oldest hash goes out of reach.
+        buffer.push_recent_hash(BlockNumber(300), hash(300));
+        assert_eq!(buffer.recent_hash(BlockNumber(44)), None);
+        assert_eq!(buffer.recent_hash(BlockNumber(300)), Some(hash(300)));
+
+        // Hashes of unwound blocks are not served after reloading.
+        buffer.load_recent_hashes(BlockNumber(101)).await.unwrap();
+        assert_eq!(
+            buffer.canonical_hash(BlockNumber(100)).await.unwrap(),
+            Some(H256::zero())
+        );
+        assert_eq!(buffer.recent_hash(BlockNumber(300)), None);
+    }
 }
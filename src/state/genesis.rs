@@ -1,4 +1,5 @@
 use crate::{
+    chain::protocol_param::param,
     kv::{tables, traits::*},
     models::*,
     state::*,
@@ -50,11 +51,18 @@ impl GenesisState {
             extra_data: seal.extra_data(),
             mix_hash: seal.mix_hash(),
             nonce: seal.nonce(),
-            base_fee_per_gas: None,
+            base_fee_per_gas: switch_is_active(
+                self.chain_spec.consensus.eip1559_block,
+                genesis.number,
+            )
+            .then(|| param::INITIAL_BASE_FEE.into()),
 
             receipts_root: EMPTY_ROOT,
             ommers_hash: EMPTY_LIST_HASH,
             transactions_root: EMPTY_ROOT,
+            withdrawals_root: None,
+            blob_gas_used: None,
+            excess_blob_gas: None,
         }
     }
 }
@@ -107,11 +115,15 @@ where
         extra_data: chainspec.genesis.seal.extra_data(),
         mix_hash: chainspec.genesis.seal.mix_hash(),
         nonce: chainspec.genesis.seal.nonce(),
-        base_fee_per_gas: None,
+        base_fee_per_gas: switch_is_active(chainspec.consensus.eip1559_block, genesis)
+            .then(|| param::INITIAL_BASE_FEE.into()),
 
         receipts_root: EMPTY_ROOT,
         ommers_hash: EMPTY_LIST_HASH,
         transactions_root: EMPTY_ROOT,
+        withdrawals_root: None,
+        blob_gas_used: None,
+        excess_blob_gas: None,
     };
     let block_hash = header.hash();
 
@@ -134,6 +146,7 @@ where
             base_tx_id: 0.into(),
             tx_amount: 0,
             uncles: vec![],
+            withdrawals: None,
         },
     )
     .await?;
@@ -0,0 +1,565 @@
+//! Flat history of accounts and storage in append-only files, one per step of [`STEP_SIZE`]
+//! blocks, replacing changesets of old blocks in the database.
+//!
+//! A history file holds every key changed within its step together with the blocks it was changed
+//! in and its values before each change, ordered by key and block. The inverted index of keys at
+//! the end of the file is loaded into memory on open, so that finding the value of a key at some
+//! block takes a binary search and one read per step.
+
+use crate::{
+    etl::collector::{Collector, OPTIMAL_BUFFER_CAPACITY},
+    kv::{tables, traits::*},
+    models::*,
+    u256_to_h256,
+};
+use anyhow::{ensure, format_err, Context};
+use parking_lot::Mutex;
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+};
+use tempfile::TempDir;
+use tracing::*;
+
+const MAGIC: &[u8; 8] = b"AKULAHIS";
+const VERSION: u8 = 1;
+const HEADER_LEN: usize = MAGIC.len() + 1 + 1 + 8;
+const FOOTER_LEN: usize = 8 + 8;
+
+/// Blocks per history file.
+pub const STEP_SIZE: u64 = 100_000;
+
+/// Blocks of `step`.
+pub fn step_blocks(step: u64) -> RangeInclusive<BlockNumber> {
+    BlockNumber(step * STEP_SIZE)..=BlockNumber((step + 1) * STEP_SIZE - 1)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HistoryDomain {
+    /// Keyed by address, values are encoded accounts, empty if there was none.
+    Accounts,
+    /// Keyed by address followed by location, values are encoded slot values.
+    Storage,
+}
+
+impl HistoryDomain {
+    fn id(self) -> u8 {
+        match self {
+            Self::Accounts => 0,
+            Self::Storage => 1,
+        }
+    }
+
+    fn key_len(self) -> usize {
+        match self {
+            Self::Accounts => ADDRESS_LENGTH,
+            Self::Storage => ADDRESS_LENGTH + KECCAK_LENGTH,
+        }
+    }
+
+    pub fn file_name(self, step: u64) -> String {
+        let name = match self {
+            Self::Accounts => "accounts",
+            Self::Storage => "storage",
+        };
+        format!("{}-{:06}.hist", name, step)
+    }
+}
+
+/// Writes a history file of one step into a temporary file, renamed once all keys are in.
+///
+/// Every key is followed by its changes, each a big endian block number and a length-prefixed
+/// value. The index of keys, their offsets and numbers of changes comes after all of them,
+/// followed by its offset and number of keys.
+#[derive(Debug)]
+pub struct HistoryFileWriter {
+    path: PathBuf,
+    tmp_path: PathBuf,
+    file: BufWriter<File>,
+    domain: HistoryDomain,
+    offset: u64,
+    index: Vec<(Vec<u8>, u64, u32)>,
+}
+
+impl HistoryFileWriter {
+    pub fn create(dir: &Path, domain: HistoryDomain, step: u64) -> anyhow::Result<Self> {
+        let path = dir.join(domain.file_name(step));
+        let tmp_path = path.with_extension("hist.tmp");
+        let mut file = BufWriter::new(
+            File::create(&tmp_path)
+                .with_context(|| format!("failed to create {}", tmp_path.display()))?,
+        );
+
+        file.write_all(MAGIC)?;
+        file.write_all(&[VERSION, domain.id()])?;
+        file.write_all(&step.to_be_bytes())?;
+
+        Ok(Self {
+            path,
+            tmp_path,
+            file,
+            domain,
+            offset: HEADER_LEN as u64,
+            index: vec![],
+        })
+    }
+
+    /// Append changes of `key`, which must be greater than all keys pushed before.
+    pub fn push(&mut self, key: &[u8], changes: &[(BlockNumber, Vec<u8>)]) -> anyhow::Result<()> {
+        ensure!(
+            key.len() == self.domain.key_len(),
+            "history key of length {}, expected {}",
+            key.len(),
+            self.domain.key_len()
+        );
+        ensure!(
+            self.index
+                .last()
+                .map_or(true, |(last, _, _)| last.as_slice() < key),
+            "history keys out of order"
+        );
+        ensure!(!changes.is_empty(), "no changes for history key");
+
+        let offset = self.offset;
+        for (block_number, value) in changes {
+            self.file.write_all(&block_number.0.to_be_bytes())?;
+            self.file.write_all(&[u8::try_from(value.len())?])?;
+            self.file.write_all(value)?;
+            self.offset += 8 + 1 + value.len() as u64;
+        }
+        self.index
+            .push((key.to_vec(), offset, u32::try_from(changes.len())?));
+
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> anyhow::Result<PathBuf> {
+        for (key, offset, count) in &self.index {
+            self.file.write_all(key)?;
+            self.file.write_all(&offset.to_be_bytes())?;
+            self.file.write_all(&count.to_be_bytes())?;
+        }
+        self.file.write_all(&self.offset.to_be_bytes())?;
+        self.file
+            .write_all(&(self.index.len() as u64).to_be_bytes())?;
+
+        self.file.flush()?;
+        self.file.get_ref().sync_all()?;
+        std::fs::rename(&self.tmp_path, &self.path)?;
+
+        Ok(self.path)
+    }
+}
+
+/// History file opened for lookups, with its index in memory.
+#[derive(Debug)]
+pub struct HistoryFile {
+    file: Mutex<File>,
+    domain: HistoryDomain,
+    step: u64,
+    /// Keys of the index, back to back.
+    keys: Vec<u8>,
+    /// Offset and number of changes of each key.
+    changes: Vec<(u64, u32)>,
+}
+
+impl HistoryFile {
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        let mut file = BufReader::new(
+            File::open(path).with_context(|| format!("failed to open {}", path.display()))?,
+        );
+
+        let mut header = [0; HEADER_LEN];
+        file.read_exact(&mut header)?;
+        ensure!(
+            &header[..MAGIC.len()] == MAGIC,
+            "{} is not a history file",
+            path.display()
+        );
+        let version = header[MAGIC.len()];
+        ensure!(
+            version == VERSION,
+            "unsupported history file version {}",
+            version
+        );
+        let domain = match header[MAGIC.len() + 1] {
+            0 => HistoryDomain::Accounts,
+            1 => HistoryDomain::Storage,
+            other => return Err(format_err!("unknown history domain {}", other)),
+        };
+        let step = u64::from_be_bytes(header[MAGIC.len() + 2..].try_into()?);
+
+        let mut footer = [0; FOOTER_LEN];
+        file.seek(SeekFrom::End(-(FOOTER_LEN as i64)))?;
+        file.read_exact(&mut footer)?;
+        let index_offset = u64::from_be_bytes(footer[..8].try_into()?);
+        let key_count = usize::try_from(u64::from_be_bytes(footer[8..].try_into()?))?;
+
+        let key_len = domain.key_len();
+        let mut keys = vec![0; key_count * key_len];
+        let mut changes = Vec::with_capacity(key_count);
+        file.seek(SeekFrom::Start(index_offset))?;
+        for key in keys.chunks_mut(key_len) {
+            let mut entry = [0; 12];
+            file.read_exact(key)?;
+            file.read_exact(&mut entry)?;
+            changes.push((
+                u64::from_be_bytes(entry[..8].try_into()?),
+                u32::from_be_bytes(entry[8..].try_into()?),
+            ));
+        }
+
+        Ok(Self {
+            file: Mutex::new(file.into_inner()),
+            domain,
+            step,
+            keys,
+            changes,
+        })
+    }
+
+    pub fn domain(&self) -> HistoryDomain {
+        self.domain
+    }
+
+    pub fn step(&self) -> u64 {
+        self.step
+    }
+
+    fn key(&self, index: usize) -> &[u8] {
+        let key_len = self.domain.key_len();
+        &self.keys[index * key_len..(index + 1) * key_len]
+    }
+
+    /// Changes of `key` within the step, ordered by block.
+    pub fn changes(&self, key: &[u8]) -> anyhow::Result<Vec<(BlockNumber, Vec<u8>)>> {
+        let (mut lo, mut hi) = (0, self.changes.len());
+        while lo < hi {
+            let mid = (lo + hi) / 2;
+            if self.key(mid) < key {
+                lo = mid + 1;
+            } else {
+                hi = mid;
+            }
+        }
+        if lo == self.changes.len() || self.key(lo) != key {
+            return Ok(vec![]);
+        }
+
+        let (offset, count) = self.changes[lo];
+        let mut file = self.file.lock();
+        file.seek(SeekFrom::Start(offset))?;
+        let mut file = BufReader::new(&mut *file);
+        let mut out = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let mut entry = [0; 9];
+            file.read_exact(&mut entry)?;
+            let mut value = vec![0; entry[8].into()];
+            file.read_exact(&mut value)?;
+            out.push((
+                BlockNumber(u64::from_be_bytes(entry[..8].try_into()?)),
+                value,
+            ));
+        }
+
+        Ok(out)
+    }
+
+    /// Value of `key` before its first change after `block_number`, if it is in this step.
+    pub fn value_after(
+        &self,
+        key: &[u8],
+        block_number: BlockNumber,
+    ) -> anyhow::Result<Option<Vec<u8>>> {
+        Ok(self
+            .changes(key)?
+            .into_iter()
+            .find(|(changed_at, _)| *changed_at > block_number)
+            .map(|(_, value)| value))
+    }
+}
+
+/// History files of consecutive steps from genesis.
+#[derive(Debug, Default)]
+pub struct HistoryFiles {
+    accounts: Vec<HistoryFile>,
+    storage: Vec<HistoryFile>,
+}
+
+impl HistoryFiles {
+    /// Open files of all consecutive steps in `dir`, which may not exist yet.
+    pub fn open(dir: &Path) -> anyhow::Result<Self> {
+        let mut files = Self::default();
+        for step in 0.. {
+            let accounts = dir.join(HistoryDomain::Accounts.file_name(step));
+            let storage = dir.join(HistoryDomain::Storage.file_name(step));
+            if !accounts.exists() || !storage.exists() {
+                break;
+            }
+
+            files.accounts.push(HistoryFile::open(&accounts)?);
+            files.storage.push(HistoryFile::open(&storage)?);
+        }
+
+        Ok(files)
+    }
+
+    /// Number of steps in files.
+    pub fn steps(&self) -> u64 {
+        self.accounts.len() as u64
+    }
+
+    /// Last block with history in files.
+    pub fn last_block(&self) -> Option<BlockNumber> {
+        self.steps()
+            .checked_sub(1)
+            .map(|step| *step_blocks(step).end())
+    }
+
+    fn value_after(
+        files: &[HistoryFile],
+        key: &[u8],
+        block_number: BlockNumber,
+    ) -> anyhow::Result<Option<Vec<u8>>> {
+        let first_step = usize::try_from(block_number.0 / STEP_SIZE)?;
+        for file in files.get(first_step..).unwrap_or_default() {
+            if let Some(value) = file.value_after(key, block_number)? {
+                return Ok(Some(value));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Account at `block_number`, if it has changed after it within files. Otherwise its value
+    /// is the one recorded in the database.
+    pub fn account(
+        &self,
+        address: Address,
+        block_number: BlockNumber,
+    ) -> anyhow::Result<Option<Option<Account>>> {
+        Self::value_after(&self.accounts, address.as_bytes(), block_number)?
+            .map(|value| {
+                Ok(if value.is_empty() {
+                    None
+                } else {
+                    Some(Account::decode(&value)?)
+                })
+            })
+            .transpose()
+    }
+
+    /// Storage value at `block_number`, if it has changed after it within files. Otherwise its
+    /// value is the one recorded in the database.
+    pub fn storage(
+        &self,
+        address: Address,
+        location: U256,
+        block_number: BlockNumber,
+    ) -> anyhow::Result<Option<U256>> {
+        let key = [address.as_bytes(), u256_to_h256(location).as_bytes()].concat();
+        Self::value_after(&self.storage, &key, block_number)?
+            .map(|value| U256::decode(&value))
+            .transpose()
+    }
+}
+
+/// Write history of `step` from changesets into files in `dir`.
+pub async fn write_step<'db, Tx: Transaction<'db>>(
+    tx: &Tx,
+    etl_dir: &TempDir,
+    dir: &Path,
+    step: u64,
+) -> anyhow::Result<()> {
+    let blocks = step_blocks(step);
+
+    // Changesets are ordered by block, files by key and block.
+    let mut accounts = Collector::<Vec<u8>, Vec<u8>>::new(etl_dir, OPTIMAL_BUFFER_CAPACITY);
+    let mut cursor = tx.cursor_dup_sort(tables::AccountChangeSet).await?;
+    let mut entry = cursor.seek(*blocks.start()).await?;
+    while let Some((block_number, tables::AccountChange { address, account })) = entry {
+        if block_number > *blocks.end() {
+            break;
+        }
+        accounts.push(
+            [address.as_bytes(), &block_number.0.to_be_bytes()].concat(),
+            account
+                .map(|account| account.encode().to_vec())
+                .unwrap_or_default(),
+        );
+        entry = cursor.next().await?;
+    }
+
+    let mut storage = Collector::<Vec<u8>, Vec<u8>>::new(etl_dir, OPTIMAL_BUFFER_CAPACITY);
+    let mut cursor = tx.cursor_dup_sort(tables::StorageChangeSet).await?;
+    let mut entry = cursor.seek(*blocks.start()).await?;
+    while let Some((
+        tables::StorageChangeKey {
+            block_number,
+            address,
+        },
+        tables::StorageChange { location, value },
+    )) = entry
+    {
+        if block_number > *blocks.end() {
+            break;
+        }
+        storage.push(
+            [
+                address.as_bytes(),
+                location.as_bytes(),
+                &block_number.0.to_be_bytes(),
+            ]
+            .concat(),
+            value.encode().to_vec(),
+        );
+        entry = cursor.next().await?;
+    }
+
+    for (domain, collector) in [
+        (HistoryDomain::Accounts, &mut accounts),
+        (HistoryDomain::Storage, &mut storage),
+    ] {
+        let mut writer = HistoryFileWriter::create(dir, domain, step)?;
+        let mut key = vec![];
+        let mut changes = vec![];
+        for entry in collector.iter() {
+            let (k, value) = entry?;
+            let (k, block_number) = k.split_at(domain.key_len());
+            if k != key.as_slice() {
+                if !changes.is_empty() {
+                    writer.push(&key, &changes)?;
+                    changes.clear();
+                }
+                key = k.to_vec();
+            }
+            changes.push((
+                BlockNumber(u64::from_be_bytes(block_number.try_into()?)),
+                value,
+            ));
+        }
+        if !changes.is_empty() {
+            writer.push(&key, &changes)?;
+        }
+        let path = writer.finish()?;
+        debug!("Wrote {}", path.display());
+    }
+
+    Ok(())
+}
+
+/// Delete changesets of blocks up to `to` whose history has been moved to files.
+pub async fn delete_frozen_changesets<'db, RwTx: MutableTransaction<'db>>(
+    tx: &RwTx,
+    to: BlockNumber,
+) -> anyhow::Result<()> {
+    tx.delete_range(tables::AccountChangeSet, BlockNumber(0), Some(to + 1))
+        .await?;
+    tx.delete_range(tables::StorageChangeSet, BlockNumber(0), Some(to + 1))
+        .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kv::new_mem_database;
+    use hex_literal::hex;
+
+    #[tokio::test]
+    async fn history_at_step_boundaries() {
+        let db = new_mem_database().unwrap();
+        let tx = db.begin_mutable().await.unwrap();
+        let etl_dir = TempDir::new().unwrap();
+        let dir = TempDir::new().unwrap();
+
+        let address = Address::from(hex!("71562b71999873db5b286df957af199ec94617f7"));
+        let other = Address::from(hex!("2e1c1e1c67b4f58e0ba43caf9a1bd6e4a5cdb9e3"));
+        let location = U256::from(7_u64);
+        let account = |balance: u64| Account {
+            balance: U256::from(balance),
+            ..Account::default()
+        };
+
+        // Address is created at block 10, updated at 50 and at the first block of step 1.
+        let changes = [
+            (BlockNumber(10), address, None),
+            (BlockNumber(50), address, Some(account(1))),
+            (BlockNumber(60), other, None),
+            (BlockNumber(STEP_SIZE), address, Some(account(2))),
+        ];
+        for (block_number, address, account) in changes {
+            tx.set(
+                tables::AccountChangeSet,
+                block_number,
+                tables::AccountChange { address, account },
+            )
+            .await
+            .unwrap();
+        }
+        tx.set(
+            tables::StorageChangeSet,
+            tables::StorageChangeKey {
+                block_number: BlockNumber(50),
+                address,
+            },
+            tables::StorageChange {
+                location: u256_to_h256(location),
+                value: U256::from(3_u64),
+            },
+        )
+        .await
+        .unwrap();
+
+        write_step(&tx, &etl_dir, dir.path(), 0).await.unwrap();
+        assert_eq!(HistoryFiles::open(dir.path()).unwrap().steps(), 1);
+        write_step(&tx, &etl_dir, dir.path(), 1).await.unwrap();
+        delete_frozen_changesets(&tx, *step_blocks(0).end())
+            .await
+            .unwrap();
+
+        let files = HistoryFiles::open(dir.path()).unwrap();
+        assert_eq!(files.steps(), 2);
+        assert_eq!(files.last_block(), Some(*step_blocks(1).end()));
+
+        assert_eq!(files.account(address, BlockNumber(5)).unwrap(), Some(None));
+        assert_eq!(
+            files.account(address, BlockNumber(10)).unwrap(),
+            Some(Some(account(1)))
+        );
+        assert_eq!(
+            files.account(address, BlockNumber(STEP_SIZE - 1)).unwrap(),
+            Some(Some(account(2)))
+        );
+        assert_eq!(
+            files.account(address, BlockNumber(STEP_SIZE)).unwrap(),
+            None
+        );
+        assert_eq!(files.account(other, BlockNumber(70)).unwrap(), None);
+
+        assert_eq!(
+            files.storage(address, location, BlockNumber(49)).unwrap(),
+            Some(U256::from(3_u64))
+        );
+        assert_eq!(
+            files.storage(address, location, BlockNumber(50)).unwrap(),
+            None
+        );
+
+        assert_eq!(files.account(other, BlockNumber(59)).unwrap(), Some(None));
+
+        // Changesets of step 1 are still in the database.
+        assert!(tx
+            .cursor(tables::AccountChangeSet)
+            .await
+            .unwrap()
+            .first()
+            .await
+            .unwrap()
+            .map_or(false, |(block_number, _)| block_number
+                == BlockNumber(STEP_SIZE)));
+    }
+}
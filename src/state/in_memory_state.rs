@@ -1,4 +1,4 @@
-use crate::{crypto::*, models::*, util::*, State};
+use crate::{crypto::*, models::*, util::*, ChainReader, StateReader, StateWriter};
 use async_trait::async_trait;
 use bytes::Bytes;
 use std::{collections::HashMap, convert::TryInto};
@@ -10,7 +10,7 @@ type AccountChanges = HashMap<Address, Option<Account>>;
 type StorageChanges = HashMap<Address, HashMap<U256, U256>>;
 
 /// Holds all state in memory.
-#[derive(Debug, Default)]
+#[derive(Clone, Debug, Default)]
 pub struct InMemoryState {
     accounts: HashMap<Address, Account>,
 
@@ -80,6 +80,14 @@ impl InMemoryState {
         0
     }
 
+    /// Non-zero storage slots of `address`.
+    pub fn account_storage(&self, address: Address) -> impl Iterator<Item = (U256, U256)> + '_ {
+        self.storage
+            .get(&address)
+            .into_iter()
+            .flat_map(|storage| storage.iter().map(|(&location, &value)| (location, value)))
+    }
+
     pub fn state_root_hash(&self) -> H256 {
         if self.accounts.is_empty() {
             return EMPTY_ROOT;
@@ -105,6 +113,7 @@ impl InMemoryState {
             header,
             transactions,
             ommers,
+            withdrawals,
         } = block;
 
         let block_number = header.number.0 as usize;
@@ -124,6 +133,7 @@ impl InMemoryState {
             BlockBody {
                 transactions,
                 ommers,
+                withdrawals,
             },
         );
 
@@ -166,6 +176,7 @@ impl InMemoryState {
                             })
                             .collect::<anyhow::Result<_>>()?,
                         ommers: body.ommers.clone(),
+                        withdrawals: body.withdrawals.clone(),
                     })
                 })
                 .transpose();
@@ -220,9 +231,7 @@ impl InMemoryState {
 }
 
 #[async_trait]
-impl State for InMemoryState {
-    // Readers
-
+impl StateReader for InMemoryState {
     async fn read_account(&self, address: Address) -> anyhow::Result<Option<Account>> {
         Ok(self.accounts.get(&address).cloned())
     }
@@ -241,25 +250,6 @@ impl State for InMemoryState {
         Ok(U256::ZERO)
     }
 
-    async fn erase_storage(&mut self, address: Address) -> anyhow::Result<()> {
-        let address_storage = self.storage.remove(&address).unwrap_or_default();
-
-        if !address_storage.is_empty() {
-            let storage_changes = self
-                .storage_changes
-                .entry(self.block_number)
-                .or_default()
-                .entry(address)
-                .or_default();
-
-            for (slot, initial) in address_storage {
-                storage_changes.insert(slot, initial);
-            }
-        }
-
-        Ok(())
-    }
-
     async fn read_header(
         &self,
         block_number: BlockNumber,
@@ -295,12 +285,44 @@ impl State for InMemoryState {
 
         Ok(None)
     }
+}
 
-    /// State changes
-    /// Change sets are backward changes of the state, i.e. account/storage values _at the beginning of a block_.
+#[async_trait]
+impl ChainReader for InMemoryState {
+    async fn canonical_hash(&self, block_number: BlockNumber) -> anyhow::Result<Option<H256>> {
+        Ok(InMemoryState::canonical_hash(self, block_number))
+    }
+
+    async fn header_number(&self, block_hash: H256) -> anyhow::Result<Option<BlockNumber>> {
+        Ok(self
+            .headers
+            .iter()
+            .position(|header_map| header_map.contains_key(&block_hash))
+            .map(|block_number| BlockNumber(block_number as u64)))
+    }
+}
+
+#[async_trait]
+impl StateWriter for InMemoryState {
+    async fn erase_storage(&mut self, address: Address) -> anyhow::Result<()> {
+        let address_storage = self.storage.remove(&address).unwrap_or_default();
+
+        if !address_storage.is_empty() {
+            let storage_changes = self
+                .storage_changes
+                .entry(self.block_number)
+                .or_default()
+                .entry(address)
+                .or_default();
+
+            for (slot, initial) in address_storage {
+                storage_changes.insert(slot, initial);
+            }
+        }
+
+        Ok(())
+    }
 
-    /// Mark the beggining of a new block.
-    /// Must be called prior to calling update_account/update_account_code/update_storage.
     fn begin_block(&mut self, block_number: BlockNumber) {
         self.block_number = block_number;
     }
@@ -341,7 +363,8 @@ impl State for InMemoryState {
             .or_default()
             .entry(address)
             .or_default()
-            .insert(location, initial);
+            .entry(location)
+            .or_insert(initial);
 
         let e = self.storage.entry(address).or_default();
 
@@ -549,4 +572,88 @@ mod tests {
             }
         })
     }
+
+    #[test]
+    fn ancestry() {
+        run_test(async {
+            let mut state = InMemoryState::new();
+
+            // Canonical chain 0..=6 and a side chain forking off block 3.
+            let mut insert_chain =
+                |from: H256, numbers: std::ops::RangeInclusive<u64>, extra: u8| {
+                    let mut parent_hash = from;
+                    let mut hashes = vec![];
+                    for number in numbers {
+                        let header = BlockHeader {
+                            extra_data: vec![extra].into(),
+                            ..BlockHeader::new(
+                                PartialHeader {
+                                    parent_hash,
+                                    number: BlockNumber(number),
+                                    ..PartialHeader::empty()
+                                },
+                                EMPTY_LIST_HASH,
+                                EMPTY_ROOT,
+                            )
+                        };
+                        parent_hash = header.hash();
+                        hashes.push(parent_hash);
+                        state.insert_block(
+                            Block {
+                                header,
+                                transactions: vec![],
+                                ommers: vec![],
+                                withdrawals: None,
+                            },
+                            parent_hash,
+                        );
+                    }
+                    hashes
+                };
+            let canonical = insert_chain(H256::zero(), 0..=6, 0);
+            let side = insert_chain(canonical[3], 4..=6, 1);
+            for (number, &hash) in canonical.iter().enumerate() {
+                state.canonize_block(BlockNumber(number as u64), hash);
+            }
+
+            assert_eq!(
+                state.canonical_number(canonical[5]).await.unwrap(),
+                Some(BlockNumber(5))
+            );
+            assert_eq!(state.canonical_number(side[1]).await.unwrap(), None);
+            assert_eq!(
+                state.header_number(side[1]).await.unwrap(),
+                Some(BlockNumber(5))
+            );
+
+            assert_eq!(
+                state
+                    .ancestor_hash(BlockNumber(6), canonical[6], 4)
+                    .await
+                    .unwrap(),
+                Some(canonical[2])
+            );
+            assert_eq!(
+                state
+                    .ancestor_hash(BlockNumber(6), side[2], 1)
+                    .await
+                    .unwrap(),
+                Some(side[1])
+            );
+            assert_eq!(
+                state
+                    .ancestor_hash(BlockNumber(6), side[2], 4)
+                    .await
+                    .unwrap(),
+                Some(canonical[2])
+            );
+            assert_eq!(
+                state
+                    .ancestor_hash(BlockNumber(6), side[2], 7)
+                    .await
+                    .unwrap(),
+                None
+            );
+        })
+    }
 }
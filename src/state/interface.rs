@@ -4,17 +4,16 @@ use auto_impl::auto_impl;
 use bytes::Bytes;
 use std::fmt::Debug;
 
+/// Read access to accounts, storage, code and chain data.
 #[async_trait]
-#[auto_impl(&mut, Box)]
-pub trait State: Debug + Send + Sync {
+#[auto_impl(&, &mut, Box)]
+pub trait StateReader: Debug + Send + Sync {
     async fn read_account(&self, address: Address) -> anyhow::Result<Option<Account>>;
 
     async fn read_code(&self, code_hash: H256) -> anyhow::Result<Bytes>;
 
     async fn read_storage(&self, address: Address, location: U256) -> anyhow::Result<U256>;
 
-    async fn erase_storage(&mut self, address: Address) -> anyhow::Result<()>;
-
     async fn read_header(
         &self,
         block_number: BlockNumber,
@@ -32,9 +31,64 @@ pub trait State: Debug + Send + Sync {
         block_number: BlockNumber,
         block_hash: H256,
     ) -> anyhow::Result<Option<U256>>;
+}
+
+/// Lookups between block numbers and hashes, and ancestry of blocks.
+#[async_trait]
+#[auto_impl(&, &mut, Box)]
+pub trait ChainReader: StateReader {
+    async fn canonical_hash(&self, block_number: BlockNumber) -> anyhow::Result<Option<H256>>;
+
+    /// Number of a known header, canonical or not.
+    async fn header_number(&self, block_hash: H256) -> anyhow::Result<Option<BlockNumber>>;
+
+    /// Number of block `block_hash` if it is canonical.
+    async fn canonical_number(&self, block_hash: H256) -> anyhow::Result<Option<BlockNumber>> {
+        if let Some(block_number) = self.header_number(block_hash).await? {
+            if self.canonical_hash(block_number).await? == Some(block_hash) {
+                return Ok(Some(block_number));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Hash of the ancestor `distance` blocks back from block `block_number`/`block_hash`.
+    ///
+    /// Walks parent hashes until the chain joins the canonical one,
+    /// then looks the ancestor up directly.
+    async fn ancestor_hash(
+        &self,
+        mut block_number: BlockNumber,
+        mut block_hash: H256,
+        distance: u64,
+    ) -> anyhow::Result<Option<H256>> {
+        let target = match block_number.0.checked_sub(distance) {
+            Some(target) => BlockNumber(target),
+            None => return Ok(None),
+        };
+
+        while block_number > target {
+            if self.canonical_hash(block_number).await? == Some(block_hash) {
+                return self.canonical_hash(target).await;
+            }
+            block_hash = match self.read_header(block_number, block_hash).await? {
+                Some(header) => header.parent_hash,
+                None => return Ok(None),
+            };
+            block_number.0 -= 1;
+        }
+
+        Ok(Some(block_hash))
+    }
+}
 
-    /// State changes
-    /// Change sets are backward changes of the state, i.e. account/storage values _at the beginning of a block_.
+/// State changes
+/// Change sets are backward changes of the state, i.e. account/storage values _at the beginning of a block_.
+#[async_trait]
+#[auto_impl(&mut, Box)]
+pub trait StateWriter: Debug + Send + Sync {
+    async fn erase_storage(&mut self, address: Address) -> anyhow::Result<()>;
 
     /// Mark the beggining of a new block.
     /// Must be called prior to calling update_account/update_account_code/update_storage.
@@ -57,3 +111,155 @@ pub trait State: Debug + Send + Sync {
         current: U256,
     ) -> anyhow::Result<()>;
 }
+
+/// State that is both read and written, as used by execution.
+pub trait State: StateReader + StateWriter + ChainReader {}
+
+impl<S: StateReader + StateWriter + ChainReader + ?Sized> State for S {}
+
+/// Blocking flavor of [`StateReader`], for sources that never wait on I/O.
+///
+/// Wrap into [`SyncState`] to use where [`StateReader`] is expected.
+pub trait StateReaderSync: Debug + Send + Sync {
+    fn read_account(&self, address: Address) -> anyhow::Result<Option<Account>>;
+
+    fn read_code(&self, code_hash: H256) -> anyhow::Result<Bytes>;
+
+    fn read_storage(&self, address: Address, location: U256) -> anyhow::Result<U256>;
+
+    fn read_header(
+        &self,
+        block_number: BlockNumber,
+        block_hash: H256,
+    ) -> anyhow::Result<Option<BlockHeader>>;
+
+    fn read_body(
+        &self,
+        block_number: BlockNumber,
+        block_hash: H256,
+    ) -> anyhow::Result<Option<BlockBody>>;
+
+    fn total_difficulty(
+        &self,
+        block_number: BlockNumber,
+        block_hash: H256,
+    ) -> anyhow::Result<Option<U256>>;
+
+    fn canonical_hash(&self, block_number: BlockNumber) -> anyhow::Result<Option<H256>>;
+
+    fn header_number(&self, block_hash: H256) -> anyhow::Result<Option<BlockNumber>>;
+}
+
+/// Blocking flavor of [`StateWriter`].
+///
+/// Wrap into [`SyncState`] to use where [`StateWriter`] is expected.
+pub trait StateWriterSync: Debug + Send + Sync {
+    fn erase_storage(&mut self, address: Address) -> anyhow::Result<()>;
+
+    fn begin_block(&mut self, block_number: BlockNumber);
+
+    fn update_account(
+        &mut self,
+        address: Address,
+        initial: Option<Account>,
+        current: Option<Account>,
+    );
+
+    fn update_code(&mut self, code_hash: H256, code: Bytes) -> anyhow::Result<()>;
+
+    fn update_storage(
+        &mut self,
+        address: Address,
+        location: U256,
+        initial: U256,
+        current: U256,
+    ) -> anyhow::Result<()>;
+}
+
+/// Adapter of a blocking state to the async [`StateReader`], [`ChainReader`] and [`StateWriter`].
+#[derive(Debug, Default)]
+pub struct SyncState<S>(pub S);
+
+#[async_trait]
+impl<S: StateReaderSync> StateReader for SyncState<S> {
+    async fn read_account(&self, address: Address) -> anyhow::Result<Option<Account>> {
+        StateReaderSync::read_account(&self.0, address)
+    }
+
+    async fn read_code(&self, code_hash: H256) -> anyhow::Result<Bytes> {
+        StateReaderSync::read_code(&self.0, code_hash)
+    }
+
+    async fn read_storage(&self, address: Address, location: U256) -> anyhow::Result<U256> {
+        StateReaderSync::read_storage(&self.0, address, location)
+    }
+
+    async fn read_header(
+        &self,
+        block_number: BlockNumber,
+        block_hash: H256,
+    ) -> anyhow::Result<Option<BlockHeader>> {
+        StateReaderSync::read_header(&self.0, block_number, block_hash)
+    }
+
+    async fn read_body(
+        &self,
+        block_number: BlockNumber,
+        block_hash: H256,
+    ) -> anyhow::Result<Option<BlockBody>> {
+        StateReaderSync::read_body(&self.0, block_number, block_hash)
+    }
+
+    async fn total_difficulty(
+        &self,
+        block_number: BlockNumber,
+        block_hash: H256,
+    ) -> anyhow::Result<Option<U256>> {
+        StateReaderSync::total_difficulty(&self.0, block_number, block_hash)
+    }
+}
+
+#[async_trait]
+impl<S: StateReaderSync> ChainReader for SyncState<S> {
+    async fn canonical_hash(&self, block_number: BlockNumber) -> anyhow::Result<Option<H256>> {
+        StateReaderSync::canonical_hash(&self.0, block_number)
+    }
+
+    async fn header_number(&self, block_hash: H256) -> anyhow::Result<Option<BlockNumber>> {
+        StateReaderSync::header_number(&self.0, block_hash)
+    }
+}
+
+#[async_trait]
+impl<S: StateWriterSync> StateWriter for SyncState<S> {
+    async fn erase_storage(&mut self, address: Address) -> anyhow::Result<()> {
+        StateWriterSync::erase_storage(&mut self.0, address)
+    }
+
+    fn begin_block(&mut self, block_number: BlockNumber) {
+        StateWriterSync::begin_block(&mut self.0, block_number)
+    }
+
+    fn update_account(
+        &mut self,
+        address: Address,
+        initial: Option<Account>,
+        current: Option<Account>,
+    ) {
+        StateWriterSync::update_account(&mut self.0, address, initial, current)
+    }
+
+    async fn update_code(&mut self, code_hash: H256, code: Bytes) -> anyhow::Result<()> {
+        StateWriterSync::update_code(&mut self.0, code_hash, code)
+    }
+
+    async fn update_storage(
+        &mut self,
+        address: Address,
+        location: U256,
+        initial: U256,
+        current: U256,
+    ) -> anyhow::Result<()> {
+        StateWriterSync::update_storage(&mut self.0, address, location, initial, current)
+    }
+}
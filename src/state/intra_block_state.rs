@@ -572,3 +572,66 @@ impl<'storage, 'r, S: State> IntraBlockState<'r, S> {
         self.refund
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::InMemoryState;
+
+    #[tokio::test]
+    async fn recreated_contract_has_empty_storage() {
+        let address = Address::from_low_u64_be(0xc0de);
+        let mut db = InMemoryState::default();
+        db.begin_block(1.into());
+        db.update_account(
+            address,
+            None,
+            Some(Account {
+                nonce: 1,
+                ..Default::default()
+            }),
+        );
+        db.update_storage(address, 1.as_u256(), U256::ZERO, 5.as_u256())
+            .await
+            .unwrap();
+
+        let mut state = IntraBlockState::new(&mut db);
+        assert_eq!(
+            state
+                .get_current_storage(address, 1.as_u256())
+                .await
+                .unwrap(),
+            5.as_u256()
+        );
+
+        // Self-destructed in one transaction and redeployed with CREATE2 in the next one.
+        state.record_selfdestruct(address);
+        state.destruct_selfdestructs().await.unwrap();
+        state.finalize_transaction();
+        state.clear_journal_and_substate();
+
+        state.create_contract(address).await.unwrap();
+        assert_eq!(
+            state
+                .get_current_storage(address, 1.as_u256())
+                .await
+                .unwrap(),
+            U256::ZERO
+        );
+        state
+            .set_storage(address, 2.as_u256(), 7.as_u256())
+            .await
+            .unwrap();
+        state.finalize_transaction();
+        state.write_to_db(2.into()).await.unwrap();
+
+        assert_eq!(
+            db.read_storage(address, 1.as_u256()).await.unwrap(),
+            U256::ZERO
+        );
+        assert_eq!(
+            db.read_storage(address, 2.as_u256()).await.unwrap(),
+            7.as_u256()
+        );
+    }
+}
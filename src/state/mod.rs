@@ -1,12 +1,17 @@
+#[cfg(feature = "node")]
 mod buffer;
+#[cfg(feature = "node")]
 mod database;
 mod delta;
+#[cfg(feature = "node")]
 pub mod genesis;
+#[cfg(feature = "node")]
+pub mod history_files;
 mod in_memory_state;
 mod interface;
 mod intra_block_state;
 mod object;
 
-pub use self::{
-    buffer::*, database::*, in_memory_state::*, interface::*, intra_block_state::*, object::*,
-};
+#[cfg(feature = "node")]
+pub use self::{buffer::*, database::*};
+pub use self::{in_memory_state::*, interface::*, intra_block_state::*, object::*};
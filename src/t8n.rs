@@ -0,0 +1,745 @@
+//! State transition of a single block, with inputs and outputs in the format of geth's
+//! `evm t8n`, so that Akula can be driven by retesteth and differential fuzzers.
+//!
+//! Block is executed on top of [`InMemoryState`] holding the pre-state allocation. Transactions
+//! that fail validation are reported as rejected instead of failing the block. `DIFFICULTY`
+//! returns `currentDifficulty`, there is no `currentRandom`.
+
+use crate::{
+    chain::intrinsic_gas::check_intrinsic_gas,
+    consensus::{engine_factory, pre_validate_transaction},
+    crypto::{keccak256, root_hash},
+    execution::{
+        address::create_address,
+        analysis_cache::AnalysisCache,
+        eip3155::{Eip3155Summary, Eip3155Tracer},
+        processor::ExecutionProcessor,
+        struct_logger::StructLoggerConfig,
+        tracer::Tracer,
+    },
+    hexbytes,
+    models::*,
+    res::chainspec::MAINNET,
+    u256_to_h256, InMemoryState, StateReader, StateWriter,
+};
+use anyhow::{bail, ensure, format_err};
+use bytes::Bytes;
+use evmodin::{Revision, StatusCode};
+use secp256k1::{Message as SecpMessage, SecretKey, SECP256K1};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    io::{self, Write},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/// Forks accepted by `--state.fork`, in activation order.
+pub const FORKS: &[&str] = &[
+    "Frontier",
+    "Homestead",
+    "EIP150",
+    "EIP158",
+    "Byzantium",
+    "Constantinople",
+    "ConstantinopleFix",
+    "Istanbul",
+    "Berlin",
+    "London",
+    "Shanghai",
+];
+
+/// Chain with every fork up to `fork` active from genesis. Without `block_reward`, no rewards
+/// are paid at all.
+pub fn chain_spec(
+    fork: &str,
+    chain_id: u64,
+    block_reward: Option<U256>,
+) -> anyhow::Result<ChainSpec> {
+    let active = FORKS
+        .iter()
+        .position(|&f| f == fork)
+        .ok_or_else(|| format_err!("unsupported fork {}", fork))?;
+    let at = |name: &str| FORKS[..=active].contains(&name).then(|| BlockNumber(0));
+
+    let mut spec = MAINNET.clone();
+    spec.name = fork.to_string();
+    spec.upgrades = Upgrades {
+        homestead: at("Homestead"),
+        tangerine: at("EIP150"),
+        spurious: at("EIP158"),
+        byzantium: at("Byzantium"),
+        constantinople: at("Constantinople"),
+        petersburg: at("ConstantinopleFix"),
+        istanbul: at("Istanbul"),
+        berlin: at("Berlin"),
+        london: at("London"),
+        shanghai: at("Shanghai"),
+    };
+    spec.consensus = ConsensusParams {
+        seal_verification: match block_reward {
+            Some(block_reward) => SealVerificationParams::Ethash {
+                duration_limit: 13,
+                block_reward: [(BlockNumber(0), block_reward)].into(),
+                homestead_formula: spec.upgrades.homestead,
+                byzantium_formula: spec.upgrades.byzantium,
+                difficulty_bomb: None,
+                skip_pow_verification: true,
+            },
+            // Clique finalizes blocks without rewards.
+            None => SealVerificationParams::Clique {
+                period: Duration::from_secs(0),
+                epoch: 30_000,
+            },
+        },
+        eip1559_block: spec.upgrades.london,
+        eip4844_block: None,
+        terminal_total_difficulty: None,
+    };
+    spec.params.chain_id = ChainId(chain_id);
+    spec.params.network_id = NetworkId(chain_id);
+    spec.contracts = Default::default();
+    spec.balances = Default::default();
+    spec.system_calls = Default::default();
+    spec.precompile_pricing = Default::default();
+    spec.reward_redirections = Default::default();
+
+    Ok(spec)
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct AllocAccount {
+    pub balance: U256,
+    #[serde(default)]
+    pub nonce: U64,
+    #[serde(default, with = "hexbytes", skip_serializing_if = "Bytes::is_empty")]
+    pub code: Bytes,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub storage: BTreeMap<U256, U256>,
+}
+
+pub type Alloc = BTreeMap<Address, AllocAccount>;
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct Ommer {
+    /// How many blocks the ommer is behind the current one.
+    pub delta: u64,
+    pub address: Address,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Env {
+    pub current_coinbase: Address,
+    #[serde(default)]
+    pub current_difficulty: U256,
+    pub current_gas_limit: U64,
+    pub current_number: U64,
+    pub current_timestamp: U64,
+    pub current_base_fee: Option<U256>,
+    pub parent_hash: Option<H256>,
+    /// Hashes of previous blocks for `BLOCKHASH`, keyed by block number in hex or decimal.
+    #[serde(default)]
+    pub block_hashes: BTreeMap<String, H256>,
+    #[serde(default)]
+    pub ommers: Vec<Ommer>,
+    pub withdrawals: Option<Vec<Withdrawal>>,
+}
+
+fn parse_block_number(s: &str) -> anyhow::Result<BlockNumber> {
+    Ok(BlockNumber(match s.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16)?,
+        None => s.parse()?,
+    }))
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccessListEntry {
+    pub address: Address,
+    #[serde(default)]
+    pub storage_keys: Vec<H256>,
+}
+
+/// Transaction as accepted by geth's `evm t8n`. It is either signed with `v`, `r` and `s`, or
+/// signed here with `secretKey`.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionJson {
+    #[serde(rename = "type", default)]
+    pub tx_type: U64,
+    pub chain_id: Option<U64>,
+    pub nonce: U64,
+    pub gas_price: Option<U256>,
+    pub max_priority_fee_per_gas: Option<U256>,
+    pub max_fee_per_gas: Option<U256>,
+    pub gas: U64,
+    pub to: Option<Address>,
+    pub value: U256,
+    #[serde(alias = "data", with = "hexbytes")]
+    pub input: Bytes,
+    #[serde(default)]
+    pub access_list: Vec<AccessListEntry>,
+    #[serde(default)]
+    pub v: U64,
+    #[serde(default)]
+    pub r: U256,
+    #[serde(default)]
+    pub s: U256,
+    pub secret_key: Option<H256>,
+}
+
+impl TransactionJson {
+    /// Signed transaction, with legacy transactions signed here replay-protected if `eip155`.
+    pub fn into_transaction(
+        self,
+        chain_id: ChainId,
+        eip155: bool,
+    ) -> anyhow::Result<MessageWithSignature> {
+        let action = match self.to {
+            Some(to) => TransactionAction::Call(to),
+            None => TransactionAction::Create,
+        };
+        let access_list = self
+            .access_list
+            .into_iter()
+            .map(|entry| AccessListItem {
+                address: entry.address,
+                slots: entry.storage_keys,
+            })
+            .collect::<Vec<_>>();
+        let typed_chain_id = self.chain_id.map_or(chain_id, |id| ChainId(id.as_u64()));
+        let gas_price = self
+            .gas_price
+            .ok_or_else(|| format_err!("missing gasPrice"));
+
+        let (legacy_chain_id, odd_y_parity) = if self.secret_key.is_some() {
+            (eip155.then(|| typed_chain_id), false)
+        } else if self.tx_type.is_zero() {
+            let v = YParityAndChainId::from_v(self.v.as_u64())
+                .ok_or_else(|| format_err!("invalid v {}", self.v))?;
+            (v.chain_id, v.odd_y_parity)
+        } else {
+            (None, !self.v.is_zero())
+        };
+
+        let message = match self.tx_type.as_u64() {
+            0 => Message::Legacy {
+                chain_id: legacy_chain_id,
+                nonce: self.nonce.as_u64(),
+                gas_price: gas_price?,
+                gas_limit: self.gas.as_u64(),
+                action,
+                value: self.value,
+                input: self.input,
+            },
+            1 => Message::EIP2930 {
+                chain_id: typed_chain_id,
+                nonce: self.nonce.as_u64(),
+                gas_price: gas_price?,
+                gas_limit: self.gas.as_u64(),
+                action,
+                value: self.value,
+                input: self.input,
+                access_list,
+            },
+            2 => Message::EIP1559 {
+                chain_id: typed_chain_id,
+                nonce: self.nonce.as_u64(),
+                max_priority_fee_per_gas: self
+                    .max_priority_fee_per_gas
+                    .ok_or_else(|| format_err!("missing maxPriorityFeePerGas"))?,
+                max_fee_per_gas: self
+                    .max_fee_per_gas
+                    .ok_or_else(|| format_err!("missing maxFeePerGas"))?,
+                gas_limit: self.gas.as_u64(),
+                action,
+                value: self.value,
+                input: self.input,
+                access_list,
+            },
+            other => bail!("unsupported transaction type {}", other),
+        };
+
+        let signature = match self.secret_key {
+            Some(key) => {
+                let key = SecretKey::from_slice(key.as_bytes())?;
+                let (recovery_id, signature) = SECP256K1
+                    .sign_ecdsa_recoverable(
+                        &SecpMessage::from_slice(message.hash().as_bytes())?,
+                        &key,
+                    )
+                    .serialize_compact();
+                MessageSignature::new(
+                    recovery_id.to_i32() != 0,
+                    H256::from_slice(&signature[..32]),
+                    H256::from_slice(&signature[32..]),
+                )
+            }
+            None => MessageSignature::new(odd_y_parity, u256_to_h256(self.r), u256_to_h256(self.s)),
+        }
+        .ok_or_else(|| format_err!("invalid signature"))?;
+
+        Ok(MessageWithSignature { message, signature })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum Txs {
+    Json(Vec<TransactionJson>),
+    /// RLP list of signed transactions.
+    Rlp(#[serde(with = "hexbytes")] Bytes),
+}
+
+impl Default for Txs {
+    fn default() -> Self {
+        Self::Json(vec![])
+    }
+}
+
+impl Txs {
+    /// Signed transactions, or why each of them is invalid.
+    pub fn into_transactions(
+        self,
+        chain_id: ChainId,
+        eip155: bool,
+    ) -> anyhow::Result<Vec<anyhow::Result<MessageWithSignature>>> {
+        Ok(match self {
+            Self::Json(txs) => txs
+                .into_iter()
+                .map(|tx| tx.into_transaction(chain_id, eip155))
+                .collect(),
+            Self::Rlp(rlp) => rlp::Rlp::new(&rlp)
+                .as_list::<MessageWithSignature>()?
+                .into_iter()
+                .map(Ok)
+                .collect(),
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct TransitionLog {
+    pub address: Address,
+    pub topics: Vec<H256>,
+    #[serde(with = "hexbytes")]
+    pub data: Bytes,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransitionReceipt {
+    #[serde(rename = "type")]
+    pub tx_type: U64,
+    pub status: U64,
+    pub cumulative_gas_used: U64,
+    pub logs_bloom: Bloom,
+    pub logs: Vec<TransitionLog>,
+    pub transaction_hash: H256,
+    pub contract_address: Option<Address>,
+    pub gas_used: U64,
+    pub transaction_index: U64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct RejectedTransaction {
+    pub index: usize,
+    pub error: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransitionResult {
+    pub state_root: H256,
+    pub tx_root: H256,
+    pub receipts_root: H256,
+    pub logs_hash: H256,
+    pub logs_bloom: Bloom,
+    pub receipts: Vec<TransitionReceipt>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub rejected: Vec<RejectedTransaction>,
+    pub current_difficulty: U256,
+    pub gas_used: U64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub current_base_fee: Option<U256>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub withdrawals_root: Option<H256>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Transition {
+    pub alloc: Alloc,
+    pub result: TransitionResult,
+    /// EIP-3155 trace of every executed transaction, with its index and hash.
+    pub traces: Vec<(usize, H256, Vec<u8>)>,
+}
+
+/// Trace output shared with the tracer, so that it can be taken out after every transaction
+/// while the processor holds the tracer.
+#[derive(Clone, Debug, Default)]
+struct TraceBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl TraceBuffer {
+    fn take(&self) -> Vec<u8> {
+        std::mem::take(&mut *self.0.lock().unwrap())
+    }
+}
+
+impl Write for TraceBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Execute `txs` in the block described by `env` on top of `alloc`, tracing them with `trace`
+/// options if set.
+pub async fn transition(
+    chain_spec: &ChainSpec,
+    alloc: Alloc,
+    env: &Env,
+    txs: Txs,
+    trace: Option<StructLoggerConfig>,
+) -> anyhow::Result<Transition> {
+    let number = BlockNumber(env.current_number.as_u64());
+    let block_spec = chain_spec.collect_block_spec(number);
+    let chain_id = block_spec.params.chain_id;
+    let txs = txs.into_transactions(chain_id, block_spec.revision >= Revision::Spurious)?;
+
+    let mut state = InMemoryState::default();
+    for (address, account) in alloc {
+        let code_hash = if account.code.is_empty() {
+            EMPTY_HASH
+        } else {
+            let code_hash = keccak256(&account.code);
+            state.update_code(code_hash, account.code).await?;
+            code_hash
+        };
+        state.update_account(
+            address,
+            None,
+            Some(Account {
+                nonce: account.nonce.as_u64(),
+                balance: account.balance,
+                code_hash,
+            }),
+        );
+        for (location, value) in account.storage {
+            state
+                .update_storage(address, location, U256::ZERO, value)
+                .await?;
+        }
+    }
+
+    let mut block_hashes = BTreeMap::new();
+    for (block, &hash) in &env.block_hashes {
+        block_hashes.insert(parse_block_number(block)?, hash);
+    }
+    let parent_number = number.0.checked_sub(1).map(BlockNumber);
+    let parent_hash = env
+        .parent_hash
+        .or_else(|| parent_number.and_then(|n| block_hashes.get(&n).copied()))
+        .unwrap_or_default();
+    // Ancestors are found by walking back from the parent, which must be canonical.
+    block_hashes.extend(parent_number.map(|n| (n, parent_hash)));
+    for (block, hash) in block_hashes {
+        state.canonize_block(block, hash);
+    }
+
+    let base_fee_per_gas = if block_spec.revision >= Revision::London {
+        Some(
+            env.current_base_fee
+                .ok_or_else(|| format_err!("currentBaseFee is required since London"))?,
+        )
+    } else {
+        None
+    };
+    ensure!(
+        block_spec.revision < Revision::Shanghai || env.withdrawals.is_some(),
+        "withdrawals are required since Shanghai"
+    );
+
+    let header = PartialHeader {
+        parent_hash,
+        beneficiary: env.current_coinbase,
+        difficulty: env.current_difficulty,
+        number,
+        gas_limit: env.current_gas_limit.as_u64(),
+        timestamp: env.current_timestamp.as_u64(),
+        base_fee_per_gas,
+        ..PartialHeader::empty()
+    };
+    let body = BlockBodyWithSenders {
+        transactions: vec![],
+        ommers: env
+            .ommers
+            .iter()
+            .map(|ommer| {
+                BlockHeader::new(
+                    PartialHeader {
+                        number: BlockNumber(number.0.saturating_sub(ommer.delta)),
+                        beneficiary: ommer.address,
+                        ..PartialHeader::empty()
+                    },
+                    EMPTY_LIST_HASH,
+                    EMPTY_ROOT,
+                )
+            })
+            .collect(),
+        withdrawals: env
+            .withdrawals
+            .clone()
+            .filter(|_| block_spec.revision >= Revision::Shanghai),
+    };
+
+    let mut engine = engine_factory(chain_spec.clone())?;
+    let mut analysis_cache = AnalysisCache::default();
+    let trace_buffer = TraceBuffer::default();
+    let mut tracer = trace.map(|config| Eip3155Tracer::new(config, trace_buffer.clone()));
+
+    let mut processor = ExecutionProcessor::new(
+        &mut state,
+        tracer.as_mut().map(|tracer| tracer as &mut dyn Tracer),
+        &mut analysis_cache,
+        &mut *engine,
+        &header,
+        &body,
+        &block_spec,
+    );
+    processor.execute_block_prologue().await?;
+
+    let mut included = vec![];
+    let mut receipts = vec![];
+    let mut transition_receipts = vec![];
+    let mut rejected = vec![];
+    let mut traces = vec![];
+    for (index, txn) in txs.into_iter().enumerate() {
+        let validated = async {
+            let txn = txn?;
+            let sender = txn.recover_sender()?;
+            pre_validate_transaction(&txn.message, chain_id, base_fee_per_gas)?;
+            check_intrinsic_gas(&txn.message, block_spec.revision.into())?;
+            let with_sender = MessageWithSender {
+                message: txn.message.clone(),
+                sender,
+            };
+            processor.validate_transaction(&with_sender).await?;
+
+            Ok::<_, anyhow::Error>((txn, with_sender))
+        }
+        .await;
+        let (txn, with_sender) = match validated {
+            Ok(validated) => validated,
+            Err(e) => {
+                rejected.push(RejectedTransaction {
+                    index,
+                    error: e.to_string(),
+                });
+                continue;
+            }
+        };
+
+        let (receipt, result) = processor
+            .execute_transaction_with_output(&with_sender)
+            .await?;
+        let gas_used = receipt.cumulative_gas_used
+            - receipts
+                .last()
+                .map_or(0, |receipt: &Receipt| receipt.cumulative_gas_used);
+        let hash = txn.hash();
+
+        if trace.is_some() {
+            let mut out = trace_buffer.take();
+            serde_json::to_writer(&mut out, &Eip3155Summary::new(gas_used, &result))?;
+            out.push(b'\n');
+            traces.push((index, hash, out));
+        }
+
+        transition_receipts.push(TransitionReceipt {
+            tx_type: (txn.tx_type() as u64).into(),
+            status: u64::from(result.status_code == StatusCode::Success).into(),
+            cumulative_gas_used: receipt.cumulative_gas_used.into(),
+            logs_bloom: receipt.bloom,
+            logs: receipt
+                .logs
+                .iter()
+                .map(|log| TransitionLog {
+                    address: log.address,
+                    topics: log.topics.clone(),
+                    data: log.data.clone(),
+                })
+                .collect(),
+            transaction_hash: hash,
+            contract_address: matches!(txn.action(), TransactionAction::Create)
+                .then(|| create_address(with_sender.sender, txn.nonce())),
+            gas_used: gas_used.into(),
+            transaction_index: (included.len() as u64).into(),
+        });
+        receipts.push(receipt);
+        included.push(txn);
+    }
+
+    processor.execute_block_epilogue().await?;
+    processor.into_state().write_to_db(number).await?;
+
+    let mut out = Alloc::new();
+    for (address, account) in state.accounts() {
+        out.insert(
+            address,
+            AllocAccount {
+                balance: account.balance,
+                nonce: account.nonce.into(),
+                code: if account.code_hash == EMPTY_HASH {
+                    Bytes::new()
+                } else {
+                    state.read_code(account.code_hash).await?
+                },
+                storage: state.account_storage(address).collect(),
+            },
+        );
+    }
+
+    let logs = receipts
+        .iter()
+        .flat_map(|receipt| receipt.logs.iter().cloned())
+        .collect::<Vec<_>>();
+
+    Ok(Transition {
+        alloc: out,
+        result: TransitionResult {
+            state_root: state.state_root_hash(),
+            tx_root: root_hash(&included),
+            receipts_root: root_hash(&receipts),
+            logs_hash: keccak256(rlp::encode_list(&logs)),
+            logs_bloom: receipts
+                .iter()
+                .fold(Bloom::zero(), |bloom, receipt| bloom | receipt.bloom),
+            receipts: transition_receipts,
+            rejected,
+            current_difficulty: env.current_difficulty,
+            gas_used: receipts
+                .last()
+                .map_or(0, |receipt| receipt.cumulative_gas_used)
+                .into(),
+            current_base_fee: base_fee_per_gas,
+            withdrawals_root: body.withdrawals.as_deref().map(Block::withdrawals_root),
+        },
+        traces,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::test_util::run_test;
+    use hex_literal::hex;
+    use serde_json::json;
+
+    #[test]
+    fn transfers_and_rejects() {
+        run_test(async {
+            let sender = Address::from(hex!("a94f5374fce5edbc8e2a8697c15331677e6ebf0b"));
+            let recipient = Address::from(hex!("0000000000000000000000000000000000001000"));
+            let coinbase = Address::from(hex!("2adc25665018aa1fe0e6bc666dac8fc2697ff9ba"));
+
+            let alloc = serde_json::from_value::<Alloc>(json!({
+                "0xa94f5374fce5edbc8e2a8697c15331677e6ebf0b": {
+                    "balance": "0x5ffd4878be161d74",
+                    "nonce": "0x0",
+                },
+                "0x0000000000000000000000000000000000001000": {
+                    "balance": "0x0",
+                    // SSTORE(0, CALLVALUE)
+                    "code": "0x34600055",
+                },
+            }))
+            .unwrap();
+            let env = serde_json::from_value::<Env>(json!({
+                "currentCoinbase": "0x2adc25665018aa1fe0e6bc666dac8fc2697ff9ba",
+                "currentDifficulty": "0x20000",
+                "currentGasLimit": "0x750a163df65e8a",
+                "currentNumber": "0x1",
+                "currentTimestamp": "0x3e8",
+                "currentBaseFee": "0xa",
+                "blockHashes": { "0": "0x5e20a0453cecd065ea59c37ac63e079ee08998b6045136a8ce6635c7912ec0b6" },
+            }))
+            .unwrap();
+            let txs = serde_json::from_value::<Txs>(json!([
+                {
+                    "type": "0x2",
+                    "chainId": "0x1",
+                    "nonce": "0x0",
+                    "maxPriorityFeePerGas": "0x1",
+                    "maxFeePerGas": "0x20",
+                    "gas": "0x186a0",
+                    "to": "0x0000000000000000000000000000000000001000",
+                    "value": "0x2a",
+                    "input": "0x",
+                    "secretKey": "0x45a915e4d060149eb4365960e6a7a45f334393093061116b197e3240065ff2d8",
+                },
+                {
+                    // Nonce reused.
+                    "gasPrice": "0x20",
+                    "nonce": "0x0",
+                    "gas": "0x5208",
+                    "to": "0x0000000000000000000000000000000000001000",
+                    "value": "0x1",
+                    "input": "0x",
+                    "secretKey": "0x45a915e4d060149eb4365960e6a7a45f334393093061116b197e3240065ff2d8",
+                },
+            ]))
+            .unwrap();
+
+            let spec =
+                chain_spec("London", 1, Some(U256::from(2_000_000_000_000_000_000_u64))).unwrap();
+            let out = transition(&spec, alloc, &env, txs, Some(StructLoggerConfig::default()))
+                .await
+                .unwrap();
+
+            assert_eq!(out.result.receipts.len(), 1);
+            assert_eq!(out.result.receipts[0].status, U64::one());
+            assert_eq!(
+                out.result.rejected,
+                vec![RejectedTransaction {
+                    index: 1,
+                    error: out.result.rejected[0].error.clone(),
+                }]
+            );
+            assert!(out.result.rejected[0].error.contains("WrongNonce"));
+
+            assert_eq!(out.alloc[&sender].nonce, U64::one());
+            assert_eq!(
+                out.alloc[&recipient].storage,
+                [(U256::ZERO, U256::from(0x2a_u64))].into()
+            );
+            assert!(out.alloc.contains_key(&coinbase));
+
+            let gas_used = out.result.gas_used.as_u64();
+            assert!(gas_used > 21_000);
+            assert_eq!(
+                out.alloc[&sender].balance,
+                U256::from(0x5ffd4878be161d74_u64)
+                    - U256::from(0x2a_u64)
+                    - U256::from(gas_used) * U256::from(11_u64)
+            );
+
+            assert_eq!(out.traces.len(), 1);
+            let lines = String::from_utf8(out.traces[0].2.clone()).unwrap();
+            assert_eq!(
+                lines
+                    .lines()
+                    .filter_map(|line| serde_json::from_str::<serde_json::Value>(line)
+                        .unwrap()
+                        .get("opName")
+                        .cloned())
+                    .collect::<Vec<_>>(),
+                [json!("CALLVALUE"), json!("PUSH1"), json!("SSTORE")]
+            );
+        })
+    }
+}
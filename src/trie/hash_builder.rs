@@ -13,7 +13,7 @@ use std::{boxed::Box, cmp};
 
 const RLP_EMPTY_STRING_CODE: u8 = 0x80;
 
-fn encode_path(nibbles: &[u8], terminating: bool) -> Vec<u8> {
+pub(crate) fn encode_path(nibbles: &[u8], terminating: bool) -> Vec<u8> {
     let mut res = vec![0u8; nibbles.len() / 2 + 1];
     let odd = nibbles.len() % 2 != 0;
     let mut i = 0usize;
@@ -555,6 +555,40 @@ where
     do_increment_intermediate_hashes(txn, etl_dir, expected_root, &mut empty).await
 }
 
+/// Hash of the node at `path` in nibbles of the account trie, or of the storage trie of the account
+/// with `hashed_address`, if it is kept in intermediate hashes.
+pub async fn stored_node_hash<'db, Tx>(
+    txn: &Tx,
+    hashed_address: Option<H256>,
+    path: &[u8],
+) -> Result<Option<H256>>
+where
+    Tx: Transaction<'db>,
+{
+    let prefix = hashed_address
+        .map(|a| a.as_bytes().to_vec())
+        .unwrap_or_default();
+    let (parent, nibble) = match path.split_last() {
+        Some((&nibble, parent)) => (parent, Some(nibble)),
+        None => (path, None),
+    };
+    let key = [prefix.as_slice(), parent].concat();
+    let node = match hashed_address {
+        Some(_) => txn.get(tables::TrieStorage, key).await?,
+        None => txn.get(tables::TrieAccount, key).await?,
+    }
+    .and_then(|v| unmarshal_node(&v));
+
+    Ok(node.and_then(|node| match nibble {
+        None => node.root_hash(),
+        Some(nibble) => {
+            let bit = 1u16 << nibble;
+            (node.hash_mask() & bit != 0)
+                .then(|| node.hashes()[(node.hash_mask() & (bit - 1)).count_ones() as usize])
+        }
+    }))
+}
+
 #[cfg(test)]
 mod tests {
     use std::collections::HashMap;
@@ -1,7 +1,18 @@
 mod hash_builder;
+#[cfg(feature = "node")]
 mod intermediate_hashes;
 mod node;
 mod prefix_set;
+pub mod proof;
+#[cfg(feature = "node")]
+mod state_root;
 mod util;
 
-pub use intermediate_hashes::{increment_intermediate_hashes, regenerate_intermediate_hashes};
+#[cfg(feature = "node")]
+pub use intermediate_hashes::{
+    increment_intermediate_hashes, regenerate_intermediate_hashes, stored_node_hash,
+};
+#[cfg(feature = "node")]
+pub use state_root::{
+    compute_state_root, compute_state_root_parallel, compute_subtrie_root, first_divergent_path,
+};
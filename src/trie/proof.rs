@@ -0,0 +1,762 @@
+use crate::{
+    crypto::keccak256,
+    h256_to_u256,
+    models::*,
+    trie::{
+        hash_builder::{encode_path, pack_nibbles, unpack_nibbles},
+        util::prefix_length,
+    },
+    u256_to_h256,
+};
+use anyhow::{bail, ensure, format_err};
+use rlp::{Rlp, RlpStream};
+use std::{
+    cmp::{min, Ordering},
+    collections::HashMap,
+};
+
+/// Partially resolved trie, built from proof nodes.
+#[derive(Clone, Debug)]
+enum Node {
+    Empty,
+    Hash(H256),
+    Leaf(Vec<u8>, Vec<u8>),
+    Extension(Vec<u8>, Box<Node>),
+    Branch(Box<[Node; 16]>),
+}
+
+impl Default for Node {
+    fn default() -> Self {
+        Self::Empty
+    }
+}
+
+fn decode_path(encoded: &[u8]) -> anyhow::Result<(Vec<u8>, bool)> {
+    let (&first, rest) = encoded
+        .split_first()
+        .ok_or_else(|| format_err!("empty node path"))?;
+    let flag = first >> 4;
+    ensure!(flag <= 3, "invalid node path flag {}", flag);
+
+    let mut nibbles = unpack_nibbles(rest);
+    if flag & 1 != 0 {
+        nibbles.insert(0, first & 0x0F);
+    } else {
+        ensure!(first & 0x0F == 0, "invalid node path padding");
+    }
+
+    Ok((nibbles, flag & 2 != 0))
+}
+
+fn decode_child(rlp: &Rlp) -> anyhow::Result<Node> {
+    if rlp.is_list() {
+        return decode_node(rlp.as_raw());
+    }
+
+    let data = rlp.data()?;
+    Ok(match data.len() {
+        0 => Node::Empty,
+        KECCAK_LENGTH => Node::Hash(H256::from_slice(data)),
+        len => bail!("invalid child reference of length {}", len),
+    })
+}
+
+fn decode_node(data: &[u8]) -> anyhow::Result<Node> {
+    let rlp = Rlp::new(data);
+    Ok(match rlp.item_count()? {
+        2 => {
+            let (path, is_leaf) = decode_path(rlp.at(0)?.data()?)?;
+            if is_leaf {
+                Node::Leaf(path, rlp.at(1)?.data()?.to_vec())
+            } else {
+                Node::Extension(path, Box::new(decode_child(&rlp.at(1)?)?))
+            }
+        }
+        17 => {
+            let mut children = Box::<[Node; 16]>::default();
+            for (i, child) in children.iter_mut().enumerate() {
+                *child = decode_child(&rlp.at(i)?)?;
+            }
+            // Keys in state tries are of equal length, so branches never have values.
+            ensure!(
+                rlp.at(16)?.data()?.is_empty(),
+                "unexpected value in branch node"
+            );
+            Node::Branch(children)
+        }
+        items => bail!("invalid trie node with {} items", items),
+    })
+}
+
+fn append_child(s: &mut RlpStream, child: &Node) {
+    match child {
+        Node::Empty => {
+            s.append_empty_data();
+        }
+        Node::Hash(hash) => {
+            s.append(hash);
+        }
+        _ => {
+            let rlp = encode_node(child);
+            if rlp.len() < KECCAK_LENGTH {
+                s.append_raw(&rlp, 1);
+            } else {
+                s.append(&keccak256(&rlp));
+            }
+        }
+    }
+}
+
+fn encode_node(node: &Node) -> Vec<u8> {
+    let mut s;
+    match node {
+        Node::Empty | Node::Hash(_) => unreachable!("only resolved nodes can be encoded"),
+        Node::Leaf(key, value) => {
+            s = RlpStream::new_list(2);
+            s.append(&encode_path(key, true));
+            s.append(value);
+        }
+        Node::Extension(key, child) => {
+            s = RlpStream::new_list(2);
+            s.append(&encode_path(key, false));
+            append_child(&mut s, child);
+        }
+        Node::Branch(children) => {
+            s = RlpStream::new_list(17);
+            for child in children.iter() {
+                append_child(&mut s, child);
+            }
+            s.append_empty_data();
+        }
+    }
+    s.out().to_vec()
+}
+
+fn root_hash(node: &Node) -> H256 {
+    match node {
+        Node::Empty => EMPTY_ROOT,
+        Node::Hash(hash) => *hash,
+        _ => keccak256(encode_node(node)),
+    }
+}
+
+fn proof_nodes<T: AsRef<[u8]>>(proof: &[T]) -> HashMap<H256, &[u8]> {
+    proof
+        .iter()
+        .map(|node| (keccak256(node), node.as_ref()))
+        .collect()
+}
+
+/// Resolve nodes along the path from the proof. Resolution stops where the proof ends.
+fn expand(node: &mut Node, path: &[u8], nodes: &HashMap<H256, &[u8]>) -> anyhow::Result<()> {
+    if let Node::Hash(hash) = *node {
+        match nodes.get(&hash) {
+            Some(data) => *node = decode_node(data)?,
+            None => return Ok(()),
+        }
+    }
+
+    match node {
+        Node::Extension(key, child) if path.starts_with(key) => {
+            expand(child, &path[key.len()..], nodes)
+        }
+        Node::Branch(children) => match path.split_first() {
+            Some((&first, rest)) => expand(&mut children[usize::from(first)], rest, nodes),
+            None => Ok(()),
+        },
+        _ => Ok(()),
+    }
+}
+
+fn lookup(node: &Node, path: &[u8]) -> anyhow::Result<Option<Vec<u8>>> {
+    match node {
+        Node::Empty => Ok(None),
+        Node::Hash(hash) => bail!("proof node {:?} missing", hash),
+        Node::Leaf(key, value) => Ok((key == path).then(|| value.clone())),
+        Node::Extension(key, child) => {
+            if path.starts_with(key) {
+                lookup(child, &path[key.len()..])
+            } else {
+                Ok(None)
+            }
+        }
+        Node::Branch(children) => match path.split_first() {
+            Some((&first, rest)) => lookup(&children[usize::from(first)], rest),
+            None => Ok(None),
+        },
+    }
+}
+
+fn insert(node: &mut Node, path: &[u8], value: Vec<u8>) -> anyhow::Result<()> {
+    fn split(
+        common: &[u8],
+        existing: (u8, Node),
+        path: &[u8],
+        value: Vec<u8>,
+    ) -> anyhow::Result<Node> {
+        let (&first, rest) = path
+            .split_first()
+            .ok_or_else(|| format_err!("keys of different length"))?;
+
+        let mut children = Box::<[Node; 16]>::default();
+        children[usize::from(existing.0)] = existing.1;
+        children[usize::from(first)] = Node::Leaf(rest.to_vec(), value);
+
+        let branch = Node::Branch(children);
+        Ok(if common.is_empty() {
+            branch
+        } else {
+            Node::Extension(common.to_vec(), Box::new(branch))
+        })
+    }
+
+    *node = match std::mem::take(node) {
+        Node::Empty => Node::Leaf(path.to_vec(), value),
+        Node::Hash(hash) => bail!("key is not covered by proof, node {:?} missing", hash),
+        Node::Leaf(key, existing_value) => {
+            if key == path {
+                Node::Leaf(key, value)
+            } else {
+                let common = prefix_length(&key, path);
+                ensure!(common < key.len(), "keys of different length");
+                split(
+                    &key[..common],
+                    (
+                        key[common],
+                        Node::Leaf(key[common + 1..].to_vec(), existing_value),
+                    ),
+                    &path[common..],
+                    value,
+                )?
+            }
+        }
+        Node::Extension(key, mut child) => {
+            let common = prefix_length(&key, path);
+            if common == key.len() {
+                insert(&mut child, &path[common..], value)?;
+                Node::Extension(key, child)
+            } else {
+                let rest = if common + 1 == key.len() {
+                    *child
+                } else {
+                    Node::Extension(key[common + 1..].to_vec(), child)
+                };
+                split(&key[..common], (key[common], rest), &path[common..], value)?
+            }
+        }
+        Node::Branch(mut children) => {
+            let (&first, rest) = path
+                .split_first()
+                .ok_or_else(|| format_err!("keys of different length"))?;
+            insert(&mut children[usize::from(first)], rest, value)?;
+            Node::Branch(children)
+        }
+    };
+
+    Ok(())
+}
+
+/// Narrow a range bound down to a subtree at `prefix`.
+/// Returns `None` if the subtree lies outside of the bound, `Some(None)` if it lies entirely inside.
+fn narrow<'a>(
+    prefix: &[u8],
+    bound: Option<&'a [u8]>,
+    outside: Ordering,
+) -> Option<Option<&'a [u8]>> {
+    let Some(bound) = bound else {
+        return Some(None);
+    };
+
+    let n = min(prefix.len(), bound.len());
+    match prefix.cmp(&bound[..n]) {
+        Ordering::Equal => Some(Some(&bound[n..])),
+        ord if ord == outside => None,
+        _ => Some(None),
+    }
+}
+
+/// Remove all entries with keys in `lo..=hi` from the trie, `None` meaning unbounded.
+fn remove_range(node: &mut Node, lo: Option<&[u8]>, hi: Option<&[u8]>) -> anyhow::Result<()> {
+    *node = match std::mem::take(node) {
+        Node::Empty => Node::Empty,
+        Node::Hash(hash) => {
+            if lo.is_some() || hi.is_some() {
+                bail!(
+                    "range boundary is not covered by proof, node {:?} missing",
+                    hash
+                );
+            }
+            Node::Empty
+        }
+        Node::Leaf(key, value) => {
+            if lo.map_or(true, |lo| key.as_slice() >= lo)
+                && hi.map_or(true, |hi| key.as_slice() <= hi)
+            {
+                Node::Empty
+            } else {
+                Node::Leaf(key, value)
+            }
+        }
+        Node::Extension(key, mut child) => {
+            if let (Some(lo), Some(hi)) = (
+                narrow(&key, lo, Ordering::Less),
+                narrow(&key, hi, Ordering::Greater),
+            ) {
+                remove_range(&mut child, lo, hi)?;
+            }
+
+            if let Node::Empty = *child {
+                Node::Empty
+            } else {
+                Node::Extension(key, child)
+            }
+        }
+        Node::Branch(mut children) => {
+            for (i, child) in children.iter_mut().enumerate() {
+                let prefix = [i as u8];
+                if let (Some(lo), Some(hi)) = (
+                    narrow(&prefix, lo, Ordering::Less),
+                    narrow(&prefix, hi, Ordering::Greater),
+                ) {
+                    remove_range(child, lo, hi)?;
+                }
+            }
+
+            if children.iter().all(|child| matches!(child, Node::Empty)) {
+                Node::Empty
+            } else {
+                Node::Branch(children)
+            }
+        }
+    };
+
+    Ok(())
+}
+
+/// Whether the trie has entries with keys greater than `path`.
+fn has_right_element(node: &Node, path: &[u8]) -> bool {
+    match node {
+        Node::Empty => false,
+        Node::Hash(_) => true,
+        Node::Leaf(key, _) => key.as_slice() > path,
+        Node::Extension(key, child) => {
+            let n = min(key.len(), path.len());
+            match key.as_slice().cmp(&path[..n]) {
+                Ordering::Less => false,
+                Ordering::Greater => true,
+                Ordering::Equal => has_right_element(child, &path[n..]),
+            }
+        }
+        Node::Branch(children) => match path.split_first() {
+            Some((&first, rest)) => {
+                let first = usize::from(first);
+                children[first + 1..]
+                    .iter()
+                    .any(|child| !matches!(child, Node::Empty))
+                    || has_right_element(&children[first], rest)
+            }
+            None => children.iter().any(|child| !matches!(child, Node::Empty)),
+        },
+    }
+}
+
+/// Verify Merkle proof for `key` and return the value stored under it, or `None` if proof shows its absence.
+pub fn verify_proof<T: AsRef<[u8]>>(
+    root: H256,
+    key: H256,
+    proof: &[T],
+) -> anyhow::Result<Option<Vec<u8>>> {
+    let path = unpack_nibbles(key.as_bytes());
+    let mut trie = Node::Hash(root);
+    if root == EMPTY_ROOT {
+        trie = Node::Empty;
+    }
+    expand(&mut trie, &path, &proof_nodes(proof))?;
+
+    lookup(&trie, &path)
+}
+
+/// Verify `eth_getProof`-style account proof against the state root.
+pub fn verify_account_proof<T: AsRef<[u8]>>(
+    state_root: H256,
+    address: Address,
+    proof: &[T],
+) -> anyhow::Result<Option<RlpAccount>> {
+    verify_proof(state_root, keccak256(address), proof)?
+        .map(|v| rlp::decode(&v).map_err(From::from))
+        .transpose()
+}
+
+/// Verify storage proof against the storage root of the account.
+pub fn verify_storage_proof<T: AsRef<[u8]>>(
+    storage_root: H256,
+    location: H256,
+    proof: &[T],
+) -> anyhow::Result<U256> {
+    Ok(
+        match verify_proof(storage_root, keccak256(location), proof)? {
+            Some(v) => rlp::decode(&v)?,
+            None => U256::ZERO,
+        },
+    )
+}
+
+/// Verify that `keys` and `values` are all trie entries starting from `first_key` up to the last key,
+/// as returned in snap protocol range responses. `proof` should contain nodes proving `first_key`
+/// and the last key; if it is empty, entries must form the whole trie.
+///
+/// Returns whether the trie has more entries after the last key.
+pub fn verify_range_proof<V: AsRef<[u8]>, T: AsRef<[u8]>>(
+    root: H256,
+    first_key: H256,
+    keys: &[H256],
+    values: &[V],
+    proof: &[T],
+) -> anyhow::Result<bool> {
+    ensure!(
+        keys.len() == values.len(),
+        "{} keys but {} values",
+        keys.len(),
+        values.len()
+    );
+    ensure!(
+        keys.windows(2).all(|w| w[0] < w[1]),
+        "keys are not strictly increasing"
+    );
+    ensure!(
+        keys.first().map_or(true, |&key| key >= first_key),
+        "keys start before requested origin"
+    );
+    ensure!(
+        values.iter().all(|v| !v.as_ref().is_empty()),
+        "empty values are not allowed"
+    );
+
+    if proof.is_empty() {
+        let mut trie = Node::Empty;
+        for (key, value) in keys.iter().zip(values) {
+            insert(
+                &mut trie,
+                &unpack_nibbles(key.as_bytes()),
+                value.as_ref().to_vec(),
+            )?;
+        }
+        let computed = root_hash(&trie);
+        ensure!(
+            computed == root,
+            "root mismatch: expected {:?}, computed {:?}",
+            root,
+            computed
+        );
+        return Ok(false);
+    }
+
+    let nodes = proof_nodes(proof);
+    let first = unpack_nibbles(first_key.as_bytes());
+    let last = keys.last().map(|key| unpack_nibbles(key.as_bytes()));
+
+    let mut trie = Node::Hash(root);
+    expand(&mut trie, &first, &nodes)?;
+    if let Some(last) = &last {
+        expand(&mut trie, last, &nodes)?;
+    }
+
+    let has_more = match &last {
+        Some(last) => has_right_element(&trie, last),
+        None => false,
+    };
+
+    // Everything within the range is replaced with the provided entries, the rest is proven by hashes.
+    remove_range(&mut trie, Some(&first), last.as_deref())?;
+    for (key, value) in keys.iter().zip(values) {
+        insert(
+            &mut trie,
+            &unpack_nibbles(key.as_bytes()),
+            value.as_ref().to_vec(),
+        )?;
+    }
+
+    let computed = root_hash(&trie);
+    ensure!(
+        computed == root,
+        "root mismatch: expected {:?}, computed {:?}",
+        root,
+        computed
+    );
+
+    Ok(has_more)
+}
+
+/// What a trie node fetched while healing says about keys under its path.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum NodeReference {
+    /// Subtree at the path, given in nibbles from the trie root, is the node with this hash.
+    Hash(Vec<u8>, H256),
+    /// Entry with this key and value.
+    Leaf(H256, Vec<u8>),
+    /// There are no entries with keys in this inclusive range.
+    Absent(H256, H256),
+}
+
+fn pad_path(path: &[u8], nibble: u8) -> H256 {
+    let mut nibbles = path.to_vec();
+    nibbles.resize(KECCAK_LENGTH * 2, nibble);
+    H256::from_slice(&pack_nibbles(&nibbles))
+}
+
+/// Push ranges of keys under `path` other than those under `inner`, which starts with `path`.
+fn push_absent_around(path: &[u8], inner: &[u8], out: &mut Vec<NodeReference>) {
+    let (lo, hi) = (pad_path(path, 0), pad_path(path, 0xf));
+    let (inner_lo, inner_hi) = (pad_path(inner, 0), pad_path(inner, 0xf));
+    if lo < inner_lo {
+        out.push(NodeReference::Absent(
+            lo,
+            u256_to_h256(h256_to_u256(inner_lo) - U256::ONE),
+        ));
+    }
+    if inner_hi < hi {
+        out.push(NodeReference::Absent(
+            u256_to_h256(h256_to_u256(inner_hi) + U256::ONE),
+            hi,
+        ));
+    }
+}
+
+fn collect_references(
+    path: Vec<u8>,
+    node: Node,
+    out: &mut Vec<NodeReference>,
+) -> anyhow::Result<()> {
+    match node {
+        Node::Empty => out.push(NodeReference::Absent(
+            pad_path(&path, 0),
+            pad_path(&path, 0xf),
+        )),
+        Node::Hash(hash) => out.push(NodeReference::Hash(path, hash)),
+        Node::Leaf(key, value) => {
+            let key = [path.as_slice(), &key].concat();
+            ensure!(
+                key.len() == KECCAK_LENGTH * 2,
+                "leaf key of {} nibbles",
+                key.len()
+            );
+            push_absent_around(&path, &key, out);
+            out.push(NodeReference::Leaf(pad_path(&key, 0), value));
+        }
+        Node::Extension(key, child) => {
+            let child_path = [path.as_slice(), &key].concat();
+            ensure!(
+                child_path.len() < KECCAK_LENGTH * 2,
+                "extension past key length"
+            );
+            push_absent_around(&path, &child_path, out);
+            collect_references(child_path, *child, out)?;
+        }
+        Node::Branch(children) => {
+            ensure!(path.len() < KECCAK_LENGTH * 2, "branch past key length");
+            for (i, child) in IntoIterator::into_iter(*children).enumerate() {
+                collect_references([path.as_slice(), &[i as u8]].concat(), child, out)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Decode trie node at `path` of a state or storage trie, fetched while healing, resolving
+/// children embedded in it.
+pub fn node_references(path: &[u8], data: &[u8]) -> anyhow::Result<Vec<NodeReference>> {
+    let mut out = vec![];
+    collect_references(path.to_vec(), decode_node(data)?, &mut out)?;
+    Ok(out)
+}
+
+/// Encoded nodes of trie with `entries` by their paths, as served to peers healing their state.
+#[cfg(test)]
+pub(crate) fn nodes_by_path(entries: &[(H256, Vec<u8>)]) -> HashMap<Vec<u8>, Vec<u8>> {
+    fn collect(path: Vec<u8>, node: &Node, out: &mut HashMap<Vec<u8>, Vec<u8>>) {
+        let rlp = encode_node(node);
+        match node {
+            Node::Extension(key, child) => collect([path.as_slice(), key].concat(), child, out),
+            Node::Branch(children) => {
+                for (i, child) in children.iter().enumerate() {
+                    if !matches!(child, Node::Empty) {
+                        collect([path.as_slice(), &[i as u8]].concat(), child, out);
+                    }
+                }
+            }
+            _ => {}
+        }
+        out.insert(path, rlp);
+    }
+
+    let mut trie = Node::Empty;
+    for (key, value) in entries {
+        insert(&mut trie, &unpack_nibbles(key.as_bytes()), value.clone()).unwrap();
+    }
+    let mut out = HashMap::new();
+    if !matches!(trie, Node::Empty) {
+        collect(vec![], &trie, &mut out);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::trie_root;
+
+    fn build(entries: &[(H256, Vec<u8>)]) -> Node {
+        let mut trie = Node::Empty;
+        for (key, value) in entries {
+            insert(&mut trie, &unpack_nibbles(key.as_bytes()), value.clone()).unwrap();
+        }
+        trie
+    }
+
+    fn prove(node: &Node, path: &[u8], proof: &mut Vec<Vec<u8>>) {
+        match node {
+            Node::Empty | Node::Hash(_) => {}
+            Node::Leaf(..) => proof.push(encode_node(node)),
+            Node::Extension(key, child) => {
+                proof.push(encode_node(node));
+                if path.starts_with(key) {
+                    prove(child, &path[key.len()..], proof);
+                }
+            }
+            Node::Branch(children) => {
+                proof.push(encode_node(node));
+                if let Some((&first, rest)) = path.split_first() {
+                    prove(&children[usize::from(first)], rest, proof);
+                }
+            }
+        }
+    }
+
+    fn entries(n: u64) -> Vec<(H256, Vec<u8>)> {
+        let mut entries = (0..n)
+            .map(|i| {
+                (
+                    keccak256(H256::from_low_u64_be(i)),
+                    rlp::encode(&(i + 1)).to_vec(),
+                )
+            })
+            .collect::<Vec<_>>();
+        entries.sort();
+        entries
+    }
+
+    #[test]
+    fn trie_root_matches() {
+        for n in [0, 1, 2, 3, 17, 100] {
+            let entries = entries(n);
+            assert_eq!(root_hash(&build(&entries)), trie_root(entries.clone()));
+        }
+    }
+
+    #[test]
+    fn single_key_proof() {
+        let entries = entries(100);
+        let trie = build(&entries);
+        let root = root_hash(&trie);
+
+        for (key, value) in &entries[..10] {
+            let mut proof = vec![];
+            prove(&trie, &unpack_nibbles(key.as_bytes()), &mut proof);
+            assert_eq!(
+                verify_proof(root, *key, &proof).unwrap(),
+                Some(value.clone())
+            );
+
+            assert!(verify_proof(H256::repeat_byte(0xAA), *key, &proof).is_err());
+        }
+
+        let absent = keccak256(H256::from_low_u64_be(1000));
+        let mut proof = vec![];
+        prove(&trie, &unpack_nibbles(absent.as_bytes()), &mut proof);
+        assert_eq!(verify_proof(root, absent, &proof).unwrap(), None);
+
+        assert_eq!(
+            verify_proof::<Vec<u8>>(EMPTY_ROOT, absent, &[]).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn range_proof() {
+        let entries = entries(100);
+        let trie = build(&entries);
+        let root = root_hash(&trie);
+
+        let (keys, values): (Vec<_>, Vec<_>) = entries.iter().cloned().unzip();
+
+        let range_proof = |from: usize, to: usize| {
+            let mut proof = vec![];
+            prove(&trie, &unpack_nibbles(keys[from].as_bytes()), &mut proof);
+            prove(&trie, &unpack_nibbles(keys[to - 1].as_bytes()), &mut proof);
+            proof
+        };
+
+        let proof = range_proof(10, 20);
+        assert!(
+            verify_range_proof(root, keys[10], &keys[10..20], &values[10..20], &proof).unwrap()
+        );
+
+        // Gap in the middle of the range.
+        let mut gapped_keys = keys[10..20].to_vec();
+        let mut gapped_values = values[10..20].to_vec();
+        gapped_keys.remove(5);
+        gapped_values.remove(5);
+        assert!(verify_range_proof(root, keys[10], &gapped_keys, &gapped_values, &proof).is_err());
+
+        // Tampered value.
+        let mut tampered_values = values[10..20].to_vec();
+        tampered_values[3] = vec![0x42];
+        assert!(
+            verify_range_proof(root, keys[10], &keys[10..20], &tampered_values, &proof).is_err()
+        );
+
+        let proof = range_proof(90, 100);
+        assert!(!verify_range_proof(root, keys[90], &keys[90..], &values[90..], &proof).unwrap());
+
+        assert!(
+            !verify_range_proof::<_, Vec<u8>>(root, H256::zero(), &keys, &values, &[]).unwrap()
+        );
+        assert!(verify_range_proof::<_, Vec<u8>>(
+            root,
+            H256::zero(),
+            &keys[1..],
+            &values[1..],
+            &[]
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn healing_references() {
+        let entries = entries(20);
+        let nodes = nodes_by_path(&entries);
+        assert_eq!(keccak256(&nodes[&vec![]]), root_hash(&build(&entries)));
+
+        // Walk the trie from the root as healing does, following references by hash.
+        let mut queue = vec![vec![]];
+        let mut leaves = vec![];
+        while let Some(path) = queue.pop() {
+            for reference in node_references(&path, &nodes[&path]).unwrap() {
+                match reference {
+                    NodeReference::Hash(path, hash) => {
+                        assert_eq!(keccak256(&nodes[&path]), hash);
+                        queue.push(path);
+                    }
+                    NodeReference::Leaf(key, value) => leaves.push((key, value)),
+                    NodeReference::Absent(lo, hi) => {
+                        assert!(lo <= hi);
+                        assert!(!entries.iter().any(|(key, _)| lo <= *key && *key <= hi));
+                    }
+                }
+            }
+        }
+        leaves.sort();
+        assert_eq!(leaves, entries);
+    }
+}
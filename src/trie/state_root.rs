@@ -0,0 +1,213 @@
+//! State root computed from hashed state alone, without intermediate hashes, to check them and
+//! the state against each other.
+
+use crate::{
+    kv::{tables, traits::*},
+    models::*,
+    trie::{
+        hash_builder::{pack_nibbles, unpack_nibbles, HashBuilder},
+        intermediate_hashes::stored_node_hash,
+    },
+};
+use anyhow::Result;
+use async_recursion::async_recursion;
+use std::sync::Arc;
+
+async fn storage_root<'db, Tx: Transaction<'db>>(txn: &Tx, hashed_address: H256) -> Result<H256> {
+    let mut state = txn.cursor_dup_sort(tables::HashedStorage).await?;
+    let mut hb = HashBuilder::new();
+
+    let mut storage = state.seek_exact(hashed_address).await?.map(|(_, v)| v);
+    while let Some((location, value)) = storage {
+        hb.add_leaf(
+            unpack_nibbles(location.as_bytes()),
+            rlp::encode(&value).as_ref(),
+        );
+        storage = state.next_dup().await?.map(|(_, v)| v);
+    }
+
+    Ok(hb.root_hash())
+}
+
+/// Hash of the node at `path` in nibbles of the account trie, or `None` if no account is
+/// under `path`.
+pub async fn compute_subtrie_root<'db, Tx: Transaction<'db>>(
+    txn: &Tx,
+    path: &[u8],
+) -> Result<Option<H256>> {
+    let mut seek_key = path.to_vec();
+    seek_key.resize(64, 0);
+
+    let mut state = txn.cursor(tables::HashedAccount).await?;
+    let mut hb = HashBuilder::new();
+    let mut empty = true;
+
+    let mut acc = state
+        .seek(H256::from_slice(&pack_nibbles(&seek_key)))
+        .await?;
+    while let Some((hashed_address, account)) = acc {
+        let unpacked_key = unpack_nibbles(hashed_address.as_bytes());
+        if !unpacked_key.starts_with(path) {
+            break;
+        }
+
+        let storage_root = storage_root(txn, hashed_address).await?;
+        hb.add_leaf(
+            unpacked_key[path.len()..].to_vec(),
+            rlp::encode(&account.to_rlp(storage_root)).as_ref(),
+        );
+        empty = false;
+
+        acc = state.next().await?;
+    }
+
+    Ok((!empty).then(|| hb.root_hash()))
+}
+
+/// State root computed from hashed state, ignoring intermediate hashes.
+pub async fn compute_state_root<'db, Tx: Transaction<'db>>(txn: &Tx) -> Result<H256> {
+    Ok(compute_subtrie_root(txn, &[]).await?.unwrap_or(EMPTY_ROOT))
+}
+
+/// Same as [`compute_state_root`], with the 16 subtries under the root computed on the blocking
+/// thread pool in parallel.
+pub async fn compute_state_root_parallel<Tx>(txn: Arc<Tx>) -> Result<H256>
+where
+    Tx: Transaction<'static> + 'static,
+{
+    let handle = tokio::runtime::Handle::current();
+    let tasks = (0..16_u8)
+        .map(|nibble| {
+            let txn = txn.clone();
+            let handle = handle.clone();
+            tokio::task::spawn_blocking(move || {
+                handle.block_on(compute_subtrie_root(&*txn, &[nibble]))
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let mut subtries = vec![];
+    for (nibble, task) in (0..16_u8).zip(tasks) {
+        if let Some(hash) = task.await?? {
+            subtries.push((nibble, hash));
+        }
+    }
+
+    // Root is a branch only if accounts are under at least two nibbles, otherwise it is
+    // a leaf or an extension, which have to be built from the whole key.
+    if subtries.len() < 2 {
+        return compute_state_root(&*txn).await;
+    }
+
+    let mut hb = HashBuilder::new();
+    for (nibble, hash) in subtries {
+        hb.add_branch_node(vec![nibble], &hash, false);
+    }
+
+    Ok(hb.root_hash())
+}
+
+/// Deepest path in nibbles of the account trie under `path` whose intermediate hash does not
+/// match hashed state, or `None` if all kept hashes match.
+#[async_recursion]
+pub async fn first_divergent_path<'db, Tx>(txn: &Tx, path: Vec<u8>) -> Result<Option<Vec<u8>>>
+where
+    Tx: Transaction<'db>,
+{
+    for nibble in 0..16 {
+        let mut child = path.clone();
+        child.push(nibble);
+
+        match stored_node_hash(txn, None, &child).await? {
+            Some(stored) => {
+                if compute_subtrie_root(txn, &child).await? != Some(stored) {
+                    return Ok(Some(
+                        first_divergent_path(txn, child.clone())
+                            .await?
+                            .unwrap_or(child),
+                    ));
+                }
+            }
+            // Hash of the root and its children is not kept, look further down.
+            None => {
+                if txn.get(tables::TrieAccount, child.clone()).await?.is_some() {
+                    if let Some(found) = first_divergent_path(txn, child).await? {
+                        return Ok(Some(found));
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{crypto::keccak256, kv::new_mem_database, trie::regenerate_intermediate_hashes};
+
+    #[tokio::test]
+    async fn matches_intermediate_hashes() {
+        let db: &'static _ = Box::leak(Box::new(new_mem_database().unwrap()));
+        let txn = db.begin_mutable().await.unwrap();
+
+        let accounts = (0..2000_u64)
+            .map(|i| {
+                (
+                    keccak256(H256::from_low_u64_be(i)),
+                    Account {
+                        nonce: i,
+                        balance: (i * 1000).as_u256(),
+                        ..Default::default()
+                    },
+                )
+            })
+            .collect::<Vec<_>>();
+        for &(hashed_address, account) in &accounts {
+            txn.set(tables::HashedAccount, hashed_address, account)
+                .await
+                .unwrap();
+        }
+        txn.set(
+            tables::HashedStorage,
+            accounts[0].0,
+            (keccak256(H256::zero()), 42.as_u256()),
+        )
+        .await
+        .unwrap();
+
+        let etl_dir = tempfile::tempdir().unwrap();
+        let root = regenerate_intermediate_hashes(&txn, &etl_dir, None)
+            .await
+            .unwrap();
+        assert_eq!(compute_state_root(&txn).await.unwrap(), root);
+        assert_eq!(first_divergent_path(&txn, vec![]).await.unwrap(), None);
+        txn.commit().await.unwrap();
+
+        assert_eq!(
+            compute_state_root_parallel(Arc::new(db.begin().await.unwrap()))
+                .await
+                .unwrap(),
+            root
+        );
+
+        let txn = db.begin_mutable().await.unwrap();
+
+        let (hashed_address, account) = accounts[7];
+        txn.set(
+            tables::HashedAccount,
+            hashed_address,
+            Account {
+                nonce: account.nonce + 1,
+                ..account
+            },
+        )
+        .await
+        .unwrap();
+        assert_ne!(compute_state_root(&txn).await.unwrap(), root);
+        let path = first_divergent_path(&txn, vec![]).await.unwrap().unwrap();
+        assert!(unpack_nibbles(hashed_address.as_bytes()).starts_with(&path));
+        assert!(path.len() >= 2);
+    }
+}
@@ -0,0 +1,212 @@
+//! Admission rules applied to transactions before they enter the pool.
+//!
+//! Rules only look at the transaction itself and the current nonce of its sender, so they are
+//! cheap enough to run on every transaction received from peers or RPC.
+
+use crate::{crypto::TrieEncode, models::*};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashSet, fmt};
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct AdmissionPolicy {
+    /// Minimum `max_priority_fee_per_gas` of typed transactions.
+    pub min_priority_fee: U256,
+    /// Minimum gas price of legacy transactions.
+    pub min_gas_price: U256,
+    /// How far ahead of the sender's current nonce a transaction may be.
+    pub max_nonce_gap: Option<u64>,
+    /// Maximum size of the signed transaction in bytes.
+    pub max_size: Option<usize>,
+    /// If set, only transactions from these senders are admitted.
+    pub allowed_senders: Option<HashSet<Address>>,
+    /// Transactions from these senders are never admitted.
+    pub denied_senders: HashSet<Address>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rejection {
+    SenderDenied,
+    SenderNotAllowed,
+    Underpriced {
+        fee: U256,
+        min: U256,
+    },
+    NonceTooLow {
+        nonce: u64,
+        current: u64,
+    },
+    NonceTooFar {
+        nonce: u64,
+        current: u64,
+        max_gap: u64,
+    },
+    Oversized {
+        size: usize,
+        max: usize,
+    },
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::SenderDenied => write!(f, "sender is denied"),
+            Self::SenderNotAllowed => write!(f, "sender is not allowed"),
+            Self::Underpriced { fee, min } => {
+                write!(
+                    f,
+                    "transaction underpriced: fee {} below minimum {}",
+                    fee, min
+                )
+            }
+            Self::NonceTooLow { nonce, current } => {
+                write!(f, "nonce too low: {} below current {}", nonce, current)
+            }
+            Self::NonceTooFar {
+                nonce,
+                current,
+                max_gap,
+            } => write!(
+                f,
+                "nonce too far in the future: {} is more than {} ahead of current {}",
+                nonce, max_gap, current
+            ),
+            Self::Oversized { size, max } => {
+                write!(f, "transaction too large: {} bytes, limit {}", size, max)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Rejection {}
+
+impl AdmissionPolicy {
+    /// Check transaction `txn` from `sender`, whose next nonce is `current_nonce`.
+    pub fn check(
+        &self,
+        txn: &MessageWithSignature,
+        sender: Address,
+        current_nonce: u64,
+    ) -> Result<(), Rejection> {
+        if self.denied_senders.contains(&sender) {
+            return Err(Rejection::SenderDenied);
+        }
+        if let Some(allowed_senders) = &self.allowed_senders {
+            if !allowed_senders.contains(&sender) {
+                return Err(Rejection::SenderNotAllowed);
+            }
+        }
+
+        // For legacy and EIP-2930 transactions this is their gas price.
+        let fee = txn.max_priority_fee_per_gas();
+        let min = match txn.tx_type() {
+            TxType::EIP1559 | TxType::EIP4844 => self.min_priority_fee,
+            TxType::Legacy | TxType::EIP2930 => self.min_gas_price,
+        };
+        if fee < min {
+            return Err(Rejection::Underpriced { fee, min });
+        }
+
+        let nonce = txn.nonce();
+        if nonce < current_nonce {
+            return Err(Rejection::NonceTooLow {
+                nonce,
+                current: current_nonce,
+            });
+        }
+        if let Some(max_gap) = self.max_nonce_gap {
+            if nonce - current_nonce > max_gap {
+                return Err(Rejection::NonceTooFar {
+                    nonce,
+                    current: current_nonce,
+                    max_gap,
+                });
+            }
+        }
+
+        if let Some(max) = self.max_size {
+            let size = txn.trie_encode().len();
+            if size > max {
+                return Err(Rejection::Oversized { size, max });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+
+    fn txn(nonce: u64, max_priority_fee_per_gas: u64, input_len: usize) -> MessageWithSignature {
+        MessageWithSignature {
+            message: Message::EIP1559 {
+                chain_id: ChainId(1),
+                nonce,
+                max_priority_fee_per_gas: max_priority_fee_per_gas.as_u256(),
+                max_fee_per_gas: 100.as_u256(),
+                gas_limit: 21_000,
+                action: TransactionAction::Call(Address::repeat_byte(1)),
+                value: U256::ZERO,
+                input: Bytes::from(vec![0; input_len]),
+                access_list: vec![],
+            },
+            signature: MessageSignature::new(false, H256::repeat_byte(2), H256::repeat_byte(3))
+                .unwrap(),
+        }
+    }
+
+    #[test]
+    fn admission_rules() {
+        let sender = Address::repeat_byte(0xaa);
+        let mut policy = AdmissionPolicy {
+            min_priority_fee: 2.as_u256(),
+            max_nonce_gap: Some(16),
+            max_size: Some(1024),
+            ..Default::default()
+        };
+
+        assert_eq!(policy.check(&txn(5, 2, 0), sender, 5), Ok(()));
+        assert_eq!(
+            policy.check(&txn(5, 1, 0), sender, 5),
+            Err(Rejection::Underpriced {
+                fee: 1.as_u256(),
+                min: 2.as_u256()
+            })
+        );
+        assert_eq!(
+            policy.check(&txn(4, 2, 0), sender, 5),
+            Err(Rejection::NonceTooLow {
+                nonce: 4,
+                current: 5
+            })
+        );
+        assert_eq!(policy.check(&txn(21, 2, 0), sender, 5), Ok(()));
+        assert_eq!(
+            policy.check(&txn(22, 2, 0), sender, 5),
+            Err(Rejection::NonceTooFar {
+                nonce: 22,
+                current: 5,
+                max_gap: 16
+            })
+        );
+        assert!(matches!(
+            policy.check(&txn(5, 2, 2048), sender, 5),
+            Err(Rejection::Oversized { max: 1024, .. })
+        ));
+
+        policy.allowed_senders = Some([Address::repeat_byte(0xbb)].into_iter().collect());
+        assert_eq!(
+            policy.check(&txn(5, 2, 0), sender, 5),
+            Err(Rejection::SenderNotAllowed)
+        );
+        policy.allowed_senders = None;
+        policy.denied_senders.insert(sender);
+        assert_eq!(
+            policy.check(&txn(5, 2, 0), sender, 5),
+            Err(Rejection::SenderDenied)
+        );
+    }
+}
@@ -0,0 +1,795 @@
+//! Transactions waiting to be included into blocks.
+//!
+//! Transactions of every sender are kept ordered by nonce. Those that continue the nonce of the
+//! sender in the latest state without gaps, and that the sender can pay for, are pending and
+//! ready for block building. The rest are queued until the gap is filled or the sender is funded.
+//!
+//! Blob transactions are only accepted while their blob fee cap covers the blob base fee of the
+//! next block, and are evicted once it does not.
+//!
+//! The pool does not read state itself: callers look up the sender account, so that the pool can
+//! sit behind a lock that is never held across database reads.
+
+use super::policy::{AdmissionPolicy, Rejection};
+use crate::{
+    chain::{
+        intrinsic_gas::{check_intrinsic_gas, IntrinsicGasSchedule},
+        protocol_param::param,
+    },
+    models::*,
+};
+use evmodin::Revision;
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, BinaryHeap, HashMap},
+    fmt,
+};
+
+#[derive(Clone, Debug)]
+pub struct PoolConfig {
+    pub chain_id: ChainId,
+    pub policy: AdmissionPolicy,
+    /// Percent by which a replacement must raise both fee caps of the transaction it replaces.
+    pub price_bump: u64,
+    /// Most transactions in pool, pending and queued together.
+    pub max_transactions: usize,
+    /// Lowest priority fee of transactions accepted and included in built blocks, see
+    /// `miner_setGasPrice`.
+    pub min_tip: U256,
+}
+
+impl PoolConfig {
+    pub fn new(chain_id: ChainId) -> Self {
+        Self {
+            chain_id,
+            policy: AdmissionPolicy::default(),
+            price_bump: 10,
+            max_transactions: 4096,
+            min_tip: U256::ZERO,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PoolError {
+    Rejected(Rejection),
+    AlreadyKnown,
+    WrongChain {
+        chain_id: ChainId,
+        expected: ChainId,
+    },
+    IntrinsicGasTooLow {
+        gas_limit: u64,
+    },
+    GasLimitExceeded {
+        gas_limit: u64,
+        block_gas_limit: u64,
+    },
+    FeeCapBelowTip,
+    TipTooLow {
+        tip: U256,
+        min: U256,
+    },
+    BlobsNotSupported,
+    NoBlobs,
+    TooManyBlobs {
+        blob_gas: u64,
+        max_blob_gas: u64,
+    },
+    BlobFeeCapTooLow {
+        fee_cap: U256,
+        blob_base_fee: U256,
+    },
+    InsufficientFunds {
+        cost: U256,
+        balance: U256,
+    },
+    ReplacementUnderpriced {
+        fee: U256,
+        min: U256,
+    },
+    PoolFull,
+}
+
+impl From<Rejection> for PoolError {
+    fn from(rejection: Rejection) -> Self {
+        Self::Rejected(rejection)
+    }
+}
+
+impl fmt::Display for PoolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Rejected(rejection) => write!(f, "{}", rejection),
+            Self::AlreadyKnown => write!(f, "already known"),
+            Self::WrongChain { chain_id, expected } => {
+                write!(f, "chain id {} instead of {}", chain_id.0, expected.0)
+            }
+            Self::IntrinsicGasTooLow { gas_limit } => {
+                write!(f, "intrinsic gas too low: gas limit {}", gas_limit)
+            }
+            Self::GasLimitExceeded {
+                gas_limit,
+                block_gas_limit,
+            } => write!(
+                f,
+                "exceeds block gas limit: gas limit {}, block gas limit {}",
+                gas_limit, block_gas_limit
+            ),
+            Self::FeeCapBelowTip => {
+                write!(f, "max priority fee per gas higher than max fee per gas")
+            }
+            Self::TipTooLow { tip, min } => write!(
+                f,
+                "transaction underpriced: priority fee {} below miner minimum {}",
+                tip, min
+            ),
+            Self::BlobsNotSupported => write!(f, "blob transactions before EIP-4844"),
+            Self::NoBlobs => write!(f, "blob transaction without blobs"),
+            Self::TooManyBlobs {
+                blob_gas,
+                max_blob_gas,
+            } => write!(
+                f,
+                "too many blobs: blob gas {}, block blob gas limit {}",
+                blob_gas, max_blob_gas
+            ),
+            Self::BlobFeeCapTooLow {
+                fee_cap,
+                blob_base_fee,
+            } => write!(
+                f,
+                "max fee per blob gas less than blob base fee: fee cap {}, blob base fee {}",
+                fee_cap, blob_base_fee
+            ),
+            Self::InsufficientFunds { cost, balance } => write!(
+                f,
+                "insufficient funds for gas * price + value: cost {}, balance {}",
+                cost, balance
+            ),
+            Self::ReplacementUnderpriced { fee, min } => write!(
+                f,
+                "replacement transaction underpriced: fee {} below {}",
+                fee, min
+            ),
+            Self::PoolFull => write!(f, "txpool is full"),
+        }
+    }
+}
+
+impl std::error::Error for PoolError {}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PooledTransaction {
+    pub hash: H256,
+    pub sender: Address,
+    pub transaction: MessageWithSignature,
+}
+
+impl PooledTransaction {
+    /// Most the sender may pay for this transaction.
+    pub fn cost(&self) -> U256 {
+        U256::from(self.transaction.gas_limit()) * self.transaction.max_fee_per_gas()
+            + U256::from(self.transaction.blob_gas()) * self.transaction.max_fee_per_blob_gas()
+            + self.transaction.value()
+    }
+
+    fn is_blob(&self) -> bool {
+        self.transaction.tx_type() == TxType::EIP4844
+    }
+
+    /// Whether this transaction may be included in block with `blob_base_fee`.
+    fn pays_blob_fee(&self, blob_base_fee: Option<U256>) -> bool {
+        !self.is_blob()
+            || blob_base_fee
+                .map(|blob_base_fee| self.transaction.max_fee_per_blob_gas() >= blob_base_fee)
+                .unwrap_or(false)
+    }
+}
+
+#[derive(Debug, Default)]
+struct SenderTransactions {
+    /// Account of the sender in the latest state.
+    account: Account,
+    transactions: BTreeMap<u64, PooledTransaction>,
+}
+
+impl SenderTransactions {
+    /// Number of leading transactions that are pending.
+    fn pending_len(&self) -> usize {
+        let mut nonce = self.account.nonce;
+        let mut spent = U256::ZERO;
+        let mut len = 0;
+        for (&tx_nonce, tx) in self.transactions.range(self.account.nonce..) {
+            if tx_nonce != nonce {
+                break;
+            }
+            spent += tx.cost();
+            if spent > self.account.balance {
+                break;
+            }
+            nonce += 1;
+            len += 1;
+        }
+        len
+    }
+}
+
+#[derive(Debug)]
+pub struct TxPool {
+    config: PoolConfig,
+    schedule: IntrinsicGasSchedule,
+    block_gas_limit: u64,
+    /// Blob base fee of the next block, `None` before EIP-4844.
+    blob_base_fee: Option<U256>,
+    senders: HashMap<Address, SenderTransactions>,
+    by_hash: HashMap<H256, (Address, u64)>,
+}
+
+impl TxPool {
+    pub fn new(config: PoolConfig, revision: Revision, block_gas_limit: u64) -> Self {
+        Self {
+            config,
+            schedule: revision.into(),
+            block_gas_limit,
+            blob_base_fee: None,
+            senders: HashMap::new(),
+            by_hash: HashMap::new(),
+        }
+    }
+
+    pub fn config(&self) -> &PoolConfig {
+        &self.config
+    }
+
+    pub fn set_policy(&mut self, policy: AdmissionPolicy) {
+        self.config.policy = policy;
+    }
+
+    /// Accept only transactions paying at least `min_tip` from now on. Transactions already in
+    /// pool are kept, but those below it are no longer returned by [`Self::best`].
+    pub fn set_min_tip(&mut self, min_tip: U256) {
+        self.config.min_tip = min_tip;
+    }
+
+    /// Validate new transactions against the rules of the latest block, and evict blob
+    /// transactions that can not pay `blob_base_fee` of the next block.
+    pub fn set_head(
+        &mut self,
+        revision: Revision,
+        block_gas_limit: u64,
+        blob_base_fee: Option<U256>,
+    ) {
+        self.schedule = revision.into();
+        self.block_gas_limit = block_gas_limit;
+        self.blob_base_fee = blob_base_fee;
+
+        let evicted = self
+            .senders
+            .values()
+            .flat_map(|sender_txs| sender_txs.transactions.values())
+            .filter(|tx| !tx.pays_blob_fee(blob_base_fee))
+            .map(|tx| tx.hash)
+            .collect::<Vec<_>>();
+        for hash in evicted {
+            self.remove(hash);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_hash.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_hash.is_empty()
+    }
+
+    /// Validate `transaction` of `sender`, whose account in the latest state is `account`,
+    /// and add it, replacing a transaction with the same nonce if it pays enough more.
+    pub fn add(
+        &mut self,
+        transaction: MessageWithSignature,
+        sender: Address,
+        account: Account,
+    ) -> Result<H256, PoolError> {
+        let hash = transaction.hash();
+        if self.by_hash.contains_key(&hash) {
+            return Err(PoolError::AlreadyKnown);
+        }
+
+        if let Some(chain_id) = transaction.chain_id() {
+            if chain_id != self.config.chain_id {
+                return Err(PoolError::WrongChain {
+                    chain_id,
+                    expected: self.config.chain_id,
+                });
+            }
+        }
+        self.config
+            .policy
+            .check(&transaction, sender, account.nonce)?;
+
+        let gas_limit = transaction.gas_limit();
+        check_intrinsic_gas(&transaction, self.schedule)
+            .map_err(|_| PoolError::IntrinsicGasTooLow { gas_limit })?;
+        if gas_limit > self.block_gas_limit {
+            return Err(PoolError::GasLimitExceeded {
+                gas_limit,
+                block_gas_limit: self.block_gas_limit,
+            });
+        }
+        if transaction.max_priority_fee_per_gas() > transaction.max_fee_per_gas() {
+            return Err(PoolError::FeeCapBelowTip);
+        }
+        if transaction.max_priority_fee_per_gas() < self.config.min_tip {
+            return Err(PoolError::TipTooLow {
+                tip: transaction.max_priority_fee_per_gas(),
+                min: self.config.min_tip,
+            });
+        }
+        if transaction.tx_type() == TxType::EIP4844 {
+            self.check_blobs(&transaction)?;
+        }
+
+        let tx = PooledTransaction {
+            hash,
+            sender,
+            transaction,
+        };
+        let cost = tx.cost();
+        if cost > account.balance {
+            return Err(PoolError::InsufficientFunds {
+                cost,
+                balance: account.balance,
+            });
+        }
+
+        let nonce = tx.transaction.nonce();
+        let replaced = self
+            .senders
+            .get(&sender)
+            .and_then(|sender| sender.transactions.get(&nonce));
+        if let Some(replaced) = replaced {
+            self.check_replacement(replaced, &tx)?;
+        } else if self.len() >= self.config.max_transactions {
+            return Err(PoolError::PoolFull);
+        }
+
+        let sender_txs = self.senders.entry(sender).or_default();
+        sender_txs.account = account;
+        if let Some(replaced) = sender_txs.transactions.insert(nonce, tx) {
+            self.by_hash.remove(&replaced.hash);
+        }
+        self.by_hash.insert(hash, (sender, nonce));
+
+        Ok(hash)
+    }
+
+    fn check_blobs(&self, transaction: &MessageWithSignature) -> Result<(), PoolError> {
+        let blob_base_fee = self.blob_base_fee.ok_or(PoolError::BlobsNotSupported)?;
+
+        let blob_gas = transaction.blob_gas();
+        if blob_gas == 0 {
+            return Err(PoolError::NoBlobs);
+        }
+        if blob_gas > param::MAX_BLOB_GAS_PER_BLOCK {
+            return Err(PoolError::TooManyBlobs {
+                blob_gas,
+                max_blob_gas: param::MAX_BLOB_GAS_PER_BLOCK,
+            });
+        }
+
+        let fee_cap = transaction.max_fee_per_blob_gas();
+        if fee_cap < blob_base_fee {
+            return Err(PoolError::BlobFeeCapTooLow {
+                fee_cap,
+                blob_base_fee,
+            });
+        }
+
+        Ok(())
+    }
+
+    fn check_replacement(
+        &self,
+        replaced: &PooledTransaction,
+        tx: &PooledTransaction,
+    ) -> Result<(), PoolError> {
+        let bump = |fee: U256| fee + fee * U256::from(self.config.price_bump) / 100;
+        for (fee, old_fee) in [
+            (
+                tx.transaction.max_fee_per_gas(),
+                replaced.transaction.max_fee_per_gas(),
+            ),
+            (
+                tx.transaction.max_priority_fee_per_gas(),
+                replaced.transaction.max_priority_fee_per_gas(),
+            ),
+            (
+                tx.transaction.max_fee_per_blob_gas(),
+                replaced.transaction.max_fee_per_blob_gas(),
+            ),
+        ] {
+            let min = bump(old_fee);
+            if fee < min {
+                return Err(PoolError::ReplacementUnderpriced { fee, min });
+            }
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, hash: H256) -> Option<&PooledTransaction> {
+        let (sender, nonce) = self.by_hash.get(&hash)?;
+        self.senders.get(sender)?.transactions.get(nonce)
+    }
+
+    pub fn remove(&mut self, hash: H256) -> Option<PooledTransaction> {
+        let (sender, nonce) = self.by_hash.remove(&hash)?;
+        let sender_txs = self.senders.get_mut(&sender)?;
+        let tx = sender_txs.transactions.remove(&nonce);
+        if sender_txs.transactions.is_empty() {
+            self.senders.remove(&sender);
+        }
+        tx
+    }
+
+    /// Senders with transactions in pool, whose accounts should be refreshed on new head.
+    pub fn senders(&self) -> Vec<Address> {
+        self.senders.keys().copied().collect()
+    }
+
+    /// Update account of `sender` after a new head, dropping transactions that were included
+    /// or can no longer be included.
+    pub fn update_account(&mut self, sender: Address, account: Account) {
+        let sender_txs = match self.senders.get_mut(&sender) {
+            Some(sender_txs) => sender_txs,
+            None => return,
+        };
+
+        let remaining = sender_txs.transactions.split_off(&account.nonce);
+        for tx in std::mem::replace(&mut sender_txs.transactions, remaining).into_values() {
+            self.by_hash.remove(&tx.hash);
+        }
+        sender_txs.account = account;
+
+        if sender_txs.transactions.is_empty() {
+            self.senders.remove(&sender);
+        }
+    }
+
+    /// Nonce of the next transaction of `sender` after its pending ones.
+    pub fn next_nonce(&self, sender: Address) -> Option<u64> {
+        let sender_txs = self.senders.get(&sender)?;
+        Some(sender_txs.account.nonce + sender_txs.pending_len() as u64)
+    }
+
+    /// Transactions ready for block building, by sender and in nonce order.
+    pub fn pending(&self) -> BTreeMap<Address, Vec<&PooledTransaction>> {
+        self.split(true)
+    }
+
+    /// Transactions waiting for a nonce gap to be filled or for funds, by sender and in nonce
+    /// order.
+    pub fn queued(&self) -> BTreeMap<Address, Vec<&PooledTransaction>> {
+        self.split(false)
+    }
+
+    /// Pending transactions to build a block with `base_fee_per_gas` and `gas_limit` from, most
+    /// profitable first, keeping nonce order of every sender.
+    ///
+    /// Blob transactions are limited by blob gas of a block. Once the next transaction of a
+    /// sender does not fit, or pays less than the minimum tip, the rest of its transactions are
+    /// left out.
+    pub fn best(&self, base_fee_per_gas: Option<U256>, gas_limit: u64) -> Vec<&PooledTransaction> {
+        let base_fee_per_gas = base_fee_per_gas.unwrap_or(U256::ZERO);
+        let tip_of = |tx: &PooledTransaction| {
+            (tx.transaction.max_fee_per_gas() >= base_fee_per_gas)
+                .then(|| tx.transaction.priority_fee_per_gas(base_fee_per_gas))
+                .filter(|tip| *tip >= self.config.min_tip)
+        };
+
+        let mut senders = self
+            .pending()
+            .into_values()
+            .map(|txs| txs.into_iter().peekable())
+            .collect::<Vec<_>>();
+        // Tip of the next transaction of every sender, senders are ordered by address on ties.
+        let mut heap = BinaryHeap::new();
+        for (i, txs) in senders.iter_mut().enumerate() {
+            if let Some(tip) = txs.peek().and_then(|tx| tip_of(*tx)) {
+                heap.push((tip, Reverse(i)));
+            }
+        }
+
+        let mut gas_left = gas_limit;
+        let mut blob_gas_left = param::MAX_BLOB_GAS_PER_BLOCK;
+        let mut best = vec![];
+        while let Some((_, Reverse(i))) = heap.pop() {
+            let tx = senders[i].next().unwrap();
+
+            let gas = tx.transaction.gas_limit();
+            let blob_gas = tx.transaction.blob_gas();
+            if gas > gas_left || blob_gas > blob_gas_left || !tx.pays_blob_fee(self.blob_base_fee) {
+                continue;
+            }
+
+            gas_left -= gas;
+            blob_gas_left -= blob_gas;
+            best.push(tx);
+
+            if let Some(tip) = senders[i].peek().and_then(|tx| tip_of(*tx)) {
+                heap.push((tip, Reverse(i)));
+            }
+        }
+
+        best
+    }
+
+    fn split(&self, pending: bool) -> BTreeMap<Address, Vec<&PooledTransaction>> {
+        self.senders
+            .iter()
+            .filter_map(|(&sender, sender_txs)| {
+                let pending_len = sender_txs.pending_len();
+                let txs = sender_txs.transactions.values();
+                let txs = if pending {
+                    txs.take(pending_len).collect::<Vec<_>>()
+                } else {
+                    txs.skip(pending_len).collect::<Vec<_>>()
+                };
+                (!txs.is_empty()).then(|| (sender, txs))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+
+    fn txn(
+        nonce: u64,
+        max_fee_per_gas: u64,
+        max_priority_fee_per_gas: u64,
+    ) -> MessageWithSignature {
+        MessageWithSignature {
+            message: Message::EIP1559 {
+                chain_id: ChainId(1),
+                nonce,
+                max_priority_fee_per_gas: max_priority_fee_per_gas.as_u256(),
+                max_fee_per_gas: max_fee_per_gas.as_u256(),
+                gas_limit: 21_000,
+                action: TransactionAction::Call(Address::repeat_byte(1)),
+                value: U256::ZERO,
+                input: Bytes::new(),
+                access_list: vec![],
+            },
+            signature: MessageSignature::new(false, H256::repeat_byte(2), H256::repeat_byte(3))
+                .unwrap(),
+        }
+    }
+
+    fn account(nonce: u64, balance: u64) -> Account {
+        Account {
+            nonce,
+            balance: balance.as_u256(),
+            ..Default::default()
+        }
+    }
+
+    fn nonces(txs: &BTreeMap<Address, Vec<&PooledTransaction>>, sender: Address) -> Vec<u64> {
+        txs.get(&sender)
+            .map(|txs| txs.iter().map(|tx| tx.transaction.nonce()).collect())
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn pending_queued_and_replacement() {
+        let sender = Address::repeat_byte(0xaa);
+        let mut pool = TxPool::new(PoolConfig::new(ChainId(1)), Revision::London, 30_000_000);
+
+        // Enough for three transactions.
+        let funded = account(5, 3 * 21_000 * 100);
+        for nonce in [5, 6, 8, 9] {
+            pool.add(txn(nonce, 100, 10), sender, funded).unwrap();
+        }
+        assert_eq!(
+            pool.add(txn(5, 100, 10), sender, funded),
+            Err(PoolError::AlreadyKnown)
+        );
+        assert_eq!(nonces(&pool.pending(), sender), vec![5, 6]);
+        assert_eq!(nonces(&pool.queued(), sender), vec![8, 9]);
+        assert_eq!(pool.next_nonce(sender), Some(7));
+
+        // Filling the gap makes 7 pending, 8 can not be paid for.
+        pool.add(txn(7, 100, 10), sender, funded).unwrap();
+        assert_eq!(nonces(&pool.pending(), sender), vec![5, 6, 7]);
+        assert_eq!(nonces(&pool.queued(), sender), vec![8, 9]);
+
+        // Both fee caps must be raised by 10%.
+        assert_eq!(
+            pool.add(txn(6, 109, 11), sender, funded),
+            Err(PoolError::ReplacementUnderpriced {
+                fee: 109.as_u256(),
+                min: 110.as_u256()
+            })
+        );
+        assert_eq!(
+            pool.add(txn(6, 110, 10), sender, funded),
+            Err(PoolError::ReplacementUnderpriced {
+                fee: 10.as_u256(),
+                min: 11.as_u256()
+            })
+        );
+        let old_hash = txn(6, 100, 10).hash();
+        let new_hash = pool.add(txn(6, 110, 11), sender, funded).unwrap();
+        assert!(pool.get(old_hash).is_none());
+        assert_eq!(pool.get(new_hash).unwrap().transaction.nonce(), 6);
+        assert_eq!(pool.len(), 5);
+
+        // Transactions 5 and 6 were included.
+        pool.update_account(sender, account(7, 10 * 21_000 * 100));
+        assert_eq!(nonces(&pool.pending(), sender), vec![7, 8, 9]);
+        assert!(pool.queued().is_empty());
+        assert!(pool.get(new_hash).is_none());
+        assert_eq!(pool.len(), 3);
+
+        assert_eq!(
+            pool.add(txn(3, 100, 10), sender, account(7, 21_000 * 100)),
+            Err(PoolError::Rejected(Rejection::NonceTooLow {
+                nonce: 3,
+                current: 7
+            }))
+        );
+        assert_eq!(
+            pool.add(txn(10, 100, 10), sender, account(7, 20_000)),
+            Err(PoolError::InsufficientFunds {
+                cost: (21_000 * 100).as_u256(),
+                balance: 20_000.as_u256()
+            })
+        );
+        assert_eq!(
+            pool.add(txn(10, 1, 2), sender, funded),
+            Err(PoolError::FeeCapBelowTip)
+        );
+    }
+
+    fn blob_txn(nonce: u64, max_fee_per_blob_gas: u64, blobs: usize) -> MessageWithSignature {
+        let mut txn = txn(nonce, 100, 10);
+        txn.message = Message::EIP4844 {
+            chain_id: ChainId(1),
+            nonce,
+            max_priority_fee_per_gas: 10.as_u256(),
+            max_fee_per_gas: 100.as_u256(),
+            gas_limit: 21_000,
+            action: TransactionAction::Call(Address::repeat_byte(1)),
+            value: U256::ZERO,
+            input: Bytes::new(),
+            access_list: vec![],
+            max_fee_per_blob_gas: max_fee_per_blob_gas.as_u256(),
+            blob_versioned_hashes: vec![H256::repeat_byte(1); blobs],
+        };
+        txn
+    }
+
+    #[test]
+    fn blob_transactions() {
+        let sender = Address::repeat_byte(0xaa);
+        let funded = account(0, u64::MAX);
+        let mut pool = TxPool::new(PoolConfig::new(ChainId(1)), Revision::London, 30_000_000);
+
+        assert_eq!(
+            pool.add(blob_txn(0, 10, 1), sender, funded),
+            Err(PoolError::BlobsNotSupported)
+        );
+
+        pool.set_head(Revision::Shanghai, 30_000_000, Some(5.as_u256()));
+        assert_eq!(
+            pool.add(blob_txn(0, 10, 0), sender, funded),
+            Err(PoolError::NoBlobs)
+        );
+        assert_eq!(
+            pool.add(blob_txn(0, 10, 7), sender, funded),
+            Err(PoolError::TooManyBlobs {
+                blob_gas: 7 * param::GAS_PER_BLOB,
+                max_blob_gas: param::MAX_BLOB_GAS_PER_BLOCK
+            })
+        );
+        assert_eq!(
+            pool.add(blob_txn(0, 4, 1), sender, funded),
+            Err(PoolError::BlobFeeCapTooLow {
+                fee_cap: 4.as_u256(),
+                blob_base_fee: 5.as_u256()
+            })
+        );
+
+        // Blob fees are paid upfront.
+        assert_eq!(
+            pool.add(blob_txn(0, 10, 2), sender, account(0, 21_000 * 100)),
+            Err(PoolError::InsufficientFunds {
+                cost: (21_000 * 100 + 2 * param::GAS_PER_BLOB * 10).as_u256(),
+                balance: (21_000 * 100).as_u256()
+            })
+        );
+
+        pool.add(blob_txn(0, 10, 2), sender, funded).unwrap();
+        pool.add(blob_txn(1, 20, 2), sender, funded).unwrap();
+        let hash = pool.add(txn(2, 100, 10), sender, funded).unwrap();
+        assert_eq!(nonces(&pool.pending(), sender), vec![0, 1, 2]);
+
+        // Blob fee cap must be raised too.
+        let mut replacement = blob_txn(1, 21, 2);
+        if let Message::EIP4844 {
+            max_fee_per_gas,
+            max_priority_fee_per_gas,
+            ..
+        } = &mut replacement.message
+        {
+            *max_fee_per_gas = 110.as_u256();
+            *max_priority_fee_per_gas = 11.as_u256();
+        }
+        assert_eq!(
+            pool.add(replacement, sender, funded),
+            Err(PoolError::ReplacementUnderpriced {
+                fee: 21.as_u256(),
+                min: 22.as_u256()
+            })
+        );
+
+        // First transaction can not pay for blobs anymore, the rest waits for its nonce.
+        pool.set_head(Revision::Shanghai, 30_000_000, Some(15.as_u256()));
+        assert!(pool.pending().is_empty());
+        assert_eq!(nonces(&pool.queued(), sender), vec![1, 2]);
+        assert!(pool.get(hash).is_some());
+    }
+
+    #[test]
+    fn best_transactions() {
+        let (a, b, c) = (
+            Address::repeat_byte(0xaa),
+            Address::repeat_byte(0xbb),
+            Address::repeat_byte(0xcc),
+        );
+        let funded = account(0, u64::MAX);
+        let mut pool = TxPool::new(PoolConfig::new(ChainId(1)), Revision::London, 30_000_000);
+        pool.set_head(Revision::Shanghai, 30_000_000, Some(1.as_u256()));
+
+        pool.add(txn(0, 100, 5), a, funded).unwrap();
+        pool.add(txn(1, 100, 50), a, funded).unwrap();
+        pool.add(txn(0, 100, 20), b, funded).unwrap();
+        pool.add(blob_txn(0, 1, 4), c, funded).unwrap();
+        pool.add(blob_txn(1, 1, 4), c, funded).unwrap();
+
+        let best = |pool: &TxPool, base_fee_per_gas: u64, gas_limit| {
+            pool.best(Some(base_fee_per_gas.as_u256()), gas_limit)
+                .into_iter()
+                .map(|tx| (tx.sender, tx.transaction.nonce()))
+                .collect::<Vec<_>>()
+        };
+
+        // Tips are capped by fee caps above base fee. Second blob transaction of `c` does not
+        // fit into blob gas of the block.
+        assert_eq!(
+            best(&pool, 0, 30_000_000),
+            vec![(b, 0), (c, 0), (a, 0), (a, 1)]
+        );
+        assert_eq!(
+            best(&pool, 95, 30_000_000),
+            vec![(a, 0), (a, 1), (b, 0), (c, 0)]
+        );
+        assert_eq!(best(&pool, 0, 2 * 21_000), vec![(b, 0), (c, 0)]);
+        assert!(best(&pool, 101, 30_000_000).is_empty());
+
+        // Tip of the first transaction of `a` is below the minimum, so both of its transactions
+        // are left out.
+        pool.set_min_tip(10.as_u256());
+        assert_eq!(best(&pool, 0, 30_000_000), vec![(b, 0), (c, 0)]);
+        assert_eq!(
+            pool.add(txn(0, 100, 9), c, funded),
+            Err(PoolError::TipTooLow {
+                tip: 9.as_u256(),
+                min: 10.as_u256()
+            })
+        );
+    }
+}
@@ -1,16 +1,44 @@
+use arrayvec::ArrayVec;
 use bytes::{Bytes, BytesMut};
+use derive_more::{Deref, DerefMut};
 use ethereum_types::*;
 use ethnum::U256;
 use num_traits::Zero;
 use serde::{
     de::{self, Error},
-    Deserialize,
+    Deserialize, Serializer,
 };
 use std::{
     borrow::Borrow,
     fmt::{self, Formatter},
 };
 
+/// Byte vector of at most `LEN` bytes, stored inline.
+#[derive(Clone, Debug, Default, Deref, DerefMut, PartialEq, Eq, PartialOrd, Ord)]
+pub struct VariableVec<const LEN: usize> {
+    pub inner: ArrayVec<u8, LEN>,
+}
+
+impl<const LEN: usize> FromIterator<u8> for VariableVec<LEN> {
+    fn from_iter<T: IntoIterator<Item = u8>>(iter: T) -> Self {
+        Self {
+            inner: ArrayVec::from_iter(iter),
+        }
+    }
+}
+
+impl<const LEN: usize> AsRef<[u8]> for VariableVec<LEN> {
+    fn as_ref(&self) -> &[u8] {
+        self.inner.as_ref()
+    }
+}
+
+impl<const LEN: usize> From<VariableVec<LEN>> for Vec<u8> {
+    fn from(v: VariableVec<LEN>) -> Self {
+        v.to_vec()
+    }
+}
+
 pub fn static_left_pad<const LEN: usize>(unpadded: &[u8]) -> [u8; LEN] {
     assert!(unpadded.len() <= LEN);
 
@@ -77,9 +105,37 @@ where
     Ok(d)
 }
 
-pub mod hexbytes {
-    use serde::Serializer;
+pub fn deserialize_hexstr_as_u256<'de, D>(deserializer: D) -> Result<U256, D::Error>
+where
+    D: de::Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+
+    let d = if let Some(stripped) = s.strip_prefix("0x") {
+        U256::from_str_radix(stripped, 16)
+    } else {
+        s.parse()
+    }
+    .map_err(|e| de::Error::custom(format!("{}/{}", e, s)))?;
 
+    Ok(d)
+}
+
+pub fn serialize_u64_as_hexstr<S>(v: &u64, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.serialize_str(&format!("{:#x}", v))
+}
+
+pub fn serialize_u256_as_hexstr<S>(v: &U256, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.serialize_str(&format!("{:#x}", v))
+}
+
+pub mod hexbytes {
     use super::*;
 
     pub fn deserialize<'de, D>(deserializer: D) -> Result<Bytes, D::Error>
@@ -101,6 +157,37 @@ pub mod hexbytes {
     }
 }
 
+/// Sequence of byte strings, each hex encoded like [`hexbytes`].
+pub mod hexbytes_seq {
+    use super::*;
+    use serde::ser::SerializeSeq;
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Vec<Bytes>, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        Vec::<String>::deserialize(deserializer)?
+            .into_iter()
+            .map(|s| {
+                hex::decode(s.strip_prefix("0x").unwrap_or(&s))
+                    .map(Bytes::from)
+                    .map_err(D::Error::custom)
+            })
+            .collect()
+    }
+
+    pub fn serialize<S>(b: &[Bytes], serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut seq = serializer.serialize_seq(Some(b.len()))?;
+        for b in b {
+            seq.serialize_element(&format!("0x{}", hex::encode(b)))?;
+        }
+        seq.end()
+    }
+}
+
 pub fn version_string() -> String {
     format!(
         "akula/v{}-{}-{}-{}/{}/rustc{}",
@@ -0,0 +1,3 @@
+// Returns the chain id of the synthetic chain.
+>> {"jsonrpc":"2.0","id":1,"method":"eth_chainId"}
+<< {"jsonrpc":"2.0","id":1,"result":"0x539"}
@@ -0,0 +1,3 @@
+// Malformed address is rejected.
+>> {"jsonrpc":"2.0","id":1,"method":"eth_getTransactionCount","params":["0x12","latest"]}
+<< {"jsonrpc":"2.0","id":1,"error":{"code":-32602,"message":"invalid params"}}
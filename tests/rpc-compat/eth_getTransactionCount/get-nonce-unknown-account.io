@@ -0,0 +1,3 @@
+// Accounts missing from state have nonce zero.
+>> {"jsonrpc":"2.0","id":1,"method":"eth_getTransactionCount","params":["0xaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa","latest"]}
+<< {"jsonrpc":"2.0","id":1,"result":"0x0"}
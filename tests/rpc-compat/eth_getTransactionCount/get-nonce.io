@@ -0,0 +1,3 @@
+// Sender of the synthetic chain sends one transaction per block.
+>> {"jsonrpc":"2.0","id":1,"method":"eth_getTransactionCount","params":["0x7e5f4552091a69125d5dfcb7b8c2659029395bdf","latest"]}
+<< {"jsonrpc":"2.0","id":1,"result":"0x8"}